@@ -0,0 +1,57 @@
+//! User asset packs: rooms and avatars dropped into the `rooms` and `avatars`
+//! folders of the platform's data directory (e.g. `~/.local/share/zine`), so
+//! content can be added without rebuilding. They're loaded through their own
+//! asset source, as `packs://rooms/<name>.glb` and the like.
+
+use bevy::asset::io::file::FileAssetReader;
+use bevy::asset::io::AssetSource;
+use bevy::prelude::*;
+use std::path::PathBuf;
+
+/// Name of the asset source pack files load from.
+pub const PACK_SOURCE: &str = "packs";
+
+/// Pack folders holding rooms and avatars.
+pub const PACK_ROOMS_DIR: &str = "rooms";
+pub const PACK_AVATARS_DIR: &str = "avatars";
+
+const PACK_DIR_NAME: &str = "zine";
+
+/// Where packs live, falling back to a `packs` folder in the working
+/// directory on platforms without a data directory.
+pub fn pack_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join(PACK_DIR_NAME))
+        .unwrap_or_else(|| PathBuf::from(PACK_SOURCE))
+}
+
+/// Register the asset source packs load from. Must run before the asset
+/// plugin is added.
+pub fn register_pack_source(app: &mut App) {
+    let dir = pack_dir();
+    app.register_asset_source(
+        PACK_SOURCE,
+        AssetSource::build().with_reader(move || Box::new(FileAssetReader::new(dir.clone()))),
+    );
+}
+
+/// Names of the glTF models in a pack folder, sorted so every player lists
+/// them in the same order.
+pub fn pack_models(folder: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(pack_dir().join(folder)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "glb"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Asset path of a model in a pack folder.
+pub fn pack_model_path(folder: &str, name: &str) -> String {
+    format!("{}://{}/{}.glb", PACK_SOURCE, folder, name)
+}
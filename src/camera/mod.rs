@@ -1,19 +1,56 @@
+pub mod screenshot;
+pub mod spectator;
 pub mod systems;
 
 use bevy::prelude::*;
 
-use crate::game_state::AppState;
-use systems::{center_cursor, grab_cursor, handle_alt_cursor_unlock, mouse_look, toggle_cursor_grab, AltCursorUnlock};
+use crate::game_state::{AppState, PauseState};
+use screenshot::take_screenshot;
+use spectator::{
+    fly_spectator_camera, not_spectating, reset_spectator, toggle_spectator, SpectatorCamera,
+};
+use systems::{
+    center_cursor, grab_cursor, handle_alt_cursor_unlock, handle_window_focus, mouse_look,
+    position_player_camera, reset_focus_cursor_release, toggle_third_person, AltCursorUnlock,
+    FocusCursorRelease, ThirdPersonView,
+};
 
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<AltCursorUnlock>()
+            .init_resource::<ThirdPersonView>()
+            .init_resource::<SpectatorCamera>()
+            .init_resource::<FocusCursorRelease>()
             .add_systems(OnEnter(AppState::InGame), grab_cursor)
+            .add_systems(
+                OnExit(AppState::InGame),
+                (reset_spectator, reset_focus_cursor_release),
+            )
+            // Pausing frees the cursor itself, and resuming grabs it again
+            .add_systems(OnEnter(PauseState::Paused), reset_focus_cursor_release)
+            .add_systems(
+                Update,
+                (
+                    handle_window_focus.before(mouse_look),
+                    mouse_look.run_if(not_spectating),
+                    center_cursor,
+                    handle_alt_cursor_unlock,
+                    toggle_third_person,
+                    (toggle_spectator, fly_spectator_camera).chain(),
+                )
+                    .run_if(in_state(PauseState::Running)),
+            )
             .add_systems(
                 Update,
-                (mouse_look, center_cursor, toggle_cursor_grab, handle_alt_cursor_unlock).run_if(in_state(AppState::InGame)),
+                (
+                    position_player_camera
+                        .after(mouse_look)
+                        .run_if(not_spectating),
+                    take_screenshot,
+                )
+                    .run_if(in_state(AppState::InGame)),
             );
     }
 }
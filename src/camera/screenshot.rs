@@ -0,0 +1,69 @@
+//! Screenshots of the rendered frame, UI included, saved as timestamped PNGs
+//! in a screenshots folder under the user's pictures folder.
+
+use bevy::prelude::*;
+use bevy::render::view::screenshot::{save_to_disk, Screenshot, ScreenshotCaptured};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::controls::{Action, KeyBindings};
+use crate::menu::NotificationEvent;
+
+/// Folder screenshots are saved in, under the user's pictures folder.
+const SCREENSHOTS_DIR_NAME: &str = "zine screenshots";
+
+/// Where a pending screenshot is saved.
+#[derive(Component)]
+struct ScreenshotPath(PathBuf);
+
+fn screenshots_dir() -> PathBuf {
+    dirs::picture_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+        .join(SCREENSHOTS_DIR_NAME)
+}
+
+/// System to take a screenshot with its key.
+pub fn take_screenshot(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if !bindings.just_pressed(Action::Screenshot, &keyboard_input) {
+        return;
+    }
+
+    let dir = screenshots_dir();
+    if let Err(e) = fs::create_dir_all(&dir) {
+        warn!("Failed to create {}: {}", dir.display(), e);
+        notifications.send(NotificationEvent("Couldn't save screenshot".to_string()));
+        return;
+    }
+    // Milliseconds, so quick presses don't overwrite each other
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let path = dir.join(format!("zine-{}.png", stamp));
+
+    commands
+        .spawn((Screenshot::primary_window(), ScreenshotPath(path.clone())))
+        .observe(save_to_disk(path))
+        .observe(announce_screenshot);
+}
+
+/// Observer to confirm a screenshot on screen once it's captured.
+fn announce_screenshot(
+    trigger: Trigger<ScreenshotCaptured>,
+    path_query: Query<&ScreenshotPath>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if let Ok(path) = path_query.get(trigger.entity()) {
+        notifications.send(NotificationEvent(format!(
+            "Screenshot saved to {}",
+            path.0.display()
+        )));
+    }
+}
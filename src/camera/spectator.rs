@@ -0,0 +1,152 @@
+//! Free-fly spectator camera for the host, for demos and debugging. The
+//! camera leaves the player and flies through walls, while the player and
+//! their avatar stay where they were, so others see them still seated.
+
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+
+use crate::controls::{Action, KeyBindings, PlayerInput};
+use crate::emote::EmoteWheel;
+use crate::network::server::GameServer;
+use crate::player::{Player, PlayerCamera, PITCH_LIMIT};
+use crate::world::components::WorldEntity;
+
+/// Base flying speed, in units per second.
+const SPECTATOR_SPEED: f32 = 4.0;
+
+/// Speed multiplier while sprinting.
+const SPECTATOR_FAST_MULTIPLIER: f32 = 4.0;
+
+/// Range the mouse wheel scales the base speed within.
+const SPECTATOR_SPEED_SCALE_RANGE: (f32, f32) = (0.125, 8.0);
+
+/// Speed scale change per mouse wheel notch.
+const SPECTATOR_SCROLL_STEP: f32 = 1.25;
+
+/// Resource for the spectator camera's state.
+#[derive(Resource)]
+pub struct SpectatorCamera {
+    pub active: bool,
+    yaw: f32,
+    pitch: f32,
+    /// Base speed multiplier, adjusted with the mouse wheel.
+    speed_scale: f32,
+}
+
+impl Default for SpectatorCamera {
+    fn default() -> Self {
+        Self {
+            active: false,
+            yaw: 0.0,
+            pitch: 0.0,
+            speed_scale: 1.0,
+        }
+    }
+}
+
+/// Run condition for systems that drive the player, paused while spectating.
+pub fn not_spectating(spectator: Res<SpectatorCamera>) -> bool {
+    !spectator.active
+}
+
+/// System to switch the host between their player and the spectator camera.
+pub fn toggle_spectator(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    server: Option<Res<GameServer>>,
+    mut spectator: ResMut<SpectatorCamera>,
+    player_query: Query<Entity, With<Player>>,
+    mut camera_query: Query<(Entity, &GlobalTransform, &mut Transform), With<PlayerCamera>>,
+) {
+    if server.is_none() || !bindings.just_pressed(Action::Spectate, &keyboard_input) {
+        return;
+    }
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let Ok((camera, global, mut transform)) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    if spectator.active {
+        // Back to the eyes, where the player camera positioning takes over
+        *transform = Transform::IDENTITY;
+        commands
+            .entity(camera)
+            .remove::<WorldEntity>()
+            .set_parent(player);
+        spectator.active = false;
+        return;
+    }
+
+    // Start from where the camera is, then leave the player behind. The
+    // world cleanup no longer reaches the camera through the player.
+    *transform = global.compute_transform();
+    let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    spectator.yaw = yaw;
+    spectator.pitch = pitch;
+    spectator.active = true;
+    commands.entity(camera).remove_parent().insert(WorldEntity);
+}
+
+/// System to fly the spectator camera: look with the mouse or right stick,
+/// move along the view, Jump and Crouch to rise and sink, Sprint to go
+/// faster and the mouse wheel to scale the base speed.
+pub fn fly_spectator_camera(
+    time: Res<Time>,
+    input: Res<PlayerInput>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut wheel_events: EventReader<MouseWheel>,
+    mut spectator: ResMut<SpectatorCamera>,
+    emote_wheel: Res<EmoteWheel>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<&mut Transform, With<PlayerCamera>>,
+) {
+    if !spectator.active {
+        wheel_events.clear();
+        return;
+    }
+    let Ok(mut transform) = camera_query.get_single_mut() else {
+        return;
+    };
+
+    for event in wheel_events.read() {
+        let factor = SPECTATOR_SCROLL_STEP.powf(event.y.signum());
+        spectator.speed_scale = (spectator.speed_scale * factor)
+            .clamp(SPECTATOR_SPEED_SCALE_RANGE.0, SPECTATOR_SPEED_SCALE_RANGE.1);
+    }
+
+    let grabbed = windows
+        .get_single()
+        .is_ok_and(|window| window.cursor_options.grab_mode != CursorGrabMode::None);
+    if grabbed && !emote_wheel.open {
+        spectator.yaw += input.look.x;
+        spectator.pitch = (spectator.pitch + input.look.y).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+    }
+    transform.rotation = Quat::from_euler(EulerRot::YXZ, spectator.yaw, spectator.pitch, 0.0);
+
+    let mut vertical = 0.0;
+    if bindings.pressed(Action::Jump, &keyboard_input) {
+        vertical += 1.0;
+    }
+    if input.crouch {
+        vertical -= 1.0;
+    }
+    let direction = transform.forward() * input.movement.y
+        + transform.right() * input.movement.x
+        + Vec3::Y * vertical;
+
+    let mut speed = SPECTATOR_SPEED * spectator.speed_scale;
+    if input.sprint {
+        speed *= SPECTATOR_FAST_MULTIPLIER;
+    }
+    transform.translation += direction.clamp_length_max(1.0) * speed * time.delta_secs();
+}
+
+/// Leave spectator mode with the session, the camera going with the world.
+pub fn reset_spectator(mut spectator: ResMut<SpectatorCamera>) {
+    spectator.active = false;
+}
@@ -1,6 +1,62 @@
-use bevy::{input::mouse::MouseMotion, prelude::*, window::CursorGrabMode};
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow, WindowFocused};
+use bevy_rapier3d::prelude::*;
+use std::f32::consts::TAU;
 
-use crate::player::{CameraController, Player, MOUSE_SENSITIVITY, PITCH_LIMIT};
+use crate::controls::{Action, KeyBindings, PlayerInput};
+use crate::emote::EmoteWheel;
+use crate::player::components::PLAYER_SPEED;
+use crate::player::{
+    CameraController, Grounded, Player, PlayerCamera, Seated, Velocity, PITCH_LIMIT,
+};
+use crate::settings::VideoSettings;
+
+/// Where the third-person camera sits relative to the eyes: behind and over
+/// the right shoulder.
+const THIRD_PERSON_OFFSET: Vec3 = Vec3::new(0.5, 0.3, 2.5);
+
+/// How far the third-person camera stays from walls it's pulled in by.
+const CAMERA_WALL_MARGIN: f32 = 0.2;
+
+/// Rate the smoothed view catches up with the look direction, per second.
+const CAMERA_SMOOTHING_RATE: f32 = 20.0;
+
+/// Head bob cycles per meter walked. Each cycle is two steps.
+const HEAD_BOB_FREQUENCY: f32 = 0.9;
+
+/// Height and sideways sway of the head bob at walking speed.
+const HEAD_BOB_HEIGHT: f32 = 0.04;
+const HEAD_BOB_SWAY: f32 = 0.025;
+
+/// Rate the head bob fades in on walking and out on stopping, per second.
+const HEAD_BOB_FADE_SPEED: f32 = 8.0;
+
+/// Whether the local player is seen from over the shoulder instead of first person.
+#[derive(Resource, Default)]
+pub struct ThirdPersonView {
+    pub active: bool,
+}
+
+/// Head bob and smoothed look of the first-person view. They only move the
+/// camera, never the player, so other players don't see them.
+#[derive(Default)]
+pub struct ViewMotion {
+    /// Look direction the camera has caught up to, when smoothing.
+    rotation: Option<Quat>,
+    /// Position along the step cycle, in radians.
+    bob_phase: f32,
+    /// How strongly the head bobs, from zero standing to one at walking speed.
+    bob_amount: f32,
+}
+
+/// Cursor grab given up when the window lost focus, so alt-tabbing away
+/// doesn't leave the cursor confined and the camera turning with it.
+#[derive(Resource, Default)]
+pub struct FocusCursorRelease {
+    /// Grab mode to restore once the player clicks back into the window.
+    pub released: Option<CursorGrabMode>,
+    prompt: Option<Entity>,
+}
 
 /// Tracks whether the Alt key is currently holding the cursor unlocked
 #[derive(Resource, Default)]
@@ -9,62 +65,57 @@ pub struct AltCursorUnlock {
     pub active: bool,
 }
 
-pub fn grab_cursor(mut windows: Query<&mut Window>) {
-    let mut window = windows.single_mut();
+pub fn grab_cursor(mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
     window.cursor_options.grab_mode = CursorGrabMode::Confined;
     window.cursor_options.visible = false;
 }
 
-pub fn toggle_cursor_grab(keyboard_input: Res<ButtonInput<KeyCode>>, mut windows: Query<&mut Window>) {
-    if keyboard_input.just_pressed(KeyCode::Escape) {
-        let mut window = windows.single_mut();
-        match window.cursor_options.grab_mode {
-            CursorGrabMode::None => {
-                window.cursor_options.grab_mode = CursorGrabMode::Confined;
-                window.cursor_options.visible = false;
-            }
-            _ => {
-                window.cursor_options.grab_mode = CursorGrabMode::None;
-                window.cursor_options.visible = true;
-            }
-        }
-    }
-}
-
+/// System to turn the camera with the mouse or the right stick.
 pub fn mouse_look(
-    mut mouse_motion: EventReader<MouseMotion>,
+    input: Res<PlayerInput>,
     mut query: Query<(&mut Transform, &mut CameraController), With<Player>>,
-    windows: Query<&Window>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    emote_wheel: Res<EmoteWheel>,
 ) {
-    let window = windows.single();
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
 
-    // Only process mouse look when cursor is grabbed
-    if window.cursor_options.grab_mode == CursorGrabMode::None {
-        mouse_motion.clear();
+    // Only process mouse look when cursor is grabbed, and the mouse isn't
+    // picking an emote
+    if window.cursor_options.grab_mode == CursorGrabMode::None || emote_wheel.open {
         return;
     }
 
-    let (mut transform, mut controller) = query.single_mut();
+    let Ok((mut transform, mut controller)) = query.get_single_mut() else {
+        return;
+    };
 
-    for event in mouse_motion.read() {
-        controller.yaw -= event.delta.x * MOUSE_SENSITIVITY;
-        controller.pitch -= event.delta.y * MOUSE_SENSITIVITY;
+    controller.yaw += input.look.x;
+    controller.pitch += input.look.y;
 
-        // Clamp pitch to prevent flipping
-        controller.pitch = controller.pitch.clamp(-PITCH_LIMIT, PITCH_LIMIT);
-    }
+    // Clamp pitch to prevent flipping
+    controller.pitch = controller.pitch.clamp(-PITCH_LIMIT, PITCH_LIMIT);
 
     // Apply rotation
     transform.rotation = Quat::from_euler(EulerRot::YXZ, controller.yaw, controller.pitch, 0.0);
 }
 
-pub fn center_cursor(mut windows: Query<&mut Window>, alt_unlock: Res<AltCursorUnlock>) {
+pub fn center_cursor(
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    alt_unlock: Res<AltCursorUnlock>,
+) {
     // Don't center cursor while Alt is held
     if alt_unlock.active {
         return;
     }
 
-    let mut window = windows.single_mut();
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
 
     // Only center cursor when it's grabbed and window is focused
     if window.cursor_options.grab_mode != CursorGrabMode::None && window.focused {
@@ -75,12 +126,15 @@ pub fn center_cursor(mut windows: Query<&mut Window>, alt_unlock: Res<AltCursorU
 
 pub fn handle_alt_cursor_unlock(
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut windows: Query<&mut Window>,
+    bindings: Res<KeyBindings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
     mut alt_unlock: ResMut<AltCursorUnlock>,
 ) {
-    let mut window = windows.single_mut();
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
 
-    if keyboard_input.just_pressed(KeyCode::AltLeft) || keyboard_input.just_pressed(KeyCode::AltRight) {
+    if bindings.just_pressed(Action::HoldCursor, &keyboard_input) {
         // Only unlock if cursor is currently grabbed
         if window.cursor_options.grab_mode != CursorGrabMode::None {
             alt_unlock.active = true;
@@ -89,7 +143,7 @@ pub fn handle_alt_cursor_unlock(
         }
     }
 
-    if keyboard_input.just_released(KeyCode::AltLeft) || keyboard_input.just_released(KeyCode::AltRight) {
+    if bindings.just_released(Action::HoldCursor, &keyboard_input) {
         // Only re-lock if we were the ones who unlocked it
         if alt_unlock.active {
             alt_unlock.active = false;
@@ -98,3 +152,185 @@ pub fn handle_alt_cursor_unlock(
         }
     }
 }
+
+/// System to free the cursor when the window loses focus, and grab it again
+/// when the player clicks back in. Mouse look stops while it's free.
+pub fn handle_window_focus(
+    mut commands: Commands,
+    mut focus_events: EventReader<WindowFocused>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut release: ResMut<FocusCursorRelease>,
+    mut alt_unlock: ResMut<AltCursorUnlock>,
+    mut input: ResMut<PlayerInput>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+
+    for event in focus_events.read() {
+        if event.focused {
+            continue;
+        }
+        if window.cursor_options.grab_mode != CursorGrabMode::None {
+            release.released = Some(window.cursor_options.grab_mode);
+            window.cursor_options.grab_mode = CursorGrabMode::None;
+            window.cursor_options.visible = true;
+        } else if alt_unlock.active {
+            // Alt-tabbing away, Alt is released where we can't see it
+            alt_unlock.active = false;
+            release.released = Some(CursorGrabMode::Confined);
+        }
+    }
+
+    let Some(grab_mode) = release.released else {
+        return;
+    };
+    // Menus may have grabbed the cursor again meanwhile
+    let regrabbed = window.cursor_options.grab_mode != CursorGrabMode::None;
+    let clicked = window.focused && mouse_input.just_pressed(MouseButton::Left);
+    if regrabbed || clicked {
+        if clicked && !regrabbed {
+            window.cursor_options.grab_mode = grab_mode;
+            window.cursor_options.visible = false;
+            // Motion from getting the window back isn't a turn
+            input.look = Vec2::ZERO;
+        }
+        release.released = None;
+        if let Some(prompt) = release.prompt.take() {
+            commands.entity(prompt).despawn_recursive();
+        }
+    } else if window.focused && release.prompt.is_none() {
+        release.prompt = Some(spawn_refocus_prompt(&mut commands));
+    }
+}
+
+fn spawn_refocus_prompt(commands: &mut Commands) -> Entity {
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_child((
+            Node {
+                padding: UiRect::axes(Val::Px(16.0), Val::Px(8.0)),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            Text::new("Click to resume"),
+            TextFont {
+                font_size: 20.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ))
+        .id()
+}
+
+/// Forget a cursor released on losing focus, with the session.
+pub fn reset_focus_cursor_release(mut commands: Commands, mut release: ResMut<FocusCursorRelease>) {
+    if let Some(prompt) = release.prompt.take() {
+        commands.entity(prompt).despawn_recursive();
+    }
+    release.released = None;
+}
+
+/// Switch between first and third person with V.
+pub fn toggle_third_person(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut third_person: ResMut<ThirdPersonView>,
+) {
+    if !bindings.just_pressed(Action::ToggleView, &keyboard_input) {
+        return;
+    }
+
+    // Only when cursor is grabbed (in game)
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if window.cursor_options.grab_mode == CursorGrabMode::None {
+        return;
+    }
+
+    third_person.active = !third_person.active;
+}
+
+/// Put the camera at the eyes in first person, or behind the shoulder in
+/// third person, pulled in front of any wall in between. The first-person
+/// view bobs with the player's steps and the look can trail the mouse, if
+/// the settings ask for it.
+pub fn position_player_camera(
+    time: Res<Time>,
+    third_person: Res<ThirdPersonView>,
+    settings: Res<VideoSettings>,
+    mut motion: Local<ViewMotion>,
+    player_query: Query<
+        (&Transform, &Velocity, &Grounded, Has<Seated>),
+        (With<Player>, Without<PlayerCamera>),
+    >,
+    mut camera_query: Query<&mut Transform, With<PlayerCamera>>,
+    rapier_context: ReadDefaultRapierContext,
+) {
+    let Ok((player_transform, velocity, grounded, seated)) = player_query.get_single() else {
+        return;
+    };
+    let Ok(mut camera_transform) = camera_query.get_single_mut() else {
+        return;
+    };
+    let dt = time.delta_secs();
+
+    // The camera turns against the player to trail behind the look
+    let look = player_transform.rotation;
+    let view = match motion.rotation {
+        Some(rotation) if settings.camera_smoothing => {
+            rotation.slerp(look, 1.0 - (-CAMERA_SMOOTHING_RATE * dt).exp())
+        }
+        _ => look,
+    };
+    motion.rotation = Some(view);
+    camera_transform.rotation = look.inverse() * view;
+
+    if !third_person.active {
+        let speed = velocity.0.with_y(0.0).length();
+        let walking = settings.head_bob && grounded.0 && !seated;
+        let target = if walking {
+            (speed / PLAYER_SPEED).min(1.0)
+        } else {
+            0.0
+        };
+        motion.bob_amount = motion
+            .bob_amount
+            .lerp(target, (HEAD_BOB_FADE_SPEED * dt).min(1.0));
+        if walking {
+            motion.bob_phase = (motion.bob_phase + speed * dt * HEAD_BOB_FREQUENCY * TAU) % TAU;
+        }
+
+        // Down on each step, swaying towards the foot that's down, level
+        // whichever way the player looks
+        let sway = player_transform.right() * (motion.bob_phase.sin() * HEAD_BOB_SWAY);
+        let dip = Vec3::NEG_Y * (motion.bob_phase.sin().abs() * HEAD_BOB_HEIGHT);
+        camera_transform.translation = look.inverse() * (sway + dip) * motion.bob_amount;
+        return;
+    }
+
+    let direction = THIRD_PERSON_OFFSET.normalize();
+    let max_distance = THIRD_PERSON_OFFSET.length();
+    let distance = rapier_context
+        .single()
+        .cast_ray(
+            player_transform.translation,
+            player_transform.rotation * direction,
+            max_distance,
+            true,
+            QueryFilter::default().exclude_sensors(),
+        )
+        .map_or(max_distance, |(_, toi)| (toi - CAMERA_WALL_MARGIN).max(0.0));
+
+    camera_transform.translation = direction * distance;
+}
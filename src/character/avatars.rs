@@ -0,0 +1,138 @@
+//! Avatars players pick in the main menu: one of the character models,
+//! tinted with one of a few colors. The choice is saved in the config file.
+//! Models from asset packs follow the built-in ones. Players without the
+//! same packs see someone else's pack avatar as the first model.
+
+use bevy::prelude::*;
+use std::sync::OnceLock;
+
+use crate::asset_packs::{pack_model_path, pack_models, PACK_AVATARS_DIR};
+use crate::network::protocol::AvatarChoice;
+
+/// Letters of the character models in `assets/characters`.
+const AVATAR_MODELS: [char; 18] = [
+    'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r',
+];
+
+/// Tints multiplied into the model's colors. The first leaves it as it is.
+const AVATAR_TINTS: [(&str, Color); 6] = [
+    ("Original", Color::WHITE),
+    ("Red", Color::srgb(1.0, 0.55, 0.55)),
+    ("Green", Color::srgb(0.6, 1.0, 0.6)),
+    ("Blue", Color::srgb(0.6, 0.7, 1.0)),
+    ("Gold", Color::srgb(1.0, 0.85, 0.45)),
+    ("Purple", Color::srgb(0.8, 0.6, 1.0)),
+];
+
+/// Character model index, as used for keys in `CharacterAssets`.
+pub type AvatarModel = u8;
+
+/// Models found in packs, scanned once at startup, as many as indices allow.
+fn pack_avatars() -> &'static [String] {
+    static PACK_AVATARS: OnceLock<Vec<String>> = OnceLock::new();
+    PACK_AVATARS.get_or_init(|| {
+        let mut names = pack_models(PACK_AVATARS_DIR);
+        names.truncate(AvatarModel::MAX as usize + 1 - AVATAR_MODELS.len());
+        if !names.is_empty() {
+            info!("Found {} avatars in asset packs", names.len());
+        }
+        names
+    })
+}
+
+/// Built-in and pack models together.
+fn model_count() -> usize {
+    AVATAR_MODELS.len() + pack_avatars().len()
+}
+
+/// Name of the pack model at `model`, `None` for built-in models.
+fn pack_avatar(model: AvatarModel) -> Option<&'static str> {
+    let index = (model as usize).checked_sub(AVATAR_MODELS.len())?;
+    pack_avatars().get(index).map(String::as_str)
+}
+
+/// The avatar this player picked, sent to the host when joining.
+#[derive(Resource, Clone, Copy, PartialEq, Debug, Default)]
+pub struct AvatarSelection(pub AvatarChoice);
+
+impl AvatarSelection {
+    /// A saved choice, with anything this build doesn't know replaced.
+    pub fn new(choice: AvatarChoice) -> Self {
+        Self(sanitize(choice))
+    }
+
+    /// Step through the models, wrapping around at either end.
+    pub fn cycle_model(&mut self, step: isize) {
+        self.0.model = cycle(self.0.model, step, model_count());
+    }
+
+    /// Step through the tints, wrapping around at either end.
+    pub fn cycle_tint(&mut self, step: isize) {
+        self.0.tint = cycle(self.0.tint, step, AVATAR_TINTS.len());
+    }
+}
+
+fn cycle(index: u8, step: isize, len: usize) -> u8 {
+    (index as isize + step).rem_euclid(len as isize) as u8
+}
+
+/// Component holding the avatar a character entity is shown with.
+#[derive(Component, Clone, Copy)]
+pub struct CharacterAvatar(pub AvatarChoice);
+
+impl CharacterAvatar {
+    pub fn new(choice: AvatarChoice) -> Self {
+        Self(sanitize(choice))
+    }
+}
+
+/// Replace indices this build doesn't know, e.g. from a newer peer, with the defaults.
+fn sanitize(choice: AvatarChoice) -> AvatarChoice {
+    AvatarChoice {
+        model: if (choice.model as usize) < model_count() {
+            choice.model
+        } else {
+            0
+        },
+        tint: if (choice.tint as usize) < AVATAR_TINTS.len() {
+            choice.tint
+        } else {
+            0
+        },
+    }
+}
+
+pub fn model_path(model: AvatarModel) -> String {
+    if let Some(name) = pack_avatar(model) {
+        return pack_model_path(PACK_AVATARS_DIR, name);
+    }
+    let letter = AVATAR_MODELS
+        .get(model as usize)
+        .unwrap_or(&AVATAR_MODELS[0]);
+    format!("characters/character-{}.glb", letter)
+}
+
+pub fn model_name(model: AvatarModel) -> String {
+    if let Some(name) = pack_avatar(model) {
+        return name.to_string();
+    }
+    let letter = AVATAR_MODELS
+        .get(model as usize)
+        .unwrap_or(&AVATAR_MODELS[0]);
+    format!("Character {}", letter.to_ascii_uppercase())
+}
+
+pub fn tint_name(tint: u8) -> &'static str {
+    AVATAR_TINTS
+        .get(tint as usize)
+        .unwrap_or(&AVATAR_TINTS[0])
+        .0
+}
+
+/// Color to multiply into the model's materials, `None` for the original look.
+pub fn tint_color(tint: u8) -> Option<Color> {
+    match tint {
+        0 => None,
+        _ => AVATAR_TINTS.get(tint as usize).map(|(_, color)| *color),
+    }
+}
@@ -0,0 +1,336 @@
+//! Inverse kinematics on top of the animation. The torso and head share the
+//! look pitch, turned about the character's own axes whatever the bones' rest
+//! orientation, and the legs reach for the ground raycast under each foot so
+//! they don't float over steps and slopes. Runs once the animation has posed
+//! the bones for the frame.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::{find_entity_by_name, AnimationInitialized, CharacterAnimationState, MODEL_OFFSET};
+use crate::network::protocol::NetworkTransform;
+
+const HEAD_BONE_NAMES: [&str; 4] = ["Head", "head", "mixamorig:Head", "Bone.Head"];
+const TORSO_BONE_NAMES: [&str; 5] = ["torso", "Torso", "Spine", "spine", "mixamorig:Spine2"];
+const PELVIS_BONE_NAMES: [&str; 3] = ["root", "Hips", "mixamorig:Hips"];
+const LEG_BONE_NAMES: [[&str; 3]; 2] = [
+    ["leg-left", "LeftUpLeg", "mixamorig:LeftUpLeg"],
+    ["leg-right", "RightUpLeg", "mixamorig:RightUpLeg"],
+];
+
+/// Share of the look pitch the torso takes, the head turning the rest.
+const TORSO_PITCH_SHARE: f32 = 0.3;
+
+/// Rate the head follows the look pitch, per second.
+const PITCH_SMOOTHING: f32 = 10.0;
+
+/// Furthest a foot lifts onto higher ground, as high as the player steps up.
+const MAX_FOOT_LIFT: f32 = 0.3;
+
+/// Furthest the body drops to reach lower ground under a foot.
+const MAX_BODY_DROP: f32 = 0.3;
+
+/// Rate feet follow the ground under them, per second.
+const FOOT_SMOOTHING: f32 = 15.0;
+
+/// Rate foot placement fades in on landing and out on leaving the ground.
+const FOOT_BLEND_SPEED: f32 = 8.0;
+
+/// Stores the interpolated pitch for smooth head movement.
+#[derive(Component, Default)]
+pub struct HeadPitch {
+    pub current: f32,
+    /// Pitch the head turns towards: the network pitch for remote players,
+    /// the camera pitch for the local one.
+    pub target: f32,
+}
+
+/// A bone the IK moves on top of the animation.
+struct IkBone {
+    entity: Entity,
+    /// Pose the animation gave the bone.
+    animated: Transform,
+    /// Pose the IK left the bone in. Clips that don't animate the bone leave
+    /// it there, and the IK mustn't build on its own pose.
+    posed: Option<Transform>,
+}
+
+impl IkBone {
+    fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            animated: Transform::IDENTITY,
+            posed: None,
+        }
+    }
+
+    /// Put the bone back in its animated pose.
+    fn restore(&mut self, transforms: &mut Query<&mut Transform>) {
+        let Ok(mut transform) = transforms.get_mut(self.entity) else {
+            return;
+        };
+        if self.posed == Some(*transform) {
+            *transform = self.animated;
+        } else {
+            self.animated = *transform;
+        }
+    }
+}
+
+/// A leg reaching for the ground.
+struct IkLeg {
+    bone: IkBone,
+    /// Height of the hip above the soles at rest, in model units.
+    length: f32,
+    /// Smoothed height of the ground under the foot, relative to the ground
+    /// the character stands on.
+    ground_offset: f32,
+}
+
+/// Bones the IK moves, found once a character's model has spawned.
+#[derive(Component)]
+pub struct CharacterRig {
+    head: Option<IkBone>,
+    torso: Option<IkBone>,
+    pelvis: Option<IkBone>,
+    legs: Vec<IkLeg>,
+    /// How much foot placement applies, zero while airborne or seated.
+    foot_weight: f32,
+}
+
+impl CharacterRig {
+    fn bones_mut(&mut self) -> impl Iterator<Item = &mut IkBone> {
+        // Parents first, so children are restored under their animated parents
+        self.pelvis
+            .iter_mut()
+            .chain(self.legs.iter_mut().map(|leg| &mut leg.bone))
+            .chain(self.torso.iter_mut())
+            .chain(self.head.iter_mut())
+    }
+}
+
+/// Pose of `bone`'s parent in the space of the `character` it belongs to,
+/// from the local transforms the animation just wrote.
+fn parent_space(
+    bone: Entity,
+    character: Entity,
+    parents: &Query<&Parent>,
+    local: impl Fn(Entity) -> Option<Transform>,
+) -> Transform {
+    let mut space = Transform::IDENTITY;
+    let mut entity = bone;
+    while let Ok(parent) = parents.get(entity) {
+        entity = parent.get();
+        if entity == character {
+            break;
+        }
+        if let Some(transform) = local(entity) {
+            space = transform.mul_transform(space);
+        }
+    }
+    space
+}
+
+/// Turn `bone` by `rotation`, given in the space of its character.
+fn rotate_bone(
+    bone: &mut IkBone,
+    character: Entity,
+    rotation: Quat,
+    parents: &Query<&Parent>,
+    transforms: &mut Query<&mut Transform>,
+) {
+    let parent = parent_space(bone.entity, character, parents, |e| {
+        transforms.get(e).ok().copied()
+    })
+    .rotation;
+    if let Ok(mut transform) = transforms.get_mut(bone.entity) {
+        transform.rotation = parent.inverse() * rotation * parent * transform.rotation;
+        bone.posed = Some(*transform);
+    }
+}
+
+/// Find the bones of characters whose animation has been set up.
+pub fn setup_character_rig(
+    mut commands: Commands,
+    query: Query<Entity, (With<AnimationInitialized>, Without<CharacterRig>)>,
+    children_query: Query<&Children>,
+    name_query: Query<&Name>,
+    parents: Query<&Parent>,
+    transforms: Query<&Transform>,
+) {
+    for root_entity in query.iter() {
+        let find = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                find_entity_by_name(root_entity, name, &children_query, &name_query)
+            })
+        };
+
+        // The hip's height over the model's origin, at the soles, is the leg's reach
+        let legs = LEG_BONE_NAMES
+            .iter()
+            .filter_map(|names| find(names))
+            .filter_map(|leg| {
+                let local = transforms.get(leg).ok()?;
+                let hip = parent_space(leg, root_entity, &parents, |e| {
+                    transforms.get(e).ok().copied()
+                })
+                .mul_transform(*local);
+                let length = hip.translation.y;
+                (length > 0.0).then(|| IkLeg {
+                    bone: IkBone::new(leg),
+                    length,
+                    ground_offset: 0.0,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let head = find(&HEAD_BONE_NAMES);
+        if head.is_none() {
+            warn!(
+                "Character {:?} has no head bone, it won't look up or down",
+                root_entity
+            );
+        }
+        info!(
+            "Rigged character {:?} with {} legs",
+            root_entity,
+            legs.len()
+        );
+        commands.entity(root_entity).insert((
+            CharacterRig {
+                head: head.map(IkBone::new),
+                torso: find(&TORSO_BONE_NAMES).map(IkBone::new),
+                pelvis: find(&PELVIS_BONE_NAMES).map(IkBone::new),
+                legs,
+                foot_weight: 0.0,
+            },
+            HeadPitch::default(),
+        ));
+    }
+}
+
+/// Pitch the torso and head towards where each character looks, and plant
+/// its feet on the ground under them.
+pub fn apply_character_ik(
+    time: Res<Time>,
+    rapier_context: ReadDefaultRapierContext,
+    mut character_query: Query<(
+        Entity,
+        &mut CharacterRig,
+        &mut HeadPitch,
+        &CharacterAnimationState,
+        Option<&NetworkTransform>,
+    )>,
+    parents: Query<&Parent>,
+    mut transforms: Query<&mut Transform>,
+) {
+    let dt = time.delta_secs();
+    let context = rapier_context.single();
+    let filter = QueryFilter::default().exclude_sensors();
+
+    for (entity, mut rig, mut pitch, anim_state, net_transform) in character_query.iter_mut() {
+        let Ok(character) = transforms.get(entity).copied() else {
+            continue;
+        };
+        for bone in rig.bones_mut() {
+            bone.restore(&mut transforms);
+        }
+
+        // Feet are planted only while standing on something
+        let grounded = !anim_state.locomotion.is_airborne() && !anim_state.is_seated;
+        let weight_target = if grounded { 1.0 } else { 0.0 };
+        rig.foot_weight = rig
+            .foot_weight
+            .lerp(weight_target, (FOOT_BLEND_SPEED * dt).min(1.0));
+
+        // Ground under each foot, measured from the hip down, relative to
+        // the ground the character stands on
+        let scale = character.scale.y.max(f32::EPSILON);
+        let ground = character.translation.y - MODEL_OFFSET;
+        for leg in rig.legs.iter_mut() {
+            let Ok(local) = transforms.get(leg.bone.entity).copied() else {
+                continue;
+            };
+            let hip = parent_space(leg.bone.entity, entity, &parents, |e| {
+                transforms.get(e).ok().copied()
+            })
+            .mul_transform(local);
+            let hip = character.transform_point(hip.translation);
+            let reach = leg.length * scale + MAX_BODY_DROP;
+            let offset = context
+                .cast_ray(hip, Vec3::NEG_Y, reach, true, filter)
+                .map_or(0.0, |(_, toi)| hip.y - toi - ground)
+                .clamp(-MAX_BODY_DROP, MAX_FOOT_LIFT);
+            leg.ground_offset = leg
+                .ground_offset
+                .lerp(offset, (FOOT_SMOOTHING * dt).min(1.0));
+        }
+
+        // The body drops to the lower foot, the other leg swings forward to
+        // meet its ground. The legs have no knees to bend.
+        let weight = rig.foot_weight;
+        let drop = rig
+            .legs
+            .iter()
+            .map(|leg| leg.ground_offset * weight / scale)
+            .fold(0.0, f32::min);
+        let drop = match rig.pelvis.as_mut() {
+            Some(pelvis) if drop < 0.0 => {
+                let parent = parent_space(pelvis.entity, entity, &parents, |e| {
+                    transforms.get(e).ok().copied()
+                });
+                if let Ok(mut transform) = transforms.get_mut(pelvis.entity) {
+                    transform.translation +=
+                        parent.rotation.inverse() * (Vec3::Y * drop) / parent.scale;
+                    pelvis.posed = Some(*transform);
+                }
+                drop
+            }
+            _ => 0.0,
+        };
+        for leg in rig.legs.iter_mut() {
+            let lift = leg.ground_offset * weight / scale - drop;
+            if lift <= 0.0 {
+                continue;
+            }
+            let angle = ((leg.length - lift) / leg.length).clamp(0.0, 1.0).acos();
+            rotate_bone(
+                &mut leg.bone,
+                entity,
+                Quat::from_rotation_x(-angle),
+                &parents,
+                &mut transforms,
+            );
+        }
+
+        // Torso and head share the pitch. Characters face +Z, so looking up
+        // turns them about -X
+        if let Some(net_transform) = net_transform {
+            pitch.target = net_transform.target_pitch;
+        }
+        pitch.current = pitch
+            .current
+            .lerp(pitch.target, (PITCH_SMOOTHING * dt).min(1.0));
+        let mut head_pitch = pitch.current;
+        if let Some(torso) = rig.torso.as_mut() {
+            let torso_pitch = pitch.current * TORSO_PITCH_SHARE;
+            rotate_bone(
+                torso,
+                entity,
+                Quat::from_rotation_x(-torso_pitch),
+                &parents,
+                &mut transforms,
+            );
+            head_pitch -= torso_pitch;
+        }
+        if let Some(head) = rig.head.as_mut() {
+            rotate_bone(
+                head,
+                entity,
+                Quat::from_rotation_x(-head_pitch),
+                &parents,
+                &mut transforms,
+            );
+        }
+    }
+}
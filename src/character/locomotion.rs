@@ -0,0 +1,246 @@
+//! Locomotion state machine picking a character's animation from its velocity:
+//! idle, walking (blended between strafe directions), running, jumping,
+//! falling, landing, sitting, crouching, turning in place and sleeping while
+//! the player is away.
+//!
+//! Remote characters are driven by the velocity players replicate, so they
+//! animate the same way for everyone instead of guessing from position updates.
+
+use bevy::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+use super::CharacterAnimationState;
+use crate::player::PLAYER_SPEED;
+
+/// Horizontal speed above which a character walks.
+const WALK_SPEED_THRESHOLD: f32 = 0.1;
+/// Horizontal speed above which a character runs.
+const RUN_SPEED_THRESHOLD: f32 = PLAYER_SPEED * 1.2;
+/// Upward speed that starts a jump.
+const JUMP_SPEED_THRESHOLD: f32 = 1.0;
+/// Downward speed that starts a fall, high enough that steps and slopes don't.
+const FALL_SPEED_THRESHOLD: f32 = -3.0;
+/// How long a character that isn't moving stays in the landing animation.
+const LAND_TIME: f32 = 0.25;
+/// Turning faster than this (radians per second) while standing turns in place.
+const TURN_RATE_THRESHOLD: f32 = 1.5;
+/// How long turning in place lasts after the last turn, to bridge network updates.
+const TURN_HOLD_TIME: f32 = 0.25;
+/// Playback speed of the turn-in-place animation.
+pub const TURN_ANIMATION_SPEED: f32 = 0.6;
+/// Range the walk playback speed is scaled within to match the movement speed.
+const MIN_WALK_ANIMATION_SPEED: f32 = 0.5;
+const MAX_WALK_ANIMATION_SPEED: f32 = 1.5;
+
+/// States of the locomotion state machine.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LocomotionState {
+    #[default]
+    Idle,
+    Walk,
+    Run,
+    Jump,
+    Fall,
+    Land,
+    Sit,
+    TurnInPlace,
+    Crouch,
+    CrouchWalk,
+    /// Dozing off while the player is away from keyboard.
+    Sleep,
+}
+
+impl LocomotionState {
+    pub fn is_airborne(self) -> bool {
+        matches!(self, LocomotionState::Jump | LocomotionState::Fall)
+    }
+
+    /// Whether the state's animation loops, rather than playing once. Falling
+    /// asleep plays once and holds its last pose.
+    pub fn repeats(self) -> bool {
+        !matches!(
+            self,
+            LocomotionState::Jump | LocomotionState::Land | LocomotionState::Sleep
+        )
+    }
+
+    /// Whether an emote can play in this state. Anything else cuts it short.
+    pub fn allows_emote(self) -> bool {
+        matches!(self, LocomotionState::Idle | LocomotionState::TurnInPlace)
+    }
+}
+
+/// Walk clips blended by the direction of movement relative to the way the
+/// character faces. They all sit under one blend node.
+pub struct StrafeClips {
+    pub blend: AnimationNodeIndex,
+    pub forward: AnimationNodeIndex,
+    pub back: AnimationNodeIndex,
+    pub left: AnimationNodeIndex,
+    pub right: AnimationNodeIndex,
+    /// Whether walking back plays the forward clip in reverse, for models without a clip for it.
+    pub back_reversed: bool,
+}
+
+impl StrafeClips {
+    fn clips(&self) -> [AnimationNodeIndex; 4] {
+        [self.forward, self.back, self.left, self.right]
+    }
+}
+
+/// System to advance every character's locomotion state machine.
+pub fn update_locomotion_state(time: Res<Time>, mut query: Query<&mut CharacterAnimationState>) {
+    let dt = time.delta_secs();
+    for mut anim_state in query.iter_mut() {
+        let turn = anim_state
+            .last_yaw
+            .map_or(0.0, |last_yaw| wrap_angle(anim_state.yaw - last_yaw));
+        anim_state.last_yaw = Some(anim_state.yaw);
+        if dt > 0.0 && turn.abs() / dt > TURN_RATE_THRESHOLD {
+            anim_state.turn_time = 0.0;
+        } else {
+            anim_state.turn_time += dt;
+        }
+
+        let next = next_locomotion_state(&anim_state);
+        if next == anim_state.locomotion {
+            anim_state.state_time += dt;
+        } else {
+            anim_state.locomotion = next;
+            anim_state.state_time = 0.0;
+        }
+    }
+}
+
+fn next_locomotion_state(anim_state: &CharacterAnimationState) -> LocomotionState {
+    let current = anim_state.locomotion;
+    let vertical = anim_state.velocity.y;
+    let horizontal = anim_state.velocity.with_y(0.0).length();
+
+    if anim_state.is_seated {
+        return LocomotionState::Sit;
+    }
+
+    // Players replicate whether they're off the ground, so a jump lasts until
+    // they land even as it peaks. Stepping off a ledge only counts as a fall
+    // once it picks up speed.
+    let leaving_ground = vertical > JUMP_SPEED_THRESHOLD || vertical < FALL_SPEED_THRESHOLD;
+    if anim_state.is_airborne && (current.is_airborne() || leaving_ground) {
+        return if vertical > 0.0 {
+            LocomotionState::Jump
+        } else {
+            LocomotionState::Fall
+        };
+    }
+
+    // Players away from keyboard doze off where they stand
+    if anim_state.is_afk && horizontal <= WALK_SPEED_THRESHOLD {
+        return LocomotionState::Sleep;
+    }
+
+    if anim_state.is_crouching {
+        return if horizontal > WALK_SPEED_THRESHOLD {
+            LocomotionState::CrouchWalk
+        } else {
+            LocomotionState::Crouch
+        };
+    }
+
+    // Landing while moving goes straight back to walking or running
+    let landing = current.is_airborne()
+        || (current == LocomotionState::Land && anim_state.state_time < LAND_TIME);
+    let sprinting = anim_state.is_sprinting && horizontal > WALK_SPEED_THRESHOLD;
+    if sprinting || horizontal > RUN_SPEED_THRESHOLD {
+        LocomotionState::Run
+    } else if horizontal > WALK_SPEED_THRESHOLD {
+        LocomotionState::Walk
+    } else if landing {
+        LocomotionState::Land
+    } else if anim_state.turn_time < TURN_HOLD_TIME {
+        LocomotionState::TurnInPlace
+    } else {
+        LocomotionState::Idle
+    }
+}
+
+/// Weight the strafe clips by the direction of movement, playing them faster
+/// or slower to match the speed. The animation transitions fade the blend node
+/// in and out as a whole, and the clips follow its weight.
+pub fn update_strafe_blend(
+    player: &mut AnimationPlayer,
+    strafe: &StrafeClips,
+    velocity: Vec3,
+    yaw: f32,
+) {
+    let fade = player
+        .animation(strafe.blend)
+        .map_or(0.0, |blend| blend.weight());
+    if fade <= 0.0 {
+        for index in strafe.clips() {
+            player.stop(index);
+        }
+        return;
+    }
+
+    let speed = walk_animation_speed(velocity);
+    let back_speed = if strafe.back_reversed { -speed } else { speed };
+    let [forward, back, left, right] = strafe_weights(velocity, yaw);
+
+    // Directions without a clip of their own share one, which plays the way
+    // of whichever direction weighs most
+    let mut blend: Vec<(AnimationNodeIndex, f32, f32)> = Vec::with_capacity(4);
+    for (index, weight, speed) in [
+        (strafe.forward, forward, speed),
+        (strafe.back, back, back_speed),
+        (strafe.left, left, speed),
+        (strafe.right, right, speed),
+    ] {
+        match blend.iter_mut().find(|(existing, _, _)| *existing == index) {
+            Some(entry) => {
+                if weight > entry.1 {
+                    entry.2 = speed;
+                }
+                entry.1 += weight;
+            }
+            None => blend.push((index, weight, speed)),
+        }
+    }
+
+    for (index, weight, speed) in blend {
+        player
+            .play(index)
+            .repeat()
+            .set_weight(weight * fade)
+            .set_speed(speed);
+    }
+}
+
+/// Walk playback speed matching how fast a character moves.
+pub fn walk_animation_speed(velocity: Vec3) -> f32 {
+    (velocity.with_y(0.0).length() / PLAYER_SPEED)
+        .clamp(MIN_WALK_ANIMATION_SPEED, MAX_WALK_ANIMATION_SPEED)
+}
+
+/// Weights of the forward, back, left and right walk clips, summing to one.
+fn strafe_weights(velocity: Vec3, yaw: f32) -> [f32; 4] {
+    let rotation = Quat::from_rotation_y(yaw);
+    let forward = (rotation * Vec3::NEG_Z).dot(velocity);
+    let right = (rotation * Vec3::X).dot(velocity);
+    let weights = [
+        forward.max(0.0),
+        (-forward).max(0.0),
+        (-right).max(0.0),
+        right.max(0.0),
+    ];
+
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        return [1.0, 0.0, 0.0, 0.0];
+    }
+    weights.map(|weight| weight / total)
+}
+
+/// Wrap an angle difference into -PI..PI.
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
@@ -0,0 +1,179 @@
+//! Level of detail for remote characters. Far or off-screen characters only
+//! animate every few frames, catching up on the time they skipped, and past
+//! a distance the model gives way to a low-poly stand-in that doesn't
+//! animate at all.
+
+use bevy::prelude::*;
+
+use super::avatars::tint_color;
+use super::{CharacterAnimationLink, CharacterAvatar, MODEL_OFFSET};
+use crate::network::protocol::RemotePlayer;
+use crate::player::components::PLAYER_RADIUS;
+use crate::player::{PlayerCamera, PLAYER_HEIGHT};
+
+/// Distance beyond which characters animate at a reduced rate.
+const THROTTLE_DISTANCE: f32 = 12.0;
+
+/// Frames between animation updates of far characters.
+const FAR_ANIMATION_INTERVAL: u32 = 2;
+
+/// Frames between animation updates of characters off-screen.
+const OFFSCREEN_ANIMATION_INTERVAL: u32 = 4;
+
+/// Distance beyond which characters show as a stand-in.
+const STAND_IN_DISTANCE: f32 = 30.0;
+
+/// How much nearer a stand-in has to come before the model comes back, so
+/// characters at the threshold don't flicker between the two.
+const STAND_IN_HYSTERESIS: f32 = 2.0;
+
+/// Margin around the view, in normalized device coordinates, within which a
+/// character still counts as on-screen. Limbs reach past its center.
+const SCREEN_MARGIN: f32 = 0.2;
+
+const STAND_IN_COLOR: Color = Color::srgb(0.6, 0.6, 0.65);
+
+/// Level of detail a remote character is shown at.
+#[derive(Component, Default)]
+pub struct CharacterLod {
+    /// Frames counted towards the next animation update.
+    frame: u32,
+    /// Animation time skipped since the last update.
+    skipped: f32,
+    /// Graph taken off the animation player while it skips frames, which
+    /// stops Bevy evaluating its clips.
+    graph: Option<Handle<AnimationGraph>>,
+    stand_in: Option<Entity>,
+    showing_stand_in: bool,
+}
+
+/// Low-poly capsule standing in for a far character's model.
+#[derive(Component)]
+pub struct CharacterStandIn;
+
+/// System to pick how often each remote character animates, and whether it
+/// shows its model or a stand-in.
+pub fn update_character_lod(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    mut character_query: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &CharacterAnimationLink,
+            &CharacterAvatar,
+            Option<&mut CharacterLod>,
+        ),
+        With<RemotePlayer>,
+    >,
+    mut player_query: Query<(&mut AnimationPlayer, Option<&AnimationGraphHandle>)>,
+    children_query: Query<&Children>,
+    mut mesh_query: Query<&mut Visibility, (With<Mesh3d>, Without<CharacterStandIn>)>,
+) {
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let dt = time.delta_secs();
+
+    for (entity, transform, link, avatar, lod) in character_query.iter_mut() {
+        let Some(mut lod) = lod else {
+            commands.entity(entity).insert(CharacterLod::default());
+            continue;
+        };
+
+        let center = transform.translation() + Vec3::Y * (PLAYER_HEIGHT / 2.0 - MODEL_OFFSET);
+        let distance = camera_transform.translation().distance(center);
+        let on_screen = camera
+            .world_to_ndc(camera_transform, center)
+            .is_some_and(|ndc| {
+                (0.0..=1.0).contains(&ndc.z)
+                    && ndc.x.abs() <= 1.0 + SCREEN_MARGIN
+                    && ndc.y.abs() <= 1.0 + SCREEN_MARGIN
+            });
+
+        let stand_in_distance = if lod.showing_stand_in {
+            STAND_IN_DISTANCE - STAND_IN_HYSTERESIS
+        } else {
+            STAND_IN_DISTANCE
+        };
+        let show_stand_in = distance > stand_in_distance;
+        if show_stand_in != lod.showing_stand_in {
+            lod.showing_stand_in = show_stand_in;
+            if show_stand_in && lod.stand_in.is_none() {
+                let color = tint_color(avatar.0.tint).unwrap_or(STAND_IN_COLOR);
+                let stand_in = commands
+                    .spawn((
+                        CharacterStandIn,
+                        Mesh3d(
+                            meshes.add(
+                                Capsule3d::new(PLAYER_RADIUS, PLAYER_HEIGHT - PLAYER_RADIUS * 2.0)
+                                    .mesh()
+                                    .rings(1)
+                                    .latitudes(4)
+                                    .longitudes(8),
+                            ),
+                        ),
+                        MeshMaterial3d(materials.add(color)),
+                        Transform::from_xyz(0.0, PLAYER_HEIGHT / 2.0 - MODEL_OFFSET, 0.0),
+                    ))
+                    .id();
+                commands.entity(entity).add_child(stand_in);
+                lod.stand_in = Some(stand_in);
+            }
+            if let Some(stand_in) = lod.stand_in {
+                let visibility = if show_stand_in {
+                    Visibility::Inherited
+                } else {
+                    Visibility::Hidden
+                };
+                commands.entity(stand_in).insert(visibility);
+            }
+            let model_visibility = if show_stand_in {
+                Visibility::Hidden
+            } else {
+                Visibility::Inherited
+            };
+            for descendant in children_query.iter_descendants(entity) {
+                if let Ok(mut visibility) = mesh_query.get_mut(descendant) {
+                    *visibility = model_visibility;
+                }
+            }
+        }
+
+        // Stand-ins don't animate at all
+        let interval = if show_stand_in {
+            None
+        } else if !on_screen {
+            Some(OFFSCREEN_ANIMATION_INTERVAL)
+        } else if distance > THROTTLE_DISTANCE {
+            Some(FAR_ANIMATION_INTERVAL)
+        } else {
+            Some(1)
+        };
+        lod.frame = lod.frame.wrapping_add(1);
+        lod.skipped += dt;
+        let animate = interval.is_some_and(|interval| lod.frame % interval == 0);
+
+        let Ok((mut player, graph)) = player_query.get_mut(link.0) else {
+            continue;
+        };
+        if animate {
+            if let Some(graph) = lod.graph.take() {
+                commands.entity(link.0).insert(AnimationGraphHandle(graph));
+                // Catch up on the frames skipped, Bevy advancing this one
+                let catch_up = lod.skipped - dt;
+                for (_, animation) in player.playing_animations_mut() {
+                    let seek_time = animation.seek_time() + catch_up * animation.speed();
+                    animation.seek_to(seek_time);
+                }
+            }
+            lod.skipped = 0.0;
+        } else if let Some(graph) = graph {
+            lod.graph = Some(graph.0.clone());
+            commands.entity(link.0).remove::<AnimationGraphHandle>();
+        }
+    }
+}
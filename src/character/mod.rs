@@ -1,22 +1,90 @@
-use bevy::{animation::prelude::AnimationTransitions, app::Animation, gltf::Gltf, prelude::*, transform::TransformSystem};
+pub mod avatars;
+pub mod ik;
+pub mod locomotion;
+pub mod lod;
+pub mod name_tags;
 
-use crate::game_state::AppState;
-use crate::network::protocol::RemotePlayer;
+use bevy::{animation::prelude::AnimationTransitions, app::Animation, gltf::Gltf, prelude::*, transform::TransformSystem};
 
-/// Resource holding the loaded character GLTF handle.
-#[derive(Resource)]
-pub struct CharacterGltfHandle(pub Handle<Gltf>);
+use std::collections::HashMap;
 
-/// Resource holding the processed character assets (set after GLTF loads).
-#[derive(Resource)]
+use crate::camera::spectator::SpectatorCamera;
+use crate::camera::systems::ThirdPersonView;
+use crate::controls::Afk;
+use crate::game_state::AppState;
+use crate::network::protocol::{Emote, LocalPlayerId, RemotePlayer};
+use crate::player::identity::PlayerIdentity;
+use crate::player::{
+    CameraController, Crouching, Grounded, Player, Seated, Sprinting, Velocity, CROUCH_HEIGHT,
+    PLAYER_HEIGHT, SEATED_EYE_HEIGHT,
+};
+use avatars::{model_path, tint_color, AvatarModel};
+use ik::{apply_character_ik, setup_character_rig, HeadPitch};
+use locomotion::{
+    update_locomotion_state, update_strafe_blend, walk_animation_speed, LocomotionState,
+    StrafeClips, TURN_ANIMATION_SPEED,
+};
+use lod::update_character_lod;
+use name_tags::{cleanup_name_tags, spawn_name_tags, update_name_tags};
+
+pub use avatars::{AvatarSelection, CharacterAvatar};
+
+/// Resource holding the character GLTF handles, loaded as avatars are needed.
+#[derive(Resource, Default)]
+pub struct CharacterGltfHandles(pub HashMap<AvatarModel, Handle<Gltf>>);
+
+/// Resource holding the processed character models (added as each GLTF loads).
+#[derive(Resource, Default)]
 pub struct CharacterAssets {
+    pub models: HashMap<AvatarModel, CharacterModel>,
+}
+
+/// Scene and animations of one character model.
+pub struct CharacterModel {
     pub scene: Handle<Scene>,
     pub animations: Vec<Handle<AnimationClip>>,
     pub animation_graph: Handle<AnimationGraph>,
     pub idle_index: AnimationNodeIndex,
-    pub walk_index: AnimationNodeIndex,
+    pub strafe: StrafeClips,
+    pub run_index: AnimationNodeIndex,
+    pub jump_index: AnimationNodeIndex,
+    pub fall_index: AnimationNodeIndex,
+    pub land_index: AnimationNodeIndex,
+    pub sit_index: AnimationNodeIndex,
+    pub turn_index: AnimationNodeIndex,
+    pub crouch_index: AnimationNodeIndex,
+    pub crouch_walk_index: AnimationNodeIndex,
+    pub sleep_index: AnimationNodeIndex,
+    pub emote_indices: HashMap<Emote, AnimationNodeIndex>,
 }
 
+impl CharacterModel {
+    /// Animation played in a locomotion state.
+    fn state_index(&self, state: LocomotionState) -> AnimationNodeIndex {
+        match state {
+            LocomotionState::Idle => self.idle_index,
+            LocomotionState::Walk => self.strafe.blend,
+            LocomotionState::Run => self.run_index,
+            LocomotionState::Jump => self.jump_index,
+            LocomotionState::Fall => self.fall_index,
+            LocomotionState::Land => self.land_index,
+            LocomotionState::Sit => self.sit_index,
+            LocomotionState::TurnInPlace => self.turn_index,
+            LocomotionState::Crouch => self.crouch_index,
+            LocomotionState::CrouchWalk => self.crouch_walk_index,
+            LocomotionState::Sleep => self.sleep_index,
+        }
+    }
+}
+
+/// Offset from a character's feet to its model pivot (adjust if characters float or clip).
+pub const MODEL_OFFSET: f32 = -0.15;
+
+/// The local player's own character. It follows the player entity, which is
+/// at eye level, and is only shown in third person.
+#[derive(Component)]
+pub struct LocalCharacter;
+
 /// Component to mark that a character model needs animation setup.
 #[derive(Component)]
 pub struct NeedsAnimationSetup;
@@ -24,23 +92,62 @@ pub struct NeedsAnimationSetup;
 /// Component to track current animation state.
 #[derive(Component)]
 pub struct CharacterAnimationState {
-    pub is_walking: bool,
-    /// Time when walking was last detected (for decay)
-    pub last_walk_time: f32,
+    /// Velocity of the player, replicated for remote players
+    pub velocity: Vec3,
+    /// Yaw the player faces, for strafing and turning in place
+    pub yaw: f32,
+    /// Whether the player is sitting in a seat
+    pub is_seated: bool,
+    /// Whether the player is crouching
+    pub is_crouching: bool,
+    /// Whether the player is sprinting
+    pub is_sprinting: bool,
+    /// Whether the player is off the ground, jumping or falling
+    pub is_airborne: bool,
+    /// Whether the player is away from keyboard
+    pub is_afk: bool,
+    /// Emote playing until its clip ends, or the player moves or sits
+    pub emote: Option<Emote>,
+    /// Current state of the locomotion state machine
+    pub locomotion: LocomotionState,
+    /// Time spent in the current locomotion state
+    pub state_time: f32,
+    /// Yaw on the previous frame, to detect turning
+    pub last_yaw: Option<f32>,
+    /// Time since the player last turned
+    pub turn_time: f32,
     /// Track the last animation we played to detect changes
-    pub last_was_walking: Option<bool>,
+    pub last_animation: Option<AnimationNodeIndex>,
 }
 
 impl Default for CharacterAnimationState {
     fn default() -> Self {
         Self {
-            is_walking: false,
-            last_walk_time: 0.0,
-            last_was_walking: None, // None means we haven't started any animation yet
+            velocity: Vec3::ZERO,
+            yaw: 0.0,
+            is_seated: false,
+            is_crouching: false,
+            is_sprinting: false,
+            is_airborne: false,
+            is_afk: false,
+            emote: None,
+            locomotion: LocomotionState::Idle,
+            state_time: 0.0,
+            last_yaw: None,
+            turn_time: f32::MAX,
+            last_animation: None, // None means we haven't started any animation yet
         }
     }
 }
 
+impl CharacterAnimationState {
+    /// Start an emote, from the beginning even if it's already playing.
+    pub fn play_emote(&mut self, emote: Emote) {
+        self.emote = Some(emote);
+        self.last_animation = None;
+    }
+}
+
 /// Tracks if animation has been initialized for this character.
 #[derive(Component)]
 pub struct AnimationInitialized;
@@ -49,36 +156,56 @@ pub struct AnimationInitialized;
 #[derive(Component)]
 pub struct CharacterAnimationLink(pub Entity);
 
-/// Links a character root entity to its head bone entity for head tracking.
+/// Marks a character whose materials have been tinted for its avatar.
 #[derive(Component)]
-pub struct CharacterHeadLink(pub Entity);
+pub struct CharacterTinted;
 
 pub struct CharacterPlugin;
 
-/// How long walking state persists after movement stops (in seconds).
-const WALK_DECAY_TIME: f32 = 0.15;
+/// Clips tried for each emote, in order. The stock character models have no
+/// dedicated emote clips, so the closest gestures stand in for them.
+fn emote_clip_names(emote: Emote) -> &'static [&'static str] {
+    match emote {
+        Emote::Wave => &["wave", "interact-right"],
+        Emote::Clap => &["clap", "interact-left"],
+        Emote::ThumbsUp => &["thumbs-up", "emote-yes"],
+    }
+}
 
 impl Plugin for CharacterPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, start_loading_character)
-            // Process GLTF in all states so it's ready before InGame
-            .add_systems(Update, process_loaded_gltf)
+        app.init_resource::<AvatarSelection>()
+            .init_resource::<CharacterGltfHandles>()
+            .init_resource::<CharacterAssets>()
+            .add_systems(Startup, start_loading_character)
+            // Process GLTFs in all states so they're ready before InGame
+            .add_systems(Update, (load_avatar_models, process_loaded_gltf).chain())
             .add_systems(
                 Update,
                 (
                     attach_model_to_players_without_model,
-                    decay_walking_state,
+                    follow_local_player,
+                    update_locomotion_state,
                     setup_character_animation_graph,
-                    setup_head_bone_link,
+                    tint_character_materials,
+                    setup_character_rig,
                     start_character_animations,
+                    update_character_lod,
                 )
                     .chain()
                     .run_if(in_state(AppState::InGame)),
             )
-            // Run head rotation after animation systems
+            .add_systems(
+                Update,
+                (spawn_name_tags, update_name_tags)
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), cleanup_name_tags)
+            // IK poses bones on top of the animation
             .add_systems(
                 PostUpdate,
-                update_head_rotation
+                apply_character_ik
                     .after(Animation)
                     .before(TransformSystem::TransformPropagate)
                     .run_if(in_state(AppState::InGame)),
@@ -86,46 +213,124 @@ impl Plugin for CharacterPlugin {
     }
 }
 
-/// Decay walking state after movement stops.
-fn decay_walking_state(time: Res<Time>, mut query: Query<&mut CharacterAnimationState>) {
-    for mut anim_state in query.iter_mut() {
-        if anim_state.is_walking {
-            anim_state.last_walk_time += time.delta_secs();
-            if anim_state.last_walk_time > WALK_DECAY_TIME {
-                anim_state.is_walking = false;
-                anim_state.last_walk_time = 0.0;
-            }
-        }
+/// Keep the local character under the player: the body turns with the yaw,
+/// the head with the pitch. It's hidden in first person so it doesn't block the view.
+fn follow_local_player(
+    third_person: Res<ThirdPersonView>,
+    spectator: Res<SpectatorCamera>,
+    afk: Res<Afk>,
+    player_query: Query<
+        (
+            &Transform,
+            &CameraController,
+            &Velocity,
+            &Grounded,
+            Has<Seated>,
+            Has<Crouching>,
+            Has<Sprinting>,
+        ),
+        With<Player>,
+    >,
+    mut character_query: Query<
+        (
+            &mut Transform,
+            &mut Visibility,
+            &mut CharacterAnimationState,
+            Option<&mut HeadPitch>,
+        ),
+        (With<LocalCharacter>, Without<Player>),
+    >,
+) {
+    let Ok((player_transform, controller, velocity, grounded, seated, crouching, sprinting)) =
+        player_query.get_single()
+    else {
+        return;
+    };
+    let Ok((mut transform, mut visibility, mut anim_state, head_pitch)) =
+        character_query.get_single_mut()
+    else {
+        return;
+    };
+
+    let eye_height = if seated {
+        SEATED_EYE_HEIGHT
+    } else if crouching {
+        CROUCH_HEIGHT
+    } else {
+        PLAYER_HEIGHT
+    };
+    transform.translation = player_transform.translation + Vec3::Y * (MODEL_OFFSET - eye_height);
+    // Characters face +Z, the camera looks down -Z
+    transform.rotation = Quat::from_rotation_y(controller.yaw + std::f32::consts::PI);
+    if let Some(mut head_pitch) = head_pitch {
+        head_pitch.target = controller.pitch;
     }
+
+    anim_state.is_seated = seated;
+    anim_state.is_crouching = crouching;
+    anim_state.is_sprinting = sprinting;
+    anim_state.is_airborne = !grounded.0 && !seated;
+    anim_state.is_afk = afk.afk;
+    anim_state.velocity = if seated { Vec3::ZERO } else { velocity.0 };
+    anim_state.yaw = controller.yaw;
+
+    visibility.set_if_neq(if third_person.active || spectator.active {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
 }
 
-fn start_loading_character(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let gltf_handle: Handle<Gltf> = asset_server.load("characters/character-a.glb");
-    commands.insert_resource(CharacterGltfHandle(gltf_handle));
+/// Start loading the local player's own avatar, so it's ready before joining.
+fn start_loading_character(
+    asset_server: Res<AssetServer>,
+    selection: Res<AvatarSelection>,
+    mut handles: ResMut<CharacterGltfHandles>,
+) {
+    let model = selection.0.model;
+    let handle = asset_server.load(model_path(model));
+    handles.0.insert(model, handle);
     info!("Character GLTF loading started");
 }
 
-/// Process the GLTF once it's loaded and extract scene + animations.
+/// Start loading the models of characters whose avatar hasn't been loaded yet.
+fn load_avatar_models(
+    asset_server: Res<AssetServer>,
+    mut handles: ResMut<CharacterGltfHandles>,
+    query: Query<&CharacterAvatar, Added<CharacterAvatar>>,
+) {
+    for avatar in query.iter() {
+        let model = avatar.0.model;
+        handles.0.entry(model).or_insert_with(|| {
+            info!("Loading character model {}", model_path(model));
+            asset_server.load(model_path(model))
+        });
+    }
+}
+
+/// Process each GLTF once it's loaded and extract scene + animations.
 fn process_loaded_gltf(
-    mut commands: Commands,
-    gltf_handle: Option<Res<CharacterGltfHandle>>,
+    handles: Res<CharacterGltfHandles>,
     gltfs: Res<Assets<Gltf>>,
     mut graphs: ResMut<Assets<AnimationGraph>>,
-    existing_assets: Option<Res<CharacterAssets>>,
+    mut assets: ResMut<CharacterAssets>,
 ) {
-    // Skip if already processed
-    if existing_assets.is_some() {
-        return;
+    for (&model, handle) in handles.0.iter() {
+        // Skip if already processed
+        if assets.models.contains_key(&model) {
+            continue;
+        }
+        let Some(gltf) = gltfs.get(handle) else {
+            continue;
+        };
+        let character_model = build_character_model(gltf, &mut graphs);
+        assets.models.insert(model, character_model);
+        info!("Character model {} processed and ready", model_path(model));
     }
+}
 
-    let Some(handle) = gltf_handle else {
-        return;
-    };
-
-    let Some(gltf) = gltfs.get(&handle.0) else {
-        return;
-    };
-
+/// Extract the scene and animations of a loaded character GLTF.
+fn build_character_model(gltf: &Gltf, graphs: &mut Assets<AnimationGraph>) -> CharacterModel {
     // Get the default scene
     let scene = gltf.default_scene.clone().unwrap_or_else(|| {
         gltf.scenes.first().cloned().expect("GLTF has no scenes")
@@ -154,39 +359,113 @@ fn process_loaded_gltf(
     // Create animation graph with static (index 0) for idle pose and walk (index 2)
     // Using "static" instead of "idle" because idle may not animate legs
     let mut graph = AnimationGraph::new();
-    let (idle_index, walk_index) = if animations.len() >= 3 {
-        let idle = graph.add_clip(animations[0].clone(), 1.0, graph.root); // "static" - full body neutral pose
-        let walk = graph.add_clip(animations[2].clone(), 1.0, graph.root); // "walk"
-        (idle, walk)
+    let root = graph.root;
+    let (idle_index, walk_clip) = if animations.len() >= 3 {
+        let idle = graph.add_clip(animations[0].clone(), 1.0, root); // "static" - full body neutral pose
+        (idle, Some(animations[2].clone())) // "walk"
     } else if !animations.is_empty() {
-        let idx = graph.add_clip(animations[0].clone(), 1.0, graph.root);
-        (idx, idx)
+        let idx = graph.add_clip(animations[0].clone(), 1.0, root);
+        (idx, Some(animations[0].clone()))
     } else {
         warn!("No animations found in character GLTF!");
-        (graph.root, graph.root)
+        (root, None)
+    };
+    let walk_index = match &walk_clip {
+        Some(clip) => graph.add_clip(clip.clone(), 1.0, root),
+        None => idle_index,
+    };
+
+    // Walking blends a clip per direction. The stock models only walk forward,
+    // so they strafe with it too and walk back by playing it in reverse.
+    let strafe_blend = graph.add_blend(1.0, root);
+    let walk_forward = match walk_clip {
+        Some(clip) => graph.add_clip(clip, 1.0, strafe_blend),
+        None => idle_index,
     };
+    let walk_back = add_named_clip(gltf, &mut graph, &["walk-back"], strafe_blend);
+    let walk_left = add_named_clip(gltf, &mut graph, &["walk-left"], strafe_blend);
+    let walk_right = add_named_clip(gltf, &mut graph, &["walk-right"], strafe_blend);
+    let strafe = StrafeClips {
+        blend: strafe_blend,
+        forward: walk_forward,
+        back: walk_back.unwrap_or(walk_forward),
+        left: walk_left.unwrap_or(walk_forward),
+        right: walk_right.unwrap_or(walk_forward),
+        back_reversed: walk_back.is_none(),
+    };
+
+    // Other locomotion clips fall back to the closest one the model has
+    let run_index = add_named_clip(gltf, &mut graph, &["sprint"], root).unwrap_or(walk_index);
+    let jump_index = add_named_clip(gltf, &mut graph, &["jump"], root).unwrap_or(idle_index);
+    let fall_index = add_named_clip(gltf, &mut graph, &["fall"], root).unwrap_or(jump_index);
+    let land_index = add_named_clip(gltf, &mut graph, &["land"], root).unwrap_or(idle_index);
+    let turn_index = add_named_clip(gltf, &mut graph, &["turn"], root).unwrap_or(walk_index);
+    let crouch_index = add_named_clip(gltf, &mut graph, &["crouch"], root).unwrap_or(idle_index);
+    let crouch_walk_index =
+        add_named_clip(gltf, &mut graph, &["crouch-walk"], root).unwrap_or(walk_index);
+
+    // The sit pose is looked up by name, falling back to idle if the model lacks one
+    let sit_index = add_named_clip(gltf, &mut graph, &["sit"], root).unwrap_or_else(|| {
+        warn!("Character GLTF has no 'sit' animation, seated players will stand");
+        idle_index
+    });
+
+    // The stock models can't sleep, so they lie down as if knocked out
+    let sleep_index = add_named_clip(gltf, &mut graph, &["sleep", "idle-sleep", "die"], root)
+        .unwrap_or(idle_index);
+
+    let mut emote_indices = HashMap::new();
+    for emote in Emote::ALL {
+        let clip = add_named_clip(gltf, &mut graph, emote_clip_names(emote), root);
+        let index = clip.unwrap_or_else(|| {
+            warn!("Character GLTF has no clip for the {} emote", emote.label());
+            idle_index
+        });
+        emote_indices.insert(emote, index);
+    }
 
     let animation_graph = graphs.add(graph);
 
-    commands.insert_resource(CharacterAssets {
+    CharacterModel {
         scene,
         animations,
         animation_graph,
         idle_index,
-        walk_index,
-    });
+        strafe,
+        run_index,
+        jump_index,
+        fall_index,
+        land_index,
+        sit_index,
+        turn_index,
+        crouch_index,
+        crouch_walk_index,
+        sleep_index,
+        emote_indices,
+    }
+}
 
-    info!("Character assets processed and ready");
+/// Add the first of the named clips the GLTF has to the graph, if any.
+fn add_named_clip(
+    gltf: &Gltf,
+    graph: &mut AnimationGraph,
+    names: &[&str],
+    parent: AnimationNodeIndex,
+) -> Option<AnimationNodeIndex> {
+    let clip = names
+        .iter()
+        .find_map(|name| gltf.named_animations.get(*name))?;
+    Some(graph.add_clip(clip.clone(), 1.0, parent))
 }
 
-/// Attach character model to remote players that were spawned without one.
+/// Attach character model to players that were spawned without one.
 fn attach_model_to_players_without_model(
     mut commands: Commands,
-    character_assets: Option<Res<CharacterAssets>>,
+    character_assets: Res<CharacterAssets>,
     query: Query<
-        (Entity, &Transform),
+        (Entity, &Transform, &CharacterAvatar),
         (
-            With<RemotePlayer>,
+            Or<(With<RemotePlayer>, With<LocalCharacter>)>,
             With<CharacterAnimationState>,
             Without<NeedsAnimationSetup>,
             Without<AnimationInitialized>,
@@ -194,19 +473,19 @@ fn attach_model_to_players_without_model(
     >,
     scene_query: Query<&SceneRoot>,
 ) {
-    let Some(assets) = character_assets else {
-        return;
-    };
-
-    for (entity, transform) in query.iter() {
+    for (entity, transform, avatar) in query.iter() {
         // Check if this entity already has a scene
         if scene_query.get(entity).is_ok() {
             continue;
         }
+        // Wait for this avatar's model to load
+        let Some(model) = character_assets.models.get(&avatar.0.model) else {
+            continue;
+        };
 
         info!("Attaching character model to player {:?} at {:?}", entity, transform.translation);
         commands.entity(entity).insert((
-            SceneRoot(assets.scene.clone()),
+            SceneRoot(model.scene.clone()),
             NeedsAnimationSetup,
             // Update scale if not already set
             Transform {
@@ -220,16 +499,19 @@ fn attach_model_to_players_without_model(
 /// Phase 1: Find AnimationPlayer in hierarchy and add the animation graph.
 fn setup_character_animation_graph(
     mut commands: Commands,
-    character_assets: Option<Res<CharacterAssets>>,
-    query: Query<Entity, (With<NeedsAnimationSetup>, Without<AnimationInitialized>)>,
+    character_assets: Res<CharacterAssets>,
+    query: Query<
+        (Entity, &CharacterAvatar),
+        (With<NeedsAnimationSetup>, Without<AnimationInitialized>),
+    >,
     children_query: Query<&Children>,
     animation_player_query: Query<Entity, With<AnimationPlayer>>,
 ) {
-    let Some(assets) = character_assets else {
-        return;
-    };
+    for (root_entity, avatar) in query.iter() {
+        let Some(model) = character_assets.models.get(&avatar.0.model) else {
+            continue;
+        };
 
-    for root_entity in query.iter() {
         // Find the AnimationPlayer entity in the hierarchy
         if let Some(anim_entity) =
             find_entity_with_component(root_entity, &children_query, &animation_player_query)
@@ -242,7 +524,7 @@ fn setup_character_animation_graph(
             // Add the animation graph and transitions to the animation player entity
             commands
                 .entity(anim_entity)
-                .insert(AnimationGraphHandle(assets.animation_graph.clone()))
+                .insert(AnimationGraphHandle(model.animation_graph.clone()))
                 .insert(AnimationTransitions::new());
 
             // Mark as initialized and store the link to animation player
@@ -255,41 +537,147 @@ fn setup_character_animation_graph(
     }
 }
 
+/// Share of a player's own color mixed into their character's tint.
+const IDENTITY_TINT: f32 = 0.2;
+
+/// Tint the materials of newly spawned characters with their avatar's color,
+/// touched with their player's color once the player list has it. Each
+/// character gets its own copies, since the scene's materials are shared.
+fn tint_character_materials(
+    mut commands: Commands,
+    query: Query<
+        (Entity, &CharacterAvatar, Option<&RemotePlayer>),
+        (With<AnimationInitialized>, Without<CharacterTinted>),
+    >,
+    local_id: Option<Res<LocalPlayerId>>,
+    identity: Res<PlayerIdentity>,
+    children_query: Query<&Children>,
+    material_query: Query<&MeshMaterial3d<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (root_entity, avatar, remote) in query.iter() {
+        let player = remote.map(|r| r.id).or(local_id.as_ref().map(|id| id.0));
+        let Some(identity_color) = player.and_then(|id| identity.color(id)) else {
+            continue;
+        };
+        commands.entity(root_entity).insert(CharacterTinted);
+        let tint = tint_color(avatar.0.tint)
+            .unwrap_or(Color::WHITE)
+            .mix(&identity_color, IDENTITY_TINT);
+
+        let mut tinted: HashMap<AssetId<StandardMaterial>, Handle<StandardMaterial>> =
+            HashMap::new();
+        for entity in children_query.iter_descendants(root_entity) {
+            let Ok(material) = material_query.get(entity) else {
+                continue;
+            };
+            let handle = match tinted.get(&material.0.id()) {
+                Some(handle) => handle.clone(),
+                None => {
+                    let Some(original) = materials.get(&material.0) else {
+                        continue;
+                    };
+                    let mut copy = original.clone();
+                    let (base, tint) = (copy.base_color.to_linear(), tint.to_linear());
+                    copy.base_color = LinearRgba::new(
+                        base.red * tint.red,
+                        base.green * tint.green,
+                        base.blue * tint.blue,
+                        base.alpha,
+                    )
+                    .into();
+                    let handle = materials.add(copy);
+                    tinted.insert(material.0.id(), handle.clone());
+                    handle
+                }
+            };
+            commands.entity(entity).insert(MeshMaterial3d(handle));
+        }
+    }
+}
+
 /// Phase 2: Start and update animations based on movement state.
 fn start_character_animations(
-    character_assets: Option<Res<CharacterAssets>>,
-    mut character_query: Query<(&CharacterAnimationLink, &mut CharacterAnimationState), With<AnimationInitialized>>,
+    character_assets: Res<CharacterAssets>,
+    mut character_query: Query<
+        (
+            &CharacterAnimationLink,
+            &CharacterAvatar,
+            &mut CharacterAnimationState,
+        ),
+        With<AnimationInitialized>,
+    >,
     mut animation_query: Query<(&mut AnimationPlayer, &mut AnimationTransitions)>,
 ) {
-    let Some(assets) = character_assets else {
-        return;
-    };
-
-    // Skip if no animations
-    if assets.animations.is_empty() {
-        return;
-    }
+    for (link, avatar, mut anim_state) in character_query.iter_mut() {
+        let Some(assets) = character_assets.models.get(&avatar.0.model) else {
+            continue;
+        };
+        // Skip if no animations
+        if assets.animations.is_empty() {
+            continue;
+        }
 
-    for (link, mut anim_state) in character_query.iter_mut() {
         if let Ok((mut player, mut transitions)) = animation_query.get_mut(link.0) {
-            // Detect state change (None means first time, always trigger)
-            let state_changed = anim_state.last_was_walking != Some(anim_state.is_walking);
+            // Moving, jumping or sitting down cuts an emote short
+            let state = anim_state.locomotion;
+            if !state.allows_emote() {
+                anim_state.emote = None;
+            }
 
-            // Choose animation based on movement state
-            let target_anim = if anim_state.is_walking {
-                assets.walk_index
-            } else {
-                assets.idle_index
+            // Emotes play once, then the character goes back to idle
+            let emote_index = anim_state.emote.map(|emote| assets.emote_indices[&emote]);
+            if let Some(index) = emote_index {
+                let active = player.animation(index);
+                let finished = anim_state.last_animation == Some(index)
+                    && active.is_none_or(|active| active.is_finished());
+                if finished {
+                    anim_state.emote = None;
+                    anim_state.last_animation = None;
+                }
+            }
+
+            // Choose animation based on locomotion state
+            let target_anim = match anim_state.emote {
+                Some(emote) => assets.emote_indices[&emote],
+                None => assets.state_index(state),
             };
 
+            // Detect state change (None means first time, always trigger)
+            let state_changed = anim_state.last_animation != Some(target_anim);
+
             // Switch animation when state changes
             if state_changed {
-                anim_state.last_was_walking = Some(anim_state.is_walking);
+                anim_state.last_animation = Some(target_anim);
                 // Use transitions for smooth blending (150ms crossfade)
-                transitions
-                    .play(&mut player, target_anim, std::time::Duration::from_millis(150))
-                    .repeat();
+                let animation = transitions.play(
+                    &mut player,
+                    target_anim,
+                    std::time::Duration::from_millis(150),
+                );
+                if anim_state.emote.is_none() && state.repeats() {
+                    animation.repeat();
+                }
             }
+
+            // Turning in place shuffles slowly and crouch walking follows the
+            // movement speed, everything else plays at normal speed
+            let speed = match (anim_state.emote, state) {
+                (None, LocomotionState::TurnInPlace) => TURN_ANIMATION_SPEED,
+                (None, LocomotionState::CrouchWalk) => walk_animation_speed(anim_state.velocity),
+                _ => 1.0,
+            };
+            if target_anim != assets.strafe.blend {
+                if let Some(animation) = player.animation_mut(target_anim) {
+                    animation.set_speed(speed);
+                }
+            }
+            update_strafe_blend(
+                &mut player,
+                &assets.strafe,
+                anim_state.velocity,
+                anim_state.yaw,
+            );
         }
     }
 }
@@ -344,63 +732,3 @@ fn find_entity_by_name(
 
     None
 }
-
-/// Find and link the head bone for characters that have been initialized.
-fn setup_head_bone_link(
-    mut commands: Commands,
-    query: Query<Entity, (With<AnimationInitialized>, Without<CharacterHeadLink>)>,
-    children_query: Query<&Children>,
-    name_query: Query<&Name>,
-) {
-    for root_entity in query.iter() {
-        // Try common head bone names
-        let head_names = ["Head", "head", "mixamorig:Head", "Bone.Head"];
-
-        for head_name in head_names {
-            if let Some(head_entity) =
-                find_entity_by_name(root_entity, head_name, &children_query, &name_query)
-            {
-                info!("Found head bone '{}' for character {:?}", head_name, root_entity);
-                commands
-                    .entity(root_entity)
-                    .insert(CharacterHeadLink(head_entity));
-                break;
-            }
-        }
-    }
-}
-
-/// Stores the interpolated pitch for smooth head movement.
-#[derive(Component, Default)]
-pub struct HeadPitch {
-    pub current: f32,
-}
-
-/// Update head rotation based on pitch from NetworkTransform.
-/// This runs after transform propagation.
-fn update_head_rotation(
-    mut commands: Commands,
-    character_query: Query<(Entity, &CharacterHeadLink, &crate::network::protocol::NetworkTransform), Without<HeadPitch>>,
-    mut head_query: Query<(Entity, &CharacterHeadLink, &crate::network::protocol::NetworkTransform, &mut HeadPitch)>,
-    mut transform_query: Query<&mut Transform>,
-    time: Res<Time>,
-) {
-    // Add HeadPitch component to new characters
-    for (entity, _, _) in character_query.iter() {
-        commands.entity(entity).insert(HeadPitch::default());
-    }
-
-    // Update head rotation for characters with HeadPitch
-    for (_entity, head_link, net_transform, mut head_pitch) in head_query.iter_mut() {
-        // Smoothly interpolate pitch
-        let target_pitch = net_transform.target_pitch;
-        head_pitch.current = head_pitch.current + (target_pitch - head_pitch.current) * time.delta_secs() * 10.0;
-
-        if let Ok(mut head_transform) = transform_query.get_mut(head_link.0) {
-            // Get the current rotation from animation and apply pitch on top
-            let current_rot = head_transform.rotation;
-            let pitch_rotation = Quat::from_rotation_x(-head_pitch.current);
-            head_transform.rotation = current_rot * pitch_rotation;
-        }
-    }
-}
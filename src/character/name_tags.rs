@@ -0,0 +1,185 @@
+//! Name tags over remote players. Each tag is a UI node kept over its
+//! character's head, so it always faces the camera. Tags fade out with
+//! distance and behind walls, found by a ray from the camera.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::{CharacterAnimationState, MODEL_OFFSET};
+use crate::network::protocol::RemotePlayer;
+use crate::network::PlayerList;
+use crate::player::identity::PlayerIdentity;
+use crate::player::{PlayerCamera, CROUCH_HEIGHT, PLAYER_HEIGHT, SEATED_EYE_HEIGHT};
+
+/// Height of a tag over the character's eyes.
+const TAG_HEIGHT_ABOVE_EYES: f32 = 0.45;
+
+/// Distance at which tags start fading out.
+const FADE_START_DISTANCE: f32 = 10.0;
+
+/// Distance beyond which tags are hidden.
+const FADE_END_DISTANCE: f32 = 20.0;
+
+/// Rate tags fade in and out, per second, when walls come between.
+const OCCLUSION_FADE_SPEED: f32 = 8.0;
+
+/// Gap left before the tag when checking for walls, so the character's own
+/// surroundings, e.g. the seat it's on, don't hide it.
+const OCCLUSION_MARGIN: f32 = 0.5;
+
+/// Width of a tag, in pixels. Names are centered in it.
+const TAG_WIDTH: f32 = 240.0;
+
+const TAG_BG_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.5);
+const TAG_TEXT_COLOR: Color = Color::srgb(0.95, 0.95, 0.95);
+
+/// A name tag's UI node, following the character it names.
+#[derive(Component)]
+pub struct NameTag {
+    character: Entity,
+    label: Entity,
+    /// Opacity after occlusion, before distance fading.
+    visible: f32,
+}
+
+/// Marks a character whose name tag has been spawned.
+#[derive(Component)]
+pub struct NameTagged;
+
+/// Spawn a name tag for each remote character that doesn't have one.
+pub fn spawn_name_tags(
+    mut commands: Commands,
+    query: Query<Entity, (With<RemotePlayer>, Without<NameTagged>)>,
+) {
+    for character in query.iter() {
+        commands.entity(character).insert(NameTagged);
+        let label = commands
+            .spawn((
+                Node {
+                    padding: UiRect::axes(Val::Px(8.0), Val::Px(2.0)),
+                    ..default()
+                },
+                BackgroundColor(TAG_BG_COLOR),
+                Text::new(""),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(TAG_TEXT_COLOR),
+            ))
+            .id();
+        commands
+            .spawn((
+                NameTag {
+                    character,
+                    label,
+                    visible: 0.0,
+                },
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: Val::Px(TAG_WIDTH),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                Visibility::Hidden,
+            ))
+            .add_child(label);
+    }
+}
+
+/// System to move each tag over its character's head, name it, and fade it
+/// with distance and walls in between. Tags of characters that left go too.
+pub fn update_name_tags(
+    mut commands: Commands,
+    time: Res<Time>,
+    player_list: Res<PlayerList>,
+    identity: Res<PlayerIdentity>,
+    rapier_context: ReadDefaultRapierContext,
+    camera_query: Query<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    character_query: Query<(&RemotePlayer, &GlobalTransform, &CharacterAnimationState)>,
+    mut tag_query: Query<(Entity, &mut NameTag, &mut Node, &mut Visibility)>,
+    mut label_query: Query<(&mut Text, &mut TextColor, &mut BackgroundColor)>,
+) {
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let context = rapier_context.single();
+    let eye = camera_transform.translation();
+
+    for (tag_entity, mut tag, mut node, mut visibility) in tag_query.iter_mut() {
+        let Ok((remote, transform, anim_state)) = character_query.get(tag.character) else {
+            commands.entity(tag_entity).despawn_recursive();
+            continue;
+        };
+
+        let eye_height = if anim_state.is_seated {
+            SEATED_EYE_HEIGHT
+        } else if anim_state.is_crouching {
+            CROUCH_HEIGHT
+        } else {
+            PLAYER_HEIGHT
+        };
+        let anchor =
+            transform.translation() + Vec3::Y * (eye_height - MODEL_OFFSET + TAG_HEIGHT_ABOVE_EYES);
+        let to_anchor = anchor - eye;
+        let distance = to_anchor.length();
+        let position = camera.world_to_viewport(camera_transform, anchor).ok();
+
+        let occluded = distance > OCCLUSION_MARGIN
+            && context
+                .cast_ray(
+                    eye,
+                    to_anchor / distance,
+                    distance - OCCLUSION_MARGIN,
+                    true,
+                    QueryFilter::default().exclude_sensors(),
+                )
+                .is_some();
+        let target = if occluded { 0.0 } else { 1.0 };
+        tag.visible = tag
+            .visible
+            .lerp(target, (OCCLUSION_FADE_SPEED * time.delta_secs()).min(1.0));
+
+        let distance_fade = 1.0
+            - ((distance - FADE_START_DISTANCE) / (FADE_END_DISTANCE - FADE_START_DISTANCE))
+                .clamp(0.0, 1.0);
+        let alpha = tag.visible * distance_fade;
+        let Some(position) = position.filter(|_| alpha > 0.01) else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+        visibility.set_if_neq(Visibility::Inherited);
+        node.left = Val::Px(position.x - TAG_WIDTH / 2.0);
+        node.top = Val::Px(position.y);
+
+        let Ok((mut text, mut text_color, mut background)) = label_query.get_mut(tag.label) else {
+            continue;
+        };
+        let name = player_list
+            .players
+            .iter()
+            .find(|p| p.id == remote.id)
+            .map(|p| {
+                if p.afk {
+                    format!("{} (AFK)", p.display_name())
+                } else {
+                    p.display_name()
+                }
+            })
+            .unwrap_or_else(|| format!("Player {}", remote.id));
+        if text.0 != name {
+            text.0 = name;
+        }
+        // Names show in their player's color
+        let color = identity.color(remote.id).unwrap_or(TAG_TEXT_COLOR);
+        text_color.0 = color.with_alpha(alpha);
+        background.0 = TAG_BG_COLOR.with_alpha(TAG_BG_COLOR.alpha() * alpha);
+    }
+}
+
+/// Remove the name tags with the session.
+pub fn cleanup_name_tags(mut commands: Commands, query: Query<Entity, With<NameTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,228 @@
+//! Command line arguments, for hosting or joining a session without going
+//! through the menus, e.g. `zine --host --name "Movie Night"` or
+//! `zine --join 192.168.1.5:5000` or `zine --join R2M02-18KH2`. They
+//! override the saved settings. A client's session can be recorded with
+//! `--record-network` and played back with `--replay-network`.
+
+use bevy::prelude::*;
+use clap::Parser;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::game_state::AppState;
+use crate::network::conditioner::{NetworkConditions, MAX_SIMULATED_DELAY};
+use crate::network::discovery::{LanSession, GAME_PORT};
+use crate::network::invite::decode_invite;
+use crate::network::replay::{NetworkRecording, NetworkReplay, REPLAY_HOST};
+use crate::network::{InstanceName, SelectedSession, SessionName};
+use crate::settings::{AudioSettings, Nickname};
+use crate::world::{CurrentRoom, RoomId};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Watch streams together in a shared room")]
+pub struct Args {
+    /// Host a session straight away.
+    #[arg(long, conflicts_with = "join")]
+    host: bool,
+    /// Name the hosted session is announced under on the LAN.
+    #[arg(long, requires = "host")]
+    name: Option<String>,
+    /// Room to host in, e.g. "Lounge" or the name of a room scene.
+    #[arg(long, requires = "host", value_parser = parse_room)]
+    room: Option<RoomId>,
+    /// Join the session at this address or invite code. The port defaults
+    /// to the game port.
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_address)]
+    join: Option<SocketAddr>,
+    /// Record every datagram received from the host to this file, to
+    /// reproduce a problem with `--replay-network`.
+    #[arg(long, value_name = "FILE", conflicts_with = "host")]
+    record_network: Option<PathBuf>,
+    /// Watch a recorded session again instead of joining one.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["host", "join"])]
+    replay_network: Option<PathBuf>,
+    /// Name shown to other players.
+    #[arg(long)]
+    nickname: Option<String>,
+    /// Neither send nor play stream audio.
+    #[arg(long)]
+    no_audio: bool,
+    /// Log debug messages and frame time diagnostics.
+    #[arg(long, short)]
+    pub verbose: bool,
+    /// Extra log filter directives, e.g. "zine::network=trace".
+    #[arg(long, value_name = "FILTER")]
+    pub log: Option<String>,
+    /// Name for this copy of the game, shown in its window title and next to
+    /// the sessions it hosts, to tell copies on one machine apart.
+    #[arg(long, value_name = "NAME")]
+    pub instance: Option<String>,
+    /// Simulate a bad network for development: latency and jitter in
+    /// milliseconds up to 10 s, loss and reordering in percent, e.g.
+    /// "latency=100,jitter=30,loss=2,reorder=1".
+    #[arg(long, hide = true, value_name = "CONDITIONS", value_parser = parse_network_conditions)]
+    simulate_network: Option<NetworkConditions>,
+}
+
+impl Args {
+    /// Title of the game's window, naming the instance if it has a name.
+    pub fn window_title(&self) -> String {
+        match &self.instance {
+            Some(instance) => format!("Zine ({})", instance),
+            None => "Zine".to_string(),
+        }
+    }
+
+    /// Override the settings and skip to hosting or joining, as asked. Runs
+    /// once the plugins have inserted their resources.
+    pub fn apply(self, app: &mut App) {
+        let world = app.world_mut();
+        if let Some(nickname) = self.nickname {
+            world.insert_resource(Nickname(nickname));
+        }
+        if self.no_audio {
+            let mut audio = world.resource_mut::<AudioSettings>();
+            audio.stream_audio = false;
+            audio.muted = true;
+        }
+        if let Some(name) = self.name {
+            world.insert_resource(SessionName(name));
+        }
+        world.insert_resource(InstanceName(self.instance));
+        if let Some(path) = self.record_network {
+            world.insert_resource(NetworkRecording(path));
+        }
+        if let Some(conditions) = self.simulate_network {
+            warn!("Simulating network conditions: {:?}", conditions);
+            world.insert_resource(conditions);
+        }
+
+        let state = if self.host {
+            world.insert_resource(CurrentRoom(self.room.unwrap_or_default()));
+            AppState::Hosting
+        } else if let Some(path) = self.replay_network {
+            world.insert_resource(SelectedSession(LanSession {
+                name: format!("Replay of {}", path.display()),
+                address: REPLAY_HOST,
+                player_count: 0,
+                room: RoomId::default(),
+                instance: None,
+            }));
+            world.insert_resource(NetworkReplay(path));
+            AppState::Connecting
+        } else if let Some(address) = self.join {
+            world.insert_resource(SelectedSession(LanSession {
+                name: address.to_string(),
+                address,
+                player_count: 0,
+                room: RoomId::default(),
+                instance: None,
+            }));
+            AppState::Connecting
+        } else {
+            return;
+        };
+        world.resource_mut::<NextState<AppState>>().set(state);
+    }
+}
+
+/// A room by its name, ignoring case.
+fn parse_room(name: &str) -> Result<RoomId, String> {
+    let rooms = RoomId::available();
+    if let Some(room) = rooms
+        .iter()
+        .find(|room| room.name().eq_ignore_ascii_case(name))
+    {
+        return Ok(room.clone());
+    }
+    let names: Vec<&str> = rooms.iter().map(RoomId::name).collect();
+    Err(format!("no such room, pick one of: {}", names.join(", ")))
+}
+
+/// Network conditions as comma separated `name=value` pairs. Conditions
+/// left out aren't simulated.
+fn parse_network_conditions(spec: &str) -> Result<NetworkConditions, String> {
+    let mut conditions = NetworkConditions::default();
+    for pair in spec.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let (name, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected name=value, got: {}", pair))?;
+        let value: f32 = value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|value: &f32| value.is_finite() && *value >= 0.0)
+            .ok_or_else(|| format!("expected a number of zero or more, got: {}", value))?;
+        let millis = || {
+            Duration::try_from_secs_f32(value / 1000.0)
+                .ok()
+                .filter(|delay| *delay <= MAX_SIMULATED_DELAY)
+                .ok_or_else(|| {
+                    format!(
+                        "too long a delay: {} ms, at most {} ms",
+                        value,
+                        MAX_SIMULATED_DELAY.as_millis()
+                    )
+                })
+        };
+        match name.trim() {
+            "latency" => conditions.latency = millis()?,
+            "jitter" => conditions.jitter = millis()?,
+            "loss" => conditions.loss = (value / 100.0).min(1.0),
+            "reorder" => conditions.reorder = (value / 100.0).min(1.0),
+            name => {
+                return Err(format!(
+                    "unknown condition {}, pick from latency, jitter, loss and reorder",
+                    name
+                ))
+            }
+        }
+    }
+    Ok(conditions)
+}
+
+/// An address with a port, just an IP joined on the game port, or an
+/// invite code.
+fn parse_address(address: &str) -> Result<SocketAddr, String> {
+    address
+        .parse::<SocketAddr>()
+        .or_else(|_| {
+            address
+                .parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, GAME_PORT))
+        })
+        .ok()
+        .or_else(|| decode_invite(address))
+        .ok_or_else(|| format!("not an IP address or invite code: {}", address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_conditions_parse() {
+        let conditions = parse_network_conditions("latency=125, jitter=250,loss=150").unwrap();
+        assert_eq!(conditions.latency, Duration::from_millis(125));
+        assert_eq!(conditions.jitter, Duration::from_millis(250));
+        assert_eq!(conditions.loss, 1.0);
+    }
+
+    #[test]
+    fn unusable_network_conditions_are_refused() {
+        let specs = [
+            "latency=inf",
+            "jitter=NaN",
+            "latency=1e30",
+            "latency=1e22",
+            "jitter=10001",
+            "loss=-1",
+            "lag=5",
+            "latency",
+        ];
+        for spec in specs {
+            assert!(parse_network_conditions(spec).is_err(), "{}", spec);
+        }
+    }
+}
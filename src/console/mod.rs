@@ -0,0 +1,216 @@
+//! Developer console, opened in game with the backtick key, for commands
+//! like `net stats` or `tp 0 2 0` while debugging.
+//!
+//! Commands live with the modules they belong to. Each is a one-shot system
+//! given the words typed after its name and returning what to print,
+//! registered with `App::add_console_command`.
+
+pub mod ui;
+
+use bevy::ecs::system::SystemId;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::game_state::{AppState, PauseState};
+use ui::{cleanup_console_ui, edit_console_input, update_console_ui};
+
+/// Lines the console keeps, older ones scroll away.
+const MAX_LINES: usize = 200;
+
+/// Commands kept to step back through with the arrow keys.
+const MAX_HISTORY: usize = 50;
+
+/// Resource with the console's state, whether or not it's open.
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    /// Line being typed.
+    pub input: String,
+    /// Everything printed, oldest first.
+    pub lines: VecDeque<String>,
+    /// Commands entered, oldest first.
+    history: Vec<String>,
+    /// Entry of the history being shown in the input, while stepping through it.
+    history_index: Option<usize>,
+    /// Lines entered, waiting to be run.
+    submitted: Vec<String>,
+}
+
+impl Console {
+    /// Add text to the console, a line at a time.
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.lines.len() >= MAX_LINES {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line.to_string());
+        }
+    }
+
+    /// Run the line being typed.
+    fn submit(&mut self) {
+        let line = std::mem::take(&mut self.input);
+        self.history_index = None;
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.history.len() >= MAX_HISTORY {
+            self.history.remove(0);
+        }
+        self.history.push(line.clone());
+        self.submitted.push(line);
+    }
+
+    /// Show an earlier (`step` below 0) or later command from the history
+    /// in the input.
+    fn step_history(&mut self, step: isize) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        let index = match (self.history_index, step < 0) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index < last => Some(index + 1),
+            (Some(_), false) => None,
+        };
+        self.history_index = index;
+        self.input = index.map(|i| self.history[i].clone()).unwrap_or_default();
+    }
+}
+
+/// A console command, run as a one-shot system.
+struct ConsoleCommand {
+    /// Words typed to run it, e.g. "net stats".
+    name: &'static str,
+    /// What to type after the name, shown by `help`.
+    usage: &'static str,
+    system: SystemId<In<Vec<String>>, String>,
+}
+
+/// Resource with every command the console knows.
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(Vec<ConsoleCommand>);
+
+impl ConsoleCommands {
+    /// The command a line runs, the one with the most of the line's first
+    /// words as its name, and how many words that is.
+    fn find(&self, words: &[String]) -> Option<(&ConsoleCommand, usize)> {
+        self.0
+            .iter()
+            .filter_map(|command| {
+                let name: Vec<&str> = command.name.split(' ').collect();
+                let matches = name.len() <= words.len()
+                    && name
+                        .iter()
+                        .zip(words)
+                        .all(|(part, word)| word.eq_ignore_ascii_case(part));
+                matches.then_some((command, name.len()))
+            })
+            .max_by_key(|(_, len)| *len)
+    }
+}
+
+/// Registering console commands, for modules to add their own.
+pub trait AddConsoleCommand {
+    /// Run `system` when a line starting with `name` is entered, with the
+    /// words after it, printing what it returns.
+    fn add_console_command<M>(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        system: impl IntoSystem<In<Vec<String>>, String, M> + 'static,
+    ) -> &mut Self;
+}
+
+impl AddConsoleCommand for App {
+    fn add_console_command<M>(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        system: impl IntoSystem<In<Vec<String>>, String, M> + 'static,
+    ) -> &mut Self {
+        let world = self.world_mut();
+        let system = world.register_system(system);
+        world
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .0
+            .push(ConsoleCommand {
+                name,
+                usage,
+                system,
+            });
+        self
+    }
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .init_resource::<ConsoleCommands>()
+            .add_console_command("help", "", help_command)
+            .add_console_command("clear", "", clear_command)
+            .add_systems(PreUpdate, block_game_keys.after(InputSystem))
+            .add_systems(
+                Update,
+                (edit_console_input, run_console_commands, update_console_ui)
+                    .chain()
+                    .run_if(in_state(PauseState::Running)),
+            )
+            .add_systems(OnEnter(PauseState::Paused), cleanup_console_ui)
+            .add_systems(OnExit(AppState::InGame), cleanup_console_ui);
+    }
+}
+
+/// Run condition for gameplay input, which the console takes while open.
+pub fn console_closed(console: Res<Console>) -> bool {
+    !console.open
+}
+
+/// System to hide the keyboard from the game while typing in the console,
+/// so typed letters don't also move the player or open menus.
+fn block_game_keys(console: Res<Console>, mut keyboard: ResMut<ButtonInput<KeyCode>>) {
+    if console.open {
+        keyboard.reset_all();
+    }
+}
+
+/// System to run the lines entered in the console and print their output.
+fn run_console_commands(world: &mut World) {
+    let submitted = std::mem::take(&mut world.resource_mut::<Console>().submitted);
+    for line in submitted {
+        let echo = format!("> {}", line);
+        world.resource_mut::<Console>().print(&echo);
+        let words: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+        let found = world
+            .resource::<ConsoleCommands>()
+            .find(&words)
+            .map(|(command, len)| (command.system, len));
+        let reply = match found {
+            Some((system, len)) => world
+                .run_system_with_input(system, words[len..].to_vec())
+                .unwrap_or_else(|e| format!("Command failed: {:?}", e)),
+            None => format!("Unknown command {}, try help", words[0]),
+        };
+        world.resource_mut::<Console>().print(&reply);
+    }
+}
+
+fn help_command(In(_): In<Vec<String>>, commands: Res<ConsoleCommands>) -> String {
+    let mut usages: Vec<String> = commands
+        .0
+        .iter()
+        .map(|command| format!("{} {}", command.name, command.usage))
+        .collect();
+    usages.sort();
+    usages.join("\n")
+}
+
+fn clear_command(In(_): In<Vec<String>>, mut console: ResMut<Console>) -> String {
+    console.lines.clear();
+    String::new()
+}
@@ -0,0 +1,153 @@
+//! The console's overlay across the top of the screen: the latest lines
+//! printed and the line being typed.
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+
+use super::Console;
+use crate::controls::{Action, KeyBindings, PlayerInput};
+
+/// Printed lines shown at once, the latest ones.
+const VISIBLE_LINES: usize = 16;
+
+const BG_COLOR: Color = Color::srgba(0.05, 0.05, 0.08, 0.9);
+const LOG_COLOR: Color = Color::srgb(0.8, 0.8, 0.8);
+const INPUT_COLOR: Color = Color::srgb(0.95, 0.85, 0.5);
+
+#[derive(Component)]
+pub struct ConsoleRoot;
+
+#[derive(Component)]
+pub struct ConsoleLogText;
+
+#[derive(Component)]
+pub struct ConsoleInputText;
+
+/// System to open and close the console with its key and type into it while
+/// it's open. Enter runs the line, Escape closes the console and the up and
+/// down arrows step through earlier commands.
+pub fn edit_console_input(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    bindings: Res<KeyBindings>,
+    mut console: ResMut<Console>,
+    mut player_input: ResMut<PlayerInput>,
+) {
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        // The key itself isn't typed
+        if bindings.matches(Action::Console, event.key_code) {
+            console.open = !console.open;
+            if console.open {
+                // Let go of anything held, the game doesn't hear the keyboard now
+                *player_input = PlayerInput::default();
+            }
+            continue;
+        }
+        if !console.open {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Enter => console.submit(),
+            Key::Escape => console.open = false,
+            Key::Backspace => {
+                console.input.pop();
+            }
+            Key::ArrowUp => console.step_history(-1),
+            Key::ArrowDown => console.step_history(1),
+            Key::Character(text) => {
+                let typed: String = text.chars().filter(|c| !c.is_control()).collect();
+                console.input.push_str(&typed);
+            }
+            Key::Space => console.input.push(' '),
+            _ => {}
+        }
+    }
+}
+
+/// System to show the console while it's open and keep its text current.
+pub fn update_console_ui(
+    mut commands: Commands,
+    console: Res<Console>,
+    root_query: Query<Entity, With<ConsoleRoot>>,
+    mut log_query: Query<&mut Text, With<ConsoleLogText>>,
+    mut input_query: Query<&mut Text, (With<ConsoleInputText>, Without<ConsoleLogText>)>,
+) {
+    if !console.open {
+        for entity in root_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    if !console.is_changed() && !root_query.is_empty() {
+        return;
+    }
+
+    let skip = console.lines.len().saturating_sub(VISIBLE_LINES);
+    let log: Vec<String> = console.lines.range(skip..).cloned().collect();
+    let log = log.join("\n");
+    let input = format!("> {}|", console.input);
+    if root_query.is_empty() {
+        spawn_console_ui(&mut commands, log, input);
+        return;
+    }
+    for mut text in log_query.iter_mut() {
+        text.0 = log.clone();
+    }
+    for mut text in input_query.iter_mut() {
+        text.0 = input.clone();
+    }
+}
+
+fn spawn_console_ui(commands: &mut Commands, log: String, input: String) {
+    commands
+        .spawn((
+            ConsoleRoot,
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.0),
+                left: Val::Px(0.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::FlexEnd,
+                row_gap: Val::Px(6.0),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(BG_COLOR),
+            GlobalZIndex(200),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ConsoleLogText,
+                Text::new(log),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(LOG_COLOR),
+            ));
+            parent.spawn((
+                ConsoleInputText,
+                Text::new(input),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(INPUT_COLOR),
+            ));
+        });
+}
+
+pub fn cleanup_console_ui(
+    mut commands: Commands,
+    mut console: ResMut<Console>,
+    root_query: Query<Entity, With<ConsoleRoot>>,
+) {
+    console.open = false;
+    for entity in root_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,210 @@
+//! Keyboard bindings for the game's actions, rebound from the settings menu
+//! and saved in the config file.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Something the player does that's bound to a key.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Crouch,
+    Sprint,
+    Interact,
+    EmoteWheel,
+    ToggleView,
+    /// Show the player list while held.
+    PlayerList,
+    /// Show or hide the stream quality indicator.
+    StreamStats,
+    /// Open or close the pause menu. Older configs called it `ReleaseCursor`.
+    #[serde(alias = "ReleaseCursor")]
+    Pause,
+    /// Free the cursor while held.
+    HoldCursor,
+    /// Save the current frame as a PNG.
+    Screenshot,
+    /// Switch the host to the free-fly spectator camera and back.
+    Spectate,
+    /// Show or hide the stream in a corner of the view.
+    PictureInPicture,
+    /// Show the host's own stream as viewers get it instead of the capture.
+    StreamPreview,
+    /// Open or close the developer console.
+    Console,
+}
+
+impl Action {
+    /// Every action, in the order the settings menu lists them.
+    pub const ALL: [Action; 19] = [
+        Action::MoveForward,
+        Action::MoveBack,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Crouch,
+        Action::Sprint,
+        Action::Interact,
+        Action::EmoteWheel,
+        Action::ToggleView,
+        Action::PlayerList,
+        Action::StreamStats,
+        Action::Pause,
+        Action::HoldCursor,
+        Action::Screenshot,
+        Action::Spectate,
+        Action::PictureInPicture,
+        Action::StreamPreview,
+        Action::Console,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBack => "Move back",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Jump => "Jump",
+            Action::Crouch => "Crouch",
+            Action::Sprint => "Sprint",
+            Action::Interact => "Interact",
+            Action::EmoteWheel => "Emote wheel",
+            Action::ToggleView => "Toggle camera view",
+            Action::PlayerList => "Player list",
+            Action::StreamStats => "Stream info",
+            Action::Pause => "Pause menu",
+            Action::HoldCursor => "Hold to free cursor",
+            Action::Screenshot => "Screenshot",
+            Action::Spectate => "Spectator camera (host)",
+            Action::PictureInPicture => "Picture-in-picture",
+            Action::StreamPreview => "Preview stream (host)",
+            Action::Console => "Developer console",
+        }
+    }
+
+    fn default_key(self) -> KeyCode {
+        match self {
+            Action::MoveForward => KeyCode::KeyW,
+            Action::MoveBack => KeyCode::KeyS,
+            Action::MoveLeft => KeyCode::KeyA,
+            Action::MoveRight => KeyCode::KeyD,
+            Action::Jump => KeyCode::Space,
+            Action::Crouch => KeyCode::ControlLeft,
+            Action::Sprint => KeyCode::ShiftLeft,
+            Action::Interact => KeyCode::KeyE,
+            Action::EmoteWheel => KeyCode::KeyG,
+            Action::ToggleView => KeyCode::KeyV,
+            Action::PlayerList => KeyCode::Tab,
+            Action::StreamStats => KeyCode::F3,
+            Action::Pause => KeyCode::Escape,
+            Action::HoldCursor => KeyCode::AltLeft,
+            Action::Screenshot => KeyCode::F12,
+            Action::Spectate => KeyCode::F8,
+            Action::PictureInPicture => KeyCode::KeyP,
+            Action::StreamPreview => KeyCode::F4,
+            Action::Console => KeyCode::Backquote,
+        }
+    }
+}
+
+/// Resource mapping each action to its key.
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct KeyBindings(HashMap<Action, KeyCode>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self(
+            Action::ALL
+                .into_iter()
+                .map(|action| (action, action.default_key()))
+                .collect(),
+        )
+    }
+}
+
+impl KeyBindings {
+    /// Saved bindings on top of the defaults, so actions missing from the
+    /// config, e.g. added since it was saved, keep their default key.
+    pub fn from_saved(saved: HashMap<Action, KeyCode>) -> Self {
+        let mut bindings = Self::default();
+        bindings.0.extend(saved);
+        bindings
+    }
+
+    /// Bindings to save in the config file.
+    pub fn to_saved(&self) -> HashMap<Action, KeyCode> {
+        self.0.clone()
+    }
+
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.0
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_key())
+    }
+
+    /// Bind an action to a key. An action already bound to that key takes
+    /// this action's old key, so nothing ends up unbound.
+    pub fn rebind(&mut self, action: Action, key: KeyCode) {
+        let old_key = self.key(action);
+        for (other, other_key) in self.0.iter_mut() {
+            if *other != action && *other_key == key {
+                *other_key = old_key;
+            }
+        }
+        self.0.insert(action, key);
+    }
+
+    /// Whether a key triggers an action. Modifiers match on either side of the keyboard.
+    pub fn matches(&self, action: Action, key: KeyCode) -> bool {
+        let bound = self.key(action);
+        key == bound || paired_key(bound) == Some(key)
+    }
+
+    pub fn pressed(&self, action: Action, input: &ButtonInput<KeyCode>) -> bool {
+        input.any_pressed(self.keys(action))
+    }
+
+    pub fn just_pressed(&self, action: Action, input: &ButtonInput<KeyCode>) -> bool {
+        input.any_just_pressed(self.keys(action))
+    }
+
+    pub fn just_released(&self, action: Action, input: &ButtonInput<KeyCode>) -> bool {
+        self.keys(action).any(|key| input.just_released(key))
+    }
+
+    fn keys(&self, action: Action) -> impl Iterator<Item = KeyCode> {
+        let key = self.key(action);
+        std::iter::once(key).chain(paired_key(key))
+    }
+}
+
+/// The same modifier on the other side of the keyboard.
+fn paired_key(key: KeyCode) -> Option<KeyCode> {
+    match key {
+        KeyCode::ControlLeft => Some(KeyCode::ControlRight),
+        KeyCode::ControlRight => Some(KeyCode::ControlLeft),
+        KeyCode::ShiftLeft => Some(KeyCode::ShiftRight),
+        KeyCode::ShiftRight => Some(KeyCode::ShiftLeft),
+        KeyCode::AltLeft => Some(KeyCode::AltRight),
+        KeyCode::AltRight => Some(KeyCode::AltLeft),
+        KeyCode::SuperLeft => Some(KeyCode::SuperRight),
+        KeyCode::SuperRight => Some(KeyCode::SuperLeft),
+        _ => None,
+    }
+}
+
+/// Short name of a key for the settings menu, e.g. "W" or "ShiftLeft".
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{:?}", key);
+    name.strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .filter(|rest| !rest.is_empty())
+        .unwrap_or(&name)
+        .to_string()
+}
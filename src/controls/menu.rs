@@ -0,0 +1,169 @@
+//! Gamepad navigation of menus: the d-pad moves focus between the buttons of
+//! the frontmost menu, shown with an outline, and A presses the focused one
+//! the same way a click would.
+
+use bevy::prelude::*;
+
+use crate::menu::components::NotificationCopyButton;
+
+/// Outline drawn around the button the gamepad has focused.
+const FOCUS_OUTLINE_WIDTH: f32 = 2.0;
+const FOCUS_OUTLINE_COLOR: Color = Color::srgb(1.0, 0.85, 0.3);
+
+/// How much being off to the side counts against a button, compared to being
+/// further away in the direction pressed.
+const SIDEWAYS_PENALTY: f32 = 2.0;
+
+/// Resource tracking the menu button focused with the gamepad.
+#[derive(Resource, Default)]
+pub struct MenuFocus {
+    pub entity: Option<Entity>,
+    /// Button pressed last frame, released again this frame.
+    pressed: Option<Entity>,
+}
+
+/// A button that can take focus, with its place on screen.
+struct FocusTarget {
+    entity: Entity,
+    root: Entity,
+    center: Vec2,
+    layer: u32,
+}
+
+/// System to move focus between menu buttons with the d-pad and press the
+/// focused one with A.
+pub fn navigate_menu(
+    mut commands: Commands,
+    mut focus: ResMut<MenuFocus>,
+    gamepads: Query<&Gamepad>,
+    buttons: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &ComputedNode,
+            &InheritedVisibility,
+        ),
+        // Toasts' copy buttons are for the mouse, and would pull focus off the menu
+        (With<Button>, Without<NotificationCopyButton>),
+    >,
+    roots: Query<&ComputedNode, Without<Parent>>,
+    parents: Query<&Parent>,
+    mut interactions: Query<&mut Interaction>,
+) {
+    if let Some(pressed) = focus.pressed.take() {
+        if let Ok(mut interaction) = interactions.get_mut(pressed) {
+            if *interaction == Interaction::Pressed {
+                *interaction = Interaction::None;
+            }
+        }
+    }
+
+    // Only the buttons of the frontmost menu, so focus can't wander under an overlay
+    let targets: Vec<FocusTarget> = buttons
+        .iter()
+        .filter(|(_, _, node, visibility)| visibility.get() && node.size() != Vec2::ZERO)
+        .map(|(entity, transform, _, _)| {
+            let root = parents.iter_ancestors(entity).last().unwrap_or(entity);
+            FocusTarget {
+                entity,
+                root,
+                center: transform.translation().truncate(),
+                layer: roots.get(root).map_or(0, |node| node.stack_index()),
+            }
+        })
+        .collect();
+    let front = targets
+        .iter()
+        .max_by_key(|target| target.layer)
+        .map(|target| target.root);
+    let targets: Vec<FocusTarget> = targets
+        .into_iter()
+        .filter(|target| Some(target.root) == front)
+        .collect();
+
+    let current = focus
+        .entity
+        .and_then(|entity| targets.iter().find(|target| target.entity == entity));
+    let mut next = current.map(|target| target.entity);
+
+    for gamepad in gamepads.iter() {
+        let direction = [
+            (GamepadButton::DPadUp, Vec2::NEG_Y),
+            (GamepadButton::DPadDown, Vec2::Y),
+            (GamepadButton::DPadLeft, Vec2::NEG_X),
+            (GamepadButton::DPadRight, Vec2::X),
+        ]
+        .into_iter()
+        .find(|(button, _)| gamepad.just_pressed(*button))
+        .map(|(_, direction)| direction);
+
+        if let Some(direction) = direction {
+            next = match current {
+                Some(current) => {
+                    closest_in_direction(current, &targets, direction).or(Some(current.entity))
+                }
+                None => first_target(&targets),
+            };
+        }
+
+        if gamepad.just_pressed(GamepadButton::South) {
+            if let Some(entity) = current.map(|target| target.entity) {
+                if let Ok(mut interaction) = interactions.get_mut(entity) {
+                    *interaction = Interaction::Pressed;
+                    focus.pressed = Some(entity);
+                }
+            }
+        }
+    }
+
+    if next != focus.entity {
+        if let Some(entity) = focus.entity {
+            if let Some(mut entity_commands) = commands.get_entity(entity) {
+                entity_commands.remove::<Outline>();
+            }
+        }
+        if let Some(entity) = next {
+            commands.entity(entity).insert(Outline::new(
+                Val::Px(FOCUS_OUTLINE_WIDTH),
+                Val::ZERO,
+                FOCUS_OUTLINE_COLOR,
+            ));
+        }
+        focus.entity = next;
+    }
+}
+
+/// The button reading order puts first: topmost, then leftmost.
+fn first_target(targets: &[FocusTarget]) -> Option<Entity> {
+    targets
+        .iter()
+        .min_by(|a, b| {
+            (a.center.y, a.center.x)
+                .partial_cmp(&(b.center.y, b.center.x))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|target| target.entity)
+}
+
+/// The nearest button in a direction (y pointing down the screen), favoring
+/// ones lined up with the current button.
+fn closest_in_direction(
+    current: &FocusTarget,
+    targets: &[FocusTarget],
+    direction: Vec2,
+) -> Option<Entity> {
+    targets
+        .iter()
+        .filter(|target| target.entity != current.entity)
+        .filter_map(|target| {
+            let offset = target.center - current.center;
+            let along = offset.dot(direction);
+            if along <= 0.0 {
+                return None;
+            }
+            let sideways = (offset - direction * along).length();
+            Some((target.entity, along + sideways * SIDEWAYS_PENALTY))
+        })
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(entity, _)| entity)
+}
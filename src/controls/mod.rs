@@ -0,0 +1,262 @@
+//! Input layer between the devices and gameplay. Keyboard and mouse or a
+//! gamepad drive the same actions, gathered into `PlayerInput` each frame, so
+//! movement and camera systems don't care where their input came from.
+//! Keys are looked up through `KeyBindings`, so players can rebind them.
+//!
+//! Gamepad layout: left stick to move, right stick to look, A to jump, B to
+//! crouch, left stick click to sprint, Select to open the share UI of the
+//! screen under the crosshair and Start to pause. The d-pad moves between
+//! menu buttons and A presses the focused one.
+
+pub mod bindings;
+pub mod menu;
+
+use bevy::input::keyboard::KeyboardInput;
+use bevy::input::mouse::{MouseButtonInput, MouseMotion, MouseWheel};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use bevy::ui::UiSystem;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::console::console_closed;
+use crate::game_state::PauseState;
+use crate::player::MOUSE_SENSITIVITY;
+use menu::{navigate_menu, MenuFocus};
+
+pub use bindings::{Action, KeyBindings};
+
+/// Mouse speed, in pixels per second, that `mouse_acceleration` is given per.
+const MOUSE_ACCELERATION_SPEED: f32 = 1000.0;
+
+/// Mouse and stick tuning, saved in the config file. Dead zones are fractions
+/// of full deflection ignored around the center, so worn sticks don't drift.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct InputSettings {
+    /// Camera turn per pixel of mouse movement, in radians.
+    pub mouse_sensitivity: f32,
+    /// Scales on the sensitivity for sideways and up-down mouse movement.
+    pub mouse_scale_x: f32,
+    pub mouse_scale_y: f32,
+    /// Turn by the mouse's movement as is. Otherwise fast movements turn
+    /// further, by `mouse_acceleration`.
+    pub raw_mouse_input: bool,
+    /// Extra turn per 1000 pixels a second of mouse speed, as a fraction of
+    /// the sensitivity, while raw input is off.
+    pub mouse_acceleration: f32,
+    pub move_dead_zone: f32,
+    pub look_dead_zone: f32,
+    /// Camera turn rate at full deflection, in radians per second.
+    pub look_speed: f32,
+    /// Look down when pushing the mouse or stick up.
+    pub invert_look_y: bool,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity: MOUSE_SENSITIVITY,
+            mouse_scale_x: 1.0,
+            mouse_scale_y: 1.0,
+            raw_mouse_input: true,
+            mouse_acceleration: 0.5,
+            move_dead_zone: 0.15,
+            look_dead_zone: 0.1,
+            look_speed: 3.0,
+            invert_look_y: false,
+        }
+    }
+}
+
+impl InputSettings {
+    /// Camera turn, yaw in x and pitch in y, for `motion` pixels of mouse
+    /// movement over `dt` seconds.
+    fn mouse_look(&self, motion: Vec2, dt: f32) -> Vec2 {
+        let mut gain = self.mouse_sensitivity;
+        if !self.raw_mouse_input && dt > 0.0 {
+            let speed = motion.length() / dt;
+            gain *= 1.0 + self.mouse_acceleration * speed / MOUSE_ACCELERATION_SPEED;
+        }
+        let pitch = if self.invert_look_y {
+            -motion.y
+        } else {
+            motion.y
+        };
+        -Vec2::new(motion.x * self.mouse_scale_x, pitch * self.mouse_scale_y) * gain
+    }
+}
+
+/// How long without input before a player counts as away from keyboard.
+const AFK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Resource for whether the local player is away from keyboard: no key,
+/// button, mouse or stick input for `AFK_TIMEOUT`, in menus or not.
+#[derive(Resource, Default, Debug)]
+pub struct Afk {
+    pub afk: bool,
+    last_input: Duration,
+}
+
+/// What the player asked for this frame, from whichever devices they use.
+#[derive(Resource, Default, Debug)]
+pub struct PlayerInput {
+    /// Movement on the ground: x to the right, y forward, no longer than one.
+    pub movement: Vec2,
+    /// Change to the camera's yaw (x) and pitch (y), in radians.
+    pub look: Vec2,
+    pub jump: bool,
+    pub crouch: bool,
+    pub sprint: bool,
+    /// Open the share UI of the screen control under the crosshair.
+    pub screen_control: bool,
+}
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KeyBindings>()
+            .init_resource::<InputSettings>()
+            .init_resource::<PlayerInput>()
+            .init_resource::<MenuFocus>()
+            .init_resource::<Afk>()
+            // After the UI has updated button interactions, so a gamepad press
+            // isn't overwritten by the mouse
+            .add_systems(
+                PreUpdate,
+                (
+                    navigate_menu,
+                    gather_player_input
+                        .run_if(not(in_state(PauseState::Paused)).and(console_closed)),
+                )
+                    .chain()
+                    .after(UiSystem::Focus),
+            )
+            .add_systems(PreUpdate, detect_afk)
+            .add_systems(OnEnter(PauseState::Paused), clear_player_input);
+    }
+}
+
+/// System to read this frame's actions from the keyboard, mouse and gamepads.
+fn gather_player_input(
+    mut input: ResMut<PlayerInput>,
+    settings: Res<InputSettings>,
+    bindings: Res<KeyBindings>,
+    time: Res<Time>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    gamepads: Query<&Gamepad>,
+    focus: Res<MenuFocus>,
+) {
+    let mut movement = Vec2::ZERO;
+    if bindings.pressed(Action::MoveForward, &keyboard_input) {
+        movement.y += 1.0;
+    }
+    if bindings.pressed(Action::MoveBack, &keyboard_input) {
+        movement.y -= 1.0;
+    }
+    if bindings.pressed(Action::MoveLeft, &keyboard_input) {
+        movement.x -= 1.0;
+    }
+    if bindings.pressed(Action::MoveRight, &keyboard_input) {
+        movement.x += 1.0;
+    }
+    movement = movement.normalize_or_zero();
+
+    let motion: Vec2 = mouse_motion.read().map(|event| event.delta).sum();
+    let mut look = settings.mouse_look(motion, time.delta_secs());
+
+    // Jump using raw keyboard events (bypasses ButtonInput state issues on Windows)
+    let mut jump = false;
+    for event in keyboard_events.read() {
+        if bindings.matches(Action::Jump, event.key_code) && event.state == ButtonState::Pressed {
+            jump = true;
+        }
+    }
+
+    let mut crouch = bindings.pressed(Action::Crouch, &keyboard_input);
+    let mut sprint = bindings.pressed(Action::Sprint, &keyboard_input);
+    let mut screen_control = mouse_input.just_pressed(MouseButton::Right);
+
+    // While a menu button has focus, the gamepad drives the menu instead
+    if focus.entity.is_none() {
+        let dt = time.delta_secs();
+        for gamepad in gamepads.iter() {
+            movement += apply_dead_zone(gamepad.left_stick(), settings.move_dead_zone);
+
+            let stick = apply_dead_zone(gamepad.right_stick(), settings.look_dead_zone);
+            let pitch = if settings.invert_look_y {
+                -stick.y
+            } else {
+                stick.y
+            };
+            look += Vec2::new(-stick.x, pitch) * settings.look_speed * dt;
+
+            jump |= gamepad.just_pressed(GamepadButton::South);
+            crouch |= gamepad.pressed(GamepadButton::East);
+            sprint |= gamepad.pressed(GamepadButton::LeftThumb);
+            screen_control |= gamepad.just_pressed(GamepadButton::Select);
+        }
+    }
+
+    *input = PlayerInput {
+        movement: movement.clamp_length_max(1.0),
+        look,
+        jump,
+        crouch,
+        sprint,
+        screen_control,
+    };
+}
+
+/// System to mark the player away once they've given no input for a while,
+/// and back as soon as they do.
+fn detect_afk(
+    mut afk: ResMut<Afk>,
+    time: Res<Time<Real>>,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut mouse_button_events: EventReader<MouseButtonInput>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    settings: Res<InputSettings>,
+    gamepads: Query<&Gamepad>,
+) {
+    let mut active = keyboard_events.read().count() > 0;
+    active |= mouse_button_events.read().count() > 0;
+    active |= mouse_motion.read().count() > 0;
+    active |= mouse_wheel.read().count() > 0;
+    active |= gamepads.iter().any(|gamepad| {
+        gamepad.get_pressed().next().is_some()
+            || apply_dead_zone(gamepad.left_stick(), settings.move_dead_zone) != Vec2::ZERO
+            || apply_dead_zone(gamepad.right_stick(), settings.look_dead_zone) != Vec2::ZERO
+    });
+
+    let now = time.elapsed();
+    if active {
+        afk.last_input = now;
+    }
+    let away = now.saturating_sub(afk.last_input) >= AFK_TIMEOUT;
+    if afk.afk != away {
+        afk.afk = away;
+    }
+}
+
+/// System to let go of everything held when the game pauses, so the player
+/// doesn't keep walking while the pause menu is open.
+fn clear_player_input(mut input: ResMut<PlayerInput>) {
+    *input = PlayerInput::default();
+}
+
+/// Zero a stick inside its dead zone and rescale the rest of its travel, so
+/// movement starts from nothing at the dead zone's edge.
+fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= dead_zone || dead_zone >= 1.0 {
+        return Vec2::ZERO;
+    }
+    let scaled = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick * (scaled / length)
+}
@@ -0,0 +1,70 @@
+//! Emotes players play from a wheel, seen by everyone in the room.
+//!
+//! Holding G opens the emote wheel. Moving the mouse picks an emote and
+//! releasing G plays it on the player's character. Emotes go through the host,
+//! which passes them on to everyone else.
+
+pub mod wheel;
+
+use bevy::prelude::*;
+
+use crate::character::{CharacterAnimationState, LocalCharacter};
+use crate::game_state::{AppState, PauseState};
+use crate::network::protocol::{Emote, LocalPlayerId, PlayerId, RemotePlayer};
+use wheel::{cleanup_emote_wheel, close_emote_wheel, open_emote_wheel, update_emote_wheel};
+
+pub use wheel::EmoteWheel;
+
+/// Event fired when a player plays an emote, the local player included.
+#[derive(Event)]
+pub struct EmoteEvent {
+    pub player: PlayerId,
+    pub emote: Emote,
+}
+
+pub struct EmotePlugin;
+
+impl Plugin for EmotePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EmoteWheel>()
+            .add_event::<EmoteEvent>()
+            .add_systems(
+                Update,
+                (
+                    (open_emote_wheel, update_emote_wheel, close_emote_wheel)
+                        .run_if(in_state(PauseState::Running)),
+                    play_emotes,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnExit(AppState::InGame), cleanup_emote_wheel);
+    }
+}
+
+/// System to play emotes on the characters of the players who sent them.
+fn play_emotes(
+    mut events: EventReader<EmoteEvent>,
+    local_id: Option<Res<LocalPlayerId>>,
+    mut remote_characters: Query<(&RemotePlayer, &mut CharacterAnimationState)>,
+    mut local_characters: Query<
+        &mut CharacterAnimationState,
+        (With<LocalCharacter>, Without<RemotePlayer>),
+    >,
+) {
+    for event in events.read() {
+        if local_id.as_ref().is_some_and(|id| id.0 == event.player) {
+            for mut anim_state in local_characters.iter_mut() {
+                anim_state.play_emote(event.emote);
+            }
+            continue;
+        }
+
+        if let Some((_, mut anim_state)) = remote_characters
+            .iter_mut()
+            .find(|(remote, _)| remote.id == event.player)
+        {
+            anim_state.play_emote(event.emote);
+        }
+    }
+}
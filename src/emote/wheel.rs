@@ -0,0 +1,201 @@
+//! The emote wheel: emotes laid out in a circle around the crosshair.
+
+use bevy::input::mouse::MouseMotion;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use super::EmoteEvent;
+use crate::controls::{Action, KeyBindings};
+use crate::network::protocol::{Emote, LocalPlayerId};
+
+/// Distance from the center of the wheel to each emote, in pixels.
+const WHEEL_RADIUS: f32 = 110.0;
+
+/// Size of each emote button, in pixels.
+const OPTION_SIZE: f32 = 90.0;
+
+/// How far the mouse has to move from the center to pick an emote.
+const SELECT_DEAD_ZONE: f32 = 20.0;
+
+const OPTION_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const OPTION_SELECTED_COLOR: Color = Color::srgba(0.25, 0.45, 0.8, 0.85);
+
+/// Resource tracking the emote wheel while G is held.
+#[derive(Resource, Default)]
+pub struct EmoteWheel {
+    pub open: bool,
+    /// Mouse movement since the wheel opened.
+    pointer: Vec2,
+    selected: Option<Emote>,
+}
+
+/// Marker for the emote wheel UI root.
+#[derive(Component)]
+pub struct EmoteWheelRoot;
+
+/// An emote button on the wheel.
+#[derive(Component)]
+pub struct EmoteWheelOption(Emote);
+
+/// Direction from the center of the wheel to the nth emote, clockwise from the top.
+fn option_direction(index: usize) -> Vec2 {
+    let angle = index as f32 / Emote::ALL.len() as f32 * std::f32::consts::TAU;
+    Vec2::new(angle.sin(), -angle.cos())
+}
+
+/// System to open the emote wheel when G is pressed.
+pub fn open_emote_wheel(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut wheel: ResMut<EmoteWheel>,
+) {
+    if wheel.open || !bindings.just_pressed(Action::EmoteWheel, &keyboard_input) {
+        return;
+    }
+
+    // Only when cursor is grabbed (in game)
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if window.cursor_options.visible {
+        return;
+    }
+
+    *wheel = EmoteWheel {
+        open: true,
+        ..default()
+    };
+
+    let wheel_size = 2.0 * WHEEL_RADIUS + OPTION_SIZE;
+    commands
+        .spawn((
+            EmoteWheelRoot,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    width: Val::Px(wheel_size),
+                    height: Val::Px(wheel_size),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (i, emote) in Emote::ALL.into_iter().enumerate() {
+                        let center =
+                            Vec2::splat(wheel_size / 2.0) + option_direction(i) * WHEEL_RADIUS;
+                        parent
+                            .spawn((
+                                EmoteWheelOption(emote),
+                                Node {
+                                    position_type: PositionType::Absolute,
+                                    left: Val::Px(center.x - OPTION_SIZE / 2.0),
+                                    top: Val::Px(center.y - OPTION_SIZE / 2.0),
+                                    width: Val::Px(OPTION_SIZE),
+                                    height: Val::Px(OPTION_SIZE),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                BorderRadius::MAX,
+                                BackgroundColor(OPTION_COLOR),
+                            ))
+                            .with_child((
+                                Text::new(emote.label()),
+                                TextFont {
+                                    font_size: 16.0,
+                                    ..default()
+                                },
+                                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                            ));
+                    }
+                });
+        });
+}
+
+/// System to pick the emote the mouse points towards while the wheel is open.
+pub fn update_emote_wheel(
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut wheel: ResMut<EmoteWheel>,
+    mut options: Query<(&EmoteWheelOption, &mut BackgroundColor)>,
+) {
+    if !wheel.open {
+        mouse_motion.clear();
+        return;
+    }
+
+    let mut pointer = wheel.pointer;
+    for event in mouse_motion.read() {
+        pointer = (pointer + event.delta).clamp_length_max(WHEEL_RADIUS);
+    }
+    let selected = if pointer.length() < SELECT_DEAD_ZONE {
+        None
+    } else {
+        let direction = pointer.normalize();
+        (0..Emote::ALL.len())
+            .max_by(|&a, &b| {
+                let a = option_direction(a).dot(direction);
+                let b = option_direction(b).dot(direction);
+                a.total_cmp(&b)
+            })
+            .map(|i| Emote::ALL[i])
+    };
+
+    if wheel.pointer != pointer {
+        wheel.pointer = pointer;
+    }
+    if wheel.selected == selected {
+        return;
+    }
+    wheel.selected = selected;
+
+    for (option, mut background) in options.iter_mut() {
+        background.0 = if Some(option.0) == selected {
+            OPTION_SELECTED_COLOR
+        } else {
+            OPTION_COLOR
+        };
+    }
+}
+
+/// System to play the picked emote and close the wheel when G is released.
+pub fn close_emote_wheel(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    local_id: Option<Res<LocalPlayerId>>,
+    roots: Query<Entity, With<EmoteWheelRoot>>,
+    mut wheel: ResMut<EmoteWheel>,
+    mut emote_events: EventWriter<EmoteEvent>,
+) {
+    if !wheel.open || !bindings.just_released(Action::EmoteWheel, &keyboard_input) {
+        return;
+    }
+
+    for entity in roots.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if let (Some(emote), Some(local_id)) = (wheel.selected, local_id) {
+        emote_events.send(EmoteEvent {
+            player: local_id.0,
+            emote,
+        });
+    }
+    *wheel = EmoteWheel::default();
+}
+
+/// Closes the wheel when leaving the game.
+pub fn cleanup_emote_wheel(mut commands: Commands, roots: Query<Entity, With<EmoteWheelRoot>>) {
+    for entity in roots.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.insert_resource(EmoteWheel::default());
+}
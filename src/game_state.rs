@@ -5,8 +5,141 @@ use bevy::prelude::*;
 pub enum AppState {
     #[default]
     MainMenu,
+    /// Host is picking which room to host in.
+    RoomSelect,
     Hosting,
     Browsing,
     Connecting,
+    /// Waiting for the room and character to load before entering the game.
+    Loading,
     InGame,
 }
+
+/// Whether the in-game pause menu is open. Only the local player's gameplay
+/// stops while paused; the session and network carry on.
+#[derive(SubStates, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[source(AppState = AppState::InGame)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::gltf::Gltf;
+    use bevy::input::InputPlugin;
+    use bevy::prelude::*;
+    use bevy::state::app::StatesPlugin;
+    use bevy::state::state::FreelyMutableState;
+    use bevy::window::WindowFocused;
+
+    use super::{AppState, PauseState};
+    use crate::camera::CameraPlugin;
+    use crate::character::{AvatarSelection, CharacterAssets, CharacterGltfHandles};
+    use crate::console::ConsolePlugin;
+    use crate::controls::ControlsPlugin;
+    use crate::emote::EmoteWheel;
+    use crate::menu::components::{LoadingScreenRoot, MainMenuRoot, PauseMenuRoot};
+    use crate::menu::loading::LoadingAssets;
+    use crate::menu::MenuPlugin;
+    use crate::network::{DiscoveredSessions, PlayerList};
+    use crate::player::PlayerPlugin;
+    use crate::screen::recording::ToggleRecording;
+    use crate::settings::ui::SettingsUIState;
+    use crate::settings::VideoSettings;
+    use crate::world::CurrentRoom;
+
+    /// The plugins whose systems look for the window or the player, on an
+    /// app with neither. What their systems take from other plugins is added
+    /// bare, so none of them are skipped for a missing resource.
+    fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            InputPlugin,
+            AssetPlugin::default(),
+        ))
+        .init_asset::<Gltf>()
+        .init_asset::<Image>()
+        .init_asset::<Scene>()
+        .init_state::<AppState>()
+        .add_sub_state::<PauseState>()
+        .add_event::<WindowFocused>()
+        .add_event::<ToggleRecording>()
+        .init_resource::<AvatarSelection>()
+        .init_resource::<CharacterAssets>()
+        .init_resource::<CharacterGltfHandles>()
+        .init_resource::<CurrentRoom>()
+        .init_resource::<DiscoveredSessions>()
+        .init_resource::<EmoteWheel>()
+        .init_resource::<PlayerList>()
+        .init_resource::<SettingsUIState>()
+        .init_resource::<VideoSettings>()
+        .add_plugins((
+            MenuPlugin,
+            PlayerPlugin,
+            CameraPlugin,
+            ControlsPlugin,
+            ConsolePlugin,
+        ));
+        app.update();
+        app
+    }
+
+    /// Switch to `state` and run a few frames in it.
+    fn enter<S: FreelyMutableState>(app: &mut App, state: S) {
+        app.world_mut().resource_mut::<NextState<S>>().set(state);
+        for _ in 0..3 {
+            app.update();
+        }
+    }
+
+    /// Whether anything with a `C` is spawned.
+    fn spawned<C: Component>(app: &mut App) -> bool {
+        let mut query = app.world_mut().query_filtered::<(), With<C>>();
+        query.iter(app.world()).next().is_some()
+    }
+
+    #[test]
+    fn hosting_without_window_or_player() {
+        let mut app = headless_app();
+        assert!(spawned::<MainMenuRoot>(&mut app));
+
+        enter(&mut app, AppState::Hosting);
+        assert!(!spawned::<MainMenuRoot>(&mut app));
+        assert!(spawned::<LoadingScreenRoot>(&mut app));
+
+        enter(&mut app, AppState::Loading);
+        assert!(app.world().contains_resource::<LoadingAssets>());
+
+        enter(&mut app, AppState::InGame);
+        assert!(!spawned::<LoadingScreenRoot>(&mut app));
+        enter(&mut app, PauseState::Paused);
+        assert!(spawned::<PauseMenuRoot>(&mut app));
+        enter(&mut app, PauseState::Running);
+        assert!(!spawned::<PauseMenuRoot>(&mut app));
+
+        enter(&mut app, AppState::MainMenu);
+        assert!(spawned::<MainMenuRoot>(&mut app));
+        assert!(!app.world().contains_resource::<LoadingAssets>());
+    }
+
+    #[test]
+    fn joining_without_window_or_player() {
+        let mut app = headless_app();
+        enter(&mut app, AppState::Connecting);
+        assert!(spawned::<LoadingScreenRoot>(&mut app));
+        enter(&mut app, AppState::Loading);
+        enter(&mut app, AppState::InGame);
+        enter(&mut app, PauseState::Paused);
+        assert!(spawned::<PauseMenuRoot>(&mut app));
+
+        // Leaving from the pause menu
+        enter(&mut app, AppState::MainMenu);
+        assert!(spawned::<MainMenuRoot>(&mut app));
+        assert!(!spawned::<PauseMenuRoot>(&mut app));
+        assert!(app.world().get_resource::<State<PauseState>>().is_none());
+    }
+}
@@ -0,0 +1,69 @@
+//! Log setup. Logs go to the console and to a file per day in the platform's
+//! data directory (e.g. `~/.local/share/zine/logs`), of which the last week is
+//! kept. What's logged can be narrowed per module with `--log` or `RUST_LOG`,
+//! e.g. `--log zine::network=debug`.
+
+use bevy::log::tracing_subscriber::{fmt, Layer};
+use bevy::log::{BoxedLayer, Level, LogPlugin};
+use bevy::prelude::*;
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+const LOG_DIR_NAME: &str = "zine";
+const LOG_FILE_PREFIX: &str = "zine";
+
+/// Daily log files kept before the oldest is deleted.
+const MAX_LOG_FILES: usize = 7;
+
+/// Filter applied to everything, quieting the renderer's chatter.
+const DEFAULT_FILTER: &str = "wgpu=error,naga=warn";
+
+/// Keeps the thread writing the log file alive, flushing it when dropped.
+#[derive(Resource)]
+struct LogFileGuard {
+    _guard: WorkerGuard,
+}
+
+/// Logging for the app. `verbose` logs the game's own debug messages, and
+/// `filter` adds directives on top of the defaults.
+pub fn log_plugin(verbose: bool, filter: Option<&str>) -> LogPlugin {
+    let mut directives = DEFAULT_FILTER.to_string();
+    if verbose {
+        directives.push_str(",zine=debug");
+    }
+    if let Some(filter) = filter {
+        directives.push(',');
+        directives.push_str(filter);
+    }
+    LogPlugin {
+        filter: directives,
+        level: Level::INFO,
+        custom_layer: file_layer,
+    }
+}
+
+/// Layer writing the log to a daily file, off the main thread.
+fn file_layer(app: &mut App) -> Option<BoxedLayer> {
+    let dir = log_dir();
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(&dir)
+        .map_err(|e| eprintln!("Not logging to {}: {}", dir.display(), e))
+        .ok()?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    app.insert_resource(LogFileGuard { _guard: guard });
+    Some(fmt::layer().with_writer(writer).with_ansi(false).boxed())
+}
+
+/// Where log files go, falling back to the working directory on platforms
+/// without a data directory.
+fn log_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join(LOG_DIR_NAME))
+        .unwrap_or_default()
+        .join("logs")
+}
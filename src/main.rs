@@ -1,44 +1,81 @@
+// Bevy systems routinely take many parameters and nested query filters
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
+mod asset_packs;
 mod camera;
 mod character;
+mod cli;
+mod console;
+mod controls;
+mod emote;
 mod game_state;
+mod logging;
 mod menu;
 mod network;
 mod player;
 mod screen;
+mod settings;
+mod sound;
 mod world;
 
+use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::{prelude::*, window::PresentMode};
+use bevy_rapier3d::prelude::*;
+use clap::Parser;
 
 use camera::CameraPlugin;
 use character::CharacterPlugin;
-use game_state::AppState;
+use cli::Args;
+use console::ConsolePlugin;
+use controls::ControlsPlugin;
+use emote::EmotePlugin;
+use game_state::{AppState, PauseState};
 use menu::MenuPlugin;
 use network::NetworkPlugin;
 use player::PlayerPlugin;
 use screen::ScreenPlugin;
+use settings::SettingsPlugin;
+use sound::SoundPlugin;
 use world::WorldPlugin;
 
 fn main() {
-    App::new()
-        .add_plugins(
-            DefaultPlugins.set(WindowPlugin {
+    let args = Args::parse();
+
+    let mut app = App::new();
+    asset_packs::register_pack_source(&mut app);
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
                 primary_window: Some(Window {
-                    title: "Zine".to_string(),
+                    title: args.window_title(),
                     present_mode: PresentMode::AutoNoVsync,
                     ..default()
                 }),
                 ..default()
-            }),
-        )
-        .init_state::<AppState>()
-        .add_plugins((
-            MenuPlugin,
-            NetworkPlugin,
-            WorldPlugin,
-            PlayerPlugin,
-            CameraPlugin,
-            ScreenPlugin,
-            CharacterPlugin,
-        ))
-        .run();
+            })
+            .set(logging::log_plugin(args.verbose, args.log.as_deref())),
+    )
+    .add_plugins(FrameTimeDiagnosticsPlugin)
+    .init_state::<AppState>()
+    .add_sub_state::<PauseState>()
+    .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+    .add_plugins((
+        MenuPlugin,
+        NetworkPlugin,
+        WorldPlugin,
+        PlayerPlugin,
+        CameraPlugin,
+        ScreenPlugin,
+        CharacterPlugin,
+        EmotePlugin,
+        SettingsPlugin,
+        SoundPlugin,
+        ControlsPlugin,
+        ConsolePlugin,
+    ));
+    if args.verbose {
+        app.add_plugins(LogDiagnosticsPlugin::default());
+    }
+    args.apply(&mut app);
+    app.run();
 }
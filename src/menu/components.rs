@@ -1,5 +1,8 @@
 use bevy::prelude::*;
 
+use crate::network::protocol::PlayerId;
+use crate::world::RoomId;
+
 /// Marker for the menu camera.
 #[derive(Component)]
 pub struct MenuCamera;
@@ -16,6 +19,28 @@ pub struct HostButton;
 #[derive(Component)]
 pub struct JoinButton;
 
+/// Marker for the settings button.
+#[derive(Component)]
+pub struct SettingsButton;
+
+/// Which part of the avatar a picker row changes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AvatarField {
+    Model,
+    Tint,
+}
+
+/// Arrow button stepping through the choices for an avatar field.
+#[derive(Component)]
+pub struct AvatarArrow {
+    pub field: AvatarField,
+    pub step: isize,
+}
+
+/// Label showing the current choice for an avatar field.
+#[derive(Component)]
+pub struct AvatarLabel(pub AvatarField);
+
 /// Marker for the back button.
 #[derive(Component)]
 pub struct BackButton;
@@ -32,6 +57,26 @@ pub struct SessionList;
 #[derive(Component)]
 pub struct SessionEntry(pub usize);
 
+/// Marker for the invite code field, typed into once clicked.
+#[derive(Component)]
+pub struct InviteCodeField;
+
+/// Marker for the text showing the invite code typed so far.
+#[derive(Component)]
+pub struct InviteCodeText;
+
+/// Marker for the button joining the session the typed invite code names.
+#[derive(Component)]
+pub struct JoinCodeButton;
+
+/// Marker for the room picker UI root.
+#[derive(Component)]
+pub struct RoomSelectRoot;
+
+/// Button that hosts in the given room.
+#[derive(Component)]
+pub struct RoomOption(pub RoomId);
+
 /// Marker for the "Searching..." text.
 #[derive(Component)]
 pub struct SearchingText;
@@ -43,3 +88,73 @@ pub struct NotificationRoot;
 /// Marker for a notification text element with remaining display time.
 #[derive(Component)]
 pub struct NotificationText(pub f32);
+
+/// Button on an error notification copying its text to the clipboard.
+#[derive(Component)]
+pub struct NotificationCopyButton(pub String);
+
+/// Marker for the in-game pause menu root UI node.
+#[derive(Component)]
+pub struct PauseMenuRoot;
+
+/// Buttons of the pause menu.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum PauseButton {
+    Resume,
+    Settings,
+    Invite,
+    Record,
+    Leave,
+}
+
+/// Marker for the label of the host's record button.
+#[derive(Component)]
+pub struct RecordButtonText;
+
+/// Marker for the text telling others where to join, shown by the invite button.
+#[derive(Component)]
+pub struct InviteText;
+
+/// Marker for the player list overlay shown while Tab is held.
+#[derive(Component)]
+pub struct PlayerListRoot;
+
+/// Marker for the container of player list rows.
+#[derive(Component)]
+pub struct PlayerListRows;
+
+/// A player's row in the player list.
+#[derive(Component)]
+pub struct PlayerListRow(pub PlayerId);
+
+/// What a player list text shows about its row's player.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PlayerListColumn {
+    Name,
+    Role,
+    Ping,
+    Mute,
+}
+
+/// Text in a player list row.
+#[derive(Component)]
+pub struct PlayerListCell {
+    pub player: PlayerId,
+    pub column: PlayerListColumn,
+}
+
+/// Button muting or unmuting a player for the local player only.
+#[derive(Component)]
+pub struct MuteButton(pub PlayerId);
+
+/// Marker for the loading screen root UI node.
+#[derive(Component)]
+pub struct LoadingScreenRoot;
+
+/// Filled part of the loading progress bar.
+#[derive(Component)]
+pub struct LoadingBarFill;
+
+/// Text saying what the loading screen waits for.
+#[derive(Component)]
+pub struct LoadingStatusText;
@@ -0,0 +1,211 @@
+//! Loading screen between the menus and the game. It covers hosting and
+//! connecting, then waits in the loading state until the room and the local
+//! player's character are loaded, so the room doesn't pop in piece by piece.
+
+use bevy::asset::{LoadState, UntypedAssetId};
+use bevy::prelude::*;
+
+use super::components::{LoadingBarFill, LoadingScreenRoot, LoadingStatusText};
+use super::styles::*;
+use crate::character::avatars::model_path;
+use crate::character::{AvatarSelection, CharacterAssets, CharacterGltfHandles};
+use crate::game_state::AppState;
+use crate::world::{CurrentRoom, RoomId};
+
+const BG_COLOR: Color = Color::srgb(0.05, 0.05, 0.05);
+const BAR_BG_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+const BAR_FILL_COLOR: Color = Color::srgb(0.35, 0.65, 0.35);
+const STATUS_TEXT_COLOR: Color = Color::srgb(0.7, 0.7, 0.7);
+
+/// Room assets loaded ahead of entering the game. The handles are kept until
+/// the session ends, so the room doesn't unload before the world spawns it.
+#[derive(Resource, Default)]
+pub struct LoadingAssets(Vec<UntypedHandle>);
+
+/// System to show the loading screen, unless it already is.
+pub fn setup_loading_screen(mut commands: Commands, query: Query<(), With<LoadingScreenRoot>>) {
+    if !query.is_empty() {
+        return;
+    }
+
+    commands
+        .spawn((
+            LoadingScreenRoot,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                position_type: PositionType::Absolute,
+                row_gap: Val::Px(20.0),
+                ..default()
+            },
+            BackgroundColor(BG_COLOR),
+            GlobalZIndex(60),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Loading"),
+                title_text_style(),
+                TextColor(TITLE_TEXT_COLOR),
+            ));
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(400.0),
+                        height: Val::Px(12.0),
+                        ..default()
+                    },
+                    BackgroundColor(BAR_BG_COLOR),
+                ))
+                .with_child((
+                    LoadingBarFill,
+                    Node {
+                        width: Val::Percent(0.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(BAR_FILL_COLOR),
+                ));
+            parent.spawn((
+                LoadingStatusText,
+                Text::new(""),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(STATUS_TEXT_COLOR),
+            ));
+        });
+}
+
+pub fn cleanup_loading_screen(
+    mut commands: Commands,
+    query: Query<Entity, With<LoadingScreenRoot>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// System to say what the session is waiting on while hosting or connecting.
+pub fn update_connecting_status(
+    state: Res<State<AppState>>,
+    mut text_query: Query<&mut Text, With<LoadingStatusText>>,
+) {
+    let status = match state.get() {
+        AppState::Hosting => "Starting session...",
+        AppState::Connecting => "Connecting to host...",
+        _ => return,
+    };
+    for mut text in text_query.iter_mut() {
+        if text.0 != status {
+            text.0 = status.to_string();
+        }
+    }
+}
+
+/// System to start loading the room the world is about to build, and the
+/// local player's character if it isn't loading yet.
+pub fn start_loading_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    room: Res<CurrentRoom>,
+    selection: Res<AvatarSelection>,
+    mut character_handles: ResMut<CharacterGltfHandles>,
+) {
+    // Same fallback as the world uses when building the room
+    let room = if room.0.is_available() {
+        room.0.clone()
+    } else {
+        RoomId::default()
+    };
+
+    let mut handles = Vec::new();
+    if room.layout().is_none() {
+        if let Some(path) = room.scene_asset_path() {
+            let scene: Handle<Scene> = asset_server.load(GltfAssetLabel::Scene(0).from_asset(path));
+            handles.push(scene.untyped());
+        }
+    }
+    if let Some(path) = room.skybox_asset_path() {
+        let image: Handle<Image> = asset_server.load(path);
+        handles.push(image.untyped());
+    }
+    commands.insert_resource(LoadingAssets(handles));
+
+    let model = selection.0.model;
+    character_handles
+        .0
+        .entry(model)
+        .or_insert_with(|| asset_server.load(model_path(model)));
+}
+
+/// Whether an asset is ready to use. Assets that failed to load count as
+/// done, so a broken file doesn't keep the player on the loading screen.
+fn asset_done(asset_server: &AssetServer, id: impl Into<UntypedAssetId>) -> bool {
+    let id = id.into();
+    asset_server.is_loaded_with_dependencies(id)
+        || matches!(asset_server.get_load_state(id), Some(LoadState::Failed(_)))
+}
+
+/// System to show loading progress and enter the game once everything is in.
+pub fn update_loading(
+    asset_server: Res<AssetServer>,
+    loading: Option<Res<LoadingAssets>>,
+    selection: Res<AvatarSelection>,
+    character_handles: Res<CharacterGltfHandles>,
+    character_assets: Res<CharacterAssets>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut fill_query: Query<&mut Node, With<LoadingBarFill>>,
+    mut text_query: Query<&mut Text, With<LoadingStatusText>>,
+) {
+    let Some(loading) = loading else {
+        return;
+    };
+
+    let room_done = loading
+        .0
+        .iter()
+        .filter(|handle| asset_done(&asset_server, handle.id()))
+        .count();
+
+    // The character only counts once it's processed into a model
+    let model = selection.0.model;
+    let character_done = character_assets.models.contains_key(&model)
+        || character_handles.0.get(&model).is_some_and(|handle| {
+            matches!(
+                asset_server.get_load_state(handle.id()),
+                Some(LoadState::Failed(_))
+            )
+        });
+
+    let total = loading.0.len() + 1;
+    let done = room_done + usize::from(character_done);
+
+    let status = if room_done < loading.0.len() {
+        "Loading room..."
+    } else if !character_done {
+        "Loading character..."
+    } else {
+        "Entering room..."
+    };
+    for mut text in text_query.iter_mut() {
+        if text.0 != status {
+            text.0 = status.to_string();
+        }
+    }
+    for mut node in fill_query.iter_mut() {
+        node.width = Val::Percent(done as f32 / total as f32 * 100.0);
+    }
+
+    if done == total {
+        next_state.set(AppState::InGame);
+    }
+}
+
+/// Let go of the room assets once the session ends.
+pub fn release_loading_assets(mut commands: Commands) {
+    commands.remove_resource::<LoadingAssets>();
+}
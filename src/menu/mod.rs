@@ -1,21 +1,32 @@
 pub mod components;
+pub mod loading;
 pub mod notification;
+pub mod pause;
+pub mod player_list;
 pub mod styles;
 pub mod systems;
 
 use bevy::prelude::*;
 
-use crate::game_state::AppState;
-pub use notification::NotificationEvent;
+use crate::game_state::{AppState, PauseState};
+use loading::*;
 use notification::*;
+pub use notification::{ErrorNotification, MenuNotice, NotificationEvent};
+use pause::*;
+pub use player_list::MutedPlayers;
+use player_list::*;
 use systems::*;
 
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        // Register notification event
-        app.add_event::<NotificationEvent>();
+        // Register notification events
+        app.add_event::<NotificationEvent>()
+            .add_event::<ErrorNotification>();
+
+        // Players muted from the player list
+        app.init_resource::<MutedPlayers>();
 
         app
             // Main menu
@@ -23,9 +34,23 @@ impl Plugin for MenuPlugin {
             .add_systems(OnExit(AppState::MainMenu), cleanup_main_menu)
             .add_systems(
                 Update,
-                (button_interaction, handle_host_click, handle_join_click)
+                (
+                    button_interaction,
+                    handle_host_click,
+                    handle_join_click,
+                    handle_settings_click,
+                    (handle_avatar_click, update_avatar_labels).chain(),
+                )
                     .run_if(in_state(AppState::MainMenu)),
             )
+            // Room picker (host)
+            .add_systems(OnEnter(AppState::RoomSelect), setup_room_select)
+            .add_systems(OnExit(AppState::RoomSelect), cleanup_room_select)
+            .add_systems(
+                Update,
+                (button_interaction, handle_back_click, handle_room_click)
+                    .run_if(in_state(AppState::RoomSelect)),
+            )
             // Browser
             .add_systems(OnEnter(AppState::Browsing), setup_browser)
             .add_systems(OnExit(AppState::Browsing), cleanup_browser)
@@ -36,18 +61,75 @@ impl Plugin for MenuPlugin {
                     handle_back_click,
                     update_session_list,
                     handle_session_click,
+                    (
+                        handle_invite_code_click,
+                        edit_invite_code,
+                        update_invite_code_text,
+                    )
+                        .chain()
+                        .run_if(resource_exists::<InviteCodeInput>),
                 )
                     .run_if(in_state(AppState::Browsing)),
             )
+            // Loading screen, from hosting or connecting until the room is loaded
+            .add_systems(OnEnter(AppState::Hosting), setup_loading_screen)
+            .add_systems(OnEnter(AppState::Connecting), setup_loading_screen)
+            .add_systems(
+                OnEnter(AppState::Loading),
+                (setup_loading_screen, start_loading_assets),
+            )
+            .add_systems(
+                Update,
+                update_connecting_status
+                    .run_if(in_state(AppState::Hosting).or(in_state(AppState::Connecting))),
+            )
+            .add_systems(Update, update_loading.run_if(in_state(AppState::Loading)))
+            .add_systems(OnExit(AppState::Loading), cleanup_loading_screen)
+            .add_systems(
+                OnEnter(AppState::MainMenu),
+                (cleanup_loading_screen, release_loading_assets),
+            )
             // Cleanup menu camera when entering game
             .add_systems(OnEnter(AppState::InGame), cleanup_menu_camera)
-            // Notification system (in-game only)
+            // Notifications, errors also showing in the menus
+            .add_systems(Startup, setup_notification_root)
             .add_systems(OnEnter(AppState::InGame), setup_notification_ui)
             .add_systems(OnExit(AppState::InGame), cleanup_notification_ui)
             .add_systems(
                 Update,
-                (display_notifications, update_notifications)
-                    .run_if(in_state(AppState::InGame)),
+                (
+                    display_notifications.run_if(in_state(AppState::InGame)),
+                    display_errors,
+                    handle_notification_copy,
+                    update_notifications,
+                ),
+            )
+            // Pause menu (in-game only)
+            .add_systems(Update, toggle_pause.run_if(in_state(AppState::InGame)))
+            .add_systems(OnEnter(PauseState::Paused), setup_pause_menu)
+            .add_systems(OnExit(PauseState::Paused), cleanup_pause_menu)
+            .add_systems(
+                Update,
+                (
+                    button_interaction,
+                    handle_pause_menu_click,
+                    update_record_button,
+                )
+                    .run_if(in_state(PauseState::Paused)),
+            )
+            // Player list (in-game only)
+            .add_systems(
+                Update,
+                toggle_player_list.run_if(in_state(PauseState::Running)),
+            )
+            .add_systems(
+                Update,
+                (update_player_list, handle_mute_click).run_if(resource_exists::<PlayerListUIRoot>),
+            )
+            .add_systems(OnEnter(PauseState::Paused), hide_player_list)
+            .add_systems(
+                OnExit(AppState::InGame),
+                (hide_player_list, clear_muted_players),
             );
     }
 }
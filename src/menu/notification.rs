@@ -1,11 +1,40 @@
 use bevy::prelude::*;
 
-use super::components::{NotificationRoot, NotificationText};
+use super::components::{NotificationCopyButton, NotificationRoot, NotificationText};
 
 /// Event to display a notification message.
 #[derive(Event)]
 pub struct NotificationEvent(pub String);
 
+/// Event to display an error, styled apart from other notifications and shown
+/// for longer. Errors show in the menus too.
+#[derive(Event)]
+pub struct ErrorNotification {
+    pub message: String,
+    /// Text the notification offers to copy, e.g. the port that's taken.
+    pub copy: Option<String>,
+}
+
+impl ErrorNotification {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            copy: None,
+        }
+    }
+
+    /// Offer to copy `text` to the clipboard.
+    pub fn with_copy(mut self, text: impl Into<String>) -> Self {
+        self.copy = Some(text.into());
+        self
+    }
+}
+
+/// Message shown on the main menu the next time it opens, e.g. why the
+/// session ended.
+#[derive(Resource)]
+pub struct MenuNotice(pub String);
+
 /// Marker for the in-game UI camera.
 #[derive(Component)]
 pub struct InGameUICamera;
@@ -13,11 +42,17 @@ pub struct InGameUICamera;
 /// Duration in seconds for notifications to display.
 const NOTIFICATION_DURATION: f32 = 3.0;
 
-/// Sets up the notification container UI.
-pub fn setup_notification_ui(mut commands: Commands) {
-    // Spawn a UI camera for in-game notifications
-    commands.spawn((InGameUICamera, Camera2d, Camera { order: 1, ..default() }));
+/// Duration in seconds for errors to display, long enough to act on.
+const ERROR_DURATION: f32 = 8.0;
+
+const NOTIFICATION_TEXT_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
+const NOTIFICATION_BG_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.7);
+const ERROR_BG_COLOR: Color = Color::srgba(0.45, 0.08, 0.08, 0.85);
+const COPY_BUTTON_COLOR: Color = Color::srgb(0.3, 0.3, 0.3);
 
+/// Sets up the notification container, kept for the whole run so errors
+/// show in the menus too.
+pub fn setup_notification_root(mut commands: Commands) {
     commands.spawn((
         NotificationRoot,
         Node {
@@ -28,17 +63,24 @@ pub fn setup_notification_ui(mut commands: Commands) {
             row_gap: Val::Px(8.0),
             ..default()
         },
+        // Over the menus
+        GlobalZIndex(100),
     ));
 }
 
-/// Cleans up the notification container and UI camera.
+/// Sets up the UI camera notifications show on in-game.
+pub fn setup_notification_ui(mut commands: Commands) {
+    commands.spawn((InGameUICamera, Camera2d, Camera { order: 1, ..default() }));
+}
+
+/// Cleans up the in-game notifications and UI camera.
 pub fn cleanup_notification_ui(
     mut commands: Commands,
     root_query: Query<Entity, With<NotificationRoot>>,
     camera_query: Query<Entity, With<InGameUICamera>>,
 ) {
     for entity in root_query.iter() {
-        commands.entity(entity).despawn_recursive();
+        commands.entity(entity).despawn_descendants();
     }
     for entity in camera_query.iter() {
         commands.entity(entity).despawn_recursive();
@@ -64,8 +106,8 @@ pub fn display_notifications(
                     font_size: 20.0,
                     ..default()
                 },
-                TextColor(Color::srgb(0.9, 0.9, 0.9)),
-                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+                TextColor(NOTIFICATION_TEXT_COLOR),
+                BackgroundColor(NOTIFICATION_BG_COLOR),
                 Node {
                     padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
                     ..default()
@@ -75,6 +117,100 @@ pub fn display_notifications(
     }
 }
 
+/// Spawns an error notification, with a copy button if it has text to copy.
+pub fn display_errors(
+    mut commands: Commands,
+    mut events: EventReader<ErrorNotification>,
+    root_query: Query<Entity, With<NotificationRoot>>,
+) {
+    let Ok(root) = root_query.get_single() else {
+        return;
+    };
+
+    for event in events.read() {
+        commands.entity(root).with_children(|parent| {
+            parent
+                .spawn((
+                    NotificationText(ERROR_DURATION),
+                    BackgroundColor(ERROR_BG_COLOR),
+                    Node {
+                        padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(12.0),
+                        ..default()
+                    },
+                ))
+                .with_children(|row| {
+                    row.spawn((
+                        Text::new(&event.message),
+                        TextFont {
+                            font_size: 20.0,
+                            ..default()
+                        },
+                        TextColor(NOTIFICATION_TEXT_COLOR),
+                    ));
+                    let Some(copy) = &event.copy else {
+                        return;
+                    };
+                    row.spawn((
+                        NotificationCopyButton(copy.clone()),
+                        Button,
+                        Node {
+                            padding: UiRect::axes(Val::Px(8.0), Val::Px(2.0)),
+                            ..default()
+                        },
+                        BackgroundColor(COPY_BUTTON_COLOR),
+                    ))
+                    .with_child((
+                        Text::new(format!("Copy {}", copy)),
+                        TextFont {
+                            font_size: 16.0,
+                            ..default()
+                        },
+                        TextColor(NOTIFICATION_TEXT_COLOR),
+                    ));
+                });
+        });
+    }
+}
+
+/// Copies an error's text to the clipboard when its copy button is clicked.
+/// The clipboard is kept open, as on Linux what's copied goes with it.
+pub fn handle_notification_copy(
+    interaction_query: Query<
+        (&Interaction, &NotificationCopyButton, &Children),
+        Changed<Interaction>,
+    >,
+    mut text_query: Query<&mut Text>,
+    mut clipboard: Local<Option<arboard::Clipboard>>,
+) {
+    for (interaction, button, children) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let copied = match clipboard.as_mut() {
+            Some(clipboard) => clipboard.set_text(&button.0),
+            None => arboard::Clipboard::new().and_then(|mut opened| {
+                opened.set_text(&button.0)?;
+                *clipboard = Some(opened);
+                Ok(())
+            }),
+        };
+        let label = match copied {
+            Ok(()) => "Copied",
+            Err(e) => {
+                warn!("Failed to copy to the clipboard: {}", e);
+                "Couldn't copy"
+            }
+        };
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.0 = label.to_string();
+            }
+        }
+    }
+}
+
 /// Updates notification timers and removes expired notifications.
 pub fn update_notifications(
     mut commands: Commands,
@@ -0,0 +1,238 @@
+//! In-game pause menu, opened with Escape or Start. Pausing only stops the
+//! local player's gameplay: the session, stream and other players carry on.
+
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use std::net::SocketAddr;
+
+use super::components::{InviteText, PauseButton, PauseMenuRoot, RecordButtonText};
+use super::styles::*;
+use crate::camera::systems::AltCursorUnlock;
+use crate::controls::{Action, KeyBindings};
+use crate::emote::EmoteWheel;
+use crate::game_state::{AppState, PauseState};
+use crate::network::discovery::local_ip;
+use crate::network::invite::encode_invite;
+use crate::network::server::GameServer;
+use crate::network::SelectedSession;
+use crate::screen::recording::{SessionRecorder, ToggleRecording};
+use crate::screen::share_ui::ShareUIRoot;
+use crate::settings::ui::{open_settings_ui, SettingsUIRoot, SettingsUIState};
+
+const OVERLAY_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const INVITE_TEXT_COLOR: Color = Color::srgb(0.7, 0.7, 0.7);
+
+/// System to open or close the pause menu. Other overlays close themselves
+/// first, so Escape doesn't pause from under the settings or share menu.
+pub fn toggle_pause(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Query<&Gamepad>,
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    settings_root: Option<Res<SettingsUIRoot>>,
+    share_root: Option<Res<ShareUIRoot>>,
+    emote_wheel: Res<EmoteWheel>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let pressed = bindings.just_pressed(Action::Pause, &keyboard_input)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::Start));
+    if !pressed || settings_root.is_some() || share_root.is_some() || emote_wheel.open {
+        return;
+    }
+
+    match pause_state.get() {
+        PauseState::Running => next_pause_state.set(PauseState::Paused),
+        PauseState::Paused => resume(&mut next_pause_state, &mut windows),
+    }
+}
+
+fn resume(
+    next_pause_state: &mut NextState<PauseState>,
+    windows: &mut Query<&mut Window, With<PrimaryWindow>>,
+) {
+    next_pause_state.set(PauseState::Running);
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor_options.grab_mode = CursorGrabMode::Confined;
+        window.cursor_options.visible = false;
+    }
+}
+
+pub fn setup_pause_menu(
+    mut commands: Commands,
+    server: Option<Res<GameServer>>,
+    session: Option<Res<SelectedSession>>,
+    recorder: Option<Res<SessionRecorder>>,
+    mut alt_unlock: ResMut<AltCursorUnlock>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    // Releasing Alt while paused goes unseen, so don't wait for it
+    alt_unlock.active = false;
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor_options.grab_mode = CursorGrabMode::None;
+        window.cursor_options.visible = true;
+    }
+
+    // The host tells others its LAN address, clients pass on the host's
+    let invite = if let Some(server) = &server {
+        let address = local_ip().map(|ip| SocketAddr::new(ip, server.port));
+        match address.map(|address| (address, encode_invite(address))) {
+            Some((address, Some(code))) => format!(
+                "Others on your network can join with code {} or at {}",
+                code, address
+            ),
+            Some((address, None)) => format!("Others on your network can join at {}", address),
+            None => "Others on your network can find this session under Join Game".to_string(),
+        }
+    } else if let Some(session) = session {
+        format!("This session is hosted at {}", session.0.address)
+    } else {
+        "No address to share".to_string()
+    };
+
+    commands
+        .spawn((
+            PauseMenuRoot,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            BackgroundColor(OVERLAY_COLOR),
+            // Under the settings menu it opens
+            GlobalZIndex(50),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Paused"),
+                title_text_style(),
+                TextColor(TITLE_TEXT_COLOR),
+                Node {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+            ));
+
+            spawn_pause_button(parent, PauseButton::Resume, "Resume");
+            spawn_pause_button(parent, PauseButton::Settings, "Settings");
+            spawn_pause_button(parent, PauseButton::Invite, "Invite");
+
+            parent.spawn((
+                InviteText,
+                Text::new(invite),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(INVITE_TEXT_COLOR),
+                Node {
+                    display: Display::None,
+                    margin: UiRect::bottom(Val::Px(10.0)),
+                    ..default()
+                },
+            ));
+
+            // Only the host has the stream to record
+            if server.is_some() {
+                spawn_labelled_pause_button(
+                    parent,
+                    PauseButton::Record,
+                    (
+                        RecordButtonText,
+                        Text::new(record_label(recorder.is_some())),
+                    ),
+                );
+            }
+
+            spawn_pause_button(parent, PauseButton::Leave, "Leave Session");
+        });
+}
+
+fn spawn_pause_button(parent: &mut ChildBuilder, button: PauseButton, label: &str) {
+    spawn_labelled_pause_button(parent, button, (Text::new(label),));
+}
+
+fn spawn_labelled_pause_button(parent: &mut ChildBuilder, button: PauseButton, label: impl Bundle) {
+    parent
+        .spawn((
+            button,
+            Button,
+            button_style(),
+            BackgroundColor(NORMAL_BUTTON),
+        ))
+        .with_children(|parent| {
+            parent.spawn((label, button_text_style(), TextColor(BUTTON_TEXT_COLOR)));
+        });
+}
+
+fn record_label(recording: bool) -> &'static str {
+    if recording {
+        "Stop Recording"
+    } else {
+        "Start Recording"
+    }
+}
+
+pub fn cleanup_pause_menu(mut commands: Commands, query: Query<Entity, With<PauseMenuRoot>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn handle_pause_menu_click(
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &PauseButton), Changed<Interaction>>,
+    mut invite_query: Query<&mut Node, With<InviteText>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    settings_root: Option<Res<SettingsUIRoot>>,
+    mut settings_state: ResMut<SettingsUIState>,
+    mut record_events: EventWriter<ToggleRecording>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            PauseButton::Resume => resume(&mut next_pause_state, &mut windows),
+            PauseButton::Settings => {
+                if settings_root.is_none() {
+                    open_settings_ui(&mut commands, &mut settings_state);
+                }
+            }
+            PauseButton::Invite => {
+                for mut node in invite_query.iter_mut() {
+                    node.display = match node.display {
+                        Display::None => Display::Flex,
+                        _ => Display::None,
+                    };
+                }
+            }
+            PauseButton::Record => {
+                record_events.send(ToggleRecording);
+            }
+            // Leaving the game state disconnects and tears the room down
+            PauseButton::Leave => next_state.set(AppState::MainMenu),
+        }
+    }
+}
+
+/// System to keep the record button's label in step with the recorder.
+pub fn update_record_button(
+    recorder: Option<Res<SessionRecorder>>,
+    mut text_query: Query<&mut Text, With<RecordButtonText>>,
+) {
+    let label = record_label(recorder.is_some());
+    for mut text in text_query.iter_mut() {
+        if text.0 != label {
+            text.0 = label.to_string();
+        }
+    }
+}
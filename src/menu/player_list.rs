@@ -0,0 +1,288 @@
+//! Player list, shown while Tab is held: everyone in the session with their
+//! ping, who is hosting, presenting or away, and buttons to mute players.
+//! Holding Tab frees the cursor so the buttons can be clicked.
+
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use std::collections::HashSet;
+
+use super::components::{
+    MuteButton, PlayerListCell, PlayerListColumn, PlayerListRoot, PlayerListRow, PlayerListRows,
+};
+use super::styles::{BUTTON_TEXT_COLOR, NORMAL_BUTTON, PRESSED_BUTTON};
+use crate::controls::{Action, KeyBindings};
+use crate::network::protocol::{PlayerId, PlayerInfo};
+use crate::network::{LocalPlayerId, PlayerList};
+
+const BG_COLOR: Color = Color::srgba(0.1, 0.1, 0.1, 0.9);
+const DETAIL_COLOR: Color = Color::srgb(0.7, 0.7, 0.7);
+
+/// Players the local player has muted. Muting the presenter silences the
+/// stream audio.
+#[derive(Resource, Default)]
+pub struct MutedPlayers(pub HashSet<PlayerId>);
+
+/// Resource marker for when the player list is showing. Remembers whether
+/// showing it freed the cursor, so hiding it only grabs the cursor back if
+/// it was grabbed before.
+#[derive(Resource)]
+pub struct PlayerListUIRoot {
+    root: Entity,
+    freed_cursor: bool,
+}
+
+/// System to show the player list while its key is held.
+pub fn toggle_player_list(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    ui_root: Option<Res<PlayerListUIRoot>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if bindings.just_pressed(Action::PlayerList, &keyboard_input) && ui_root.is_none() {
+        let mut freed_cursor = false;
+        if let Ok(mut window) = windows.get_single_mut() {
+            if window.cursor_options.grab_mode != CursorGrabMode::None {
+                window.cursor_options.grab_mode = CursorGrabMode::None;
+                window.cursor_options.visible = true;
+                freed_cursor = true;
+            }
+        }
+        let root = spawn_player_list(&mut commands);
+        commands.insert_resource(PlayerListUIRoot { root, freed_cursor });
+        return;
+    }
+
+    if bindings.just_released(Action::PlayerList, &keyboard_input) {
+        let Some(ui_root) = ui_root else {
+            return;
+        };
+        commands.entity(ui_root.root).despawn_recursive();
+        commands.remove_resource::<PlayerListUIRoot>();
+        if ui_root.freed_cursor {
+            if let Ok(mut window) = windows.get_single_mut() {
+                window.cursor_options.grab_mode = CursorGrabMode::Confined;
+                window.cursor_options.visible = false;
+            }
+        }
+    }
+}
+
+/// Hide the player list without touching the cursor, when pausing or leaving.
+pub fn hide_player_list(mut commands: Commands, ui_root: Option<Res<PlayerListUIRoot>>) {
+    if let Some(ui_root) = ui_root {
+        commands.entity(ui_root.root).despawn_recursive();
+        commands.remove_resource::<PlayerListUIRoot>();
+    }
+}
+
+/// Mutes only last for the session.
+pub fn clear_muted_players(mut muted: ResMut<MutedPlayers>) {
+    muted.0.clear();
+}
+
+fn spawn_player_list(commands: &mut Commands) -> Entity {
+    commands
+        .spawn((
+            PlayerListRoot,
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(80.0),
+                left: Val::Percent(50.0),
+                width: Val::Px(520.0),
+                margin: UiRect::left(Val::Px(-260.0)),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(16.0)),
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            BackgroundColor(BG_COLOR),
+            GlobalZIndex(40),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("Players"),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+            parent.spawn((
+                PlayerListRows,
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.0),
+                    ..default()
+                },
+            ));
+        })
+        .id()
+}
+
+/// What a player list cell says.
+fn cell_text(column: PlayerListColumn, player: &PlayerInfo, local: bool, muted: bool) -> String {
+    match column {
+        PlayerListColumn::Name if local => format!("{} (you)", player.display_name()),
+        PlayerListColumn::Name => player.display_name(),
+        PlayerListColumn::Role => {
+            let role = match (player.host, player.presenting) {
+                (true, true) => "Host, presenting",
+                (true, false) => "Host",
+                (false, true) => "Presenting",
+                (false, false) => "",
+            };
+            match (role.is_empty(), player.afk) {
+                (_, false) => role.to_string(),
+                (true, true) => "AFK".to_string(),
+                (false, true) => format!("{}, AFK", role),
+            }
+        }
+        PlayerListColumn::Ping => match player.ping_ms {
+            _ if player.host => "-".to_string(),
+            Some(ms) => format!("{} ms", ms),
+            None => "...".to_string(),
+        },
+        PlayerListColumn::Mute if muted => "Unmute".to_string(),
+        PlayerListColumn::Mute => "Mute".to_string(),
+    }
+}
+
+/// System to keep the rows in step with who is in the session. Rows are only
+/// respawned when players come or go, so clicks on mute buttons aren't lost.
+pub fn update_player_list(
+    mut commands: Commands,
+    player_list: Res<PlayerList>,
+    muted: Res<MutedPlayers>,
+    local_id: Option<Res<LocalPlayerId>>,
+    rows_query: Query<(Entity, Option<&Children>), With<PlayerListRows>>,
+    row_query: Query<&PlayerListRow>,
+    mut cell_query: Query<(&PlayerListCell, &mut Text)>,
+    mut button_query: Query<(&MuteButton, &mut BackgroundColor)>,
+) {
+    let local_id = local_id.map(|id| id.0);
+    let Ok((rows, children)) = rows_query.get_single() else {
+        return;
+    };
+    let children = children.map(|c| c.to_vec()).unwrap_or_default();
+
+    let shown: Vec<PlayerId> = row_query.iter_many(&children).map(|row| row.0).collect();
+    let current: Vec<PlayerId> = player_list.players.iter().map(|p| p.id).collect();
+    if shown != current {
+        commands.entity(rows).despawn_descendants();
+        commands.entity(rows).with_children(|parent| {
+            for player in &player_list.players {
+                let local = Some(player.id) == local_id;
+                spawn_row(parent, player, local, muted.0.contains(&player.id));
+            }
+        });
+        return;
+    }
+
+    for (cell, mut text) in cell_query.iter_mut() {
+        let Some(player) = player_list.players.iter().find(|p| p.id == cell.player) else {
+            continue;
+        };
+        let local = Some(player.id) == local_id;
+        let value = cell_text(cell.column, player, local, muted.0.contains(&player.id));
+        if text.0 != value {
+            text.0 = value;
+        }
+    }
+    for (button, mut color) in button_query.iter_mut() {
+        let value = if muted.0.contains(&button.0) {
+            PRESSED_BUTTON
+        } else {
+            NORMAL_BUTTON
+        };
+        if color.0 != value {
+            color.0 = value;
+        }
+    }
+}
+
+fn spawn_row(parent: &mut ChildBuilder, player: &PlayerInfo, local: bool, muted: bool) {
+    parent
+        .spawn((
+            PlayerListRow(player.id),
+            Node {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
+                height: Val::Px(30.0),
+                ..default()
+            },
+        ))
+        .with_children(|row| {
+            for (column, width) in [
+                (PlayerListColumn::Name, 200.0),
+                (PlayerListColumn::Role, 130.0),
+                (PlayerListColumn::Ping, 60.0),
+            ] {
+                row.spawn((
+                    PlayerListCell {
+                        player: player.id,
+                        column,
+                    },
+                    Text::new(cell_text(column, player, local, muted)),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    TextColor(if column == PlayerListColumn::Name {
+                        Color::WHITE
+                    } else {
+                        DETAIL_COLOR
+                    }),
+                    Node {
+                        width: Val::Px(width),
+                        overflow: Overflow::clip(),
+                        ..default()
+                    },
+                ));
+            }
+
+            // Nobody needs to mute themselves
+            if local {
+                return;
+            }
+            row.spawn((
+                MuteButton(player.id),
+                Button,
+                Node {
+                    width: Val::Px(70.0),
+                    height: Val::Px(26.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                BackgroundColor(if muted { PRESSED_BUTTON } else { NORMAL_BUTTON }),
+            ))
+            .with_child((
+                PlayerListCell {
+                    player: player.id,
+                    column: PlayerListColumn::Mute,
+                },
+                Text::new(cell_text(PlayerListColumn::Mute, player, local, muted)),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(BUTTON_TEXT_COLOR),
+            ));
+        });
+}
+
+pub fn handle_mute_click(
+    interaction_query: Query<(&Interaction, &MuteButton), Changed<Interaction>>,
+    mut muted: ResMut<MutedPlayers>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if !muted.0.remove(&button.0) {
+            muted.0.insert(button.0);
+        }
+    }
+}
@@ -1,11 +1,28 @@
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
 use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
 
 use super::components::*;
 use super::styles::*;
+use super::{ErrorNotification, MenuNotice};
+use crate::character::avatars::{model_name, tint_name};
+use crate::character::AvatarSelection;
 use crate::game_state::AppState;
-use crate::network::DiscoveredSessions;
+use crate::network::discovery::LanSession;
+use crate::network::invite::decode_invite;
+use crate::network::{DiscoveredSessions, SelectedSession};
+use crate::settings::ui::{open_settings_ui, SettingsUIRoot, SettingsUIState};
+use crate::world::{CurrentRoom, RoomId};
+
+pub fn setup_main_menu(
+    mut commands: Commands,
+    avatar: Res<AvatarSelection>,
+    notice: Option<Res<MenuNotice>>,
+) {
+    // The notice is shown once
+    commands.remove_resource::<MenuNotice>();
 
-pub fn setup_main_menu(mut commands: Commands) {
     // Spawn menu camera for UI rendering
     commands.spawn((MenuCamera, Camera2d));
 
@@ -35,6 +52,22 @@ pub fn setup_main_menu(mut commands: Commands) {
                 },
             ));
 
+            if let Some(notice) = &notice {
+                parent.spawn((
+                    Text::new(notice.0.clone()),
+                    button_text_style(),
+                    TextColor(BUTTON_TEXT_COLOR),
+                    Node {
+                        margin: UiRect::bottom(Val::Px(20.0)),
+                        ..default()
+                    },
+                ));
+            }
+
+            // Avatar picker
+            spawn_avatar_picker(parent, AvatarField::Model, &avatar);
+            spawn_avatar_picker(parent, AvatarField::Tint, &avatar);
+
             // Host button
             parent
                 .spawn((
@@ -66,9 +99,81 @@ pub fn setup_main_menu(mut commands: Commands) {
                         TextColor(BUTTON_TEXT_COLOR),
                     ));
                 });
+
+            // Settings button
+            parent
+                .spawn((
+                    SettingsButton,
+                    Button,
+                    button_style(),
+                    BackgroundColor(NORMAL_BUTTON),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Settings"),
+                        button_text_style(),
+                        TextColor(BUTTON_TEXT_COLOR),
+                    ));
+                });
+        });
+}
+
+/// A row of `< choice >` that cycles through the choices for an avatar field.
+fn spawn_avatar_picker(parent: &mut ChildBuilder, field: AvatarField, avatar: &AvatarSelection) {
+    parent
+        .spawn(Node {
+            align_items: AlignItems::Center,
+            ..default()
+        })
+        .with_children(|row| {
+            spawn_avatar_arrow(row, field, -1);
+            row.spawn((
+                AvatarLabel(field),
+                Text::new(avatar_label(field, avatar)),
+                button_text_style(),
+                TextColor(BUTTON_TEXT_COLOR),
+                Node {
+                    width: Val::Px(250.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                TextLayout::new_with_justify(JustifyText::Center),
+            ));
+            spawn_avatar_arrow(row, field, 1);
         });
 }
 
+fn spawn_avatar_arrow(parent: &mut ChildBuilder, field: AvatarField, step: isize) {
+    parent
+        .spawn((
+            AvatarArrow { field, step },
+            Button,
+            Node {
+                width: Val::Px(50.0),
+                height: Val::Px(50.0),
+                margin: UiRect::all(Val::Px(5.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(NORMAL_BUTTON),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(if step < 0 { "<" } else { ">" }),
+                button_text_style(),
+                TextColor(BUTTON_TEXT_COLOR),
+            ));
+        });
+}
+
+fn avatar_label(field: AvatarField, avatar: &AvatarSelection) -> String {
+    match field {
+        AvatarField::Model => model_name(avatar.0.model),
+        AvatarField::Tint => format!("Tint: {}", tint_name(avatar.0.tint)),
+    }
+}
+
 pub fn cleanup_main_menu(mut commands: Commands, query: Query<Entity, With<MainMenuRoot>>) {
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive();
@@ -81,7 +186,92 @@ pub fn cleanup_menu_camera(mut commands: Commands, query: Query<Entity, With<Men
     }
 }
 
+pub fn setup_room_select(mut commands: Commands) {
+    commands
+        .spawn((
+            RoomSelectRoot,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+        ))
+        .with_children(|parent| {
+            // Title
+            parent.spawn((
+                Text::new("Choose a Room"),
+                title_text_style(),
+                TextColor(TITLE_TEXT_COLOR),
+                Node {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                },
+            ));
+
+            for room in RoomId::available() {
+                parent
+                    .spawn((
+                        RoomOption(room.clone()),
+                        Button,
+                        button_style(),
+                        BackgroundColor(NORMAL_BUTTON),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            Text::new(room.name()),
+                            button_text_style(),
+                            TextColor(BUTTON_TEXT_COLOR),
+                        ));
+                    });
+            }
+
+            // Back button
+            parent
+                .spawn((
+                    BackButton,
+                    Button,
+                    Node {
+                        margin: UiRect::top(Val::Px(30.0)),
+                        ..button_style()
+                    },
+                    BackgroundColor(NORMAL_BUTTON),
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Back"),
+                        button_text_style(),
+                        TextColor(BUTTON_TEXT_COLOR),
+                    ));
+                });
+        });
+}
+
+pub fn cleanup_room_select(mut commands: Commands, query: Query<Entity, With<RoomSelectRoot>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Longest invite code that can be typed, with its dash.
+const MAX_INVITE_CODE_CHARS: usize = 11;
+
+const PLACEHOLDER_TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+
+/// Resource holding the invite code typed in the browser.
+#[derive(Resource, Default)]
+pub struct InviteCodeInput {
+    pub code: String,
+    /// Whether keys go to the code field.
+    pub editing: bool,
+}
+
 pub fn setup_browser(mut commands: Commands) {
+    commands.insert_resource(InviteCodeInput::default());
+
     // Root container for browser
     commands
         .spawn((
@@ -132,6 +322,51 @@ pub fn setup_browser(mut commands: Commands) {
                 },
             ));
 
+            // Join with an invite code, for sessions discovery can't see
+            parent
+                .spawn(Node {
+                    margin: UiRect::top(Val::Px(30.0)),
+                    align_items: AlignItems::Center,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn((
+                            InviteCodeField,
+                            Button,
+                            Node {
+                                width: Val::Px(300.0),
+                                ..button_style()
+                            },
+                            BackgroundColor(NORMAL_BUTTON),
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                InviteCodeText,
+                                Text::new(invite_code_label(&InviteCodeInput::default())),
+                                button_text_style(),
+                                TextColor(PLACEHOLDER_TEXT_COLOR),
+                            ));
+                        });
+                    parent
+                        .spawn((
+                            JoinCodeButton,
+                            Button,
+                            Node {
+                                width: Val::Px(120.0),
+                                ..button_style()
+                            },
+                            BackgroundColor(NORMAL_BUTTON),
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                Text::new("Join"),
+                                button_text_style(),
+                                TextColor(BUTTON_TEXT_COLOR),
+                            ));
+                        });
+                });
+
             // Back button
             parent
                 .spawn((
@@ -157,6 +392,115 @@ pub fn cleanup_browser(mut commands: Commands, query: Query<Entity, With<Browser
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive();
     }
+    commands.remove_resource::<InviteCodeInput>();
+}
+
+fn invite_code_label(input: &InviteCodeInput) -> String {
+    match (input.code.is_empty(), input.editing) {
+        (true, false) => "Enter invite code".to_string(),
+        (_, true) => format!("{}_", input.code),
+        (false, false) => input.code.clone(),
+    }
+}
+
+/// Start typing into the invite code field, or join with the code typed.
+pub fn handle_invite_code_click(
+    field_query: Query<&Interaction, (Changed<Interaction>, With<InviteCodeField>)>,
+    join_query: Query<&Interaction, (Changed<Interaction>, With<JoinCodeButton>)>,
+    mut input: ResMut<InviteCodeInput>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut errors: EventWriter<ErrorNotification>,
+) {
+    if field_query.iter().any(|i| *i == Interaction::Pressed) {
+        input.editing = true;
+    }
+    if join_query.iter().any(|i| *i == Interaction::Pressed) {
+        input.editing = false;
+        join_with_code(&input.code, &mut commands, &mut next_state, &mut errors);
+    }
+}
+
+/// Type into the invite code field while it's being edited. Enter joins,
+/// Escape finishes editing.
+pub fn edit_invite_code(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut input: ResMut<InviteCodeInput>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut errors: EventWriter<ErrorNotification>,
+) {
+    if !input.editing {
+        keyboard_events.clear();
+        return;
+    }
+
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Enter => {
+                input.editing = false;
+                join_with_code(&input.code, &mut commands, &mut next_state, &mut errors);
+            }
+            Key::Escape => input.editing = false,
+            Key::Backspace => {
+                input.code.pop();
+            }
+            Key::Character(text) => {
+                for c in text.chars() {
+                    let valid = c.is_ascii_alphanumeric() || c == '-';
+                    if valid && input.code.len() < MAX_INVITE_CODE_CHARS {
+                        input.code.push(c.to_ascii_uppercase());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Connect to the session an invite code names, or say the code is wrong.
+fn join_with_code(
+    code: &str,
+    commands: &mut Commands,
+    next_state: &mut NextState<AppState>,
+    errors: &mut EventWriter<ErrorNotification>,
+) {
+    let Some(address) = decode_invite(code) else {
+        errors.send(ErrorNotification::new(
+            "That invite code isn't right - check it with the host",
+        ));
+        return;
+    };
+    commands.insert_resource(SelectedSession(LanSession {
+        name: code.to_string(),
+        address,
+        player_count: 0,
+        room: RoomId::default(),
+        instance: None,
+    }));
+    next_state.set(AppState::Connecting);
+}
+
+/// Show the invite code typed so far.
+pub fn update_invite_code_text(
+    input: Res<InviteCodeInput>,
+    mut text_query: Query<(&mut Text, &mut TextColor), With<InviteCodeText>>,
+) {
+    if !input.is_changed() {
+        return;
+    }
+    let color = if input.code.is_empty() && !input.editing {
+        PLACEHOLDER_TEXT_COLOR
+    } else {
+        BUTTON_TEXT_COLOR
+    };
+    for (mut text, mut text_color) in text_query.iter_mut() {
+        text.0 = invite_code_label(&input);
+        text_color.0 = color;
+    }
 }
 
 pub fn button_interaction(
@@ -186,6 +530,19 @@ pub fn handle_host_click(
 ) {
     for interaction in interaction_query.iter() {
         if *interaction == Interaction::Pressed {
+            next_state.set(AppState::RoomSelect);
+        }
+    }
+}
+
+pub fn handle_room_click(
+    interaction_query: Query<(&Interaction, &RoomOption), Changed<Interaction>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut commands: Commands,
+) {
+    for (interaction, option) in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            commands.insert_resource(CurrentRoom(option.0.clone()));
             next_state.set(AppState::Hosting);
         }
     }
@@ -202,6 +559,46 @@ pub fn handle_join_click(
     }
 }
 
+pub fn handle_settings_click(
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<SettingsButton>)>,
+    mut commands: Commands,
+    settings_root: Option<Res<SettingsUIRoot>>,
+    mut settings_state: ResMut<SettingsUIState>,
+) {
+    for interaction in interaction_query.iter() {
+        if *interaction == Interaction::Pressed && settings_root.is_none() {
+            open_settings_ui(&mut commands, &mut settings_state);
+        }
+    }
+}
+
+/// Step the avatar choice. The config file saves it for next time.
+pub fn handle_avatar_click(
+    interaction_query: Query<(&Interaction, &AvatarArrow), Changed<Interaction>>,
+    mut avatar: ResMut<AvatarSelection>,
+) {
+    for (interaction, arrow) in interaction_query.iter() {
+        if *interaction == Interaction::Pressed {
+            match arrow.field {
+                AvatarField::Model => avatar.cycle_model(arrow.step),
+                AvatarField::Tint => avatar.cycle_tint(arrow.step),
+            }
+        }
+    }
+}
+
+pub fn update_avatar_labels(
+    avatar: Res<AvatarSelection>,
+    mut label_query: Query<(&AvatarLabel, &mut Text)>,
+) {
+    if !avatar.is_changed() {
+        return;
+    }
+    for (label, mut text) in label_query.iter_mut() {
+        text.0 = avatar_label(label.0, &avatar);
+    }
+}
+
 pub fn handle_back_click(
     interaction_query: Query<&Interaction, (Changed<Interaction>, With<BackButton>)>,
     mut next_state: ResMut<NextState<AppState>>,
@@ -250,8 +647,17 @@ pub fn update_session_list(
                         BackgroundColor(NORMAL_BUTTON),
                     ))
                     .with_children(|parent| {
+                        let name = match &session.instance {
+                            Some(instance) => format!("{} [{}]", session.name, instance),
+                            None => session.name.clone(),
+                        };
                         parent.spawn((
-                            Text::new(format!("{} ({} players)", session.name, session.player_count)),
+                            Text::new(format!(
+                                "{} - {} ({} players)",
+                                name,
+                                session.room.name(),
+                                session.player_count
+                            )),
                             button_text_style(),
                             TextColor(BUTTON_TEXT_COLOR),
                         ));
@@ -278,7 +684,7 @@ pub fn handle_session_click(
     }
 }
 
-pub fn release_cursor(mut windows: Query<&mut Window>) {
+pub fn release_cursor(mut windows: Query<&mut Window, With<PrimaryWindow>>) {
     if let Ok(mut window) = windows.get_single_mut() {
         window.cursor_options.grab_mode = bevy::window::CursorGrabMode::None;
         window.cursor_options.visible = true;
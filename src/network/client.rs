@@ -1,52 +1,183 @@
 use bevy::prelude::*;
-use std::net::UdpSocket;
+use bevy_rapier3d::prelude::{Collider, Sensor};
+use std::net::{SocketAddr, UdpSocket};
 use std::time::Duration;
 
+use super::conditioner::NetworkConditions;
 use super::discovery::SelectedSession;
 use super::protocol::{
-    ClientMessage, LocalPlayerId, NetworkTransform, RemotePlayer, RemotePlayers, ServerMessage,
+    AssignedSpawn, AudioCodecKind, ClientMessage, LocalPlayerId, NetworkTransform, PlayerList, RemotePlayer, RemotePlayerEntities, RemotePlayers, ScreenId,
+    ServerMessage,
 };
-use crate::character::{CharacterAssets, CharacterAnimationState, NeedsAnimationSetup};
+use super::receiver::{Received, SocketReceiver};
+use super::replay::{NetworkRecording, NetworkReplay};
+use crate::character::{AvatarSelection, CharacterAnimationState, CharacterAvatar, MODEL_OFFSET};
+use crate::controls::Afk;
+use crate::emote::EmoteEvent;
 use crate::game_state::AppState;
-use crate::player::Player;
+use crate::menu::{MenuNotice, MutedPlayers};
+use crate::player::{
+    body_shape, Crouching, Grounded, Player, Seated, Sprinting, Velocity, CROUCH_HEIGHT, GRAVITY,
+    PLAYER_HEIGHT, SEATED_EYE_HEIGHT,
+};
+use crate::world::{
+    CurrentRoom, DoorStates, PosterAssignments, PosterCache, ScreenLayout, Whiteboard,
+};
 
 use crate::screen::audio_decoder::AudioDecoder;
+use crate::screen::av_sync::AvSyncClock;
+use crate::screen::diagnostics::StreamCounters;
+use crate::screen::stream_stats::StreamStats;
 use crate::screen::video_decoder::{VideoDecoder, VideoJitterBuffer};
+use crate::screen::AnnouncedStreamSize;
+use crate::settings::{AudioSettings, Nickname, VideoSettings};
+
+/// Largest datagram the client receives, fitting video frame chunks.
+const RECEIVE_BUFFER_SIZE: usize = 32768;
 
 /// Resource indicating this instance is a client.
 #[derive(Resource)]
 pub struct GameClient {
     pub socket: UdpSocket,
+    /// Messages from the host, received on their own thread.
+    pub receiver: SocketReceiver<ServerMessage>,
+}
+
+/// Sensor capsule standing in for a remote player's body, a child of their character.
+#[derive(Component)]
+pub struct RemotePlayerBody {
+    pub height: f32,
+}
+
+impl RemotePlayerBody {
+    fn bundle(height: f32) -> (Self, Collider, Transform) {
+        // The character's origin is at its feet, the capsule hangs below the eyes
+        let (collider, offset, _) = body_shape(height);
+        let eyes = Vec3::Y * (height - MODEL_OFFSET);
+        (Self { height }, collider, Transform::from_translation(eyes + offset))
+    }
 }
 
 /// Timer for sending updates to server.
 #[derive(Resource)]
 pub struct ClientSyncTimer(pub Timer);
 
+/// Room screen the host's video stream is currently addressed to.
+#[derive(Resource, Default)]
+pub struct ActiveStreamScreen(pub ScreenId);
+
 pub fn client_plugin(app: &mut App) {
-    app.add_systems(OnEnter(AppState::Connecting), setup_client)
+    app.init_resource::<ActiveStreamScreen>()
+        .add_systems(OnEnter(AppState::Connecting), setup_client)
         .add_systems(OnExit(AppState::InGame), cleanup_client)
         .add_systems(OnExit(AppState::Connecting), cleanup_on_connect_fail)
         .add_systems(
             Update,
             (client_receive, client_connect_check, handle_host_disconnected)
                 .run_if(in_state(AppState::Connecting)),
+        )
+        // Keep answering the host while the room loads
+        .add_systems(
+            Update,
+            (client_receive, handle_host_disconnected).run_if(in_state(AppState::Loading)),
+        )
+        .add_systems(
+            OnTransition {
+                exited: AppState::Loading,
+                entered: AppState::MainMenu,
+            },
+            cleanup_client,
         );
 
     app.add_systems(
         Update,
-        (client_receive, send_player_update, process_video_decoder, handle_host_disconnected)
+        (
+            client_receive,
+            apply_position_correction,
+            send_player_update,
+            send_whiteboard_strokes,
+            send_door_toggles,
+            send_poster_requests,
+            send_emotes,
+            send_nickname_changes,
+            process_video_decoder,
+            apply_output_device_setting,
+            apply_playback_settings,
+            handle_host_disconnected,
+        )
             .run_if(in_state(AppState::InGame).and(resource_exists::<GameClient>)),
     );
 }
 
+/// Restart audio playback on the device chosen in the settings.
+fn apply_output_device_setting(
+    mut commands: Commands,
+    audio_settings: Res<AudioSettings>,
+    audio_decoder: Option<Res<AudioDecoder>>,
+    av_clock: Option<Res<AvSyncClock>>,
+) {
+    if !audio_settings.is_changed() {
+        return;
+    }
+    let (Some(audio_decoder), Some(av_clock)) = (audio_decoder, av_clock) else {
+        return;
+    };
+    if audio_decoder.output_device == audio_settings.output_device {
+        return;
+    }
+
+    info!("Switching audio output device to {:?}", audio_settings.output_device);
+    // Replacing the resource drops the old decoder, which stops its playback stream
+    match AudioDecoder::new(av_clock.clone(), audio_settings.output_device.clone()) {
+        Some(decoder) => commands.insert_resource(decoder),
+        None => warn!("Failed to switch audio output device, keeping {:?}", audio_decoder.output_device),
+    }
+}
+
+/// Apply the volume/mute and buffering settings to stream audio playback.
+/// Stream audio is the presenter's, so muting them in the player list
+/// silences it too.
+fn apply_playback_settings(
+    audio_settings: Res<AudioSettings>,
+    audio_decoder: Option<Res<AudioDecoder>>,
+    player_list: Res<PlayerList>,
+    muted_players: Res<MutedPlayers>,
+    mut presenter_muted: Local<bool>,
+) {
+    let Some(audio_decoder) = audio_decoder else {
+        return;
+    };
+    let muted = player_list
+        .players
+        .iter()
+        .any(|player| player.presenting && muted_players.0.contains(&player.id));
+    // A recreated decoder starts with default settings
+    if audio_settings.is_changed() || audio_decoder.is_added() || *presenter_muted != muted {
+        *presenter_muted = muted;
+        let gain = if muted {
+            0.0
+        } else {
+            audio_settings.playback_gain()
+        };
+        audio_decoder.set_gain(gain);
+        audio_decoder.set_jitter_target_ms(audio_settings.jitter_buffer_ms);
+        audio_decoder.set_latency_target_ms(audio_settings.playback_latency_ms);
+    }
+}
+
 /// Handle host disconnection by returning to main menu.
 fn handle_host_disconnected(
     mut commands: Commands,
     mut next_state: ResMut<NextState<AppState>>,
     disconnected: Option<Res<HostDisconnected>>,
 ) {
-    if disconnected.is_some() {
+    if let Some(disconnected) = disconnected {
+        let notice = match &*disconnected {
+            HostDisconnected::SessionClosed => "Host ended the session",
+            HostDisconnected::ConnectionLost => "Lost connection to the host",
+            HostDisconnected::Kicked(reason) => reason,
+        };
+        commands.insert_resource(MenuNotice(notice.to_string()));
         commands.remove_resource::<HostDisconnected>();
         next_state.set(AppState::MainMenu);
     }
@@ -65,13 +196,26 @@ fn cleanup_on_connect_fail(
         commands.remove_resource::<ClientSyncTimer>();
         commands.remove_resource::<VideoDecoder>();
         commands.remove_resource::<VideoJitterBuffer>();
+        commands.remove_resource::<StreamStats>();
         commands.remove_resource::<AudioDecoder>();
+        commands.remove_resource::<AvSyncClock>();
         commands.remove_resource::<HostDisconnected>();
         commands.remove_resource::<SelectedSession>();
+        commands.remove_resource::<NetworkReplay>();
     }
 }
 
-fn setup_client(mut commands: Commands, selected: Option<Res<SelectedSession>>) {
+fn setup_client(
+    mut commands: Commands,
+    selected: Option<Res<SelectedSession>>,
+    audio_settings: Res<AudioSettings>,
+    avatar: Res<AvatarSelection>,
+    nickname: Res<Nickname>,
+    conditions: Option<Res<NetworkConditions>>,
+    recording: Option<Res<NetworkRecording>>,
+    replay: Option<Res<NetworkReplay>>,
+    rejoin: Option<Res<RejoinToken>>,
+) {
     let Some(selected) = selected else {
         error!("No session selected");
         return;
@@ -112,13 +256,32 @@ fn setup_client(mut commands: Commands, selected: Option<Res<SelectedSession>>)
         return;
     }
 
-    // Send join request
-    let join_msg = ClientMessage::Join;
+    // A replay stands in for the host, so nothing is read off the socket
+    let conditions = conditions.as_deref().copied();
+    let recording = recording.as_ref().map(|recording| recording.0.as_path());
+    let receiver = match &replay {
+        Some(replay) => SocketReceiver::replay(&replay.0, selected.0.address),
+        None => SocketReceiver::spawn(&socket, RECEIVE_BUFFER_SIZE, conditions, recording),
+    };
+    let Some(receiver) = receiver else {
+        return;
+    };
+
+    // Send join request, advertising which codecs we can decode, our avatar and name
+    let join_msg = ClientMessage::Join {
+        supported_codecs: VideoDecoder::supported_codecs(),
+        supported_audio_codecs: AudioCodecKind::supported(),
+        avatar: avatar.0,
+        nickname: nickname.0.clone(),
+        rejoin_token: rejoin
+            .filter(|rejoin| rejoin.host == selected.0.address)
+            .map(|rejoin| rejoin.token),
+    };
     if let Ok(data) = serde_json::to_vec(&join_msg) {
         let _ = socket.send(&data);
     }
 
-    commands.insert_resource(GameClient { socket });
+    commands.insert_resource(GameClient { socket, receiver });
     commands.insert_resource(RemotePlayers::default());
     commands.insert_resource(ClientSyncTimer(Timer::new(
         Duration::from_millis(50),
@@ -128,15 +291,18 @@ fn setup_client(mut commands: Commands, selected: Option<Res<SelectedSession>>)
     if let Some(decoder) = VideoDecoder::new() {
         commands.insert_resource(decoder);
         commands.insert_resource(VideoJitterBuffer::default());
+        commands.insert_resource(StreamStats::default());
         info!("Video decoder initialized (OpenH264)");
     } else {
         error!("Failed to initialize video decoder");
     }
 
     // Initialize audio decoder and playback
-    if let Some(audio_decoder) = AudioDecoder::new() {
+    let av_clock = AvSyncClock::default();
+    commands.insert_resource(av_clock.clone());
+    if let Some(audio_decoder) = AudioDecoder::new(av_clock, audio_settings.output_device.clone()) {
         commands.insert_resource(audio_decoder);
-        info!("Audio decoder initialized (Opus)");
+        info!("Audio decoder initialized");
     } else {
         warn!("Failed to initialize audio decoder - audio playback disabled");
     }
@@ -155,13 +321,20 @@ fn cleanup_client(mut commands: Commands, client: Option<Res<GameClient>>) {
 
     commands.remove_resource::<GameClient>();
     commands.remove_resource::<SelectedSession>();
+    commands.remove_resource::<NetworkReplay>();
     commands.remove_resource::<LocalPlayerId>();
+    commands.remove_resource::<AssignedSpawn>();
+    commands.remove_resource::<CorrectedPosition>();
     commands.remove_resource::<RemotePlayers>();
+    commands.insert_resource(PlayerList::default());
     commands.remove_resource::<ClientSyncTimer>();
     commands.remove_resource::<VideoDecoder>();
     commands.remove_resource::<VideoJitterBuffer>();
+    commands.remove_resource::<StreamStats>();
     commands.remove_resource::<AudioDecoder>();
+    commands.remove_resource::<AvSyncClock>();
     commands.remove_resource::<HostDisconnected>();
+    commands.remove_resource::<AnnouncedStreamSize>();
 }
 
 /// Event to update the screen texture with received frame data.
@@ -170,19 +343,49 @@ pub struct ReceivedScreenFrame {
     pub rgba: Vec<u8>,
     pub width: u32,
     pub height: u32,
+    /// Room screen the frame is shown on.
+    pub screen_id: ScreenId,
 }
 
 /// Resource to signal that the host has disconnected.
 #[derive(Resource)]
-pub struct HostDisconnected;
+pub enum HostDisconnected {
+    /// The host said it ended the session.
+    SessionClosed,
+    /// The connection to the host failed.
+    ConnectionLost,
+    /// The host removed us from the session, for the reason given.
+    Kicked(String),
+}
+
+/// Token the host last welcomed us with. Kept after leaving, so joining that
+/// host again within its grace period gets our place back.
+#[derive(Resource)]
+struct RejoinToken {
+    host: SocketAddr,
+    token: u64,
+}
+
+/// Where the host put the local player back to, applied on the next frame.
+#[derive(Resource)]
+struct CorrectedPosition(Vec3);
 
 fn client_receive(
     client: Option<Res<GameClient>>,
     mut commands: Commands,
     mut remote_players: Option<ResMut<RemotePlayers>>,
+    mut player_list: ResMut<PlayerList>,
     local_id: Option<Res<LocalPlayerId>>,
     mut video_decoder: Option<ResMut<VideoDecoder>>,
+    mut stream_stats: Option<ResMut<StreamStats>>,
     audio_decoder: Option<Res<AudioDecoder>>,
+    mut stream_screen: ResMut<ActiveStreamScreen>,
+    mut screen_layout: ResMut<ScreenLayout>,
+    mut whiteboard: ResMut<Whiteboard>,
+    mut door_states: ResMut<DoorStates>,
+    mut poster_assignments: ResMut<PosterAssignments>,
+    mut poster_cache: ResMut<PosterCache>,
+    mut emote_events: EventWriter<EmoteEvent>,
     disconnected: Option<Res<HostDisconnected>>,
 ) {
     // Skip receiving if already marked as disconnected
@@ -192,80 +395,199 @@ fn client_receive(
 
     let Some(client) = client else { return };
 
-    // Large buffer to handle video frame chunks
-    let mut buf = [0u8; 32768];
-
-    loop {
-        match client.socket.recv(&mut buf) {
-            Ok(len) => {
-                if let Ok(msg) = serde_json::from_slice::<ServerMessage>(&buf[..len]) {
-                    match msg {
-                        ServerMessage::Welcome { your_id } => {
-                            info!("Received welcome, assigned ID: {}", your_id);
-                            commands.insert_resource(LocalPlayerId(your_id));
-                        }
-                        ServerMessage::GameState { players } => {
-                            if let Some(ref mut remote) = remote_players {
-                                let my_id = local_id.as_ref().map(|id| id.0);
-                                remote.players = players
-                                    .into_iter()
-                                    .filter(|p| Some(p.id) != my_id)
-                                    .collect();
-                            }
-                        }
-                        ServerMessage::PlayerLeft { id } => {
-                            info!("Player {} left", id);
-                            if let Some(ref mut remote) = remote_players {
-                                remote.players.retain(|p| p.id != id);
-                            }
-                        }
-                        ServerMessage::VideoFrame(chunk) => {
-                            static CHUNK_COUNT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
-                            let count = CHUNK_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                            if count % 100 == 0 {
-                                info!("Received video chunk {} (frame {}, chunk {}/{})", count, chunk.frame_id, chunk.chunk_idx, chunk.total_chunks);
-                            }
-                            if let Some(ref mut decoder) = video_decoder {
-                                decoder.add_chunk(chunk);
-                            }
+    while let Some(received) = client.receiver.try_recv() {
+        match received {
+            Received::Message { msg, from, len } => match msg {
+                ServerMessage::Welcome {
+                    your_id,
+                    room,
+                    spawn,
+                    rejoin_token,
+                } => {
+                    info!(
+                        "Received welcome, assigned ID: {} in room {}",
+                        your_id,
+                        room.name()
+                    );
+                    commands.insert_resource(CurrentRoom(room));
+                    commands.insert_resource(LocalPlayerId(your_id));
+                    if let Some(spawn) = spawn {
+                        commands.insert_resource(AssignedSpawn(spawn));
+                    }
+                    if let Some(token) = rejoin_token {
+                        commands.insert_resource(RejoinToken { host: from, token });
+                    }
+                }
+                ServerMessage::GameState {
+                    players,
+                    deltas,
+                    info,
+                } => {
+                    if let Some(ref mut remote) = remote_players {
+                        let my_id = local_id.as_ref().map(|id| id.0);
+                        for state in players.iter().filter(|p| Some(p.id) != my_id) {
+                            remote.upsert(state);
                         }
-                        ServerMessage::VideoCodec(info) => {
-                            if let Some(ref mut decoder) = video_decoder {
-                                decoder.set_codec_info(info);
-                            }
+                        for delta in deltas.iter().filter(|d| Some(d.id) != my_id) {
+                            remote.apply_delta(delta);
                         }
-                        ServerMessage::AudioFrame(chunk) => {
-                            if let Some(ref decoder) = audio_decoder {
-                                decoder.add_chunk(chunk);
-                            }
+                        if let Some(ref info) = info {
+                            remote.retain(|id| info.iter().any(|p| p.id == id));
                         }
                     }
+                    if let Some(info) = info {
+                        player_list.players = info;
+                    }
                 }
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
-            Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+                ServerMessage::Ping { sequence } => {
+                    let pong = ClientMessage::Pong { sequence };
+                    if let Ok(data) = serde_json::to_vec(&pong) {
+                        let _ = client.socket.send(&data);
+                    }
+                }
+                ServerMessage::FullStateSync(snapshot) => {
+                    info!("Received world state");
+                    if let Some(screen_id) = snapshot.stream_screen {
+                        stream_screen.0 = screen_id;
+                        commands.insert_resource(AnnouncedStreamSize {
+                            screen_id,
+                            width: snapshot.video.width,
+                            height: snapshot.video.height,
+                        });
+                    }
+                    if let Some(ref mut decoder) = video_decoder {
+                        decoder.set_codec_info(snapshot.video);
+                    }
+                    screen_layout.screens = snapshot.screens;
+                    door_states.open = snapshot.open_doors.into_iter().collect();
+                    poster_assignments.posters = snapshot.posters;
+                }
+                ServerMessage::SessionClosed => {
+                    info!("Host ended the session");
+                    commands.insert_resource(HostDisconnected::SessionClosed);
+                    break;
+                }
+                ServerMessage::Kicked { reason } => {
+                    info!("Removed from the session: {}", reason);
+                    commands.insert_resource(HostDisconnected::Kicked(reason));
+                    break;
+                }
+                ServerMessage::PlayerLeft { id } => {
+                    info!("Player {} left", id);
+                    if let Some(ref mut remote) = remote_players {
+                        remote.remove(id);
+                    }
+                    player_list.players.retain(|p| p.id != id);
+                }
+                ServerMessage::VideoFrame(chunk) => {
+                    if chunk.chunk_idx == 0 {
+                        trace!(
+                            "Receiving video frame {} in {} chunks",
+                            chunk.frame_id,
+                            chunk.total_chunks
+                        );
+                    }
+                    if stream_screen.0 != chunk.screen_id {
+                        info!("Video stream moved to screen {}", chunk.screen_id);
+                        stream_screen.0 = chunk.screen_id;
+                    }
+                    if let Some(ref mut stats) = stream_stats {
+                        stats.record_packet(len);
+                    }
+                    if let Some(ref mut decoder) = video_decoder {
+                        decoder.add_chunk(chunk);
+                    }
+                }
+                ServerMessage::ScreenLayout { screens } => {
+                    if screen_layout.screens != screens {
+                        screen_layout.screens = screens;
+                    }
+                }
+                ServerMessage::WhiteboardStroke(stroke) => {
+                    whiteboard.incoming.push(stroke);
+                }
+                ServerMessage::WhiteboardClear => {
+                    whiteboard.incoming.clear();
+                    whiteboard.clear_pending = true;
+                }
+                ServerMessage::Doors { open } => {
+                    if door_states.open_list() != open {
+                        door_states.open = open.into_iter().collect();
+                    }
+                }
+                ServerMessage::Posters { posters } => {
+                    if poster_assignments.posters != posters {
+                        poster_assignments.posters = posters;
+                    }
+                }
+                ServerMessage::PosterImage(chunk) => {
+                    poster_cache.insert_chunk(&chunk);
+                }
+                ServerMessage::Emote { player, emote } => {
+                    emote_events.send(EmoteEvent { player, emote });
+                }
+                ServerMessage::VideoCodec(info) => {
+                    if info.width > 0 && info.height > 0 {
+                        commands.insert_resource(AnnouncedStreamSize {
+                            screen_id: stream_screen.0,
+                            width: info.width,
+                            height: info.height,
+                        });
+                    }
+                    if let Some(ref mut decoder) = video_decoder {
+                        decoder.set_codec_info(info);
+                    }
+                }
+                ServerMessage::AudioFrame(chunk) => {
+                    if let Some(ref decoder) = audio_decoder {
+                        decoder.add_chunk(chunk);
+                    }
+                }
+                ServerMessage::PositionCorrection { position } => {
+                    commands.insert_resource(CorrectedPosition(position.into()));
+                }
+            },
+            Received::Error(e) if e.kind() == std::io::ErrorKind::ConnectionReset => {
                 // Host disconnected - this is expected when host closes
                 info!("Host disconnected");
-                commands.insert_resource(HostDisconnected);
+                commands.insert_resource(HostDisconnected::ConnectionLost);
                 break;
             }
-            Err(_) => {
+            Received::Error(_) => {
                 // Other errors - treat as disconnection
-                commands.insert_resource(HostDisconnected);
+                commands.insert_resource(HostDisconnected::ConnectionLost);
                 break;
             }
         }
     }
 }
 
+/// Move the player back to where the host has them after it turned down a
+/// move, e.g. one too fast or out of the room.
+fn apply_position_correction(
+    mut commands: Commands,
+    correction: Option<Res<CorrectedPosition>>,
+    mut player: Query<(&mut Transform, &mut Velocity), With<Player>>,
+) {
+    let Some(correction) = correction else {
+        return;
+    };
+    if let Ok((mut transform, mut velocity)) = player.get_single_mut() {
+        debug!("Host corrected our position to {}", correction.0);
+        transform.translation = correction.0;
+        *velocity = Velocity::default();
+    }
+    commands.remove_resource::<CorrectedPosition>();
+}
+
 fn client_connect_check(
     mut next_state: ResMut<NextState<AppState>>,
     local_id: Option<Res<LocalPlayerId>>,
 ) {
-    // Transition to InGame once we have our player ID
+    // Load the room once we have our player ID
     if local_id.is_some() {
         info!("Connected to server!");
-        next_state.set(AppState::InGame);
+        next_state.set(AppState::Loading);
     }
 }
 
@@ -273,19 +595,39 @@ fn send_player_update(
     time: Res<Time>,
     mut timer: ResMut<ClientSyncTimer>,
     client: Res<GameClient>,
-    player_query: Query<(&Transform, &crate::player::CameraController), With<Player>>,
+    player_query: Query<
+        (
+            &Transform,
+            &crate::player::CameraController,
+            &Velocity,
+            &Grounded,
+            Has<Seated>,
+            Has<Crouching>,
+            Has<Sprinting>,
+        ),
+        With<Player>,
+    >,
+    afk: Res<Afk>,
 ) {
     timer.0.tick(time.delta());
     if !timer.0.just_finished() {
         return;
     }
 
-    if let Ok((transform, camera_controller)) = player_query.get_single() {
+    if let Ok((transform, camera_controller, velocity, grounded, seated, crouching, sprinting)) =
+        player_query.get_single()
+    {
         let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
         let msg = ClientMessage::PlayerUpdate {
             position: transform.translation.into(),
             yaw,
             pitch: camera_controller.pitch,
+            seated,
+            velocity: velocity.0.into(),
+            crouching,
+            sprinting,
+            airborne: !grounded.0 && !seated,
+            afk: afk.afk,
         };
 
         if let Ok(data) = serde_json::to_vec(&msg) {
@@ -294,25 +636,97 @@ fn send_player_update(
     }
 }
 
+/// Send the strokes the local player drew on the whiteboard to the host.
+fn send_whiteboard_strokes(client: Res<GameClient>, mut whiteboard: ResMut<Whiteboard>) {
+    if whiteboard.outgoing.is_empty() {
+        return;
+    }
+
+    for points in whiteboard.outgoing.drain(..) {
+        let msg = ClientMessage::WhiteboardStroke { points };
+        if let Ok(data) = serde_json::to_vec(&msg) {
+            let _ = client.socket.send(&data);
+        }
+    }
+}
+
+/// Ask the host to open or close the doors the local player used.
+fn send_door_toggles(client: Res<GameClient>, mut door_states: ResMut<DoorStates>) {
+    if door_states.toggle_requests.is_empty() {
+        return;
+    }
+
+    for door in door_states.toggle_requests.drain(..) {
+        let msg = ClientMessage::ToggleDoor { door };
+        if let Ok(data) = serde_json::to_vec(&msg) {
+            let _ = client.socket.send(&data);
+        }
+    }
+}
+
+/// Ask the host for the poster image chunks the local client is missing.
+fn send_poster_requests(client: Res<GameClient>, mut poster_cache: ResMut<PosterCache>) {
+    if poster_cache.requests.is_empty() {
+        return;
+    }
+
+    for (hash, chunks) in poster_cache.requests.drain(..) {
+        let msg = ClientMessage::RequestPosterImage { hash, chunks };
+        if let Ok(data) = serde_json::to_vec(&msg) {
+            let _ = client.socket.send(&data);
+        }
+    }
+}
+
+/// Send the local player's emotes to the host.
+fn send_emotes(
+    client: Res<GameClient>,
+    local_id: Res<LocalPlayerId>,
+    mut emote_events: EventReader<EmoteEvent>,
+) {
+    for event in emote_events.read() {
+        if event.player != local_id.0 {
+            continue;
+        }
+        let msg = ClientMessage::Emote { emote: event.emote };
+        if let Ok(data) = serde_json::to_vec(&msg) {
+            let _ = client.socket.send(&data);
+        }
+    }
+}
+
+/// Tell the host when the nickname is changed in the settings mid-session.
+fn send_nickname_changes(client: Res<GameClient>, nickname: Res<Nickname>) {
+    // The first run sees the nickname as changed, but the join already sent it
+    if !nickname.is_changed() || client.is_added() {
+        return;
+    }
+    let msg = ClientMessage::SetNickname {
+        nickname: nickname.0.clone(),
+    };
+    if let Ok(data) = serde_json::to_vec(&msg) {
+        let _ = client.socket.send(&data);
+    }
+}
+
 /// Process decoded video frames
 fn process_video_decoder(
     mut decoder: Option<ResMut<VideoDecoder>>,
     mut jitter: Option<ResMut<VideoJitterBuffer>>,
+    video_settings: Res<VideoSettings>,
+    av_clock: Option<Res<AvSyncClock>>,
+    stream_screen: Res<ActiveStreamScreen>,
     mut screen_frame_events: EventWriter<ReceivedScreenFrame>,
+    mut counters: ResMut<StreamCounters>,
 ) {
-    use std::sync::atomic::{AtomicU32, Ordering};
-    use std::time::Instant;
-    use std::sync::Mutex;
-
-    // FPS tracking for decoded frames
-    static DECODED_FPS_COUNTER: AtomicU32 = AtomicU32::new(0);
-    static DISPLAYED_FPS_COUNTER: AtomicU32 = AtomicU32::new(0);
-    static LAST_FPS_LOG: Mutex<Option<Instant>> = Mutex::new(None);
+    if let Some(ref mut jitter) = jitter {
+        jitter.set_latency(video_settings.stream_latency);
+    }
 
     // Get decoded frames from decoder and add to jitter buffer
     if let Some(ref mut decoder) = decoder {
         while let Some(frame) = decoder.get_decoded() {
-            DECODED_FPS_COUNTER.fetch_add(1, Ordering::Relaxed);
+            counters.decoded_frames += 1;
             if let Some(ref mut jitter) = jitter {
                 jitter.push(frame);
             }
@@ -326,131 +740,161 @@ fn process_video_decoder(
             latest_frame = Some(frame);
         }
         if let Some(frame) = latest_frame {
-            DISPLAYED_FPS_COUNTER.fetch_add(1, Ordering::Relaxed);
+            if let Some(ref clock) = av_clock {
+                clock.record_video_release(frame.pts_ms);
+            }
             screen_frame_events.send(ReceivedScreenFrame {
                 rgba: frame.rgba,
                 width: frame.width,
                 height: frame.height,
+                screen_id: stream_screen.0,
             });
         }
     }
-
-    // Log FPS every second
-    let should_log = {
-        let mut last = LAST_FPS_LOG.lock().unwrap();
-        match *last {
-            None => {
-                *last = Some(Instant::now());
-                false
-            }
-            Some(t) if t.elapsed().as_secs() >= 1 => {
-                *last = Some(Instant::now());
-                true
-            }
-            _ => false,
-        }
-    };
-
-    if should_log {
-        DECODED_FPS_COUNTER.swap(0, Ordering::Relaxed);
-        DISPLAYED_FPS_COUNTER.swap(0, Ordering::Relaxed);
-    }
 }
 
 /// Interpolation speed - higher = faster catch-up, lower = smoother but more latency.
 const INTERPOLATION_SPEED: f32 = 15.0;
 
-/// System to spawn/update remote player visuals (sets network targets).
+/// Longest an airborne player is carried along their jump without hearing
+/// from them, so a missed landing doesn't sink them through the floor.
+const MAX_EXTRAPOLATION: f32 = 0.25;
+
+/// System to spawn, update and despawn remote player entities as their
+/// states change.
 pub fn update_remote_player_visuals(
     mut commands: Commands,
-    remote_players: Option<Res<RemotePlayers>>,
-    mut remote_query: Query<(Entity, &RemotePlayer, &mut NetworkTransform, &mut CharacterAnimationState)>,
-    character_assets: Option<Res<CharacterAssets>>,
+    remote_players: Option<ResMut<RemotePlayers>>,
+    mut entities: ResMut<RemotePlayerEntities>,
+    mut remote_query: Query<
+        (&mut NetworkTransform, &mut CharacterAnimationState),
+        With<RemotePlayer>,
+    >,
 ) {
-    let Some(remote_players) = remote_players else {
+    let Some(mut remote_players) = remote_players else {
         return;
     };
-
-    if !remote_players.is_changed() {
-        return;
+    let RemotePlayers {
+        players,
+        changed,
+        left,
+    } = &mut *remote_players;
+
+    for id in left.drain(..) {
+        if let Some(entity) = entities.0.remove(&id) {
+            commands.entity(entity).despawn_recursive();
+        }
     }
 
-    // Player height constant (eye level above feet)
-    const PLAYER_HEIGHT: f32 = 2.0;
-    // Model pivot offset (adjust if character floats or clips)
-    const MODEL_OFFSET: f32 = -0.15;
-
-    for player_state in &remote_players.players {
+    for id in changed.drain() {
+        let Some(player_state) = players.get(&id) else {
+            continue;
+        };
         // Convert from eye position to character feet position
+        let eye_height = if player_state.seated {
+            SEATED_EYE_HEIGHT
+        } else if player_state.crouching {
+            CROUCH_HEIGHT
+        } else {
+            PLAYER_HEIGHT
+        };
         let target_pos = Vec3::new(
             player_state.position[0],
-            player_state.position[1] - PLAYER_HEIGHT + MODEL_OFFSET,
+            player_state.position[1] - eye_height + MODEL_OFFSET,
             player_state.position[2],
         );
 
         // Add PI to yaw to flip the character to face the correct direction
         let corrected_yaw = player_state.yaw + std::f32::consts::PI;
 
-        if let Some((_, _, mut net_transform, mut anim_state)) = remote_query
-            .iter_mut()
-            .find(|(_, rp, _, _)| rp.id == player_state.id)
-        {
-            // Check if player is moving (for animation state)
-            let distance = net_transform.target_position.distance(target_pos);
-
-            // If significant movement detected, mark as walking and update timestamp
-            if distance > 0.05 {
-                anim_state.is_walking = true;
-                anim_state.last_walk_time = 0.0; // Will be updated by decay system
-            }
+        let existing = entities
+            .0
+            .get(&id)
+            .and_then(|&entity| remote_query.get_mut(entity).ok());
+        if let Some((mut net_transform, mut anim_state)) = existing {
+            // Locomotion animations follow the player's replicated velocity
+            anim_state.velocity = Vec3::from(player_state.velocity);
+            anim_state.yaw = player_state.yaw;
+            anim_state.is_seated = player_state.seated;
+            anim_state.is_crouching = player_state.crouching;
+            anim_state.is_sprinting = player_state.sprinting;
+            anim_state.is_airborne = player_state.airborne;
+            anim_state.is_afk = player_state.afk;
 
             // Update target for existing remote player
             net_transform.target_position = target_pos;
             net_transform.target_yaw = corrected_yaw;
             net_transform.target_pitch = player_state.pitch;
+            net_transform.vertical_velocity = player_state.velocity[1];
+            net_transform.airborne = player_state.airborne;
+            net_transform.extrapolated = 0.0;
         } else {
-            // Spawn new remote player with character model
+            // Spawn new remote player; the character model for their avatar
+            // is attached once it has loaded
             info!("Spawning remote player {}", player_state.id);
-
-            // Add character scene if assets are loaded
-            if let Some(ref assets) = character_assets {
-                commands.spawn((
-                    RemotePlayer { id: player_state.id },
-                    NetworkTransform {
-                        target_position: target_pos,
-                        target_yaw: corrected_yaw,
-                        target_pitch: player_state.pitch,
-                    },
-                    CharacterAnimationState::default(),
-                    Transform::from_translation(target_pos)
-                        .with_rotation(Quat::from_rotation_y(corrected_yaw))
-                        .with_scale(Vec3::splat(1.0)), // Character scale
-                    SceneRoot(assets.scene.clone()),
-                    NeedsAnimationSetup,
-                ));
+            let body_height = if player_state.crouching {
+                CROUCH_HEIGHT
             } else {
-                // Fallback: spawn without character model (will be added later)
-                warn!("Character assets not loaded yet, spawning player without model");
-                commands.spawn((
+                PLAYER_HEIGHT
+            };
+            let entity = commands
+                .spawn((
                     RemotePlayer { id: player_state.id },
+                    CharacterAvatar::new(player_state.avatar),
                     NetworkTransform {
                         target_position: target_pos,
                         target_yaw: corrected_yaw,
                         target_pitch: player_state.pitch,
+                        vertical_velocity: player_state.velocity[1],
+                        airborne: player_state.airborne,
+                        extrapolated: 0.0,
+                    },
+                    CharacterAnimationState {
+                        velocity: Vec3::from(player_state.velocity),
+                        yaw: player_state.yaw,
+                        is_seated: player_state.seated,
+                        is_crouching: player_state.crouching,
+                        is_sprinting: player_state.sprinting,
+                        is_airborne: player_state.airborne,
+                        is_afk: player_state.afk,
+                        ..default()
                     },
-                    CharacterAnimationState::default(),
                     Transform::from_translation(target_pos)
                         .with_rotation(Quat::from_rotation_y(corrected_yaw)),
-                ));
-            }
+                ))
+                .with_child((RemotePlayerBody::bundle(body_height), Sensor))
+                .id();
+            entities.0.insert(id, entity);
         }
     }
+}
+
+/// Despawn every remote player with the session.
+pub fn cleanup_remote_players(mut commands: Commands, mut entities: ResMut<RemotePlayerEntities>) {
+    for (_, entity) in entities.0.drain() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
 
-    // Remove players that left
-    let current_ids: Vec<u64> = remote_players.players.iter().map(|p| p.id).collect();
-    for (entity, rp, _, _) in remote_query.iter() {
-        if !current_ids.contains(&rp.id) {
-            commands.entity(entity).despawn_recursive();
+/// System to resize remote players' capsules as they crouch and stand up.
+pub fn update_remote_player_bodies(
+    mut commands: Commands,
+    character_query: Query<&CharacterAnimationState, With<RemotePlayer>>,
+    body_query: Query<(Entity, &Parent, &RemotePlayerBody)>,
+) {
+    for (entity, parent, body) in body_query.iter() {
+        let Ok(anim_state) = character_query.get(parent.get()) else {
+            continue;
+        };
+        let height = if anim_state.is_crouching {
+            CROUCH_HEIGHT
+        } else {
+            PLAYER_HEIGHT
+        };
+        if body.height != height {
+            commands
+                .entity(entity)
+                .insert(RemotePlayerBody::bundle(height));
         }
     }
 }
@@ -458,12 +902,20 @@ pub fn update_remote_player_visuals(
 /// System to smoothly interpolate remote players towards their target transforms.
 pub fn interpolate_remote_players(
     time: Res<Time>,
-    mut query: Query<(&mut Transform, &NetworkTransform), With<RemotePlayer>>,
+    mut query: Query<(&mut Transform, &mut NetworkTransform), With<RemotePlayer>>,
 ) {
     let dt = time.delta_secs();
     let t = (INTERPOLATION_SPEED * dt).min(1.0);
 
-    for (mut transform, net_transform) in query.iter_mut() {
+    for (mut transform, mut net_transform) in query.iter_mut() {
+        // Updates come a few times a second, so jumps follow the replicated
+        // vertical speed and gravity in between instead of stepping
+        if net_transform.airborne && net_transform.extrapolated < MAX_EXTRAPOLATION {
+            net_transform.target_position.y += net_transform.vertical_velocity * dt;
+            net_transform.vertical_velocity -= GRAVITY * dt;
+            net_transform.extrapolated += dt;
+        }
+
         // Lerp position
         transform.translation = transform
             .translation
@@ -0,0 +1,99 @@
+//! Simulated network conditions for development. With `--simulate-network`
+//! the receive threads delay, drop and reorder datagrams as they arrive, on
+//! both the host and clients, so interpolation, the jitter buffers and loss
+//! recovery can be tried without a bad network to try them on.
+
+use bevy::prelude::*;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How much longer than the rest a reordered datagram is held, so the ones
+/// after it overtake it.
+const REORDER_DELAY: Duration = Duration::from_millis(30);
+
+/// Longest latency or jitter that can be asked for.
+pub const MAX_SIMULATED_DELAY: Duration = Duration::from_secs(10);
+
+/// Resource with the conditions to simulate, present only when asked for.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct NetworkConditions {
+    /// Delay added to every datagram.
+    pub latency: Duration,
+    /// Most extra delay added at random on top of the latency.
+    pub jitter: Duration,
+    /// Chance of a datagram being lost, 0 to 1.
+    pub loss: f32,
+    /// Chance of a datagram arriving after ones sent after it, 0 to 1.
+    pub reorder: f32,
+}
+
+/// Datagrams held back until their delay is up.
+pub struct Conditioner<T> {
+    conditions: NetworkConditions,
+    held: Vec<(Instant, T)>,
+    /// When the last datagram kept in order is due, so jitter alone doesn't
+    /// reorder them.
+    last_due: Instant,
+    rng: u64,
+}
+
+impl<T> Conditioner<T> {
+    pub fn new(conditions: NetworkConditions) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Self {
+            conditions,
+            held: Vec::new(),
+            last_due: Instant::now(),
+            rng: seed | 1,
+        }
+    }
+
+    /// Take in a datagram, to come out once its delay is up, or never if
+    /// it's lost.
+    pub fn push(&mut self, datagram: T) {
+        if self.chance(self.conditions.loss) {
+            return;
+        }
+        let jitter = self.conditions.jitter.mul_f32(self.random());
+        // A delay past what `Instant` can hold is let through at once
+        let now = Instant::now();
+        let mut due = self
+            .conditions
+            .latency
+            .checked_add(jitter)
+            .and_then(|delay| now.checked_add(delay))
+            .unwrap_or(now);
+        if self.chance(self.conditions.reorder) {
+            due = due.checked_add(REORDER_DELAY).unwrap_or(due);
+        } else {
+            due = due.max(self.last_due);
+            self.last_due = due;
+        }
+        self.held.push((due, datagram));
+    }
+
+    /// The next datagram whose delay is up, if any.
+    pub fn pop_due(&mut self) -> Option<T> {
+        let now = Instant::now();
+        let (index, _) = self
+            .held
+            .iter()
+            .enumerate()
+            .filter(|(_, (due, _))| *due <= now)
+            .min_by_key(|(_, (due, _))| *due)?;
+        Some(self.held.remove(index).1)
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        probability > 0.0 && self.random() < probability
+    }
+
+    /// A random number from 0 to 1, by xorshift.
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
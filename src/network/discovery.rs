@@ -1,26 +1,53 @@
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::net::{SocketAddr, UdpSocket};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
 use std::time::Duration;
 
+use super::server::GameServer;
+use crate::world::{CurrentRoom, RoomId};
+
 /// Port used for LAN discovery broadcasts.
 pub const DISCOVERY_PORT: u16 = 7777;
 
 /// Port used for game connections.
 pub const GAME_PORT: u16 = 5000;
 
+/// Ports from `GAME_PORT` on a host tries in turn when one is taken, e.g.
+/// by another game open on the same machine.
+pub const GAME_PORT_ATTEMPTS: u16 = 10;
+
 /// Information about a LAN session.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LanSession {
     pub name: String,
     pub address: SocketAddr,
     pub player_count: u32,
+    pub room: RoomId,
+    /// Name of the game instance hosting it, if it was given one.
+    pub instance: Option<String>,
 }
 
 /// Resource holding the currently selected session to connect to.
 #[derive(Resource, Clone)]
 pub struct SelectedSession(pub LanSession);
 
+/// Name the host announces its session under.
+#[derive(Resource, Clone)]
+pub struct SessionName(pub String);
+
+impl Default for SessionName {
+    fn default() -> Self {
+        Self("Local Game".to_string())
+    }
+}
+
+/// Name given to this copy of the game with `--instance`, to tell copies
+/// running on one machine apart.
+#[derive(Resource, Clone, Default)]
+pub struct InstanceName(pub Option<String>);
+
 /// Resource holding all discovered sessions.
 #[derive(Resource, Default)]
 pub struct DiscoveredSessions(pub Vec<LanSession>);
@@ -43,6 +70,19 @@ struct SessionAnnouncement {
     name: String,
     port: u16,
     player_count: u32,
+    #[serde(default)]
+    room: RoomId,
+    #[serde(default)]
+    instance: Option<String>,
+}
+
+/// This machine's address on the LAN, for telling other players where to
+/// connect. Connecting a UDP socket only picks the outgoing interface, so no
+/// packet is sent.
+pub fn local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
 }
 
 pub fn setup_broadcast(mut commands: Commands) {
@@ -83,8 +123,13 @@ pub fn broadcast_session(
     time: Res<Time>,
     timer: Option<ResMut<BroadcastTimer>>,
     socket: Option<Res<BroadcastSocket>>,
+    server: Option<Res<GameServer>>,
+    room: Res<CurrentRoom>,
+    session_name: Res<SessionName>,
+    instance: Res<InstanceName>,
 ) {
-    let (Some(socket), Some(mut timer)) = (socket, timer) else {
+    // Nothing to announce until the server has a port
+    let (Some(socket), Some(mut timer), Some(server)) = (socket, timer, server) else {
         return;
     };
 
@@ -94,9 +139,11 @@ pub fn broadcast_session(
     }
 
     let announcement = SessionAnnouncement {
-        name: "Local Game".to_string(),
-        port: GAME_PORT,
+        name: session_name.0.clone(),
+        port: server.port,
         player_count: 1,
+        room: room.0.clone(),
+        instance: instance.0.clone(),
     };
 
     let data = match serde_json::to_vec(&announcement) {
@@ -119,7 +166,7 @@ pub fn broadcast_session(
 
 pub fn setup_listener(mut commands: Commands) {
     // Create listener socket
-    let socket = match UdpSocket::bind(format!("0.0.0.0:{}", DISCOVERY_PORT)) {
+    let socket = match bind_shared(DISCOVERY_PORT) {
         Ok(s) => s,
         Err(e) => {
             error!("Failed to create listener socket: {}", e);
@@ -138,6 +185,18 @@ pub fn setup_listener(mut commands: Commands) {
     info!("Listening for LAN sessions");
 }
 
+/// Bind a UDP socket other instances on this machine may bind too, each
+/// getting every broadcast sent to the port.
+fn bind_shared(port: u16) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    // Needed on macOS and the BSDs for both to hear broadcasts
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port).into())?;
+    Ok(socket.into())
+}
+
 pub fn cleanup_listener(mut commands: Commands) {
     commands.remove_resource::<ListenerSocket>();
 }
@@ -160,16 +219,18 @@ pub fn listen_for_sessions(
                         name: announcement.name,
                         address: SocketAddr::new(src_addr.ip(), announcement.port),
                         player_count: announcement.player_count,
+                        room: announcement.room,
+                        instance: announcement.instance,
                     };
 
                     // Update or add session
-                    if let Some(existing) = sessions
-                        .0
-                        .iter_mut()
-                        .find(|s| s.address.ip() == session.address.ip())
+                    if let Some(existing) =
+                        sessions.0.iter_mut().find(|s| s.address == session.address)
                     {
                         existing.name = session.name;
                         existing.player_count = session.player_count;
+                        existing.room = session.room;
+                        existing.instance = session.instance;
                     } else {
                         info!("Discovered session: {} at {}", session.name, session.address);
                         sessions.0.push(session);
@@ -0,0 +1,70 @@
+//! Invite codes: a host's LAN address as ten letters and digits, e.g.
+//! `3F8KQ-20MZA`, easier to read out over the phone than an IP and port.
+//! The address's six bytes and a two-bit check fill the code's 50 bits.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// Crockford's base 32, which leaves out I, L, O and U so codes can't be
+/// misread.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Symbols in a code, not counting the dash.
+const CODE_LEN: usize = 10;
+
+/// The invite code for an address. Only IPv4 addresses fit in one.
+pub fn encode_invite(address: SocketAddr) -> Option<String> {
+    let IpAddr::V4(ip) = address.ip() else {
+        return None;
+    };
+    let mut bytes = [0u8; 6];
+    bytes[..4].copy_from_slice(&ip.octets());
+    bytes[4..].copy_from_slice(&address.port().to_be_bytes());
+
+    let value = bytes
+        .iter()
+        .fold(0u64, |value, &byte| value << 8 | byte as u64);
+    let value = value << 2 | checksum(value);
+
+    let mut code = String::with_capacity(CODE_LEN + 1);
+    for i in (0..CODE_LEN).rev() {
+        code.push(ALPHABET[(value >> (i * 5)) as usize & 31] as char);
+        if i == CODE_LEN / 2 {
+            code.push('-');
+        }
+    }
+    Some(code)
+}
+
+/// The address an invite code stands for. Case, dashes and spaces don't
+/// matter, and letters easily taken for digits are read as them.
+pub fn decode_invite(code: &str) -> Option<SocketAddr> {
+    let mut value = 0u64;
+    let mut len = 0;
+    for c in code.chars().filter(|c| !matches!(c, '-' | ' ')) {
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        };
+        let digit = ALPHABET.iter().position(|&symbol| symbol as char == c)?;
+        value = value << 5 | digit as u64;
+        len += 1;
+    }
+    if len != CODE_LEN {
+        return None;
+    }
+
+    if value & 3 != checksum(value >> 2) {
+        return None;
+    }
+    let bytes: [u8; 6] = std::array::from_fn(|i| (value >> (2 + (5 - i) * 8)) as u8);
+    let ip = Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+    let port = u16::from_be_bytes([bytes[4], bytes[5]]);
+    Some(SocketAddr::new(IpAddr::V4(ip), port))
+}
+
+/// Two bits to catch mistyped codes: the address modulo 3, which about two
+/// thirds of wrong symbols change.
+fn checksum(address: u64) -> u64 {
+    address % 3
+}
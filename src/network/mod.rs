@@ -1,16 +1,35 @@
+//! Multiplayer over plain UDP. Every datagram passes through a
+//! `SocketReceiver` on the way in, where simulated conditions, recordings and
+//! bans apply, and the stream goes out from its own threads. A transport
+//! library would sit between both and the socket, so the protocol recovers
+//! from loss itself: state goes out in full every so often, and clients ask
+//! again for poster chunks they miss.
+
 pub mod client;
+pub mod conditioner;
 pub mod discovery;
+pub mod invite;
 pub mod protocol;
+pub mod receiver;
+pub mod replay;
 pub mod server;
+#[cfg(test)]
+pub mod test_support;
+#[cfg(feature = "web-spectators")]
+pub mod web_spectators;
 
 use bevy::prelude::*;
 
 pub use client::ReceivedScreenFrame;
-pub use discovery::{DiscoveredSessions, LanSession, SelectedSession};
-pub use protocol::{LocalPlayerId, RemotePlayer, RemotePlayers};
+pub use discovery::{DiscoveredSessions, InstanceName, SelectedSession, SessionName};
+pub use protocol::{LocalPlayerId, PlayerList, RemotePlayerEntities, RemotePlayers};
 
+use crate::console::AddConsoleCommand;
 use crate::game_state::AppState;
-use client::{interpolate_remote_players, update_remote_player_visuals};
+use client::{
+    cleanup_remote_players, interpolate_remote_players, update_remote_player_bodies,
+    update_remote_player_visuals,
+};
 use discovery::{
     broadcast_session, cleanup_broadcast, cleanup_listener, listen_for_sessions, setup_broadcast,
     setup_listener,
@@ -21,7 +40,13 @@ pub struct NetworkPlugin;
 impl Plugin for NetworkPlugin {
     fn build(&self, app: &mut App) {
         // Initialize discovery resources
-        app.init_resource::<DiscoveredSessions>();
+        app.init_resource::<DiscoveredSessions>()
+            .init_resource::<SessionName>()
+            .init_resource::<InstanceName>();
+
+        // Everyone in the session, shown in the player list
+        app.init_resource::<PlayerList>();
+        app.init_resource::<RemotePlayerEntities>();
 
         // Register screen frame event
         app.add_event::<ReceivedScreenFrame>();
@@ -32,12 +57,21 @@ impl Plugin for NetworkPlugin {
         // Client plugin
         client::client_plugin(app);
 
+        // Browser spectators while hosting
+        #[cfg(feature = "web-spectators")]
+        web_spectators::web_spectators_plugin(app);
+
         // Remote player visuals (for both host and client)
         app.add_systems(
             Update,
-            (update_remote_player_visuals, interpolate_remote_players)
+            (
+                update_remote_player_visuals,
+                update_remote_player_bodies,
+                interpolate_remote_players,
+            )
                 .run_if(in_state(AppState::InGame)),
         );
+        app.add_systems(OnExit(AppState::InGame), cleanup_remote_players);
 
         // Host also needs RemotePlayers to see clients
         app.add_systems(OnEnter(AppState::Hosting), setup_host_remote_players);
@@ -50,6 +84,8 @@ impl Plugin for NetworkPlugin {
         // Discovery - host broadcasts
         app.add_systems(OnEnter(AppState::Hosting), setup_broadcast)
             .add_systems(OnExit(AppState::InGame), cleanup_broadcast)
+            // Hosting can fail before the game starts
+            .add_systems(OnEnter(AppState::MainMenu), cleanup_broadcast)
             .add_systems(
                 Update,
                 broadcast_session
@@ -63,11 +99,44 @@ impl Plugin for NetworkPlugin {
                 Update,
                 listen_for_sessions.run_if(in_state(AppState::Browsing)),
             );
+
+        app.add_console_command("net stats", "", net_stats_command);
     }
 }
 
+/// Console command listing everyone in the session with their ping, and on
+/// the host the client datagrams it dropped.
+fn net_stats_command(
+    In(_): In<Vec<String>>,
+    players: Res<PlayerList>,
+    server: Option<Res<server::GameServer>>,
+    client: Option<Res<client::GameClient>>,
+) -> String {
+    if server.is_none() && client.is_none() {
+        return "Not in a session".to_string();
+    }
+    let mut lines = vec![format!("{} players", players.players.len())];
+    for player in &players.players {
+        let ping = match (player.host, player.ping_ms) {
+            (true, _) => "host".to_string(),
+            (false, Some(ping)) => format!("{} ms", ping),
+            (false, None) => "no ping yet".to_string(),
+        };
+        let name = player.display_name();
+        lines.push(format!("  {} {}: {}", player.id, name, ping));
+    }
+    if let Some(server) = server {
+        let drops = server.packet_drops;
+        lines.push(format!(
+            "Dropped over rate limit: {}, frames at datagram cap: {}",
+            drops.rate_limited, drops.capped_frames
+        ));
+    }
+    lines.join("\n")
+}
+
 fn setup_host_remote_players(mut commands: Commands) {
-    commands.init_resource::<RemotePlayers>();
+    commands.insert_resource(RemotePlayers::default());
 }
 
 fn sync_host_remote_players(
@@ -76,10 +145,10 @@ fn sync_host_remote_players(
     mut remote_players: ResMut<RemotePlayers>,
 ) {
     // Host sees all players except themselves
-    remote_players.players = server
-        .player_states
-        .values()
-        .filter(|p| p.id != local_id.0)
-        .cloned()
-        .collect();
+    for state in server.player_states.values() {
+        if state.id != local_id.0 {
+            remote_players.upsert(state);
+        }
+    }
+    remote_players.retain(|id| server.player_states.contains_key(&id));
 }
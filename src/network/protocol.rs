@@ -1,17 +1,86 @@
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use bevy::prelude::*;
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::world::RoomId;
 
 /// Unique identifier for a player in the session.
 pub type PlayerId = u64;
 
+/// Identifies a screen within the room. Rooms number their screens from 0.
+pub type ScreenId = u8;
+
+/// Identifies a door within the room.
+pub type DoorId = u8;
+
+/// Longest nickname players can pick, in characters.
+pub const MAX_NICKNAME_CHARS: usize = 24;
+
+/// Furthest from the origin a replicated position may be, and the fastest a
+/// replicated velocity may be. Anything past them is garbage.
+const MAX_COORDINATE: f32 = 10_000.0;
+const MAX_SPEED: f32 = 1_000.0;
+
+/// Most codecs a client may list when joining. There aren't nearly as many.
+const MAX_CODECS: usize = 16;
+
+/// Identifies a poster frame within the room.
+pub type PosterId = u8;
+
 /// Messages sent from client to server.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ClientMessage {
     /// Client sending their current position and rotation.
-    PlayerUpdate { position: [f32; 3], yaw: f32, pitch: f32 },
-    /// Client requesting to join.
-    Join,
+    PlayerUpdate {
+        position: [f32; 3],
+        yaw: f32,
+        pitch: f32,
+        #[serde(default)]
+        seated: bool,
+        #[serde(default)]
+        velocity: [f32; 3],
+        #[serde(default)]
+        crouching: bool,
+        #[serde(default)]
+        sprinting: bool,
+        #[serde(default)]
+        airborne: bool,
+        #[serde(default)]
+        afk: bool,
+    },
+    /// Client requesting to join, advertising the video and audio codecs it can decode.
+    Join {
+        supported_codecs: Vec<VideoCodecKind>,
+        #[serde(default)]
+        supported_audio_codecs: Vec<AudioCodecKind>,
+        #[serde(default)]
+        avatar: AvatarChoice,
+        #[serde(default)]
+        nickname: String,
+        /// Token from the `Welcome` of a session the client dropped out of,
+        /// to get its place back.
+        #[serde(default)]
+        rejoin_token: Option<u64>,
+    },
+    /// Client drew part of a stroke on the whiteboard.
+    WhiteboardStroke { points: Vec<[u16; 2]> },
+    /// Client opened or closed a door.
+    ToggleDoor { door: DoorId },
+    /// Client asking for chunks of a poster image it doesn't have, all of
+    /// them if `chunks` is empty.
+    RequestPosterImage {
+        hash: u64,
+        #[serde(default)]
+        chunks: Vec<u16>,
+    },
+    /// Client played an emote.
+    Emote { emote: Emote },
+    /// Client changed their nickname.
+    SetNickname { nickname: String },
+    /// Answer to the server's ping, echoing its sequence number.
+    Pong { sequence: u32 },
     /// Client leaving gracefully.
     Leave,
 }
@@ -19,22 +88,119 @@ pub enum ClientMessage {
 /// Messages sent from server to clients.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ServerMessage {
-    /// Welcome message with assigned player ID.
-    Welcome { your_id: PlayerId },
-    /// Update containing all player states.
-    GameState { players: Vec<PlayerState> },
+    /// Welcome message with assigned player ID, the room to build and where
+    /// in it to start.
+    Welcome {
+        your_id: PlayerId,
+        #[serde(default)]
+        room: RoomId,
+        #[serde(default)]
+        spawn: Option<SpawnTransform>,
+        /// Secret the client sends with its `Join` if it comes back after
+        /// dropping out, to be given its place back.
+        #[serde(default)]
+        rejoin_token: Option<u64>,
+    },
+    /// Players that changed since the client last heard of them, nearby
+    /// ones more often than distant ones. Players the client has no copy of
+    /// come in full, the rest as the fields that changed. Every so often
+    /// everyone comes in full, so lost updates are recovered. `info` is what
+    /// the player list shows about each player, sent when it changes;
+    /// players missing from it have left.
+    GameState {
+        players: Vec<PlayerState>,
+        #[serde(default)]
+        deltas: Vec<PlayerStateDelta>,
+        #[serde(default)]
+        info: Option<Vec<PlayerInfo>>,
+    },
     /// A player has disconnected.
     PlayerLeft { id: PlayerId },
-    /// H.264 video frame chunk for streaming.
+    /// Video frame chunk for streaming. Sent as a binary packet rather
+    /// than JSON, see `VideoChunk::encode_into`.
+    #[serde(skip)]
     VideoFrame(VideoChunk),
     /// Video codec information for client initialization.
     VideoCodec(VideoCodecInfo),
-    /// Opus audio chunk for streaming.
+    /// Audio chunk for streaming.
     AudioFrame(AudioChunk),
+    /// Placement of every screen the host has moved.
+    ScreenLayout { screens: Vec<ScreenPlacement> },
+    /// Part of a stroke someone drew on the whiteboard.
+    WhiteboardStroke(WhiteboardStroke),
+    /// The host wiped the whiteboard.
+    WhiteboardClear,
+    /// Doors that are currently open.
+    Doors { open: Vec<DoorId> },
+    /// Image hanging in each poster frame that has one.
+    Posters { posters: Vec<PosterAssignment> },
+    /// Chunk of a poster image file.
+    PosterImage(PosterImageChunk),
+    /// A player played an emote.
+    Emote { player: PlayerId, emote: Emote },
+    /// Round trip probe, answered with a `Pong` carrying the same sequence.
+    Ping { sequence: u32 },
+    /// The host ended the session.
+    SessionClosed,
+    /// The host removed this client from the session, and why.
+    Kicked { reason: String },
+    /// Shared world state, sent to a new client right after `Welcome` so
+    /// the room looks right from the start.
+    FullStateSync(WorldSnapshot),
+    /// Where the host has the player after clamping or refusing a move it
+    /// claimed. The client goes back there.
+    PositionCorrection { position: [f32; 3] },
+}
+
+/// Where a joining player starts: the player's eye-level position, and the
+/// way it faces.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct SpawnTransform {
+    pub position: [f32; 3],
+    pub yaw: f32,
 }
 
-/// H.264 video chunk for streaming.
+/// Shared world state a client needs on joining. Whiteboard strokes follow
+/// as their own messages, since they can outgrow a packet.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorldSnapshot {
+    /// Codec the stream uses, sized to the stream while the host shares.
+    pub video: VideoCodecInfo,
+    /// Screen the host is sharing to, if sharing.
+    pub stream_screen: Option<ScreenId>,
+    /// Placement of every screen the host has moved.
+    pub screens: Vec<ScreenPlacement>,
+    /// Doors that are currently open.
+    pub open_doors: Vec<DoorId>,
+    /// Image hanging in each poster frame that has one.
+    pub posters: Vec<PosterAssignment>,
+}
+
+/// Largest datagram we send. The smallest path MTU IPv6 allows is 1280
+/// bytes, 48 of them IP and UDP headers, and this leaves room for tunnels
+/// and VPNs besides, so packets aren't fragmented on any path. A fragment
+/// lost on Wi-Fi loses the whole packet.
+pub const MAX_PACKET_SIZE: usize = 1200;
+
+/// Room for everything but the data in a JSON message carrying base64 data.
+const JSON_MESSAGE_OVERHEAD: usize = 200;
+
+/// Largest data a JSON message can carry as base64 and still fit a packet.
+pub const BASE64_CHUNK_SIZE: usize = (MAX_PACKET_SIZE - JSON_MESSAGE_OVERHEAD) / 4 * 3;
+
+/// First byte of a binary video packet. JSON messages start with `{` or `"`.
+const VIDEO_PACKET_TAG: u8 = 0x01;
+
+/// Size of a video packet's header: tag, frame id, chunk index, total
+/// chunks, keyframe flag, timestamp and screen.
+const VIDEO_PACKET_HEADER_SIZE: usize = 19;
+
+/// Largest video data in one packet, after its header.
+pub const VIDEO_CHUNK_SIZE: usize = MAX_PACKET_SIZE - VIDEO_PACKET_HEADER_SIZE;
+
+/// Video chunk for streaming. The payload is a slice of the encoded access
+/// unit, shared rather than copied on the way to and from the socket.
+#[derive(Debug, Clone)]
 pub struct VideoChunk {
     /// Frame sequence number.
     pub frame_id: u32,
@@ -44,35 +210,165 @@ pub struct VideoChunk {
     pub total_chunks: u16,
     /// Whether this is a keyframe (I-frame).
     pub is_keyframe: bool,
-    /// H.264 NAL unit data (base64 encoded).
-    data_b64: String,
+    /// Capture time on the host stream clock, in milliseconds.
+    pub pts_ms: u64,
+    /// Screen in the room this stream is shown on.
+    pub screen_id: ScreenId,
+    /// Encoded video data.
+    pub data: Bytes,
 }
 
 impl VideoChunk {
-    pub fn new(frame_id: u32, chunk_idx: u16, total_chunks: u16, is_keyframe: bool, data: Vec<u8>) -> Self {
+    pub fn new(
+        frame_id: u32,
+        chunk_idx: u16,
+        total_chunks: u16,
+        is_keyframe: bool,
+        pts_ms: u64,
+        data: Bytes,
+    ) -> Self {
         Self {
             frame_id,
             chunk_idx,
             total_chunks,
             is_keyframe,
-            data_b64: BASE64.encode(&data),
+            pts_ms,
+            screen_id: 0,
+            data,
         }
     }
 
-    pub fn decode_data(&self) -> Option<Vec<u8>> {
-        BASE64.decode(&self.data_b64).ok()
+    /// Write the chunk as a binary packet, replacing what `packet` held so
+    /// its allocation can be reused for every chunk.
+    pub fn encode_into(&self, packet: &mut Vec<u8>) {
+        packet.clear();
+        packet.reserve(VIDEO_PACKET_HEADER_SIZE + self.data.len());
+        packet.push(VIDEO_PACKET_TAG);
+        packet.extend_from_slice(&self.frame_id.to_le_bytes());
+        packet.extend_from_slice(&self.chunk_idx.to_le_bytes());
+        packet.extend_from_slice(&self.total_chunks.to_le_bytes());
+        packet.push(self.is_keyframe as u8);
+        packet.extend_from_slice(&self.pts_ms.to_le_bytes());
+        packet.push(self.screen_id);
+        packet.extend_from_slice(&self.data);
+    }
+
+    /// Read a binary packet. The payload stays a view into `packet`.
+    fn decode(packet: Bytes) -> Option<Self> {
+        let header = packet.get(..VIDEO_PACKET_HEADER_SIZE)?;
+        if header[0] != VIDEO_PACKET_TAG {
+            return None;
+        }
+        let chunk_idx = u16::from_le_bytes(header[5..7].try_into().ok()?);
+        let total_chunks = u16::from_le_bytes(header[7..9].try_into().ok()?);
+        if chunk_idx >= total_chunks {
+            return None;
+        }
+        Some(Self {
+            frame_id: u32::from_le_bytes(header[1..5].try_into().ok()?),
+            chunk_idx,
+            total_chunks,
+            is_keyframe: header[9] != 0,
+            pts_ms: u64::from_le_bytes(header[10..18].try_into().ok()?),
+            screen_id: header[18],
+            data: packet.slice(VIDEO_PACKET_HEADER_SIZE..),
+        })
+    }
+}
+
+/// Messages that can be read from a received datagram.
+pub trait Packet: Sized {
+    fn parse(packet: Bytes) -> Option<Self>;
+}
+
+impl Packet for ClientMessage {
+    fn parse(packet: Bytes) -> Option<Self> {
+        serde_json::from_slice(&packet)
+            .ok()
+            .filter(ClientMessage::is_valid)
+    }
+}
+
+impl ClientMessage {
+    /// Whether the message's values are in range. Clients can send anything,
+    /// and values that aren't would break physics, animation or the whiteboard.
+    pub fn is_valid(&self) -> bool {
+        let in_range =
+            |values: &[f32], max: f32| values.iter().all(|v| v.is_finite() && v.abs() <= max);
+        match self {
+            ClientMessage::PlayerUpdate {
+                position,
+                yaw,
+                pitch,
+                velocity,
+                ..
+            } => {
+                in_range(position, MAX_COORDINATE)
+                    && in_range(velocity, MAX_SPEED)
+                    && yaw.is_finite()
+                    && in_range(&[*pitch], std::f32::consts::PI)
+            }
+            ClientMessage::Join {
+                supported_codecs,
+                supported_audio_codecs,
+                ..
+            } => supported_codecs.len() <= MAX_CODECS && supported_audio_codecs.len() <= MAX_CODECS,
+            ClientMessage::WhiteboardStroke { points } => !points.is_empty(),
+            _ => true,
+        }
     }
+}
 
-    // For internal use - direct data access
-    pub fn data(&self) -> Vec<u8> {
-        self.decode_data().unwrap_or_default()
+impl Packet for ServerMessage {
+    fn parse(packet: Bytes) -> Option<Self> {
+        if packet.first() == Some(&VIDEO_PACKET_TAG) {
+            return VideoChunk::decode(packet).map(ServerMessage::VideoFrame);
+        }
+        serde_json::from_slice(&packet).ok()
+    }
+}
+
+/// Video codecs that can be negotiated between host and clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum VideoCodecKind {
+    #[default]
+    H264,
+    Hevc,
+    Av1,
+}
+
+impl VideoCodecKind {
+    /// All codecs, best compression first.
+    pub const PREFERENCE: [VideoCodecKind; 3] =
+        [VideoCodecKind::Av1, VideoCodecKind::Hevc, VideoCodecKind::H264];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoCodecKind::H264 => "H.264",
+            VideoCodecKind::Hevc => "HEVC",
+            VideoCodecKind::Av1 => "AV1",
+        }
+    }
+
+    /// Pick the best codec the host can encode and every client can decode.
+    /// H.264 is the universal fallback.
+    pub fn negotiate<'a>(
+        host: &[VideoCodecKind],
+        clients: impl Iterator<Item = &'a [VideoCodecKind]> + Clone,
+    ) -> VideoCodecKind {
+        Self::PREFERENCE
+            .into_iter()
+            .find(|codec| {
+                host.contains(codec) && clients.clone().all(|supported| supported.contains(codec))
+            })
+            .unwrap_or_default()
     }
 }
 
 /// Video codec information sent to clients.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VideoCodecInfo {
-    pub codec: String,
+    pub codec: VideoCodecKind,
     pub width: u32,
     pub height: u32,
     pub fps: u32,
@@ -80,7 +376,48 @@ pub struct VideoCodecInfo {
     pub extradata: Vec<u8>,
 }
 
-/// Opus audio chunk for streaming.
+/// Audio codecs that can be negotiated between host and clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AudioCodecKind {
+    /// Raw little-endian i16 PCM, always supported.
+    #[default]
+    Pcm,
+    /// Opus in 20 ms frames, available when built with the `opus` feature.
+    Opus,
+}
+
+impl AudioCodecKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioCodecKind::Pcm => "PCM",
+            AudioCodecKind::Opus => "Opus",
+        }
+    }
+
+    /// Codecs this build can encode and decode.
+    pub fn supported() -> Vec<AudioCodecKind> {
+        let mut codecs = vec![AudioCodecKind::Pcm];
+        if cfg!(feature = "opus") {
+            codecs.push(AudioCodecKind::Opus);
+        }
+        codecs
+    }
+
+    /// Use Opus when the host and every client support it, PCM otherwise.
+    pub fn negotiate<'a>(
+        host: &[AudioCodecKind],
+        mut clients: impl Iterator<Item = &'a [AudioCodecKind]>,
+    ) -> AudioCodecKind {
+        let opus = AudioCodecKind::Opus;
+        if host.contains(&opus) && clients.all(|supported| supported.contains(&opus)) {
+            opus
+        } else {
+            AudioCodecKind::Pcm
+        }
+    }
+}
+
+/// Audio chunk for streaming.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AudioChunk {
     /// Sequence number for ordering and loss detection.
@@ -89,16 +426,31 @@ pub struct AudioChunk {
     pub sample_rate: u32,
     /// Number of channels (1 = mono, 2 = stereo).
     pub channels: u8,
-    /// Opus-encoded audio data (base64 encoded).
+    /// Codec of the payload. Older hosts only sent PCM.
+    #[serde(default)]
+    pub codec: AudioCodecKind,
+    /// Capture time of the first sample on the host stream clock, in milliseconds.
+    #[serde(default)]
+    pub pts_ms: u64,
+    /// Encoded audio data (base64 encoded).
     data_b64: String,
 }
 
 impl AudioChunk {
-    pub fn new(sequence: u32, sample_rate: u32, channels: u8, data: Vec<u8>) -> Self {
+    pub fn new(
+        sequence: u32,
+        sample_rate: u32,
+        channels: u8,
+        codec: AudioCodecKind,
+        pts_ms: u64,
+        data: Vec<u8>,
+    ) -> Self {
         Self {
             sequence,
             sample_rate,
             channels,
+            codec,
+            pts_ms,
             data_b64: BASE64.encode(&data),
         }
     }
@@ -106,25 +458,287 @@ impl AudioChunk {
     pub fn decode_data(&self) -> Option<Vec<u8>> {
         BASE64.decode(&self.data_b64).ok()
     }
-
-    pub fn data(&self) -> Vec<u8> {
-        self.decode_data().unwrap_or_default()
-    }
 }
 
 /// State of a single player, broadcast by the server.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PlayerState {
     pub id: PlayerId,
     pub position: [f32; 3],
     pub yaw: f32,
     pub pitch: f32,
+    /// Whether the player is sitting in a seat.
+    #[serde(default)]
+    pub seated: bool,
+    /// Movement velocity, which drives the character's locomotion animations.
+    #[serde(default)]
+    pub velocity: [f32; 3],
+    /// Whether the player is crouching, which shortens their body.
+    #[serde(default)]
+    pub crouching: bool,
+    /// Whether the player is sprinting.
+    #[serde(default)]
+    pub sprinting: bool,
+    /// Whether the player is off the ground, jumping or falling.
+    #[serde(default)]
+    pub airborne: bool,
+    /// Whether the player has given no input for a while.
+    #[serde(default)]
+    pub afk: bool,
+    /// Character model and tint the player picked.
+    #[serde(default)]
+    pub avatar: AvatarChoice,
+    /// Name the player picked, empty if they haven't.
+    #[serde(default)]
+    pub nickname: String,
+    /// Color the host gave the player on joining, see `PlayerIdentity`.
+    #[serde(default)]
+    pub color: u8,
+}
+
+/// Fields of a player's state that changed since the client's copy of it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerStateDelta {
+    pub id: PlayerId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yaw: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity: Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crouching: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprinting: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub airborne: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub afk: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<AvatarChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<u8>,
+}
+
+impl PlayerStateDelta {
+    /// What changed from `old` to `new`.
+    pub fn between(old: &PlayerState, new: &PlayerState) -> Self {
+        fn changed<T: PartialEq + Clone>(old: &T, new: &T) -> Option<T> {
+            (old != new).then(|| new.clone())
+        }
+        Self {
+            id: new.id,
+            position: changed(&old.position, &new.position),
+            yaw: changed(&old.yaw, &new.yaw),
+            pitch: changed(&old.pitch, &new.pitch),
+            seated: changed(&old.seated, &new.seated),
+            velocity: changed(&old.velocity, &new.velocity),
+            crouching: changed(&old.crouching, &new.crouching),
+            sprinting: changed(&old.sprinting, &new.sprinting),
+            airborne: changed(&old.airborne, &new.airborne),
+            afk: changed(&old.afk, &new.afk),
+            avatar: changed(&old.avatar, &new.avatar),
+            nickname: changed(&old.nickname, &new.nickname),
+            color: changed(&old.color, &new.color),
+        }
+    }
+
+    pub fn apply(&self, state: &mut PlayerState) {
+        if let Some(position) = self.position {
+            state.position = position;
+        }
+        if let Some(yaw) = self.yaw {
+            state.yaw = yaw;
+        }
+        if let Some(pitch) = self.pitch {
+            state.pitch = pitch;
+        }
+        if let Some(seated) = self.seated {
+            state.seated = seated;
+        }
+        if let Some(velocity) = self.velocity {
+            state.velocity = velocity;
+        }
+        if let Some(crouching) = self.crouching {
+            state.crouching = crouching;
+        }
+        if let Some(sprinting) = self.sprinting {
+            state.sprinting = sprinting;
+        }
+        if let Some(airborne) = self.airborne {
+            state.airborne = airborne;
+        }
+        if let Some(afk) = self.afk {
+            state.afk = afk;
+        }
+        if let Some(avatar) = self.avatar {
+            state.avatar = avatar;
+        }
+        if let Some(nickname) = &self.nickname {
+            state.nickname = nickname.clone();
+        }
+        if let Some(color) = self.color {
+            state.color = color;
+        }
+    }
+}
+
+/// What the player list shows about a player, broadcast by the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerInfo {
+    pub id: PlayerId,
+    /// Name the player picked, empty if they haven't.
+    pub nickname: String,
+    /// Round trip time between the host and the player, `None` for the host
+    /// itself or before the first answer.
+    pub ping_ms: Option<u32>,
+    pub host: bool,
+    /// Whether the player is sharing their screen.
+    pub presenting: bool,
+    /// Whether the player is away from keyboard.
+    #[serde(default)]
+    pub afk: bool,
+    /// Color the host gave the player on joining.
+    #[serde(default)]
+    pub color: u8,
+}
+
+impl PlayerInfo {
+    /// The nickname, or a stand-in for players who haven't picked one.
+    pub fn display_name(&self) -> String {
+        if self.nickname.is_empty() {
+            format!("Player {}", self.id)
+        } else {
+            self.nickname.clone()
+        }
+    }
+}
+
+/// A player's look: indices into the avatar models and tints every client ships with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AvatarChoice {
+    pub model: u8,
+    pub tint: u8,
+}
+
+/// Trim a nickname a player sent and cut it to the longest allowed, dropping
+/// control characters that would mess up how it's shown.
+pub fn clean_nickname(nickname: &str) -> String {
+    nickname
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NICKNAME_CHARS)
+        .collect()
+}
+
+/// World-space placement of a room screen, set by the host in screen edit mode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScreenPlacement {
+    pub screen: ScreenId,
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: f32,
+}
+
+impl ScreenPlacement {
+    pub fn new(screen: ScreenId, transform: &Transform) -> Self {
+        Self {
+            screen,
+            position: transform.translation.into(),
+            rotation: transform.rotation.into(),
+            scale: transform.scale.x,
+        }
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: Vec3::from(self.position),
+            rotation: Quat::from_array(self.rotation),
+            scale: Vec3::splat(self.scale),
+        }
+    }
+}
+
+/// Part of a whiteboard stroke, as a polyline in board coordinates.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WhiteboardStroke {
+    /// Player who drew it, which picks the color.
+    pub player: PlayerId,
+    /// Points from (0, 0) at the top-left to (u16::MAX, u16::MAX) at the bottom-right.
+    pub points: Vec<[u16; 2]>,
+}
+
+/// Emotes players can play from the emote wheel.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Emote {
+    Wave,
+    Clap,
+    ThumbsUp,
+}
+
+impl Emote {
+    /// All emotes, in the order they appear around the wheel.
+    pub const ALL: [Emote; 3] = [Emote::Wave, Emote::Clap, Emote::ThumbsUp];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Emote::Wave => "Wave",
+            Emote::Clap => "Clap",
+            Emote::ThumbsUp => "Thumbs up",
+        }
+    }
+}
+
+/// The image hanging in a poster frame, identified by the hash of its file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PosterAssignment {
+    pub poster: PosterId,
+    pub hash: u64,
+}
+
+/// Chunk of a PNG or JPEG file shown on poster frames.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PosterImageChunk {
+    /// Hash of the whole file.
+    pub hash: u64,
+    /// Chunk index within the file.
+    pub chunk_idx: u16,
+    /// Total chunks for the file.
+    pub total_chunks: u16,
+    /// File data (base64 encoded).
+    data_b64: String,
+}
+
+impl PosterImageChunk {
+    pub fn new(hash: u64, chunk_idx: u16, total_chunks: u16, data: &[u8]) -> Self {
+        Self {
+            hash,
+            chunk_idx,
+            total_chunks,
+            data_b64: BASE64.encode(data),
+        }
+    }
+
+    pub fn decode_data(&self) -> Option<Vec<u8>> {
+        BASE64.decode(&self.data_b64).ok()
+    }
 }
 
 /// Resource storing the local player's network ID.
 #[derive(Resource)]
 pub struct LocalPlayerId(pub PlayerId);
 
+/// Resource storing where the host placed the local player on joining.
+#[derive(Resource)]
+pub struct AssignedSpawn(pub SpawnTransform);
+
 /// Component marking a remote player entity.
 #[derive(Component)]
 pub struct RemotePlayer {
@@ -137,10 +751,249 @@ pub struct NetworkTransform {
     pub target_position: Vec3,
     pub target_yaw: f32,
     pub target_pitch: f32,
+    /// Vertical speed of an airborne player, carrying them up or down
+    /// between updates.
+    pub vertical_velocity: f32,
+    /// Whether the player is off the ground.
+    pub airborne: bool,
+    /// Time the target has been carried since the last update.
+    pub extrapolated: f32,
 }
 
-/// Resource tracking all known remote players for the client.
+/// Resource tracking all known remote players, and which of them changed
+/// since their entities last caught up.
 #[derive(Resource, Default)]
 pub struct RemotePlayers {
-    pub players: Vec<PlayerState>,
+    pub players: HashMap<PlayerId, PlayerState>,
+    /// Players that joined or changed, whose entities need updating.
+    pub changed: HashSet<PlayerId>,
+    /// Players that left, whose entities need despawning.
+    pub left: Vec<PlayerId>,
+}
+
+impl RemotePlayers {
+    /// Add a player or update their state, marking them changed if it differs.
+    pub fn upsert(&mut self, state: &PlayerState) {
+        if self.players.get(&state.id) == Some(state) {
+            return;
+        }
+        self.changed.insert(state.id);
+        self.players.insert(state.id, state.clone());
+    }
+
+    /// Update a known player with the fields that changed. Changes to players
+    /// not known yet are dropped, they come in full soon.
+    pub fn apply_delta(&mut self, delta: &PlayerStateDelta) {
+        if let Some(state) = self.players.get_mut(&delta.id) {
+            delta.apply(state);
+            self.changed.insert(delta.id);
+        }
+    }
+
+    pub fn remove(&mut self, id: PlayerId) {
+        if self.players.remove(&id).is_some() {
+            self.changed.remove(&id);
+            self.left.push(id);
+        }
+    }
+
+    /// Remove every player `keep` returns false for.
+    pub fn retain(&mut self, mut keep: impl FnMut(PlayerId) -> bool) {
+        let Self {
+            players,
+            changed,
+            left,
+        } = self;
+        players.retain(|&id, _| {
+            let kept = keep(id);
+            if !kept {
+                changed.remove(&id);
+                left.push(id);
+            }
+            kept
+        });
+    }
+}
+
+/// Resource mapping each remote player to the entity showing them.
+#[derive(Resource, Default)]
+pub struct RemotePlayerEntities(pub HashMap<PlayerId, Entity>);
+
+/// Resource with everyone in the session, the local player included, for
+/// the player list.
+#[derive(Resource, Default)]
+pub struct PlayerList {
+    pub players: Vec<PlayerInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs tried per test. Deterministic, so a failure reproduces.
+    const ROUNDS: usize = 20_000;
+
+    /// Small xorshift generator, enough to throw junk at the parsers.
+    struct Junk(u64);
+
+    impl Junk {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+            let len = self.below(max_len + 1);
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+
+        /// `packet` with a few bytes flipped, dropped, inserted or cut off.
+        fn mutate(&mut self, packet: &[u8]) -> Vec<u8> {
+            let mut packet = packet.to_vec();
+            for _ in 0..=self.below(4) {
+                let at = self.below(packet.len() + 1);
+                match self.below(4) {
+                    0 if at < packet.len() => packet[at] ^= 1 << self.below(8),
+                    1 if at < packet.len() => {
+                        packet.remove(at);
+                    }
+                    2 => packet.insert(at, self.next() as u8),
+                    _ => packet.truncate(at),
+                }
+            }
+            packet
+        }
+    }
+
+    /// Well formed packets of each kind, to mutate into nearly valid ones.
+    fn seeds() -> Vec<Vec<u8>> {
+        let client = [
+            ClientMessage::Join {
+                supported_codecs: vec![VideoCodecKind::H264, VideoCodecKind::Av1],
+                supported_audio_codecs: vec![AudioCodecKind::Opus],
+                avatar: AvatarChoice::default(),
+                nickname: "Alice".to_string(),
+                rejoin_token: Some(42),
+            },
+            ClientMessage::PlayerUpdate {
+                position: [1.0, 1.7, -3.5],
+                yaw: 0.5,
+                pitch: -0.25,
+                seated: false,
+                velocity: [0.0, 2.0, 0.0],
+                crouching: false,
+                sprinting: true,
+                airborne: true,
+                afk: false,
+            },
+            ClientMessage::WhiteboardStroke {
+                points: vec![[0, 0], [u16::MAX, 512]],
+            },
+            ClientMessage::RequestPosterImage {
+                hash: 42,
+                chunks: vec![0, 3],
+            },
+            ClientMessage::Leave,
+        ];
+        let mut seeds: Vec<Vec<u8>> = client
+            .iter()
+            .map(|msg| serde_json::to_vec(msg).unwrap())
+            .collect();
+        let welcome = ServerMessage::Welcome {
+            your_id: 3,
+            room: RoomId::default(),
+            spawn: None,
+            rejoin_token: Some(42),
+        };
+        seeds.push(serde_json::to_vec(&welcome).unwrap());
+        let chunk = VideoChunk::new(7, 1, 3, true, 1234, Bytes::from_static(b"frame data"));
+        let mut packet = Vec::new();
+        chunk.encode_into(&mut packet);
+        seeds.push(packet);
+        seeds
+    }
+
+    /// Feed a packet to every parser. Whatever gets through must hold up.
+    fn parse_all(packet: &[u8]) {
+        let packet = Bytes::copy_from_slice(packet);
+        if let Some(msg) = ClientMessage::parse(packet.clone()) {
+            assert!(msg.is_valid(), "invalid client message parsed: {:?}", msg);
+        }
+        let _ = ServerMessage::parse(packet.clone());
+        if let Some(chunk) = VideoChunk::decode(packet) {
+            assert!(chunk.chunk_idx < chunk.total_chunks);
+        }
+    }
+
+    #[test]
+    fn random_bytes_dont_panic() {
+        let mut junk = Junk(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..ROUNDS {
+            let mut packet = junk.bytes(MAX_PACKET_SIZE);
+            // Aim some at each parser's happy path
+            match junk.below(3) {
+                0 if !packet.is_empty() => packet[0] = VIDEO_PACKET_TAG,
+                1 if !packet.is_empty() => packet[0] = b'{',
+                _ => {}
+            }
+            parse_all(&packet);
+        }
+    }
+
+    #[test]
+    fn mutated_packets_dont_panic() {
+        let seeds = seeds();
+        let mut junk = Junk(0x2545_f491_4f6c_dd1d);
+        for _ in 0..ROUNDS {
+            let seed = &seeds[junk.below(seeds.len())];
+            parse_all(&junk.mutate(seed));
+        }
+    }
+
+    #[test]
+    fn video_chunks_round_trip() {
+        let chunk = VideoChunk::new(9, 2, 5, false, 77, Bytes::from_static(b"payload"));
+        let mut packet = Vec::new();
+        chunk.encode_into(&mut packet);
+        let decoded = VideoChunk::decode(Bytes::from(packet)).unwrap();
+        assert_eq!(decoded.frame_id, 9);
+        assert_eq!((decoded.chunk_idx, decoded.total_chunks), (2, 5));
+        assert!(!decoded.is_keyframe);
+        assert_eq!(decoded.pts_ms, 77);
+        assert_eq!(&decoded.data[..], b"payload");
+    }
+
+    #[test]
+    fn out_of_range_chunks_are_refused() {
+        let chunk = VideoChunk::new(1, 4, 4, false, 0, Bytes::new());
+        let mut packet = Vec::new();
+        chunk.encode_into(&mut packet);
+        assert!(VideoChunk::decode(Bytes::from(packet)).is_none());
+    }
+
+    #[test]
+    fn out_of_range_updates_are_refused() {
+        let update = |position: [f32; 3], pitch: f32| ClientMessage::PlayerUpdate {
+            position,
+            yaw: 0.0,
+            pitch,
+            seated: false,
+            velocity: [0.0; 3],
+            crouching: false,
+            sprinting: false,
+            airborne: false,
+            afk: false,
+        };
+        assert!(update([0.0, 1.0, 0.0], 0.0).is_valid());
+        assert!(!update([MAX_COORDINATE * 2.0, 1.0, 0.0], 0.0).is_valid());
+        assert!(!update([0.0, 1.0, 0.0], 4.0).is_valid());
+        let packet = br#"{"WhiteboardStroke":{"points":[]}}"#;
+        assert!(ClientMessage::parse(Bytes::from_static(packet)).is_none());
+    }
 }
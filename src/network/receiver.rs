@@ -0,0 +1,242 @@
+//! Socket receive threads. Each reads datagrams off a UDP socket and parses
+//! them away from the main schedule, so a burst of video chunks doesn't eat
+//! into a frame. Systems just drain what arrived. Addresses that keep sending
+//! datagrams that don't parse are ignored for a while. Simulated network
+//! conditions, if any, are applied here, and datagrams are recorded or
+//! replayed here.
+
+use bevy::prelude::*;
+use bytes::BytesMut;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::conditioner::{Conditioner, NetworkConditions};
+use super::protocol::Packet;
+use super::replay::{open_recording, replay_loop, DatagramRecorder};
+
+/// Pause between polls of an empty socket. The socket stays non-blocking,
+/// so sends from the game never wait behind a full buffer.
+const RECEIVE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Pause after a receive error, so a broken socket doesn't spin the thread.
+const ERROR_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Malformed datagrams an address may send within `MALFORMED_WINDOW` before
+/// it's ignored for `BAN_DURATION`.
+const MALFORMED_LIMIT: u32 = 20;
+const MALFORMED_WINDOW: Duration = Duration::from_secs(10);
+const BAN_DURATION: Duration = Duration::from_secs(60);
+
+/// Addresses tracked before old ones are forgotten, so a flood from spoofed
+/// addresses doesn't grow the table without end.
+const MAX_TRACKED_SENDERS: usize = 1024;
+
+/// What a receive thread hands the game.
+pub enum Received<M> {
+    /// A parsed message, the sender's address and the datagram's size.
+    Message {
+        msg: M,
+        from: SocketAddr,
+        len: usize,
+    },
+    /// The socket failed, e.g. with `ConnectionReset` once the other end is gone.
+    Error(io::Error),
+}
+
+/// Thread receiving messages of type `M` from a socket. Dropping it stops
+/// the thread.
+pub struct SocketReceiver<M> {
+    receiver: Mutex<Receiver<Received<M>>>,
+    running: Arc<AtomicBool>,
+}
+
+impl<M: Packet + Send + 'static> SocketReceiver<M> {
+    /// Start receiving on a clone of the non-blocking `socket`, in datagrams
+    /// of up to `buffer_size` bytes, under simulated `conditions` if given.
+    /// Datagrams are also written to `recording` if given.
+    pub fn spawn(
+        socket: &UdpSocket,
+        buffer_size: usize,
+        conditions: Option<NetworkConditions>,
+        recording: Option<&Path>,
+    ) -> Option<Self> {
+        let socket = socket
+            .try_clone()
+            .map_err(|e| error!("Failed to clone socket for receiving: {}", e))
+            .ok()?;
+        let recorder = match recording.map(DatagramRecorder::create) {
+            Some(Err(e)) => {
+                error!("Failed to start recording datagrams: {}", e);
+                None
+            }
+            recorder => recorder.and_then(Result::ok),
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        thread::spawn(move || {
+            receive_loop(
+                socket,
+                buffer_size,
+                conditions,
+                recorder,
+                tx,
+                thread_running,
+            )
+        });
+
+        Some(Self {
+            receiver: Mutex::new(rx),
+            running,
+        })
+    }
+
+    /// Play back the recording at `path` as messages from `from`, instead of
+    /// receiving on a socket.
+    pub fn replay(path: &Path, from: SocketAddr) -> Option<Self> {
+        let file = open_recording(path)
+            .map_err(|e| error!("Failed to open recording {}: {}", path.display(), e))
+            .ok()?;
+        info!("Replaying datagrams from {}", path.display());
+
+        let (tx, rx) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        thread::spawn(move || replay_loop(file, from, tx, thread_running));
+
+        Some(Self {
+            receiver: Mutex::new(rx),
+            running,
+        })
+    }
+
+    /// Next received message or error, if any (non-blocking).
+    pub fn try_recv(&self) -> Option<Received<M>> {
+        self.receiver.lock().ok()?.try_recv().ok()
+    }
+}
+
+impl<M> Drop for SocketReceiver<M> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Addresses that sent datagrams that don't parse, and those banned for it.
+#[derive(Default)]
+struct MalformedSenders {
+    /// Malformed datagrams from each address, and when it started sending them.
+    counts: HashMap<SocketAddr, (u32, Instant)>,
+    /// When each banned address is heard again.
+    banned: HashMap<SocketAddr, Instant>,
+}
+
+impl MalformedSenders {
+    fn is_banned(&mut self, from: SocketAddr) -> bool {
+        match self.banned.get(&from) {
+            Some(&until) if Instant::now() < until => true,
+            Some(_) => {
+                self.banned.remove(&from);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn record(&mut self, from: SocketAddr) {
+        let now = Instant::now();
+        if self.counts.len() >= MAX_TRACKED_SENDERS {
+            self.counts
+                .retain(|_, (_, since)| now.duration_since(*since) < MALFORMED_WINDOW);
+            self.banned.retain(|_, until| now < *until);
+        }
+
+        let (count, since) = self.counts.entry(from).or_insert((0, now));
+        if now.duration_since(*since) >= MALFORMED_WINDOW {
+            *count = 0;
+            *since = now;
+        }
+        *count += 1;
+        if *count >= MALFORMED_LIMIT {
+            warn!(
+                "Ignoring {} for {}s after {} malformed packets",
+                from,
+                BAN_DURATION.as_secs(),
+                count
+            );
+            self.counts.remove(&from);
+            self.banned.insert(from, now + BAN_DURATION);
+        }
+    }
+}
+
+fn receive_loop<M: Packet>(
+    socket: UdpSocket,
+    buffer_size: usize,
+    conditions: Option<NetworkConditions>,
+    mut recorder: Option<DatagramRecorder>,
+    tx: Sender<Received<M>>,
+    running: Arc<AtomicBool>,
+) {
+    // Datagrams are split off the front of one buffer, so messages can keep
+    // views into it. Growing it back reuses the allocation once they're gone.
+    let mut buf = BytesMut::new();
+    let mut malformed = MalformedSenders::default();
+    let mut conditioner = conditions.map(Conditioner::new);
+    while running.load(Ordering::Relaxed) {
+        if let Some(conditioner) = &mut conditioner {
+            while let Some(received) = conditioner.pop_due() {
+                if tx.send(received).is_err() {
+                    return;
+                }
+            }
+        }
+
+        buf.resize(buffer_size, 0);
+        let received = match socket.recv_from(&mut buf) {
+            Ok((_, from)) if malformed.is_banned(from) => continue,
+            Ok((len, from)) => {
+                let packet = buf.split_to(len).freeze();
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&packet);
+                }
+                // Anything that isn't a message is dropped
+                let Some(msg) = M::parse(packet) else {
+                    malformed.record(from);
+                    continue;
+                };
+                Received::Message { msg, from, len }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if let Some(recorder) = &mut recorder {
+                    recorder.flush();
+                }
+                thread::sleep(RECEIVE_POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                thread::sleep(ERROR_BACKOFF);
+                Received::Error(e)
+            }
+        };
+        // Errors aren't held back, they're about the socket not the network
+        match &mut conditioner {
+            Some(conditioner) if matches!(received, Received::Message { .. }) => {
+                conditioner.push(received);
+                continue;
+            }
+            _ => {}
+        }
+        if tx.send(received).is_err() {
+            return;
+        }
+    }
+}
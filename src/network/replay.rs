@@ -0,0 +1,144 @@
+//! Recording what a client receives and playing it back, to reproduce
+//! streaming bugs users report. With `--record-network` every datagram the
+//! client receives is written to a file as it arrives; `--replay-network`
+//! joins a pretend session fed from such a file, at the pace it was
+//! recorded, instead of a host.
+//!
+//! A recording is `RECORDING_MAGIC` followed by each datagram as the
+//! microseconds since recording started and its length, both little endian,
+//! then its bytes.
+
+use bevy::prelude::*;
+use bytes::Bytes;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::protocol::Packet;
+use super::receiver::Received;
+
+/// Start of every recording, naming the format and its version.
+const RECORDING_MAGIC: &[u8; 8] = b"ZINENET1";
+
+/// Longest the replay waits for a datagram's time before checking it's
+/// still wanted.
+const REPLAY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Largest datagram a recording can hold, the most UDP carries. Anything
+/// longer means the file is damaged.
+const MAX_RECORDED_DATAGRAM: usize = 64 * 1024;
+
+/// Where a replayed session pretends its host is. Nothing listens there, so
+/// what the client sends goes nowhere.
+pub const REPLAY_HOST: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9));
+
+/// Resource with the file to record the client's datagrams to.
+#[derive(Resource)]
+pub struct NetworkRecording(pub PathBuf);
+
+/// Resource with the recording to play back instead of joining a host.
+#[derive(Resource)]
+pub struct NetworkReplay(pub PathBuf);
+
+/// Writes datagrams to a recording as they arrive.
+pub struct DatagramRecorder {
+    file: BufWriter<File>,
+    started: Instant,
+}
+
+impl DatagramRecorder {
+    /// Start a new recording at `path`, replacing any file there.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(RECORDING_MAGIC)?;
+        info!("Recording received datagrams to {}", path.display());
+        Ok(Self {
+            file,
+            started: Instant::now(),
+        })
+    }
+
+    /// Add a datagram. Writing fails for good once the disk is full, so
+    /// errors are only logged.
+    pub fn record(&mut self, datagram: &[u8]) {
+        let micros = self.started.elapsed().as_micros() as u64;
+        let result = self
+            .file
+            .write_all(&micros.to_le_bytes())
+            .and_then(|_| self.file.write_all(&(datagram.len() as u32).to_le_bytes()))
+            .and_then(|_| self.file.write_all(datagram));
+        if let Err(e) = result {
+            warn!("Failed to record datagram: {}", e);
+        }
+    }
+
+    /// Write out what's buffered, e.g. while the socket is quiet, so a crash
+    /// loses little of the recording.
+    pub fn flush(&mut self) {
+        let _ = self.file.flush();
+    }
+}
+
+/// Open a recording, checking it is one.
+pub fn open_recording(path: &Path) -> io::Result<BufReader<File>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != RECORDING_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a network recording",
+        ));
+    }
+    Ok(file)
+}
+
+/// Hand the game a recording's datagrams as messages from `from`, each once
+/// as long has passed since starting as had when it was recorded.
+pub fn replay_loop<M: Packet>(
+    mut file: BufReader<File>,
+    from: SocketAddr,
+    tx: Sender<Received<M>>,
+    running: Arc<AtomicBool>,
+) {
+    let started = Instant::now();
+    let mut header = [0u8; 12];
+    let mut count = 0;
+    while running.load(Ordering::Relaxed) {
+        if file.read_exact(&mut header).is_err() {
+            break;
+        }
+        let micros = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let len = u32::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+        if len > MAX_RECORDED_DATAGRAM {
+            warn!("Recording has a {} byte datagram, stopping the replay", len);
+            break;
+        }
+        let mut datagram = vec![0u8; len];
+        if file.read_exact(&mut datagram).is_err() {
+            break;
+        }
+
+        let due = started + Duration::from_micros(micros);
+        while let Some(wait) = due.checked_duration_since(Instant::now()) {
+            if !running.load(Ordering::Relaxed) {
+                return;
+            }
+            thread::sleep(wait.min(REPLAY_POLL_INTERVAL));
+        }
+        count += 1;
+        let Some(msg) = M::parse(Bytes::from(datagram)) else {
+            continue;
+        };
+        if tx.send(Received::Message { msg, from, len }).is_err() {
+            return;
+        }
+    }
+    info!("Replay finished after {} datagrams", count);
+}
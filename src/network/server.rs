@@ -1,31 +1,344 @@
 use bevy::prelude::*;
-use std::collections::HashMap;
-use std::net::{SocketAddr, UdpSocket};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasher;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
 use std::time::{Duration, Instant};
 
-use super::discovery::GAME_PORT;
-use super::protocol::{ClientMessage, LocalPlayerId, PlayerId, PlayerState, ServerMessage};
+use super::conditioner::NetworkConditions;
+use super::discovery::{GAME_PORT, GAME_PORT_ATTEMPTS};
+use super::protocol::{
+    clean_nickname, AvatarChoice, ClientMessage, LocalPlayerId, PlayerId, PlayerInfo, PlayerList,
+    PlayerState, PlayerStateDelta, ServerMessage, SpawnTransform, VideoChunk, VideoCodecInfo,
+    VideoCodecKind, WhiteboardStroke, WorldSnapshot,
+};
+use super::receiver::{Received, SocketReceiver};
+#[cfg(feature = "web-spectators")]
+use super::web_spectators::WebSpectators;
+use crate::camera::spectator::not_spectating;
+use crate::character::AvatarSelection;
+use crate::console::AddConsoleCommand;
+use crate::controls::Afk;
+use crate::emote::EmoteEvent;
 use crate::game_state::AppState;
-use crate::menu::NotificationEvent;
-use crate::player::Player;
-use crate::screen::streaming::{LatestCapturedFrame, ScreenStreamState};
+use crate::menu::{ErrorNotification, NotificationEvent};
+use crate::player::identity::free_color;
+use crate::player::{
+    Crouching, Grounded, Player, Seated, Sprinting, Velocity, JUMP_VELOCITY, PLAYER_HEIGHT,
+    SPRINT_SPEED,
+};
+use crate::screen::streaming::{LatestCapturedFrame, ScreenStreamState, StreamClock};
 
-use crate::network::protocol::AudioChunk;
-use crate::screen::audio_capture::AudioCapture;
+use crate::network::protocol::{AudioChunk, AudioCodecKind};
+use crate::screen::audio_capture::{AudioCapture, AudioCaptureTarget};
+use crate::screen::capture::{CaptureSource, CaptureSourceType, CaptureTarget};
+use crate::screen::diagnostics::StreamCounters;
 use crate::screen::audio_encoder::{AudioEncoder, AudioSender};
-use crate::screen::video_encoder::{VideoEncoder, VideoSender};
+use crate::screen::video_encoder::{StreamQuality, VideoEncoder, VideoSender};
+use crate::screen::preview::LocalPreview;
+use crate::screen::recording::SessionRecorder;
+use crate::settings::{AudioSettings, Nickname, SessionSettings, VideoSettings};
+use crate::world::zones::Zone;
+use crate::world::{
+    CurrentRoom, DoorStates, PosterAssignments, PosterCache, RoomId, ScreenLayout, Seat,
+    SpawnPoint, Whiteboard,
+};
 
-/// Client timeout duration in seconds.
-const CLIENT_TIMEOUT_SECS: u64 = 5;
+/// Poster image chunks sent per frame, so a file doesn't flood the socket.
+const POSTER_CHUNKS_PER_FRAME: usize = 64;
+
+/// Times the end of the session is announced, in case a packet is lost.
+const SESSION_CLOSED_REPEATS: usize = 3;
+
+/// How often clients are pinged to measure their round trip time.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Every this many state updates carry every player, not just those that
+/// changed, so clients recover from lost packets.
+const FULL_STATE_INTERVAL: u64 = 20;
+
+/// Players within this distance of a client reach it with every update.
+const NEAR_INTEREST_DISTANCE: f32 = 8.0;
+
+/// Players within this distance and in front of a client reach it every
+/// `MID_INTEREST_INTERVAL` updates, the rest every `FAR_INTEREST_INTERVAL`.
+const MID_INTEREST_DISTANCE: f32 = 20.0;
+const MID_INTEREST_INTERVAL: u64 = 2;
+const FAR_INTEREST_INTERVAL: u64 = 5;
+
+/// Round trip time above which a client gets updates half as often, so a
+/// congested link isn't flooded further.
+const SLOW_CLIENT_PING_MS: u32 = 150;
+
+/// Largest datagram the server receives from clients.
+const RECEIVE_BUFFER_SIZE: usize = 1024;
+
+/// Most clients a session takes. Joins past it are ignored.
+const MAX_CLIENTS: usize = 32;
+
+/// Most poster image chunks queued for one client, so requests can't pile
+/// up more uploads than the socket gets through.
+const MAX_QUEUED_POSTER_CHUNKS: usize = 1024;
+
+/// Spawn points with nobody within this distance are free for a new player.
+const SPAWN_CLEARANCE: f32 = 0.8;
+
+/// Bytes each address may send per second, and in one burst. Well above what
+/// a player's updates and strokes take.
+const CLIENT_BYTE_RATE: f32 = 32_000.0;
+const CLIENT_BYTE_BURST: f32 = 64_000.0;
+
+/// Bytes a join counts as on top of its size, so joins can't be spammed.
+const JOIN_COST: f32 = 8_000.0;
+
+/// Datagrams handled per frame. The rest wait for the next frame.
+const MAX_DATAGRAMS_PER_FRAME: usize = 512;
+
+/// Addresses rate limited before quiet ones are forgotten.
+const MAX_RATE_LIMITED_ADDRS: usize = 1024;
+
+/// How long the stream keeps being encoded after the last client leaves, so
+/// one reconnecting doesn't restart it.
+const STREAM_PAUSE_DELAY: Duration = Duration::from_secs(3);
+
+/// Fastest a client may move across the ground or up, leaving headroom over
+/// sprinting and jumping for uneven frame times. Moves past these are clamped,
+/// so a client claiming to teleport just runs there.
+const MAX_GROUND_SPEED: f32 = SPRINT_SPEED * 1.5;
+const MAX_RISE_SPEED: f32 = JUMP_VELOCITY * 1.5;
+
+/// Most a client may move beyond the speed limits, for jitter in when its
+/// updates are sent. Spent by moves that go over and earned back over time.
+const MOVE_TOLERANCE: f32 = 0.5;
+
+/// Slack earned back per second, in metres.
+const MOVE_SLACK_RATE: f32 = 1.0;
+
+/// How far sitting down or standing up may move a player at once, when there
+/// is a seat that close to where they end up.
+const SEAT_SNAP_DISTANCE: f32 = 4.0;
+
+/// Longest gap between updates counted towards how far a player may move, so
+/// a client can't go quiet to save up for a teleport.
+const MAX_MOVE_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Resource indicating this instance is the server/host.
 #[derive(Resource)]
 pub struct GameServer {
     pub socket: UdpSocket,
+    /// Port the server listens on, `GAME_PORT` unless that was taken.
+    pub port: u16,
+    /// Messages from clients, received on their own thread.
+    pub receiver: SocketReceiver<ClientMessage>,
     pub clients: HashMap<SocketAddr, PlayerId>,
     pub client_last_activity: HashMap<SocketAddr, Instant>,
     pub player_states: HashMap<PlayerId, PlayerState>,
     pub next_player_id: PlayerId,
+    /// Video codecs each client reported it can decode.
+    pub client_codecs: HashMap<SocketAddr, Vec<VideoCodecKind>>,
+    /// Codec currently used for the video stream.
+    pub active_codec: VideoCodecKind,
+    /// Codec, size and parameter sets of the encoder's current stream.
+    pub stream_info: Option<VideoCodecInfo>,
+    /// Chunks of the stream's latest keyframe, replayed to clients joining
+    /// mid-stream so they show a picture before the next one.
+    pub last_keyframe: Vec<VideoChunk>,
+    /// Audio codecs each client reported it can decode.
+    pub client_audio_codecs: HashMap<SocketAddr, Vec<AudioCodecKind>>,
+    /// Codec currently used for the audio stream.
+    pub active_audio_codec: AudioCodecKind,
+    /// Room this session takes place in, told to clients when they join.
+    pub room: RoomId,
+    /// Where new players appear when the room has no spawn points.
+    pub spawn_point: Vec3,
+    /// Poster image chunks clients asked for, waiting to be sent.
+    pub poster_uploads: VecDeque<(SocketAddr, u64, u16)>,
+    /// Latest round trip time measured to each client, in milliseconds.
+    pub client_pings: HashMap<SocketAddr, u32>,
+    /// Sequence number of the last ping sent and when it went out.
+    pub last_ping: (u32, Instant),
+    /// Player states as last sent to each client, to send only what changed.
+    pub client_sent_states: HashMap<SocketAddr, HashMap<PlayerId, PlayerState>>,
+    /// State updates broadcast so far.
+    pub state_tick: u64,
+    /// Player list last broadcast, to send it only when it changes.
+    pub sent_info: Vec<PlayerInfo>,
+    /// Byte budget of each address sending to the server.
+    rate_limits: HashMap<SocketAddr, RateLimit>,
+    /// How far each client may move next, to bound its moves.
+    move_budgets: HashMap<SocketAddr, MoveBudget>,
+    /// When each client that's away from their keyboard went away.
+    afk_since: HashMap<SocketAddr, Instant>,
+    /// Secret each client was welcomed with, for it to rejoin with.
+    rejoin_tokens: HashMap<SocketAddr, u64>,
+    /// Players who left recently, who get their place back if they rejoin
+    /// within the grace period.
+    departed: Vec<DepartedPlayer>,
+    /// Datagrams dropped or left for later, shown in the stream info.
+    pub packet_drops: PacketDrops,
+}
+
+impl GameServer {
+    /// A server for a session in `room` with no clients yet, the host being
+    /// player 0 at the room's spawn point.
+    pub fn new(
+        socket: UdpSocket,
+        port: u16,
+        receiver: SocketReceiver<ClientMessage>,
+        room: RoomId,
+        avatar: AvatarChoice,
+        nickname: &str,
+    ) -> Self {
+        let host_id: PlayerId = 0;
+        let spawn_point = room.spawn_point(PLAYER_HEIGHT);
+        let mut player_states = HashMap::new();
+        player_states.insert(
+            host_id,
+            PlayerState {
+                id: host_id,
+                position: spawn_point.into(),
+                yaw: std::f32::consts::PI,
+                pitch: 0.0,
+                seated: false,
+                velocity: [0.0; 3],
+                crouching: false,
+                sprinting: false,
+                airborne: false,
+                afk: false,
+                avatar,
+                nickname: clean_nickname(nickname),
+                color: 0,
+            },
+        );
+
+        Self {
+            socket,
+            port,
+            receiver,
+            clients: HashMap::new(),
+            client_last_activity: HashMap::new(),
+            player_states,
+            next_player_id: 1,
+            client_codecs: HashMap::new(),
+            active_codec: VideoCodecKind::H264,
+            stream_info: None,
+            last_keyframe: Vec::new(),
+            client_audio_codecs: HashMap::new(),
+            active_audio_codec: AudioCodecKind::Pcm,
+            room,
+            spawn_point,
+            poster_uploads: VecDeque::new(),
+            client_pings: HashMap::new(),
+            last_ping: (0, Instant::now()),
+            client_sent_states: HashMap::new(),
+            state_tick: 0,
+            sent_info: Vec::new(),
+            rate_limits: HashMap::new(),
+            move_budgets: HashMap::new(),
+            afk_since: HashMap::new(),
+            rejoin_tokens: HashMap::new(),
+            departed: Vec::new(),
+            packet_drops: PacketDrops::default(),
+        }
+    }
+
+    /// Whether `from` has the budget for a datagram of `len` bytes carrying
+    /// `msg`, spending it if so.
+    fn admit(&mut self, from: SocketAddr, msg: &ClientMessage, len: usize, now: Instant) -> bool {
+        let cost = match msg {
+            ClientMessage::Join { .. } => len as f32 + JOIN_COST,
+            _ => len as f32,
+        };
+        let limits = &mut self.rate_limits;
+        if limits.len() >= MAX_RATE_LIMITED_ADDRS && !limits.contains_key(&from) {
+            // Addresses with a full budget haven't sent anything in a while
+            limits.retain(|_, limit| !limit.refill(now));
+        }
+        limits
+            .entry(from)
+            .or_insert_with(|| RateLimit::new(now))
+            .take(cost, now)
+    }
+}
+
+/// A player who left, as they were when they did.
+struct DepartedPlayer {
+    ip: IpAddr,
+    /// Token the player was welcomed with, which it has to rejoin with.
+    token: u64,
+    state: PlayerState,
+    left_at: Instant,
+}
+
+/// Token bucket of bytes an address may send, refilled over time.
+struct RateLimit {
+    tokens: f32,
+    refilled_at: Instant,
+}
+
+impl RateLimit {
+    fn new(now: Instant) -> Self {
+        Self {
+            tokens: CLIENT_BYTE_BURST,
+            refilled_at: now,
+        }
+    }
+
+    /// Add the bytes earned since the last refill. True once the budget is full.
+    fn refill(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * CLIENT_BYTE_RATE).min(CLIENT_BYTE_BURST);
+        self.refilled_at = now;
+        self.tokens >= CLIENT_BYTE_BURST
+    }
+
+    /// Spend `cost` bytes if the budget has them.
+    fn take(&mut self, cost: f32, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens < cost {
+            return false;
+        }
+        self.tokens -= cost;
+        true
+    }
+}
+
+/// When a client last moved and the slack it has left for going over the
+/// speed limits. Only time earns slack, so sending updates faster doesn't.
+#[derive(Clone, Copy)]
+struct MoveBudget {
+    slack: f32,
+    moved_at: Instant,
+}
+
+impl MoveBudget {
+    fn new(now: Instant) -> Self {
+        Self {
+            slack: MOVE_TOLERANCE,
+            moved_at: now,
+        }
+    }
+
+    /// Add the slack earned since the last move. Seconds that count towards
+    /// the next move.
+    fn refill(&mut self, now: Instant) -> f32 {
+        let secs = now
+            .duration_since(self.moved_at)
+            .min(MAX_MOVE_INTERVAL)
+            .as_secs_f32();
+        self.slack = (self.slack + secs * MOVE_SLACK_RATE).min(MOVE_TOLERANCE);
+        self.moved_at = now;
+        secs
+    }
+}
+
+/// Counts of client datagrams the server didn't handle when they arrived.
+#[derive(Clone, Copy, Default)]
+pub struct PacketDrops {
+    /// Dropped for going over their sender's rate limit.
+    pub rate_limited: u64,
+    /// Frames that hit `MAX_DATAGRAMS_PER_FRAME`, leaving datagrams queued.
+    pub capped_frames: u64,
 }
 
 /// Timer for sending state updates.
@@ -35,6 +348,7 @@ pub struct ServerSyncTimer(pub Timer);
 pub fn server_plugin(app: &mut App) {
     app.add_systems(OnEnter(AppState::Hosting), setup_server)
         .add_systems(OnExit(AppState::InGame), cleanup_server)
+        .add_systems(Last, close_session_on_exit)
         .add_systems(
             Update,
             server_ready_check.run_if(in_state(AppState::Hosting)),
@@ -46,21 +360,53 @@ pub fn server_plugin(app: &mut App) {
             receive_client_messages,
             check_client_timeouts,
             broadcast_game_state,
-            update_host_player_state,
+            // The host's avatar stays put while they fly the spectator camera
+            update_host_player_state.run_if(not_spectating),
+            negotiate_video_codec,
+            negotiate_audio_codec,
             broadcast_video_frames,
+            follow_capture_source_audio,
+            apply_capture_device_setting,
+            apply_audio_bitrate_setting,
             broadcast_audio_frames,
+            broadcast_whiteboard,
+            send_poster_uploads,
+            broadcast_emotes,
+            ping_clients,
         )
             .run_if(in_state(AppState::InGame).and(resource_exists::<GameServer>)),
     );
-}
 
-fn setup_server(mut commands: Commands) {
-    let server_addr = format!("0.0.0.0:{}", GAME_PORT);
+    app.add_console_command("kick", "<id>", kick_command);
+}
 
-    let socket = match UdpSocket::bind(&server_addr) {
-        Ok(s) => s,
+fn setup_server(
+    mut commands: Commands,
+    audio_settings: Res<AudioSettings>,
+    video_settings: Res<VideoSettings>,
+    room: Res<CurrentRoom>,
+    avatar: Res<AvatarSelection>,
+    nickname: Res<Nickname>,
+    conditions: Option<Res<NetworkConditions>>,
+    mut errors: EventWriter<ErrorNotification>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let (socket, port) = match bind_game_socket() {
+        Ok(bound) => bound,
         Err(e) => {
             error!("Failed to bind server socket: {}", e);
+            let last_port = GAME_PORT + GAME_PORT_ATTEMPTS - 1;
+            let error = if e.kind() == std::io::ErrorKind::AddrInUse {
+                ErrorNotification::new(format!(
+                    "Can't host: ports {} to {} are all in use, are other games open?",
+                    GAME_PORT, last_port
+                ))
+                .with_copy(GAME_PORT.to_string())
+            } else {
+                ErrorNotification::new(format!("Can't host on port {}: {}", GAME_PORT, e))
+            };
+            errors.send(error);
+            next_state.set(AppState::MainMenu);
             return;
         }
     };
@@ -90,27 +436,21 @@ fn setup_server(mut commands: Commands) {
     // Clone sockets for streaming before moving into GameServer
     let video_socket = socket.try_clone().ok();
     let audio_socket = socket.try_clone().ok();
+    let conditions = conditions.as_deref().copied();
+    let receiver = SocketReceiver::spawn(&socket, RECEIVE_BUFFER_SIZE, conditions, None);
+    let Some(receiver) = receiver else {
+        return;
+    };
 
-    // Host is player 0
     let host_id: PlayerId = 0;
-    let mut player_states = HashMap::new();
-    player_states.insert(
-        host_id,
-        PlayerState {
-            id: host_id,
-            position: [0.0, 1.8, 4.0],
-            yaw: std::f32::consts::PI,
-            pitch: 0.0,
-        },
-    );
-
-    commands.insert_resource(GameServer {
+    commands.insert_resource(GameServer::new(
         socket,
-        clients: HashMap::new(),
-        client_last_activity: HashMap::new(),
-        player_states,
-        next_player_id: 1,
-    });
+        port,
+        receiver,
+        room.0.clone(),
+        avatar.0,
+        &nickname.0,
+    ));
 
     commands.insert_resource(LocalPlayerId(host_id));
     commands.insert_resource(ServerSyncTimer(Timer::new(
@@ -118,10 +458,15 @@ fn setup_server(mut commands: Commands) {
         TimerMode::Repeating,
     )));
     commands.insert_resource(ScreenStreamState::default());
+    commands.insert_resource(StreamClock::default());
     commands.insert_resource(LastStreamedFrame::default());
 
-    // Initialize H.264 video encoder (1920x1080 @ 30fps as default)
-    if let Some(video_encoder) = VideoEncoder::new(1920, 1080, 30) {
+    // Initialize the video encoder, sized by the first captured frame
+    let idle_timeout = match video_settings.encoder_idle_secs {
+        0 => Duration::MAX,
+        secs => Duration::from_secs(secs as u64),
+    };
+    if let Some(video_encoder) = VideoEncoder::new(idle_timeout) {
         info!("Video encoder initialized (OpenH264)");
         commands.insert_resource(video_encoder);
 
@@ -134,14 +479,18 @@ fn setup_server(mut commands: Commands) {
     }
 
     // Initialize audio capture (system loopback)
-    if let Some(audio_capture) = AudioCapture::new() {
+    if let Some(audio_capture) = AudioCapture::new(
+        AudioCaptureTarget::System,
+        audio_settings.capture_device.as_deref(),
+    ) {
         let sample_rate = audio_capture.sample_rate;
         let channels = audio_capture.channels;
         commands.insert_resource(audio_capture);
 
-        // Initialize Opus audio encoder
-        if let Some(audio_encoder) = AudioEncoder::new(sample_rate, channels) {
-            info!("Audio encoder initialized (Opus)");
+        // Initialize audio encoder (PCM until Opus is negotiated)
+        let bitrate = audio_settings.audio_bitrate_kbps as i32 * 1000;
+        if let Some(audio_encoder) = AudioEncoder::new(sample_rate, channels, bitrate) {
+            info!("Audio encoder initialized");
             commands.insert_resource(audio_encoder);
 
             // Create audio sender with cloned socket
@@ -155,11 +504,32 @@ fn setup_server(mut commands: Commands) {
         warn!("Failed to initialize audio capture - audio streaming disabled");
     }
 
-    info!("Server started on port {}", GAME_PORT);
+    info!("Server started on port {}", port);
 }
 
-fn cleanup_server(mut commands: Commands) {
+/// Bind the server socket to `GAME_PORT`, or the next free port after it.
+fn bind_game_socket() -> std::io::Result<(UdpSocket, u16)> {
+    for port in GAME_PORT..GAME_PORT + GAME_PORT_ATTEMPTS {
+        match UdpSocket::bind(("0.0.0.0", port)) {
+            Ok(socket) => {
+                if port != GAME_PORT {
+                    warn!("Port {} is taken, hosting on {} instead", GAME_PORT, port);
+                }
+                return Ok((socket, port));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::ErrorKind::AddrInUse.into())
+}
+
+fn cleanup_server(mut commands: Commands, server: Option<Res<GameServer>>) {
+    if let Some(server) = server {
+        announce_session_closed(&server);
+    }
     commands.remove_resource::<GameServer>();
+    commands.insert_resource(PlayerList::default());
     commands.remove_resource::<ServerSyncTimer>();
     commands.remove_resource::<LocalPlayerId>();
     commands.remove_resource::<ScreenStreamState>();
@@ -169,6 +539,36 @@ fn cleanup_server(mut commands: Commands) {
     commands.remove_resource::<AudioCapture>();
     commands.remove_resource::<AudioEncoder>();
     commands.remove_resource::<AudioSender>();
+    commands.remove_resource::<StreamClock>();
+}
+
+/// Tell every client the session is over, so they leave right away instead
+/// of timing out. Sends go straight to the OS, so they're out even if the
+/// app quits right after.
+fn announce_session_closed(server: &GameServer) {
+    if server.clients.is_empty() {
+        return;
+    }
+    let Ok(data) = serde_json::to_vec(&ServerMessage::SessionClosed) else {
+        return;
+    };
+    for _ in 0..SESSION_CLOSED_REPEATS {
+        for addr in server.clients.keys() {
+            let _ = server.socket.send_to(&data, addr);
+        }
+    }
+    info!("Told {} clients the session ended", server.clients.len());
+}
+
+/// System to end the session for clients when the host closes the game,
+/// which skips leaving the game state.
+fn close_session_on_exit(mut exit_events: EventReader<AppExit>, server: Option<Res<GameServer>>) {
+    if exit_events.read().count() == 0 {
+        return;
+    }
+    if let Some(server) = server {
+        announce_session_closed(&server);
+    }
 }
 
 fn server_ready_check(
@@ -176,81 +576,407 @@ fn server_ready_check(
     server: Option<Res<GameServer>>,
 ) {
     if server.is_some() {
-        next_state.set(AppState::InGame);
+        next_state.set(AppState::Loading);
+    }
+}
+
+/// Pick where a joining player starts: the first spawn point nobody stands
+/// at, or else the one furthest from everyone.
+fn assign_spawn(
+    server: &GameServer,
+    spawn_points: &Query<&GlobalTransform, With<SpawnPoint>>,
+) -> SpawnTransform {
+    let clearance = |point: Vec3| {
+        server
+            .player_states
+            .values()
+            .map(|state| {
+                let position = Vec3::from(state.position);
+                Vec2::new(position.x - point.x, position.z - point.z).length()
+            })
+            .fold(f32::INFINITY, f32::min)
+    };
+
+    let mut best: Option<(&GlobalTransform, f32)> = None;
+    for spawn_point in spawn_points.iter() {
+        let distance = clearance(spawn_point.translation());
+        if distance > SPAWN_CLEARANCE {
+            best = Some((spawn_point, distance));
+            break;
+        }
+        if best.is_none_or(|(_, best_distance)| distance > best_distance) {
+            best = Some((spawn_point, distance));
+        }
+    }
+
+    match best {
+        Some((spawn_point, _)) => {
+            let (yaw, _, _) = spawn_point
+                .compute_transform()
+                .rotation
+                .to_euler(EulerRot::YXZ);
+            SpawnTransform {
+                position: (spawn_point.translation() + Vec3::Y * PLAYER_HEIGHT).into(),
+                yaw,
+            }
+        }
+        None => SpawnTransform {
+            position: server.spawn_point.into(),
+            yaw: 0.0,
+        },
     }
 }
 
 fn receive_client_messages(
     mut server: ResMut<GameServer>,
+    mut whiteboard: ResMut<Whiteboard>,
+    mut door_states: ResMut<DoorStates>,
+    poster_cache: Res<PosterCache>,
+    screen_layout: Res<ScreenLayout>,
+    poster_assignments: Res<PosterAssignments>,
+    latest_frame: Res<LatestCapturedFrame>,
+    capture_target: Option<Res<CaptureTarget>>,
+    spawn_points: Query<&GlobalTransform, With<SpawnPoint>>,
+    zones: Query<(&Zone, &GlobalTransform)>,
+    seats: Query<&GlobalTransform, With<Seat>>,
+    encoder: Option<Res<VideoEncoder>>,
+    session: Res<SessionSettings>,
+    mut emote_events: EventWriter<EmoteEvent>,
     mut notifications: EventWriter<NotificationEvent>,
 ) {
-    let mut buf = [0u8; 1024];
+    let rejoin_grace = Duration::from_secs(session.rejoin_grace_secs as u64);
     let mut players_to_remove: Vec<SocketAddr> = Vec::new();
+    let now = Instant::now();
 
-    loop {
-        match server.socket.recv_from(&mut buf) {
-            Ok((len, src_addr)) => {
-                if let Ok(msg) = serde_json::from_slice::<ClientMessage>(&buf[..len]) {
-                    match msg {
-                        ClientMessage::Join => {
-                            // New client joining
-                            if !server.clients.contains_key(&src_addr) {
+    // A flood can't hold up the frame, what's left waits for the next one
+    let mut handled = 0;
+    while handled < MAX_DATAGRAMS_PER_FRAME {
+        let Some(received) = server.receiver.try_recv() else {
+            break;
+        };
+        handled += 1;
+        match received {
+            Received::Message { msg, from, len } if !server.admit(from, &msg, len, now) => {
+                server.packet_drops.rate_limited += 1;
+            }
+            Received::Message {
+                msg,
+                from: src_addr,
+                ..
+            } => match msg {
+                ClientMessage::Join {
+                    supported_codecs,
+                    supported_audio_codecs,
+                    avatar,
+                    nickname,
+                    rejoin_token,
+                } => {
+                    if !server.clients.contains_key(&src_addr)
+                        && server.clients.len() >= MAX_CLIENTS
+                    {
+                        warn!("Session is full, ignoring join from {}", src_addr);
+                        continue;
+                    }
+                    // New client joining
+                    if !server.clients.contains_key(&src_addr) {
+                        let nickname = clean_nickname(&nickname);
+                        // Someone who just dropped out comes back as themselves
+                        let rejoined = server.departed.iter().rposition(|departed| {
+                            Some(departed.token) == rejoin_token
+                                && departed.ip == src_addr.ip()
+                                && departed.left_at.elapsed() <= rejoin_grace
+                        });
+                        let rejoined = rejoined.map(|i| server.departed.remove(i).state);
+                        let player_id = match &rejoined {
+                            Some(state) => state.id,
+                            None => {
                                 let player_id = server.next_player_id;
                                 server.next_player_id += 1;
-                                server.clients.insert(src_addr, player_id);
-                                server.client_last_activity.insert(src_addr, Instant::now());
-                                server.player_states.insert(
-                                    player_id,
-                                    PlayerState {
-                                        id: player_id,
-                                        position: [0.0, 1.8, 4.0],
-                                        yaw: std::f32::consts::PI,
-                                        pitch: 0.0,
-                                    },
-                                );
-
-                                info!("Player {} joined from {}", player_id, src_addr);
-                                notifications.send(NotificationEvent("A user has joined".to_string()));
-
-                                // Send welcome message
-                                let welcome = ServerMessage::Welcome { your_id: player_id };
-                                if let Ok(data) = serde_json::to_vec(&welcome) {
-                                    let _ = server.socket.send_to(&data, src_addr);
-                                }
+                                player_id
                             }
+                        };
+                        server.clients.insert(src_addr, player_id);
+                        server.client_codecs.insert(src_addr, supported_codecs);
+                        server
+                            .client_audio_codecs
+                            .insert(src_addr, supported_audio_codecs);
+                        server.client_last_activity.insert(src_addr, Instant::now());
+                        server.move_budgets.insert(src_addr, MoveBudget::new(now));
+                        let token = RandomState::new().hash_one((src_addr, now));
+                        server.rejoin_tokens.insert(src_addr, token);
+                        let joined = match (nickname.is_empty(), rejoined.is_some()) {
+                            (true, false) => "A user has joined".to_string(),
+                            (true, true) => "A user has rejoined".to_string(),
+                            (false, false) => format!("{} has joined", nickname),
+                            (false, true) => format!("{} has rejoined", nickname),
+                        };
+                        let (spawn, color) = match &rejoined {
+                            Some(state) => (
+                                SpawnTransform {
+                                    position: state.position,
+                                    yaw: state.yaw,
+                                },
+                                state.color,
+                            ),
+                            None => (
+                                assign_spawn(&server, &spawn_points),
+                                free_color(server.player_states.values().map(|s| s.color)),
+                            ),
+                        };
+                        server.player_states.insert(
+                            player_id,
+                            PlayerState {
+                                id: player_id,
+                                position: spawn.position,
+                                yaw: spawn.yaw,
+                                pitch: 0.0,
+                                seated: false,
+                                velocity: [0.0; 3],
+                                crouching: false,
+                                sprinting: false,
+                                airborne: false,
+                                afk: false,
+                                avatar,
+                                nickname,
+                                color,
+                            },
+                        );
+                        // The newcomer has been sent nobody yet
+                        server.client_sent_states.insert(src_addr, HashMap::new());
+
+                        info!("Player {} joined from {}", player_id, src_addr);
+                        notifications.send(NotificationEvent(joined));
+
+                        // Send welcome message
+                        let welcome = ServerMessage::Welcome {
+                            your_id: player_id,
+                            room: server.room.clone(),
+                            spawn: Some(spawn),
+                            rejoin_token: Some(token),
+                        };
+                        if let Ok(data) = serde_json::to_vec(&welcome) {
+                            let _ = server.socket.send_to(&data, src_addr);
+                        }
+
+                        // Catch the new client up on the stream and the room
+                        let sharing = capture_target.is_some() && latest_frame.width > 0;
+                        let stream = server
+                            .stream_info
+                            .clone()
+                            .filter(|info| sharing && info.codec == server.active_codec);
+                        let streaming = stream.is_some();
+                        let video = stream.unwrap_or_else(|| {
+                            let (width, height) = if sharing {
+                                (latest_frame.width, latest_frame.height)
+                            } else {
+                                (0, 0)
+                            };
+                            VideoCodecInfo {
+                                codec: server.active_codec,
+                                width,
+                                height,
+                                fps: 60,
+                                extradata: Vec::new(),
+                            }
+                        });
+                        let snapshot = ServerMessage::FullStateSync(WorldSnapshot {
+                            video,
+                            stream_screen: capture_target.as_ref().map(|target| target.0),
+                            screens: screen_layout.screens.clone(),
+                            open_doors: door_states.open_list(),
+                            posters: poster_assignments.posters.clone(),
+                        });
+                        if let Ok(data) = serde_json::to_vec(&snapshot) {
+                            let _ = server.socket.send_to(&data, src_addr);
+                        }
+
+                        // Show the stream straight away from its last keyframe, and
+                        // have a fresh one sent to get in step with the frames after it
+                        if streaming {
+                            let mut packet = Vec::new();
+                            for chunk in &server.last_keyframe {
+                                chunk.encode_into(&mut packet);
+                                let _ = server.socket.send_to(&packet, src_addr);
+                            }
+                            if let Some(encoder) = &encoder {
+                                encoder.request_keyframe();
+                            }
+                        }
+
+                        // Catch the new client up on the whiteboard
+                        for stroke in &whiteboard.history {
+                            let msg = ServerMessage::WhiteboardStroke(stroke.clone());
+                            if let Ok(data) = serde_json::to_vec(&msg) {
+                                let _ = server.socket.send_to(&data, src_addr);
+                            }
+                        }
+                    }
+                }
+                ClientMessage::PlayerUpdate {
+                    position,
+                    yaw,
+                    pitch,
+                    seated,
+                    velocity,
+                    crouching,
+                    sprinting,
+                    airborne,
+                    afk,
+                } => {
+                    // Update player state and activity timestamp
+                    if let Some(&player_id) = server.clients.get(&src_addr) {
+                        server.client_last_activity.insert(src_addr, now);
+                        let mut budget = server
+                            .move_budgets
+                            .get(&src_addr)
+                            .copied()
+                            .unwrap_or_else(|| MoveBudget::new(now));
+                        let mut correction = None;
+                        if let Some(state) = server.player_states.get_mut(&player_id) {
+                            let allowed = validate_move(
+                                state,
+                                &mut budget,
+                                position,
+                                seated,
+                                now,
+                                &zones,
+                                &seats,
+                            );
+                            if Vec3::from(allowed).distance(Vec3::from(position)) > MOVE_TOLERANCE {
+                                correction = Some(allowed);
+                            }
+                            state.position = allowed;
+                            state.yaw = yaw;
+                            state.pitch = pitch;
+                            state.seated = seated;
+                            state.velocity = velocity;
+                            state.crouching = crouching;
+                            state.sprinting = sprinting;
+                            state.airborne = airborne;
+                            state.afk = afk;
                         }
-                        ClientMessage::PlayerUpdate { position, yaw, pitch } => {
-                            // Update player state and activity timestamp
-                            if let Some(&player_id) = server.clients.get(&src_addr) {
-                                server.client_last_activity.insert(src_addr, Instant::now());
-                                if let Some(state) = server.player_states.get_mut(&player_id) {
-                                    state.position = position;
-                                    state.yaw = yaw;
-                                    state.pitch = pitch;
+                        server.move_budgets.insert(src_addr, budget);
+                        // Put the client back where everyone else sees it
+                        if let Some(position) = correction {
+                            let msg = ServerMessage::PositionCorrection { position };
+                            if let Ok(data) = serde_json::to_vec(&msg) {
+                                let _ = server.socket.send_to(&data, src_addr);
+                            }
+                        }
+                    }
+                }
+                ClientMessage::WhiteboardStroke { points } => {
+                    if let Some(&player_id) = server.clients.get(&src_addr) {
+                        let stroke = WhiteboardStroke {
+                            player: player_id,
+                            points,
+                        };
+                        // Relay to everyone but the player who drew it
+                        let msg = ServerMessage::WhiteboardStroke(stroke.clone());
+                        if let Ok(data) = serde_json::to_vec(&msg) {
+                            for &client_addr in server.clients.keys() {
+                                if client_addr != src_addr {
+                                    let _ = server.socket.send_to(&data, client_addr);
                                 }
                             }
                         }
-                        ClientMessage::Leave => {
-                            // Client leaving gracefully
-                            if server.clients.contains_key(&src_addr) {
-                                players_to_remove.push(src_addr);
+                        whiteboard.incoming.push(stroke);
+                    }
+                }
+                ClientMessage::ToggleDoor { door } => {
+                    if server.clients.contains_key(&src_addr) {
+                        door_states.toggle(door);
+                    }
+                }
+                ClientMessage::RequestPosterImage { hash, chunks } => {
+                    if !server.clients.contains_key(&src_addr) {
+                        continue;
+                    }
+                    let Some(file) = poster_cache.files.get(&hash) else {
+                        continue;
+                    };
+                    let total_chunks = file.total_chunks();
+                    let chunks = if chunks.is_empty() {
+                        (0..total_chunks).collect()
+                    } else {
+                        chunks
+                    };
+                    // Chunks the poster doesn't have are dropped, and so are
+                    // any past what the client may have queued
+                    let queued = server
+                        .poster_uploads
+                        .iter()
+                        .filter(|(addr, _, _)| *addr == src_addr)
+                        .count();
+                    let room = MAX_QUEUED_POSTER_CHUNKS.saturating_sub(queued);
+                    server.poster_uploads.extend(
+                        chunks
+                            .into_iter()
+                            .filter(|&idx| idx < total_chunks)
+                            .take(room)
+                            .map(|idx| (src_addr, hash, idx)),
+                    );
+                }
+                ClientMessage::Emote { emote } => {
+                    if let Some(&player_id) = server.clients.get(&src_addr) {
+                        // Relay to everyone but the player who emoted
+                        let msg = ServerMessage::Emote {
+                            player: player_id,
+                            emote,
+                        };
+                        if let Ok(data) = serde_json::to_vec(&msg) {
+                            for &client_addr in server.clients.keys() {
+                                if client_addr != src_addr {
+                                    let _ = server.socket.send_to(&data, client_addr);
+                                }
                             }
                         }
+                        emote_events.send(EmoteEvent {
+                            player: player_id,
+                            emote,
+                        });
                     }
                 }
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
-            Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+                ClientMessage::SetNickname { nickname } => {
+                    if let Some(&player_id) = server.clients.get(&src_addr) {
+                        if let Some(state) = server.player_states.get_mut(&player_id) {
+                            state.nickname = clean_nickname(&nickname);
+                        }
+                    }
+                }
+                ClientMessage::Pong { sequence } => {
+                    let (last_sequence, sent_at) = server.last_ping;
+                    // Answers to older pings arrive too late to trust
+                    if sequence == last_sequence && server.clients.contains_key(&src_addr) {
+                        let rtt_ms = sent_at.elapsed().as_millis() as u32;
+                        server.client_pings.insert(src_addr, rtt_ms);
+                    }
+                    // Clients still loading only answer pings, which keeps them connected
+                    if server.clients.contains_key(&src_addr) {
+                        server.client_last_activity.insert(src_addr, Instant::now());
+                    }
+                }
+                ClientMessage::Leave => {
+                    // Client leaving gracefully
+                    if server.clients.contains_key(&src_addr) {
+                        players_to_remove.push(src_addr);
+                    }
+                }
+            },
+            Received::Error(e) if e.kind() == std::io::ErrorKind::ConnectionReset => {
                 // Client forcibly disconnected - this is expected on Windows
                 // The timeout system will clean up the client
                 continue;
             }
-            Err(e) => {
+            Received::Error(e) => {
                 error!("Server receive error: {}", e);
                 break;
             }
         }
     }
+    if handled == MAX_DATAGRAMS_PER_FRAME {
+        server.packet_drops.capped_frames += 1;
+    }
 
     // Remove players who sent Leave messages
     for addr in players_to_remove {
@@ -258,6 +984,59 @@ fn receive_client_messages(
     }
 }
 
+/// Where a player that was at `state` may be after claiming to move to
+/// `position` now. Moves too fast are clamped to the fastest `budget` allows,
+/// and moves out of the room's zones are refused.
+fn validate_move(
+    state: &PlayerState,
+    budget: &mut MoveBudget,
+    position: [f32; 3],
+    seated: bool,
+    now: Instant,
+    zones: &Query<(&Zone, &GlobalTransform)>,
+    seats: &Query<&GlobalTransform, With<Seat>>,
+) -> [f32; 3] {
+    let from = Vec3::from(state.position);
+    let to = Vec3::from(position);
+    let secs = budget.refill(now);
+    let max_ground = MAX_GROUND_SPEED * secs;
+    let max_rise = MAX_RISE_SPEED * secs;
+
+    // Sitting down or standing up may jump to or from a seat, if there is one
+    let snapping = seated != state.seated
+        && seats
+            .iter()
+            .any(|seat| seat.translation().distance(to) <= SEAT_SNAP_DISTANCE);
+    let slack = if snapping {
+        SEAT_SNAP_DISTANCE
+    } else {
+        budget.slack
+    };
+
+    // Falling is left to the zones to bound
+    let ground = Vec2::new(to.x - from.x, to.z - from.z).clamp_length_max(max_ground + slack);
+    let rise = (to.y - from.y).min(max_rise + slack);
+    if !snapping {
+        let over = (ground.length() - max_ground).max(rise - max_rise);
+        budget.slack -= over.max(0.0);
+    }
+    let allowed = from + Vec3::new(ground.x, rise, ground.y);
+    if allowed.distance(to) > 0.01 {
+        debug!("Player {} moved too fast, clamping", state.id);
+    }
+
+    // Rooms without zones have no bounds to keep
+    let in_room = zones.is_empty()
+        || zones
+            .iter()
+            .any(|(zone, transform)| zone.contains(transform, allowed));
+    if !in_room {
+        debug!("Player {} moved out of the room, ignoring", state.id);
+        return state.position;
+    }
+    allowed.into()
+}
+
 /// Helper to remove a client and notify others.
 fn remove_client(
     server: &mut GameServer,
@@ -266,10 +1045,38 @@ fn remove_client(
 ) {
     if let Some(player_id) = server.clients.remove(&addr) {
         server.client_last_activity.remove(&addr);
-        server.player_states.remove(&player_id);
+        server.move_budgets.remove(&addr);
+        server.afk_since.remove(&addr);
+        server.client_codecs.remove(&addr);
+        server.client_audio_codecs.remove(&addr);
+        server.client_pings.remove(&addr);
+        let state = server.player_states.remove(&player_id);
+        let nickname = state
+            .as_ref()
+            .map(|state| state.nickname.clone())
+            .unwrap_or_default();
+        let token = server.rejoin_tokens.remove(&addr);
+        if let (Some(state), Some(token)) = (state, token) {
+            server.departed.push(DepartedPlayer {
+                ip: addr.ip(),
+                token,
+                state,
+                left_at: Instant::now(),
+            });
+        }
+        server.client_sent_states.remove(&addr);
+        for sent in server.client_sent_states.values_mut() {
+            sent.remove(&player_id);
+        }
+        server.poster_uploads.retain(|(client_addr, _, _)| *client_addr != addr);
 
         info!("Player {} left", player_id);
-        notifications.send(NotificationEvent("A user has left".to_string()));
+        let left = if nickname.is_empty() {
+            "A user has left".to_string()
+        } else {
+            format!("{} has left", nickname)
+        };
+        notifications.send(NotificationEvent(left));
 
         // Notify remaining clients
         let msg = ServerMessage::PlayerLeft { id: player_id };
@@ -281,12 +1088,58 @@ fn remove_client(
     }
 }
 
+/// Everyone in the session for the player list, host first, then in the
+/// order they joined. Only the host can present for now.
+fn player_info(server: &GameServer, host_id: PlayerId, host_presenting: bool) -> Vec<PlayerInfo> {
+    let pings: HashMap<PlayerId, u32> = server
+        .clients
+        .iter()
+        .filter_map(|(addr, id)| Some((*id, *server.client_pings.get(addr)?)))
+        .collect();
+
+    let mut info: Vec<PlayerInfo> = server
+        .player_states
+        .values()
+        .map(|state| {
+            let host = state.id == host_id;
+            PlayerInfo {
+                id: state.id,
+                nickname: state.nickname.clone(),
+                ping_ms: pings.get(&state.id).copied(),
+                host,
+                presenting: host && host_presenting,
+                afk: state.afk,
+                color: state.color,
+            }
+        })
+        .collect();
+    info.sort_by_key(|player| (!player.host, player.id));
+    info
+}
+
+/// System to ping every client, measuring round trip times for the player list.
+fn ping_clients(mut server: ResMut<GameServer>) {
+    if server.last_ping.1.elapsed() < PING_INTERVAL {
+        return;
+    }
+    let sequence = server.last_ping.0.wrapping_add(1);
+    server.last_ping = (sequence, Instant::now());
+
+    let msg = ServerMessage::Ping { sequence };
+    if let Ok(data) = serde_json::to_vec(&msg) {
+        for &client_addr in server.clients.keys() {
+            let _ = server.socket.send_to(&data, client_addr);
+        }
+    }
+}
+
 /// Check for clients that haven't sent updates and remove them.
 fn check_client_timeouts(
     mut server: ResMut<GameServer>,
+    session: Res<SessionSettings>,
     mut notifications: EventWriter<NotificationEvent>,
 ) {
-    let timeout = Duration::from_secs(CLIENT_TIMEOUT_SECS);
+    let timeout = Duration::from_secs(session.client_timeout_secs as u64);
     let now = Instant::now();
 
     let timed_out: Vec<SocketAddr> = server
@@ -299,42 +1152,419 @@ fn check_client_timeouts(
     for addr in timed_out {
         remove_client(&mut server, addr, &mut notifications);
     }
+
+    // Note when players went away, and remove those away too long
+    let server = &mut *server;
+    let afk_since = &mut server.afk_since;
+    for (addr, player_id) in &server.clients {
+        if server.player_states.get(player_id).is_some_and(|s| s.afk) {
+            afk_since.entry(*addr).or_insert(now);
+        } else {
+            afk_since.remove(addr);
+        }
+    }
+    if session.afk_kick_mins > 0 {
+        let afk_limit = Duration::from_secs(session.afk_kick_mins as u64 * 60);
+        let away_too_long: Vec<SocketAddr> = afk_since
+            .iter()
+            .filter(|(_, since)| now.duration_since(**since) > afk_limit)
+            .map(|(addr, _)| *addr)
+            .collect();
+        for addr in away_too_long {
+            let reason = format!(
+                "You were removed from the session for being away over {} minutes",
+                session.afk_kick_mins
+            );
+            kick_client(server, addr, &reason, &mut notifications);
+        }
+    }
+
+    let grace = Duration::from_secs(session.rejoin_grace_secs as u64);
+    server
+        .departed
+        .retain(|departed| now.duration_since(departed.left_at) <= grace);
+}
+
+/// Tell a client why it's being removed, then remove it.
+fn kick_client(
+    server: &mut GameServer,
+    addr: SocketAddr,
+    reason: &str,
+    notifications: &mut EventWriter<NotificationEvent>,
+) {
+    let msg = ServerMessage::Kicked {
+        reason: reason.to_string(),
+    };
+    if let Ok(data) = serde_json::to_vec(&msg) {
+        let _ = server.socket.send_to(&data, addr);
+    }
+    info!("Removing {}: {}", addr, reason);
+    remove_client(server, addr, notifications);
+}
+
+/// Console command removing a player from the session by their id, as
+/// listed by `net stats`.
+fn kick_command(
+    In(args): In<Vec<String>>,
+    server: Option<ResMut<GameServer>>,
+    mut notifications: EventWriter<NotificationEvent>,
+) -> String {
+    let Some(mut server) = server else {
+        return "Only the host can remove players".to_string();
+    };
+    let Some(id) = args.first().and_then(|id| id.parse::<PlayerId>().ok()) else {
+        return "Usage: kick <id>".to_string();
+    };
+    let addr = server
+        .clients
+        .iter()
+        .find(|(_, client)| **client == id)
+        .map(|(addr, _)| *addr);
+    let Some(addr) = addr else {
+        return format!("No player {}", id);
+    };
+    let reason = "The host removed you from the session";
+    kick_client(&mut server, addr, reason, &mut notifications);
+    format!("Removed player {}", id)
 }
 
 fn update_host_player_state(
     mut server: ResMut<GameServer>,
-    player_query: Query<(&Transform, &crate::player::CameraController), With<Player>>,
+    player_query: Query<
+        (
+            &Transform,
+            &crate::player::CameraController,
+            &Velocity,
+            &Grounded,
+            Has<Seated>,
+            Has<Crouching>,
+            Has<Sprinting>,
+        ),
+        With<Player>,
+    >,
     local_id: Res<LocalPlayerId>,
+    nickname: Res<Nickname>,
+    afk: Res<Afk>,
 ) {
-    if let Ok((transform, camera_controller)) = player_query.get_single() {
+    if nickname.is_changed() {
+        if let Some(state) = server.player_states.get_mut(&local_id.0) {
+            state.nickname = clean_nickname(&nickname.0);
+        }
+    }
+
+    if let Ok((transform, camera_controller, velocity, grounded, seated, crouching, sprinting)) =
+        player_query.get_single()
+    {
         if let Some(state) = server.player_states.get_mut(&local_id.0) {
             state.position = transform.translation.into();
             // Extract yaw from rotation
             let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
             state.yaw = yaw;
             state.pitch = camera_controller.pitch;
+            state.seated = seated;
+            state.velocity = velocity.0.into();
+            state.crouching = crouching;
+            state.sprinting = sprinting;
+            state.airborne = !grounded.0 && !seated;
+            state.afk = afk.afk;
         }
     }
 }
 
+/// How many state updates apart a viewer hears about another player: every
+/// one for those close by, fewer for those further away or behind them.
+fn interest_interval(viewer: &PlayerState, other: &PlayerState, slow: bool) -> u64 {
+    let offset = Vec3::from(other.position) - Vec3::from(viewer.position);
+    let distance = offset.length();
+    let forward = Quat::from_rotation_y(viewer.yaw) * Vec3::NEG_Z;
+    let in_front = offset.normalize_or_zero().dot(forward) > -0.5;
+
+    let interval = if distance < NEAR_INTEREST_DISTANCE {
+        1
+    } else if distance < MID_INTEREST_DISTANCE && in_front {
+        MID_INTEREST_INTERVAL
+    } else {
+        FAR_INTEREST_INTERVAL
+    };
+    if slow {
+        interval * 2
+    } else {
+        interval
+    }
+}
+
 fn broadcast_game_state(
     time: Res<Time>,
     mut timer: ResMut<ServerSyncTimer>,
-    server: Res<GameServer>,
+    mut server: ResMut<GameServer>,
+    local_id: Res<LocalPlayerId>,
+    capture_target: Option<Res<CaptureTarget>>,
+    mut player_list: ResMut<PlayerList>,
+    screen_layout: Res<ScreenLayout>,
+    door_states: Res<DoorStates>,
+    poster_assignments: Res<PosterAssignments>,
+    mut world_changed: Local<bool>,
 ) {
+    // Noted every frame, since changes between updates would go unseen
+    *world_changed |=
+        door_states.is_changed() || screen_layout.is_changed() || poster_assignments.is_changed();
     timer.0.tick(time.delta());
     if !timer.0.just_finished() {
         return;
     }
 
-    let players: Vec<PlayerState> = server.player_states.values().cloned().collect();
-    let msg = ServerMessage::GameState { players };
+    server.state_tick += 1;
+    let tick = server.state_tick;
+    if tick % FULL_STATE_INTERVAL == 0 {
+        for sent in server.client_sent_states.values_mut() {
+            sent.clear();
+        }
+    }
+    let info = player_info(&server, local_id.0, capture_target.is_some());
+    player_list.players = info.clone();
+    let info_changed = server.sent_info != info;
+    if info_changed {
+        server.sent_info = info.clone();
+    }
+
+    // Sent on change, and to everyone every so often so late joiners and
+    // lost packets catch up
+    let mut world_messages = vec![ServerMessage::Doors {
+        open: door_states.open_list(),
+    }];
+    if !screen_layout.screens.is_empty() {
+        world_messages.push(ServerMessage::ScreenLayout {
+            screens: screen_layout.screens.clone(),
+        });
+    }
+    if !poster_assignments.posters.is_empty() {
+        world_messages.push(ServerMessage::Posters {
+            posters: poster_assignments.posters.clone(),
+        });
+    }
+    let world_data: Vec<Vec<u8>> = world_messages
+        .iter()
+        .filter_map(|msg| serde_json::to_vec(msg).ok())
+        .collect();
+
+    // Each client hears about the players that changed, sooner the more
+    // they matter to it
+    let server = &mut *server;
+    for (addr, viewer_id) in &server.clients {
+        let (Some(viewer), Some(sent)) = (
+            server.player_states.get(viewer_id),
+            server.client_sent_states.get_mut(addr),
+        ) else {
+            continue;
+        };
+        // New clients and full snapshots start from nothing
+        let refresh = sent.is_empty();
+        let slow = server
+            .client_pings
+            .get(addr)
+            .is_some_and(|&ping| ping > SLOW_CLIENT_PING_MS);
+
+        let mut players = Vec::new();
+        let mut deltas = Vec::new();
+        for state in server.player_states.values() {
+            if state.id == *viewer_id {
+                continue;
+            }
+            match sent.get_mut(&state.id) {
+                None => {
+                    players.push(state.clone());
+                    sent.insert(state.id, state.clone());
+                }
+                Some(last) if last == state => {}
+                Some(last) => {
+                    let interval = interest_interval(viewer, state, slow);
+                    // Offset by id, so players due at a rate don't all go out together
+                    if tick % interval == state.id % interval {
+                        deltas.push(PlayerStateDelta::between(last, state));
+                        *last = state.clone();
+                    }
+                }
+            }
+        }
+
+        let info = (info_changed || refresh).then(|| info.clone());
+        if !players.is_empty() || !deltas.is_empty() || info.is_some() {
+            let msg = ServerMessage::GameState {
+                players,
+                deltas,
+                info,
+            };
+            if let Ok(data) = serde_json::to_vec(&msg) {
+                let _ = server.socket.send_to(&data, addr);
+            }
+        }
 
+        if *world_changed || refresh {
+            for data in &world_data {
+                let _ = server.socket.send_to(data, addr);
+            }
+        }
+    }
+    *world_changed = false;
+}
+
+/// Send the host's whiteboard strokes, and clears, to all clients.
+fn broadcast_whiteboard(
+    server: Res<GameServer>,
+    mut whiteboard: ResMut<Whiteboard>,
+    local_id: Res<LocalPlayerId>,
+) {
+    if !whiteboard.clear_unsent && whiteboard.outgoing.is_empty() {
+        return;
+    }
+
+    let mut messages = Vec::new();
+    if whiteboard.clear_unsent {
+        whiteboard.clear_unsent = false;
+        messages.push(ServerMessage::WhiteboardClear);
+    }
+    for points in whiteboard.outgoing.drain(..) {
+        messages.push(ServerMessage::WhiteboardStroke(WhiteboardStroke {
+            player: local_id.0,
+            points,
+        }));
+    }
+
+    for msg in messages {
+        if let Ok(data) = serde_json::to_vec(&msg) {
+            for &client_addr in server.clients.keys() {
+                let _ = server.socket.send_to(&data, client_addr);
+            }
+        }
+    }
+}
+
+/// Send a few of the poster image chunks clients asked for.
+fn send_poster_uploads(mut server: ResMut<GameServer>, poster_cache: Res<PosterCache>) {
+    let count = server.poster_uploads.len().min(POSTER_CHUNKS_PER_FRAME);
+    let uploads: Vec<_> = server.poster_uploads.drain(..count).collect();
+
+    for (client_addr, hash, chunk_idx) in uploads {
+        let Some(chunk) = poster_cache
+            .files
+            .get(&hash)
+            .and_then(|file| file.chunk(hash, chunk_idx))
+        else {
+            continue;
+        };
+        let msg = ServerMessage::PosterImage(chunk);
+        if let Ok(data) = serde_json::to_vec(&msg) {
+            let _ = server.socket.send_to(&data, client_addr);
+        }
+    }
+}
+
+/// Send the host's emotes to all clients.
+fn broadcast_emotes(
+    server: Res<GameServer>,
+    local_id: Res<LocalPlayerId>,
+    mut emote_events: EventReader<EmoteEvent>,
+) {
+    for event in emote_events.read() {
+        // Client emotes were relayed when they arrived
+        if event.player != local_id.0 {
+            continue;
+        }
+        let msg = ServerMessage::Emote {
+            player: event.player,
+            emote: event.emote,
+        };
+        if let Ok(data) = serde_json::to_vec(&msg) {
+            for &client_addr in server.clients.keys() {
+                let _ = server.socket.send_to(&data, client_addr);
+            }
+        }
+    }
+}
+
+/// Pick the best codec supported by the encoder and all clients, switching when it changes.
+fn negotiate_video_codec(
+    mut server: ResMut<GameServer>,
+    encoder: Option<Res<VideoEncoder>>,
+    latest_frame: Res<LatestCapturedFrame>,
+) {
+    let Some(encoder) = encoder else {
+        return;
+    };
+
+    let best = VideoCodecKind::negotiate(
+        &encoder.supported_codecs(),
+        server.client_codecs.values().map(|codecs| codecs.as_slice()),
+    );
+    if best == server.active_codec {
+        return;
+    }
+
+    info!(
+        "Negotiated video codec: {} (was {})",
+        best.name(),
+        server.active_codec.name()
+    );
+    server.active_codec = best;
+    encoder.set_codec(best);
+
+    let msg = ServerMessage::VideoCodec(VideoCodecInfo {
+        codec: best,
+        width: latest_frame.width,
+        height: latest_frame.height,
+        fps: 60,
+        extradata: Vec::new(),
+    });
+    if let Ok(data) = serde_json::to_vec(&msg) {
+        for &client_addr in server.clients.keys() {
+            let _ = server.socket.send_to(&data, client_addr);
+        }
+    }
+}
+
+/// Tell clients the codec, size and parameter sets of a new encoder's
+/// stream, so they size the screen and prime the decoder before it arrives.
+fn announce_video_stream(server: &mut GameServer, info: VideoCodecInfo) {
+    info!(
+        "Announcing {} stream at {}x{} ({} bytes of parameter sets)",
+        info.codec.name(),
+        info.width,
+        info.height,
+        info.extradata.len()
+    );
+    let msg = ServerMessage::VideoCodec(info.clone());
     if let Ok(data) = serde_json::to_vec(&msg) {
         for &client_addr in server.clients.keys() {
             let _ = server.socket.send_to(&data, client_addr);
         }
     }
+    server.stream_info = Some(info);
+    // The old stream's keyframe won't decode with the new parameter sets
+    server.last_keyframe.clear();
+}
+
+/// Use Opus when the host and all clients support it, PCM otherwise.
+/// Audio chunks carry their codec, so clients need no separate notice.
+fn negotiate_audio_codec(mut server: ResMut<GameServer>, encoder: Option<Res<AudioEncoder>>) {
+    let Some(encoder) = encoder else {
+        return;
+    };
+
+    let best = AudioCodecKind::negotiate(
+        &AudioCodecKind::supported(),
+        server.client_audio_codecs.values().map(|codecs| codecs.as_slice()),
+    );
+    if best == server.active_audio_codec {
+        return;
+    }
+
+    info!(
+        "Negotiated audio codec: {} (was {})",
+        best.name(),
+        server.active_audio_codec.name()
+    );
+    server.active_audio_codec = best;
+    encoder.set_codec(best);
 }
 
 /// Tracks the last frame number we submitted for encoding.
@@ -343,76 +1573,195 @@ pub struct LastStreamedFrame(pub u64);
 
 /// Broadcast video frames using H.264 encoding
 fn broadcast_video_frames(
-    server: Res<GameServer>,
+    mut server: ResMut<GameServer>,
     latest_frame: Option<Res<LatestCapturedFrame>>,
     mut stream_state: ResMut<ScreenStreamState>,
     mut last_streamed: ResMut<LastStreamedFrame>,
+    clock: Res<StreamClock>,
     encoder: Option<Res<VideoEncoder>>,
     sender: Option<Res<VideoSender>>,
+    recorder: Option<Res<SessionRecorder>>,
+    mut preview: Option<ResMut<LocalPreview>>,
+    video_settings: Res<VideoSettings>,
+    mut counters: ResMut<StreamCounters>,
+    #[cfg(feature = "web-spectators")] spectators: Option<Res<WebSpectators>>,
+    mut last_watched: Local<Option<Instant>>,
+    mut applied_quality: Local<Option<StreamQuality>>,
 ) {
-    use std::sync::atomic::{AtomicU32, Ordering};
-    use std::time::Instant;
-    use std::sync::Mutex;
-
-    static SUBMITTED_FPS: AtomicU32 = AtomicU32::new(0);
-    static SENT_FPS: AtomicU32 = AtomicU32::new(0);
-    static LAST_LOG: Mutex<Option<Instant>> = Mutex::new(None);
-
     let Some(encoder) = encoder else {
         return;
     };
 
+    // Keep the stream within the upload limit, split between the viewers. A
+    // join lowering the bitrate restarts the encoder together with the
+    // keyframe it asks for, and a leave raises it at the next restart
+    let quality = video_settings
+        .upload_limit
+        .stream_quality(server.clients.len());
+    if encoder.is_added() || *applied_quality != Some(quality) {
+        encoder.set_quality(quality);
+        *applied_quality = Some(quality);
+    }
+
     let Some(sender) = sender else {
         return;
     };
 
+    // A preview turned on mid-stream starts like a joining client
+    if let Some(preview) = preview.as_mut().filter(|preview| preview.is_added()) {
+        if let Some(info) = server.stream_info.clone() {
+            preview.set_codec_info(info);
+        }
+        encoder.request_keyframe();
+    }
+
+    // With nobody watching or recording, encoding pauses after a grace period
+    // and the encoder shuts down once it's been idle a while. The host's own
+    // preview counts as watching
+    let now = Instant::now();
+    if !server.clients.is_empty() || recorder.is_some() || preview.is_some() {
+        *last_watched = Some(now);
+    }
+    #[cfg(feature = "web-spectators")]
+    if spectators.as_ref().is_some_and(|s| s.is_watched()) {
+        *last_watched = Some(now);
+    }
+    let watched = last_watched.is_some_and(|at| now - at < STREAM_PAUSE_DELAY);
+    if !watched && !server.last_keyframe.is_empty() {
+        // Too old to show anyone joining once encoding picks up again
+        server.last_keyframe.clear();
+    }
+
     // Submit new frames for encoding - no interval gating, let encoder handle it
-    if let Some(ref latest_frame) = latest_frame {
+    if let Some(latest_frame) = latest_frame.as_ref().filter(|_| watched) {
         let has_data = !latest_frame.rgba.is_empty();
         let is_new = latest_frame.frame_number != last_streamed.0;
 
         if has_data && is_new {
+            let captured_at = latest_frame.captured_at.unwrap_or_else(Instant::now);
             encoder.submit_frame(
                 latest_frame.rgba.clone(),
                 latest_frame.width,
                 latest_frame.height,
+                clock.pts_ms(captured_at),
             );
             last_streamed.0 = latest_frame.frame_number;
-            SUBMITTED_FPS.fetch_add(1, Ordering::Relaxed);
+            counters.captured_frames += 1;
         }
     }
 
-    // Check for encoded video and send
-    if server.clients.is_empty() {
-        return;
-    }
-
-    if let Some(encoded) = encoder.get_encoded() {
+    // Check for encoded video and send. Frames encoded while a recording runs
+    // with nobody watching are still taken, so they don't pile up
+    if let Some(mut encoded) = encoder.get_encoded() {
+        // Announced on the main socket, so it's out before the frame's chunks
+        if let Some(info) = encoded.codec_info.take() {
+            if let Some(preview) = preview.as_mut() {
+                preview.set_codec_info(info.clone());
+            }
+            announce_video_stream(&mut server, info);
+        }
+        // Address the stream to the screen the host is showing it on
+        let screen_id = latest_frame.as_ref().map_or(0, |frame| frame.screen_id);
+        for chunk in &mut encoded.chunks {
+            chunk.screen_id = screen_id;
+        }
+        if encoded.chunks.first().is_some_and(|c| c.is_keyframe) {
+            server.last_keyframe = encoded.chunks.clone();
+        }
+        if let Some(preview) = preview.as_mut() {
+            for chunk in &encoded.chunks {
+                preview.add_chunk(chunk.clone());
+            }
+        }
+        // Browsers are only offered H.264
+        #[cfg(feature = "web-spectators")]
+        if let Some(spectators) = spectators.as_ref() {
+            if server.active_codec == VideoCodecKind::H264 {
+                spectators.send_frame(&encoded.chunks);
+            }
+        }
+        if server.clients.is_empty() {
+            return;
+        }
         let clients: Vec<SocketAddr> = server.clients.keys().cloned().collect();
-        SENT_FPS.fetch_add(1, Ordering::Relaxed);
+        counters.encoded_frames += 1;
+        counters.sent_bytes += encoded
+            .chunks
+            .iter()
+            .map(|chunk| chunk.data.len())
+            .sum::<usize>()
+            * clients.len();
         sender.submit_chunks(encoded.chunks, clients);
         stream_state.frame_id = stream_state.frame_id.wrapping_add(1);
     }
+}
 
-    // Log FPS every second
-    let should_log = {
-        let mut last = LAST_LOG.lock().unwrap();
-        match *last {
-            None => {
-                *last = Some(Instant::now());
-                false
-            }
-            Some(t) if t.elapsed().as_secs() >= 1 => {
-                *last = Some(Instant::now());
-                true
-            }
-            _ => false,
-        }
+/// Capture only the shared window's audio when a window is shared,
+/// and the whole system mix when a display is shared.
+fn follow_capture_source_audio(
+    mut commands: Commands,
+    mut events: EventReader<CaptureSource>,
+    audio_capture: Option<Res<AudioCapture>>,
+) {
+    let Some(event) = events.read().last() else {
+        return;
+    };
+    // Audio capture failed to start at all on this platform
+    let Some(audio_capture) = audio_capture else {
+        return;
     };
 
-    if should_log {
-        SUBMITTED_FPS.swap(0, Ordering::Relaxed);
-        SENT_FPS.swap(0, Ordering::Relaxed);
+    let target = match event.source {
+        CaptureSourceType::Display(_) => AudioCaptureTarget::System,
+        CaptureSourceType::Window(hwnd) => AudioCaptureTarget::Window(hwnd),
+    };
+    if audio_capture.target == target {
+        return;
+    }
+
+    info!("Switching audio capture to {:?}", target);
+    match AudioCapture::new(target, audio_capture.device.as_deref()) {
+        Some(capture) => commands.insert_resource(capture),
+        None => warn!("Failed to switch audio capture, keeping {:?}", audio_capture.target),
+    }
+}
+
+/// Restart audio capture on the device chosen in the settings.
+fn apply_capture_device_setting(
+    mut commands: Commands,
+    audio_settings: Res<AudioSettings>,
+    audio_capture: Option<Res<AudioCapture>>,
+) {
+    if !audio_settings.is_changed() {
+        return;
+    }
+    let Some(audio_capture) = audio_capture else {
+        return;
+    };
+    if audio_capture.device == audio_settings.capture_device {
+        return;
+    }
+
+    info!("Switching audio capture device to {:?}", audio_settings.capture_device);
+    match AudioCapture::new(audio_capture.target, audio_settings.capture_device.as_deref()) {
+        Some(capture) => commands.insert_resource(capture),
+        None => warn!("Failed to switch audio capture device, keeping {:?}", audio_capture.device),
+    }
+}
+
+/// System to pass a changed audio bitrate setting to the encoder.
+fn apply_audio_bitrate_setting(
+    audio_settings: Res<AudioSettings>,
+    audio_encoder: Option<Res<AudioEncoder>>,
+    mut applied: Local<Option<u32>>,
+) {
+    let Some(encoder) = audio_encoder else {
+        return;
+    };
+    let kbps = audio_settings.audio_bitrate_kbps;
+    if *applied != Some(kbps) {
+        *applied = Some(kbps);
+        encoder.set_bitrate(kbps as i32 * 1000);
     }
 }
 
@@ -422,15 +1771,11 @@ fn broadcast_audio_frames(
     audio_capture: Option<Res<AudioCapture>>,
     audio_encoder: Option<Res<AudioEncoder>>,
     audio_sender: Option<Res<AudioSender>>,
+    clock: Res<StreamClock>,
+    audio_settings: Res<AudioSettings>,
+    recorder: Option<Res<SessionRecorder>>,
+    mut counters: ResMut<StreamCounters>,
 ) {
-    use std::sync::atomic::{AtomicU32, Ordering};
-    use std::time::Instant;
-    use std::sync::Mutex;
-
-    static CAPTURED_COUNT: AtomicU32 = AtomicU32::new(0);
-    static SENT_COUNT: AtomicU32 = AtomicU32::new(0);
-    static LAST_LOG: Mutex<Option<Instant>> = Mutex::new(None);
-
     let Some(capture) = audio_capture else {
         return;
     };
@@ -445,8 +1790,18 @@ fn broadcast_audio_frames(
 
     // Capture audio samples and submit for encoding
     while let Some(samples) = capture.try_recv() {
-        CAPTURED_COUNT.fetch_add(1, Ordering::Relaxed);
-        encoder.submit_samples(samples, capture.sample_rate, capture.channels);
+        // Keep draining capture while audio is switched off so it doesn't back up
+        if !audio_settings.stream_audio {
+            continue;
+        }
+        // Samples were captured just now, so the first one is a packet-length ago
+        let samples_per_sec = (capture.sample_rate as u64 * capture.channels as u64).max(1);
+        let duration_ms = samples.len() as u64 * 1000 / samples_per_sec;
+        let pts_ms = clock.now_ms().saturating_sub(duration_ms);
+        if let Some(recorder) = &recorder {
+            recorder.record_audio(&samples, capture.sample_rate, capture.channels);
+        }
+        encoder.submit_samples(samples, capture.sample_rate, capture.channels, pts_ms);
     }
 
     // Don't send if no clients
@@ -456,38 +1811,281 @@ fn broadcast_audio_frames(
 
     // Get encoded audio and send
     while let Some(encoded) = encoder.get_encoded() {
+        let bytes = encoded.data.len();
         let chunk = AudioChunk::new(
             encoded.sequence,
             encoded.sample_rate,
             encoded.channels,
+            encoded.codec,
+            encoded.pts_ms,
             encoded.data,
         );
         let clients: Vec<SocketAddr> = server.clients.keys().cloned().collect();
+        counters.sent_bytes += bytes * clients.len();
         sender.send(chunk, clients);
-        SENT_COUNT.fetch_add(1, Ordering::Relaxed);
     }
+}
 
-    // Log audio stats every second
-    let should_log = {
-        let mut last = LAST_LOG.lock().unwrap();
-        match *last {
-            None => {
-                *last = Some(Instant::now());
-                false
-            }
-            Some(t) if t.elapsed().as_secs() >= 1 => {
-                *last = Some(Instant::now());
-                true
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::test_support::{headless_app, loopback_socket, run_until, FakeClient};
+
+    /// A headless app hosting on loopback, running the systems that handle
+    /// clients. Returns the address clients reach it at.
+    fn host() -> (App, SocketAddr) {
+        let mut app = headless_app();
+        let socket = loopback_socket();
+        let addr = socket.local_addr().unwrap();
+        let receiver = SocketReceiver::spawn(&socket, RECEIVE_BUFFER_SIZE, None, None).unwrap();
+        let server = GameServer::new(
+            socket,
+            addr.port(),
+            receiver,
+            RoomId::default(),
+            AvatarChoice::default(),
+            "Host",
+        );
+        app.insert_resource(server)
+            .insert_resource(LocalPlayerId(0))
+            .insert_resource(ServerSyncTimer(Timer::new(
+                Duration::from_millis(50),
+                TimerMode::Repeating,
+            )))
+            .init_resource::<PlayerList>()
+            .init_resource::<Whiteboard>()
+            .init_resource::<DoorStates>()
+            .init_resource::<PosterCache>()
+            .init_resource::<ScreenLayout>()
+            .init_resource::<PosterAssignments>()
+            .init_resource::<LatestCapturedFrame>()
+            .init_resource::<SessionSettings>()
+            .add_systems(
+                Update,
+                (
+                    receive_client_messages,
+                    check_client_timeouts,
+                    broadcast_game_state,
+                )
+                    .chain(),
+            );
+        (app, addr)
+    }
+
+    /// Join as `nickname` and wait to be welcomed. The player's id and
+    /// where they start.
+    fn join(app: &mut App, client: &mut FakeClient, nickname: &str) -> (PlayerId, [f32; 3]) {
+        client.join(nickname);
+        join_reply(app, client)
+    }
+
+    /// The id and spawn position the server welcomes `client` with.
+    fn join_reply(app: &mut App, client: &mut FakeClient) -> (PlayerId, [f32; 3]) {
+        client
+            .wait_for(app, |msg| match msg {
+                ServerMessage::Welcome { your_id, spawn, .. } => {
+                    Some((*your_id, spawn.as_ref().unwrap().position))
+                }
+                _ => None,
+            })
+            .expect("welcome")
+    }
+
+    fn server(app: &App) -> &GameServer {
+        app.world().resource::<GameServer>()
+    }
+
+    #[test]
+    fn join_is_welcomed_and_caught_up() {
+        let (mut app, addr) = host();
+        let mut client = FakeClient::new(addr);
+        let (id, _) = join(&mut app, &mut client, "Alice");
+
+        assert_eq!(id, 1);
+        assert_eq!(server(&app).clients.get(&client.addr()), Some(&id));
+        let synced = client.wait_for(&mut app, |msg| {
+            matches!(msg, ServerMessage::FullStateSync(_)).then_some(())
+        });
+        assert!(synced.is_some());
+        // The host is in the first update
+        let players = client
+            .wait_for(&mut app, |msg| match msg {
+                ServerMessage::GameState { players, .. } => Some(players.clone()),
+                _ => None,
+            })
+            .expect("game state");
+        assert!(players.iter().any(|p| p.id == 0 && p.nickname == "Host"));
+        assert!(players.iter().all(|p| p.id != id));
+    }
+
+    #[test]
+    fn updates_reach_other_clients() {
+        let (mut app, addr) = host();
+        let mut alice = FakeClient::new(addr);
+        let (alice_id, position) = join(&mut app, &mut alice, "Alice");
+        alice.send(&ClientMessage::PlayerUpdate {
+            position,
+            yaw: 1.0,
+            pitch: 0.25,
+            seated: false,
+            velocity: [0.0; 3],
+            crouching: true,
+            sprinting: false,
+            airborne: false,
+            afk: false,
+        });
+        assert!(run_until(&mut app, |world| {
+            world.resource::<GameServer>().player_states[&alice_id].crouching
+        }));
+
+        let mut bob = FakeClient::new(addr);
+        join(&mut app, &mut bob, "Bob");
+        let seen = bob.wait_for(&mut app, |msg| match msg {
+            ServerMessage::GameState { players, .. } => {
+                players.iter().find(|p| p.id == alice_id).cloned()
             }
-            _ => false,
-        }
-    };
+            _ => None,
+        });
+        let seen = seen.expect("Alice in Bob's updates");
+        assert_eq!(seen.yaw, 1.0);
+        assert_eq!(seen.pitch, 0.25);
+        assert!(seen.crouching);
+        assert_eq!(seen.nickname, "Alice");
+    }
+
+    #[test]
+    fn teleports_are_corrected() {
+        let (mut app, addr) = host();
+        let mut alice = FakeClient::new(addr);
+        let (alice_id, spawn) = join(&mut app, &mut alice, "Alice");
+        let claimed = [spawn[0] + 100.0, spawn[1], spawn[2]];
+        alice.send(&ClientMessage::PlayerUpdate {
+            position: claimed,
+            yaw: 0.0,
+            pitch: 0.0,
+            seated: false,
+            velocity: [0.0; 3],
+            crouching: false,
+            sprinting: false,
+            airborne: false,
+            afk: false,
+        });
+
+        let corrected = alice.wait_for(&mut app, |msg| match msg {
+            ServerMessage::PositionCorrection { position } => Some(*position),
+            _ => None,
+        });
+        let corrected = Vec3::from(corrected.expect("position correction"));
+        let stored = server(&app).player_states[&alice_id].position;
+        assert_eq!(corrected, Vec3::from(stored));
+        assert!(corrected.distance(Vec3::from(spawn)) < 10.0);
+    }
 
-    if should_log {
-        let captured = CAPTURED_COUNT.swap(0, Ordering::Relaxed);
-        let sent = SENT_COUNT.swap(0, Ordering::Relaxed);
-        if captured > 0 || sent > 0 {
-            info!("Server audio - captured: {}/s, sent: {}/s", captured, sent);
+    #[test]
+    fn rapid_updates_earn_no_extra_distance() {
+        let (mut app, addr) = host();
+        let mut alice = FakeClient::new(addr);
+        let (_, spawn) = join(&mut app, &mut alice, "Alice");
+        // Small steps sent all at once, sitting down and standing up each time
+        for step in 1..=40 {
+            alice.send(&ClientMessage::PlayerUpdate {
+                position: [spawn[0] + step as f32 * 0.4, spawn[1], spawn[2]],
+                yaw: 0.0,
+                pitch: 0.0,
+                seated: step % 2 == 1,
+                velocity: [0.0; 3],
+                crouching: false,
+                sprinting: false,
+                airborne: false,
+                afk: false,
+            });
         }
+
+        let corrected = alice.wait_for(&mut app, |msg| match msg {
+            ServerMessage::PositionCorrection { position } => Some(*position),
+            _ => None,
+        });
+        let corrected = Vec3::from(corrected.expect("position correction"));
+        assert!(corrected.distance(Vec3::from(spawn)) < 8.0);
+    }
+
+    #[test]
+    fn leaving_is_told_to_others() {
+        let (mut app, addr) = host();
+        let mut alice = FakeClient::new(addr);
+        let mut bob = FakeClient::new(addr);
+        let (alice_id, _) = join(&mut app, &mut alice, "Alice");
+        join(&mut app, &mut bob, "Bob");
+
+        alice.send(&ClientMessage::Leave);
+        let left = bob.wait_for(&mut app, |msg| match msg {
+            ServerMessage::PlayerLeft { id } => Some(*id),
+            _ => None,
+        });
+        assert_eq!(left, Some(alice_id));
+        assert!(!server(&app).clients.contains_key(&alice.addr()));
+        assert!(!server(&app).player_states.contains_key(&alice_id));
+        assert!(server(&app).clients.contains_key(&bob.addr()));
+    }
+
+    #[test]
+    fn rejoining_takes_the_welcome_token() {
+        let (mut app, addr) = host();
+        let mut alice = FakeClient::new(addr);
+        alice.join("");
+        let (alice_id, token) = alice
+            .wait_for(&mut app, |msg| match msg {
+                ServerMessage::Welcome {
+                    your_id,
+                    rejoin_token,
+                    ..
+                } => Some((*your_id, *rejoin_token)),
+                _ => None,
+            })
+            .expect("welcome");
+        assert!(token.is_some());
+        alice.send(&ClientMessage::Leave);
+        let alice_addr = alice.addr();
+        let left = run_until(&mut app, |world| {
+            let server = world.resource::<GameServer>();
+            !server.clients.contains_key(&alice_addr)
+        });
+        assert!(left);
+
+        // Someone else behind the same address, with the same empty name
+        let mut bob = FakeClient::new(addr);
+        let (bob_id, _) = join(&mut app, &mut bob, "");
+        assert_ne!(bob_id, alice_id);
+
+        let mut alice = FakeClient::new(addr);
+        alice.rejoin("", token);
+        let (rejoined_id, _) = join_reply(&mut app, &mut alice);
+        assert_eq!(rejoined_id, alice_id);
+    }
+
+    #[test]
+    fn silent_clients_time_out() {
+        let (mut app, addr) = host();
+        let mut alice = FakeClient::new(addr);
+        let mut bob = FakeClient::new(addr);
+        let (alice_id, _) = join(&mut app, &mut alice, "Alice");
+        join(&mut app, &mut bob, "Bob");
+
+        // Alice was last heard from longer ago than the timeout
+        let session = app.world().resource::<SessionSettings>();
+        let timeout = Duration::from_secs(session.client_timeout_secs as u64);
+        let last_heard = Instant::now() - timeout - Duration::from_secs(1);
+        app.world_mut()
+            .resource_mut::<GameServer>()
+            .client_last_activity
+            .insert(alice.addr(), last_heard);
+
+        let left = bob.wait_for(&mut app, |msg| match msg {
+            ServerMessage::PlayerLeft { id } => Some(*id),
+            _ => None,
+        });
+        assert_eq!(left, Some(alice_id));
+        assert!(!server(&app).clients.contains_key(&alice.addr()));
+        assert!(server(&app).clients.contains_key(&bob.addr()));
     }
 }
@@ -0,0 +1,142 @@
+//! Pieces for testing the networking without a window: a headless app to run
+//! the host's systems on, and fake clients that are bare sockets speaking the
+//! protocol, so tests see exactly what goes over the wire.
+
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::protocol::{AvatarChoice, ClientMessage, ServerMessage, VideoCodecKind};
+use super::receiver::{Received, SocketReceiver};
+use crate::emote::EmoteEvent;
+use crate::game_state::AppState;
+use crate::menu::NotificationEvent;
+
+/// How long a fake client waits for a message before giving up.
+pub const RECEIVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Pause between frames while waiting, so the receive threads get a turn.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Largest datagram a fake client receives.
+const RECEIVE_BUFFER_SIZE: usize = 65536;
+
+/// An app with no window, renderer or audio, already in game, with the
+/// events the network systems send.
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, StatesPlugin))
+        .insert_state(AppState::InGame)
+        .add_event::<NotificationEvent>()
+        .add_event::<EmoteEvent>();
+    app
+}
+
+/// Run `app` until `done` holds for its world, or `RECEIVE_TIMEOUT` passes.
+/// Whether it came to hold.
+pub fn run_until(app: &mut App, mut done: impl FnMut(&World) -> bool) -> bool {
+    let deadline = Instant::now() + RECEIVE_TIMEOUT;
+    while !done(app.world()) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        app.update();
+        thread::sleep(POLL_INTERVAL);
+    }
+    true
+}
+
+/// A non-blocking socket on a free loopback port.
+pub fn loopback_socket() -> UdpSocket {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).expect("bind loopback socket");
+    socket.set_nonblocking(true).expect("set non-blocking");
+    socket
+}
+
+/// A client that sends and receives protocol messages and nothing else.
+pub struct FakeClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    receiver: SocketReceiver<ServerMessage>,
+    /// Messages received but not yet waited for.
+    pending: VecDeque<ServerMessage>,
+}
+
+impl FakeClient {
+    /// A client talking to the server at `server`.
+    pub fn new(server: SocketAddr) -> Self {
+        let socket = loopback_socket();
+        let receiver = SocketReceiver::spawn(&socket, RECEIVE_BUFFER_SIZE, None, None)
+            .expect("spawn fake client receiver");
+        Self {
+            socket,
+            server,
+            receiver,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Address the server sees this client at.
+    pub fn addr(&self) -> SocketAddr {
+        self.socket.local_addr().expect("fake client address")
+    }
+
+    pub fn send(&self, msg: &ClientMessage) {
+        let data = serde_json::to_vec(msg).expect("encode client message");
+        self.socket
+            .send_to(&data, self.server)
+            .expect("send client message");
+    }
+
+    /// Ask to join as `nickname`, decoding only H.264 video.
+    pub fn join(&self, nickname: &str) {
+        self.rejoin(nickname, None);
+    }
+
+    /// Ask to join as `nickname`, with the rejoin token from an earlier
+    /// welcome if any.
+    pub fn rejoin(&self, nickname: &str, rejoin_token: Option<u64>) {
+        self.send(&ClientMessage::Join {
+            supported_codecs: vec![VideoCodecKind::H264],
+            supported_audio_codecs: Vec::new(),
+            avatar: AvatarChoice::default(),
+            nickname: nickname.to_string(),
+            rejoin_token,
+        });
+    }
+
+    /// Run `app` until a message `pick` returns something for arrives, and
+    /// return that. Other messages are kept for later waits. `None` if
+    /// nothing matched within `RECEIVE_TIMEOUT`.
+    pub fn wait_for<T>(
+        &mut self,
+        app: &mut App,
+        mut pick: impl FnMut(&ServerMessage) -> Option<T>,
+    ) -> Option<T> {
+        let deadline = Instant::now() + RECEIVE_TIMEOUT;
+        loop {
+            while let Some(received) = self.receiver.try_recv() {
+                if let Received::Message { msg, .. } = received {
+                    self.pending.push_back(msg);
+                }
+            }
+            let found = self
+                .pending
+                .iter()
+                .enumerate()
+                .find_map(|(i, msg)| pick(msg).map(|found| (i, found)));
+            if let Some((i, found)) = found {
+                self.pending.remove(i);
+                return Some(found);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            app.update();
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
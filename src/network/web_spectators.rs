@@ -0,0 +1,502 @@
+//! Browser spectators, with the `web-spectators` feature. While hosting, the
+//! host serves a page over HTTP on the game port's number, TCP rather than
+//! UDP. Opening it in a browser connects over WebRTC as a view-only
+//! spectator: the stream comes as an H.264 video track, and everyone's place
+//! in the room over a data channel, drawn as a map from above. Spectators
+//! don't join the session and nobody sees them. They get the stream only
+//! while it's H.264, which it is unless every client decodes better.
+//!
+//! WebRTC runs on its own thread with a tokio runtime. The game hands it
+//! access units and map updates over a channel.
+
+use bevy::prelude::*;
+use bytes::{Bytes, BytesMut};
+use serde::Serialize;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener as StdTcpListener};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264};
+use webrtc::api::{APIBuilder, API};
+use webrtc::data_channel::data_channel_state::RTCDataChannelState;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::interceptor::registry::Registry;
+use webrtc::media::Sample;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocal;
+
+use super::protocol::{PlayerId, VideoChunk};
+use super::server::GameServer;
+use crate::game_state::AppState;
+use crate::player::identity::color_rgb;
+use crate::screen::video_encoder::VideoEncoder;
+
+/// The page spectators open.
+const PAGE: &str = include_str!("../../assets/web/spectate.html");
+
+/// Most spectators watching at once. Each costs the host an encrypted copy
+/// of the stream.
+const MAX_SPECTATORS: usize = 8;
+
+/// Largest HTTP request taken, headers and body. An SDP offer is a few KB.
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// How long a browser gets to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often spectators' maps are updated.
+const MAP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Length of the first frame sent, before there's a previous one to go by.
+const FIRST_FRAME_DURATION: Duration = Duration::from_millis(33);
+
+/// What the game hands the WebRTC thread.
+enum Feed {
+    /// An H.264 access unit and when it was captured on the stream clock.
+    Frame { data: Bytes, pts_ms: u64 },
+    /// Everyone's place in the room, as JSON.
+    Map(String),
+}
+
+/// What the WebRTC thread tells the game.
+#[derive(Default)]
+struct Shared {
+    /// Spectators connected or connecting.
+    viewers: AtomicUsize,
+    /// Whether a spectator connected since the last keyframe request.
+    wants_keyframe: AtomicBool,
+}
+
+/// One browser watching.
+struct Spectator {
+    connection: Arc<RTCPeerConnection>,
+    video: Arc<TrackLocalStaticSample>,
+    /// The map's data channel, once the browser has opened it.
+    map: Arc<Mutex<Option<Arc<RTCDataChannel>>>>,
+}
+
+type Spectators = Arc<tokio::sync::Mutex<Vec<Spectator>>>;
+
+/// Resource serving browser spectators, present while hosting. Dropping it
+/// disconnects them and stops the server.
+#[derive(Resource)]
+pub struct WebSpectators {
+    feed: UnboundedSender<Feed>,
+    shared: Arc<Shared>,
+}
+
+impl WebSpectators {
+    /// Serve the page and take spectators on TCP `port`.
+    pub fn start(port: u16) -> Option<Self> {
+        let listener = StdTcpListener::bind((Ipv4Addr::UNSPECIFIED, port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|e| warn!("Can't take browser spectators on port {}: {}", port, e))
+            .ok()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()
+            .map_err(|e| error!("Failed to start the spectator runtime: {}", e))
+            .ok()?;
+        let api = Arc::new(webrtc_api()?);
+        let (feed, feed_rx) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared::default());
+        let thread_shared = Arc::clone(&shared);
+
+        thread::Builder::new()
+            .name("web-spectators".to_string())
+            .spawn(move || {
+                runtime.block_on(async move {
+                    let listener = match TcpListener::from_std(listener) {
+                        Ok(listener) => listener,
+                        Err(e) => {
+                            error!("Failed to listen for spectators: {}", e);
+                            return;
+                        }
+                    };
+                    let spectators = Spectators::default();
+                    let serving = serve(
+                        listener,
+                        api,
+                        Arc::clone(&spectators),
+                        Arc::clone(&thread_shared),
+                    );
+                    let feeding = feed_spectators(feed_rx, Arc::clone(&spectators), thread_shared);
+                    // Stops when the game drops its end of the feed
+                    tokio::select! {
+                        _ = serving => {}
+                        _ = feeding => {}
+                    }
+                    for spectator in spectators.lock().await.iter() {
+                        let _ = spectator.connection.close().await;
+                    }
+                });
+            })
+            .map_err(|e| error!("Failed to start the spectator thread: {}", e))
+            .ok()?;
+
+        Some(Self { feed, shared })
+    }
+
+    /// Whether any spectator is watching, so the stream has to be encoded.
+    pub fn is_watched(&self) -> bool {
+        self.shared.viewers.load(Ordering::Relaxed) > 0
+    }
+
+    /// Whether a spectator connected and needs a keyframe to start from.
+    fn take_keyframe_request(&self) -> bool {
+        self.shared.wants_keyframe.swap(false, Ordering::Relaxed)
+    }
+
+    /// Send spectators an encoded H.264 frame, as chunked for clients.
+    pub fn send_frame(&self, chunks: &[VideoChunk]) {
+        let Some(first) = chunks.first().filter(|_| self.is_watched()) else {
+            return;
+        };
+        let mut data = BytesMut::with_capacity(chunks.iter().map(|c| c.data.len()).sum());
+        for chunk in chunks {
+            data.extend_from_slice(&chunk.data);
+        }
+        let _ = self.feed.send(Feed::Frame {
+            data: data.freeze(),
+            pts_ms: first.pts_ms,
+        });
+    }
+}
+
+/// A player on a spectator's map.
+#[derive(Serialize)]
+struct MapPlayer<'a> {
+    id: PlayerId,
+    x: f32,
+    z: f32,
+    yaw: f32,
+    nickname: &'a str,
+    color: [u8; 3],
+}
+
+pub fn web_spectators_plugin(app: &mut App) {
+    app.add_systems(
+        OnEnter(AppState::InGame),
+        start_web_spectators.run_if(resource_exists::<GameServer>),
+    )
+    .add_systems(OnExit(AppState::InGame), stop_web_spectators)
+    .add_systems(
+        Update,
+        (request_spectator_keyframes, send_spectator_map).run_if(
+            in_state(AppState::InGame)
+                .and(resource_exists::<GameServer>)
+                .and(resource_exists::<WebSpectators>),
+        ),
+    );
+}
+
+fn start_web_spectators(mut commands: Commands, server: Res<GameServer>) {
+    if let Some(spectators) = WebSpectators::start(server.port) {
+        info!(
+            "Browser spectators can watch at http://<this machine>:{}/",
+            server.port
+        );
+        commands.insert_resource(spectators);
+    }
+}
+
+fn stop_web_spectators(mut commands: Commands) {
+    commands.remove_resource::<WebSpectators>();
+}
+
+/// System to start newly connected spectators on a fresh keyframe.
+fn request_spectator_keyframes(spectators: Res<WebSpectators>, encoder: Option<Res<VideoEncoder>>) {
+    if spectators.take_keyframe_request() {
+        if let Some(encoder) = encoder {
+            encoder.request_keyframe();
+        }
+    }
+}
+
+/// System to send spectators where everyone is, a few times a second.
+fn send_spectator_map(
+    spectators: Res<WebSpectators>,
+    server: Res<GameServer>,
+    mut last_sent: Local<Option<Instant>>,
+) {
+    let now = Instant::now();
+    if !spectators.is_watched() || last_sent.is_some_and(|at| now - at < MAP_INTERVAL) {
+        return;
+    }
+    *last_sent = Some(now);
+    let players: Vec<MapPlayer> = server
+        .player_states
+        .values()
+        .map(|state| MapPlayer {
+            id: state.id,
+            x: state.position[0],
+            z: state.position[2],
+            yaw: state.yaw,
+            nickname: &state.nickname,
+            color: color_rgb(state.color),
+        })
+        .collect();
+    if let Ok(json) = serde_json::to_string(&players) {
+        let _ = spectators.feed.send(Feed::Map(json));
+    }
+}
+
+/// WebRTC with the default codecs, H.264 among them, and the default
+/// interceptors for NACKs and reports.
+fn webrtc_api() -> Option<API> {
+    let mut media = MediaEngine::default();
+    media
+        .register_default_codecs()
+        .map_err(|e| error!("Failed to register WebRTC codecs: {}", e))
+        .ok()?;
+    let registry = register_default_interceptors(Registry::new(), &mut media)
+        .map_err(|e| error!("Failed to register WebRTC interceptors: {}", e))
+        .ok()?;
+    Some(
+        APIBuilder::new()
+            .with_media_engine(media)
+            .with_interceptor_registry(registry)
+            .build(),
+    )
+}
+
+/// Take HTTP requests: the page, and offers from browsers that opened it.
+async fn serve(listener: TcpListener, api: Arc<API>, spectators: Spectators, shared: Arc<Shared>) {
+    loop {
+        let (stream, from) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept a spectator: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let api = Arc::clone(&api);
+        let spectators = Arc::clone(&spectators);
+        let shared = Arc::clone(&shared);
+        tokio::spawn(async move {
+            handle_request(stream, from, &api, &spectators, &shared).await;
+        });
+    }
+}
+
+async fn handle_request(
+    mut stream: TcpStream,
+    from: SocketAddr,
+    api: &API,
+    spectators: &Spectators,
+    shared: &Arc<Shared>,
+) {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await;
+    let Ok(Some((method, path, body))) = request else {
+        return;
+    };
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/") => respond(&mut stream, "200 OK", "text/html", PAGE.as_bytes()).await,
+        ("POST", "/offer") => {
+            if shared.viewers.load(Ordering::Relaxed) >= MAX_SPECTATORS {
+                respond(
+                    &mut stream,
+                    "503 Service Unavailable",
+                    "text/plain",
+                    b"Full",
+                )
+                .await;
+                return;
+            }
+            let Ok(offer) = serde_json::from_slice::<RTCSessionDescription>(&body) else {
+                respond(&mut stream, "400 Bad Request", "text/plain", b"Bad offer").await;
+                return;
+            };
+            match connect(api, offer, spectators, shared).await {
+                Ok(answer) => {
+                    info!("Browser spectator connecting from {}", from);
+                    let answer = serde_json::to_vec(&answer).unwrap_or_default();
+                    respond(&mut stream, "200 OK", "application/json", &answer).await;
+                }
+                Err(e) => {
+                    warn!("Failed to connect a spectator from {}: {}", from, e);
+                    let status = "500 Internal Server Error";
+                    respond(&mut stream, status, "text/plain", b"Failed").await;
+                }
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"Not found").await,
+    }
+}
+
+/// Read an HTTP request's method, path and body, `None` if it's malformed
+/// or too large.
+async fn read_request(stream: &mut TcpStream) -> Option<(String, String, Vec<u8>)> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let header_end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if data.len() > MAX_REQUEST_SIZE {
+            return None;
+        }
+        let read = stream.read(&mut buf).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buf[..read]);
+    };
+
+    let head = std::str::from_utf8(&data[..header_end]).ok()?;
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .map_or(Some(0), |(_, value)| value.trim().parse::<usize>().ok())?;
+    if header_end + length > MAX_REQUEST_SIZE {
+        return None;
+    }
+
+    let mut body = data.split_off(header_end);
+    while body.len() < length {
+        let read = stream.read(&mut buf).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        body.extend_from_slice(&buf[..read]);
+    }
+    body.truncate(length);
+    Some((method, path, body))
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(body).await;
+}
+
+/// Answer a browser's offer with a connection sending it the stream. ICE
+/// candidates go in the answer rather than trickling, so one request does.
+async fn connect(
+    api: &API,
+    offer: RTCSessionDescription,
+    spectators: &Spectators,
+    shared: &Arc<Shared>,
+) -> webrtc::error::Result<RTCSessionDescription> {
+    let connection = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await?);
+    let video = Arc::new(TrackLocalStaticSample::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_H264.to_owned(),
+            ..Default::default()
+        },
+        "video".to_owned(),
+        "zine".to_owned(),
+    ));
+    let track = Arc::clone(&video) as Arc<dyn TrackLocal + Send + Sync>;
+    let sender = connection.add_track(track).await?;
+    // RTCP has to be read for NACKs to be answered
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 1500];
+        while sender.read(&mut buf).await.is_ok() {}
+    });
+
+    let map = Arc::new(Mutex::new(None));
+    let opened = Arc::clone(&map);
+    connection.on_data_channel(Box::new(move |channel| {
+        if let Ok(mut map) = opened.lock() {
+            *map = Some(channel);
+        }
+        Box::pin(async {})
+    }));
+    let connected = Arc::clone(shared);
+    connection.on_peer_connection_state_change(Box::new(move |state| {
+        // Start them on a fresh keyframe
+        if state == RTCPeerConnectionState::Connected {
+            connected.wants_keyframe.store(true, Ordering::Relaxed);
+        }
+        Box::pin(async {})
+    }));
+
+    connection.set_remote_description(offer).await?;
+    let answer = connection.create_answer(None).await?;
+    let mut gathered = connection.gathering_complete_promise().await;
+    connection.set_local_description(answer).await?;
+    let _ = gathered.recv().await;
+    let answer = connection
+        .local_description()
+        .await
+        .ok_or(webrtc::Error::ErrConnectionClosed)?;
+
+    let mut spectators = spectators.lock().await;
+    spectators.push(Spectator {
+        connection,
+        video,
+        map,
+    });
+    shared.viewers.store(spectators.len(), Ordering::Relaxed);
+    Ok(answer)
+}
+
+/// Pass what the game sends on to every spectator, forgetting those whose
+/// connection ended.
+async fn feed_spectators(
+    mut feed: UnboundedReceiver<Feed>,
+    spectators: Spectators,
+    shared: Arc<Shared>,
+) {
+    let mut last_pts = None;
+    while let Some(item) = feed.recv().await {
+        let mut spectators = spectators.lock().await;
+        spectators.retain(|spectator| {
+            !matches!(
+                spectator.connection.connection_state(),
+                RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed
+            )
+        });
+        shared.viewers.store(spectators.len(), Ordering::Relaxed);
+
+        match item {
+            Feed::Frame { data, pts_ms } => {
+                let duration = last_pts.map_or(FIRST_FRAME_DURATION, |last| {
+                    Duration::from_millis(pts_ms.saturating_sub(last))
+                });
+                last_pts = Some(pts_ms);
+                let sample = Sample {
+                    data,
+                    duration,
+                    ..Default::default()
+                };
+                for spectator in spectators.iter() {
+                    let _ = spectator.video.write_sample(&sample).await;
+                }
+            }
+            Feed::Map(json) => {
+                for spectator in spectators.iter() {
+                    let channel = spectator.map.lock().ok().and_then(|map| map.clone());
+                    let Some(channel) = channel else {
+                        continue;
+                    };
+                    if channel.ready_state() == RTCDataChannelState::Open {
+                        let _ = channel.send_text(json.clone()).await;
+                    }
+                }
+            }
+        }
+    }
+}
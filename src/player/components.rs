@@ -1,13 +1,48 @@
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
 
 /// Marker component for the player entity.
 #[derive(Component)]
 pub struct Player;
 
+/// Marker for the local player's camera, a child of the player entity.
+#[derive(Component)]
+pub struct PlayerCamera;
+
 /// Velocity component for physics-based movement.
 #[derive(Component, Default)]
 pub struct Velocity(pub Vec3);
 
+/// Whether the player is standing on something, from the character controller.
+#[derive(Component, Default)]
+pub struct Grounded(pub bool);
+
+/// Present while the player is sitting in a seat.
+#[derive(Component)]
+pub struct Seated {
+    pub seat: Entity,
+    /// Where to put the player back when they stand up.
+    pub stand_position: Vec3,
+}
+
+/// Present while the player is crouching: the body is shorter and the eyes lower.
+#[derive(Component)]
+pub struct Crouching;
+
+/// Present while the player is sprinting.
+#[derive(Component)]
+pub struct Sprinting;
+
+/// Seconds of sprinting the player has left.
+#[derive(Component)]
+pub struct Stamina(pub f32);
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Self(SPRINT_STAMINA)
+    }
+}
+
 /// Camera controller for first-person mouse look.
 #[derive(Component)]
 pub struct CameraController {
@@ -29,8 +64,28 @@ pub const PLAYER_SPEED: f32 = 5.0;
 pub const JUMP_VELOCITY: f32 = 8.0;
 pub const GRAVITY: f32 = 20.0;
 pub const PLAYER_HEIGHT: f32 = 2.0;
-pub const GROUND_LEVEL: f32 = 0.0;
+pub const PLAYER_RADIUS: f32 = 0.3;
+pub const SEATED_EYE_HEIGHT: f32 = 1.2;
+pub const CROUCH_HEIGHT: f32 = 1.3;
+pub const CROUCH_SPEED: f32 = 2.5;
+pub const SPRINT_SPEED: f32 = 8.0;
+/// Seconds of sprinting on full stamina.
+pub const SPRINT_STAMINA: f32 = 5.0;
+/// Stamina regained per second while not sprinting.
+pub const STAMINA_REGEN: f32 = 1.0;
+/// Stamina needed to start sprinting again after running out.
+pub const STAMINA_TO_SPRINT: f32 = 1.0;
 
 // Mouse look constants
 pub const MOUSE_SENSITIVITY: f32 = 0.003;
 pub const PITCH_LIMIT: f32 = 1.5; // ~86 degrees, just under 90
+
+/// Body capsule for the character controller. The player entity sits at eye
+/// level, `height` above the feet, so the capsule hangs below it.
+pub fn body_shape(height: f32) -> (Collider, Vec3, Quat) {
+    (
+        Collider::capsule_y(height / 2.0 - PLAYER_RADIUS, PLAYER_RADIUS),
+        Vec3::new(0.0, -height / 2.0, 0.0),
+        Quat::IDENTITY,
+    )
+}
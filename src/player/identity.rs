@@ -0,0 +1,67 @@
+//! Each player's color, to tell them apart at a glance: it shows on their
+//! name tag, their whiteboard pen and as a touch of their character's tint.
+//! The host hands colors out on joining, one player each while there are
+//! enough to go round.
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::network::protocol::PlayerId;
+use crate::network::PlayerList;
+
+/// Player colors, readable on the whiteboard and on dark name tags alike.
+const PLAYER_COLORS: [[u8; 3]; 8] = [
+    [220, 60, 60],
+    [60, 110, 230],
+    [40, 160, 80],
+    [230, 140, 30],
+    [150, 70, 200],
+    [20, 160, 170],
+    [220, 80, 160],
+    [170, 140, 20],
+];
+
+/// Resource with each player's color, kept from the player list.
+#[derive(Resource, Default)]
+pub struct PlayerIdentity {
+    colors: HashMap<PlayerId, u8>,
+}
+
+impl PlayerIdentity {
+    /// A player's color, `None` until the player list names them.
+    pub fn color(&self, player: PlayerId) -> Option<Color> {
+        let [r, g, b] = self.rgb(player)?;
+        Some(Color::srgb_u8(r, g, b))
+    }
+
+    /// A player's color as bytes, for drawing into textures.
+    pub fn rgb(&self, player: PlayerId) -> Option<[u8; 3]> {
+        self.colors.get(&player).copied().map(color_rgb)
+    }
+}
+
+/// The bytes of the color handed out as `color`.
+pub fn color_rgb(color: u8) -> [u8; 3] {
+    PLAYER_COLORS[color as usize % PLAYER_COLORS.len()]
+}
+
+/// The first color nobody in the session has, or a shared one once they're
+/// all taken.
+pub fn free_color(taken: impl Iterator<Item = u8>) -> u8 {
+    let taken: Vec<u8> = taken.collect();
+    (0..PLAYER_COLORS.len() as u8)
+        .find(|color| !taken.contains(color))
+        .unwrap_or(taken.len() as u8 % PLAYER_COLORS.len() as u8)
+}
+
+/// System to follow the colors in the player list.
+pub fn update_player_identity(player_list: Res<PlayerList>, mut identity: ResMut<PlayerIdentity>) {
+    if !player_list.is_changed() {
+        return;
+    }
+    identity.colors = player_list
+        .players
+        .iter()
+        .map(|player| (player.id, player.color))
+        .collect();
+}
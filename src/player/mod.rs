@@ -1,22 +1,72 @@
 pub mod components;
+pub mod identity;
 pub mod systems;
 
 use bevy::prelude::*;
 
 pub use components::{
-    CameraController, Player, Velocity, MOUSE_SENSITIVITY, PITCH_LIMIT, PLAYER_HEIGHT,
+    body_shape, CameraController, Crouching, Grounded, Player, PlayerCamera, Seated, Sprinting,
+    Stamina, Velocity, CROUCH_HEIGHT, GRAVITY, JUMP_VELOCITY, MOUSE_SENSITIVITY, PITCH_LIMIT,
+    PLAYER_HEIGHT, SEATED_EYE_HEIGHT, SPRINT_SPEED,
 };
 
+use crate::camera::spectator::not_spectating;
+use crate::console::AddConsoleCommand;
 use crate::game_state::AppState;
-use systems::{apply_gravity, apply_velocity, player_movement};
+use identity::{update_player_identity, PlayerIdentity};
+use systems::{
+    apply_gravity, apply_velocity, player_movement, separate_from_remote_players, update_grounded,
+    update_stance,
+};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerIdentity>()
+            .add_systems(Update, update_player_identity);
         app.add_systems(
             Update,
-            (player_movement, apply_gravity, apply_velocity).run_if(in_state(AppState::InGame)),
+            (
+                update_grounded,
+                update_stance,
+                player_movement,
+                apply_gravity,
+                apply_velocity,
+                separate_from_remote_players,
+            )
+                .chain()
+                .run_if(
+                    in_state(AppState::InGame)
+                        .and(player_is_standing)
+                        .and(not_spectating),
+                ),
         );
+        app.add_console_command("tp", "<x> <y> <z>", teleport_command);
+    }
+}
+
+/// Console command moving the player, putting their eyes at the point given.
+fn teleport_command(
+    In(args): In<Vec<String>>,
+    mut query: Query<(&mut Transform, &mut Velocity, Has<Seated>), With<Player>>,
+) -> String {
+    let coords: Result<Vec<f32>, _> = args.iter().map(|arg| arg.parse()).collect();
+    let Ok([x, y, z]) = coords.as_deref() else {
+        return "Usage: tp <x> <y> <z>".to_string();
+    };
+    let Ok((mut transform, mut velocity, seated)) = query.get_single_mut() else {
+        return "No player to move".to_string();
+    };
+    if seated {
+        return "Stand up first".to_string();
     }
+    transform.translation = Vec3::new(*x, *y, *z);
+    *velocity = Velocity::default();
+    format!("Moved to {:.1} {:.1} {:.1}", x, y, z)
+}
+
+/// Movement and physics are suspended while seated.
+fn player_is_standing(query: Query<(), (With<Player>, With<Seated>)>) -> bool {
+    query.is_empty()
 }
@@ -1,89 +1,239 @@
-use bevy::input::keyboard::KeyboardInput;
-use bevy::input::ButtonState;
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
 
 use super::components::{
-    Player, Velocity, GRAVITY, GROUND_LEVEL, JUMP_VELOCITY, PLAYER_HEIGHT, PLAYER_SPEED,
+    body_shape, Crouching, Grounded, Player, Sprinting, Stamina, Velocity, CROUCH_HEIGHT,
+    CROUCH_SPEED, GRAVITY, JUMP_VELOCITY, PLAYER_HEIGHT, PLAYER_RADIUS, PLAYER_SPEED, SPRINT_SPEED,
+    SPRINT_STAMINA, STAMINA_REGEN, STAMINA_TO_SPRINT,
 };
-use crate::world::ROOM_HALF_DEPTH;
-use crate::world::ROOM_HALF_WIDTH;
+use crate::character::{CharacterAnimationState, MODEL_OFFSET};
+use crate::controls::PlayerInput;
+use crate::network::protocol::RemotePlayer;
+
+/// Clearance above the head needed to stand up from a crouch.
+const STAND_UP_MARGIN: f32 = 0.05;
 
 pub fn player_movement(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut keyboard_events: EventReader<KeyboardInput>,
-    mut query: Query<(&Transform, &mut Velocity), With<Player>>,
+    input: Res<PlayerInput>,
+    mut query: Query<
+        (
+            &Transform,
+            &mut Velocity,
+            &Grounded,
+            Has<Crouching>,
+            Has<Sprinting>,
+        ),
+        With<Player>,
+    >,
 ) {
-    let (transform, mut velocity) = query.single_mut();
-
-    // Get movement direction from WASD
-    let mut direction = Vec3::ZERO;
-
-    if keyboard_input.pressed(KeyCode::KeyW) {
-        direction.z -= 1.0;
-    }
-    if keyboard_input.pressed(KeyCode::KeyS) {
-        direction.z += 1.0;
-    }
-    if keyboard_input.pressed(KeyCode::KeyA) {
-        direction.x -= 1.0;
-    }
-    if keyboard_input.pressed(KeyCode::KeyD) {
-        direction.x += 1.0;
-    }
-
-    // Normalize diagonal movement
-    if direction.length() > 0.0 {
-        direction = direction.normalize();
-    }
+    let Ok((transform, mut velocity, grounded, crouching, sprinting)) = query.get_single_mut()
+    else {
+        return;
+    };
 
-    // Apply movement relative to camera facing direction (only yaw)
+    // Apply movement relative to camera facing direction (only yaw). A stick
+    // pushed part of the way moves slower.
     let forward = transform.forward();
     let forward_flat = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
     let right_flat = Vec3::new(-forward.z, 0.0, forward.x).normalize_or_zero();
 
-    let move_direction = forward_flat * -direction.z + right_flat * direction.x;
+    let move_direction = forward_flat * input.movement.y + right_flat * input.movement.x;
 
     // Set horizontal velocity
-    velocity.0.x = move_direction.x * PLAYER_SPEED;
-    velocity.0.z = move_direction.z * PLAYER_SPEED;
-
-    // Jump using raw keyboard events (bypasses ButtonInput state issues on Windows)
-    let is_grounded = transform.translation.y <= GROUND_LEVEL + PLAYER_HEIGHT + 0.01;
-
-    for event in keyboard_events.read() {
-        if event.key_code == KeyCode::Space && event.state == ButtonState::Pressed && is_grounded {
-            velocity.0.y = JUMP_VELOCITY;
-        }
+    let speed = if crouching {
+        CROUCH_SPEED
+    } else if sprinting {
+        SPRINT_SPEED
+    } else {
+        PLAYER_SPEED
+    };
+    velocity.0.x = move_direction.x * speed;
+    velocity.0.z = move_direction.z * speed;
+
+    if input.jump && grounded.0 {
+        velocity.0.y = JUMP_VELOCITY;
     }
 }
 
+/// Crouch while the crouch input is held and stand back up when it's released,
+/// once there's room overhead. Sprint while the sprint input is held and
+/// there's stamina left.
+pub fn update_stance(
+    mut commands: Commands,
+    time: Res<Time>,
+    input: Res<PlayerInput>,
+    rapier_context: ReadDefaultRapierContext,
+    mut query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut KinematicCharacterController,
+            &mut Stamina,
+            &Velocity,
+            &Grounded,
+            Has<Crouching>,
+            Has<Sprinting>,
+        ),
+        With<Player>,
+    >,
+) {
+    let Ok((
+        entity,
+        mut transform,
+        mut controller,
+        mut stamina,
+        velocity,
+        grounded,
+        mut crouching,
+        sprinting,
+    )) = query.get_single_mut()
+    else {
+        return;
+    };
+
+    // The feet stay on the ground and the eyes move, so only change stance when grounded
+    let crouch_drop = PLAYER_HEIGHT - CROUCH_HEIGHT;
+    if grounded.0 && input.crouch && !crouching {
+        transform.translation.y -= crouch_drop;
+        controller.custom_shape = Some(body_shape(CROUCH_HEIGHT));
+        commands.entity(entity).insert(Crouching);
+        crouching = true;
+    } else if grounded.0 && !input.crouch && crouching {
+        let blocked = rapier_context
+            .single()
+            .cast_ray(
+                transform.translation,
+                Vec3::Y,
+                crouch_drop + STAND_UP_MARGIN,
+                true,
+                QueryFilter::default().exclude_sensors(),
+            )
+            .is_some();
+        if !blocked {
+            transform.translation.y += crouch_drop;
+            controller.custom_shape = Some(body_shape(PLAYER_HEIGHT));
+            commands.entity(entity).remove::<Crouching>();
+            crouching = false;
+        }
+    }
 
-pub fn apply_gravity(time: Res<Time>, mut query: Query<(&Transform, &mut Velocity), With<Player>>) {
-    let (transform, mut velocity) = query.single_mut();
+    // Once stamina runs out, it has to build up a little before sprinting again
+    let moving = velocity.0.with_y(0.0).length() > 0.1;
+    let has_stamina = if sprinting {
+        stamina.0 > 0.0
+    } else {
+        stamina.0 >= STAMINA_TO_SPRINT
+    };
+    let sprint = input.sprint && moving && has_stamina && !crouching;
+
+    let dt = time.delta_secs();
+    if sprint {
+        stamina.0 = (stamina.0 - dt).max(0.0);
+    } else {
+        stamina.0 = (stamina.0 + STAMINA_REGEN * dt).min(SPRINT_STAMINA);
+    }
+    if sprint && !sprinting {
+        commands.entity(entity).insert(Sprinting);
+    } else if !sprint && sprinting {
+        commands.entity(entity).remove::<Sprinting>();
+    }
+}
 
-    let is_grounded = transform.translation.y <= GROUND_LEVEL + PLAYER_HEIGHT + 0.01;
+pub fn apply_gravity(time: Res<Time>, mut query: Query<(&Grounded, &mut Velocity), With<Player>>) {
+    let Ok((grounded, mut velocity)) = query.get_single_mut() else {
+        return;
+    };
 
-    if !is_grounded {
+    if !grounded.0 {
         velocity.0.y -= GRAVITY * time.delta_secs();
     }
 }
 
+/// Hand this frame's movement to the character controller, which slides it
+/// along colliders during the physics step.
 pub fn apply_velocity(
     time: Res<Time>,
-    mut query: Query<(&mut Transform, &mut Velocity), With<Player>>,
+    mut query: Query<(&Velocity, &mut KinematicCharacterController), With<Player>>,
 ) {
-    let (mut transform, mut velocity) = query.single_mut();
+    let Ok((velocity, mut controller)) = query.get_single_mut() else {
+        return;
+    };
 
-    // Apply velocity to position
-    transform.translation += velocity.0 * time.delta_secs();
+    controller.translation = Some(velocity.0 * time.delta_secs());
+}
 
-    // Ground collision
-    if transform.translation.y < GROUND_LEVEL + PLAYER_HEIGHT {
-        transform.translation.y = GROUND_LEVEL + PLAYER_HEIGHT;
-        velocity.0.y = 0.0;
+/// Push the player out of other players' bodies, so nobody ends up standing
+/// inside anyone else. Bodies are upright capsules, so where their heights
+/// overlap only the distance between them across the floor matters.
+pub fn separate_from_remote_players(
+    mut player_query: Query<
+        (
+            &Transform,
+            &mut KinematicCharacterController,
+            Has<Crouching>,
+        ),
+        With<Player>,
+    >,
+    remote_query: Query<
+        (&Transform, &CharacterAnimationState),
+        (With<RemotePlayer>, Without<Player>),
+    >,
+) {
+    let Ok((transform, mut controller, crouching)) = player_query.get_single_mut() else {
+        return;
+    };
+    // The player is at eye level, remote characters at their feet
+    let height = if crouching {
+        CROUCH_HEIGHT
+    } else {
+        PLAYER_HEIGHT
+    };
+    let feet = transform.translation.y - height;
+
+    let mut push = Vec3::ZERO;
+    for (remote, anim_state) in remote_query.iter() {
+        let remote_height = if anim_state.is_crouching {
+            CROUCH_HEIGHT
+        } else {
+            PLAYER_HEIGHT
+        };
+        let remote_feet = remote.translation.y - MODEL_OFFSET;
+        if feet >= remote_feet + remote_height || remote_feet >= feet + height {
+            continue;
+        }
+
+        let offset = (transform.translation - remote.translation).with_y(0.0);
+        let overlap = PLAYER_RADIUS * 2.0 - offset.length();
+        if overlap <= 0.0 {
+            continue;
+        }
+        // Players right on top of each other still have to go some way
+        push += offset.try_normalize().unwrap_or(Vec3::X) * overlap;
     }
 
-    // Wall collisions (keep player inside room)
-    transform.translation.x = transform.translation.x.clamp(-ROOM_HALF_WIDTH, ROOM_HALF_WIDTH);
-    transform.translation.z = transform.translation.z.clamp(-ROOM_HALF_DEPTH, ROOM_HALF_DEPTH);
+    if push != Vec3::ZERO {
+        controller.translation = Some(controller.translation.unwrap_or_default() + push);
+    }
+}
+
+/// Read back the result of the last controller move: whether we're standing on
+/// something, and whether a jump hit the ceiling.
+pub fn update_grounded(
+    mut query: Query<
+        (&KinematicCharacterControllerOutput, &mut Grounded, &mut Velocity),
+        With<Player>,
+    >,
+) {
+    let Ok((output, mut grounded, mut velocity)) = query.get_single_mut() else {
+        return;
+    };
+
+    grounded.0 = output.grounded;
+
+    if output.grounded && velocity.0.y < 0.0 {
+        velocity.0.y = 0.0;
+    }
+    if velocity.0.y > 0.0 && output.effective_translation.y < output.desired_translation.y * 0.5 {
+        velocity.0.y = 0.0;
+    }
 }
@@ -1,37 +1,66 @@
 use bevy::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, Mutex};
 
-/// Resource managing system audio capture via WASAPI loopback.
+/// Which audio the host captures for the stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioCaptureTarget {
+    /// Everything playing on the default output device.
+    System,
+    /// Only the process owning this window (HWND) and its child processes.
+    Window(isize),
+}
+
+/// Resource managing audio capture via WASAPI loopback.
 #[derive(Resource)]
 pub struct AudioCapture {
     /// Receiver for captured audio samples (f32, interleaved stereo).
     rx: Arc<Mutex<Receiver<Vec<f32>>>>,
+    /// Tells the capture thread to stop when this resource is dropped.
+    running: Arc<AtomicBool>,
+    /// What is being captured.
+    pub target: AudioCaptureTarget,
+    /// Capture device chosen in the settings (`None` for the platform default).
+    pub device: Option<String>,
     /// Sample rate of the capture.
     pub sample_rate: u32,
     /// Number of channels.
     pub channels: u16,
 }
 
+/// Format reported by a capture backend once it has started.
+struct CaptureInfo {
+    sample_rate: u32,
+    channels: u16,
+}
+
 #[cfg(windows)]
 mod wasapi_loopback {
     use super::*;
     use std::ptr::null_mut;
     use std::sync::mpsc::Sender;
+    use std::time::Duration;
+    use windows::core::{implement, Interface, IUnknown, HRESULT, PROPVARIANT};
+    use windows::Win32::Foundation::{CloseHandle, E_FAIL, HANDLE, WAIT_OBJECT_0};
     use windows::Win32::Media::Audio::*;
     use windows::Win32::System::Com::*;
+    use windows::Win32::System::Threading::{CreateEventW, WaitForSingleObject};
 
-    pub struct CaptureInfo {
-        pub sample_rate: u32,
-        pub channels: u16,
-    }
+    /// `WAVE_FORMAT_IEEE_FLOAT` from mmreg.h.
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+    /// `VT_BLOB` variant type for PROPVARIANT.
+    const VT_BLOB: u16 = 65;
 
     pub fn start_loopback_capture(
+        target: AudioCaptureTarget,
         tx: Sender<Vec<f32>>,
         info_tx: Sender<CaptureInfo>,
+        running: Arc<AtomicBool>,
     ) -> Option<()> {
         std::thread::spawn(move || {
-            if let Err(e) = capture_thread(tx, info_tx) {
+            if let Err(e) = capture_thread(target, tx, info_tx, running) {
                 error!("WASAPI loopback capture error: {:?}", e);
             }
         });
@@ -39,14 +68,165 @@ mod wasapi_loopback {
         Some(())
     }
 
+    /// PROPVARIANT laid out as a VT_BLOB, used to pass activation params.
+    #[repr(C)]
+    struct BlobPropVariant {
+        vt: u16,
+        reserved: [u16; 3],
+        size: u32,
+        data: *mut u8,
+    }
+
+    /// Signals when asynchronous audio interface activation has finished.
+    #[implement(IActivateAudioInterfaceCompletionHandler, IAgileObject)]
+    struct ActivationHandler {
+        done: Mutex<Option<Sender<()>>>,
+    }
+
+    impl IAgileObject_Impl for ActivationHandler_Impl {}
+
+    impl IActivateAudioInterfaceCompletionHandler_Impl for ActivationHandler_Impl {
+        fn ActivateCompleted(
+            &self,
+            _operation: Option<&IActivateAudioInterfaceAsyncOperation>,
+        ) -> windows::core::Result<()> {
+            if let Some(done) = self.done.lock().ok().and_then(|mut done| done.take()) {
+                let _ = done.send(());
+            }
+            Ok(())
+        }
+    }
+
+    /// Look up the process that owns a window.
+    fn window_process_id(hwnd: isize) -> Option<u32> {
+        let mut pid: u32 = 0;
+        unsafe {
+            winapi::um::winuser::GetWindowThreadProcessId(hwnd as winapi::shared::windef::HWND, &mut pid);
+        }
+        (pid != 0).then_some(pid)
+    }
+
+    /// Activate an audio client that only captures `pid` and its child processes.
+    /// Requires Windows 10 build 20348 or later.
+    unsafe fn activate_process_loopback(pid: u32) -> windows::core::Result<IAudioClient> {
+        let mut params = AUDIOCLIENT_ACTIVATION_PARAMS {
+            ActivationType: AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK,
+            Anonymous: AUDIOCLIENT_ACTIVATION_PARAMS_0 {
+                ProcessLoopbackParams: AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS {
+                    TargetProcessId: pid,
+                    ProcessLoopbackMode: PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE,
+                },
+            },
+        };
+        let prop = BlobPropVariant {
+            vt: VT_BLOB,
+            reserved: [0; 3],
+            size: std::mem::size_of::<AUDIOCLIENT_ACTIVATION_PARAMS>() as u32,
+            data: &mut params as *mut _ as *mut u8,
+        };
+
+        let (done_tx, done_rx) = mpsc::channel();
+        let handler: IActivateAudioInterfaceCompletionHandler = ActivationHandler {
+            done: Mutex::new(Some(done_tx)),
+        }
+        .into();
+
+        let operation = ActivateAudioInterfaceAsync(
+            VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK,
+            &IAudioClient::IID,
+            Some(&prop as *const BlobPropVariant as *const PROPVARIANT),
+            &handler,
+        )?;
+
+        if done_rx.recv_timeout(Duration::from_secs(5)).is_err() {
+            return Err(E_FAIL.into());
+        }
+
+        let mut result = HRESULT(0);
+        let mut activated: Option<IUnknown> = None;
+        operation.GetActivateResult(&mut result, &mut activated)?;
+        result.ok()?;
+        activated.ok_or_else(|| windows::core::Error::from(E_FAIL))?.cast()
+    }
+
+    /// Set up process loopback for the window's process: 48 kHz stereo float, event driven.
+    unsafe fn init_process_loopback(
+        hwnd: isize,
+    ) -> windows::core::Result<(IAudioClient, CaptureInfo, u16, HANDLE)> {
+        let pid = window_process_id(hwnd).ok_or_else(|| windows::core::Error::from(E_FAIL))?;
+        let audio_client = activate_process_loopback(pid)?;
+
+        // Process loopback clients have no mix format; request one and let WASAPI convert
+        let format = WAVEFORMATEX {
+            wFormatTag: WAVE_FORMAT_IEEE_FLOAT,
+            nChannels: 2,
+            nSamplesPerSec: 48000,
+            nAvgBytesPerSec: 48000 * 2 * 4,
+            nBlockAlign: 2 * 4,
+            wBitsPerSample: 32,
+            cbSize: 0,
+        };
+
+        audio_client.Initialize(
+            AUDCLNT_SHAREMODE_SHARED,
+            AUDCLNT_STREAMFLAGS_LOOPBACK
+                | AUDCLNT_STREAMFLAGS_EVENTCALLBACK
+                | AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM,
+            1_000_000, // 100ms in 100-nanosecond units
+            0,
+            &format,
+            None,
+        )?;
+
+        let event = CreateEventW(None, false, false, None)?;
+        audio_client.SetEventHandle(event)?;
+
+        info!("WASAPI process loopback: pid {}, 48000 Hz, 2 channels, 32 bits", pid);
+
+        let info = CaptureInfo {
+            sample_rate: 48000,
+            channels: 2,
+        };
+        Ok((audio_client, info, 32, event))
+    }
+
     fn capture_thread(
+        target: AudioCaptureTarget,
         tx: Sender<Vec<f32>>,
         info_tx: Sender<CaptureInfo>,
+        running: Arc<AtomicBool>,
     ) -> windows::core::Result<()> {
         unsafe {
             // Initialize COM
             CoInitializeEx(Some(null_mut()), COINIT_MULTITHREADED).ok()?;
 
+            // Prefer capturing just the shared window's process
+            if let AudioCaptureTarget::Window(hwnd) = target {
+                match init_process_loopback(hwnd) {
+                    Ok((audio_client, info, bits_per_sample, event)) => {
+                        let channels = info.channels;
+                        let _ = info_tx.send(info);
+                        let result = run_capture_loop(
+                            &audio_client,
+                            channels,
+                            bits_per_sample,
+                            Some(event),
+                            &tx,
+                            &running,
+                        );
+                        let _ = CloseHandle(event);
+                        CoUninitialize();
+                        return result;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Process audio capture unavailable ({:?}), falling back to system loopback",
+                            e
+                        );
+                    }
+                }
+            }
+
             // Get default audio endpoint (render device for loopback)
             let enumerator: IMMDeviceEnumerator =
                 CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
@@ -87,68 +267,95 @@ mod wasapi_loopback {
                 None,
             )?;
 
-            // Get capture client
-            let capture_client: IAudioCaptureClient = audio_client.GetService()?;
+            let result =
+                run_capture_loop(&audio_client, channels, bits_per_sample, None, &tx, &running);
+            CoUninitialize();
+            result
+        }
+    }
+
+    /// Pump captured packets until the receiver is dropped or capture is stopped.
+    /// Waits on `event` when the client is event driven, otherwise polls.
+    unsafe fn run_capture_loop(
+        audio_client: &IAudioClient,
+        channels: u16,
+        bits_per_sample: u16,
+        event: Option<HANDLE>,
+        tx: &Sender<Vec<f32>>,
+        running: &AtomicBool,
+    ) -> windows::core::Result<()> {
+        // Get capture client
+        let capture_client: IAudioCaptureClient = audio_client.GetService()?;
 
-            // Start capturing
-            audio_client.Start()?;
-            info!("WASAPI loopback capture started");
+        // Start capturing
+        audio_client.Start()?;
+        info!("WASAPI loopback capture started");
 
-            // Polling loop - check for data periodically
-            loop {
-                // Sleep for ~10ms to avoid busy waiting
-                std::thread::sleep(std::time::Duration::from_millis(10));
+        loop {
+            if !running.load(Ordering::Relaxed) {
+                audio_client.Stop()?;
+                return Ok(());
+            }
 
-                // Get next packet size
-                let packet_size = capture_client.GetNextPacketSize()?;
-                if packet_size == 0 {
-                    continue;
+            match event {
+                // Wake when a buffer is ready (or every 100ms to check for stop)
+                Some(event) => {
+                    if WaitForSingleObject(event, 100) != WAIT_OBJECT_0 {
+                        continue;
+                    }
                 }
+                // Sleep for ~10ms to avoid busy waiting
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
 
-                // Process all available packets
-                loop {
-                    let mut buffer_ptr: *mut u8 = null_mut();
-                    let mut num_frames: u32 = 0;
-                    let mut flags: u32 = 0;
-
-                    let result = capture_client.GetBuffer(
-                        &mut buffer_ptr,
-                        &mut num_frames,
-                        &mut flags,
-                        None,
-                        None,
-                    );
+            // Get next packet size
+            let packet_size = capture_client.GetNextPacketSize()?;
+            if packet_size == 0 {
+                continue;
+            }
 
-                    if result.is_err() || num_frames == 0 {
-                        break;
-                    }
+            // Process all available packets
+            loop {
+                let mut buffer_ptr: *mut u8 = null_mut();
+                let mut num_frames: u32 = 0;
+                let mut flags: u32 = 0;
+
+                let result = capture_client.GetBuffer(
+                    &mut buffer_ptr,
+                    &mut num_frames,
+                    &mut flags,
+                    None,
+                    None,
+                );
+
+                if result.is_err() || num_frames == 0 {
+                    break;
+                }
 
-                    // Check if buffer contains silence
-                    let is_silent = (flags & (AUDCLNT_BUFFERFLAGS_SILENT.0 as u32)) != 0;
+                // Check if buffer contains silence
+                let is_silent = (flags & (AUDCLNT_BUFFERFLAGS_SILENT.0 as u32)) != 0;
 
-                    if !is_silent && num_frames > 0 && !buffer_ptr.is_null() {
-                        let samples = convert_buffer_to_f32(
-                            buffer_ptr,
-                            num_frames as usize,
-                            channels as usize,
-                            bits_per_sample,
-                        );
+                if !is_silent && num_frames > 0 && !buffer_ptr.is_null() {
+                    let samples = convert_buffer_to_f32(
+                        buffer_ptr,
+                        num_frames as usize,
+                        channels as usize,
+                        bits_per_sample,
+                    );
 
-                        if tx.send(samples).is_err() {
-                            // Receiver dropped, stop capturing
-                            audio_client.Stop()?;
-                            CoUninitialize();
-                            return Ok(());
-                        }
+                    if tx.send(samples).is_err() {
+                        // Receiver dropped, stop capturing
+                        audio_client.Stop()?;
+                        return Ok(());
                     }
+                }
 
-                    capture_client.ReleaseBuffer(num_frames)?;
+                capture_client.ReleaseBuffer(num_frames)?;
 
-                    // Check if there's more data
-                    let next_size = capture_client.GetNextPacketSize()?;
-                    if next_size == 0 {
-                        break;
-                    }
+                // Check if there's more data
+                let next_size = capture_client.GetNextPacketSize()?;
+                if next_size == 0 {
+                    break;
                 }
             }
         }
@@ -197,19 +404,242 @@ mod wasapi_loopback {
     }
 }
 
+/// Linux backend: records the default sink's monitor source through `parec`,
+/// which works with both PulseAudio and PipeWire (via pipewire-pulse).
+#[cfg(target_os = "linux")]
+mod pulse_monitor {
+    use super::*;
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+    use std::sync::mpsc::Sender;
+
+    const SAMPLE_RATE: u32 = 48000;
+    const CHANNELS: u16 = 2;
+
+    /// 10ms of interleaved f32 stereo per read.
+    const READ_BYTES: usize = (SAMPLE_RATE as usize / 100) * CHANNELS as usize * 4;
+
+    /// Record `source` (a PulseAudio source name), or the default sink's monitor.
+    pub fn start_monitor_capture(
+        source: Option<&str>,
+        tx: Sender<Vec<f32>>,
+        info_tx: Sender<CaptureInfo>,
+        running: Arc<AtomicBool>,
+    ) -> Option<()> {
+        let mut child = Command::new("parec")
+            .args([
+                &format!("--device={}", source.unwrap_or("@DEFAULT_MONITOR@")),
+                "--format=float32le",
+                &format!("--rate={}", SAMPLE_RATE),
+                &format!("--channels={}", CHANNELS),
+                "--latency-msec=20",
+                "--raw",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| warn!("Failed to start parec (is PulseAudio/PipeWire installed?): {}", e))
+            .ok()?;
+        let mut stdout = child.stdout.take()?;
+
+        info!("PulseAudio monitor capture: {} Hz, {} channels", SAMPLE_RATE, CHANNELS);
+        let _ = info_tx.send(CaptureInfo {
+            sample_rate: SAMPLE_RATE,
+            channels: CHANNELS,
+        });
+
+        std::thread::spawn(move || {
+            let mut buffer = vec![0u8; READ_BYTES];
+            while running.load(Ordering::Relaxed) {
+                if let Err(e) = stdout.read_exact(&mut buffer) {
+                    error!("PulseAudio monitor capture error: {}", e);
+                    break;
+                }
+
+                let samples: Vec<f32> = buffer
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+
+                // Skip pure silence like the WASAPI backend does
+                if samples.iter().all(|&s| s == 0.0) {
+                    continue;
+                }
+
+                if tx.send(samples).is_err() {
+                    // Receiver dropped, stop capturing
+                    break;
+                }
+            }
+
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+
+        Some(())
+    }
+}
+
+/// cpal backend: records a specific device chosen in the settings.
+///
+/// On macOS CoreAudio has no system loopback, so this captures from a virtual
+/// loopback input device (BlackHole, Loopback, Soundflower) or an aggregate
+/// device that includes one, as set up by the user. On Windows it is used for
+/// loopback recording of a non-default output device.
+#[cfg(any(windows, target_os = "macos"))]
+mod cpal_device {
+    use super::*;
+    use cpal::traits::{DeviceTrait, StreamTrait};
+    use cpal::SampleFormat;
+    use std::sync::mpsc::Sender;
+
+    /// Substrings identifying input devices that carry system output.
+    #[cfg(target_os = "macos")]
+    const LOOPBACK_DEVICE_NAMES: [&str; 4] = ["BlackHole", "Loopback", "Soundflower", "Aggregate"];
+
+    #[cfg(target_os = "macos")]
+    pub fn find_loopback_device() -> Option<cpal::Device> {
+        use cpal::traits::HostTrait;
+
+        let host = cpal::default_host();
+        let devices = host.input_devices().ok()?;
+        for device in devices {
+            let Ok(name) = device.name() else {
+                continue;
+            };
+            if LOOPBACK_DEVICE_NAMES.iter().any(|n| name.contains(n)) {
+                info!("Using loopback input device: {}", name);
+                return Some(device);
+            }
+        }
+        warn!("No loopback input device found - install BlackHole to stream system audio on macOS");
+        None
+    }
+
+    pub fn start_device_capture(
+        device: cpal::Device,
+        tx: Sender<Vec<f32>>,
+        info_tx: Sender<CaptureInfo>,
+        running: Arc<AtomicBool>,
+    ) -> Option<()> {
+        // WASAPI records output devices in loopback mode using their output format
+        #[cfg(windows)]
+        let config = device.default_output_config();
+        #[cfg(not(windows))]
+        let config = device.default_input_config();
+        let config = config
+            .map_err(|e| error!("Failed to get capture device config: {}", e))
+            .ok()?;
+
+        let sample_format = config.sample_format();
+        let stream_config: cpal::StreamConfig = config.into();
+        let _ = info_tx.send(CaptureInfo {
+            sample_rate: stream_config.sample_rate.0,
+            channels: stream_config.channels,
+        });
+
+        // Stream must be created in the same thread that runs it
+        std::thread::spawn(move || {
+            let err_fn = |err| error!("Device capture error: {}", err);
+            let stream = match sample_format {
+                SampleFormat::F32 => {
+                    let tx = tx.clone();
+                    device.build_input_stream(
+                        &stream_config,
+                        move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                            let _ = tx.send(data.to_vec());
+                        },
+                        err_fn,
+                        None,
+                    )
+                }
+                SampleFormat::I16 => {
+                    let tx = tx.clone();
+                    device.build_input_stream(
+                        &stream_config,
+                        move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                            let _ = tx.send(data.iter().map(|&s| s as f32 / 32768.0).collect());
+                        },
+                        err_fn,
+                        None,
+                    )
+                }
+                other => {
+                    error!("Unsupported capture sample format: {:?}", other);
+                    return;
+                }
+            };
+
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("Failed to create capture stream: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = stream.play() {
+                error!("Failed to start device capture: {}", e);
+                return;
+            }
+
+            info!("Device capture started");
+
+            // Keep stream alive until capture is stopped
+            while running.load(Ordering::Relaxed) {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+        });
+
+        Some(())
+    }
+}
+
 impl AudioCapture {
-    /// Start capturing system audio (loopback).
-    pub fn new() -> Option<Self> {
+    /// Start capturing audio (loopback). Window targets fall back to system audio
+    /// when per-process capture is unavailable. `device` picks a capture device from
+    /// [`capture_devices`](super::audio_devices::capture_devices) for system audio;
+    /// `None` uses the platform default.
+    pub fn new(target: AudioCaptureTarget, device: Option<&str>) -> Option<Self> {
         let (tx, rx) = mpsc::channel::<Vec<f32>>();
         let rx = Arc::new(Mutex::new(rx));
+        let running = Arc::new(AtomicBool::new(true));
 
-        #[cfg(windows)]
+        #[cfg(any(windows, target_os = "linux", target_os = "macos"))]
         {
-            use wasapi_loopback::CaptureInfo;
-
             let (info_tx, info_rx) = mpsc::channel::<CaptureInfo>();
 
-            wasapi_loopback::start_loopback_capture(tx, info_tx)?;
+            #[cfg(windows)]
+            let backend = match (target, device) {
+                // Process loopback always follows the window, whatever the device
+                (AudioCaptureTarget::System, Some(name)) => {
+                    let device = super::audio_devices::find_capture_device(name)?;
+                    cpal_device::start_device_capture(device, tx, info_tx, running.clone())?;
+                    "WASAPI device loopback"
+                }
+                _ => {
+                    wasapi_loopback::start_loopback_capture(target, tx, info_tx, running.clone())?;
+                    "WASAPI loopback"
+                }
+            };
+            #[cfg(target_os = "linux")]
+            let backend = {
+                pulse_monitor::start_monitor_capture(device, tx, info_tx, running.clone())?;
+                "PulseAudio monitor"
+            };
+            #[cfg(target_os = "macos")]
+            let backend = {
+                let device = match device {
+                    Some(name) => super::audio_devices::find_capture_device(name)?,
+                    None => cpal_device::find_loopback_device()?,
+                };
+                cpal_device::start_device_capture(device, tx, info_tx, running.clone())?;
+                "loopback device"
+            };
+            #[cfg(not(windows))]
+            if let AudioCaptureTarget::Window(_) = target {
+                info!("Per-window audio capture is only supported on Windows, capturing system audio");
+            }
 
             // Wait for actual capture info from thread (with timeout)
             let info = info_rx
@@ -223,20 +653,23 @@ impl AudioCapture {
                 });
 
             info!(
-                "Audio capture initialized: {} Hz, {} channels (WASAPI loopback)",
-                info.sample_rate, info.channels
+                "Audio capture initialized: {} Hz, {} channels ({}, {:?})",
+                info.sample_rate, info.channels, backend, target
             );
 
             Some(Self {
                 rx,
+                running,
+                target,
+                device: device.map(str::to_string),
                 sample_rate: info.sample_rate,
                 channels: info.channels,
             })
         }
 
-        #[cfg(not(windows))]
+        #[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
         {
-            drop(tx);
+            drop((tx, rx, running, target, device));
             warn!("Audio loopback capture not supported on this platform");
             None
         }
@@ -247,3 +680,9 @@ impl AudioCapture {
         self.rx.lock().ok()?.try_recv().ok()
     }
 }
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
@@ -1,11 +1,40 @@
 use bevy::prelude::*;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::traits::{DeviceTrait, StreamTrait};
 use cpal::{SampleFormat, StreamConfig};
 use ringbuf::{traits::*, HeapRb};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::network::protocol::AudioChunk;
+use super::audio_devices::find_output_device;
+use super::av_sync::AvSyncClock;
+use crate::network::protocol::{AudioChunk, AudioCodecKind};
+
+/// Default time the jitter buffer waits for late or out-of-order chunks.
+pub const DEFAULT_AUDIO_JITTER_MS: u32 = 60;
+
+/// How often the decoder thread checks for chunks that are due.
+const JITTER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A sequence jump larger than this means the host restarted its encoder.
+const MAX_SEQUENCE_GAP: u32 = 100;
+
+/// Lost packets beyond this many are left silent instead of concealed.
+const MAX_CONCEALED_PACKETS: u32 = 3;
+
+/// Default amount of decoded audio kept queued for playback.
+pub const DEFAULT_AUDIO_LATENCY_MS: u32 = 80;
+
+/// Largest playback rate change used to correct drift (0.5% is inaudible).
+const MAX_DRIFT_CORRECTION: f64 = 0.005;
+
+/// Latency errors within this fraction of the target are left alone.
+const DRIFT_DEADBAND: f64 = 0.15;
+
+/// Beyond this much over the target, chunks are dropped to catch up quickly.
+const DRIFT_DROP_MS: f64 = 200.0;
 
 /// Audio decoder and playback resource for the client.
 /// The actual playback stream runs in a background thread to avoid Send/Sync issues.
@@ -13,21 +42,36 @@ use crate::network::protocol::AudioChunk;
 pub struct AudioDecoder {
     /// Sender for received audio chunks.
     chunk_tx: Sender<AudioChunk>,
+    /// Tells the playback thread to stop when this resource is dropped.
+    running: Arc<AtomicBool>,
+    /// Playback gain (f32 bits), applied as samples leave the ring buffer.
+    gain: Arc<AtomicU32>,
+    /// Jitter buffer target latency in milliseconds.
+    jitter_target_ms: Arc<AtomicU32>,
+    /// Playback buffer latency target in milliseconds.
+    latency_target_ms: Arc<AtomicU32>,
+    /// Times playback ran dry mid-stream since last taken.
+    underruns: Arc<AtomicU32>,
+    /// Output device chosen in the settings (`None` for the system default).
+    pub output_device: Option<String>,
 }
 
 impl AudioDecoder {
-    /// Create a new audio decoder with playback.
-    pub fn new() -> Option<Self> {
-        let host = cpal::default_host();
-        let device = host.default_output_device()?;
+    /// Create a new audio decoder with playback, delayed to stay in sync with `clock`.
+    /// Plays on the named output device, or the system default when `None`.
+    pub fn new(clock: AvSyncClock, output_device: Option<String>) -> Option<Self> {
+        let device = find_output_device(output_device.as_deref())?;
+        let device_name = output_device.clone();
         let config = Self::get_playback_config(&device)?;
 
         let sample_rate = config.sample_rate.0;
         let channels = config.channels;
 
         info!(
-            "Audio playback: {} Hz, {} channels",
-            sample_rate, channels
+            "Audio playback: {} Hz, {} channels ({})",
+            sample_rate,
+            channels,
+            device.name().unwrap_or_default()
         );
 
         // Create ring buffer for audio samples (500ms buffer)
@@ -39,52 +83,124 @@ impl AudioDecoder {
 
         let (chunk_tx, chunk_rx) = mpsc::channel::<AudioChunk>();
 
-        // Decoder thread - decodes PCM and pushes to ring buffer
+        // Decoder thread - reorders, decodes PCM/Opus and pushes to ring buffer
         let producer_clone = producer.clone();
+        let jitter_target_ms = Arc::new(AtomicU32::new(DEFAULT_AUDIO_JITTER_MS));
+        let jitter_target_clone = jitter_target_ms.clone();
+        let latency_target_ms = Arc::new(AtomicU32::new(DEFAULT_AUDIO_LATENCY_MS));
+        let latency_target_clone = latency_target_ms.clone();
         std::thread::spawn(move || {
-            let mut last_sequence: Option<u32> = None;
+            use ringbuf::traits::Observer;
+
+            let mut jitter = AudioJitterBuffer::default();
+            let mut drift = DriftCorrector::default();
+            let samples_per_ms = sample_rate as f64 * channels as f64 / 1000.0;
             let mut resample_buffer = Vec::with_capacity(4096);
+            // Last played frame, repeated to conceal lost PCM packets
+            let mut last_frame: Vec<f32> = Vec::new();
+            #[cfg(feature = "opus")]
+            let mut opus: Option<OpusState> = None;
 
             info!("Audio decoder thread started");
 
-            while let Ok(chunk) = chunk_rx.recv() {
-                // Check for packet loss (just log it for now)
-                if let Some(last_seq) = last_sequence {
-                    let expected = last_seq.wrapping_add(1);
-                    if chunk.sequence != expected {
-                        let lost = chunk.sequence.wrapping_sub(expected);
-                        if lost > 0 && lost < 100 {
-                            trace!("Audio packet loss: {} packets", lost);
+            loop {
+                match chunk_rx.recv_timeout(JITTER_POLL_INTERVAL) {
+                    Ok(chunk) => jitter.push(chunk),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                while let Ok(chunk) = chunk_rx.try_recv() {
+                    jitter.push(chunk);
+                }
+                jitter.target_latency =
+                    Duration::from_millis(jitter_target_clone.load(Ordering::Relaxed) as u64);
+
+                loop {
+                    let playback_idle = producer_clone
+                        .lock()
+                        .is_ok_and(|prod| prod.occupied_len() == 0);
+                    let Some((chunk, lost_packets)) = jitter.pop(playback_idle) else {
+                        break;
+                    };
+                    if lost_packets > 0 {
+                        trace!("Audio packet loss: {} packets", lost_packets);
+                    }
+
+                    let Some(data) = chunk.decode_data() else {
+                        continue;
+                    };
+
+                    let samples = match chunk.codec {
+                        AudioCodecKind::Pcm => {
+                            // Fill the gap before decoding the packet after it
+                            if lost_packets > 0 && !last_frame.is_empty() {
+                                let concealed = conceal(&last_frame, lost_packets, channels);
+                                if let Ok(mut prod) = producer_clone.lock() {
+                                    let _ = prod.push_slice(&concealed);
+                                }
+                            }
+                            decode_pcm(&data)
+                        }
+                        #[cfg(feature = "opus")]
+                        AudioCodecKind::Opus => {
+                            if !opus.as_ref().is_some_and(|state| state.matches(&chunk)) {
+                                opus = OpusState::new(&chunk);
+                            }
+                            match opus.as_mut() {
+                                Some(state) => state.decode(&data, lost_packets),
+                                None => continue,
+                            }
                         }
+                        #[cfg(not(feature = "opus"))]
+                        AudioCodecKind::Opus => {
+                            warn_once!("Received Opus audio but this build has no Opus support");
+                            continue;
+                        }
+                    };
+                    if samples.is_empty() {
+                        continue;
                     }
-                }
-                last_sequence = Some(chunk.sequence);
 
-                // Decode PCM data (i16 little-endian to f32)
-                let pcm_data = chunk.data();
-                if pcm_data.is_empty() || pcm_data.len() % 2 != 0 {
-                    continue;
-                }
+                    // Nudge the playback rate to hold the buffered latency near the
+                    // target. While video is shown, A/V sync decides the latency instead.
+                    let queued_ms = producer_clone
+                        .lock()
+                        .map(|prod| prod.occupied_len() as f64 / samples_per_ms)
+                        .unwrap_or(0.0);
+                    let target_ms = latency_target_clone.load(Ordering::Relaxed) as f64;
+                    let speed = if clock.video_lag_ms().is_some() {
+                        drift.reset(queued_ms);
+                        1.0
+                    } else {
+                        match drift.speed(queued_ms, target_ms) {
+                            Some(speed) => speed,
+                            None => {
+                                trace!("Audio buffer at {:.0} ms, dropping chunk", queued_ms);
+                                continue;
+                            }
+                        }
+                    };
 
-                let samples: Vec<f32> = pcm_data
-                    .chunks_exact(2)
-                    .map(|bytes| {
-                        let sample = i16::from_le_bytes([bytes[0], bytes[1]]);
-                        sample as f32 / 32768.0
-                    })
-                    .collect();
-
-                // Resample if needed and push to ring buffer
-                if let Ok(mut prod) = producer_clone.lock() {
-                    resample_and_push(
+                    // Resample if needed and push to ring buffer
+                    resample(
                         &samples,
                         chunk.sample_rate,
-                        sample_rate,
+                        (sample_rate as f64 / speed).round() as u32,
                         chunk.channels as u16,
                         channels,
                         &mut resample_buffer,
-                        &mut prod,
                     );
+                    if let Ok(mut prod) = producer_clone.lock() {
+                        push_synced(
+                            &resample_buffer,
+                            chunk.pts_ms,
+                            &clock,
+                            sample_rate,
+                            channels,
+                            &mut prod,
+                        );
+                    }
+                    last_frame.clone_from(&resample_buffer);
                 }
             }
         });
@@ -92,9 +208,14 @@ impl AudioDecoder {
         // Playback thread - runs the audio stream
         // Stream must be created in the same thread that runs it
         let consumer_clone = consumer.clone();
+        let running = Arc::new(AtomicBool::new(true));
+        let running_clone = running.clone();
+        let gain = Arc::new(AtomicU32::new(1.0f32.to_bits()));
+        let gain_clone = gain.clone();
+        let underruns = Arc::new(AtomicU32::new(0));
+        let underruns_clone = underruns.clone();
         std::thread::spawn(move || {
-            let host = cpal::default_host();
-            let device = match host.default_output_device() {
+            let device = match find_output_device(output_device.as_deref()) {
                 Some(d) => d,
                 None => {
                     error!("No audio output device found");
@@ -111,6 +232,7 @@ impl AudioDecoder {
             };
 
             let err_fn = |err| error!("Audio playback error: {}", err);
+            let mut playing = false;
 
             let stream = match device.build_output_stream(
                 &config,
@@ -123,6 +245,18 @@ impl AudioDecoder {
 
                         // Read from ring buffer
                         cons.pop_slice(&mut data[..to_read]);
+                        if playing && to_read < data.len() {
+                            underruns_clone.fetch_add(1, Ordering::Relaxed);
+                        }
+                        playing = to_read == data.len();
+
+                        // Apply volume
+                        let gain = f32::from_bits(gain_clone.load(Ordering::Relaxed));
+                        if gain != 1.0 {
+                            for sample in &mut data[..to_read] {
+                                *sample *= gain;
+                            }
+                        }
 
                         // Fill rest with silence
                         for sample in &mut data[to_read..] {
@@ -152,13 +286,21 @@ impl AudioDecoder {
 
             info!("Audio playback started");
 
-            // Keep stream alive
-            loop {
-                std::thread::sleep(std::time::Duration::from_secs(3600));
+            // Keep stream alive until the decoder is dropped
+            while running_clone.load(Ordering::Relaxed) {
+                std::thread::sleep(std::time::Duration::from_millis(100));
             }
         });
 
-        Some(Self { chunk_tx })
+        Some(Self {
+            chunk_tx,
+            running,
+            gain,
+            jitter_target_ms,
+            latency_target_ms,
+            underruns,
+            output_device: device_name,
+        })
     }
 
     fn get_playback_config(device: &cpal::Device) -> Option<StreamConfig> {
@@ -189,21 +331,284 @@ impl AudioDecoder {
         device.default_output_config().ok().map(|c| c.into())
     }
 
+    /// Set the playback volume (0.0 silences the stream).
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Set how long to wait for late/out-of-order chunks (and to prebuffer after
+    /// playback runs dry) before playing on.
+    pub fn set_jitter_target_ms(&self, ms: u32) {
+        self.jitter_target_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// Times playback ran dry mid-stream since the last call.
+    pub fn take_underruns(&self) -> u32 {
+        self.underruns.swap(0, Ordering::Relaxed)
+    }
+
+    /// Set how much decoded audio to keep queued for playback. The playback rate is
+    /// adjusted slightly to hold the queue there when no video is being synced to.
+    pub fn set_latency_target_ms(&self, ms: u32) {
+        self.latency_target_ms.store(ms, Ordering::Relaxed);
+    }
+
     /// Add a received audio chunk for decoding and playback.
     pub fn add_chunk(&self, chunk: AudioChunk) {
         let _ = self.chunk_tx.send(chunk);
     }
 }
 
+impl Drop for AudioDecoder {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Reorders audio chunks by sequence number and reports gaps.
+///
+/// A chunk is released as soon as it is the next one expected. When there is a gap,
+/// it is held for up to `target_latency` in case the missing chunks arrive late, then
+/// released with the number of chunks considered lost. After playback runs dry the
+/// buffer also waits `target_latency` before resuming, so short stalls don't cause
+/// repeated underruns.
+struct AudioJitterBuffer {
+    /// Buffered chunks in sequence order, with their arrival time.
+    chunks: VecDeque<(AudioChunk, Instant)>,
+    /// Sequence number of the next chunk to play.
+    next_sequence: Option<u32>,
+    target_latency: Duration,
+}
+
+impl Default for AudioJitterBuffer {
+    fn default() -> Self {
+        Self {
+            chunks: VecDeque::with_capacity(16),
+            next_sequence: None,
+            target_latency: Duration::from_millis(DEFAULT_AUDIO_JITTER_MS as u64),
+        }
+    }
+}
+
+impl AudioJitterBuffer {
+    fn push(&mut self, chunk: AudioChunk) {
+        if let Some(next) = self.next_sequence {
+            let behind = next.wrapping_sub(chunk.sequence);
+            if behind > 0 && behind <= MAX_SEQUENCE_GAP {
+                // Arrived after we gave up on it
+                trace!("Dropping late audio chunk {}", chunk.sequence);
+                return;
+            }
+            if behind > MAX_SEQUENCE_GAP && chunk.sequence.wrapping_sub(next) > MAX_SEQUENCE_GAP {
+                info!("Audio sequence reset ({} after {}), clearing jitter buffer", chunk.sequence, next);
+                self.chunks.clear();
+                self.next_sequence = None;
+            }
+        }
+
+        // Insert sorted by sequence (wrapping-aware), dropping duplicates
+        let mut pos = self.chunks.len();
+        for (i, (buffered, _)) in self.chunks.iter().enumerate() {
+            let diff = buffered.sequence.wrapping_sub(chunk.sequence) as i32;
+            if diff == 0 {
+                return;
+            }
+            if diff > 0 {
+                pos = i;
+                break;
+            }
+        }
+        self.chunks.insert(pos, (chunk, Instant::now()));
+    }
+
+    /// Next chunk to play and how many chunks were lost right before it.
+    fn pop(&mut self, playback_idle: bool) -> Option<(AudioChunk, u32)> {
+        let (front, arrived) = self.chunks.front()?;
+        let waited = arrived.elapsed() >= self.target_latency;
+
+        let lost = match self.next_sequence {
+            Some(next) => front.sequence.wrapping_sub(next),
+            None => 0,
+        };
+        if (lost > 0 || playback_idle) && !waited {
+            return None;
+        }
+
+        let (chunk, _) = self.chunks.pop_front()?;
+        self.next_sequence = Some(chunk.sequence.wrapping_add(1));
+        Some((chunk, lost))
+    }
+}
+
+/// Tracks playback buffer occupancy and picks a playback speed that slowly steers
+/// it back to the target, so latency doesn't creep up when the host's clock runs
+/// slightly faster than ours (or drain into underruns when it runs slower).
+#[derive(Default)]
+struct DriftCorrector {
+    /// Smoothed queued audio in milliseconds.
+    smoothed_ms: Option<f64>,
+}
+
+impl DriftCorrector {
+    fn reset(&mut self, queued_ms: f64) {
+        self.smoothed_ms = Some(queued_ms);
+    }
+
+    /// Speed factor for the next chunk (above 1.0 plays faster), or `None` if the
+    /// chunk should be dropped because far too much audio is queued.
+    fn speed(&mut self, queued_ms: f64, target_ms: f64) -> Option<f64> {
+        let smoothed = match self.smoothed_ms {
+            Some(previous) => previous + (queued_ms - previous) * 0.05,
+            None => queued_ms,
+        };
+        self.smoothed_ms = Some(smoothed);
+
+        if queued_ms > target_ms + DRIFT_DROP_MS {
+            return None;
+        }
+
+        let error = (smoothed - target_ms) / target_ms.max(1.0);
+        if error.abs() < DRIFT_DEADBAND {
+            return Some(1.0);
+        }
+        Some(1.0 + (error * 0.01).clamp(-MAX_DRIFT_CORRECTION, MAX_DRIFT_CORRECTION))
+    }
+}
+
+/// Stand-in audio for `lost` packets: the last frame repeated, fading out.
+fn conceal(last_frame: &[f32], lost: u32, channels: u16) -> Vec<f32> {
+    let channels = channels.max(1) as usize;
+    let frames = lost.min(MAX_CONCEALED_PACKETS) as usize;
+    let total = last_frame.len() * frames;
+    let mut out = Vec::with_capacity(total);
+
+    for repeat in 0..frames {
+        for (i, sample) in last_frame.iter().enumerate() {
+            // Linear fade across all concealed samples, per sample frame
+            let position = (repeat * last_frame.len() + i - i % channels) as f32;
+            let fade = 1.0 - position / total as f32;
+            out.push(sample * fade);
+        }
+    }
+    out
+}
+
+/// Decode little-endian i16 PCM to f32 samples.
+fn decode_pcm(data: &[u8]) -> Vec<f32> {
+    if !data.len().is_multiple_of(2) {
+        return Vec::new();
+    }
+
+    data.chunks_exact(2)
+        .map(|bytes| {
+            let sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+            sample as f32 / 32768.0
+        })
+        .collect()
+}
+
+/// Max Opus frame is 120 ms; at 48 kHz stereo that is 11520 samples.
+#[cfg(feature = "opus")]
+const MAX_OPUS_FRAME_SAMPLES: usize = 48000 * 120 / 1000 * 2;
+
+/// Opus decoder for the current stream format.
+#[cfg(feature = "opus")]
+struct OpusState {
+    decoder: opus::Decoder,
+    sample_rate: u32,
+    channels: u8,
+    output: Vec<f32>,
+}
+
+#[cfg(feature = "opus")]
+impl OpusState {
+    fn new(chunk: &AudioChunk) -> Option<Self> {
+        let channels = match chunk.channels {
+            1 => opus::Channels::Mono,
+            2 => opus::Channels::Stereo,
+            n => {
+                error!("Unsupported Opus channel count: {}", n);
+                return None;
+            }
+        };
+        let decoder = opus::Decoder::new(chunk.sample_rate, channels)
+            .map_err(|e| error!("Failed to create Opus decoder: {}", e))
+            .ok()?;
+
+        Some(Self {
+            decoder,
+            sample_rate: chunk.sample_rate,
+            channels: chunk.channels,
+            output: vec![0.0; MAX_OPUS_FRAME_SAMPLES],
+        })
+    }
+
+    fn matches(&self, chunk: &AudioChunk) -> bool {
+        self.sample_rate == chunk.sample_rate && self.channels == chunk.channels
+    }
+
+    /// Decode a packet, concealing up to a few lost packets before it.
+    fn decode(&mut self, data: &[u8], lost_packets: u32) -> Vec<f32> {
+        let channels = self.channels as usize;
+        let mut samples = Vec::new();
+
+        for _ in 0..lost_packets.min(MAX_CONCEALED_PACKETS) {
+            if let Ok(frames) = self.decoder.decode_float(&[], &mut self.output, false) {
+                samples.extend_from_slice(&self.output[..frames * channels]);
+            }
+        }
+
+        match self.decoder.decode_float(data, &mut self.output, false) {
+            Ok(frames) => samples.extend_from_slice(&self.output[..frames * channels]),
+            Err(e) => warn!("Opus decode error: {}", e),
+        }
+        samples
+    }
+}
+
+/// Push playback samples, padding with silence or dropping samples so the audio
+/// plays out with the same lag as the displayed video.
+fn push_synced(
+    samples: &[f32],
+    pts_ms: u64,
+    clock: &AvSyncClock,
+    sample_rate: u32,
+    channels: u16,
+    producer: &mut ringbuf::HeapProd<f32>,
+) {
+    use ringbuf::traits::Observer;
+
+    let samples_per_ms = (sample_rate as usize * channels as usize / 1000).max(1);
+    let queued_ms = (producer.occupied_len() / samples_per_ms) as i64;
+    let correction_ms = clock.audio_correction_ms(pts_ms, queued_ms);
+
+    let mut samples = samples;
+    if correction_ms > 0 {
+        // Audio is ahead of the picture: hold it back with silence
+        let silence = (correction_ms as usize * samples_per_ms).min(producer.vacant_len());
+        let silence = silence - silence % channels.max(1) as usize;
+        for _ in 0..silence {
+            let _ = producer.try_push(0.0);
+        }
+    } else if correction_ms < 0 {
+        // Audio is behind the picture: skip ahead
+        let skip = (-correction_ms as usize * samples_per_ms).min(samples.len());
+        let skip = skip - skip % channels.max(1) as usize;
+        samples = &samples[skip..];
+    }
+
+    // Push to ring buffer (non-blocking, drops newest if full)
+    let _ = producer.push_slice(samples);
+}
+
 /// Simple linear resampling and channel conversion.
-fn resample_and_push(
+fn resample(
     samples: &[f32],
     src_rate: u32,
     dst_rate: u32,
     src_channels: u16,
     dst_channels: u16,
     buffer: &mut Vec<f32>,
-    producer: &mut ringbuf::HeapProd<f32>,
 ) {
     buffer.clear();
 
@@ -249,7 +654,4 @@ fn resample_and_push(
             buffer.push(sample);
         }
     }
-
-    // Push to ring buffer (non-blocking, drops oldest if full)
-    let _ = producer.push_slice(buffer);
 }
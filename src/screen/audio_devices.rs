@@ -0,0 +1,105 @@
+//! Audio device enumeration for the settings menu.
+//!
+//! Output devices are playback devices for the stream audio. Capture devices are
+//! what the host records system audio from, which differs per platform:
+//! render endpoints (WASAPI loopback) on Windows, PulseAudio sources on Linux and
+//! input devices such as BlackHole on macOS.
+
+use bevy::prelude::*;
+use cpal::traits::{DeviceTrait, HostTrait};
+
+/// Names of all playback devices.
+pub fn output_devices() -> Vec<String> {
+    let host = cpal::default_host();
+    match host.output_devices() {
+        Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+        Err(e) => {
+            warn!("Failed to enumerate audio output devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Find a playback device by name, falling back to the default device.
+pub fn find_output_device(name: Option<&str>) -> Option<cpal::Device> {
+    let host = cpal::default_host();
+    if let Some(name) = name {
+        let found = host
+            .output_devices()
+            .ok()
+            .and_then(|mut devices| devices.find(|d| d.name().is_ok_and(|n| n == name)));
+        match found {
+            Some(device) => return Some(device),
+            None => warn!("Audio output device '{}' not found, using default", name),
+        }
+    }
+    host.default_output_device()
+}
+
+/// Names of devices system audio can be captured from.
+pub fn capture_devices() -> Vec<String> {
+    #[cfg(windows)]
+    {
+        // WASAPI can loopback-record any render endpoint
+        output_devices()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        pulse_sources()
+    }
+
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        let host = cpal::default_host();
+        match host.input_devices() {
+            Ok(devices) => devices.filter_map(|d| d.name().ok()).collect(),
+            Err(e) => {
+                warn!("Failed to enumerate audio input devices: {}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Find a device to capture from by name (see [`capture_devices`]).
+#[cfg(any(windows, target_os = "macos"))]
+pub fn find_capture_device(name: &str) -> Option<cpal::Device> {
+    let host = cpal::default_host();
+    #[cfg(windows)]
+    let devices = host.output_devices();
+    #[cfg(not(windows))]
+    let devices = host.input_devices();
+
+    let found = devices
+        .ok()
+        .and_then(|mut devices| devices.find(|d| d.name().is_ok_and(|n| n == name)));
+    if found.is_none() {
+        warn!("Audio capture device '{}' not found", name);
+    }
+    found
+}
+
+/// PulseAudio/PipeWire source names, monitors first since those carry system audio.
+#[cfg(target_os = "linux")]
+fn pulse_sources() -> Vec<String> {
+    let output = match std::process::Command::new("pactl")
+        .args(["list", "short", "sources"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("Failed to list PulseAudio sources: {}", e);
+            return Vec::new();
+        }
+    };
+
+    // Each line is: index, name, driver, format, state (tab separated)
+    let mut sources: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .map(str::to_string)
+        .collect();
+    sources.sort_by_key(|name| !name.ends_with(".monitor"));
+    sources
+}
@@ -4,14 +4,37 @@ use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 
-use crate::network::protocol::{AudioChunk, ServerMessage};
+use crate::network::protocol::{AudioChunk, AudioCodecKind, ServerMessage, BASE64_CHUNK_SIZE};
+
+/// Default Opus bitrate for 48 kHz stereo system audio, in kbit/s.
+pub const DEFAULT_OPUS_BITRATE_KBPS: u32 = 128;
+
+/// Opus frame duration in milliseconds.
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+const OPUS_FRAME_MS: u32 = 20;
+
+/// Sample rates Opus encodes as they are. Anything else, such as 44.1 kHz,
+/// is resampled to `OPUS_SAMPLE_RATE` first.
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+const OPUS_RATES: [u32; 5] = [8_000, 12_000, 16_000, 24_000, 48_000];
+
+/// Sample rate Opus is fed at when the capture rate isn't one of `OPUS_RATES`.
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+
+/// Max size of a single UDP audio payload, before base64 encoding.
+const MAX_CHUNK_SIZE: usize = BASE64_CHUNK_SIZE;
 
 /// Audio encoder resource for streaming.
-/// Currently uses raw PCM with simple compression for simplicity.
+/// Encodes Opus in 20 ms frames when available, falling back to raw PCM.
 #[derive(Resource)]
 pub struct AudioEncoder {
     /// Sender for raw audio samples to encode.
     tx: Sender<AudioFrame>,
+    /// Sender for codec switches requested by negotiation.
+    send_codec: Mutex<Sender<AudioCodecKind>>,
+    /// Sender for Opus bitrate changes from the settings.
+    send_bitrate: Mutex<Sender<i32>>,
     /// Receiver for encoded audio chunks.
     rx: Arc<Mutex<Receiver<EncodedAudio>>>,
     /// Encoder thread handle.
@@ -22,71 +45,62 @@ struct AudioFrame {
     samples: Vec<f32>,
     sample_rate: u32,
     channels: u16,
+    pts_ms: u64,
 }
 
 pub struct EncodedAudio {
     pub data: Vec<u8>,
     pub sample_rate: u32,
     pub channels: u8,
+    pub codec: AudioCodecKind,
     pub sequence: u32,
+    pub pts_ms: u64,
 }
 
 impl AudioEncoder {
-    /// Create a new audio encoder.
-    pub fn new(sample_rate: u32, channels: u16) -> Option<Self> {
+    /// Create a new audio encoder. `bitrate` only applies to Opus.
+    pub fn new(sample_rate: u32, channels: u16, bitrate: i32) -> Option<Self> {
         let (input_tx, input_rx) = mpsc::channel::<AudioFrame>();
+        let (codec_tx, codec_rx) = mpsc::channel::<AudioCodecKind>();
+        let (bitrate_tx, bitrate_rx) = mpsc::channel::<i32>();
         let (output_tx, output_rx) = mpsc::channel::<EncodedAudio>();
         let output_rx = Arc::new(Mutex::new(output_rx));
 
         let thread = thread::spawn(move || {
-            let mut sequence: u32 = 0;
-
-            info!("Audio encoder started: {} Hz, {} channels (PCM)", sample_rate, channels);
-
-            while let Ok(frame) = input_rx.recv() {
-                // Convert f32 samples to i16 for transmission (reduces size by half)
-                let samples_i16: Vec<i16> = frame
-                    .samples
-                    .iter()
-                    .map(|&s| (s.clamp(-1.0, 1.0) * 32767.0) as i16)
-                    .collect();
-
-                // Convert i16 samples to bytes (little-endian)
-                let mut data = Vec::with_capacity(samples_i16.len() * 2);
-                for sample in samples_i16 {
-                    data.extend_from_slice(&sample.to_le_bytes());
-                }
-
-                // Split into chunks to fit in UDP packets (max ~1200 bytes to be safe)
-                const MAX_CHUNK_SIZE: usize = 1200;
-                for chunk_data in data.chunks(MAX_CHUNK_SIZE) {
-                    let encoded = EncodedAudio {
-                        data: chunk_data.to_vec(),
-                        sample_rate: frame.sample_rate,
-                        channels: frame.channels as u8,
-                        sequence,
-                    };
-                    sequence = sequence.wrapping_add(1);
-                    if output_tx.send(encoded).is_err() {
-                        return; // Receiver dropped
-                    }
-                }
-            }
+            info!("Audio encoder started: {} Hz, {} channels", sample_rate, channels);
+            run_encoder_thread(input_rx, codec_rx, bitrate_rx, output_tx, bitrate);
         });
 
         Some(Self {
             tx: input_tx,
+            send_codec: Mutex::new(codec_tx),
+            send_bitrate: Mutex::new(bitrate_tx),
             rx: output_rx,
             _thread: thread,
         })
     }
 
+    /// Switch the codec used for subsequent audio.
+    pub fn set_codec(&self, codec: AudioCodecKind) {
+        if let Ok(tx) = self.send_codec.lock() {
+            let _ = tx.send(codec);
+        }
+    }
+
+    /// Change the Opus bitrate, from the next packet on.
+    pub fn set_bitrate(&self, bitrate: i32) {
+        if let Ok(tx) = self.send_bitrate.lock() {
+            let _ = tx.send(bitrate);
+        }
+    }
+
     /// Submit audio samples for encoding.
-    pub fn submit_samples(&self, samples: Vec<f32>, sample_rate: u32, channels: u16) {
+    pub fn submit_samples(&self, samples: Vec<f32>, sample_rate: u32, channels: u16, pts_ms: u64) {
         let _ = self.tx.send(AudioFrame {
             samples,
             sample_rate,
             channels,
+            pts_ms,
         });
     }
 
@@ -96,6 +110,261 @@ impl AudioEncoder {
     }
 }
 
+fn run_encoder_thread(
+    input_rx: Receiver<AudioFrame>,
+    codec_rx: Receiver<AudioCodecKind>,
+    bitrate_rx: Receiver<i32>,
+    output_tx: Sender<EncodedAudio>,
+    mut bitrate: i32,
+) {
+    let mut sequence: u32 = 0;
+    let mut codec = AudioCodecKind::Pcm;
+    #[cfg(feature = "opus")]
+    let mut opus: Option<OpusState> = None;
+
+    while let Ok(frame) = input_rx.recv() {
+        while let Ok(requested) = bitrate_rx.try_recv() {
+            bitrate = requested;
+            #[cfg(feature = "opus")]
+            if let Some(state) = opus.as_mut() {
+                state.set_bitrate(bitrate);
+            }
+        }
+        #[cfg(not(feature = "opus"))]
+        let _ = bitrate;
+        while let Ok(requested) = codec_rx.try_recv() {
+            if requested != codec {
+                info!("Audio encoder switching to {}", requested.name());
+                codec = requested;
+                #[cfg(feature = "opus")]
+                {
+                    opus = None;
+                }
+            }
+        }
+
+        let packets = match codec {
+            #[cfg(feature = "opus")]
+            AudioCodecKind::Opus => {
+                if !opus.as_ref().is_some_and(|state| state.matches(&frame)) {
+                    opus = OpusState::new(&frame, bitrate);
+                }
+                match opus.as_mut() {
+                    Some(state) => state.encode(&frame.samples, frame.pts_ms),
+                    None => {
+                        // Opus only takes mono or stereo
+                        warn!(
+                            "Opus unavailable for {} channels, falling back to PCM",
+                            frame.channels
+                        );
+                        codec = AudioCodecKind::Pcm;
+                        encode_pcm(&frame)
+                    }
+                }
+            }
+            _ => encode_pcm(&frame),
+        };
+        #[cfg(feature = "opus")]
+        let sample_rate = match (codec, opus.as_ref()) {
+            (AudioCodecKind::Opus, Some(state)) => state.sample_rate,
+            _ => frame.sample_rate,
+        };
+        #[cfg(not(feature = "opus"))]
+        let sample_rate = frame.sample_rate;
+
+        for (pts_ms, data) in packets {
+            let encoded = EncodedAudio {
+                data,
+                sample_rate,
+                channels: frame.channels as u8,
+                codec,
+                sequence,
+                pts_ms,
+            };
+            sequence = sequence.wrapping_add(1);
+            if output_tx.send(encoded).is_err() {
+                return; // Receiver dropped
+            }
+        }
+    }
+}
+
+/// Convert f32 samples to little-endian i16 PCM split into UDP-sized chunks,
+/// each tagged with the timestamp of its first sample.
+fn encode_pcm(frame: &AudioFrame) -> Vec<(u64, Vec<u8>)> {
+    let mut data = Vec::with_capacity(frame.samples.len() * 2);
+    for &sample in &frame.samples {
+        let sample = (sample.clamp(-1.0, 1.0) * 32767.0) as i16;
+        data.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    // Bytes per second of interleaved i16 audio
+    let byte_rate = (frame.sample_rate as u64 * frame.channels as u64 * 2).max(1);
+    data.chunks(MAX_CHUNK_SIZE)
+        .enumerate()
+        .map(|(idx, chunk)| {
+            let offset_ms = (idx * MAX_CHUNK_SIZE) as u64 * 1000 / byte_rate;
+            (frame.pts_ms + offset_ms, chunk.to_vec())
+        })
+        .collect()
+}
+
+/// Opus encoder plus the samples left over from the last partial frame.
+#[cfg(feature = "opus")]
+struct OpusState {
+    encoder: opus::Encoder,
+    /// Rate of the captured audio.
+    input_rate: u32,
+    /// Rate the encoder runs at, which the chunks are tagged with.
+    sample_rate: u32,
+    channels: u16,
+    /// Converts the capture to `sample_rate` when Opus can't take it as is.
+    resampler: Option<Resampler>,
+    resampled: Vec<f32>,
+    frame_len: usize,
+    pending: Vec<f32>,
+    /// Timestamp of the first sample in `pending`.
+    pending_pts_ms: u64,
+    output: Vec<u8>,
+}
+
+#[cfg(feature = "opus")]
+impl OpusState {
+    fn new(frame: &AudioFrame, bitrate: i32) -> Option<Self> {
+        let channels = match frame.channels {
+            1 => opus::Channels::Mono,
+            2 => opus::Channels::Stereo,
+            _ => return None,
+        };
+        let sample_rate = if OPUS_RATES.contains(&frame.sample_rate) {
+            frame.sample_rate
+        } else {
+            OPUS_SAMPLE_RATE
+        };
+        let encoder = opus::Encoder::new(sample_rate, channels, opus::Application::Audio)
+            .map_err(|e| error!("Failed to create Opus encoder: {}", e))
+            .ok()?;
+        let resampler = (sample_rate != frame.sample_rate).then(|| {
+            info!("Resampling {} Hz audio for Opus", frame.sample_rate);
+            Resampler::new(frame.sample_rate, sample_rate, frame.channels)
+        });
+        let mut state = Self {
+            encoder,
+            input_rate: frame.sample_rate,
+            sample_rate,
+            channels: frame.channels,
+            resampler,
+            resampled: Vec::new(),
+            frame_len: (sample_rate * OPUS_FRAME_MS / 1000) as usize * frame.channels as usize,
+            pending: Vec::new(),
+            pending_pts_ms: frame.pts_ms,
+            output: vec![0; MAX_CHUNK_SIZE],
+        };
+        state.set_bitrate(bitrate);
+
+        info!("Opus encoder: {} Hz, {} channels, {} bps", sample_rate, frame.channels, bitrate);
+
+        Some(state)
+    }
+
+    fn set_bitrate(&mut self, bitrate: i32) {
+        if let Err(e) = self.encoder.set_bitrate(opus::Bitrate::Bits(bitrate)) {
+            warn!("Failed to set Opus bitrate: {}", e);
+        }
+    }
+
+    fn matches(&self, frame: &AudioFrame) -> bool {
+        self.input_rate == frame.sample_rate && self.channels == frame.channels
+    }
+
+    /// Buffer samples and encode every complete 20 ms frame.
+    fn encode(&mut self, samples: &[f32], pts_ms: u64) -> Vec<(u64, Vec<u8>)> {
+        if self.pending.is_empty() {
+            self.pending_pts_ms = pts_ms;
+        }
+        match self.resampler.as_mut() {
+            Some(resampler) => {
+                self.resampled.clear();
+                resampler.process(samples, &mut self.resampled);
+                self.pending.extend_from_slice(&self.resampled);
+            }
+            None => self.pending.extend_from_slice(samples),
+        }
+
+        let mut packets = Vec::new();
+        let mut consumed = 0;
+        while self.pending.len() - consumed >= self.frame_len {
+            let frame = &self.pending[consumed..consumed + self.frame_len];
+            consumed += self.frame_len;
+            match self.encoder.encode_float(frame, &mut self.output) {
+                Ok(len) => packets.push((self.pending_pts_ms, self.output[..len].to_vec())),
+                Err(e) => warn!("Opus encode error: {}", e),
+            }
+            self.pending_pts_ms += OPUS_FRAME_MS as u64;
+        }
+        self.pending.drain(..consumed);
+        packets
+    }
+}
+
+/// Linear resampler for interleaved audio. It keeps its position and the
+/// last input frame between calls, so consecutive buffers join up smoothly.
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+struct Resampler {
+    channels: usize,
+    /// Input frames advanced per output frame.
+    step: f64,
+    /// Position of the next output frame, in input frames from `last`.
+    position: f64,
+    /// Last input frame of the previous call.
+    last: Vec<f32>,
+    input: Vec<f32>,
+}
+
+#[cfg_attr(not(feature = "opus"), allow(dead_code))]
+impl Resampler {
+    fn new(from_rate: u32, to_rate: u32, channels: u16) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            step: from_rate as f64 / to_rate as f64,
+            position: 0.0,
+            last: Vec::new(),
+            input: Vec::new(),
+        }
+    }
+
+    /// Append `samples`, resampled, to `out`.
+    fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        let channels = self.channels;
+        self.input.clear();
+        self.input.extend_from_slice(&self.last);
+        self.input.extend_from_slice(samples);
+        let frames = self.input.len() / channels;
+        if frames == 0 {
+            return;
+        }
+
+        while self.position + 1.0 < frames as f64 {
+            let index = self.position as usize;
+            let frac = (self.position - index as f64) as f32;
+            let (from, to) = (index * channels, (index + 1) * channels);
+            for channel in 0..channels {
+                let a = self.input[from + channel];
+                let b = self.input[to + channel];
+                out.push(a + (b - a) * frac);
+            }
+            self.position += self.step;
+        }
+
+        // Keep the final frame to interpolate from next time
+        let kept = frames - 1;
+        self.position -= kept as f64;
+        self.last.clear();
+        self.last
+            .extend_from_slice(&self.input[kept * channels..frames * channels]);
+    }
+}
+
 /// Resource for sending audio over the network.
 #[derive(Resource)]
 pub struct AudioSender {
@@ -125,3 +394,39 @@ impl AudioSender {
         let _ = self.tx.send((chunk, clients));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resampling_follows_the_signal_across_buffers() {
+        let mut resampler = Resampler::new(44_100, 48_000, 1);
+        let mut out = Vec::new();
+        // A ramp whose value is its frame index, fed in uneven buffers
+        let ramp: Vec<f32> = (0..4410).map(|i| i as f32).collect();
+        for buffer in ramp.chunks(441 + 7) {
+            resampler.process(buffer, &mut out);
+        }
+
+        // 0.1 s of input makes 0.1 s of output, give or take the held frame
+        assert!((4798..=4800).contains(&out.len()), "{} frames", out.len());
+        for (i, &sample) in out.iter().enumerate() {
+            let expected = i as f64 * 44_100.0 / 48_000.0;
+            assert!((sample as f64 - expected).abs() < 0.01, "frame {}", i);
+        }
+    }
+
+    #[test]
+    fn resampling_keeps_channels_apart() {
+        let mut resampler = Resampler::new(44_100, 48_000, 2);
+        let mut out = Vec::new();
+        let stereo: Vec<f32> = [0.25, -0.5].repeat(441);
+        resampler.process(&stereo, &mut out);
+
+        assert_eq!(out.len() % 2, 0);
+        for frame in out.chunks(2) {
+            assert_eq!(frame, [0.25, -0.5]);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Client-side audio/video synchronization.
+//!
+//! Video frames wait in a jitter buffer before display while audio is played as soon
+//! as it arrives. Both streams carry presentation timestamps from the host stream clock,
+//! so the client measures how far behind the host each stream is presented ("lag") and
+//! delays audio in the playback ring buffer until it matches the video lag.
+
+use bevy::prelude::*;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Audio is considered in sync within this window (lip-sync is noticeable past ~45 ms).
+pub const SYNC_TOLERANCE_MS: i64 = 40;
+
+/// Video lag measurements older than this are ignored (screen share stopped).
+const VIDEO_LAG_STALE_MS: i64 = 1000;
+
+/// Marker for "no video lag measured yet".
+const UNKNOWN: i64 = i64::MIN;
+
+/// Shared clock relating host presentation timestamps to local playback time.
+/// Cheap to clone; the video path records lag and the audio thread reads it.
+#[derive(Resource, Clone)]
+pub struct AvSyncClock {
+    inner: Arc<ClockState>,
+}
+
+struct ClockState {
+    epoch: Instant,
+    /// Smoothed (local release time - host pts) of displayed video frames.
+    video_lag_ms: AtomicI64,
+    /// Local time the video lag was last updated.
+    video_updated_ms: AtomicI64,
+}
+
+impl Default for AvSyncClock {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(ClockState {
+                epoch: Instant::now(),
+                video_lag_ms: AtomicI64::new(UNKNOWN),
+                video_updated_ms: AtomicI64::new(UNKNOWN),
+            }),
+        }
+    }
+}
+
+impl AvSyncClock {
+    /// Current local time in milliseconds.
+    pub fn now_ms(&self) -> i64 {
+        self.inner.epoch.elapsed().as_millis() as i64
+    }
+
+    /// Record that a video frame with the given host timestamp is being displayed now.
+    pub fn record_video_release(&self, pts_ms: u64) {
+        let now = self.now_ms();
+        let lag = now - pts_ms as i64;
+        let previous = self.inner.video_lag_ms.load(Ordering::Relaxed);
+        let last_update = self.inner.video_updated_ms.load(Ordering::Relaxed);
+
+        // Smooth out per-frame jitter, but jump straight to the first/fresh measurement
+        let smoothed = if previous == UNKNOWN || now - last_update > VIDEO_LAG_STALE_MS {
+            lag
+        } else {
+            previous + (lag - previous) / 10
+        };
+
+        self.inner.video_lag_ms.store(smoothed, Ordering::Relaxed);
+        self.inner.video_updated_ms.store(now, Ordering::Relaxed);
+    }
+
+    /// Current video lag, or `None` if no video has been displayed recently.
+    pub fn video_lag_ms(&self) -> Option<i64> {
+        let lag = self.inner.video_lag_ms.load(Ordering::Relaxed);
+        let last_update = self.inner.video_updated_ms.load(Ordering::Relaxed);
+        if lag == UNKNOWN || self.now_ms() - last_update > VIDEO_LAG_STALE_MS {
+            None
+        } else {
+            Some(lag)
+        }
+    }
+
+    /// How much extra delay (positive) or catch-up (negative) audio with host timestamp
+    /// `pts_ms` needs, given `queued_ms` of audio already waiting to be played.
+    /// Returns 0 while in sync or when there is no video to follow.
+    pub fn audio_correction_ms(&self, pts_ms: u64, queued_ms: i64) -> i64 {
+        let Some(video_lag) = self.video_lag_ms() else {
+            return 0;
+        };
+
+        let audio_lag = self.now_ms() + queued_ms - pts_ms as i64;
+        let correction = video_lag - audio_lag;
+        if correction.abs() > SYNC_TOLERANCE_MS {
+            correction
+        } else {
+            0
+        }
+    }
+}
@@ -1,20 +1,21 @@
 use bevy::prelude::*;
 use bevy::render::render_asset::RenderAssetUsages;
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
-use scrap::{Capturer, Display};
-use std::io::ErrorKind;
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::Mutex;
+use bevy::window::{PrimaryWindow, RawHandleWrapper};
 use std::time::{Duration, Instant};
 
+use crate::menu::ErrorNotification;
+use crate::network::protocol::ScreenId;
 use crate::world::Screen;
 use crate::world::setup::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use super::display_capture::DisplayCapture;
+use super::preview::LocalPreview;
 use super::streaming::LatestCapturedFrame;
-use super::window_capture::{start_wgc_capture, WgcCapturedFrame};
+use super::window_capture::{set_excluded_from_capture, WindowCapture};
 use super::ScreenDimensions;
 
 /// Type of capture source
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CaptureSourceType {
     Display(usize),
     Window(isize), // HWND on Windows
@@ -24,60 +25,164 @@ pub enum CaptureSourceType {
 #[derive(Event)]
 pub struct CaptureSource {
     pub source: CaptureSourceType,
+    /// Room screen to show the capture on.
+    pub screen: ScreenId,
+    /// Name of the source, as the share UI lists it.
+    pub label: String,
 }
 
-/// Resource holding the screen texture handle.
+/// Event to stop capturing whatever is shared.
+#[derive(Event)]
+pub struct StopCapture;
+
+/// Resource describing what the host is sharing, kept up to date as captures
+/// start, stop or end on their own.
 #[derive(Resource, Default)]
+pub struct SharingState {
+    /// Source being captured, `None` while nothing is shared.
+    pub source: Option<CaptureSourceType>,
+    /// Name of the source, e.g. "Display 2 (1920x1080)".
+    pub label: String,
+    /// Room screen the capture is shown on.
+    pub screen: ScreenId,
+}
+
+/// Texture and material currently shown on a screen.
+#[derive(Component, Default)]
 pub struct ScreenTexture {
     pub handle: Option<Handle<Image>>,
     pub material_handle: Option<Handle<StandardMaterial>>,
 }
 
-/// NonSend resource for active display capture (must run on main thread).
-pub struct ActiveDisplayCapture {
-    pub capturer: Capturer,
+/// A frame from a capture source, as RGBA with the bottom row first.
+pub struct CapturedFrame {
+    pub rgba: Vec<u8>,
     pub width: u32,
     pub height: u32,
-    pub last_capture: Instant,
-    pub capture_interval: Duration,
-    pub frame_count: u32,
-    pub would_block_count: u32,
-    pub fps_counter: u32,
-    pub fps_timer: Instant,
 }
 
-/// Resource for active window capture with background thread (using WGC)
-#[derive(Resource)]
-pub struct ActiveWindowCapture {
-    pub hwnd: isize,
-    pub width: u32,
-    pub height: u32,
+/// What polling a capture source gave.
+pub enum CapturePoll {
+    /// A new frame to show and stream.
+    Frame(CapturedFrame),
+    /// Nothing new yet.
+    Pending,
+    /// The source is there but sends nothing, e.g. a minimized window.
+    Paused,
+    /// The source changed, e.g. a display's resolution, and has to be
+    /// started again.
+    Restart,
+    /// The source is gone, with why to tell the host unless it's obvious.
+    Ended(Option<String>),
+}
+
+/// A source of frames to share. Each kind of source is started by its own
+/// `start` constructor, then polled once a frame on the main thread, so
+/// backends need not be `Send`.
+pub trait CaptureBackend {
+    /// Size of the frames, if known yet.
+    fn dimensions(&self) -> Option<(u32, u32)>;
+
+    /// The latest frame, if there's a new one.
+    fn poll_frame(&mut self) -> CapturePoll;
+
+    /// Stop capturing, for backends capturing on threads of their own.
+    fn stop(&mut self) {}
+}
+
+/// Start capturing a source, or say why it can't be.
+fn start_backend(source: CaptureSourceType) -> Result<Box<dyn CaptureBackend>, String> {
+    Ok(match source {
+        CaptureSourceType::Display(screen_index) => Box::new(DisplayCapture::start(screen_index)?),
+        CaptureSourceType::Window(hwnd) => Box::new(WindowCapture::start(hwnd)?),
+    })
+}
+
+/// NonSend resource for the active capture, polled by `process_capture`.
+pub struct ActiveCapture {
+    pub source: CaptureSourceType,
+    pub backend: Box<dyn CaptureBackend>,
     pub frame_count: u32,
-    pub frame_receiver: Mutex<Receiver<WgcCapturedFrame>>,
-    pub stop_sender: Mutex<Sender<()>>,
     pub fps_counter: u32,
     pub fps_timer: Instant,
+    /// When the placeholder shown while the source is paused was last sent,
+    /// `None` while it isn't.
+    pub paused_since: Option<Instant>,
 }
 
+/// How often the placeholder is sent again while a source is paused, so
+/// clients joining meanwhile get it and the encoder keeps going.
+const PAUSED_FRAME_INTERVAL: Duration = Duration::from_millis(500);
+
 /// Resource to signal that capture should start.
 #[derive(Resource)]
 pub struct PendingCapture {
     pub source: CaptureSourceType,
+    pub screen: ScreenId,
+    pub label: String,
 }
 
+/// Resource to signal that capture should stop.
+#[derive(Resource)]
+pub struct PendingCaptureStop;
+
+/// Resource holding the room screen the active capture is shown on.
+#[derive(Resource)]
+pub struct CaptureTarget(pub ScreenId);
+
 /// Handle capture events and create pending capture.
 pub fn handle_capture_events(
     mut events: EventReader<CaptureSource>,
+    mut stop_events: EventReader<StopCapture>,
     mut commands: Commands,
 ) {
+    if stop_events.read().count() > 0 {
+        commands.insert_resource(PendingCaptureStop);
+    }
     for event in events.read() {
-        info!("Capture event received: {:?}", event.source);
+        info!(
+            "Capture event received: {:?} on screen {}",
+            event.source, event.screen
+        );
         commands.insert_resource(PendingCapture {
             source: event.source,
+            screen: event.screen,
+            label: event.label.clone(),
         });
     }
 }
 
+/// Console command stopping whatever the host is sharing.
+pub fn stream_stop_command(
+    In(_): In<Vec<String>>,
+    sharing: Res<SharingState>,
+    mut stop_events: EventWriter<StopCapture>,
+) -> String {
+    if sharing.source.is_none() {
+        return "Nothing is being shared".to_string();
+    }
+    stop_events.send(StopCapture);
+    format!("Stopped sharing {}", sharing.label)
+}
+
+/// Stop the active capture, if any, and stop showing it as shared.
+fn stop_active_capture(world: &mut World) {
+    // Stop background capture threads if running
+    if let Some(mut capture) = world.remove_non_send_resource::<ActiveCapture>() {
+        capture.backend.stop();
+    }
+    world.remove_resource::<CaptureTarget>();
+    world.insert_resource(SharingState::default());
+}
+
+/// Exclusive system to stop capture when asked to.
+pub fn stop_capture(world: &mut World) {
+    if world.remove_resource::<PendingCaptureStop>().is_some() {
+        info!("Stopping capture");
+        stop_active_capture(world);
+    }
+}
+
 /// Exclusive system to start capture (display or window).
 pub fn start_capture(world: &mut World) {
     let pending = world.remove_resource::<PendingCapture>();
@@ -86,101 +191,58 @@ pub fn start_capture(world: &mut World) {
     };
 
     // Clean up any existing captures
-    // Stop background window capture thread if running
-    if let Some(capture) = world.get_resource::<ActiveWindowCapture>() {
-        if let Ok(sender) = capture.stop_sender.lock() {
-            let _ = sender.send(());
-        }
-    }
-    world.remove_non_send_resource::<ActiveDisplayCapture>();
-    world.remove_resource::<ActiveWindowCapture>();
+    stop_active_capture(world);
+    world.insert_resource(CaptureTarget(pending.screen));
 
-    match pending.source {
-        CaptureSourceType::Display(screen_index) => {
-            start_display_capture(world, screen_index);
-        }
-        CaptureSourceType::Window(hwnd) => {
-            start_window_capture_impl(world, hwnd);
-        }
+    if !start_active_capture(world, pending.source) {
+        world.remove_resource::<CaptureTarget>();
+        return;
     }
+    world.insert_resource(SharingState {
+        source: Some(pending.source),
+        label: pending.label,
+        screen: pending.screen,
+    });
 
     // Apply material to screen
     apply_material_to_screen(world);
 }
 
-fn start_display_capture(world: &mut World, screen_index: usize) {
-    info!("Starting display capture for screen {}", screen_index);
-
-    let displays = match Display::all() {
-        Ok(d) => d,
-        Err(e) => {
-            error!("Failed to enumerate displays: {}", e);
-            return;
-        }
-    };
-
-    let display = match displays.into_iter().nth(screen_index) {
-        Some(d) => d,
-        None => {
-            error!("Display {} not found", screen_index);
-            return;
-        }
-    };
-
-    let width = display.width() as u32;
-    let height = display.height() as u32;
-
-    let capturer = match Capturer::new(display) {
-        Ok(c) => c,
-        Err(e) => {
-            error!("Failed to create capturer: {}", e);
-            return;
+/// Start capturing a source onto the capture target, telling the host if it
+/// can't be. Whether it started.
+fn start_active_capture(world: &mut World, source: CaptureSourceType) -> bool {
+    let backend = match start_backend(source) {
+        Ok(backend) => backend,
+        Err(message) => {
+            world.send_event(ErrorNotification::new(message));
+            return false;
         }
     };
 
+    // Sources that don't know their size yet get a placeholder until the
+    // first frame
+    let (width, height) = backend.dimensions().unwrap_or((1920, 1080));
     create_capture_texture(world, width, height);
 
-    world.insert_non_send_resource(ActiveDisplayCapture {
-        capturer,
-        width,
-        height,
-        last_capture: Instant::now() - Duration::from_millis(100),
-        capture_interval: Duration::from_millis(16), // ~60fps
+    world.insert_non_send_resource(ActiveCapture {
+        source,
+        backend,
         frame_count: 0,
-        would_block_count: 0,
         fps_counter: 0,
         fps_timer: Instant::now(),
+        paused_since: None,
     });
-
-    info!("Display capture started: {}x{}", width, height);
+    true
 }
 
-fn start_window_capture_impl(world: &mut World, hwnd: isize) {
-    info!("Starting window capture for hwnd {}", hwnd);
-
-    // Start WGC capture - it handles the background thread internally
-    match start_wgc_capture(hwnd) {
-        Some((frame_rx, stop_tx)) => {
-            info!("WGC window capture started for hwnd {}", hwnd);
-
-            // Create initial texture with placeholder dimensions (will be updated on first frame)
-            create_capture_texture(world, 1920, 1080);
-
-            world.insert_resource(ActiveWindowCapture {
-                hwnd,
-                width: 0,
-                height: 0,
-                frame_count: 0,
-                frame_receiver: Mutex::new(frame_rx),
-                stop_sender: Mutex::new(stop_tx),
-                fps_counter: 0,
-                fps_timer: Instant::now(),
-            });
-        }
-        None => {
-            error!("Failed to start WGC capture for hwnd {}", hwnd);
-        }
-    }
+/// Find the screen entity the active capture is shown on.
+fn capture_target_screen(world: &mut World) -> Option<Entity> {
+    let target = world.get_resource::<CaptureTarget>()?.0;
+    let mut query = world.query::<(Entity, &Screen)>();
+    query
+        .iter(world)
+        .find(|(_, screen)| screen.id == target)
+        .map(|(entity, _)| entity)
 }
 
 fn create_capture_texture(world: &mut World, width: u32, height: u32) {
@@ -210,7 +272,11 @@ fn create_capture_texture(world: &mut World, width: u32, height: u32) {
             ..default()
         });
 
-    if let Some(mut screen_texture) = world.get_resource_mut::<ScreenTexture>() {
+    let Some(screen) = capture_target_screen(world) else {
+        warn!("Capture target screen not found");
+        return;
+    };
+    if let Some(mut screen_texture) = world.get_mut::<ScreenTexture>(screen) {
         screen_texture.handle = Some(image_handle);
         screen_texture.material_handle = Some(material);
     }
@@ -219,25 +285,43 @@ fn create_capture_texture(world: &mut World, width: u32, height: u32) {
 }
 
 fn apply_material_to_screen(world: &mut World) {
-    let screen_texture = world.resource::<ScreenTexture>();
-    let Some(material_handle) = screen_texture.material_handle.clone() else {
+    let Some(screen) = capture_target_screen(world) else {
+        return;
+    };
+    let Some(material_handle) = world
+        .get::<ScreenTexture>(screen)
+        .and_then(|screen_texture| screen_texture.material_handle.clone())
+    else {
         return;
     };
 
-    let mut query = world.query_filtered::<&mut MeshMaterial3d<StandardMaterial>, With<Screen>>();
-    for mut screen_mat in query.iter_mut(world) {
+    if let Some(mut screen_mat) = world.get_mut::<MeshMaterial3d<StandardMaterial>>(screen) {
         info!("Applying capture material to screen");
-        screen_mat.0 = material_handle.clone();
+        screen_mat.0 = material_handle;
     }
 }
 
 fn update_texture(world: &mut World, rgba: Vec<u8>, width: u32, height: u32, log: bool) {
+    let screen_id = world
+        .get_resource::<CaptureTarget>()
+        .map_or(0, |target| target.0);
+    let Some(screen) = capture_target_screen(world) else {
+        return;
+    };
+
     // Update the latest captured frame for streaming
     if let Some(mut latest_frame) = world.get_resource_mut::<LatestCapturedFrame>() {
         latest_frame.rgba = rgba.clone();
         latest_frame.width = width;
         latest_frame.height = height;
         latest_frame.frame_number += 1;
+        latest_frame.captured_at = Some(Instant::now());
+        latest_frame.screen_id = screen_id;
+    }
+
+    // The preview shows the stream as viewers get it instead
+    if world.contains_resource::<LocalPreview>() {
+        return;
     }
 
     // Update screen dimensions for aspect ratio adjustment
@@ -250,7 +334,7 @@ fn update_texture(world: &mut World, rgba: Vec<u8>, width: u32, height: u32, log
         (SCREEN_HEIGHT * video_aspect, SCREEN_HEIGHT)
     };
 
-    if let Some(mut screen_dims) = world.get_resource_mut::<ScreenDimensions>() {
+    if let Some(mut screen_dims) = world.get_mut::<ScreenDimensions>(screen) {
         if !screen_dims.initialized
             || (screen_dims.width - new_width).abs() > 0.01
             || (screen_dims.height - new_height).abs() > 0.01
@@ -265,7 +349,9 @@ fn update_texture(world: &mut World, rgba: Vec<u8>, width: u32, height: u32, log
         }
     }
 
-    let screen_texture = world.resource::<ScreenTexture>();
+    let Some(screen_texture) = world.get::<ScreenTexture>(screen) else {
+        return;
+    };
     let image_handle = screen_texture.handle.clone();
     let material_handle = screen_texture.material_handle.clone();
 
@@ -308,7 +394,7 @@ fn update_texture(world: &mut World, rgba: Vec<u8>, width: u32, height: u32, log
     }
 
     // Store new handle
-    if let Some(mut screen_texture) = world.get_resource_mut::<ScreenTexture>() {
+    if let Some(mut screen_texture) = world.get_mut::<ScreenTexture>(screen) {
         screen_texture.handle = Some(new_handle);
     }
 
@@ -317,140 +403,126 @@ fn update_texture(world: &mut World, rgba: Vec<u8>, width: u32, height: u32, log
     }
 }
 
-/// Exclusive system to process display capture frames.
-pub fn process_display_capture(world: &mut World) {
-    let Some(capture) = world.get_non_send_resource::<ActiveDisplayCapture>() else {
+/// Exclusive system polling the active capture, whatever its source, and
+/// feeding its frames to the screen and the stream.
+pub fn process_capture(world: &mut World) {
+    let Some(mut capture) = world.get_non_send_resource_mut::<ActiveCapture>() else {
         return;
     };
 
-    let interval = capture.capture_interval;
-    let last = capture.last_capture;
-    let width = capture.width;
-    let height = capture.height;
-    let frame_count = capture.frame_count;
+    match capture.backend.poll_frame() {
+        CapturePoll::Frame(frame) => {
+            let log = capture.frame_count < 5;
+            capture.frame_count += 1;
+            capture.fps_counter += 1;
+            if capture.paused_since.take().is_some() {
+                info!("Capture resumed");
+            }
 
-    // Rate limit (but not for first frames)
-    if frame_count > 0 && last.elapsed() < interval {
-        return;
-    }
+            // Log FPS every second
+            if capture.fps_timer.elapsed() >= Duration::from_secs(1) {
+                info!("Capture FPS: {}", capture.fps_counter);
+                capture.fps_counter = 0;
+                capture.fps_timer = Instant::now();
+            }
 
-    let max_attempts = if frame_count == 0 { 10 } else { 3 };
-
-    let frame_data = {
-        let mut capture = world.get_non_send_resource_mut::<ActiveDisplayCapture>().unwrap();
-        let mut result = None;
-
-        for _attempt in 0..max_attempts {
-            match capture.capturer.frame() {
-                Ok(frame) => {
-                    let stride = frame.len() / height as usize;
-                    let pixel_count = (width * height) as usize;
-                    let mut rgba = vec![0u8; pixel_count * 4];
-
-                    // Read rows in reverse (DXGI is flipped) with optimized conversion
-                    for y in 0..height as usize {
-                        let src_y = height as usize - 1 - y;
-                        let src_row_start = src_y * stride;
-                        let dst_row_start = y * width as usize * 4;
-
-                        for x in 0..width as usize {
-                            let src_i = src_row_start + x * 4;
-                            let dst_i = dst_row_start + x * 4;
-                            // BGRA -> RGBA
-                            rgba[dst_i] = frame[src_i + 2];     // R
-                            rgba[dst_i + 1] = frame[src_i + 1]; // G
-                            rgba[dst_i + 2] = frame[src_i];     // B
-                            rgba[dst_i + 3] = 255;              // A
-                        }
-                    }
-
-                    capture.frame_count += 1;
-                    capture.would_block_count = 0;
-                    capture.last_capture = Instant::now();
-                    capture.fps_counter += 1;
-
-                    // Log FPS every second
-                    if capture.fps_timer.elapsed() >= Duration::from_secs(1) {
-                        info!("Display capture FPS: {}", capture.fps_counter);
-                        capture.fps_counter = 0;
-                        capture.fps_timer = Instant::now();
-                    }
-
-                    result = Some(rgba);
-                    break;
-                }
-                Err(e) => {
-                    if e.kind() == ErrorKind::WouldBlock {
-                        capture.would_block_count += 1;
-                        if capture.frame_count == 0 && capture.would_block_count == 1 {
-                            info!("Display capture waiting for first frame...");
-                        }
-                        std::thread::sleep(Duration::from_millis(1));
-                    } else {
-                        error!("Display capture error: {}", e);
-                        break;
-                    }
-                }
+            update_texture(world, frame.rgba, frame.width, frame.height, log);
+        }
+        CapturePoll::Pending => {}
+        CapturePoll::Paused => show_paused_frame(world),
+        CapturePoll::Restart => {
+            let source = capture.source;
+            // The old capture has to go first, a display can't be captured twice
+            world.remove_non_send_resource::<ActiveCapture>();
+            if start_active_capture(world, source) {
+                apply_material_to_screen(world);
+            } else {
+                // Why it failed has been shown already
+                stop_active_capture(world);
+            }
+        }
+        CapturePoll::Ended(reason) => {
+            stop_active_capture(world);
+            if let Some(message) = reason {
+                world.send_event(ErrorNotification::new(message));
             }
         }
-        result
-    };
-
-    if let Some(rgba) = frame_data {
-        let log = frame_count < 5;
-        update_texture(world, rgba, width, height, log);
     }
 }
 
-/// System to process window capture frames (receives from background thread).
-pub fn process_window_capture(world: &mut World) {
-    // Check if we have an active window capture and try to receive a frame
-    let frame_data = {
-        let Some(capture) = world.get_resource::<ActiveWindowCapture>() else {
-            return;
-        };
-
-        // Non-blocking receive - get the latest frame if available
-        let mut latest_frame = None;
-        if let Ok(receiver) = capture.frame_receiver.lock() {
-            while let Ok(frame) = receiver.try_recv() {
-                latest_frame = Some(frame);
-            }
+/// While the source is paused no frames come, so show a dimmed last frame
+/// with a pause sign instead of leaving viewers on a frozen one.
+fn show_paused_frame(world: &mut World) {
+    let Some(capture) = world.get_non_send_resource::<ActiveCapture>() else {
+        return;
+    };
+    let paused_since = capture.paused_since;
+    if paused_since.is_some_and(|sent| sent.elapsed() < PAUSED_FRAME_INTERVAL) {
+        return;
+    }
+    let Some(latest) = world.get_resource::<LatestCapturedFrame>() else {
+        return;
+    };
+    if latest.rgba.is_empty() {
+        return;
+    }
+    let (width, height) = (latest.width, latest.height);
+    let rgba = match paused_since {
+        // The latest frame is already the placeholder
+        Some(_) => latest.rgba.clone(),
+        None => {
+            info!("Captured source paused, showing it as paused");
+            paused_placeholder(&latest.rgba, width, height)
         }
-
-        latest_frame.map(|f| (f, capture.frame_count))
     };
+    update_texture(world, rgba, width, height, false);
+    if let Some(mut capture) = world.get_non_send_resource_mut::<ActiveCapture>() {
+        capture.paused_since = Some(Instant::now());
+    }
+}
 
-    if let Some((frame, frame_count)) = frame_data {
-        let log = frame_count < 5;
-        update_texture(world, frame.rgba, frame.width, frame.height, log);
-
-        // Update capture state
-        if let Some(mut capture) = world.get_resource_mut::<ActiveWindowCapture>() {
-            capture.frame_count += 1;
-            capture.width = frame.width;
-            capture.height = frame.height;
-            capture.fps_counter += 1;
-
-            // Log FPS every second
-            if capture.fps_timer.elapsed() >= Duration::from_secs(1) {
-                info!("Window capture FPS: {}", capture.fps_counter);
-                capture.fps_counter = 0;
-                capture.fps_timer = Instant::now();
+/// A frame dimmed, with a pause sign in its middle.
+fn paused_placeholder(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut frame: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[0] / 3, pixel[1] / 3, pixel[2] / 3, pixel[3]])
+        .collect();
+
+    // Two bars, each a twentieth of the height wide and a fifth tall
+    let (w, h) = (width as usize, height as usize);
+    let bar_width = (h / 20).max(1);
+    let bar_height = h / 5;
+    let top = (h - bar_height) / 2;
+    let left = (w / 2).saturating_sub(bar_width * 3 / 2);
+    for y in top..top + bar_height {
+        for x in (left..left + bar_width).chain(left + bar_width * 2..left + bar_width * 3) {
+            if x < w {
+                let i = (y * w + x) * 4;
+                frame[i..i + 3].fill(230);
             }
         }
     }
+    frame
 }
 
-pub fn cleanup_capture(world: &mut World) {
-    // Stop background window capture thread if running
-    if let Some(capture) = world.get_resource::<ActiveWindowCapture>() {
-        if let Ok(sender) = capture.stop_sender.lock() {
-            let _ = sender.send(());
-        }
+/// System to hide the game's window from capture while a display is shared,
+/// so sharing the display it's on doesn't mirror it into itself endlessly.
+pub fn exclude_own_window(
+    sharing: Res<SharingState>,
+    windows: Query<&RawHandleWrapper, With<PrimaryWindow>>,
+) {
+    if !sharing.is_changed() {
+        return;
     }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let excluded = matches!(sharing.source, Some(CaptureSourceType::Display(_)));
+    set_excluded_from_capture(window, excluded);
+}
 
-    world.remove_non_send_resource::<ActiveDisplayCapture>();
-    world.remove_resource::<ActiveWindowCapture>();
+pub fn cleanup_capture(world: &mut World) {
+    stop_active_capture(world);
     world.remove_resource::<PendingCapture>();
+    world.remove_resource::<PendingCaptureStop>();
 }
@@ -0,0 +1,95 @@
+//! Streaming metrics as Bevy diagnostics: what the host captures, encodes and
+//! sends, and what a viewer decodes, buffers and plays. They show in the
+//! diagnostics log with `--verbose` and feed the stream info overlay.
+
+use bevy::diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+use super::audio_decoder::AudioDecoder;
+use super::video_decoder::{VideoDecoder, VideoJitterBuffer};
+use super::video_encoder::VideoEncoder;
+
+pub const CAPTURE_FPS: DiagnosticPath = DiagnosticPath::const_new("stream/capture_fps");
+pub const ENCODE_FPS: DiagnosticPath = DiagnosticPath::const_new("stream/encode_fps");
+pub const SEND_KBPS: DiagnosticPath = DiagnosticPath::const_new("stream/send_kbps");
+pub const DECODE_FPS: DiagnosticPath = DiagnosticPath::const_new("stream/decode_fps");
+pub const JITTER_DEPTH: DiagnosticPath = DiagnosticPath::const_new("stream/jitter_depth");
+pub const AUDIO_UNDERRUNS: DiagnosticPath = DiagnosticPath::const_new("stream/audio_underruns");
+
+/// How often the counted rates are measured.
+const MEASURE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Resource counting stream events until the next measurement.
+#[derive(Resource)]
+pub struct StreamCounters {
+    /// New captured frames handed to the encoder.
+    pub captured_frames: u32,
+    /// Encoded frames sent to clients.
+    pub encoded_frames: u32,
+    /// Video and audio bytes sent, over all clients.
+    pub sent_bytes: usize,
+    /// Frames the viewer's decoder put out.
+    pub decoded_frames: u32,
+    since: Instant,
+}
+
+impl Default for StreamCounters {
+    fn default() -> Self {
+        Self {
+            captured_frames: 0,
+            encoded_frames: 0,
+            sent_bytes: 0,
+            decoded_frames: 0,
+            since: Instant::now(),
+        }
+    }
+}
+
+pub fn stream_diagnostics_plugin(app: &mut App) {
+    app.init_resource::<StreamCounters>()
+        .register_diagnostic(Diagnostic::new(CAPTURE_FPS).with_suffix(" fps"))
+        .register_diagnostic(Diagnostic::new(ENCODE_FPS).with_suffix(" fps"))
+        .register_diagnostic(Diagnostic::new(SEND_KBPS).with_suffix(" kbps"))
+        .register_diagnostic(Diagnostic::new(DECODE_FPS).with_suffix(" fps"))
+        .register_diagnostic(Diagnostic::new(JITTER_DEPTH).with_suffix(" frames"))
+        .register_diagnostic(Diagnostic::new(AUDIO_UNDERRUNS).with_suffix("/s"))
+        .add_systems(Last, measure_stream_diagnostics);
+}
+
+/// System to turn the counters into rates once an interval has passed, for
+/// the side of the stream this instance is on. The jitter buffer's depth is
+/// measured every frame.
+fn measure_stream_diagnostics(
+    mut diagnostics: Diagnostics,
+    mut counters: ResMut<StreamCounters>,
+    encoder: Option<Res<VideoEncoder>>,
+    decoder: Option<Res<VideoDecoder>>,
+    jitter: Option<Res<VideoJitterBuffer>>,
+    audio_decoder: Option<Res<AudioDecoder>>,
+) {
+    if let Some(jitter) = &jitter {
+        diagnostics.add_measurement(&JITTER_DEPTH, || jitter.depth() as f64);
+    }
+
+    let elapsed = counters.since.elapsed();
+    if elapsed < MEASURE_INTERVAL {
+        return;
+    }
+    let secs = elapsed.as_secs_f64();
+    if encoder.is_some() {
+        diagnostics.add_measurement(&CAPTURE_FPS, || counters.captured_frames as f64 / secs);
+        diagnostics.add_measurement(&ENCODE_FPS, || counters.encoded_frames as f64 / secs);
+        diagnostics.add_measurement(&SEND_KBPS, || {
+            counters.sent_bytes as f64 * 8.0 / 1000.0 / secs
+        });
+    }
+    if decoder.is_some() {
+        diagnostics.add_measurement(&DECODE_FPS, || counters.decoded_frames as f64 / secs);
+    }
+    if let Some(audio_decoder) = &audio_decoder {
+        let underruns = audio_decoder.take_underruns();
+        diagnostics.add_measurement(&AUDIO_UNDERRUNS, || underruns as f64 / secs);
+    }
+    *counters = StreamCounters::default();
+}
@@ -0,0 +1,154 @@
+//! Capturing a whole display with scrap, which uses desktop duplication on
+//! Windows.
+
+use bevy::prelude::*;
+use scrap::{Capturer, Display};
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
+
+use super::capture::{CaptureBackend, CapturePoll, CapturedFrame};
+use super::display_orientation::{bgra_to_rgba, display_rotation, DisplayRotation};
+
+/// Capture errors in a row after which the display is looked for again, to
+/// restart capture if it changed mode or stop it if it's gone.
+const DISPLAY_LOST_ERRORS: u32 = 30;
+
+/// Time between frames, ~60fps.
+const CAPTURE_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Capture of one display. scrap's capturer isn't `Send`, so this lives on
+/// the main thread.
+pub struct DisplayCapture {
+    capturer: Capturer,
+    /// Index of the display among all displays.
+    screen_index: usize,
+    /// Size of the display as shown, in physical pixels.
+    width: u32,
+    height: u32,
+    /// How the display is turned; frames come in its native orientation.
+    rotation: DisplayRotation,
+    last_capture: Instant,
+    frame_count: u32,
+    would_block_count: u32,
+    /// Capture errors in a row, e.g. once the display is unplugged.
+    error_count: u32,
+}
+
+impl DisplayCapture {
+    /// Start capturing a display, or say why it can't be.
+    pub fn start(screen_index: usize) -> Result<Self, String> {
+        info!("Starting display capture for screen {}", screen_index);
+
+        let displays = Display::all().map_err(|e| {
+            error!("Failed to enumerate displays: {}", e);
+            format!("Couldn't list your displays: {}", e)
+        })?;
+
+        let Some(display) = displays.into_iter().nth(screen_index) else {
+            error!("Display {} not found", screen_index);
+            return Err(format!(
+                "Display {} is gone - pick another",
+                screen_index + 1
+            ));
+        };
+
+        let width = display.width() as u32;
+        let height = display.height() as u32;
+        let rotation = display_rotation(screen_index);
+
+        let capturer = Capturer::new(display).map_err(|e| {
+            error!("Failed to create capturer: {}", e);
+            format!("Couldn't capture that display: {}", e)
+        })?;
+
+        info!("Display capture started: {}x{}", width, height);
+        Ok(Self {
+            capturer,
+            screen_index,
+            width,
+            height,
+            rotation,
+            last_capture: Instant::now() - Duration::from_millis(100),
+            frame_count: 0,
+            would_block_count: 0,
+            error_count: 0,
+        })
+    }
+
+    /// What to do about a display that keeps failing: restart capturing it,
+    /// which is needed when its resolution changes, or stop sharing it if
+    /// it's been unplugged.
+    fn recover(&self) -> CapturePoll {
+        let index = self.screen_index;
+        if Display::all().is_ok_and(|displays| index < displays.len()) {
+            info!("Display {} changed, restarting capture", index);
+            return CapturePoll::Restart;
+        }
+        warn!("Display {} is gone, stopping capture", index);
+        CapturePoll::Ended(Some(
+            "The shared display was disconnected, so sharing stopped".to_string(),
+        ))
+    }
+}
+
+impl CaptureBackend for DisplayCapture {
+    fn dimensions(&self) -> Option<(u32, u32)> {
+        Some((self.width, self.height))
+    }
+
+    fn poll_frame(&mut self) -> CapturePoll {
+        // Rate limit (but not for first frames)
+        if self.frame_count > 0 && self.last_capture.elapsed() < CAPTURE_INTERVAL {
+            return CapturePoll::Pending;
+        }
+
+        let (width, height) = (self.width, self.height);
+        let max_attempts = if self.frame_count == 0 { 10 } else { 3 };
+        for _attempt in 0..max_attempts {
+            match self.capturer.frame() {
+                Ok(frame) => {
+                    let converted =
+                        bgra_to_rgba(&frame, width as usize, height as usize, self.rotation);
+                    let Some(rgba) = converted else {
+                        // The display changed mode under us; counts towards
+                        // restarting capture with its new size
+                        if self.error_count == 0 {
+                            warn!("Display frame doesn't match {}x{}", width, height);
+                        }
+                        self.error_count += 1;
+                        break;
+                    };
+
+                    self.frame_count += 1;
+                    self.would_block_count = 0;
+                    self.error_count = 0;
+                    self.last_capture = Instant::now();
+                    return CapturePoll::Frame(CapturedFrame {
+                        rgba,
+                        width,
+                        height,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    self.would_block_count += 1;
+                    if self.frame_count == 0 && self.would_block_count == 1 {
+                        info!("Display capture waiting for first frame...");
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(e) => {
+                    if self.error_count == 0 {
+                        error!("Display capture error: {}", e);
+                    }
+                    self.error_count += 1;
+                    break;
+                }
+            }
+        }
+
+        if self.error_count >= DISPLAY_LOST_ERRORS {
+            return self.recover();
+        }
+        CapturePoll::Pending
+    }
+}
@@ -0,0 +1,225 @@
+//! Orientation of captured displays. Desktop duplication hands over frames in
+//! a display's native orientation, so a display turned to portrait comes as a
+//! landscape frame with its rows as wide as the native mode. Frames are turned
+//! the way the display is shown before going anywhere else.
+
+use rayon::prelude::*;
+
+use super::pixels::flip_bgra_to_rgba;
+
+/// How a display is turned from its native orientation, clockwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisplayRotation {
+    #[default]
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl DisplayRotation {
+    /// Whether the native frame is the shown one with width and height swapped.
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, DisplayRotation::Rotate90 | DisplayRotation::Rotate270)
+    }
+}
+
+/// Rotation of each display, in the order scrap lists them.
+#[cfg(windows)]
+pub fn display_rotations() -> Vec<DisplayRotation> {
+    use std::ptr::null_mut;
+    use winapi::shared::dxgi::{
+        CreateDXGIFactory1, IDXGIAdapter1, IDXGIFactory1, IDXGIOutput, DXGI_OUTPUT_DESC,
+    };
+    use winapi::shared::dxgitype::{
+        DXGI_MODE_ROTATION_ROTATE180, DXGI_MODE_ROTATION_ROTATE270, DXGI_MODE_ROTATION_ROTATE90,
+    };
+    use winapi::Interface;
+
+    let mut rotations = Vec::new();
+    unsafe {
+        let mut factory: *mut IDXGIFactory1 = null_mut();
+        if CreateDXGIFactory1(&IDXGIFactory1::uuidof(), &mut factory as *mut _ as *mut _) < 0 {
+            return rotations;
+        }
+        let mut adapter_index = 0;
+        loop {
+            let mut adapter: *mut IDXGIAdapter1 = null_mut();
+            if (*factory).EnumAdapters1(adapter_index, &mut adapter) < 0 {
+                break;
+            }
+            let mut output_index = 0;
+            loop {
+                let mut output: *mut IDXGIOutput = null_mut();
+                if (*adapter).EnumOutputs(output_index, &mut output) < 0 {
+                    break;
+                }
+                let mut desc: DXGI_OUTPUT_DESC = std::mem::zeroed();
+                let described = (*output).GetDesc(&mut desc) >= 0;
+                (*output).Release();
+                // Like scrap, only outputs showing part of the desktop count
+                if described && desc.AttachedToDesktop != 0 {
+                    rotations.push(match desc.Rotation {
+                        DXGI_MODE_ROTATION_ROTATE90 => DisplayRotation::Rotate90,
+                        DXGI_MODE_ROTATION_ROTATE180 => DisplayRotation::Rotate180,
+                        DXGI_MODE_ROTATION_ROTATE270 => DisplayRotation::Rotate270,
+                        _ => DisplayRotation::Identity,
+                    });
+                }
+                output_index += 1;
+            }
+            (*adapter).Release();
+            adapter_index += 1;
+        }
+        (*factory).Release();
+    }
+    rotations
+}
+
+/// Other platforms capture displays as they're shown.
+#[cfg(not(windows))]
+pub fn display_rotations() -> Vec<DisplayRotation> {
+    Vec::new()
+}
+
+/// Rotation of one display, as listed by scrap.
+pub fn display_rotation(index: usize) -> DisplayRotation {
+    display_rotations().get(index).copied().unwrap_or_default()
+}
+
+/// Turn a captured BGRA frame in the display's native orientation, top row
+/// first, into RGBA as the display is shown, `width` by `height`, with the
+/// bottom row first as the rest of the pipeline expects. `None` if the frame
+/// is too small for that size, e.g. while the display changes mode.
+pub fn bgra_to_rgba(
+    frame: &[u8],
+    width: usize,
+    height: usize,
+    rotation: DisplayRotation,
+) -> Option<Vec<u8>> {
+    let (native_width, native_height) = if rotation.swaps_axes() {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    // Rows can be padded past the native width
+    let stride = frame.len().checked_div(native_height)?;
+    if stride < native_width * 4 {
+        return None;
+    }
+
+    if rotation == DisplayRotation::Identity {
+        return Some(flip_bgra_to_rgba(frame, stride, width, height, true));
+    }
+
+    // Turned displays gather each row from a column of the native frame
+    let mut rgba = vec![0u8; width * height * 4];
+    rgba.par_chunks_exact_mut(width * 4)
+        .enumerate()
+        .for_each(|(row, dst)| {
+            let y = height - 1 - row;
+            for (x, d) in dst.chunks_exact_mut(4).enumerate() {
+                let (src_x, src_y) = match rotation {
+                    DisplayRotation::Identity => (x, y),
+                    DisplayRotation::Rotate90 => (y, width - 1 - x),
+                    DisplayRotation::Rotate180 => (width - 1 - x, height - 1 - y),
+                    DisplayRotation::Rotate270 => (height - 1 - y, x),
+                };
+                let src_i = src_y * stride + src_x * 4;
+                // BGRA -> RGBA
+                d.copy_from_slice(&[frame[src_i + 2], frame[src_i + 1], frame[src_i], 255]);
+            }
+        });
+    Some(rgba)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Native BGRA frame `abc` over `def`, each pixel's letter in its red
+    /// channel, with rows `stride` bytes apart and padding left as junk.
+    fn native_frame(stride: usize) -> Vec<u8> {
+        let mut frame = vec![0xEE; stride * 2];
+        for (y, row) in [b"abc", b"def"].iter().enumerate() {
+            for (x, &letter) in row.iter().enumerate() {
+                let i = y * stride + x * 4;
+                frame[i..i + 4].copy_from_slice(&[0, 0, letter, 0]);
+            }
+        }
+        frame
+    }
+
+    /// Rows of an RGBA frame `width` wide as the letters in their red
+    /// channel, checking every pixel came out opaque.
+    fn letters(rgba: &[u8], width: usize) -> Vec<String> {
+        rgba.chunks_exact(width * 4)
+            .map(|row| {
+                row.chunks_exact(4)
+                    .map(|pixel| {
+                        assert_eq!(pixel[1..], [0, 0, 255]);
+                        pixel[0] as char
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn turned(rotation: DisplayRotation, stride: usize) -> Vec<String> {
+        let (width, height) = if rotation.swaps_axes() {
+            (2, 3)
+        } else {
+            (3, 2)
+        };
+        let rgba = bgra_to_rgba(&native_frame(stride), width, height, rotation).unwrap();
+        assert_eq!(rgba.len(), width * height * 4);
+        letters(&rgba, width)
+    }
+
+    // Expected rows are bottom first
+
+    #[test]
+    fn identity_flips_rows() {
+        assert_eq!(turned(DisplayRotation::Identity, 12), ["def", "abc"]);
+    }
+
+    #[test]
+    fn rotate90_turns_clockwise() {
+        // Shown as da, eb, fc from the top
+        assert_eq!(turned(DisplayRotation::Rotate90, 12), ["fc", "eb", "da"]);
+    }
+
+    #[test]
+    fn rotate180_turns_upside_down() {
+        // Shown as fed over cba
+        assert_eq!(turned(DisplayRotation::Rotate180, 12), ["cba", "fed"]);
+    }
+
+    #[test]
+    fn rotate270_turns_anticlockwise() {
+        // Shown as cf, be, ad from the top
+        assert_eq!(turned(DisplayRotation::Rotate270, 12), ["ad", "be", "cf"]);
+    }
+
+    #[test]
+    fn row_padding_is_skipped() {
+        for rotation in [
+            DisplayRotation::Identity,
+            DisplayRotation::Rotate90,
+            DisplayRotation::Rotate180,
+            DisplayRotation::Rotate270,
+        ] {
+            assert_eq!(turned(rotation, 20), turned(rotation, 12), "{:?}", rotation);
+        }
+    }
+
+    #[test]
+    fn short_frames_are_refused() {
+        let frame = native_frame(12);
+        let short = &frame[..frame.len() - 4];
+        assert!(bgra_to_rgba(short, 3, 2, DisplayRotation::Identity).is_none());
+        assert!(bgra_to_rgba(short, 2, 3, DisplayRotation::Rotate90).is_none());
+        assert!(bgra_to_rgba(&[], 3, 2, DisplayRotation::Identity).is_none());
+        assert!(bgra_to_rgba(&frame, 3, 0, DisplayRotation::Identity).is_none());
+    }
+}
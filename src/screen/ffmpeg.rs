@@ -0,0 +1,576 @@
+//! FFmpeg subprocess backends for codecs OpenH264 cannot handle (HEVC and AV1).
+//!
+//! FFmpeg is optional: when the binary is not on PATH (or lacks the codec),
+//! only H.264 is advertised and the OpenH264 path is used.
+
+use bevy::prelude::*;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::OnceLock;
+use std::thread;
+
+use crate::network::protocol::VideoCodecKind;
+
+/// IVF container header size (AV1 is piped through IVF for easy framing).
+const IVF_HEADER_SIZE: usize = 32;
+/// IVF per-frame header size (4-byte length + 8-byte timestamp).
+const IVF_FRAME_HEADER_SIZE: usize = 12;
+/// HEVC access unit delimiter NAL type, used to split the encoder output into frames.
+const HEVC_NAL_AUD: u8 = 35;
+
+/// Encoder candidates per codec, hardware first.
+const HEVC_ENCODERS: [&str; 4] = ["hevc_nvenc", "hevc_qsv", "hevc_amf", "libx265"];
+const AV1_ENCODERS: [&str; 5] = ["av1_nvenc", "av1_qsv", "av1_amf", "libsvtav1", "libaom-av1"];
+
+/// What the FFmpeg binary lists, read once.
+struct FfmpegTools {
+    /// Output of `ffmpeg -encoders`, for the encoder probe.
+    encoders: String,
+    /// Codecs FFmpeg can decode.
+    decoders: HashSet<VideoCodecKind>,
+}
+
+/// The FFmpeg binary's listings, `None` when it isn't on PATH. Only lists
+/// codecs, so it's quick enough for a client to ask while connecting.
+fn tools() -> Option<&'static FfmpegTools> {
+    static TOOLS: OnceLock<Option<FfmpegTools>> = OnceLock::new();
+    TOOLS
+        .get_or_init(|| {
+            let (Some(encoders), Some(decoders)) =
+                (ffmpeg_listing("-encoders"), ffmpeg_listing("-decoders"))
+            else {
+                info!("FFmpeg not found - only H.264 is available");
+                return None;
+            };
+
+            let mut tools = FfmpegTools {
+                encoders,
+                decoders: HashSet::new(),
+            };
+            if listing_contains(&decoders, "hevc") {
+                tools.decoders.insert(VideoCodecKind::Hevc);
+            }
+            if listing_contains(&decoders, "libdav1d") || listing_contains(&decoders, "av1") {
+                tools.decoders.insert(VideoCodecKind::Av1);
+            }
+            Some(tools)
+        })
+        .as_ref()
+}
+
+/// Working encoder name per codec, once the probe has finished.
+static PROBED_ENCODERS: OnceLock<Vec<(VideoCodecKind, &'static str)>> = OnceLock::new();
+
+/// Start finding which encoders work on a background thread. Test-encoding
+/// takes a subprocess per candidate, too slow for the main thread, so it
+/// runs from startup and HEVC and AV1 are offered once it's done.
+pub fn start_probe() {
+    thread::spawn(|| {
+        PROBED_ENCODERS.get_or_init(probe_encoders);
+    });
+}
+
+fn probe_encoders() -> Vec<(VideoCodecKind, &'static str)> {
+    let Some(tools) = tools() else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for (codec, candidates) in [
+        (VideoCodecKind::Hevc, &HEVC_ENCODERS[..]),
+        (VideoCodecKind::Av1, &AV1_ENCODERS[..]),
+    ] {
+        // Listed hardware encoders may still fail without a matching GPU, so test-encode
+        if let Some(name) = candidates
+            .iter()
+            .find(|name| listing_contains(&tools.encoders, name) && probe_encoder(name))
+        {
+            info!("FFmpeg {} encoder: {}", codec.name(), name);
+            found.push((codec, *name));
+        }
+    }
+    found
+}
+
+/// Run `ffmpeg -hide_banner <flag>` and return its stdout.
+fn ffmpeg_listing(flag: &str) -> Option<String> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", flag])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Check whether an `-encoders`/`-decoders` listing contains a codec name.
+fn listing_contains(listing: &str, name: &str) -> bool {
+    listing
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(name))
+}
+
+/// Encode a single black frame to verify the encoder actually works.
+fn probe_encoder(name: &str) -> bool {
+    Command::new("ffmpeg")
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-f",
+            "lavfi",
+            "-i",
+            "color=black:s=256x256",
+            "-frames:v",
+            "1",
+            "-c:v",
+            name,
+            "-f",
+            "null",
+            "-",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Whether FFmpeg is on PATH.
+pub fn is_available() -> bool {
+    tools().is_some()
+}
+
+/// Codecs the host can encode through FFmpeg, none while the probe is
+/// still running.
+pub fn available_encoders() -> Vec<VideoCodecKind> {
+    let probed = PROBED_ENCODERS.get().map(Vec::as_slice).unwrap_or_default();
+    probed.iter().map(|(codec, _)| *codec).collect()
+}
+
+/// Codecs this client can decode through FFmpeg.
+pub fn available_decoders() -> Vec<VideoCodecKind> {
+    tools().map_or_else(Vec::new, |tools| tools.decoders.iter().copied().collect())
+}
+
+/// Extra low-latency arguments for a given FFmpeg encoder.
+fn low_latency_args(encoder: &str) -> &'static [&'static str] {
+    match encoder {
+        "hevc_nvenc" | "av1_nvenc" => &["-preset", "p1", "-tune", "ull"],
+        "hevc_qsv" | "av1_qsv" => &["-preset", "veryfast", "-low_power", "1"],
+        "hevc_amf" | "av1_amf" => &["-usage", "ultralowlatency"],
+        "libx265" => &["-preset", "ultrafast", "-tune", "zerolatency"],
+        "libsvtav1" => &["-preset", "10", "-svtav1-params", "lookahead=0"],
+        "libaom-av1" => &["-cpu-used", "8", "-usage", "realtime", "-lag-in-frames", "0"],
+        _ => &[],
+    }
+}
+
+/// FFmpeg encoder process fed with raw RGBA frames on stdin.
+pub struct FfmpegEncoder {
+    child: Child,
+    stdin: ChildStdin,
+    access_units: Receiver<Vec<u8>>,
+}
+
+impl FfmpegEncoder {
+    /// Spawn an encoder for the given codec, frame size, bitrate and frame rate.
+    pub fn spawn(
+        codec: VideoCodecKind,
+        width: u32,
+        height: u32,
+        bitrate_bps: u32,
+        fps: u32,
+    ) -> Option<Self> {
+        let encoder = PROBED_ENCODERS
+            .get()?
+            .iter()
+            .find(|(c, _)| *c == codec)
+            .map(|(_, name)| *name)?;
+
+        let size = format!("{}x{}", width, height);
+        let bitrate = bitrate_bps.to_string();
+        let fps = fps.to_string();
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-hide_banner", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-s", &size, "-framerate", &fps])
+            .args(["-i", "-"])
+            .args(["-c:v", encoder, "-pix_fmt", "yuv420p", "-g", "120", "-bf", "0"])
+            .args(["-b:v", &bitrate])
+            .args(low_latency_args(encoder));
+
+        match codec {
+            VideoCodecKind::Hevc => {
+                command.args(["-bsf:v", "hevc_metadata=aud=insert", "-f", "hevc", "-"]);
+            }
+            VideoCodecKind::Av1 => {
+                command.args(["-f", "ivf", "-"]);
+            }
+            VideoCodecKind::H264 => return None,
+        }
+
+        let mut child = match command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to spawn FFmpeg encoder: {}", e);
+                return None;
+            }
+        };
+
+        let stdin = child.stdin.take()?;
+        let stdout = child.stdout.take()?;
+        let (unit_tx, unit_rx) = mpsc::channel::<Vec<u8>>();
+
+        thread::spawn(move || match codec {
+            VideoCodecKind::Hevc => read_hevc_access_units(stdout, unit_tx),
+            _ => read_ivf_frames(stdout, unit_tx),
+        });
+
+        info!("FFmpeg {} encoder started ({}, {})", codec.name(), encoder, size);
+
+        Some(Self {
+            child,
+            stdin,
+            access_units: unit_rx,
+        })
+    }
+
+    /// Write a frame and collect any access units that are ready.
+    /// Returns `None` if the FFmpeg process has exited.
+    pub fn encode(&mut self, rgba: &[u8]) -> Option<Vec<Vec<u8>>> {
+        if self.stdin.write_all(rgba).is_err() {
+            return None;
+        }
+
+        let mut units = Vec::new();
+        loop {
+            match self.access_units.try_recv() {
+                Ok(unit) => units.push(unit),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return None,
+            }
+        }
+        Some(units)
+    }
+}
+
+impl Drop for FfmpegEncoder {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// FFmpeg decoder process emitting PPM frames on stdout.
+pub struct FfmpegDecoder {
+    child: Child,
+    stdin: ChildStdin,
+    frames: Receiver<(Vec<u8>, u32, u32)>,
+    /// AV1 input is wrapped in IVF framing.
+    ivf: bool,
+    frame_index: u64,
+}
+
+impl FfmpegDecoder {
+    /// Spawn a decoder for the given codec.
+    pub fn spawn(codec: VideoCodecKind) -> Option<Self> {
+        if !tools().is_some_and(|tools| tools.decoders.contains(&codec)) {
+            return None;
+        }
+
+        let input_format = match codec {
+            VideoCodecKind::Hevc => "hevc",
+            VideoCodecKind::Av1 => "ivf",
+            VideoCodecKind::H264 => return None,
+        };
+
+        let mut child = match Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error"])
+            .args(["-fflags", "nobuffer", "-flags", "low_delay"])
+            .args(["-probesize", "32", "-analyzeduration", "0"])
+            .args(["-f", input_format, "-i", "-"])
+            .args(["-f", "image2pipe", "-c:v", "ppm", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to spawn FFmpeg decoder: {}", e);
+                return None;
+            }
+        };
+
+        let mut stdin = child.stdin.take()?;
+        let stdout = child.stdout.take()?;
+        let (frame_tx, frame_rx) = mpsc::channel();
+
+        thread::spawn(move || read_ppm_frames(stdout, frame_tx));
+
+        let ivf = codec == VideoCodecKind::Av1;
+        if ivf && stdin.write_all(&ivf_file_header()).is_err() {
+            return None;
+        }
+
+        info!("FFmpeg {} decoder started", codec.name());
+
+        Some(Self {
+            child,
+            stdin,
+            frames: frame_rx,
+            ivf,
+            frame_index: 0,
+        })
+    }
+
+    /// Feed one access unit to the decoder. Returns false if FFmpeg has exited.
+    pub fn decode(&mut self, data: &[u8]) -> bool {
+        if self.ivf {
+            let mut header = [0u8; IVF_FRAME_HEADER_SIZE];
+            header[..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            header[4..].copy_from_slice(&self.frame_index.to_le_bytes());
+            self.frame_index += 1;
+            if self.stdin.write_all(&header).is_err() {
+                return false;
+            }
+        }
+        self.stdin.write_all(data).is_ok() && self.stdin.flush().is_ok()
+    }
+
+    /// Get the next decoded RGBA frame if one is ready.
+    pub fn try_recv_frame(&self) -> Option<(Vec<u8>, u32, u32)> {
+        self.frames.try_recv().ok()
+    }
+}
+
+impl Drop for FfmpegDecoder {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Check whether an encoded access unit starts a decodable sequence.
+pub fn is_keyframe(codec: VideoCodecKind, data: &[u8]) -> bool {
+    match codec {
+        // IDR NAL unit type = 5
+        VideoCodecKind::H264 => data.len() > 4 && (data[4] & 0x1F) == 5,
+        // IRAP NAL unit types 16..=21
+        VideoCodecKind::Hevc => hevc_nal_types(data).any(|t| (16..=21).contains(&t)),
+        // Encoders emit a sequence header OBU with every keyframe
+        VideoCodecKind::Av1 => av1_has_sequence_header(data),
+    }
+}
+
+/// Iterate over the NAL unit types in an Annex B HEVC buffer.
+fn hevc_nal_types(data: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    data.windows(4)
+        .filter(|w| w[0] == 0 && w[1] == 0 && w[2] == 1)
+        .map(|w| (w[3] >> 1) & 0x3F)
+}
+
+/// Parameter sets in an access unit, which a decoder needs before it can
+/// decode the stream: SPS and PPS for H.264, VPS, SPS and PPS for HEVC and
+/// the sequence header for AV1. Empty if the unit carries none.
+pub fn parameter_sets(codec: VideoCodecKind, data: &[u8]) -> Vec<u8> {
+    match codec {
+        VideoCodecKind::H264 => annex_b_units(data)
+            .filter(|(header, _)| matches!(header & 0x1F, 7 | 8))
+            .flat_map(|(_, unit)| unit.iter().copied())
+            .collect(),
+        VideoCodecKind::Hevc => annex_b_units(data)
+            .filter(|(header, _)| (32..=34).contains(&((header >> 1) & 0x3F)))
+            .flat_map(|(_, unit)| unit.iter().copied())
+            .collect(),
+        VideoCodecKind::Av1 => av1_sequence_header(data)
+            .map(<[u8]>::to_vec)
+            .unwrap_or_default(),
+    }
+}
+
+/// Iterate over the NAL units in an Annex B buffer, each as its first
+/// header byte and the unit with its start code.
+fn annex_b_units(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> + '_ {
+    let starts: Vec<usize> = (0..data.len().saturating_sub(3))
+        .filter(|&i| data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1)
+        .collect();
+    // Include the leading zero of a 4-byte start code
+    let begin = |i: usize| if i > 0 && data[i - 1] == 0 { i - 1 } else { i };
+    (0..starts.len()).map(move |n| {
+        let end = starts.get(n + 1).map_or(data.len(), |&next| begin(next));
+        (data[starts[n] + 3], &data[begin(starts[n])..end])
+    })
+}
+
+/// Walk AV1 OBUs looking for OBU_SEQUENCE_HEADER.
+fn av1_has_sequence_header(data: &[u8]) -> bool {
+    av1_sequence_header(data).is_some()
+}
+
+/// The OBU_SEQUENCE_HEADER in AV1 data, header included.
+fn av1_sequence_header(data: &[u8]) -> Option<&[u8]> {
+    let mut pos = 0;
+    while pos < data.len() {
+        let start = pos;
+        let header = data[pos];
+        let obu_type = (header >> 3) & 0x0F;
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+        pos += 1 + has_extension as usize;
+        if !has_size {
+            // Runs to the end of the data
+            return (obu_type == 1).then(|| &data[start..]);
+        }
+
+        // LEB128 payload size
+        let mut size: usize = 0;
+        for i in 0..8 {
+            let byte = *data.get(pos)?;
+            pos += 1;
+            size |= ((byte & 0x7F) as usize) << (i * 7);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        pos += size;
+        if obu_type == 1 {
+            return data.get(start..pos);
+        }
+    }
+    None
+}
+
+/// Find the next HEVC AUD start code at or after `from`.
+fn next_hevc_aud(buf: &[u8], from: usize) -> Option<usize> {
+    (from..buf.len().saturating_sub(3)).find_map(|i| {
+        let is_aud = buf[i] == 0
+            && buf[i + 1] == 0
+            && buf[i + 2] == 1
+            && (buf[i + 3] >> 1) & 0x3F == HEVC_NAL_AUD;
+        // Include the leading zero of a 4-byte start code
+        is_aud.then(|| if i > 0 && buf[i - 1] == 0 { i - 1 } else { i })
+    })
+}
+
+/// Split FFmpeg's HEVC Annex B stream into access units at each AUD.
+fn read_hevc_access_units(mut stdout: impl Read, unit_tx: Sender<Vec<u8>>) {
+    let mut buf = Vec::with_capacity(256 * 1024);
+    let mut read_buf = vec![0u8; 64 * 1024];
+
+    while let Ok(n) = stdout.read(&mut read_buf) {
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&read_buf[..n]);
+
+        // An access unit is complete once the next one's delimiter arrives
+        while let Some(first) = next_hevc_aud(&buf, 0) {
+            let Some(second) = next_hevc_aud(&buf, first + 4) else {
+                break;
+            };
+            let unit: Vec<u8> = buf.drain(..second).skip(first).collect();
+            if unit_tx.send(unit).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Read AV1 frames from an IVF stream.
+fn read_ivf_frames(mut stdout: impl Read, unit_tx: Sender<Vec<u8>>) {
+    let mut file_header = [0u8; IVF_HEADER_SIZE];
+    if stdout.read_exact(&mut file_header).is_err() {
+        return;
+    }
+
+    let mut frame_header = [0u8; IVF_FRAME_HEADER_SIZE];
+    while stdout.read_exact(&mut frame_header).is_ok() {
+        let size = u32::from_le_bytes([
+            frame_header[0],
+            frame_header[1],
+            frame_header[2],
+            frame_header[3],
+        ]) as usize;
+        let mut frame = vec![0u8; size];
+        if stdout.read_exact(&mut frame).is_err() || unit_tx.send(frame).is_err() {
+            return;
+        }
+    }
+}
+
+/// IVF file header announcing an AV1 stream of unknown size.
+fn ivf_file_header() -> [u8; IVF_HEADER_SIZE] {
+    let mut header = [0u8; IVF_HEADER_SIZE];
+    header[..4].copy_from_slice(b"DKIF");
+    header[6..8].copy_from_slice(&(IVF_HEADER_SIZE as u16).to_le_bytes());
+    header[8..12].copy_from_slice(b"AV01");
+    // Timebase 1/60; dimensions are taken from the sequence header
+    header[16..20].copy_from_slice(&60u32.to_le_bytes());
+    header[20..24].copy_from_slice(&1u32.to_le_bytes());
+    header
+}
+
+/// Read a whitespace-delimited ASCII token from a PPM header.
+fn read_ppm_token(reader: &mut impl Read) -> Option<String> {
+    let mut token = String::new();
+    let mut byte = [0u8; 1];
+    loop {
+        reader.read_exact(&mut byte).ok()?;
+        if byte[0].is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+            return Some(token);
+        }
+        token.push(byte[0] as char);
+    }
+}
+
+/// Parse binary PPM (P6) frames and convert them to RGBA.
+fn read_ppm_frames(mut stdout: impl Read, frame_tx: Sender<(Vec<u8>, u32, u32)>) {
+    loop {
+        let Some(magic) = read_ppm_token(&mut stdout) else {
+            return;
+        };
+        if magic != "P6" {
+            error!("Unexpected FFmpeg decoder output ({})", magic);
+            return;
+        }
+
+        let parsed = (|| {
+            let width: u32 = read_ppm_token(&mut stdout)?.parse().ok()?;
+            let height: u32 = read_ppm_token(&mut stdout)?.parse().ok()?;
+            let _max_value = read_ppm_token(&mut stdout)?;
+            Some((width, height))
+        })();
+        let Some((width, height)) = parsed else {
+            return;
+        };
+
+        let mut rgb = vec![0u8; (width * height * 3) as usize];
+        if stdout.read_exact(&mut rgb).is_err() {
+            return;
+        }
+
+        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+        for pixel in rgb.chunks_exact(3) {
+            rgba.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+        }
+
+        if frame_tx.send((rgba, width, height)).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+use super::streaming::LatestCapturedFrame;
+use crate::network::protocol::ScreenId;
+use crate::network::ReceivedScreenFrame;
+use crate::world::{RoomLight, ScreenGlow};
+
+/// How long without frames before the stream counts as stopped.
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Room light level while a stream plays, relative to its base intensity.
+const DIMMED_LIGHT_FACTOR: f32 = 0.15;
+
+/// Intensity of the screen glow while a stream plays.
+const GLOW_INTENSITY: f32 = 400_000.0;
+
+/// How quickly lights fade towards their target, per second.
+const FADE_RATE: f32 = 2.0;
+
+/// Only every Nth pixel is sampled for the average frame color.
+const COLOR_SAMPLE_STRIDE: usize = 61;
+
+/// Resource tracking what the stream looks like, for the lighting controller.
+#[derive(Resource)]
+pub struct StreamLighting {
+    /// When the last frame was shown, `None` before the first one.
+    pub last_frame_at: Option<Instant>,
+    /// Screen the stream is shown on.
+    pub screen: ScreenId,
+    /// Average color of the latest frame.
+    pub average_color: Color,
+}
+
+impl Default for StreamLighting {
+    fn default() -> Self {
+        Self {
+            last_frame_at: None,
+            screen: 0,
+            average_color: Color::WHITE,
+        }
+    }
+}
+
+impl StreamLighting {
+    fn is_streaming(&self) -> bool {
+        self.last_frame_at
+            .is_some_and(|at| at.elapsed() < STREAM_IDLE_TIMEOUT)
+    }
+}
+
+/// Track frames shown on screen: decoded ones on clients, captured ones on the host.
+pub fn track_stream_frames(
+    mut events: EventReader<ReceivedScreenFrame>,
+    latest_frame: Res<LatestCapturedFrame>,
+    mut lighting: ResMut<StreamLighting>,
+) {
+    if let Some(frame) = events.read().last() {
+        lighting.last_frame_at = Some(Instant::now());
+        lighting.screen = frame.screen_id;
+        lighting.average_color = average_color(&frame.rgba);
+    } else if latest_frame.is_changed() && !latest_frame.rgba.is_empty() {
+        lighting.last_frame_at = Some(Instant::now());
+        lighting.screen = latest_frame.screen_id;
+        lighting.average_color = average_color(&latest_frame.rgba);
+    }
+}
+
+/// Dim the room lights while a stream plays and tint the glow of the screen showing it.
+pub fn update_theater_lighting(
+    time: Res<Time>,
+    lighting: Res<StreamLighting>,
+    mut room_lights: Query<(&RoomLight, &mut PointLight), Without<ScreenGlow>>,
+    mut glows: Query<(&ScreenGlow, &mut PointLight), Without<RoomLight>>,
+) {
+    let streaming = lighting.is_streaming();
+    let t = (FADE_RATE * time.delta_secs()).min(1.0);
+
+    for (room_light, mut light) in room_lights.iter_mut() {
+        let factor = if streaming { DIMMED_LIGHT_FACTOR } else { 1.0 };
+        let target = room_light.base_intensity * factor;
+        light.intensity = light.intensity.lerp(target, t);
+    }
+
+    for (glow, mut light) in glows.iter_mut() {
+        let lit = streaming && glow.screen == lighting.screen;
+        let target = if lit { GLOW_INTENSITY } else { 0.0 };
+        light.intensity = light.intensity.lerp(target, t);
+        if lit {
+            light.color = light.color.mix(&lighting.average_color, t);
+        }
+    }
+}
+
+/// Average color of an RGBA frame, from a sample of its pixels.
+fn average_color(rgba: &[u8]) -> Color {
+    let mut sum = [0u64; 3];
+    let mut count = 0u64;
+    for pixel in rgba.chunks_exact(4).step_by(COLOR_SAMPLE_STRIDE) {
+        sum[0] += pixel[0] as u64;
+        sum[1] += pixel[1] as u64;
+        sum[2] += pixel[2] as u64;
+        count += 1;
+    }
+    if count == 0 {
+        return Color::WHITE;
+    }
+
+    Color::srgb_u8(
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8,
+    )
+}
@@ -1,9 +1,23 @@
 pub mod audio_capture;
 pub mod audio_decoder;
+pub mod audio_devices;
 pub mod audio_encoder;
+pub mod av_sync;
 pub mod capture;
+pub mod diagnostics;
+pub mod display_capture;
+pub mod display_orientation;
+pub mod ffmpeg;
+pub mod lighting;
+pub mod pip;
+pub mod pixels;
+pub mod preview;
+pub mod recording;
+pub mod screen_card;
 pub mod share_ui;
+pub mod stream_stats;
 pub mod streaming;
+pub mod thumbnails;
 pub mod video_decoder;
 pub mod video_encoder;
 pub mod window_capture;
@@ -12,24 +26,40 @@ use bevy::prelude::*;
 use bevy::render::render_asset::RenderAssetUsages;
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 
-use crate::game_state::AppState;
+use crate::console::AddConsoleCommand;
+use crate::game_state::{AppState, PauseState};
+use crate::network::protocol::ScreenId;
 use crate::network::ReceivedScreenFrame;
 use crate::world::setup::{
-    BUTTON_OFFSET_X, BUTTON_SIZE, FRAME_THICKNESS, SCREEN_HEIGHT, SCREEN_WIDTH, SCREEN_Y,
+    BUTTON_OFFSET_X, BUTTON_SIZE, FRAME_THICKNESS, SCREEN_HEIGHT, SCREEN_WIDTH,
 };
 use crate::world::{Screen, ScreenControlButton, ScreenControlEvent, ScreenFrame};
 use capture::{
-    cleanup_capture, handle_capture_events, process_display_capture, process_window_capture,
-    start_capture, CaptureSource, ScreenTexture,
+    cleanup_capture, exclude_own_window, handle_capture_events, process_capture, start_capture,
+    stop_capture, stream_stop_command, CaptureSource, ScreenTexture, SharingState, StopCapture,
 };
+use lighting::{track_stream_frames, update_theater_lighting, StreamLighting};
+use pip::{
+    cleanup_picture_in_picture, toggle_picture_in_picture, update_picture_in_picture,
+    PictureInPicture,
+};
+use recording::{
+    report_finished_recordings, stop_recording, toggle_recording, FinishingRecordings,
+    ToggleRecording,
+};
+use screen_card::{cleanup_screen_cards, setup_screen_cards, update_screen_cards};
 use share_ui::{
-    cleanup_share_ui, handle_share_ui_interaction, setup_share_ui, update_source_list,
-    ShareUIState,
+    cleanup_share_ui, handle_share_ui_interaction, refresh_displays_on_change, setup_share_ui,
+    update_sharing_header, update_source_list, ShareUIState,
+};
+use stream_stats::{
+    cleanup_stream_stats_hud, toggle_stream_stats, track_shown_frames, update_stream_stats_hud,
 };
 use streaming::LatestCapturedFrame;
+use thumbnails::{release_source_thumbnails, show_source_thumbnails, SourceThumbnails};
 
-/// Resource tracking current screen dimensions for aspect ratio adjustment.
-#[derive(Resource, Default)]
+/// Current dimensions of a screen, for aspect ratio adjustment.
+#[derive(Component, Default)]
 pub struct ScreenDimensions {
     /// Current width of the screen in world units.
     pub width: f32,
@@ -39,35 +69,93 @@ pub struct ScreenDimensions {
     pub initialized: bool,
 }
 
+/// Size of the stream the host announced, on joining or when its encoder
+/// restarts. Its screen takes the stream's shape as soon as it's built,
+/// before the first frame arrives.
+#[derive(Resource)]
+pub struct AnnouncedStreamSize {
+    pub screen_id: ScreenId,
+    pub width: u32,
+    pub height: u32,
+}
+
 pub struct ScreenPlugin;
 
 impl Plugin for ScreenPlugin {
     fn build(&self, app: &mut App) {
+        // Find working FFmpeg encoders without holding up hosting
+        ffmpeg::start_probe();
+        diagnostics::stream_diagnostics_plugin(app);
+        preview::local_preview_plugin(app);
         app.init_resource::<ShareUIState>()
-            .init_resource::<ScreenTexture>()
             .init_resource::<LatestCapturedFrame>()
-            .init_resource::<ScreenDimensions>()
+            .init_resource::<StreamLighting>()
+            .init_resource::<SharingState>()
+            .init_resource::<FinishingRecordings>()
+            .init_resource::<PictureInPicture>()
             .add_event::<CaptureSource>()
+            .add_event::<StopCapture>()
+            .add_event::<ToggleRecording>()
             .add_systems(
                 Update,
                 (
                     open_share_ui.run_if(in_state(AppState::InGame)),
                     handle_share_ui_interaction.run_if(resource_exists::<share_ui::ShareUIRoot>),
+                    refresh_displays_on_change
+                        .before(update_source_list)
+                        .run_if(resource_exists::<share_ui::ShareUIRoot>),
                     update_source_list.run_if(resource_exists::<share_ui::ShareUIRoot>),
+                    update_sharing_header.run_if(resource_exists::<share_ui::ShareUIRoot>),
+                    show_source_thumbnails
+                        .after(update_source_list)
+                        .run_if(resource_exists::<SourceThumbnails>),
+                    release_source_thumbnails.run_if(
+                        resource_exists::<SourceThumbnails>
+                            .and(not(resource_exists::<share_ui::ShareUIRoot>)),
+                    ),
                     handle_capture_events,
+                    toggle_recording.run_if(in_state(AppState::InGame)),
+                    report_finished_recordings,
                     (
                         handle_received_screen_frames,
+                        apply_announced_stream_size
+                            .before(handle_received_screen_frames)
+                            .run_if(resource_exists::<AnnouncedStreamSize>),
                         update_screen_aspect_ratio.after(handle_received_screen_frames),
+                        (track_stream_frames, update_theater_lighting).chain(),
+                        (track_shown_frames, update_stream_stats_hud).chain(),
+                        (setup_screen_cards, update_screen_cards)
+                            .chain()
+                            .after(handle_received_screen_frames),
+                        update_picture_in_picture.after(handle_received_screen_frames),
                     )
                         .run_if(in_state(AppState::InGame)),
+                    toggle_stream_stats.run_if(in_state(PauseState::Running)),
+                    toggle_picture_in_picture.run_if(in_state(PauseState::Running)),
                 ),
             )
             // Exclusive systems for capture (need direct World access)
             .add_systems(
                 Update,
-                (start_capture, process_display_capture, process_window_capture),
+                (
+                    start_capture,
+                    stop_capture,
+                    process_capture,
+                    exclude_own_window,
+                ),
+            )
+            .add_systems(
+                OnExit(AppState::InGame),
+                (
+                    cleanup_share_ui,
+                    cleanup_capture,
+                    cleanup_stream_stats_hud,
+                    cleanup_screen_cards,
+                    cleanup_picture_in_picture,
+                    stop_recording,
+                ),
             )
-            .add_systems(OnExit(AppState::InGame), (cleanup_share_ui, cleanup_capture));
+            .add_console_command("stream stop", "", stream_stop_command);
     }
 }
 
@@ -77,28 +165,66 @@ fn open_share_ui(
     ui_root: Option<Res<share_ui::ShareUIRoot>>,
     mut state: ResMut<ShareUIState>,
 ) {
-    for _ in events.read() {
+    for event in events.read() {
         if ui_root.is_none() {
             setup_share_ui(&mut commands);
             // Mark for refresh so the list repopulates
             state.needs_refresh = true;
             state.selected_source = None;
+            state.target_screen = event.screen;
         }
     }
 }
 
+/// System to shape the announced stream's screen once it's built.
+fn apply_announced_stream_size(
+    mut commands: Commands,
+    size: Res<AnnouncedStreamSize>,
+    mut screen_query: Query<(&Screen, &mut ScreenDimensions)>,
+) {
+    let Some((_, mut screen_dims)) = screen_query
+        .iter_mut()
+        .find(|(screen, _)| screen.id == size.screen_id)
+    else {
+        return;
+    };
+    commands.remove_resource::<AnnouncedStreamSize>();
+    if size.width == 0 || size.height == 0 {
+        return;
+    }
+
+    let video_aspect = size.width as f32 / size.height as f32;
+    let (width, height) = if video_aspect >= SCREEN_WIDTH / SCREEN_HEIGHT {
+        (SCREEN_WIDTH, SCREEN_WIDTH / video_aspect)
+    } else {
+        (SCREEN_HEIGHT * video_aspect, SCREEN_HEIGHT)
+    };
+    if !screen_dims.initialized
+        || (screen_dims.width - width).abs() > 0.01
+        || (screen_dims.height - height).abs() > 0.01
+    {
+        screen_dims.width = width;
+        screen_dims.height = height;
+        screen_dims.initialized = true;
+    }
+}
+
 /// Counter for logging received frames
 #[derive(Resource, Default)]
 struct ReceivedFrameCounter(u32);
 
-/// Handle received screen frames from the network and update the screen texture.
+/// Handle received screen frames from the network and update the texture of
+/// the screen they are addressed to.
 fn handle_received_screen_frames(
     mut events: EventReader<ReceivedScreenFrame>,
-    mut screen_texture: ResMut<ScreenTexture>,
-    mut screen_dims: ResMut<ScreenDimensions>,
     mut images: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut screen_query: Query<&mut MeshMaterial3d<StandardMaterial>, With<Screen>>,
+    mut screen_query: Query<(
+        &Screen,
+        &mut ScreenTexture,
+        &mut ScreenDimensions,
+        &mut MeshMaterial3d<StandardMaterial>,
+    )>,
     mut counter: Local<ReceivedFrameCounter>,
 ) {
     // Process only the most recent frame to avoid lag
@@ -108,6 +234,14 @@ fn handle_received_screen_frames(
 
     counter.0 += 1;
 
+    let Some((_, mut screen_texture, mut screen_dims, mut screen_mat)) = screen_query
+        .iter_mut()
+        .find(|(screen, ..)| screen.id == frame.screen_id)
+    else {
+        warn_once!("Received frames for screen {}, which this room lacks", frame.screen_id);
+        return;
+    };
+
     let expected_size = (frame.width * frame.height * 4) as usize;
     if frame.rgba.len() != expected_size {
         error!(
@@ -186,26 +320,26 @@ fn handle_received_screen_frames(
     };
 
     // Always apply material to screen (ensures it's set even after world respawn)
-    for mut screen_mat in screen_query.iter_mut() {
-        if screen_mat.0 != material_handle {
-            screen_mat.0 = material_handle.clone();
-        }
+    if screen_mat.0 != material_handle {
+        screen_mat.0 = material_handle;
     }
 
     screen_texture.handle = Some(new_handle);
 }
 
-/// Update the screen mesh scale and frame positions based on current dimensions.
+/// Update each screen's mesh scale, and the positions of its frame and
+/// control button, based on its current dimensions.
 fn update_screen_aspect_ratio(
-    screen_dims: Res<ScreenDimensions>,
     mut screen_query: Query<
-        &mut Transform,
+        (&ScreenDimensions, &Parent, &mut Transform),
         (
             With<Screen>,
+            Changed<ScreenDimensions>,
             Without<ScreenFrame>,
             Without<ScreenControlButton>,
         ),
     >,
+    children_query: Query<&Children>,
     mut frame_query: Query<
         (&ScreenFrame, &mut Transform),
         (Without<Screen>, Without<ScreenControlButton>),
@@ -219,54 +353,61 @@ fn update_screen_aspect_ratio(
         ),
     >,
 ) {
-    if !screen_dims.initialized || !screen_dims.is_changed() {
-        return;
-    }
+    for (screen_dims, parent, mut screen_transform) in screen_query.iter_mut() {
+        if !screen_dims.initialized {
+            continue;
+        }
+        // Frames and button are siblings of the screen under its anchor
+        let Ok(siblings) = children_query.get(parent.get()) else {
+            continue;
+        };
 
-    let scale_x = screen_dims.width / SCREEN_WIDTH;
-    let scale_y = screen_dims.height / SCREEN_HEIGHT;
+        let scale_x = screen_dims.width / SCREEN_WIDTH;
+        let scale_y = screen_dims.height / SCREEN_HEIGHT;
 
-    info!(
-        "Applying screen scale: {:.3} x {:.3} (dims {:.2}x{:.2})",
-        scale_x, scale_y, screen_dims.width, screen_dims.height
-    );
+        info!(
+            "Applying screen scale: {:.3} x {:.3} (dims {:.2}x{:.2})",
+            scale_x, scale_y, screen_dims.width, screen_dims.height
+        );
 
-    // Update screen scale
-    for mut transform in screen_query.iter_mut() {
-        transform.scale.x = scale_x;
-        transform.scale.y = scale_y;
-    }
+        // Update screen scale
+        screen_transform.scale.x = scale_x;
+        screen_transform.scale.y = scale_y;
 
-    // Update frame positions
-    let half_width = screen_dims.width / 2.0;
-    let half_height = screen_dims.height / 2.0;
+        // Update frame positions, relative to the screen center
+        let half_width = screen_dims.width / 2.0;
+        let half_height = screen_dims.height / 2.0;
 
-    for (frame_side, mut transform) in frame_query.iter_mut() {
-        match frame_side {
-            ScreenFrame::Top => {
-                transform.translation.y = SCREEN_Y + half_height + FRAME_THICKNESS / 2.0;
-                transform.scale.x = (screen_dims.width + FRAME_THICKNESS * 2.0)
-                    / (SCREEN_WIDTH + FRAME_THICKNESS * 2.0);
-            }
-            ScreenFrame::Bottom => {
-                transform.translation.y = SCREEN_Y - half_height - FRAME_THICKNESS / 2.0;
-                transform.scale.x = (screen_dims.width + FRAME_THICKNESS * 2.0)
-                    / (SCREEN_WIDTH + FRAME_THICKNESS * 2.0);
-            }
-            ScreenFrame::Left => {
-                transform.translation.x = -half_width - FRAME_THICKNESS / 2.0;
-                transform.scale.y = screen_dims.height / SCREEN_HEIGHT;
-            }
-            ScreenFrame::Right => {
-                transform.translation.x = half_width + FRAME_THICKNESS / 2.0;
-                transform.scale.y = screen_dims.height / SCREEN_HEIGHT;
+        let mut frames = frame_query.iter_many_mut(siblings);
+        while let Some((frame_side, mut transform)) = frames.fetch_next() {
+            match frame_side {
+                ScreenFrame::Top => {
+                    transform.translation.y = half_height + FRAME_THICKNESS / 2.0;
+                    transform.scale.x = (screen_dims.width + FRAME_THICKNESS * 2.0)
+                        / (SCREEN_WIDTH + FRAME_THICKNESS * 2.0);
+                }
+                ScreenFrame::Bottom => {
+                    transform.translation.y = -half_height - FRAME_THICKNESS / 2.0;
+                    transform.scale.x = (screen_dims.width + FRAME_THICKNESS * 2.0)
+                        / (SCREEN_WIDTH + FRAME_THICKNESS * 2.0);
+                }
+                ScreenFrame::Left => {
+                    transform.translation.x = -half_width - FRAME_THICKNESS / 2.0;
+                    transform.scale.y = screen_dims.height / SCREEN_HEIGHT;
+                }
+                ScreenFrame::Right => {
+                    transform.translation.x = half_width + FRAME_THICKNESS / 2.0;
+                    transform.scale.y = screen_dims.height / SCREEN_HEIGHT;
+                }
             }
         }
-    }
 
-    // Update button position (bottom-right of screen)
-    for mut transform in button_query.iter_mut() {
-        transform.translation.x = half_width + FRAME_THICKNESS + BUTTON_OFFSET_X + BUTTON_SIZE / 2.0;
-        transform.translation.y = SCREEN_Y - half_height + BUTTON_SIZE / 2.0;
+        // Update button position (bottom-right of screen)
+        let mut buttons = button_query.iter_many_mut(siblings);
+        while let Some(mut transform) = buttons.fetch_next() {
+            transform.translation.x =
+                half_width + FRAME_THICKNESS + BUTTON_OFFSET_X + BUTTON_SIZE / 2.0;
+            transform.translation.y = -half_height + BUTTON_SIZE / 2.0;
+        }
     }
 }
@@ -0,0 +1,143 @@
+//! Picture-in-picture view of the stream: a copy of the streamed screen in a
+//! corner of the HUD, so the stream stays readable from anywhere in the room,
+//! even where the wall screen is far away or out of sight.
+
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+use super::capture::{CaptureTarget, ScreenTexture};
+use crate::controls::{Action, KeyBindings};
+use crate::network::client::ActiveStreamScreen;
+use crate::world::Screen;
+
+/// How long without frames before the window hides.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Window width, in pixels. Its height follows the stream's aspect ratio.
+const PIP_WIDTH: f32 = 360.0;
+
+/// Gap between the window and the corner of the view, in pixels.
+const PIP_MARGIN: f32 = 20.0;
+
+const PIP_BORDER_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+
+/// Resource for whether the player opened the picture-in-picture window.
+#[derive(Resource, Default)]
+pub struct PictureInPicture {
+    pub open: bool,
+    last_frame_at: Option<Instant>,
+}
+
+#[derive(Component)]
+pub struct PictureInPictureRoot;
+
+/// Image node showing the stream.
+#[derive(Component)]
+struct PictureInPictureImage;
+
+/// System to open or close the window with its key.
+pub fn toggle_picture_in_picture(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut pip: ResMut<PictureInPicture>,
+) {
+    if bindings.just_pressed(Action::PictureInPicture, &keyboard_input) {
+        pip.open = !pip.open;
+    }
+}
+
+/// System to spawn, update and despawn the window. It shows while open and
+/// frames reach the streamed screen.
+pub fn update_picture_in_picture(
+    mut commands: Commands,
+    mut pip: ResMut<PictureInPicture>,
+    images: Res<Assets<Image>>,
+    capture_target: Option<Res<CaptureTarget>>,
+    stream_screen: Res<ActiveStreamScreen>,
+    screen_query: Query<(&Screen, Ref<ScreenTexture>)>,
+    root_query: Query<Entity, With<PictureInPictureRoot>>,
+    mut image_query: Query<(&mut ImageNode, &mut Node), With<PictureInPictureImage>>,
+) {
+    // Same screen the cards treat as streamed
+    let streamed_screen = match capture_target {
+        Some(target) => target.0,
+        None => stream_screen.0,
+    };
+    let texture = screen_query
+        .iter()
+        .find(|(screen, _)| screen.id == streamed_screen)
+        .map(|(_, texture)| texture);
+
+    let handle = texture.and_then(|texture| {
+        if texture.is_changed() && texture.handle.is_some() {
+            pip.last_frame_at = Some(Instant::now());
+        }
+        texture.handle.clone()
+    });
+    let live = pip
+        .last_frame_at
+        .is_some_and(|at| at.elapsed() < FRAME_TIMEOUT);
+    let Some(handle) = handle.filter(|_| pip.open && live) else {
+        for entity in root_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    };
+
+    let height = images
+        .get(&handle)
+        .map(|image| image.size_f32())
+        .filter(|size| size.x > 0.0)
+        .map_or(PIP_WIDTH * 9.0 / 16.0, |size| PIP_WIDTH * size.y / size.x);
+    if root_query.is_empty() {
+        spawn_picture_in_picture(&mut commands, handle, height);
+        return;
+    }
+
+    for (mut image, mut node) in image_query.iter_mut() {
+        if image.image != handle {
+            image.image = handle.clone();
+        }
+        if node.height != Val::Px(height) {
+            node.height = Val::Px(height);
+        }
+    }
+}
+
+fn spawn_picture_in_picture(commands: &mut Commands, handle: Handle<Image>, height: f32) {
+    commands
+        .spawn((
+            PictureInPictureRoot,
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(PIP_MARGIN),
+                top: Val::Px(PIP_MARGIN),
+                padding: UiRect::all(Val::Px(3.0)),
+                ..default()
+            },
+            BackgroundColor(PIP_BORDER_COLOR),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                PictureInPictureImage,
+                ImageNode::new(handle),
+                Node {
+                    width: Val::Px(PIP_WIDTH),
+                    height: Val::Px(height),
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Close the window with the session.
+pub fn cleanup_picture_in_picture(
+    mut commands: Commands,
+    mut pip: ResMut<PictureInPicture>,
+    query: Query<Entity, With<PictureInPictureRoot>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    *pip = PictureInPicture::default();
+}
@@ -0,0 +1,77 @@
+//! BGRA to RGBA conversion for captured frames. Rows are converted in
+//! parallel, a pixel at a time as whole words so the compiler vectorizes the
+//! swizzle, and written to the flipped row directly so flipping costs nothing.
+
+use rayon::prelude::*;
+
+/// Swap the blue and red channels of one BGRA row into `dst`. `opaque`
+/// forces alpha to 255 for sources that leave it undefined.
+pub fn bgra_row_to_rgba(src: &[u8], dst: &mut [u8], opaque: bool) {
+    let alpha = if opaque { 0xFF00_0000 } else { 0 };
+    for (s, d) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        let bgra = u32::from_le_bytes([s[0], s[1], s[2], s[3]]);
+        let rgba = (bgra & 0xFF00_FF00) | ((bgra >> 16) & 0xFF) | ((bgra & 0xFF) << 16) | alpha;
+        d.copy_from_slice(&rgba.to_le_bytes());
+    }
+}
+
+/// Convert a top-down BGRA frame with rows `stride` bytes apart into tightly
+/// packed RGBA with the bottom row first, as the rest of the pipeline expects.
+pub fn flip_bgra_to_rgba(
+    src: &[u8],
+    stride: usize,
+    width: usize,
+    height: usize,
+    opaque: bool,
+) -> Vec<u8> {
+    let row_len = width * 4;
+    let mut rgba = vec![0u8; row_len * height];
+    rgba.par_chunks_exact_mut(row_len)
+        .enumerate()
+        .for_each(|(y, dst)| {
+            let start = (height - 1 - y) * stride;
+            // A short last row (it shouldn't happen) stays black
+            if let Some(src) = src.get(start..start + row_len) {
+                bgra_row_to_rgba(src, dst, opaque);
+            }
+        });
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_swaps_blue_and_red() {
+        let src = [1, 2, 3, 4, 5, 6, 7, 8];
+        let mut dst = [0; 8];
+        bgra_row_to_rgba(&src, &mut dst, false);
+        assert_eq!(dst, [3, 2, 1, 4, 7, 6, 5, 8]);
+        bgra_row_to_rgba(&src, &mut dst, true);
+        assert_eq!(dst, [3, 2, 1, 255, 7, 6, 5, 255]);
+    }
+
+    #[test]
+    fn frame_is_flipped_and_unpadded() {
+        // Two rows of two pixels, each row padded by 4 bytes
+        let src = [
+            10, 11, 12, 13, 14, 15, 16, 17, 99, 99, 99, 99, //
+            20, 21, 22, 23, 24, 25, 26, 27, 99, 99, 99, 99,
+        ];
+        let rgba = flip_bgra_to_rgba(&src, 12, 2, 2, false);
+        assert_eq!(
+            rgba,
+            [22, 21, 20, 23, 26, 25, 24, 27, 12, 11, 10, 13, 16, 15, 14, 17]
+        );
+    }
+
+    #[test]
+    fn short_rows_stay_black() {
+        // The last row is cut short, and it comes first once flipped
+        let src = [10, 11, 12, 13, 14, 15, 16, 17, 20, 21, 22, 23];
+        let rgba = flip_bgra_to_rgba(&src, 8, 2, 2, true);
+        assert_eq!(rgba[..8], [0; 8]);
+        assert_eq!(rgba[8..], [12, 11, 10, 255, 16, 15, 14, 255]);
+    }
+}
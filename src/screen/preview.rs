@@ -0,0 +1,102 @@
+//! The host's preview of its own stream. While it's on, the host's screen
+//! shows its encoded output decoded again instead of the captured frames, so
+//! presenters see the quality and framing viewers get.
+
+use bevy::prelude::*;
+
+use super::capture::{CaptureTarget, SharingState};
+use super::video_decoder::VideoDecoder;
+use crate::controls::{Action, KeyBindings};
+use crate::game_state::{AppState, PauseState};
+use crate::menu::NotificationEvent;
+use crate::network::protocol::{VideoChunk, VideoCodecInfo};
+use crate::network::ReceivedScreenFrame;
+
+/// Resource decoding the host's own stream, present while the preview is on.
+/// The server feeds it everything it sends clients.
+#[derive(Resource)]
+pub struct LocalPreview {
+    decoder: VideoDecoder,
+}
+
+impl LocalPreview {
+    /// Switch to the stream's new codec, as clients are told to.
+    pub fn set_codec_info(&mut self, info: VideoCodecInfo) {
+        self.decoder.set_codec_info(info);
+    }
+
+    /// Decode a chunk of the stream, as clients are sent it.
+    pub fn add_chunk(&mut self, chunk: VideoChunk) {
+        self.decoder.add_chunk(chunk);
+    }
+}
+
+pub fn local_preview_plugin(app: &mut App) {
+    app.add_systems(
+        Update,
+        (
+            toggle_local_preview.run_if(in_state(PauseState::Running)),
+            show_local_preview
+                .before(super::handle_received_screen_frames)
+                .run_if(resource_exists::<LocalPreview>),
+        )
+            .run_if(in_state(AppState::InGame)),
+    )
+    .add_systems(OnExit(AppState::InGame), cleanup_local_preview);
+}
+
+/// System to turn the preview on or off with its key while sharing.
+fn toggle_local_preview(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    sharing: Res<SharingState>,
+    preview: Option<Res<LocalPreview>>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if !bindings.just_pressed(Action::StreamPreview, &keyboard_input) {
+        return;
+    }
+    if preview.is_some() {
+        commands.remove_resource::<LocalPreview>();
+        notifications.send(NotificationEvent("Showing the captured source".to_string()));
+        return;
+    }
+    if sharing.source.is_none() {
+        notifications.send(NotificationEvent(
+            "Share something to preview what viewers see".to_string(),
+        ));
+        return;
+    }
+    let Some(decoder) = VideoDecoder::new() else {
+        warn!("Couldn't start a decoder for the stream preview");
+        return;
+    };
+    commands.insert_resource(LocalPreview { decoder });
+    notifications.send(NotificationEvent("Showing what viewers see".to_string()));
+}
+
+/// System to show the preview's decoded frames on the shared screen, the way
+/// clients show the stream.
+fn show_local_preview(
+    preview: Res<LocalPreview>,
+    capture_target: Option<Res<CaptureTarget>>,
+    mut screen_frame_events: EventWriter<ReceivedScreenFrame>,
+) {
+    let Some(frame) = preview.decoder.get_decoded() else {
+        return;
+    };
+    let Some(target) = capture_target else {
+        return;
+    };
+    screen_frame_events.send(ReceivedScreenFrame {
+        rgba: frame.rgba,
+        width: frame.width,
+        height: frame.height,
+        screen_id: target.0,
+    });
+}
+
+fn cleanup_local_preview(mut commands: Commands) {
+    commands.remove_resource::<LocalPreview>();
+}
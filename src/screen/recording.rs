@@ -0,0 +1,334 @@
+//! Host-side session recording, started and stopped from the pause menu.
+//! The already-encoded video stream and the captured audio are written to
+//! temporary files while recording, then FFmpeg muxes them into a Matroska
+//! file in the videos folder.
+
+use bevy::prelude::*;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::ffmpeg;
+use super::video_encoder::{RecordedVideoUnit, VideoEncoder};
+use crate::menu::{ErrorNotification, NotificationEvent};
+use crate::network::protocol::VideoCodecKind;
+
+/// Folder recordings are saved in, under the user's videos folder.
+const RECORDINGS_DIR_NAME: &str = "zine";
+
+/// Event to start recording, or stop if already recording.
+#[derive(Event)]
+pub struct ToggleRecording;
+
+/// Captured audio handed to the recorder.
+struct AudioBlock {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+/// Resource present while the host records the session.
+#[derive(Resource)]
+pub struct SessionRecorder {
+    audio_tx: Mutex<Sender<AudioBlock>>,
+    video_thread: Option<JoinHandle<bool>>,
+    audio_thread: Option<JoinHandle<Option<(u32, u16)>>>,
+    video_path: PathBuf,
+    audio_path: PathBuf,
+    output: PathBuf,
+}
+
+/// Resource holding recordings still being muxed, each reporting where it
+/// was saved or what went wrong.
+#[derive(Resource, Default)]
+pub struct FinishingRecordings(Vec<Mutex<Receiver<Result<PathBuf, String>>>>);
+
+impl SessionRecorder {
+    /// Start recording what the encoder produces from now on.
+    fn start(encoder: &VideoEncoder) -> Result<Self, String> {
+        if !ffmpeg::is_available() {
+            return Err("Recording needs FFmpeg installed".to_string());
+        }
+
+        let dir = dirs::video_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_default()
+            .join(RECORDINGS_DIR_NAME);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))?;
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let name = format!("zine-{}", stamp);
+        let output = dir.join(format!("{}.mkv", name));
+        let video_path = std::env::temp_dir().join(format!("{}.video.mkv", name));
+        let audio_path = std::env::temp_dir().join(format!("{}.audio.raw", name));
+
+        let (video_tx, video_rx) = mpsc::channel();
+        let (audio_tx, audio_rx) = mpsc::channel();
+        let video_thread = {
+            let path = video_path.clone();
+            thread::spawn(move || record_video(video_rx, &path))
+        };
+        let audio_thread = {
+            let path = audio_path.clone();
+            thread::spawn(move || record_audio(audio_rx, &path))
+        };
+        encoder.set_recorder(Some(video_tx));
+
+        info!("Recording session to {}", output.display());
+        Ok(Self {
+            audio_tx: Mutex::new(audio_tx),
+            video_thread: Some(video_thread),
+            audio_thread: Some(audio_thread),
+            video_path,
+            audio_path,
+            output,
+        })
+    }
+
+    /// Record audio as it's captured.
+    pub fn record_audio(&self, samples: &[f32], sample_rate: u32, channels: u16) {
+        if let Ok(sender) = self.audio_tx.lock() {
+            let _ = sender.send(AudioBlock {
+                samples: samples.to_vec(),
+                sample_rate,
+                channels,
+            });
+        }
+    }
+
+    /// Stop recording and mux the file in the background.
+    fn finish(&mut self, encoder: Option<&VideoEncoder>) -> Receiver<Result<PathBuf, String>> {
+        if let Some(encoder) = encoder {
+            encoder.set_recorder(None);
+        }
+        // Closing the channel ends the audio thread
+        let (closed_tx, _) = mpsc::channel();
+        if let Ok(mut sender) = self.audio_tx.lock() {
+            *sender = closed_tx;
+        }
+
+        let video_thread = self.video_thread.take();
+        let audio_thread = self.audio_thread.take();
+        let video_path = self.video_path.clone();
+        let audio_path = self.audio_path.clone();
+        let output = self.output.clone();
+        let (result_tx, result_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let has_video = video_thread.is_some_and(|t| t.join().unwrap_or(false));
+            let audio_format = audio_thread.and_then(|t| t.join().ok().flatten());
+            let result = mux_recording(has_video, audio_format, &video_path, &audio_path, &output);
+            let _ = fs::remove_file(&video_path);
+            let _ = fs::remove_file(&audio_path);
+            let _ = result_tx.send(result);
+        });
+        result_rx
+    }
+}
+
+/// FFmpeg input format of raw access units in a codec.
+fn raw_video_format(codec: VideoCodecKind) -> &'static str {
+    match codec {
+        VideoCodecKind::H264 => "h264",
+        VideoCodecKind::Hevc => "hevc",
+        VideoCodecKind::Av1 => "obu",
+    }
+}
+
+fn spawn_video_writer(codec: VideoCodecKind, path: &Path) -> Option<(Child, ChildStdin)> {
+    // Raw streams carry no timestamps, so stamp units as they arrive
+    let mut child = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-use_wallclock_as_timestamps", "1"])
+        .args(["-f", raw_video_format(codec), "-i", "-"])
+        .args(["-c:v", "copy"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| error!("Failed to spawn FFmpeg for recording: {}", e))
+        .ok()?;
+    let stdin = child.stdin.take()?;
+    Some((child, stdin))
+}
+
+/// Write encoded video to `path` from the first keyframe on. Returns whether
+/// any video was written.
+fn record_video(units: Receiver<RecordedVideoUnit>, path: &Path) -> bool {
+    let mut writer: Option<(Child, ChildStdin, VideoCodecKind)> = None;
+    for unit in units.iter() {
+        let (_, stdin, codec) = match &mut writer {
+            Some(writer) => writer,
+            None => {
+                // Frames before the first keyframe can't be decoded
+                if !unit.keyframe {
+                    continue;
+                }
+                let Some((child, stdin)) = spawn_video_writer(unit.codec, path) else {
+                    return false;
+                };
+                writer.insert((child, stdin, unit.codec))
+            }
+        };
+        // A new client can switch the stream's codec, which one file can't hold
+        if unit.codec != *codec {
+            warn!("Video codec changed, ending the recording's video");
+            break;
+        }
+        if stdin.write_all(&unit.data).is_err() {
+            error!("Recording FFmpeg process stopped");
+            break;
+        }
+    }
+
+    let Some((mut child, stdin, _)) = writer else {
+        return false;
+    };
+    drop(stdin);
+    child.wait().is_ok_and(|status| status.success())
+}
+
+/// Write captured audio to `path` as raw 32-bit float samples. Returns the
+/// format, if any audio was written.
+fn record_audio(blocks: Receiver<AudioBlock>, path: &Path) -> Option<(u32, u16)> {
+    let mut file = BufWriter::new(File::create(path).ok()?);
+    let mut format = None;
+    for block in blocks.iter() {
+        // Blocks in a different format after a device switch are left out
+        let block_format = (block.sample_rate, block.channels);
+        if *format.get_or_insert(block_format) != block_format {
+            continue;
+        }
+        for sample in &block.samples {
+            if file.write_all(&sample.to_le_bytes()).is_err() {
+                return None;
+            }
+        }
+    }
+    file.flush().ok()?;
+    format
+}
+
+fn mux_recording(
+    has_video: bool,
+    audio_format: Option<(u32, u16)>,
+    video_path: &Path,
+    audio_path: &Path,
+    output: &Path,
+) -> Result<PathBuf, String> {
+    if !has_video {
+        return Err("Nothing was shared while recording".to_string());
+    }
+    let Some((sample_rate, channels)) = audio_format else {
+        fs::copy(video_path, output).map_err(|e| format!("Couldn't save recording: {}", e))?;
+        return Ok(output.to_path_buf());
+    };
+
+    let status = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .arg("-i")
+        .arg(video_path)
+        .args(["-f", "f32le", "-ar", &sample_rate.to_string()])
+        .args(["-ac", &channels.to_string(), "-i"])
+        .arg(audio_path)
+        .args(["-c:v", "copy", "-c:a", "aac"])
+        .arg(output)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("Couldn't run FFmpeg: {}", e))?;
+    if !status.success() {
+        return Err("FFmpeg couldn't save the recording".to_string());
+    }
+    Ok(output.to_path_buf())
+}
+
+/// System to start or stop recording when asked to.
+pub fn toggle_recording(
+    mut commands: Commands,
+    mut events: EventReader<ToggleRecording>,
+    recorder: Option<ResMut<SessionRecorder>>,
+    encoder: Option<Res<VideoEncoder>>,
+    mut finishing: ResMut<FinishingRecordings>,
+    mut notifications: EventWriter<NotificationEvent>,
+    mut errors: EventWriter<ErrorNotification>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+
+    if let Some(mut recorder) = recorder {
+        let result = recorder.finish(encoder.as_deref());
+        finishing.0.push(Mutex::new(result));
+        commands.remove_resource::<SessionRecorder>();
+        notifications.send(NotificationEvent("Saving recording...".to_string()));
+        return;
+    }
+
+    let Some(encoder) = encoder else {
+        return;
+    };
+    match SessionRecorder::start(&encoder) {
+        Ok(recorder) => {
+            commands.insert_resource(recorder);
+            notifications.send(NotificationEvent("Recording started".to_string()));
+        }
+        Err(message) => {
+            warn!("Couldn't start recording: {}", message);
+            errors.send(ErrorNotification::new(message));
+        }
+    }
+}
+
+/// System to say where each recording was saved once it's muxed.
+pub fn report_finished_recordings(
+    mut finishing: ResMut<FinishingRecordings>,
+    mut notifications: EventWriter<NotificationEvent>,
+    mut errors: EventWriter<ErrorNotification>,
+) {
+    finishing.0.retain(|receiver| {
+        let Ok(receiver) = receiver.lock() else {
+            return false;
+        };
+        match receiver.try_recv() {
+            Ok(Ok(path)) => {
+                info!("Recording saved to {}", path.display());
+                notifications.send(NotificationEvent(format!(
+                    "Recording saved to {}",
+                    path.display()
+                )));
+                false
+            }
+            Ok(Err(message)) => {
+                warn!("Recording failed: {}", message);
+                errors.send(ErrorNotification::new(message));
+                false
+            }
+            Err(mpsc::TryRecvError::Empty) => true,
+            Err(mpsc::TryRecvError::Disconnected) => false,
+        }
+    });
+}
+
+/// Stop recording when the session ends, still saving the file.
+pub fn stop_recording(
+    mut commands: Commands,
+    recorder: Option<ResMut<SessionRecorder>>,
+    encoder: Option<Res<VideoEncoder>>,
+    mut finishing: ResMut<FinishingRecordings>,
+) {
+    if let Some(mut recorder) = recorder {
+        let result = recorder.finish(encoder.as_deref());
+        finishing.0.push(Mutex::new(result));
+        commands.remove_resource::<SessionRecorder>();
+    }
+}
@@ -0,0 +1,340 @@
+//! What a screen shows when no video plays on it. Each screen gets a card,
+//! UI rendered to a texture: "No one is presenting" while idle, or a spinner
+//! while a stream is starting and the jitter buffer fills. Once frames flow,
+//! a tag in the corner of the screen names the presenter.
+
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use std::time::{Duration, Instant};
+
+use super::capture::{CaptureTarget, ScreenTexture};
+use crate::network::client::ActiveStreamScreen;
+use crate::network::server::GameServer;
+use crate::network::PlayerList;
+use crate::world::setup::{SCREEN_DEPTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::world::Screen;
+
+/// How long without frames before a screen goes back to its card.
+const FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Card texture size, in the screen's base aspect ratio.
+const CARD_SIZE: (u32, u32) = (960, 480);
+
+/// Presenter tag texture size.
+const TAG_SIZE: (u32, u32) = (512, 64);
+
+/// Presenter tag width on the screen, in world units.
+const TAG_WIDTH: f32 = 1.5;
+
+/// Gap between the tag and the screen's corner, in world units.
+const TAG_MARGIN: f32 = 0.1;
+
+/// Spinner turn rate, in radians per second.
+const SPINNER_SPEED: f32 = 6.0;
+
+const CARD_BG_COLOR: Color = Color::srgb(0.05, 0.05, 0.08);
+const CARD_TITLE_COLOR: Color = Color::srgb(0.85, 0.85, 0.9);
+const CARD_DETAIL_COLOR: Color = Color::srgb(0.5, 0.5, 0.55);
+const SPINNER_COLOR: Color = Color::srgb(0.9, 0.75, 0.3);
+const TAG_BG_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+
+/// Card and presenter tag of a screen, added to the screen entity.
+#[derive(Component)]
+pub struct ScreenCard {
+    material: Handle<StandardMaterial>,
+    card_camera: Entity,
+    title: Entity,
+    detail: Entity,
+    spinner: Entity,
+    tag_camera: Entity,
+    tag: Entity,
+    tag_text: Entity,
+    last_frame_at: Option<Instant>,
+}
+
+/// Marker for the cameras and UI trees that render screen cards, which live
+/// outside the world hierarchy.
+#[derive(Component)]
+pub struct ScreenCardView;
+
+/// What a screen is showing.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScreenCardState {
+    Idle,
+    Connecting,
+    Live,
+}
+
+/// Image a camera can render UI into.
+fn render_target_image(images: &mut Assets<Image>, (width, height): (u32, u32)) -> Handle<Image> {
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Bgra8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
+    images.add(image)
+}
+
+fn spawn_view_camera(commands: &mut Commands, image: Handle<Image>, clear_color: Color) -> Entity {
+    commands
+        .spawn((
+            ScreenCardView,
+            Camera2d,
+            Camera {
+                target: RenderTarget::Image(image),
+                clear_color: ClearColorConfig::Custom(clear_color),
+                ..default()
+            },
+        ))
+        .id()
+}
+
+/// System to give each new screen its card and presenter tag.
+pub fn setup_screen_cards(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    screen_query: Query<Entity, Added<Screen>>,
+) {
+    for screen in screen_query.iter() {
+        // Card
+        let card_image = render_target_image(&mut images, CARD_SIZE);
+        let card_camera = spawn_view_camera(&mut commands, card_image.clone(), CARD_BG_COLOR);
+        let title = commands
+            .spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 48.0,
+                    ..default()
+                },
+                TextColor(CARD_TITLE_COLOR),
+            ))
+            .id();
+        let detail = commands
+            .spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+                TextColor(CARD_DETAIL_COLOR),
+            ))
+            .id();
+        let spinner = commands
+            .spawn((
+                Node {
+                    width: Val::Px(48.0),
+                    height: Val::Px(8.0),
+                    margin: UiRect::bottom(Val::Px(24.0)),
+                    ..default()
+                },
+                BackgroundColor(SPINNER_COLOR),
+                Visibility::Hidden,
+            ))
+            .id();
+        commands
+            .spawn((
+                ScreenCardView,
+                TargetCamera(card_camera),
+                Node {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(12.0),
+                    ..default()
+                },
+            ))
+            .add_children(&[spinner, title, detail]);
+
+        // Presenter tag, just in front of the bottom-left corner
+        let tag_image = render_target_image(&mut images, TAG_SIZE);
+        let tag_camera = spawn_view_camera(&mut commands, tag_image.clone(), Color::NONE);
+        let tag_text = commands
+            .spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 32.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ))
+            .id();
+        commands
+            .spawn((
+                ScreenCardView,
+                TargetCamera(tag_camera),
+                Node {
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    padding: UiRect::horizontal(Val::Px(16.0)),
+                    ..default()
+                },
+                BackgroundColor(TAG_BG_COLOR),
+            ))
+            .add_child(tag_text);
+
+        let tag_height = TAG_WIDTH * TAG_SIZE.1 as f32 / TAG_SIZE.0 as f32;
+        let tag = commands
+            .spawn((
+                Mesh3d(meshes.add(Rectangle::new(TAG_WIDTH, tag_height))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color_texture: Some(tag_image),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..default()
+                })),
+                Transform::from_xyz(
+                    -SCREEN_WIDTH / 2.0 + TAG_WIDTH / 2.0 + TAG_MARGIN,
+                    -SCREEN_HEIGHT / 2.0 + tag_height / 2.0 + TAG_MARGIN,
+                    SCREEN_DEPTH / 2.0 + 0.005,
+                ),
+                Visibility::Hidden,
+            ))
+            .id();
+        commands.entity(screen).add_child(tag);
+
+        let material = materials.add(StandardMaterial {
+            base_color_texture: Some(card_image),
+            unlit: true,
+            ..default()
+        });
+        commands.entity(screen).insert(ScreenCard {
+            material,
+            card_camera,
+            title,
+            detail,
+            spinner,
+            tag_camera,
+            tag,
+            tag_text,
+            last_frame_at: None,
+        });
+    }
+}
+
+/// System to switch each screen between its card and the video on it.
+pub fn update_screen_cards(
+    time: Res<Time>,
+    player_list: Res<PlayerList>,
+    server: Option<Res<GameServer>>,
+    capture_target: Option<Res<CaptureTarget>>,
+    stream_screen: Res<ActiveStreamScreen>,
+    mut screen_query: Query<(
+        &Screen,
+        Ref<ScreenTexture>,
+        &mut ScreenCard,
+        &mut MeshMaterial3d<StandardMaterial>,
+    )>,
+    mut camera_query: Query<&mut Camera>,
+    mut text_query: Query<&mut Text>,
+    mut view_query: Query<(&mut Visibility, &mut Transform)>,
+) {
+    // The host streams to the screen it captures to, clients watch the one
+    // the host names
+    let streamed_screen = match capture_target {
+        Some(target) => target.0,
+        None => stream_screen.0,
+    };
+    let presenter = player_list.players.iter().find(|p| p.presenting);
+
+    for (screen, texture, mut card, mut screen_mat) in screen_query.iter_mut() {
+        // Both captured and received frames replace the texture
+        if texture.is_changed() && texture.handle.is_some() {
+            card.last_frame_at = Some(Instant::now());
+        }
+        let live = texture.material_handle.is_some()
+            && card
+                .last_frame_at
+                .is_some_and(|at| at.elapsed() < FRAME_TIMEOUT);
+        let presenter = presenter.filter(|_| screen.id == streamed_screen);
+        let state = match (live, presenter) {
+            (true, _) => ScreenCardState::Live,
+            (false, Some(_)) => ScreenCardState::Connecting,
+            (false, None) => ScreenCardState::Idle,
+        };
+
+        let material = match state {
+            ScreenCardState::Live => texture.material_handle.clone(),
+            _ => Some(card.material.clone()),
+        };
+        if let Some(material) = material {
+            if screen_mat.0 != material {
+                screen_mat.0 = material;
+            }
+        }
+
+        // Only render what shows
+        if let Ok(mut camera) = camera_query.get_mut(card.card_camera) {
+            camera.is_active = state != ScreenCardState::Live;
+        }
+        let tagged = state == ScreenCardState::Live && presenter.is_some();
+        if let Ok(mut camera) = camera_query.get_mut(card.tag_camera) {
+            camera.is_active = tagged;
+        }
+        if let Ok((mut visibility, _)) = view_query.get_mut(card.tag) {
+            visibility.set_if_neq(if tagged {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            });
+        }
+
+        let presenter_name = presenter.map(|p| p.display_name()).unwrap_or_default();
+        let (title, detail) = match state {
+            ScreenCardState::Idle if server.is_some() => (
+                "No one is presenting".to_string(),
+                "Right-click the button beside the screen to share".to_string(),
+            ),
+            ScreenCardState::Idle => (
+                "No one is presenting".to_string(),
+                "Waiting for the host to share their screen".to_string(),
+            ),
+            ScreenCardState::Connecting => (
+                "Connecting to stream...".to_string(),
+                format!("{} is presenting", presenter_name),
+            ),
+            ScreenCardState::Live => (String::new(), String::new()),
+        };
+        for (entity, value) in [
+            (card.title, title),
+            (card.detail, detail),
+            (card.tag_text, presenter_name),
+        ] {
+            if let Ok(mut text) = text_query.get_mut(entity) {
+                if text.0 != value {
+                    text.0 = value;
+                }
+            }
+        }
+
+        if let Ok((mut visibility, mut transform)) = view_query.get_mut(card.spinner) {
+            if state == ScreenCardState::Connecting {
+                visibility.set_if_neq(Visibility::Inherited);
+                transform.rotate_z(-SPINNER_SPEED * time.delta_secs());
+            } else {
+                visibility.set_if_neq(Visibility::Hidden);
+            }
+        }
+    }
+}
+
+/// Despawn the card cameras and UI, which the world cleanup doesn't reach.
+pub fn cleanup_screen_cards(mut commands: Commands, query: Query<Entity, With<ScreenCardView>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
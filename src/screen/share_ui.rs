@@ -1,9 +1,12 @@
 use bevy::prelude::*;
-use bevy::window::CursorGrabMode;
+use bevy::window::{CursorGrabMode, Monitor, PrimaryWindow};
 use scrap::Display;
 
-use super::capture::{CaptureSource, CaptureSourceType};
+use super::capture::{CaptureSource, CaptureSourceType, SharingState, StopCapture};
+use super::thumbnails::{SourceThumbnail, SourceThumbnails};
 use super::window_capture::{enumerate_windows, WindowInfo};
+use crate::menu::ErrorNotification;
+use crate::network::protocol::ScreenId;
 
 /// Resource tracking the share UI state.
 #[derive(Resource, Default)]
@@ -14,6 +17,8 @@ pub struct ShareUIState {
     pub available_windows: Vec<WindowInfo>,
     pub needs_refresh: bool,
     pub last_rendered_tab: Option<ShareTab>,
+    /// Room screen the chosen source will be shown on.
+    pub target_screen: ScreenId,
 }
 
 #[derive(Default, Clone, Copy, PartialEq, Eq)]
@@ -49,6 +54,17 @@ pub struct CancelButton;
 #[derive(Component)]
 pub struct ShareButton;
 
+/// Label of the share button, which switches sources while sharing.
+#[derive(Component)]
+pub struct ShareButtonText;
+
+/// Header line saying what is being shared.
+#[derive(Component)]
+pub struct SharingStatusText;
+
+#[derive(Component)]
+pub struct StopSharingButton;
+
 #[derive(Component)]
 pub struct SourceListContainer;
 
@@ -57,10 +73,10 @@ const BG_COLOR: Color = Color::srgba(0.1, 0.1, 0.1, 0.95);
 const TAB_NORMAL: Color = Color::srgb(0.2, 0.2, 0.2);
 const TAB_SELECTED: Color = Color::srgb(0.3, 0.5, 0.3);
 const BUTTON_NORMAL: Color = Color::srgb(0.25, 0.25, 0.25);
-const BUTTON_HOVER: Color = Color::srgb(0.35, 0.35, 0.35);
 const SOURCE_SELECTED: Color = Color::srgb(0.2, 0.4, 0.6);
 const CANCEL_COLOR: Color = Color::srgb(0.5, 0.3, 0.3);
 const SHARE_COLOR: Color = Color::srgb(0.3, 0.5, 0.3);
+const STATUS_COLOR: Color = Color::srgb(0.7, 0.7, 0.7);
 
 pub fn setup_share_ui(commands: &mut Commands) {
     // Release cursor for UI interaction
@@ -103,11 +119,57 @@ pub fn setup_share_ui(commands: &mut Commands) {
                         },
                         TextColor(Color::WHITE),
                         Node {
-                            margin: UiRect::bottom(Val::Px(15.0)),
+                            margin: UiRect::bottom(Val::Px(10.0)),
                             ..default()
                         },
                     ));
 
+                    // What is shared right now, with a button to stop it
+                    modal
+                        .spawn((Node {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::SpaceBetween,
+                            height: Val::Px(30.0),
+                            margin: UiRect::bottom(Val::Px(10.0)),
+                            ..default()
+                        },))
+                        .with_children(|header| {
+                            header.spawn((
+                                SharingStatusText,
+                                Text::new("Not sharing"),
+                                TextFont {
+                                    font_size: 14.0,
+                                    ..default()
+                                },
+                                TextColor(STATUS_COLOR),
+                            ));
+                            header
+                                .spawn((
+                                    StopSharingButton,
+                                    Button,
+                                    Node {
+                                        display: Display::None,
+                                        width: Val::Px(80.0),
+                                        height: Val::Px(28.0),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(CANCEL_COLOR),
+                                ))
+                                .with_children(|btn| {
+                                    btn.spawn((
+                                        Text::new("Stop"),
+                                        TextFont {
+                                            font_size: 14.0,
+                                            ..default()
+                                        },
+                                        TextColor(Color::WHITE),
+                                    ));
+                                });
+                        });
+
                     // Tab bar
                     modal
                         .spawn((Node {
@@ -185,6 +247,7 @@ pub fn setup_share_ui(commands: &mut Commands) {
                                 ))
                                 .with_children(|btn| {
                                     btn.spawn((
+                                        ShareButtonText,
                                         Text::new("Share"),
                                         TextFont {
                                             font_size: 16.0,
@@ -203,12 +266,6 @@ pub fn setup_share_ui(commands: &mut Commands) {
     info!("Share UI opened, marking state for refresh");
 }
 
-/// Call this when opening the UI to ensure the list refreshes
-pub fn mark_share_ui_needs_refresh(mut state: ResMut<ShareUIState>) {
-    state.needs_refresh = true;
-    state.selected_source = None;
-}
-
 fn spawn_tab_button(parent: &mut ChildBuilder, label: &str, tab: ShareTab, selected: bool) {
     parent
         .spawn((
@@ -246,15 +303,17 @@ pub fn cleanup_share_ui(mut commands: Commands, root: Option<Res<ShareUIRoot>>)
 pub fn handle_share_ui_interaction(
     mut commands: Commands,
     mut state: ResMut<ShareUIState>,
-    mut windows: Query<&mut Window>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
     root: Option<Res<ShareUIRoot>>,
     tab_query: Query<(&Interaction, &TabButton), Changed<Interaction>>,
     source_query: Query<(&Interaction, &SourceButton), Changed<Interaction>>,
     cancel_query: Query<&Interaction, (Changed<Interaction>, With<CancelButton>)>,
     share_query: Query<&Interaction, (Changed<Interaction>, With<ShareButton>)>,
+    stop_query: Query<&Interaction, (Changed<Interaction>, With<StopSharingButton>)>,
     mut tab_buttons: Query<(&TabButton, &mut BackgroundColor), Without<SourceButton>>,
     mut source_buttons: Query<(&SourceButton, &mut BackgroundColor), Without<TabButton>>,
     mut capture_events: EventWriter<CaptureSource>,
+    mut stop_events: EventWriter<StopCapture>,
 ) {
     let Some(root) = root else { return };
 
@@ -299,6 +358,13 @@ pub fn handle_share_ui_interaction(
         }
     }
 
+    // Handle stop, keeping the UI open to pick something else
+    for interaction in stop_query.iter() {
+        if *interaction == Interaction::Pressed {
+            stop_events.send(StopCapture);
+        }
+    }
+
     // Handle cancel
     for interaction in cancel_query.iter() {
         if *interaction == Interaction::Pressed {
@@ -323,7 +389,10 @@ pub fn handle_share_ui_interaction(
                     ShareTab::Screens => {
                         if let Some(screen) = state.available_screens.get(source_idx) {
                             info!("Starting display capture for screen {}", screen.index);
-                            Some(CaptureSourceType::Display(screen.index))
+                            Some((
+                                CaptureSourceType::Display(screen.index),
+                                screen.name.clone(),
+                            ))
                         } else {
                             None
                         }
@@ -331,15 +400,19 @@ pub fn handle_share_ui_interaction(
                     ShareTab::Windows => {
                         if let Some(window) = state.available_windows.get(source_idx) {
                             info!("Starting window capture for: {} (hwnd: {})", window.title, window.hwnd);
-                            Some(CaptureSourceType::Window(window.hwnd))
+                            Some((CaptureSourceType::Window(window.hwnd), window.title.clone()))
                         } else {
                             None
                         }
                     }
                 };
 
-                if let Some(source) = capture_source {
-                    capture_events.send(CaptureSource { source });
+                if let Some((source, label)) = capture_source {
+                    capture_events.send(CaptureSource {
+                        source,
+                        screen: state.target_screen,
+                        label,
+                    });
 
                     // Close UI
                     commands.entity(root.0).despawn_recursive();
@@ -357,10 +430,28 @@ pub fn handle_share_ui_interaction(
     }
 }
 
+/// System to list the displays again when one is plugged in or out while
+/// the share UI is open.
+pub fn refresh_displays_on_change(
+    added: Query<(), Added<Monitor>>,
+    mut removed: RemovedComponents<Monitor>,
+    mut state: ResMut<ShareUIState>,
+) {
+    if added.is_empty() && removed.read().count() == 0 {
+        return;
+    }
+    info!("Displays changed, listing them again");
+    state.needs_refresh = true;
+    if state.selected_tab == ShareTab::Screens {
+        state.selected_source = None;
+    }
+}
+
 pub fn update_source_list(
     mut commands: Commands,
     mut state: ResMut<ShareUIState>,
     list_container: Query<Entity, With<SourceListContainer>>,
+    mut errors: EventWriter<ErrorNotification>,
 ) {
     // Check if tab changed
     let tab_changed = state.last_rendered_tab != Some(state.selected_tab);
@@ -382,24 +473,30 @@ pub fn update_source_list(
     // Enumerate data based on selected tab
     match state.selected_tab {
         ShareTab::Screens => {
-            if state.available_screens.is_empty() {
-                // Enumerate displays
-                match Display::all() {
-                    Ok(displays) => {
-                        info!("Found {} displays", displays.len());
-                        for (i, disp) in displays.iter().enumerate() {
-                            let w = disp.width();
-                            let h = disp.height();
-                            let name = format!("Display {} ({}x{})", i + 1, w, h);
-                            info!("  Display {}: {}x{}", i, w, h);
-                            state.available_screens.push(ScreenInfo {
-                                name,
-                                index: i,
-                            });
-                        }
+            // Enumerate displays afresh, as they may have been plugged in or out
+            state.available_screens.clear();
+            match Display::all() {
+                Ok(displays) => {
+                    info!("Found {} displays", displays.len());
+                    for (i, disp) in displays.iter().enumerate() {
+                        let w = disp.width();
+                        let h = disp.height();
+                        let name = format!("Display {} ({}x{})", i + 1, w, h);
+                        info!("  Display {}: {}x{}", i, w, h);
+                        state.available_screens.push(ScreenInfo {
+                            name,
+                            index: i,
+                        });
                     }
-                    Err(e) => {
-                        error!("Failed to enumerate displays: {}", e);
+                }
+                Err(e) => {
+                    error!("Failed to enumerate displays: {}", e);
+                    // Retried every frame, so only shown once per visit to the tab
+                    if tab_changed {
+                        errors.send(ErrorNotification::new(format!(
+                            "Couldn't list your displays: {}",
+                            e
+                        )));
                     }
                 }
             }
@@ -417,6 +514,21 @@ pub fn update_source_list(
         }
     }
 
+    // Preview what each source shows
+    let sources = match state.selected_tab {
+        ShareTab::Screens => state
+            .available_screens
+            .iter()
+            .map(|screen| CaptureSourceType::Display(screen.index))
+            .collect(),
+        ShareTab::Windows => state
+            .available_windows
+            .iter()
+            .map(|window| CaptureSourceType::Window(window.hwnd))
+            .collect(),
+    };
+    commands.insert_resource(SourceThumbnails::capture(sources));
+
     // Populate the list
     if let Ok(container) = list_container.get_single() {
         // Clear all children of the container (buttons and placeholder text)
@@ -426,7 +538,12 @@ pub fn update_source_list(
         commands.entity(container).with_children(|parent| {
             if state.selected_tab == ShareTab::Screens {
                 for screen in &state.available_screens {
-                    spawn_source_button(parent, &screen.name, screen.index);
+                    spawn_source_button(
+                        parent,
+                        &screen.name,
+                        screen.index,
+                        CaptureSourceType::Display(screen.index),
+                    );
                 }
 
                 if state.available_screens.is_empty() {
@@ -452,7 +569,12 @@ pub fn update_source_list(
                     } else {
                         window.title.clone()
                     };
-                    spawn_source_button(parent, &title, idx);
+                    spawn_source_button(
+                        parent,
+                        &title,
+                        idx,
+                        CaptureSourceType::Window(window.hwnd),
+                    );
                 }
 
                 if state.available_windows.is_empty() {
@@ -474,22 +596,39 @@ pub fn update_source_list(
     }
 }
 
-fn spawn_source_button(parent: &mut ChildBuilder, label: &str, index: usize) {
+fn spawn_source_button(
+    parent: &mut ChildBuilder,
+    label: &str,
+    index: usize,
+    source: CaptureSourceType,
+) {
     parent
         .spawn((
             SourceButton(index),
             Button,
             Node {
                 width: Val::Percent(100.0),
-                height: Val::Px(45.0),
+                height: Val::Px(65.0),
                 padding: UiRect::all(Val::Px(10.0)),
                 margin: UiRect::all(Val::Px(2.0)),
                 align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
                 ..default()
             },
             BackgroundColor(BUTTON_NORMAL),
         ))
         .with_children(|btn| {
+            // Filled in once the thumbnail is captured
+            btn.spawn((
+                SourceThumbnail(source),
+                Node {
+                    width: Val::Px(80.0),
+                    height: Val::Px(45.0),
+                    flex_shrink: 0.0,
+                    ..default()
+                },
+                BackgroundColor(Color::srgb(0.1, 0.1, 0.1)),
+            ));
             btn.spawn((
                 Text::new(label),
                 TextFont {
@@ -500,3 +639,37 @@ fn spawn_source_button(parent: &mut ChildBuilder, label: &str, index: usize) {
             ));
         });
 }
+
+/// System to keep the share UI header and share button in step with what is
+/// being shared.
+pub fn update_sharing_header(
+    sharing: Res<SharingState>,
+    mut status_query: Query<&mut Text, (With<SharingStatusText>, Without<ShareButtonText>)>,
+    mut share_text_query: Query<&mut Text, (With<ShareButtonText>, Without<SharingStatusText>)>,
+    mut stop_query: Query<&mut Node, With<StopSharingButton>>,
+) {
+    let (status, share_label, stop_display) = match sharing.source {
+        Some(_) => (
+            format!("Currently sharing: {}", sharing.label),
+            "Switch",
+            Display::Flex,
+        ),
+        None => ("Not sharing".to_string(), "Share", Display::None),
+    };
+
+    for mut text in status_query.iter_mut() {
+        if text.0 != status {
+            text.0 = status.clone();
+        }
+    }
+    for mut text in share_text_query.iter_mut() {
+        if text.0 != share_label {
+            text.0 = share_label.to_string();
+        }
+    }
+    for mut node in stop_query.iter_mut() {
+        if node.display != stop_display {
+            node.display = stop_display;
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Stream quality indicator for viewers: resolution, frame rate and bitrate
+//! of the stream as received, and a spinner while frames stop coming out of
+//! the jitter buffer even though packets still arrive. That tells viewers
+//! whether a stutter is on their end or the presenter's. For the host it
+//! shows the encoded stream and the client datagrams dropped by the server's
+//! rate limits instead.
+
+use bevy::diagnostic::DiagnosticsStore;
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+use super::diagnostics::{ENCODE_FPS, SEND_KBPS};
+use crate::controls::{Action, KeyBindings};
+use crate::network::server::GameServer;
+use crate::network::ReceivedScreenFrame;
+use crate::settings::VideoSettings;
+
+/// How often the frame rate and bitrate are recomputed.
+const STATS_WINDOW: Duration = Duration::from_secs(1);
+
+/// How long without video packets before the stream counts as stopped.
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Gap between shown frames, while packets still arrive, that counts as buffering.
+const BUFFERING_THRESHOLD: Duration = Duration::from_millis(300);
+
+/// Spinner turn rate, in radians per second.
+const SPINNER_SPEED: f32 = 6.0;
+
+const HUD_BG_COLOR: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const HUD_TEXT_COLOR: Color = Color::srgb(0.85, 0.85, 0.85);
+const SPINNER_COLOR: Color = Color::srgb(0.9, 0.75, 0.3);
+
+/// Resource measuring the stream a client receives. Inserted with the video
+/// decoder, so it only exists for viewers.
+#[derive(Resource)]
+pub struct StreamStats {
+    window_start: Instant,
+    window_bytes: usize,
+    window_frames: u32,
+    last_packet_at: Option<Instant>,
+    last_frame_at: Option<Instant>,
+    /// Size of the latest frame shown.
+    pub resolution: Option<(u32, u32)>,
+    /// Frames shown per second over the last window.
+    pub fps: f32,
+    /// Video received over the last window, in bits per second.
+    pub bitrate_bps: f32,
+}
+
+impl Default for StreamStats {
+    fn default() -> Self {
+        Self {
+            window_start: Instant::now(),
+            window_bytes: 0,
+            window_frames: 0,
+            last_packet_at: None,
+            last_frame_at: None,
+            resolution: None,
+            fps: 0.0,
+            bitrate_bps: 0.0,
+        }
+    }
+}
+
+impl StreamStats {
+    /// Count a video packet of `bytes` received from the host.
+    pub fn record_packet(&mut self, bytes: usize) {
+        self.window_bytes += bytes;
+        self.last_packet_at = Some(Instant::now());
+    }
+
+    fn record_frame(&mut self, width: u32, height: u32) {
+        self.window_frames += 1;
+        self.last_frame_at = Some(Instant::now());
+        self.resolution = Some((width, height));
+    }
+
+    /// Recompute the rates once a window has passed.
+    fn refresh(&mut self) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < STATS_WINDOW {
+            return;
+        }
+        let secs = elapsed.as_secs_f32();
+        self.fps = self.window_frames as f32 / secs;
+        self.bitrate_bps = self.window_bytes as f32 * 8.0 / secs;
+        self.window_start = Instant::now();
+        self.window_bytes = 0;
+        self.window_frames = 0;
+    }
+
+    fn receiving(&self) -> bool {
+        self.last_packet_at
+            .is_some_and(|at| at.elapsed() < STREAM_IDLE_TIMEOUT)
+    }
+
+    /// Packets are arriving but the jitter buffer has nothing to show.
+    fn buffering(&self) -> bool {
+        self.receiving()
+            && self
+                .last_frame_at
+                .is_none_or(|at| at.elapsed() > BUFFERING_THRESHOLD)
+    }
+
+    fn summary(&self) -> String {
+        if !self.receiving() {
+            return "No stream".to_string();
+        }
+        let resolution = match self.resolution {
+            Some((width, height)) => format!("{}x{}", width, height),
+            None => "-".to_string(),
+        };
+        format!(
+            "{}  {:.0} fps  {:.1} Mbps",
+            resolution,
+            self.fps,
+            self.bitrate_bps / 1_000_000.0
+        )
+    }
+}
+
+/// Marker for the stream quality indicator root UI node.
+#[derive(Component)]
+pub struct StreamStatsRoot;
+
+#[derive(Component)]
+pub struct StreamStatsText;
+
+/// Bar that turns while the stream is buffering.
+#[derive(Component)]
+pub struct BufferingSpinner;
+
+/// System to count the frames the client shows.
+pub fn track_shown_frames(
+    mut events: EventReader<ReceivedScreenFrame>,
+    stats: Option<ResMut<StreamStats>>,
+) {
+    let Some(mut stats) = stats else {
+        events.clear();
+        return;
+    };
+    for frame in events.read() {
+        stats.record_frame(frame.width, frame.height);
+    }
+}
+
+/// System to show or hide the indicator with its key.
+pub fn toggle_stream_stats(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut settings: ResMut<VideoSettings>,
+) {
+    if bindings.just_pressed(Action::StreamStats, &keyboard_input) {
+        settings.show_stream_stats = !settings.show_stream_stats;
+    }
+}
+
+/// System to spawn, update and despawn the indicator. It shows for viewers
+/// and the host once turned on.
+pub fn update_stream_stats_hud(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<VideoSettings>,
+    mut stats: Option<ResMut<StreamStats>>,
+    server: Option<Res<GameServer>>,
+    diagnostics: Res<DiagnosticsStore>,
+    root_query: Query<Entity, With<StreamStatsRoot>>,
+    mut text_query: Query<&mut Text, With<StreamStatsText>>,
+    mut spinner_query: Query<(&mut Transform, &mut Visibility), With<BufferingSpinner>>,
+) {
+    let drops = server.map(|server| server.packet_drops);
+    if !settings.show_stream_stats || (stats.is_none() && drops.is_none()) {
+        for entity in root_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+    if root_query.is_empty() {
+        spawn_stream_stats_hud(&mut commands);
+        return;
+    }
+
+    let summary = match (stats.as_deref_mut(), drops) {
+        (Some(stats), _) => {
+            stats.refresh();
+            stats.summary()
+        }
+        (None, Some(drops)) => {
+            let value = |path| {
+                diagnostics
+                    .get(path)
+                    .and_then(|diagnostic| diagnostic.smoothed())
+                    .unwrap_or(0.0)
+            };
+            // Upload against the limit, so the host sees how close it runs
+            let upload = match settings.upload_limit.bps() {
+                Some(limit) => format!("{:.0} of {} kbps", value(&SEND_KBPS), limit / 1000),
+                None => format!("{:.0} kbps", value(&SEND_KBPS)),
+            };
+            format!(
+                "Hosting  {:.0} fps  {}  {} packets dropped  {} frames over the limit",
+                value(&ENCODE_FPS),
+                upload,
+                drops.rate_limited,
+                drops.capped_frames
+            )
+        }
+        (None, None) => return,
+    };
+    for mut text in text_query.iter_mut() {
+        if text.0 != summary {
+            text.0 = summary.clone();
+        }
+    }
+
+    let buffering = stats.is_some_and(|stats| stats.buffering());
+    for (mut transform, mut visibility) in spinner_query.iter_mut() {
+        let value = if buffering {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        visibility.set_if_neq(value);
+        if buffering {
+            transform.rotate_z(-SPINNER_SPEED * time.delta_secs());
+        }
+    }
+}
+
+fn spawn_stream_stats_hud(commands: &mut Commands) {
+    commands
+        .spawn((
+            StreamStatsRoot,
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(20.0),
+                bottom: Val::Px(20.0),
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
+                padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                ..default()
+            },
+            BackgroundColor(HUD_BG_COLOR),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                BufferingSpinner,
+                Node {
+                    width: Val::Px(14.0),
+                    height: Val::Px(3.0),
+                    ..default()
+                },
+                BackgroundColor(SPINNER_COLOR),
+                Visibility::Hidden,
+            ));
+            parent.spawn((
+                StreamStatsText,
+                Text::new(""),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(HUD_TEXT_COLOR),
+            ));
+        });
+}
+
+pub fn cleanup_stream_stats_hud(
+    mut commands: Commands,
+    query: Query<Entity, With<StreamStatsRoot>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
-use std::time::{Duration, Instant};
+use std::time::Instant;
+
+use crate::network::protocol::ScreenId;
 
 /// Resource holding the latest captured frame for streaming.
 #[derive(Resource, Default)]
@@ -8,22 +10,38 @@ pub struct LatestCapturedFrame {
     pub width: u32,
     pub height: u32,
     pub frame_number: u64,
+    /// When the frame was captured, for presentation timestamps.
+    pub captured_at: Option<Instant>,
+    /// Room screen the capture is shown on.
+    pub screen_id: ScreenId,
 }
 
 /// Resource tracking screen streaming state.
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct ScreenStreamState {
     pub frame_id: u32,
-    pub last_stream_time: Instant,
-    pub stream_interval: Duration,
 }
 
-impl Default for ScreenStreamState {
+/// Host clock that audio and video presentation timestamps are measured against.
+#[derive(Resource)]
+pub struct StreamClock {
+    epoch: Instant,
+}
+
+impl Default for StreamClock {
     fn default() -> Self {
-        Self {
-            frame_id: 0,
-            last_stream_time: Instant::now() - Duration::from_secs(1),
-            stream_interval: Duration::from_millis(16), // ~60fps target
-        }
+        Self { epoch: Instant::now() }
+    }
+}
+
+impl StreamClock {
+    /// Milliseconds between the clock epoch and `instant`.
+    pub fn pts_ms(&self, instant: Instant) -> u64 {
+        instant.saturating_duration_since(self.epoch).as_millis() as u64
+    }
+
+    /// Current time on the stream clock.
+    pub fn now_ms(&self) -> u64 {
+        self.pts_ms(Instant::now())
     }
 }
@@ -0,0 +1,200 @@
+//! Preview thumbnails of the displays and windows listed in the share UI.
+//! Each source is captured once on a background thread, so listing many
+//! windows doesn't stall the game.
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use scrap::{Capturer, Display};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::capture::CaptureSourceType;
+use super::display_orientation::{bgra_to_rgba, display_rotation};
+use super::window_capture::start_wgc_capture;
+
+/// Width thumbnails are scaled down to, in pixels.
+const THUMBNAIL_WIDTH: u32 = 160;
+
+/// How long to wait for a source's first frame before giving up on it.
+const THUMBNAIL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A scaled-down frame of one source, top row first.
+struct Thumbnail {
+    source: CaptureSourceType,
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+/// Resource receiving the thumbnails of the sources the share UI lists.
+/// Replacing or removing it stops the capture thread.
+#[derive(Resource)]
+pub struct SourceThumbnails {
+    receiver: Mutex<Receiver<Thumbnail>>,
+    images: HashMap<CaptureSourceType, Handle<Image>>,
+}
+
+impl SourceThumbnails {
+    /// Start capturing a thumbnail of each source in the background.
+    pub fn capture(sources: Vec<CaptureSourceType>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || capture_thumbnails(sources, tx));
+        Self {
+            receiver: Mutex::new(rx),
+            images: HashMap::new(),
+        }
+    }
+}
+
+/// Placeholder in a source button that shows the source's thumbnail once
+/// it's captured.
+#[derive(Component)]
+pub struct SourceThumbnail(pub CaptureSourceType);
+
+fn capture_thumbnails(sources: Vec<CaptureSourceType>, tx: Sender<Thumbnail>) {
+    for source in sources {
+        let thumbnail = match source {
+            CaptureSourceType::Display(index) => capture_display_thumbnail(index),
+            CaptureSourceType::Window(hwnd) => capture_window_thumbnail(hwnd),
+        };
+        let Some((rgba, width, height)) = thumbnail else {
+            continue;
+        };
+        let thumbnail = Thumbnail {
+            source,
+            rgba,
+            width,
+            height,
+        };
+        // The share UI moved on, so nobody wants the rest
+        if tx.send(thumbnail).is_err() {
+            return;
+        }
+    }
+}
+
+fn capture_display_thumbnail(index: usize) -> Option<(Vec<u8>, u32, u32)> {
+    let display = Display::all().ok()?.into_iter().nth(index)?;
+    let width = display.width() as u32;
+    let height = display.height() as u32;
+    let rotation = display_rotation(index);
+    let mut capturer = Capturer::new(display).ok()?;
+
+    let started = Instant::now();
+    while started.elapsed() < THUMBNAIL_TIMEOUT {
+        match capturer.frame() {
+            Ok(frame) => {
+                let rgba = bgra_to_rgba(&frame, width as usize, height as usize, rotation)?;
+                let stride = width as usize * 4;
+                return Some(scale_down(&rgba, width, height, stride, false, true));
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => {
+                warn!("Failed to capture thumbnail of display {}: {}", index, e);
+                return None;
+            }
+        }
+    }
+    None
+}
+
+fn capture_window_thumbnail(hwnd: isize) -> Option<(Vec<u8>, u32, u32)> {
+    let (frame_rx, stop_tx) = start_wgc_capture(hwnd)?;
+    let frame = frame_rx.recv_timeout(THUMBNAIL_TIMEOUT).ok();
+    let _ = stop_tx.send(());
+    let frame = frame?;
+    // Window frames come converted to RGBA, bottom row first
+    Some(scale_down(
+        &frame.rgba,
+        frame.width,
+        frame.height,
+        frame.width as usize * 4,
+        false,
+        true,
+    ))
+}
+
+/// Nearest-neighbour scale a frame down to `THUMBNAIL_WIDTH`, as RGBA with
+/// the top row first.
+fn scale_down(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+    bgra: bool,
+    bottom_up: bool,
+) -> (Vec<u8>, u32, u32) {
+    let thumb_width = THUMBNAIL_WIDTH.min(width).max(1);
+    let thumb_height = (height * thumb_width / width.max(1)).max(1);
+    let mut rgba = Vec::with_capacity((thumb_width * thumb_height * 4) as usize);
+
+    for y in 0..thumb_height {
+        let mut src_y = (y * height / thumb_height) as usize;
+        if bottom_up {
+            src_y = height as usize - 1 - src_y;
+        }
+        for x in 0..thumb_width {
+            let src_i = src_y * stride + (x * width / thumb_width) as usize * 4;
+            let Some(pixel) = pixels.get(src_i..src_i + 4) else {
+                rgba.extend_from_slice(&[0, 0, 0, 255]);
+                continue;
+            };
+            if bgra {
+                rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+            } else {
+                rgba.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+            }
+        }
+    }
+    (rgba, thumb_width, thumb_height)
+}
+
+/// System to turn captured thumbnails into images and show them in their
+/// source buttons.
+pub fn show_source_thumbnails(
+    mut commands: Commands,
+    mut thumbnails: ResMut<SourceThumbnails>,
+    mut images: ResMut<Assets<Image>>,
+    placeholder_query: Query<(Entity, &SourceThumbnail), Without<ImageNode>>,
+) {
+    let received: Vec<Thumbnail> = match thumbnails.receiver.lock() {
+        Ok(receiver) => receiver.try_iter().collect(),
+        Err(_) => return,
+    };
+    for thumbnail in received {
+        let image = Image::new(
+            Extent3d {
+                width: thumbnail.width,
+                height: thumbnail.height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            thumbnail.rgba,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        thumbnails
+            .images
+            .insert(thumbnail.source, images.add(image));
+    }
+
+    for (entity, placeholder) in placeholder_query.iter() {
+        if let Some(image) = thumbnails.images.get(&placeholder.0) {
+            commands
+                .entity(entity)
+                .insert(ImageNode::new(image.clone()));
+        }
+    }
+}
+
+/// Let go of the thumbnails once the share UI closes.
+pub fn release_source_thumbnails(mut commands: Commands) {
+    commands.remove_resource::<SourceThumbnails>();
+}
@@ -1,9 +1,11 @@
-//! H.264 video decoder using OpenH264.
+//! Video decoder using OpenH264 for H.264, with optional FFmpeg backends.
 //!
 //! Uses Cisco's OpenH264 library for software H.264 decoding.
 //! No external dependencies required - the library is downloaded automatically at build time.
+//! HEVC and AV1 streams are decoded through FFmpeg when the host negotiates them.
 
 use bevy::prelude::*;
+use bytes::{Bytes, BytesMut};
 use openh264::decoder::Decoder;
 use openh264::formats::YUVSource;
 use std::collections::VecDeque;
@@ -12,7 +14,9 @@ use std::sync::Mutex;
 use std::thread;
 use std::time::Instant;
 
-use crate::network::protocol::{VideoChunk, VideoCodecInfo};
+use super::ffmpeg::{self, FfmpegDecoder};
+use crate::network::protocol::{VideoChunk, VideoCodecInfo, VideoCodecKind};
+use crate::settings::video::StreamLatency;
 
 /// Decoded frame ready for display
 pub struct DecodedFrame {
@@ -20,54 +24,93 @@ pub struct DecodedFrame {
     pub width: u32,
     pub height: u32,
     pub frame_id: u32,
+    /// Host capture timestamp, used for A/V sync.
+    pub pts_ms: u64,
 }
 
 /// Timeout for incomplete frame assembly (ms)
 const FRAME_ASSEMBLY_TIMEOUT_MS: u64 = 200;
 
+/// Frames between logs of how many arrived whole
+const DELIVERY_LOG_FRAMES: u32 = 300;
+
 /// Resource for video frame assembly and decoding
 #[derive(Resource)]
 pub struct VideoDecoder {
     /// Send assembled NAL units for decoding
-    send_data: Mutex<Sender<(Vec<u8>, u32)>>,
+    send_data: Mutex<Sender<(Bytes, u32, u64)>>,
+    /// Send codec changes to the decoder thread
+    send_codec: Mutex<Sender<VideoCodecInfo>>,
     /// Receive decoded RGBA frames
     recv_decoded: Mutex<Receiver<DecodedFrame>>,
     /// Current frame being assembled
     current_frame_id: u32,
+    /// Presentation timestamp of the current frame
+    current_pts_ms: u64,
     /// Chunks for current frame
-    chunks: Vec<Option<Vec<u8>>>,
+    chunks: Vec<Option<Bytes>>,
+    /// Buffer frames are assembled in, reused once the decoder is done
+    /// with the previous frame
+    frame_buf: BytesMut,
     /// Total chunks expected
     total_chunks: u16,
     /// Chunks received count
     received_count: u16,
     /// When we started assembling current frame
     frame_start_time: Option<Instant>,
+    /// Frames assembled whole since the last delivery log
+    frames_complete: u32,
+    /// Frames abandoned with chunks missing since the last delivery log
+    frames_dropped: u32,
 }
 
 impl VideoDecoder {
     pub fn new() -> Option<Self> {
-        let (data_tx, data_rx) = mpsc::channel::<(Vec<u8>, u32)>();
+        let (data_tx, data_rx) = mpsc::channel::<(Bytes, u32, u64)>();
+        let (codec_tx, codec_rx) = mpsc::channel::<VideoCodecInfo>();
         let (decoded_tx, decoded_rx) = mpsc::channel::<DecodedFrame>();
 
         // Spawn decoder thread
         thread::spawn(move || {
-            run_decoder_thread(data_rx, decoded_tx);
+            run_decoder_thread(data_rx, codec_rx, decoded_tx);
         });
 
         Some(Self {
             send_data: Mutex::new(data_tx),
+            send_codec: Mutex::new(codec_tx),
             recv_decoded: Mutex::new(decoded_rx),
             current_frame_id: 0,
+            current_pts_ms: 0,
             chunks: Vec::new(),
+            frame_buf: BytesMut::new(),
             total_chunks: 0,
             received_count: 0,
             frame_start_time: None,
+            frames_complete: 0,
+            frames_dropped: 0,
         })
     }
 
-    /// Set codec information from server (not needed for OpenH264 but kept for API compatibility)
-    pub fn set_codec_info(&mut self, _info: VideoCodecInfo) {
-        // OpenH264 decoder auto-detects dimensions from the stream
+    /// Codecs this client can decode, advertised in the Join message.
+    pub fn supported_codecs() -> Vec<VideoCodecKind> {
+        let mut codecs = ffmpeg::available_decoders();
+        codecs.push(VideoCodecKind::H264);
+        codecs
+    }
+
+    /// Set codec information from server, switching the decoder backend if needed
+    /// and priming it with the stream's parameter sets.
+    pub fn set_codec_info(&mut self, info: VideoCodecInfo) {
+        info!(
+            "Server selected {} video codec ({}x{})",
+            info.codec.name(),
+            info.width,
+            info.height
+        );
+        self.reset_assembly();
+        if let Ok(sender) = self.send_codec.lock() {
+            let _ = sender.send(info);
+        }
     }
 
     /// Add a received video chunk
@@ -98,9 +141,14 @@ impl VideoDecoder {
                     self.current_frame_id, self.received_count, self.total_chunks, chunk.frame_id
                 );
             }
+            if self.received_count > 0 {
+                self.count_frame(false);
+            }
             self.current_frame_id = chunk.frame_id;
+            self.current_pts_ms = chunk.pts_ms;
             self.total_chunks = chunk.total_chunks;
-            self.chunks = vec![None; chunk.total_chunks as usize];
+            self.chunks.clear();
+            self.chunks.resize(chunk.total_chunks as usize, None);
             self.received_count = 0;
             self.frame_start_time = Some(Instant::now());
         }
@@ -113,29 +161,34 @@ impl VideoDecoder {
         // Store chunk
         let idx = chunk.chunk_idx as usize;
         if idx < self.chunks.len() && self.chunks[idx].is_none() {
-            self.chunks[idx] = Some(chunk.decode_data().unwrap_or_default());
+            self.chunks[idx] = Some(chunk.data);
             self.received_count += 1;
 
             // Check if complete
             if self.received_count == self.total_chunks {
-                // Assemble NAL data
-                let mut data = Vec::new();
-                for c in &self.chunks {
-                    if let Some(d) = c {
-                        data.extend_from_slice(d);
+                // Assemble NAL data, a single chunk as is
+                let data = if self.chunks.len() == 1 {
+                    self.chunks[0].take().unwrap_or_default()
+                } else {
+                    let size = self.chunks.iter().flatten().map(Bytes::len).sum();
+                    self.frame_buf.reserve(size);
+                    for d in self.chunks.iter().flatten() {
+                        self.frame_buf.extend_from_slice(d);
                     }
-                }
+                    self.frame_buf.split().freeze()
+                };
 
                 static ASSEMBLED_COUNT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
                 let count = ASSEMBLED_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                if count % 30 == 0 {
+                if count.is_multiple_of(30) {
                     info!("Assembled complete frame {} ({} bytes)", count, data.len());
                 }
 
                 // Send for decoding
                 if let Ok(sender) = self.send_data.lock() {
-                    let _ = sender.send((data, self.current_frame_id));
+                    let _ = sender.send((data, self.current_frame_id, self.current_pts_ms));
                 }
+                self.count_frame(true);
 
                 // Reset
                 self.chunks.clear();
@@ -146,6 +199,26 @@ impl VideoDecoder {
         }
     }
 
+    /// Count a frame as delivered whole or not, logging the share delivered
+    /// every so often.
+    fn count_frame(&mut self, complete: bool) {
+        if complete {
+            self.frames_complete += 1;
+        } else {
+            self.frames_dropped += 1;
+        }
+        let total = self.frames_complete + self.frames_dropped;
+        if total >= DELIVERY_LOG_FRAMES {
+            info!(
+                "Video delivery: {:.1}% of the last {} frames complete",
+                self.frames_complete as f32 * 100.0 / total as f32,
+                total
+            );
+            self.frames_complete = 0;
+            self.frames_dropped = 0;
+        }
+    }
+
     /// Get decoded frame if available
     pub fn get_decoded(&self) -> Option<DecodedFrame> {
         if let Ok(receiver) = self.recv_decoded.lock() {
@@ -202,14 +275,95 @@ fn yuv420_to_rgba(y_plane: &[u8], u_plane: &[u8], v_plane: &[u8], width: usize,
     rgba
 }
 
-/// Run the decoder thread using OpenH264
-fn run_decoder_thread(data_rx: Receiver<(Vec<u8>, u32)>, decoded_tx: Sender<DecodedFrame>) {
-    let mut decoder = match Decoder::new() {
-        Ok(dec) => dec,
+/// Active decoder implementation for the current codec.
+enum DecoderBackend {
+    OpenH264(Decoder),
+    Ffmpeg {
+        decoder: FfmpegDecoder,
+        /// Frame IDs and timestamps submitted but not yet output (FFmpeg decodes in order)
+        pending: VecDeque<(u32, u64)>,
+    },
+}
+
+impl DecoderBackend {
+    fn create(codec: VideoCodecKind) -> Option<Self> {
+        if codec != VideoCodecKind::H264 {
+            return FfmpegDecoder::spawn(codec).map(|decoder| DecoderBackend::Ffmpeg {
+                decoder,
+                pending: VecDeque::new(),
+            });
+        }
+
+        match Decoder::new() {
+            Ok(dec) => Some(DecoderBackend::OpenH264(dec)),
+            Err(e) => {
+                error!("Failed to create OpenH264 decoder: {:?}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Outcome of feeding one access unit to a decoder backend.
+enum DecodeResult {
+    Frames(Vec<DecodedFrame>),
+    NoFrame,
+    Error,
+}
+
+/// Decode an access unit with OpenH264.
+fn decode_openh264(decoder: &mut Decoder, data: &[u8], frame_id: u32, pts_ms: u64) -> DecodeResult {
+    match decoder.decode(data) {
+        Ok(Some(yuv)) => {
+            let (width, height) = yuv.dimensions();
+
+            // Get YUV planes
+            let y_plane = yuv.y();
+            let u_plane = yuv.u();
+            let v_plane = yuv.v();
+
+            // Get strides (y_stride, u_stride, v_stride)
+            let (y_stride, u_stride, _v_stride) = yuv.strides();
+
+            // Convert to RGBA
+            let rgba = yuv420_to_rgba(y_plane, u_plane, v_plane, width, height, y_stride, u_stride);
+
+            DecodeResult::Frames(vec![DecodedFrame {
+                rgba,
+                width: width as u32,
+                height: height as u32,
+                frame_id,
+                pts_ms,
+            }])
+        }
+        // No frame produced (might need more data or waiting for keyframe)
+        Ok(None) => DecodeResult::NoFrame,
         Err(e) => {
-            error!("Failed to create OpenH264 decoder: {:?}", e);
-            return;
+            // Only log occasionally to avoid spam
+            static LAST_ERROR: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let last = LAST_ERROR.load(std::sync::atomic::Ordering::Relaxed);
+            if now > last + 5 {
+                LAST_ERROR.store(now, std::sync::atomic::Ordering::Relaxed);
+                warn!("Decode error (may need keyframe): {:?}", e);
+            }
+            DecodeResult::Error
         }
+    }
+}
+
+/// Run the decoder thread, switching backends when the negotiated codec changes
+fn run_decoder_thread(
+    data_rx: Receiver<(Bytes, u32, u64)>,
+    codec_rx: Receiver<VideoCodecInfo>,
+    decoded_tx: Sender<DecodedFrame>,
+) {
+    let mut codec = VideoCodecKind::H264;
+    let Some(mut decoder) = DecoderBackend::create(codec) else {
+        return;
     };
 
     info!("Video decoder started using OpenH264");
@@ -217,82 +371,121 @@ fn run_decoder_thread(data_rx: Receiver<(Vec<u8>, u32)>, decoded_tx: Sender<Deco
     let mut consecutive_errors: u32 = 0;
     let mut last_successful_decode = Instant::now();
 
-    while let Ok((mut data, mut frame_id)) = data_rx.recv() {
+    while let Ok((mut data, mut frame_id, mut pts_ms)) = data_rx.recv() {
         // Skip to latest data
-        while let Ok((newer_data, newer_id)) = data_rx.try_recv() {
+        while let Ok((newer_data, newer_id, newer_pts)) = data_rx.try_recv() {
             data = newer_data;
             frame_id = newer_id;
+            pts_ms = newer_pts;
+        }
+
+        let mut needs_reset = consecutive_errors > 30 || last_successful_decode.elapsed().as_secs() > 5;
+        let mut parameter_sets = Vec::new();
+        while let Ok(info) = codec_rx.try_recv() {
+            let requested = info.codec;
+            if requested != codec {
+                info!("Switching video decoder: {} -> {}", codec.name(), requested.name());
+                codec = requested;
+                needs_reset = true;
+            }
+            parameter_sets = info.extradata;
         }
 
-        // Reset decoder if too many consecutive errors or long time since success
-        if consecutive_errors > 30 || last_successful_decode.elapsed().as_secs() > 5 {
-            info!("Resetting decoder after {} errors or {}s without success",
-                consecutive_errors, last_successful_decode.elapsed().as_secs());
-            decoder = match Decoder::new() {
-                Ok(dec) => dec,
-                Err(e) => {
-                    error!("Failed to recreate decoder: {:?}", e);
+        // Reset decoder if codec changed, too many consecutive errors or long time since success
+        if needs_reset {
+            info!("Resetting {} decoder after {} errors or {}s without success",
+                codec.name(), consecutive_errors, last_successful_decode.elapsed().as_secs());
+            decoder = match DecoderBackend::create(codec) {
+                Some(dec) => dec,
+                None => {
+                    error!("Failed to recreate {} decoder", codec.name());
                     continue;
                 }
             };
             consecutive_errors = 0;
+            last_successful_decode = Instant::now();
         }
 
-        // Decode the H.264 data
-        match decoder.decode(&data) {
-            Ok(Some(yuv)) => {
-                consecutive_errors = 0;
-                last_successful_decode = Instant::now();
-
-                let (width, height) = yuv.dimensions();
+        // Prime the decoder with the new stream's parameter sets. AV1's
+        // sequence header can't go through FFmpeg on its own.
+        if !parameter_sets.is_empty() && codec != VideoCodecKind::Av1 {
+            match &mut decoder {
+                DecoderBackend::OpenH264(dec) => {
+                    let _ = dec.decode(&parameter_sets);
+                }
+                DecoderBackend::Ffmpeg { decoder: dec, .. } => {
+                    dec.decode(&parameter_sets);
+                }
+            }
+        }
 
-                // Get YUV planes
-                let y_plane = yuv.y();
-                let u_plane = yuv.u();
-                let v_plane = yuv.v();
+        let result = match decoder {
+            DecoderBackend::OpenH264(ref mut dec) => decode_openh264(dec, &data, frame_id, pts_ms),
+            DecoderBackend::Ffmpeg { ref mut decoder, ref mut pending } => {
+                if decoder.decode(&data) {
+                    pending.push_back((frame_id, pts_ms));
+                    let mut frames = Vec::new();
+                    while let Some((rgba, width, height)) = decoder.try_recv_frame() {
+                        let (frame_id, pts_ms) = pending.pop_front().unwrap_or((frame_id, pts_ms));
+                        frames.push(DecodedFrame {
+                            rgba,
+                            width,
+                            height,
+                            frame_id,
+                            pts_ms,
+                        });
+                    }
+                    if frames.is_empty() {
+                        DecodeResult::NoFrame
+                    } else {
+                        DecodeResult::Frames(frames)
+                    }
+                } else {
+                    DecodeResult::Error
+                }
+            }
+        };
 
-                // Get strides (y_stride, u_stride, v_stride)
-                let (y_stride, u_stride, _v_stride) = yuv.strides();
+        match result {
+            DecodeResult::Frames(frames) => {
+                consecutive_errors = 0;
+                last_successful_decode = Instant::now();
 
-                // Convert to RGBA
-                let rgba = yuv420_to_rgba(y_plane, u_plane, v_plane, width, height, y_stride, u_stride);
+                for frame in frames {
+                    static DECODED_COUNT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+                    let count = DECODED_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    if count.is_multiple_of(30) {
+                        info!("Decoded frame {} ({}x{})", count, frame.width, frame.height);
+                    }
 
-                static DECODED_COUNT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
-                let count = DECODED_COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                if count % 30 == 0 {
-                    info!("Decoded frame {} ({}x{})", count, width, height);
+                    let _ = decoded_tx.send(frame);
                 }
-
-                let _ = decoded_tx.send(DecodedFrame {
-                    rgba,
-                    width: width as u32,
-                    height: height as u32,
-                    frame_id,
-                });
             }
-            Ok(None) => {
-                // No frame produced (might need more data or waiting for keyframe)
+            DecodeResult::NoFrame | DecodeResult::Error => {
                 consecutive_errors += 1;
             }
-            Err(e) => {
-                consecutive_errors += 1;
-                // Only log occasionally to avoid spam
-                static LAST_ERROR: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
-                let now = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs();
-                let last = LAST_ERROR.load(std::sync::atomic::Ordering::Relaxed);
-                if now > last + 5 {
-                    LAST_ERROR.store(now, std::sync::atomic::Ordering::Relaxed);
-                    warn!("Decode error (may need keyframe): {:?}", e);
-                }
-            }
         }
     }
 }
 
-/// Jitter buffer for video frames
+/// Weight of each new inter-arrival sample in the running averages.
+const JITTER_SMOOTHING: f32 = 1.0 / 16.0;
+
+/// Longest the jitter buffer holds a frame back, however bad the jitter.
+const MAX_JITTER_DELAY_MS: f32 = 200.0;
+
+/// Depth range, jitter margin and shortest hold of a latency mode. The
+/// buffer covers `margin` times the observed jitter.
+fn jitter_tuning(latency: StreamLatency) -> (usize, usize, f32, f32) {
+    match latency {
+        // ~0.5 frames at 60fps
+        StreamLatency::Low => (1, 3, 1.0, 8.0),
+        StreamLatency::Smooth => (2, 8, 3.0, 33.0),
+    }
+}
+
+/// Jitter buffer for video frames. Its depth follows the jitter in frame
+/// arrivals, within the bounds of the chosen latency mode.
 #[derive(Resource)]
 pub struct VideoJitterBuffer {
     frames: VecDeque<DecodedFrame>,
@@ -300,21 +493,78 @@ pub struct VideoJitterBuffer {
     min_delay: std::time::Duration,
     last_released_id: u32,
     frame_times: VecDeque<Instant>,
+    latency: StreamLatency,
+    last_arrival: Option<Instant>,
+    /// Running average of the time between frame arrivals.
+    mean_interval_ms: f32,
+    /// Running average of how far arrivals stray from that.
+    jitter_ms: f32,
 }
 
 impl Default for VideoJitterBuffer {
     fn default() -> Self {
+        let latency = StreamLatency::default();
+        let (min_depth, _, _, min_delay_ms) = jitter_tuning(latency);
         Self {
             frames: VecDeque::with_capacity(8),
-            target_size: 1,
-            min_delay: std::time::Duration::from_millis(8), // ~0.5 frames at 60fps - low latency
+            target_size: min_depth,
+            min_delay: std::time::Duration::from_secs_f32(min_delay_ms / 1000.0),
             last_released_id: 0,
             frame_times: VecDeque::with_capacity(8),
+            latency,
+            last_arrival: None,
+            mean_interval_ms: 0.0,
+            jitter_ms: 0.0,
         }
     }
 }
 
 impl VideoJitterBuffer {
+    /// Switch latency mode, keeping what was learned about the jitter.
+    pub fn set_latency(&mut self, latency: StreamLatency) {
+        if latency != self.latency {
+            info!("Video jitter buffer set to {}", latency.label());
+            self.latency = latency;
+            self.retune();
+        }
+    }
+
+    /// Fold a frame arrival into the jitter estimate and retune the depth.
+    fn record_arrival(&mut self, now: Instant) {
+        if let Some(last) = self.last_arrival.replace(now) {
+            let interval = now.duration_since(last).as_secs_f32() * 1000.0;
+            if self.mean_interval_ms == 0.0 {
+                self.mean_interval_ms = interval;
+            }
+            self.mean_interval_ms += (interval - self.mean_interval_ms) * JITTER_SMOOTHING;
+            let deviation = (interval - self.mean_interval_ms).abs();
+            self.jitter_ms += (deviation - self.jitter_ms) * JITTER_SMOOTHING;
+            self.retune();
+        }
+    }
+
+    fn retune(&mut self) {
+        let (min_depth, max_depth, margin, min_delay_ms) = jitter_tuning(self.latency);
+        let cover_ms = self.jitter_ms * margin;
+        // Extra frames needed to bridge the gaps jitter leaves
+        let extra = (cover_ms / self.mean_interval_ms.max(1.0)).ceil() as usize;
+        let target_size = (min_depth + extra).min(max_depth);
+        if target_size != self.target_size {
+            info!(
+                "Video jitter buffer depth {} -> {} ({:.1} ms jitter)",
+                self.target_size, target_size, self.jitter_ms
+            );
+            self.target_size = target_size;
+        }
+        let delay_ms = cover_ms.clamp(min_delay_ms, MAX_JITTER_DELAY_MS);
+        self.min_delay = std::time::Duration::from_secs_f32(delay_ms / 1000.0);
+    }
+
+    /// Frames waiting to be shown.
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
     pub fn push(&mut self, frame: DecodedFrame) {
         // Detect encoder reset: new frame_id is much lower than last released
         // This happens when switching capture sources (encoder restarts at frame 0)
@@ -339,6 +589,7 @@ impl VideoJitterBuffer {
         // Insert sorted by frame_id
         let pos = self.frames.iter().position(|f| f.frame_id > frame.frame_id);
         let now = Instant::now();
+        self.record_arrival(now);
         match pos {
             Some(i) => {
                 self.frames.insert(i, frame);
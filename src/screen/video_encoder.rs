@@ -1,69 +1,175 @@
-//! H.264 video encoder using OpenH264.
+//! Video encoder using OpenH264 for H.264, with optional FFmpeg backends.
 //!
 //! Uses Cisco's OpenH264 library for software H.264 encoding.
 //! No external dependencies required - the library is downloaded automatically at build time.
+//! HEVC and AV1 are encoded through FFmpeg when the negotiated codec requires them.
 
 use bevy::prelude::*;
+use bytes::Bytes;
 use openh264::encoder::{Encoder, EncoderConfig};
 use openh264::OpenH264API;
+use rayon::prelude::*;
 use std::net::SocketAddr;
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::Mutex;
 use std::thread;
+use std::time::Duration;
 
-use crate::network::protocol::{ServerMessage, VideoChunk};
+use super::ffmpeg::{self, FfmpegEncoder};
+use crate::network::protocol::{VideoChunk, VideoCodecInfo, VideoCodecKind, VIDEO_CHUNK_SIZE};
 
 /// Frame to be encoded
 struct FrameToEncode {
     rgba: Vec<u8>,
     width: u32,
     height: u32,
+    pts_ms: u64,
 }
 
 /// Encoded video data ready to send
 pub struct EncodedVideoData {
     pub chunks: Vec<VideoChunk>,
+    /// Codec, size and parameter sets of a stream from a new encoder,
+    /// announced with its first frame that carries them.
+    pub codec_info: Option<VideoCodecInfo>,
 }
 
-/// Maximum chunk size for network transmission
-const MAX_CHUNK_SIZE: usize = 4000;
+/// Encoded access unit handed to the session recorder.
+pub struct RecordedVideoUnit {
+    pub codec: VideoCodecKind,
+    pub data: Bytes,
+    pub keyframe: bool,
+}
+
+/// Target encoder bitrate (8 Mbps)
+const TARGET_BITRATE_BPS: u32 = 8_000_000;
+
+/// Bitrate, size and frame rate the stream is encoded at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StreamQuality {
+    pub bitrate_bps: u32,
+    /// Frames taller than this are scaled down to it.
+    pub max_height: u32,
+    pub fps: u32,
+}
+
+impl Default for StreamQuality {
+    /// Full quality, for when the upload isn't limited.
+    fn default() -> Self {
+        Self {
+            bitrate_bps: TARGET_BITRATE_BPS,
+            max_height: u32::MAX,
+            fps: 60,
+        }
+    }
+}
 
-/// Resource for background H.264 encoding with dynamic resolution support
+/// Resource for background video encoding with dynamic resolution support
 #[derive(Resource)]
 pub struct VideoEncoder {
     send_frame: Mutex<Sender<FrameToEncode>>,
+    send_codec: Mutex<Sender<VideoCodecKind>>,
+    send_recorder: Mutex<Sender<Option<Sender<RecordedVideoUnit>>>>,
+    send_keyframe: Mutex<Sender<()>>,
+    send_quality: Mutex<Sender<StreamQuality>>,
     recv_encoded: Mutex<Receiver<EncodedVideoData>>,
 }
 
 impl VideoEncoder {
-    /// Create a new video encoder with dynamic resolution support.
-    pub fn new(_width: u32, _height: u32, _fps: u32) -> Option<Self> {
+    /// Create a new video encoder. The codec's encoder is sized by the frames
+    /// submitted and restarted whenever their size changes, and shut down
+    /// once no frame has come for `idle_timeout`, to start with the next.
+    pub fn new(idle_timeout: Duration) -> Option<Self> {
         let (frame_tx, frame_rx) = mpsc::channel::<FrameToEncode>();
+        let (codec_tx, codec_rx) = mpsc::channel::<VideoCodecKind>();
+        let (recorder_tx, recorder_rx) = mpsc::channel::<Option<Sender<RecordedVideoUnit>>>();
+        let (keyframe_tx, keyframe_rx) = mpsc::channel::<()>();
+        let (quality_tx, quality_rx) = mpsc::channel::<StreamQuality>();
         let (encoded_tx, encoded_rx) = mpsc::channel::<EncodedVideoData>();
 
         // Spawn encoding thread - will adapt to incoming frame dimensions
         thread::spawn(move || {
-            run_encoder_thread(frame_rx, encoded_tx);
+            run_encoder_thread(
+                frame_rx,
+                codec_rx,
+                recorder_rx,
+                keyframe_rx,
+                quality_rx,
+                encoded_tx,
+                idle_timeout,
+            );
         });
 
         Some(Self {
             send_frame: Mutex::new(frame_tx),
+            send_codec: Mutex::new(codec_tx),
+            send_recorder: Mutex::new(recorder_tx),
+            send_keyframe: Mutex::new(keyframe_tx),
+            send_quality: Mutex::new(quality_tx),
             recv_encoded: Mutex::new(encoded_rx),
         })
     }
 
+    /// Codecs this host can encode, best first. HEVC and AV1 join H.264
+    /// once the FFmpeg probe finds an encoder for them.
+    pub fn supported_codecs(&self) -> Vec<VideoCodecKind> {
+        let mut codecs = ffmpeg::available_encoders();
+        codecs.push(VideoCodecKind::H264);
+        codecs
+    }
+
+    /// Switch the encoder to a different codec (restarts the encoder on the next frame).
+    pub fn set_codec(&self, codec: VideoCodecKind) {
+        if let Ok(sender) = self.send_codec.lock() {
+            let _ = sender.send(codec);
+        }
+    }
+
+    /// Also hand every encoded access unit to a recorder, or stop with `None`.
+    /// Starting a recording forces a keyframe so the file starts promptly.
+    pub fn set_recorder(&self, recorder: Option<Sender<RecordedVideoUnit>>) {
+        if let Ok(sender) = self.send_recorder.lock() {
+            let _ = sender.send(recorder);
+        }
+    }
+
+    /// Make the next frame a keyframe, e.g. for a client that just joined.
+    pub fn request_keyframe(&self) {
+        if let Ok(sender) = self.send_keyframe.lock() {
+            let _ = sender.send(());
+        }
+    }
+
+    /// Encode at a different quality. A new size, frame rate or lower bitrate
+    /// restarts the encoder from the next frame on. A higher bitrate waits
+    /// for the encoder's next restart, so a viewer leaving doesn't cost one.
+    pub fn set_quality(&self, quality: StreamQuality) {
+        if let Ok(sender) = self.send_quality.lock() {
+            let _ = sender.send(quality);
+        }
+    }
+
     /// Submit a frame for encoding (non-blocking)
-    pub fn submit_frame(&self, rgba: Vec<u8>, width: u32, height: u32) {
+    pub fn submit_frame(&self, rgba: Vec<u8>, width: u32, height: u32, pts_ms: u64) {
         if let Ok(sender) = self.send_frame.lock() {
-            let _ = sender.send(FrameToEncode { rgba, width, height });
+            let _ = sender.send(FrameToEncode {
+                rgba,
+                width,
+                height,
+                pts_ms,
+            });
         }
     }
 
     /// Get encoded video data if available (non-blocking)
     pub fn get_encoded(&self) -> Option<EncodedVideoData> {
         if let Ok(receiver) = self.recv_encoded.lock() {
-            let mut latest = None;
-            while let Ok(data) = receiver.try_recv() {
+            let mut latest: Option<EncodedVideoData> = None;
+            while let Ok(mut data) = receiver.try_recv() {
+                // A skipped frame's announcement still goes out
+                if data.codec_info.is_none() {
+                    data.codec_info = latest.and_then(|skipped| skipped.codec_info);
+                }
                 latest = Some(data);
             }
             latest
@@ -73,6 +179,47 @@ impl VideoEncoder {
     }
 }
 
+/// Drop a frame's last column or row if it has an odd number of them, as
+/// YUV 4:2:0 halves both and encoders reject odd sizes. Windows are often
+/// captured at odd sizes.
+fn crop_to_even(frame: &mut FrameToEncode) {
+    let width = frame.width & !1;
+    let height = frame.height & !1;
+    if width != frame.width {
+        let (old_row, new_row) = (frame.width as usize * 4, width as usize * 4);
+        for y in 1..height as usize {
+            frame
+                .rgba
+                .copy_within(y * old_row..y * old_row + new_row, y * new_row);
+        }
+    }
+    frame.rgba.truncate(width as usize * height as usize * 4);
+    frame.width = width;
+    frame.height = height;
+}
+
+/// Scale a frame down to `height` rows, keeping its aspect ratio, taking the
+/// nearest source pixel for each.
+fn scale_to_height(frame: &mut FrameToEncode, height: u32) {
+    let width = (frame.width as u64 * height as u64 / frame.height as u64).max(1) as u32;
+    let (src_width, src_height) = (frame.width as usize, frame.height as usize);
+    let (dst_width, dst_height) = (width as usize, height as usize);
+    let mut rgba = vec![0u8; dst_width * dst_height * 4];
+    rgba.par_chunks_exact_mut(dst_width * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let src_y = y * src_height / dst_height;
+            let src_row = &frame.rgba[src_y * src_width * 4..(src_y + 1) * src_width * 4];
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let src_x = x * src_width / dst_width * 4;
+                pixel.copy_from_slice(&src_row[src_x..src_x + 4]);
+            }
+        });
+    frame.rgba = rgba;
+    frame.width = width;
+    frame.height = height;
+}
+
 /// Convert RGBA to YUV420 frame for OpenH264 (optimized)
 fn rgba_to_yuv_frame(rgba: &[u8], width: u32, height: u32) -> YuvFrame {
     let w = width as usize;
@@ -157,118 +304,288 @@ impl openh264::formats::YUVSource for YuvFrame {
     }
 }
 
-/// Run the encoder thread using OpenH264 with dynamic resolution support
+/// Active encoder implementation for the current codec.
+enum EncoderBackend {
+    OpenH264(Box<Encoder>),
+    Ffmpeg(FfmpegEncoder),
+}
+
+impl EncoderBackend {
+    fn create(
+        codec: VideoCodecKind,
+        width: u32,
+        height: u32,
+        quality: StreamQuality,
+    ) -> Option<Self> {
+        if codec != VideoCodecKind::H264 {
+            return FfmpegEncoder::spawn(codec, width, height, quality.bitrate_bps, quality.fps)
+                .map(EncoderBackend::Ffmpeg);
+        }
+
+        let config = EncoderConfig::new()
+            .set_bitrate_bps(quality.bitrate_bps)
+            .max_frame_rate(quality.fps as f32)
+            .enable_skip_frame(true);
+        let api = OpenH264API::from_source();
+        match Encoder::with_api_config(api, config) {
+            Ok(enc) => Some(EncoderBackend::OpenH264(Box::new(enc))),
+            Err(e) => {
+                error!("Failed to create OpenH264 encoder: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Request an IDR frame. FFmpeg backends rely on their fixed GOP instead,
+    /// and are restarted when a keyframe is needed sooner.
+    fn force_keyframe(&mut self) {
+        if let EncoderBackend::OpenH264(enc) = self {
+            enc.force_intra_frame();
+        }
+    }
+
+    /// Encode a frame, returning any completed access units.
+    /// Returns `None` if the backend has failed and must be recreated.
+    fn encode(&mut self, frame: &FrameToEncode, frame_count: u32) -> Option<Vec<Vec<u8>>> {
+        match self {
+            EncoderBackend::OpenH264(enc) => {
+                // Convert RGBA to YUV420 planes
+                let yuv_frame = rgba_to_yuv_frame(&frame.rgba, frame.width, frame.height);
+
+                if frame_count == 0 {
+                    info!("YUV frame: y={} u={} v={} dims={}x{}",
+                        yuv_frame.y.len(), yuv_frame.u.len(), yuv_frame.v.len(),
+                        yuv_frame.width, yuv_frame.height);
+                }
+
+                match enc.encode(&yuv_frame) {
+                    Ok(bitstream) => Some(vec![bitstream.to_vec()]),
+                    Err(e) => {
+                        error!("Encoding error: {:?}", e);
+                        Some(Vec::new())
+                    }
+                }
+            }
+            EncoderBackend::Ffmpeg(enc) => enc.encode(&frame.rgba),
+        }
+    }
+}
+
+/// Run the encoder thread with dynamic resolution and codec support
 fn run_encoder_thread(
     frame_rx: Receiver<FrameToEncode>,
+    codec_rx: Receiver<VideoCodecKind>,
+    recorder_rx: Receiver<Option<Sender<RecordedVideoUnit>>>,
+    keyframe_rx: Receiver<()>,
+    quality_rx: Receiver<StreamQuality>,
     encoded_tx: Sender<EncodedVideoData>,
+    idle_timeout: Duration,
 ) {
-    let mut encoder: Option<Encoder> = None;
+    let mut encoder: Option<EncoderBackend> = None;
+    let mut recorder: Option<Sender<RecordedVideoUnit>> = None;
+    // Whether a new recording or client asked for a keyframe
+    let mut keyframe_requested = false;
+    let mut codec = VideoCodecKind::H264;
+    let mut quality = StreamQuality::default();
+    // Timestamp of the last frame encoded, to keep to the frame rate
+    let mut last_pts: Option<u64> = None;
     let mut current_width: u32 = 0;
     let mut current_height: u32 = 0;
     let mut frame_count: u32 = 0;
+    // Whether the current encoder's stream still has to be announced
+    let mut announce = false;
 
     info!("Video encoder thread started (dynamic resolution)");
 
-    while let Ok(mut frame) = frame_rx.recv() {
+    loop {
+        let mut frame = match frame_rx.recv_timeout(idle_timeout) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => {
+                // Frees an FFmpeg process or OpenH264's buffers while nobody watches
+                if encoder.take().is_some() {
+                    info!("No frames to encode, stopping the {} encoder", codec.name());
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
         // Skip to latest frame
         while let Ok(newer) = frame_rx.try_recv() {
             frame = newer;
         }
 
+        // Apply codec changes from negotiation
+        while let Ok(requested) = codec_rx.try_recv() {
+            if requested != codec {
+                info!("Switching video codec: {} -> {}", codec.name(), requested.name());
+                codec = requested;
+                encoder = None;
+            }
+        }
+
+        while let Ok(requested) = recorder_rx.try_recv() {
+            keyframe_requested |= requested.is_some();
+            recorder = requested;
+        }
+        while keyframe_rx.try_recv().is_ok() {
+            keyframe_requested = true;
+        }
+        while let Ok(requested) = quality_rx.try_recv() {
+            if requested == quality {
+                continue;
+            }
+            // Going over the upload limit can't wait, spare upload can
+            let restart = requested.bitrate_bps < quality.bitrate_bps
+                || requested.max_height != quality.max_height
+                || requested.fps != quality.fps;
+            info!("Stream quality set to {:?}", requested);
+            quality = requested;
+            if restart {
+                encoder = None;
+            }
+        }
+
+        // Frames coming faster than the frame rate are dropped. The margin
+        // keeps every other frame of a 60fps capture at 30fps despite jitter
+        let min_interval = 750 / quality.fps.max(1) as u64;
+        if last_pts.is_some_and(|last| (last..last + min_interval).contains(&frame.pts_ms)) {
+            continue;
+        }
+        last_pts = Some(frame.pts_ms);
+
         // Validate frame size
         let expected_size = (frame.width * frame.height * 4) as usize;
         if frame.rgba.len() != expected_size {
             continue;
         }
+        if frame.height > quality.max_height {
+            scale_to_height(&mut frame, quality.max_height);
+        }
+        crop_to_even(&mut frame);
+        if frame.width == 0 || frame.height == 0 {
+            continue;
+        }
 
-        // Check if we need to create encoder (first frame or resolution change)
+        // An FFmpeg process can't be asked for a keyframe, but a new one
+        // starts with one and announces its parameter sets again
+        if keyframe_requested && matches!(encoder, Some(EncoderBackend::Ffmpeg(_))) {
+            info!("Restarting the {} encoder for a keyframe", codec.name());
+            encoder = None;
+        }
+
+        // Check if we need to create encoder (first frame, resolution or codec change)
         if encoder.is_none() || frame.width != current_width || frame.height != current_height {
             info!(
-                "Creating encoder for {}x{} (was {}x{})",
-                frame.width, frame.height, current_width, current_height
+                "Creating {} encoder for {}x{} (was {}x{})",
+                codec.name(), frame.width, frame.height, current_width, current_height
             );
-            // Configure encoder for 60fps with good quality (8 Mbps)
-            let config = EncoderConfig::new()
-                .set_bitrate_bps(8_000_000)
-                .max_frame_rate(60.0)
-                .enable_skip_frame(true);
-            let api = OpenH264API::from_source();
-            encoder = match Encoder::with_api_config(api, config) {
-                Ok(mut enc) => {
+            // Drop the old backend first so an FFmpeg process is not left running
+            encoder = None;
+            let backend = EncoderBackend::create(codec, frame.width, frame.height, quality)
+                .or_else(|| {
+                    warn!("{} encoder unavailable, falling back to H.264", codec.name());
+                    codec = VideoCodecKind::H264;
+                    EncoderBackend::create(codec, frame.width, frame.height, quality)
+                });
+            encoder = match backend {
+                Some(mut enc) => {
                     current_width = frame.width;
                     current_height = frame.height;
                     // Force keyframe on first frame with new encoder so clients can decode immediately
-                    enc.force_intra_frame();
+                    enc.force_keyframe();
                     // Reset frame count to ensure proper keyframe scheduling
                     frame_count = 0;
+                    announce = true;
                     Some(enc)
                 }
-                Err(e) => {
-                    error!("Failed to create OpenH264 encoder: {:?}", e);
-                    continue;
-                }
+                None => continue,
             };
         }
 
         // Force keyframe every 120 frames for late-joining clients (less frequent = faster)
         if let Some(ref mut enc) = encoder {
-            if frame_count > 0 && frame_count % 120 == 0 {
+            if frame_count > 0 && frame_count.is_multiple_of(120) {
                 info!("Forcing keyframe at frame {}", frame_count);
-                enc.force_intra_frame();
+                enc.force_keyframe();
             }
         }
 
         let Some(ref mut enc) = encoder else {
             continue;
         };
-
-        // Convert RGBA to YUV420 planes
-        let yuv_frame = rgba_to_yuv_frame(&frame.rgba, frame.width, frame.height);
-
-        if frame_count == 0 {
-            info!("YUV frame: y={} u={} v={} dims={}x{}",
-                yuv_frame.y.len(), yuv_frame.u.len(), yuv_frame.v.len(),
-                yuv_frame.width, yuv_frame.height);
+        if keyframe_requested {
+            enc.force_keyframe();
+            keyframe_requested = false;
         }
 
         // Encode the frame
-        match enc.encode(&yuv_frame) {
-            Ok(bitstream) => {
-                // Get encoded data as bytes
-                let encoded_data = bitstream.to_vec();
+        let Some(access_units) = enc.encode(&frame, frame_count) else {
+            error!("{} encoder stopped, recreating", codec.name());
+            encoder = None;
+            continue;
+        };
 
-                if frame_count % 30 == 0 {
-                    info!("Encoded frame {}: {} bytes", frame_count, encoded_data.len());
-                }
+        for encoded_data in access_units {
+            if frame_count.is_multiple_of(30) {
+                info!("Encoded frame {}: {} bytes", frame_count, encoded_data.len());
+            }
+
+            if encoded_data.is_empty() {
+                continue;
+            }
 
-                if !encoded_data.is_empty() {
-                    // Check if this is a keyframe (IDR NAL unit type = 5)
-                    let is_keyframe = encoded_data.len() > 4 && (encoded_data[4] & 0x1F) == 5;
-
-                    // Fragment into chunks for network transmission
-                    let total_chunks =
-                        ((encoded_data.len() + MAX_CHUNK_SIZE - 1) / MAX_CHUNK_SIZE) as u16;
-                    let chunks: Vec<VideoChunk> = encoded_data
-                        .chunks(MAX_CHUNK_SIZE)
-                        .enumerate()
-                        .map(|(idx, chunk)| {
-                            VideoChunk::new(
-                                frame_count,
-                                idx as u16,
-                                total_chunks,
-                                is_keyframe && idx == 0,
-                                chunk.to_vec(),
-                            )
-                        })
-                        .collect();
-
-                    let _ = encoded_tx.send(EncodedVideoData { chunks });
-                    frame_count = frame_count.wrapping_add(1);
+            let is_keyframe = ffmpeg::is_keyframe(codec, &encoded_data);
+            // Chunks and the recorder share the access unit instead of copying it
+            let encoded_data = Bytes::from(encoded_data);
+
+            if let Some(tx) = &recorder {
+                let unit = RecordedVideoUnit {
+                    codec,
+                    data: encoded_data.clone(),
+                    keyframe: is_keyframe,
+                };
+                // The recording stopped
+                if tx.send(unit).is_err() {
+                    recorder = None;
                 }
             }
-            Err(e) => {
-                error!("Encoding error: {:?}", e);
+
+            // Fragment into chunks for network transmission
+            let total_chunks = encoded_data.len().div_ceil(VIDEO_CHUNK_SIZE) as u16;
+            let chunks: Vec<VideoChunk> = (0..encoded_data.len())
+                .step_by(VIDEO_CHUNK_SIZE)
+                .enumerate()
+                .map(|(idx, start)| {
+                    let end = (start + VIDEO_CHUNK_SIZE).min(encoded_data.len());
+                    VideoChunk::new(
+                        frame_count,
+                        idx as u16,
+                        total_chunks,
+                        is_keyframe && idx == 0,
+                        frame.pts_ms,
+                        encoded_data.slice(start..end),
+                    )
+                })
+                .collect();
+
+            let mut codec_info = None;
+            if announce {
+                let extradata = ffmpeg::parameter_sets(codec, &encoded_data);
+                if !extradata.is_empty() {
+                    codec_info = Some(VideoCodecInfo {
+                        codec,
+                        width: current_width,
+                        height: current_height,
+                        fps: quality.fps,
+                        extradata,
+                    });
+                    announce = false;
+                }
             }
+
+            let _ = encoded_tx.send(EncodedVideoData { chunks, codec_info });
+            frame_count = frame_count.wrapping_add(1);
         }
     }
 }
@@ -284,6 +601,8 @@ impl VideoSender {
         let (chunks_tx, chunks_rx) = mpsc::channel::<(Vec<VideoChunk>, Vec<SocketAddr>)>();
 
         thread::spawn(move || {
+            // One packet buffer, reused for every chunk
+            let mut packet = Vec::new();
             while let Ok((chunks, clients)) = chunks_rx.recv() {
                 // Skip to latest
                 let (mut chunks, mut clients) = (chunks, clients);
@@ -292,14 +611,11 @@ impl VideoSender {
                     clients = newer_clients;
                 }
 
-                for chunk in chunks {
-                    let msg = ServerMessage::VideoFrame(chunk);
-                    if let Ok(data) = serde_json::to_vec(&msg) {
-                        for client in &clients {
-                            let _ = socket.send_to(&data, client);
-                        }
+                for chunk in &chunks {
+                    chunk.encode_into(&mut packet);
+                    for client in &clients {
+                        let _ = socket.send_to(&packet, client);
                     }
-
                 }
             }
         });
@@ -1,6 +1,11 @@
 //! Window enumeration and capture for Windows platform using Windows Graphics Capture API
 
+#[cfg(windows)]
 use bevy::prelude::*;
+use bevy::window::RawHandleWrapper;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+
+use super::capture::{CaptureBackend, CapturePoll, CapturedFrame};
 
 /// Information about a capturable window
 #[derive(Clone, Debug, Default)]
@@ -18,6 +23,10 @@ pub fn enumerate_windows() -> Vec<WindowInfo> {
         Ok(windows) => {
             let mut result = Vec::new();
             for window in windows {
+                // Sharing the game's own window would only mirror itself
+                if is_own_window(window.as_raw_hwnd() as isize) {
+                    continue;
+                }
                 if let Ok(title) = window.title() {
                     // Filter out system windows and empty titles
                     if !title.is_empty()
@@ -47,6 +56,131 @@ pub fn enumerate_windows() -> Vec<WindowInfo> {
     Vec::new()
 }
 
+/// Whether a window belongs to this process.
+#[cfg(windows)]
+fn is_own_window(hwnd: isize) -> bool {
+    let mut process_id = 0;
+    unsafe {
+        winapi::um::winuser::GetWindowThreadProcessId(
+            hwnd as winapi::shared::windef::HWND,
+            &mut process_id,
+        );
+    }
+    process_id == std::process::id()
+}
+
+/// Hide one of the game's windows from screen capture, its own included, or
+/// show it again. Needs Windows 10 2004 or later, older versions are left
+/// capturing it.
+#[cfg(windows)]
+pub fn set_excluded_from_capture(window: &RawHandleWrapper, excluded: bool) {
+    use raw_window_handle::RawWindowHandle;
+
+    // Not in winapi, see SetWindowDisplayAffinity
+    const WDA_NONE: u32 = 0x0;
+    const WDA_EXCLUDEFROMCAPTURE: u32 = 0x11;
+
+    let RawWindowHandle::Win32(handle) = window.get_window_handle() else {
+        return;
+    };
+    let affinity = if excluded {
+        WDA_EXCLUDEFROMCAPTURE
+    } else {
+        WDA_NONE
+    };
+    let hwnd = handle.hwnd.get() as winapi::shared::windef::HWND;
+    if unsafe { winapi::um::winuser::SetWindowDisplayAffinity(hwnd, affinity) } == 0 {
+        warn!("Couldn't exclude the game window from capture");
+    }
+}
+
+#[cfg(not(windows))]
+pub fn set_excluded_from_capture(_window: &RawHandleWrapper, _excluded: bool) {}
+
+/// Whether a window is minimized, which stops its frames coming.
+#[cfg(windows)]
+pub fn is_window_minimized(hwnd: isize) -> bool {
+    unsafe { winapi::um::winuser::IsIconic(hwnd as winapi::shared::windef::HWND) != 0 }
+}
+
+#[cfg(not(windows))]
+pub fn is_window_minimized(_hwnd: isize) -> bool {
+    false
+}
+
+/// Capture of one window. WGC delivers frames on its own thread, so this
+/// only picks up the latest of them.
+pub struct WindowCapture {
+    hwnd: isize,
+    frame_receiver: Receiver<WgcCapturedFrame>,
+    stop_sender: Sender<()>,
+    /// Size of the latest frame, unknown until the first one comes.
+    size: Option<(u32, u32)>,
+}
+
+impl WindowCapture {
+    /// Start capturing a window, or say why it can't be.
+    pub fn start(hwnd: isize) -> Result<Self, String> {
+        bevy::log::info!("Starting window capture for hwnd {}", hwnd);
+        let Some((frame_receiver, stop_sender)) = start_wgc_capture(hwnd) else {
+            bevy::log::error!("Failed to start WGC capture for hwnd {}", hwnd);
+            return Err("Couldn't capture that window".to_string());
+        };
+        bevy::log::info!("WGC window capture started for hwnd {}", hwnd);
+        Ok(Self {
+            hwnd,
+            frame_receiver,
+            stop_sender,
+            size: None,
+        })
+    }
+}
+
+impl CaptureBackend for WindowCapture {
+    fn dimensions(&self) -> Option<(u32, u32)> {
+        self.size
+    }
+
+    fn poll_frame(&mut self) -> CapturePoll {
+        // Non-blocking receive - get the latest frame if available
+        let mut latest = None;
+        let mut ended = false;
+        loop {
+            match self.frame_receiver.try_recv() {
+                Ok(frame) => latest = Some(frame),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    ended = true;
+                    break;
+                }
+            }
+        }
+
+        match latest {
+            Some(frame) => {
+                self.size = Some((frame.width, frame.height));
+                CapturePoll::Frame(CapturedFrame {
+                    rgba: frame.rgba,
+                    width: frame.width,
+                    height: frame.height,
+                })
+            }
+            // The capture thread ends when the window closes
+            None if ended => {
+                bevy::log::info!("Window capture ended (hwnd {})", self.hwnd);
+                CapturePoll::Ended(None)
+            }
+            // No frames come while the window is minimized
+            None if is_window_minimized(self.hwnd) => CapturePoll::Paused,
+            None => CapturePoll::Pending,
+        }
+    }
+
+    fn stop(&mut self) {
+        let _ = self.stop_sender.send(());
+    }
+}
+
 /// Captured frame data from the capture thread
 pub struct WgcCapturedFrame {
     pub rgba: Vec<u8>,
@@ -63,6 +197,7 @@ pub fn start_wgc_capture(
     std::sync::mpsc::Receiver<WgcCapturedFrame>,
     std::sync::mpsc::Sender<()>,
 )> {
+    use super::pixels::flip_bgra_to_rgba;
     use std::sync::mpsc;
     use std::sync::{Arc, Mutex};
     use std::thread;
@@ -146,30 +281,13 @@ pub fn start_wgc_capture(
                 Err(_) => return Ok(()),
             };
 
+            // Rows can be padded, and the padding changes as the window is resized
+            let src_stride = buffer.row_pitch() as usize;
             let raw_data = buffer.as_raw_buffer();
-            let pixel_count = (width * height) as usize;
-            let mut rgba = vec![0u8; pixel_count * 4];
-
-            // Convert BGRA to RGBA and flip vertically
-            // WGC provides top-down, but pipeline expects bottom-up
-            let stride = width as usize * 4;
-            for y in 0..height as usize {
-                let src_y = y;
-                let dst_y = height as usize - 1 - y;
-                let src_row = src_y * stride;
-                let dst_row = dst_y * stride;
-
-                for x in 0..width as usize {
-                    let src_i = src_row + x * 4;
-                    let dst_i = dst_row + x * 4;
-                    if src_i + 3 < raw_data.len() {
-                        rgba[dst_i] = raw_data[src_i + 2];     // R (from B)
-                        rgba[dst_i + 1] = raw_data[src_i + 1]; // G
-                        rgba[dst_i + 2] = raw_data[src_i];     // B (from R)
-                        rgba[dst_i + 3] = raw_data[src_i + 3]; // A
-                    }
-                }
-            }
+
+            // WGC provides top-down BGRA, but pipeline expects bottom-up RGBA
+            let rgba =
+                flip_bgra_to_rgba(raw_data, src_stride, width as usize, height as usize, false);
 
             // Send frame (non-blocking, drop if receiver is gone)
             let _ = self.frame_tx.send(WgcCapturedFrame { rgba, width, height });
@@ -215,9 +333,3 @@ pub fn start_wgc_capture(
 )> {
     None
 }
-
-// Legacy function for compatibility - not used with WGC but kept for non-Windows
-#[cfg(not(windows))]
-pub fn capture_window(_hwnd: isize) -> Option<(Vec<u8>, u32, u32)> {
-    None
-}
@@ -0,0 +1,141 @@
+//! The config file settings are saved to, in the platform's config directory
+//! (e.g. `~/.config/zine/config.ron`). It's read once at startup and written
+//! shortly after any setting changes.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::{AudioSettings, Nickname, SessionSettings, VideoSettings};
+use crate::character::AvatarSelection;
+use crate::controls::{Action, InputSettings, KeyBindings};
+use crate::network::protocol::AvatarChoice;
+
+const CONFIG_DIR_NAME: &str = "zine";
+const CONFIG_FILE_NAME: &str = "config.ron";
+
+/// How long settings have to stay unchanged before they're saved, so
+/// dragging a slider doesn't write the file every frame.
+const SAVE_DELAY_SECS: f32 = 0.5;
+
+/// Everything saved between runs. Missing fields, e.g. from an older
+/// version, keep their defaults.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub nickname: String,
+    pub avatar: AvatarChoice,
+    pub audio: AudioSettings,
+    pub video: VideoSettings,
+    pub input: InputSettings,
+    pub bindings: HashMap<Action, KeyCode>,
+    pub session: SessionSettings,
+}
+
+impl Config {
+    /// Read the config file, or the defaults if there is none.
+    pub fn load() -> Self {
+        let path = config_path();
+        let Ok(data) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match ron::from_str(&data) {
+            Ok(config) => {
+                info!("Loaded settings from {}", path.display());
+                config
+            }
+            Err(e) => {
+                warn!("Ignoring unreadable {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    fn save(&self) {
+        let path = config_path();
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(std::io::Error::other)
+            .and_then(|data| {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&path, data)
+            });
+        if let Err(e) = result {
+            warn!("Failed to save settings to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Insert the resources the settings live in while the game runs.
+    pub fn insert_resources(self, app: &mut App) {
+        app.insert_resource(Nickname(self.nickname))
+            .insert_resource(AvatarSelection::new(self.avatar))
+            .insert_resource(self.audio)
+            .insert_resource(self.video)
+            .insert_resource(self.input)
+            .insert_resource(self.session)
+            .insert_resource(KeyBindings::from_saved(self.bindings));
+    }
+}
+
+/// Where the config file lives, falling back to the working directory on
+/// platforms without a config directory.
+fn config_path() -> PathBuf {
+    dirs::config_dir()
+        .map(|dir| dir.join(CONFIG_DIR_NAME))
+        .unwrap_or_default()
+        .join(CONFIG_FILE_NAME)
+}
+
+/// Resource counting down to saving the config after a change.
+#[derive(Resource, Default)]
+pub struct PendingConfigSave(Option<f32>);
+
+/// System to save the config once settings have stopped changing for a moment.
+pub fn save_config(
+    time: Res<Time>,
+    mut pending: ResMut<PendingConfigSave>,
+    nickname: Res<Nickname>,
+    avatar: Res<AvatarSelection>,
+    audio: Res<AudioSettings>,
+    video: Res<VideoSettings>,
+    input: Res<InputSettings>,
+    bindings: Res<KeyBindings>,
+    session: Res<SessionSettings>,
+) {
+    // The resources count as changed when inserted at startup, which isn't an edit
+    let changed = [
+        nickname.is_changed() && !nickname.is_added(),
+        avatar.is_changed() && !avatar.is_added(),
+        audio.is_changed() && !audio.is_added(),
+        video.is_changed() && !video.is_added(),
+        input.is_changed() && !input.is_added(),
+        bindings.is_changed() && !bindings.is_added(),
+        session.is_changed() && !session.is_added(),
+    ];
+    if changed.contains(&true) {
+        pending.0 = Some(SAVE_DELAY_SECS);
+        return;
+    }
+
+    let Some(remaining) = pending.0.as_mut() else {
+        return;
+    };
+    *remaining -= time.delta_secs();
+    if *remaining > 0.0 {
+        return;
+    }
+    pending.0 = None;
+
+    Config {
+        nickname: nickname.0.clone(),
+        avatar: avatar.0,
+        audio: audio.clone(),
+        video: video.clone(),
+        input: input.clone(),
+        bindings: bindings.to_saved(),
+        session: session.clone(),
+    }
+    .save();
+}
@@ -0,0 +1,195 @@
+//! Controls tab of the settings menu: mouse sensitivity and feel, then every
+//! action with the key it's bound to. Clicking a key waits for the next key press
+//! and binds it.
+
+use bevy::prelude::*;
+
+use super::ui::{
+    spawn_slider_row, spawn_toggle_button, SettingsPanel, SettingsSlider, SettingsTab,
+    SettingsToggle, SettingsUIState,
+};
+use crate::controls::bindings::key_name;
+use crate::controls::{Action, KeyBindings};
+use crate::menu::styles::{BUTTON_TEXT_COLOR, NORMAL_BUTTON, PRESSED_BUTTON};
+
+const LABEL_COLOR: Color = Color::srgb(0.7, 0.7, 0.7);
+
+const WAITING_LABEL: &str = "Press a key...";
+
+/// Button showing an action's key, clicked to rebind it.
+#[derive(Component)]
+pub struct BindingButton(pub Action);
+
+#[derive(Component)]
+pub struct BindingLabel(pub Action);
+
+#[derive(Component)]
+pub struct ResetBindingsButton;
+
+pub fn spawn_controls_panel(parent: &mut ChildBuilder) {
+    parent
+        .spawn((
+            SettingsPanel(SettingsTab::Controls),
+            Node {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                display: Display::None,
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            spawn_slider_row(panel, "Mouse sensitivity", SettingsSlider::MouseSensitivity);
+            spawn_slider_row(panel, "Horizontal scale", SettingsSlider::MouseScaleX);
+            spawn_slider_row(panel, "Vertical scale", SettingsSlider::MouseScaleY);
+            spawn_toggle_button(panel, SettingsToggle::RawMouseInput);
+            spawn_slider_row(
+                panel,
+                "Mouse acceleration",
+                SettingsSlider::MouseAcceleration,
+            );
+            spawn_toggle_button(panel, SettingsToggle::InvertLookY);
+
+            for action in Action::ALL {
+                spawn_binding_row(panel, action);
+            }
+
+            panel
+                .spawn((
+                    ResetBindingsButton,
+                    Button,
+                    Node {
+                        width: Val::Px(160.0),
+                        height: Val::Px(35.0),
+                        margin: UiRect::top(Val::Px(6.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    BackgroundColor(NORMAL_BUTTON),
+                ))
+                .with_child((
+                    Text::new("Reset to defaults"),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                ));
+        });
+}
+
+fn spawn_binding_row(parent: &mut ChildBuilder, action: Action) {
+    parent
+        .spawn(Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::SpaceBetween,
+            ..default()
+        })
+        .with_children(|row| {
+            row.spawn((
+                Text::new(action.label()),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(LABEL_COLOR),
+            ));
+            row.spawn((
+                BindingButton(action),
+                Button,
+                Node {
+                    width: Val::Px(160.0),
+                    height: Val::Px(26.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                BackgroundColor(NORMAL_BUTTON),
+            ))
+            .with_child((
+                BindingLabel(action),
+                Text::new(""),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(BUTTON_TEXT_COLOR),
+            ));
+        });
+}
+
+/// Start rebinding the clicked action, or stop if it was already waiting.
+/// Resetting puts every key back to its default.
+pub fn handle_controls_interaction(
+    mut state: ResMut<SettingsUIState>,
+    mut bindings: ResMut<KeyBindings>,
+    binding_query: Query<(&Interaction, &BindingButton), Changed<Interaction>>,
+    reset_query: Query<&Interaction, (Changed<Interaction>, With<ResetBindingsButton>)>,
+) {
+    for (interaction, button) in binding_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        state.rebinding = if state.rebinding == Some(button.0) {
+            None
+        } else {
+            Some(button.0)
+        };
+    }
+
+    for interaction in reset_query.iter() {
+        if *interaction == Interaction::Pressed {
+            *bindings = KeyBindings::default();
+            state.rebinding = None;
+            info!("Key bindings reset to defaults");
+        }
+    }
+}
+
+/// Bind the action waiting for a key to the next key pressed.
+pub fn capture_key_binding(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut state: ResMut<SettingsUIState>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    let Some(action) = state.rebinding else {
+        return;
+    };
+    let Some(&key) = keyboard_input.get_just_pressed().next() else {
+        return;
+    };
+
+    bindings.rebind(action, key);
+    state.rebinding = None;
+    info!("{} bound to {}", action.label(), key_name(key));
+}
+
+/// Show each action's key, and which one is waiting for a key press.
+pub fn update_binding_labels(
+    state: Res<SettingsUIState>,
+    bindings: Res<KeyBindings>,
+    mut labels: Query<(&BindingLabel, &mut Text)>,
+    mut buttons: Query<(&BindingButton, &mut BackgroundColor)>,
+) {
+    for (label, mut text) in labels.iter_mut() {
+        let value = if state.rebinding == Some(label.0) {
+            WAITING_LABEL.to_string()
+        } else {
+            key_name(bindings.key(label.0))
+        };
+        if text.0 != value {
+            text.0 = value;
+        }
+    }
+    for (button, mut color) in buttons.iter_mut() {
+        let value = if state.rebinding == Some(button.0) {
+            PRESSED_BUTTON
+        } else {
+            NORMAL_BUTTON
+        };
+        if color.0 != value {
+            color.0 = value;
+        }
+    }
+}
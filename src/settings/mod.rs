@@ -0,0 +1,131 @@
+pub mod config;
+pub mod controls;
+pub mod session;
+pub mod ui;
+pub mod video;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::console::AddConsoleCommand;
+use crate::game_state::AppState;
+use crate::screen::audio_decoder::{DEFAULT_AUDIO_JITTER_MS, DEFAULT_AUDIO_LATENCY_MS};
+use crate::screen::audio_encoder::DEFAULT_OPUS_BITRATE_KBPS;
+use config::{save_config, Config, PendingConfigSave};
+use controls::{capture_key_binding, handle_controls_interaction, update_binding_labels};
+use ui::*;
+use video::{
+    apply_camera_fov, apply_window_settings, limit_frame_rate, quality_command,
+    throttle_hidden_window, HiddenWindow,
+};
+
+pub use session::SessionSettings;
+pub use video::VideoSettings;
+
+/// Choices for the bitrate the host's audio is compressed to, in kbit/s.
+pub const AUDIO_BITRATE_OPTIONS: [u32; 5] = [64, 96, 128, 192, 256];
+
+/// Audio device choices and levels. A `None` device means the system default.
+/// Changing devices while streaming restarts capture/playback on the new device.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// Playback device for stream audio (clients).
+    pub output_device: Option<String>,
+    /// Device system audio is captured from (host).
+    pub capture_device: Option<String>,
+    /// Level of everything the game plays, 0.0 to 1.0.
+    pub master_volume: f32,
+    /// Stream audio playback volume, 0.0 to 1.0 (clients).
+    pub stream_volume: f32,
+    /// Voice chat playback volume, 0.0 to 1.0. Kept for when voice chat lands.
+    pub voice_volume: f32,
+    /// Silence stream audio playback (clients).
+    pub muted: bool,
+    /// Whether the host sends its audio to clients at all.
+    pub stream_audio: bool,
+    /// How long clients wait for late audio packets before concealing them.
+    pub jitter_buffer_ms: u32,
+    /// Decoded audio clients keep queued for playback; drift is corrected towards it.
+    pub playback_latency_ms: u32,
+    /// Bitrate the host compresses its audio to with Opus, in kbit/s.
+    pub audio_bitrate_kbps: u32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            output_device: None,
+            capture_device: None,
+            master_volume: 1.0,
+            stream_volume: 1.0,
+            voice_volume: 1.0,
+            muted: false,
+            stream_audio: true,
+            jitter_buffer_ms: DEFAULT_AUDIO_JITTER_MS,
+            playback_latency_ms: DEFAULT_AUDIO_LATENCY_MS,
+            audio_bitrate_kbps: DEFAULT_OPUS_BITRATE_KBPS,
+        }
+    }
+}
+
+impl AudioSettings {
+    /// Gain to apply to stream audio playback.
+    pub fn playback_gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.master_volume * self.stream_volume
+        }
+    }
+}
+
+/// Name shown to other players. Empty until the player picks one.
+#[derive(Resource, Clone, PartialEq, Debug, Default)]
+pub struct Nickname(pub String);
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        // Restore the saved settings before anything reads them
+        Config::load().insert_resources(app);
+
+        app.init_resource::<SettingsUIState>()
+            .init_resource::<PendingConfigSave>()
+            .init_resource::<HiddenWindow>()
+            .add_systems(
+                Update,
+                (
+                    toggle_settings_ui.run_if(in_state(AppState::InGame)),
+                    (
+                        handle_settings_interaction,
+                        handle_controls_interaction,
+                        capture_key_binding,
+                        edit_nickname,
+                        update_settings_tabs,
+                        update_settings_labels,
+                        update_binding_labels,
+                    )
+                        .chain()
+                        .run_if(resource_exists::<SettingsUIRoot>),
+                    apply_window_settings,
+                    throttle_hidden_window,
+                    apply_camera_fov,
+                    apply_master_volume,
+                    save_config,
+                ),
+            )
+            .add_systems(Last, limit_frame_rate)
+            .add_systems(OnExit(AppState::MainMenu), cleanup_settings_ui)
+            .add_systems(OnExit(AppState::InGame), cleanup_settings_ui)
+            .add_console_command("quality", "<native|1080p60|1080p30|720p>", quality_command);
+    }
+}
+
+/// System to scale the game's sound effects by the master volume.
+fn apply_master_volume(settings: Res<AudioSettings>, mut global_volume: ResMut<GlobalVolume>) {
+    if settings.is_changed() {
+        *global_volume = GlobalVolume::new(settings.master_volume);
+    }
+}
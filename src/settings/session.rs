@@ -0,0 +1,49 @@
+//! How the host treats players who stop responding, go away from their
+//! keyboard or drop out and come back.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Choices for how long an unresponsive client is kept, in seconds.
+pub const CLIENT_TIMEOUT_OPTIONS: [u32; 5] = [5, 10, 30, 60, 120];
+
+/// Choices for how long a player may be away before being removed, in
+/// minutes. 0 never removes them.
+pub const AFK_KICK_OPTIONS: [u32; 6] = [0, 5, 10, 15, 30, 60];
+
+/// Choices for how long a player who left can come back as themselves, in
+/// seconds. 0 turns it off.
+pub const REJOIN_GRACE_OPTIONS: [u32; 5] = [0, 30, 60, 120, 300];
+
+/// Host policies for players' connections, used while hosting.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct SessionSettings {
+    /// Seconds without hearing from a client before it's dropped.
+    pub client_timeout_secs: u32,
+    /// Minutes a player may stay away before being removed, 0 for never.
+    pub afk_kick_mins: u32,
+    /// Seconds after leaving during which a player rejoining from the same
+    /// address with the same nickname gets their id, color and place back.
+    pub rejoin_grace_secs: u32,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            client_timeout_secs: 5,
+            afk_kick_mins: 0,
+            rejoin_grace_secs: 60,
+        }
+    }
+}
+
+/// A number of seconds as shown in the settings menu.
+pub fn duration_label(secs: u32) -> String {
+    match secs {
+        0 => "Off".to_string(),
+        60 => "1 minute".to_string(),
+        secs if secs % 60 == 0 => format!("{} minutes", secs / 60),
+        secs => format!("{} seconds", secs),
+    }
+}
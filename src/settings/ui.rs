@@ -0,0 +1,1055 @@
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::input::ButtonState;
+use bevy::prelude::*;
+use bevy::ui::{FocusPolicy, RelativeCursorPosition};
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+
+use super::controls::spawn_controls_panel;
+use super::session::{
+    duration_label, AFK_KICK_OPTIONS, CLIENT_TIMEOUT_OPTIONS, REJOIN_GRACE_OPTIONS,
+};
+use super::video::{
+    StreamLatency, UploadLimit, WindowModeSetting, MAX_FOV, MAX_FRAME_LIMIT, MIN_FOV,
+    MIN_FRAME_LIMIT, RESOLUTIONS,
+};
+use super::{AudioSettings, Nickname, SessionSettings, VideoSettings, AUDIO_BITRATE_OPTIONS};
+use crate::controls::{Action, InputSettings};
+use crate::game_state::{AppState, PauseState};
+use crate::menu::styles::{BUTTON_TEXT_COLOR, HOVERED_BUTTON, NORMAL_BUTTON};
+use crate::network::protocol::MAX_NICKNAME_CHARS;
+use crate::player::MOUSE_SENSITIVITY;
+use crate::screen::audio_devices::{capture_devices, output_devices};
+
+/// Device lists shown in the settings menu, refreshed each time it opens,
+/// and which part of the menu is showing.
+#[derive(Resource, Default)]
+pub struct SettingsUIState {
+    pub output_devices: Vec<String>,
+    pub capture_devices: Vec<String>,
+    pub tab: SettingsTab,
+    /// Action waiting for a key press to bind it to.
+    pub rebinding: Option<Action>,
+    /// Whether typing goes into the nickname field.
+    pub editing_nickname: bool,
+}
+
+/// Resource marker for when the settings menu is open.
+#[derive(Resource)]
+pub struct SettingsUIRoot(pub Entity);
+
+/// Pages of the settings menu, picked with the tab buttons along the top.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SettingsTab {
+    #[default]
+    General,
+    Audio,
+    Video,
+    Controls,
+}
+
+impl SettingsTab {
+    const ALL: [SettingsTab; 4] = [
+        SettingsTab::General,
+        SettingsTab::Audio,
+        SettingsTab::Video,
+        SettingsTab::Controls,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            SettingsTab::General => "General",
+            SettingsTab::Audio => "Audio",
+            SettingsTab::Video => "Video",
+            SettingsTab::Controls => "Controls",
+        }
+    }
+}
+
+/// Contents of one settings tab, shown while it's selected.
+#[derive(Component)]
+pub struct SettingsPanel(pub SettingsTab);
+
+/// Which setting a picker row cycles through.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PickerKind {
+    OutputDevice,
+    CaptureDevice,
+    AudioBitrate,
+    WindowMode,
+    Resolution,
+    StreamLatency,
+    UploadLimit,
+    ClientTimeout,
+    AfkKick,
+    RejoinGrace,
+}
+
+// UI Components
+#[derive(Component)]
+pub struct PickerArrow {
+    pub kind: PickerKind,
+    pub step: isize,
+}
+
+#[derive(Component)]
+pub struct PickerLabel(pub PickerKind);
+
+/// Clickable track setting a value by where it's clicked; the fill child
+/// shows the current value.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsSlider {
+    MasterVolume,
+    StreamVolume,
+    VoiceVolume,
+    Fov,
+    FrameLimit,
+    MouseSensitivity,
+    MouseScaleX,
+    MouseScaleY,
+    MouseAcceleration,
+}
+
+#[derive(Component)]
+pub struct SliderFill(pub SettingsSlider);
+
+#[derive(Component)]
+pub struct SliderLabel(pub SettingsSlider);
+
+/// On/off setting toggled by a button.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsToggle {
+    Mute,
+    StreamAudio,
+    Vsync,
+    StreamStats,
+    HeadBob,
+    CameraSmoothing,
+    RawMouseInput,
+    InvertLookY,
+}
+
+/// Text field for the nickname, typed into after clicking it.
+#[derive(Component)]
+pub struct NicknameField;
+
+#[derive(Component)]
+pub struct NicknameLabel;
+
+#[derive(Component)]
+pub struct SettingsCloseButton;
+
+// Colors
+const BG_COLOR: Color = Color::srgba(0.1, 0.1, 0.1, 0.95);
+const LABEL_COLOR: Color = Color::srgb(0.7, 0.7, 0.7);
+const CLOSE_COLOR: Color = Color::srgb(0.5, 0.3, 0.3);
+const SLIDER_TRACK: Color = Color::srgb(0.2, 0.2, 0.2);
+const SLIDER_FILL: Color = Color::srgb(0.3, 0.5, 0.3);
+const FIELD_COLOR: Color = Color::srgb(0.05, 0.05, 0.05);
+const FIELD_EDITING_COLOR: Color = Color::srgb(0.2, 0.25, 0.35);
+
+const DEFAULT_DEVICE_LABEL: &str = "System default";
+const NO_NICKNAME_LABEL: &str = "Click to pick a nickname";
+
+/// Range the mouse sensitivity slider covers, as multiples of the default.
+const MIN_SENSITIVITY_SCALE: f32 = 0.2;
+const MAX_SENSITIVITY_SCALE: f32 = 3.0;
+
+/// Range the horizontal and vertical mouse scales cover.
+const MIN_MOUSE_AXIS_SCALE: f32 = 0.5;
+const MAX_MOUSE_AXIS_SCALE: f32 = 2.0;
+
+/// Most mouse acceleration the slider sets.
+const MAX_MOUSE_ACCELERATION: f32 = 2.0;
+
+impl SettingsSlider {
+    /// Where the slider sits for the current value, from 0.0 to 1.0.
+    fn fraction(self, audio: &AudioSettings, video: &VideoSettings, input: &InputSettings) -> f32 {
+        match self {
+            SettingsSlider::MasterVolume => audio.master_volume,
+            SettingsSlider::StreamVolume => audio.stream_volume,
+            SettingsSlider::VoiceVolume => audio.voice_volume,
+            SettingsSlider::Fov => (video.fov - MIN_FOV) / (MAX_FOV - MIN_FOV),
+            SettingsSlider::FrameLimit if video.frame_limit == 0 => 1.0,
+            SettingsSlider::FrameLimit => {
+                (video.frame_limit as f32 - MIN_FRAME_LIMIT as f32)
+                    / (MAX_FRAME_LIMIT - MIN_FRAME_LIMIT) as f32
+            }
+            SettingsSlider::MouseSensitivity => {
+                (input.mouse_sensitivity / MOUSE_SENSITIVITY - MIN_SENSITIVITY_SCALE)
+                    / (MAX_SENSITIVITY_SCALE - MIN_SENSITIVITY_SCALE)
+            }
+            SettingsSlider::MouseScaleX => {
+                (input.mouse_scale_x - MIN_MOUSE_AXIS_SCALE)
+                    / (MAX_MOUSE_AXIS_SCALE - MIN_MOUSE_AXIS_SCALE)
+            }
+            SettingsSlider::MouseScaleY => {
+                (input.mouse_scale_y - MIN_MOUSE_AXIS_SCALE)
+                    / (MAX_MOUSE_AXIS_SCALE - MIN_MOUSE_AXIS_SCALE)
+            }
+            SettingsSlider::MouseAcceleration => input.mouse_acceleration / MAX_MOUSE_ACCELERATION,
+        }
+        .clamp(0.0, 1.0)
+    }
+
+    fn set_fraction(
+        self,
+        fraction: f32,
+        audio: &mut ResMut<AudioSettings>,
+        video: &mut ResMut<VideoSettings>,
+        input: &mut ResMut<InputSettings>,
+    ) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        match self {
+            SettingsSlider::MasterVolume => {
+                set_if_changed(audio, |a| &mut a.master_volume, fraction);
+            }
+            SettingsSlider::StreamVolume => {
+                set_if_changed(audio, |a| &mut a.stream_volume, fraction);
+            }
+            SettingsSlider::VoiceVolume => {
+                set_if_changed(audio, |a| &mut a.voice_volume, fraction);
+            }
+            SettingsSlider::Fov => {
+                let fov = (MIN_FOV + fraction * (MAX_FOV - MIN_FOV)).round();
+                set_if_changed(video, |v| &mut v.fov, fov);
+            }
+            SettingsSlider::FrameLimit => {
+                // Steps of ten, the top of the track lifting the limit
+                let range = (MAX_FRAME_LIMIT - MIN_FRAME_LIMIT) as f32;
+                let limit =
+                    ((MIN_FRAME_LIMIT as f32 + fraction * range) / 10.0).round() as u32 * 10;
+                let limit = if limit >= MAX_FRAME_LIMIT { 0 } else { limit };
+                if video.bypass_change_detection().frame_limit != limit {
+                    video.frame_limit = limit;
+                }
+            }
+            SettingsSlider::MouseSensitivity => {
+                // Steps of a tenth, so the label shows the exact value
+                let scale = MIN_SENSITIVITY_SCALE
+                    + fraction * (MAX_SENSITIVITY_SCALE - MIN_SENSITIVITY_SCALE);
+                let sensitivity = (scale * 10.0).round() / 10.0 * MOUSE_SENSITIVITY;
+                set_if_changed(input, |i| &mut i.mouse_sensitivity, sensitivity);
+            }
+            SettingsSlider::MouseScaleX | SettingsSlider::MouseScaleY => {
+                let scale =
+                    MIN_MOUSE_AXIS_SCALE + fraction * (MAX_MOUSE_AXIS_SCALE - MIN_MOUSE_AXIS_SCALE);
+                let scale = (scale * 10.0).round() / 10.0;
+                if self == SettingsSlider::MouseScaleX {
+                    set_if_changed(input, |i| &mut i.mouse_scale_x, scale);
+                } else {
+                    set_if_changed(input, |i| &mut i.mouse_scale_y, scale);
+                }
+            }
+            SettingsSlider::MouseAcceleration => {
+                let acceleration = (fraction * MAX_MOUSE_ACCELERATION * 10.0).round() / 10.0;
+                set_if_changed(input, |i| &mut i.mouse_acceleration, acceleration);
+            }
+        }
+    }
+
+    fn label(self, audio: &AudioSettings, video: &VideoSettings, input: &InputSettings) -> String {
+        match self {
+            SettingsSlider::MasterVolume
+            | SettingsSlider::StreamVolume
+            | SettingsSlider::VoiceVolume => {
+                format!("{}%", (self.fraction(audio, video, input) * 100.0).round())
+            }
+            SettingsSlider::Fov => format!("{}°", video.fov.round()),
+            SettingsSlider::FrameLimit if video.frame_limit == 0 => "Unlimited".to_string(),
+            SettingsSlider::FrameLimit => format!("{} fps", video.frame_limit),
+            SettingsSlider::MouseSensitivity => {
+                format!("{:.1}x", input.mouse_sensitivity / MOUSE_SENSITIVITY)
+            }
+            SettingsSlider::MouseScaleX => format!("{:.1}x", input.mouse_scale_x),
+            SettingsSlider::MouseScaleY => format!("{:.1}x", input.mouse_scale_y),
+            SettingsSlider::MouseAcceleration => format!("{:.1}", input.mouse_acceleration),
+        }
+    }
+}
+
+/// Write a setting only when it differs, so unchanged settings aren't saved
+/// or applied again.
+fn set_if_changed<T: Resource>(
+    resource: &mut ResMut<T>,
+    field: impl Fn(&mut T) -> &mut f32,
+    value: f32,
+) {
+    if *field(resource.bypass_change_detection()) != value {
+        *field(&mut **resource) = value;
+    }
+}
+
+/// Open the settings menu and re-enumerate audio devices.
+pub fn open_settings_ui(commands: &mut Commands, state: &mut SettingsUIState) {
+    state.output_devices = output_devices();
+    state.capture_devices = capture_devices();
+    state.tab = SettingsTab::General;
+    state.rebinding = None;
+    state.editing_nickname = false;
+
+    let root = commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+            // Keep clicks from reaching the menu underneath
+            FocusPolicy::Block,
+            GlobalZIndex(100),
+        ))
+        .with_children(|parent| {
+            // Modal container
+            parent
+                .spawn((
+                    Node {
+                        width: Val::Px(560.0),
+                        flex_direction: FlexDirection::Column,
+                        padding: UiRect::all(Val::Px(20.0)),
+                        row_gap: Val::Px(10.0),
+                        ..default()
+                    },
+                    BackgroundColor(BG_COLOR),
+                ))
+                .with_children(|modal| {
+                    // Title
+                    modal.spawn((
+                        Text::new("Settings"),
+                        TextFont {
+                            font_size: 24.0,
+                            ..default()
+                        },
+                        TextColor(Color::WHITE),
+                        Node {
+                            margin: UiRect::bottom(Val::Px(5.0)),
+                            ..default()
+                        },
+                    ));
+
+                    modal
+                        .spawn(Node {
+                            flex_direction: FlexDirection::Row,
+                            column_gap: Val::Px(10.0),
+                            margin: UiRect::bottom(Val::Px(5.0)),
+                            ..default()
+                        })
+                        .with_children(|tabs| {
+                            for tab in SettingsTab::ALL {
+                                spawn_tab_button(tabs, tab);
+                            }
+                        });
+
+                    spawn_panel(modal, SettingsTab::General, |panel| {
+                        spawn_nickname_field(panel);
+                        spawn_picker(panel, "Timeout (host)", PickerKind::ClientTimeout);
+                        spawn_picker(panel, "Remove if away (host)", PickerKind::AfkKick);
+                        spawn_picker(panel, "Rejoin grace period (host)", PickerKind::RejoinGrace);
+                    });
+
+                    spawn_panel(modal, SettingsTab::Audio, |panel| {
+                        spawn_picker(panel, "Audio output", PickerKind::OutputDevice);
+                        spawn_picker(panel, "Audio capture (host)", PickerKind::CaptureDevice);
+                        spawn_picker(panel, "Audio quality (host)", PickerKind::AudioBitrate);
+                        spawn_slider_row(panel, "Master volume", SettingsSlider::MasterVolume);
+                        spawn_slider_row(panel, "Stream volume", SettingsSlider::StreamVolume);
+                        spawn_slider_row(panel, "Voice volume", SettingsSlider::VoiceVolume);
+                        spawn_toggle_button(panel, SettingsToggle::StreamAudio);
+                    });
+
+                    spawn_panel(modal, SettingsTab::Video, |panel| {
+                        spawn_slider_row(panel, "Field of view", SettingsSlider::Fov);
+                        spawn_toggle_button(panel, SettingsToggle::HeadBob);
+                        spawn_toggle_button(panel, SettingsToggle::CameraSmoothing);
+                        spawn_picker(panel, "Window mode", PickerKind::WindowMode);
+                        spawn_picker(panel, "Resolution (windowed)", PickerKind::Resolution);
+                        spawn_toggle_button(panel, SettingsToggle::Vsync);
+                        spawn_slider_row(panel, "Frame limit", SettingsSlider::FrameLimit);
+                        spawn_picker(panel, "Stream latency", PickerKind::StreamLatency);
+                        spawn_picker(panel, "Upload limit (host)", PickerKind::UploadLimit);
+                        spawn_toggle_button(panel, SettingsToggle::StreamStats);
+                    });
+
+                    spawn_controls_panel(modal);
+
+                    // Close button
+                    modal
+                        .spawn((Node {
+                            justify_content: JustifyContent::FlexEnd,
+                            margin: UiRect::top(Val::Px(10.0)),
+                            ..default()
+                        },))
+                        .with_children(|buttons| {
+                            buttons
+                                .spawn((
+                                    SettingsCloseButton,
+                                    Button,
+                                    Node {
+                                        width: Val::Px(100.0),
+                                        height: Val::Px(40.0),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    BackgroundColor(CLOSE_COLOR),
+                                ))
+                                .with_children(|btn| {
+                                    btn.spawn((
+                                        Text::new("Close"),
+                                        TextFont {
+                                            font_size: 16.0,
+                                            ..default()
+                                        },
+                                        TextColor(Color::WHITE),
+                                    ));
+                                });
+                        });
+                });
+        })
+        .id();
+
+    commands.insert_resource(SettingsUIRoot(root));
+}
+
+fn spawn_tab_button(parent: &mut ChildBuilder, tab: SettingsTab) {
+    parent
+        .spawn((
+            tab,
+            Button,
+            Node {
+                min_width: Val::Px(100.0),
+                height: Val::Px(35.0),
+                padding: UiRect::horizontal(Val::Px(10.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(NORMAL_BUTTON),
+        ))
+        .with_child((
+            Text::new(tab.label()),
+            TextFont {
+                font_size: 16.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+        ));
+}
+
+/// A tab's column of settings, hidden until the tab is picked.
+fn spawn_panel(
+    parent: &mut ChildBuilder,
+    tab: SettingsTab,
+    spawn_rows: impl FnOnce(&mut ChildBuilder),
+) {
+    parent
+        .spawn((
+            SettingsPanel(tab),
+            Node {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(10.0),
+                display: Display::None,
+                ..default()
+            },
+        ))
+        .with_children(spawn_rows);
+}
+
+fn spawn_row_label(parent: &mut ChildBuilder, label: &str) {
+    parent.spawn((
+        Text::new(label),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(LABEL_COLOR),
+    ));
+}
+
+/// The nickname, typed into after clicking the field.
+fn spawn_nickname_field(parent: &mut ChildBuilder) {
+    spawn_row_label(parent, "Nickname");
+
+    parent
+        .spawn((
+            NicknameField,
+            Button,
+            Node {
+                height: Val::Px(35.0),
+                padding: UiRect::horizontal(Val::Px(10.0)),
+                align_items: AlignItems::Center,
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            BackgroundColor(FIELD_COLOR),
+        ))
+        .with_child((
+            NicknameLabel,
+            Text::new(NO_NICKNAME_LABEL),
+            TextFont {
+                font_size: 16.0,
+                ..default()
+            },
+            TextColor(BUTTON_TEXT_COLOR),
+        ));
+}
+
+/// A labelled row of `< value >` that cycles through the choices.
+fn spawn_picker(parent: &mut ChildBuilder, label: &str, kind: PickerKind) {
+    spawn_row_label(parent, label);
+
+    parent
+        .spawn((Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(10.0),
+            ..default()
+        },))
+        .with_children(|row| {
+            spawn_arrow_button(row, "<", kind, -1);
+            row.spawn((
+                PickerLabel(kind),
+                Text::new(DEFAULT_DEVICE_LABEL),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(BUTTON_TEXT_COLOR),
+                Node {
+                    flex_grow: 1.0,
+                    overflow: Overflow::clip(),
+                    ..default()
+                },
+            ));
+            spawn_arrow_button(row, ">", kind, 1);
+        });
+}
+
+/// A slider with its value, and a mute toggle for the stream volume.
+pub fn spawn_slider_row(parent: &mut ChildBuilder, label: &str, slider: SettingsSlider) {
+    spawn_row_label(parent, label);
+
+    parent
+        .spawn((Node {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(10.0),
+            ..default()
+        },))
+        .with_children(|row| {
+            row.spawn((
+                slider,
+                Button,
+                RelativeCursorPosition::default(),
+                Node {
+                    flex_grow: 1.0,
+                    height: Val::Px(20.0),
+                    ..default()
+                },
+                BackgroundColor(SLIDER_TRACK),
+            ))
+            .with_children(|track| {
+                track.spawn((
+                    SliderFill(slider),
+                    Node {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    BackgroundColor(SLIDER_FILL),
+                    // Let clicks reach the track
+                    FocusPolicy::Pass,
+                ));
+            });
+            row.spawn((
+                SliderLabel(slider),
+                Text::new(""),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(BUTTON_TEXT_COLOR),
+                Node {
+                    width: Val::Px(50.0),
+                    ..default()
+                },
+            ));
+            if slider == SettingsSlider::StreamVolume {
+                spawn_toggle_button(row, SettingsToggle::Mute);
+            }
+        });
+}
+
+pub fn spawn_toggle_button(parent: &mut ChildBuilder, toggle: SettingsToggle) {
+    parent
+        .spawn((
+            toggle,
+            Button,
+            Node {
+                min_width: Val::Px(100.0),
+                height: Val::Px(35.0),
+                padding: UiRect::horizontal(Val::Px(10.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(NORMAL_BUTTON),
+        ))
+        .with_children(|btn| {
+            btn.spawn((
+                toggle,
+                Text::new(toggle_label(
+                    toggle,
+                    &AudioSettings::default(),
+                    &VideoSettings::default(),
+                    &InputSettings::default(),
+                )),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+        });
+}
+
+fn toggle_label(
+    toggle: SettingsToggle,
+    audio: &AudioSettings,
+    video: &VideoSettings,
+    input: &InputSettings,
+) -> &'static str {
+    match toggle {
+        SettingsToggle::Mute if audio.muted => "Unmute",
+        SettingsToggle::Mute => "Mute",
+        SettingsToggle::StreamAudio if audio.stream_audio => "Send stream audio: On",
+        SettingsToggle::StreamAudio => "Send stream audio: Off",
+        SettingsToggle::Vsync if video.vsync => "VSync: On",
+        SettingsToggle::Vsync => "VSync: Off",
+        SettingsToggle::StreamStats if video.show_stream_stats => "Stream info: On",
+        SettingsToggle::StreamStats => "Stream info: Off",
+        SettingsToggle::HeadBob if video.head_bob => "Head bob: On",
+        SettingsToggle::HeadBob => "Head bob: Off",
+        SettingsToggle::CameraSmoothing if video.camera_smoothing => "Camera smoothing: On",
+        SettingsToggle::CameraSmoothing => "Camera smoothing: Off",
+        SettingsToggle::RawMouseInput if input.raw_mouse_input => "Raw mouse input: On",
+        SettingsToggle::RawMouseInput => "Raw mouse input: Off",
+        SettingsToggle::InvertLookY if input.invert_look_y => "Invert look: On",
+        SettingsToggle::InvertLookY => "Invert look: Off",
+    }
+}
+
+fn spawn_arrow_button(parent: &mut ChildBuilder, label: &str, kind: PickerKind, step: isize) {
+    parent
+        .spawn((
+            PickerArrow { kind, step },
+            Button,
+            Node {
+                width: Val::Px(35.0),
+                height: Val::Px(35.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(NORMAL_BUTTON),
+        ))
+        .with_children(|btn| {
+            btn.spawn((
+                Text::new(label),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+        });
+}
+
+pub fn cleanup_settings_ui(mut commands: Commands, root: Option<Res<SettingsUIRoot>>) {
+    if let Some(root) = root {
+        commands.entity(root.0).despawn_recursive();
+        commands.remove_resource::<SettingsUIRoot>();
+    }
+}
+
+/// Open/close the settings menu in-game with F1.
+pub fn toggle_settings_ui(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    root: Option<Res<SettingsUIRoot>>,
+    mut state: ResMut<SettingsUIState>,
+    pause_state: Res<State<PauseState>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F1) {
+        return;
+    }
+
+    // The pause menu underneath keeps the cursor free
+    let regrab_cursor = *pause_state.get() == PauseState::Running;
+    match root {
+        Some(root) => close_settings_ui(&mut commands, root.0, &mut windows, regrab_cursor),
+        None => open_settings_ui(&mut commands, &mut state),
+    }
+}
+
+fn close_settings_ui(
+    commands: &mut Commands,
+    root: Entity,
+    windows: &mut Query<&mut Window, With<PrimaryWindow>>,
+    regrab_cursor: bool,
+) {
+    commands.entity(root).despawn_recursive();
+    commands.remove_resource::<SettingsUIRoot>();
+
+    if regrab_cursor {
+        if let Ok(mut window) = windows.get_single_mut() {
+            window.cursor_options.grab_mode = CursorGrabMode::Locked;
+            window.cursor_options.visible = false;
+        }
+    }
+}
+
+pub fn handle_settings_interaction(
+    mut commands: Commands,
+    root: Res<SettingsUIRoot>,
+    app_state: Res<State<AppState>>,
+    pause_state: Option<Res<State<PauseState>>>,
+    mut state: ResMut<SettingsUIState>,
+    mut audio: ResMut<AudioSettings>,
+    mut video: ResMut<VideoSettings>,
+    mut input: ResMut<InputSettings>,
+    mut session: ResMut<SessionSettings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+    tab_query: Query<(&Interaction, &SettingsTab), (Changed<Interaction>, With<Button>)>,
+    arrow_query: Query<(&Interaction, &PickerArrow), Changed<Interaction>>,
+    slider_query: Query<(&Interaction, &RelativeCursorPosition, &SettingsSlider)>,
+    toggle_query: Query<(&Interaction, &SettingsToggle), (Changed<Interaction>, With<Button>)>,
+    nickname_query: Query<&Interaction, (Changed<Interaction>, With<NicknameField>)>,
+    close_query: Query<&Interaction, (Changed<Interaction>, With<SettingsCloseButton>)>,
+) {
+    let in_game = *app_state.get() == AppState::InGame;
+
+    // Release cursor when UI is open
+    if in_game {
+        if let Ok(mut window) = windows.get_single_mut() {
+            if window.cursor_options.grab_mode != CursorGrabMode::None {
+                window.cursor_options.grab_mode = CursorGrabMode::None;
+                window.cursor_options.visible = true;
+            }
+        }
+    }
+
+    for (interaction, tab) in tab_query.iter() {
+        if *interaction == Interaction::Pressed {
+            state.tab = *tab;
+            state.rebinding = None;
+            state.editing_nickname = false;
+        }
+    }
+
+    for interaction in nickname_query.iter() {
+        if *interaction == Interaction::Pressed {
+            state.editing_nickname = !state.editing_nickname;
+        }
+    }
+
+    for (interaction, arrow) in arrow_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match arrow.kind {
+            // Cycle devices; index 0 is the system default
+            PickerKind::OutputDevice | PickerKind::CaptureDevice => {
+                let (devices, current) = match arrow.kind {
+                    PickerKind::OutputDevice => (&state.output_devices, &mut audio.output_device),
+                    _ => (&state.capture_devices, &mut audio.capture_device),
+                };
+                let index = current
+                    .as_ref()
+                    .and_then(|name| devices.iter().position(|d| d == name))
+                    .map_or(0, |i| i + 1);
+                let next = cycle(index, arrow.step, devices.len() + 1);
+                *current = next.checked_sub(1).map(|i| devices[i].clone());
+
+                info!(
+                    "Audio {} device set to {}",
+                    match arrow.kind {
+                        PickerKind::OutputDevice => "output",
+                        _ => "capture",
+                    },
+                    current.as_deref().unwrap_or(DEFAULT_DEVICE_LABEL)
+                );
+            }
+            PickerKind::AudioBitrate => {
+                let options = AUDIO_BITRATE_OPTIONS;
+                let index = options
+                    .iter()
+                    .position(|b| *b == audio.audio_bitrate_kbps)
+                    .unwrap_or(0);
+                audio.audio_bitrate_kbps = options[cycle(index, arrow.step, options.len())];
+            }
+            PickerKind::WindowMode => {
+                let modes = WindowModeSetting::ALL;
+                let index = modes
+                    .iter()
+                    .position(|m| *m == video.window_mode)
+                    .unwrap_or(0);
+                video.window_mode = modes[cycle(index, arrow.step, modes.len())];
+            }
+            PickerKind::Resolution => {
+                // A size set by hand that isn't in the list steps from the start
+                let index = RESOLUTIONS
+                    .iter()
+                    .position(|r| *r == video.resolution)
+                    .unwrap_or(0);
+                video.resolution = RESOLUTIONS[cycle(index, arrow.step, RESOLUTIONS.len())];
+            }
+            PickerKind::StreamLatency => {
+                let modes = StreamLatency::ALL;
+                let index = modes
+                    .iter()
+                    .position(|m| *m == video.stream_latency)
+                    .unwrap_or(0);
+                video.stream_latency = modes[cycle(index, arrow.step, modes.len())];
+            }
+            PickerKind::UploadLimit => {
+                let limits = UploadLimit::ALL;
+                let index = limits
+                    .iter()
+                    .position(|l| *l == video.upload_limit)
+                    .unwrap_or(0);
+                video.upload_limit = limits[cycle(index, arrow.step, limits.len())];
+            }
+            PickerKind::ClientTimeout | PickerKind::AfkKick | PickerKind::RejoinGrace => {
+                let session = &mut *session;
+                let (options, current) = match arrow.kind {
+                    PickerKind::ClientTimeout => (
+                        &CLIENT_TIMEOUT_OPTIONS[..],
+                        &mut session.client_timeout_secs,
+                    ),
+                    PickerKind::AfkKick => (&AFK_KICK_OPTIONS[..], &mut session.afk_kick_mins),
+                    _ => (&REJOIN_GRACE_OPTIONS[..], &mut session.rejoin_grace_secs),
+                };
+                let index = options.iter().position(|v| v == current).unwrap_or(0);
+                *current = options[cycle(index, arrow.step, options.len())];
+            }
+        }
+    }
+
+    // Drag along a track to set its value
+    for (interaction, cursor, slider) in slider_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(position) = cursor.normalized {
+            slider.set_fraction(position.x, &mut audio, &mut video, &mut input);
+        }
+    }
+
+    for (interaction, toggle) in toggle_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match toggle {
+            SettingsToggle::Mute => audio.muted = !audio.muted,
+            SettingsToggle::StreamAudio => {
+                audio.stream_audio = !audio.stream_audio;
+                info!(
+                    "Stream audio {}",
+                    if audio.stream_audio {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    }
+                );
+            }
+            SettingsToggle::Vsync => video.vsync = !video.vsync,
+            SettingsToggle::StreamStats => video.show_stream_stats = !video.show_stream_stats,
+            SettingsToggle::HeadBob => video.head_bob = !video.head_bob,
+            SettingsToggle::CameraSmoothing => video.camera_smoothing = !video.camera_smoothing,
+            SettingsToggle::RawMouseInput => input.raw_mouse_input = !input.raw_mouse_input,
+            SettingsToggle::InvertLookY => input.invert_look_y = !input.invert_look_y,
+        }
+    }
+
+    for interaction in close_query.iter() {
+        if *interaction == Interaction::Pressed {
+            state.editing_nickname = false;
+            let paused = pause_state.is_some_and(|s| *s.get() == PauseState::Paused);
+            close_settings_ui(&mut commands, root.0, &mut windows, in_game && !paused);
+            return;
+        }
+    }
+}
+
+fn cycle(index: usize, step: isize, len: usize) -> usize {
+    (index as isize + step).rem_euclid(len as isize) as usize
+}
+
+/// Type into the nickname field while it's being edited. Enter or Escape
+/// finishes editing.
+pub fn edit_nickname(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut state: ResMut<SettingsUIState>,
+    mut nickname: ResMut<Nickname>,
+) {
+    if !state.editing_nickname {
+        keyboard_events.clear();
+        return;
+    }
+
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Enter | Key::Escape => state.editing_nickname = false,
+            Key::Backspace => {
+                nickname.0.pop();
+            }
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if nickname.0.chars().count() < MAX_NICKNAME_CHARS {
+                        nickname.0.push(c);
+                    }
+                }
+            }
+            Key::Space if nickname.0.chars().count() < MAX_NICKNAME_CHARS => {
+                nickname.0.push(' ');
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Show the selected tab's panel and highlight its button.
+pub fn update_settings_tabs(
+    state: Res<SettingsUIState>,
+    mut panels: Query<(&SettingsPanel, &mut Node)>,
+    mut tab_buttons: Query<(&SettingsTab, &mut BackgroundColor), With<Button>>,
+) {
+    for (panel, mut node) in panels.iter_mut() {
+        let display = if panel.0 == state.tab {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if node.display != display {
+            node.display = display;
+        }
+    }
+    for (tab, mut color) in tab_buttons.iter_mut() {
+        let value = if *tab == state.tab {
+            HOVERED_BUTTON
+        } else {
+            NORMAL_BUTTON
+        };
+        if color.0 != value {
+            color.0 = value;
+        }
+    }
+}
+
+/// Show the current settings values.
+pub fn update_settings_labels(
+    state: Res<SettingsUIState>,
+    audio: Res<AudioSettings>,
+    video: Res<VideoSettings>,
+    input: Res<InputSettings>,
+    session: Res<SessionSettings>,
+    nickname: Res<Nickname>,
+    mut picker_labels: Query<(&PickerLabel, &mut Text)>,
+    mut toggle_labels: Query<
+        (&SettingsToggle, &mut Text),
+        (
+            Without<PickerLabel>,
+            Without<SliderLabel>,
+            Without<NicknameLabel>,
+        ),
+    >,
+    mut slider_labels: Query<
+        (&SliderLabel, &mut Text),
+        (Without<PickerLabel>, Without<NicknameLabel>),
+    >,
+    mut nickname_label: Query<&mut Text, (With<NicknameLabel>, Without<PickerLabel>)>,
+    mut slider_fills: Query<(&SliderFill, &mut Node)>,
+    mut nickname_field: Query<&mut BackgroundColor, With<NicknameField>>,
+) {
+    for (toggle, mut text) in toggle_labels.iter_mut() {
+        let value = toggle_label(*toggle, &audio, &video, &input);
+        if text.0 != value {
+            text.0 = value.to_string();
+        }
+    }
+
+    for (label, mut text) in slider_labels.iter_mut() {
+        let value = label.0.label(&audio, &video, &input);
+        if text.0 != value {
+            text.0 = value;
+        }
+    }
+    for (fill, mut node) in slider_fills.iter_mut() {
+        let width = Val::Percent(fill.0.fraction(&audio, &video, &input) * 100.0);
+        if node.width != width {
+            node.width = width;
+        }
+    }
+
+    for mut text in nickname_label.iter_mut() {
+        let value = if state.editing_nickname {
+            format!("{}|", nickname.0)
+        } else if nickname.0.is_empty() {
+            NO_NICKNAME_LABEL.to_string()
+        } else {
+            nickname.0.clone()
+        };
+        if text.0 != value {
+            text.0 = value;
+        }
+    }
+    for mut color in nickname_field.iter_mut() {
+        let value = if state.editing_nickname {
+            FIELD_EDITING_COLOR
+        } else {
+            FIELD_COLOR
+        };
+        if color.0 != value {
+            color.0 = value;
+        }
+    }
+
+    for (label, mut text) in picker_labels.iter_mut() {
+        let value = match label.0 {
+            PickerKind::OutputDevice => audio
+                .output_device
+                .as_deref()
+                .unwrap_or(DEFAULT_DEVICE_LABEL)
+                .to_string(),
+            PickerKind::CaptureDevice => audio
+                .capture_device
+                .as_deref()
+                .unwrap_or(DEFAULT_DEVICE_LABEL)
+                .to_string(),
+            PickerKind::AudioBitrate => format!("{} kbps", audio.audio_bitrate_kbps),
+            PickerKind::WindowMode => video.window_mode.label().to_string(),
+            PickerKind::Resolution => {
+                let (width, height) = video.resolution;
+                format!("{} x {}", width, height)
+            }
+            PickerKind::StreamLatency => video.stream_latency.label().to_string(),
+            PickerKind::UploadLimit => video.upload_limit.label().to_string(),
+            PickerKind::ClientTimeout => duration_label(session.client_timeout_secs),
+            PickerKind::AfkKick if session.afk_kick_mins == 0 => "Never".to_string(),
+            PickerKind::AfkKick => duration_label(session.afk_kick_mins * 60),
+            PickerKind::RejoinGrace => duration_label(session.rejoin_grace_secs),
+        };
+        if text.0 != value {
+            text.0 = value;
+        }
+    }
+}
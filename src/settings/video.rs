@@ -0,0 +1,362 @@
+//! Display settings: field of view, vsync, frame limit, window mode and
+//! resolution, applied to the window and player camera whenever they change,
+//! whether the stream quality indicator shows, how much the stream is
+//! buffered and how much of the host's upload it may use. The view also
+//! widens a little while sprinting. While the window is minimized or covered
+//! the cameras stop rendering and the app ticks at a low steady rate, which
+//! still keeps the session and the stream going.
+
+use bevy::prelude::*;
+use bevy::window::{MonitorSelection, PresentMode, PrimaryWindow, WindowMode, WindowOccluded};
+use bevy::winit::{UpdateMode, WinitSettings};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::player::{Player, PlayerCamera, Sprinting};
+use crate::screen::video_encoder::StreamQuality;
+
+/// Range the field of view can be set within, in degrees.
+pub const MIN_FOV: f32 = 60.0;
+pub const MAX_FOV: f32 = 110.0;
+/// About what Bevy's default vertical angle gives in a 16:9 window.
+const DEFAULT_FOV: f32 = 75.0;
+/// Change in the vertical angle, in radians, small enough to leave alone.
+const FOV_EPSILON: f32 = 1e-4;
+/// How much wider the view gets while sprinting, in degrees.
+const SPRINT_FOV_KICK: f32 = 6.0;
+/// Rate the view widens and narrows with sprinting, per second.
+const SPRINT_FOV_SMOOTHING: f32 = 6.0;
+
+/// Range the frame limit can be set within, in frames per second. Past the
+/// top of the range frames are unlimited.
+pub const MIN_FRAME_LIMIT: u32 = 30;
+pub const MAX_FRAME_LIMIT: u32 = 240;
+
+/// Time between ticks while the window is hidden, fast enough for
+/// networking, capture and encoding to keep up.
+const HIDDEN_TICK: Duration = Duration::from_millis(16);
+
+/// Share of the upload limit the video gets; the rest is left for stream
+/// audio, game updates and packet overhead.
+const UPLOAD_VIDEO_SHARE_PERCENT: u64 = 80;
+
+/// Lowest bitrate a limited stream is cut to however many watch, below
+/// which the picture falls apart.
+const MIN_STREAM_BITRATE_BPS: u32 = 500_000;
+
+/// Window sizes offered in the settings menu.
+pub const RESOLUTIONS: [(u32, u32); 6] = [
+    (1280, 720),
+    (1366, 768),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+
+/// How the game window is shown.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum WindowModeSetting {
+    #[default]
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl WindowModeSetting {
+    pub const ALL: [WindowModeSetting; 3] = [
+        WindowModeSetting::Windowed,
+        WindowModeSetting::Borderless,
+        WindowModeSetting::Fullscreen,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            WindowModeSetting::Windowed => "Windowed",
+            WindowModeSetting::Borderless => "Borderless fullscreen",
+            WindowModeSetting::Fullscreen => "Fullscreen",
+        }
+    }
+
+    fn window_mode(self) -> WindowMode {
+        match self {
+            WindowModeSetting::Windowed => WindowMode::Windowed,
+            WindowModeSetting::Borderless => {
+                WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+            }
+            WindowModeSetting::Fullscreen => WindowMode::Fullscreen(MonitorSelection::Current),
+        }
+    }
+}
+
+/// Trade-off between delay and smoothness when watching a stream. Either
+/// way the buffer grows and shrinks with how unevenly frames arrive.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum StreamLatency {
+    /// Show frames as soon as possible, stuttering on a shaky connection.
+    #[default]
+    Low,
+    /// Hold frames back long enough to play evenly through jitter.
+    Smooth,
+}
+
+impl StreamLatency {
+    pub const ALL: [StreamLatency; 2] = [StreamLatency::Low, StreamLatency::Smooth];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StreamLatency::Low => "Low latency",
+            StreamLatency::Smooth => "Smooth",
+        }
+    }
+}
+
+/// Most of the host's upload the stream may use, for hosting over Wi-Fi or a
+/// metered connection. Each viewer is sent their own copy, so the more
+/// watch the lower the bitrate, and lower limits also lower the size and
+/// frame rate, which looks better than a starved bitrate.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum UploadLimit {
+    #[default]
+    Unlimited,
+    Mbps10,
+    Mbps6,
+    Mbps3,
+}
+
+impl UploadLimit {
+    pub const ALL: [UploadLimit; 4] = [
+        UploadLimit::Unlimited,
+        UploadLimit::Mbps10,
+        UploadLimit::Mbps6,
+        UploadLimit::Mbps3,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            UploadLimit::Unlimited => "Unlimited",
+            UploadLimit::Mbps10 => "10 Mbps",
+            UploadLimit::Mbps6 => "6 Mbps",
+            UploadLimit::Mbps3 => "3 Mbps",
+        }
+    }
+
+    /// The limit in bits per second, `None` when unlimited.
+    pub fn bps(self) -> Option<u32> {
+        match self {
+            UploadLimit::Unlimited => None,
+            UploadLimit::Mbps10 => Some(10_000_000),
+            UploadLimit::Mbps6 => Some(6_000_000),
+            UploadLimit::Mbps3 => Some(3_000_000),
+        }
+    }
+
+    /// Quality to encode the stream at with `viewers` watching.
+    pub fn stream_quality(self, viewers: usize) -> StreamQuality {
+        let Some(limit) = self.bps() else {
+            return StreamQuality::default();
+        };
+        let (max_height, fps) = match self {
+            UploadLimit::Mbps10 => (1080, 60),
+            UploadLimit::Mbps6 => (1080, 30),
+            _ => (720, 30),
+        };
+        let share = limit as u64 * UPLOAD_VIDEO_SHARE_PERCENT / 100 / viewers.max(1) as u64;
+        let bitrate_bps =
+            (share as u32).clamp(MIN_STREAM_BITRATE_BPS, StreamQuality::default().bitrate_bps);
+        StreamQuality {
+            bitrate_bps,
+            max_height,
+            fps,
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct VideoSettings {
+    /// Horizontal field of view of the player camera, in degrees.
+    pub fov: f32,
+    /// Bob the first-person view with the player's steps.
+    pub head_bob: bool,
+    /// Ease the view towards where the player looks, rather than snapping.
+    pub camera_smoothing: bool,
+    pub vsync: bool,
+    /// Most frames rendered per second, 0 for no limit. Leaves CPU time to
+    /// capture and encoding while hosting.
+    pub frame_limit: u32,
+    pub window_mode: WindowModeSetting,
+    /// Window size when windowed, in logical pixels.
+    pub resolution: (u32, u32),
+    /// Show the stream quality indicator while watching a stream.
+    pub show_stream_stats: bool,
+    /// How much the stream is buffered against uneven frame delivery.
+    pub stream_latency: StreamLatency,
+    /// Seconds the host's stream encoder is kept without frames to encode,
+    /// e.g. with nobody watching, before it's shut down. 0 keeps it running.
+    pub encoder_idle_secs: u32,
+    /// Most of the host's upload the stream may use.
+    pub upload_limit: UploadLimit,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            fov: DEFAULT_FOV,
+            head_bob: false,
+            camera_smoothing: false,
+            vsync: false,
+            frame_limit: 0,
+            window_mode: WindowModeSetting::Windowed,
+            resolution: (1280, 720),
+            show_stream_stats: false,
+            stream_latency: StreamLatency::Low,
+            encoder_idle_secs: 30,
+            upload_limit: UploadLimit::Unlimited,
+        }
+    }
+}
+
+/// System to apply vsync, window mode and resolution to the window. Only
+/// what changed is applied, so other settings don't undo the player resizing
+/// the window.
+pub fn apply_window_settings(
+    settings: Res<VideoSettings>,
+    mut applied: Local<Option<VideoSettings>>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    if applied.as_ref() == Some(&*settings) {
+        return;
+    }
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+    let previous = applied.replace(settings.clone());
+
+    if previous.as_ref().map(|p| p.vsync) != Some(settings.vsync) {
+        // No vsync by default: waiting on the display fights with capture and encoding
+        window.present_mode = if settings.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+    }
+    if previous.as_ref().map(|p| p.window_mode) != Some(settings.window_mode) {
+        window.mode = settings.window_mode.window_mode();
+    }
+    if previous.as_ref().map(|p| p.resolution) != Some(settings.resolution) {
+        let (width, height) = settings.resolution;
+        window.resolution.set(width as f32, height as f32);
+    }
+}
+
+/// Resource for whether the window is hidden, and the cameras switched off
+/// while it is.
+#[derive(Resource, Default)]
+pub struct HiddenWindow {
+    /// The window is minimized or fully covered by other windows.
+    pub hidden: bool,
+    occluded: bool,
+    cameras: Vec<Entity>,
+}
+
+/// System to stop rendering and throttle the app's tick while the window is
+/// hidden, and undo it once the window shows again. Minimized windows either
+/// report being occluded or shrink to nothing, depending on the platform.
+pub fn throttle_hidden_window(
+    mut events: EventReader<WindowOccluded>,
+    windows: Query<(Entity, &Window), With<PrimaryWindow>>,
+    mut hidden: ResMut<HiddenWindow>,
+    mut winit: ResMut<WinitSettings>,
+    mut cameras: Query<(Entity, &mut Camera)>,
+) {
+    let Ok((entity, window)) = windows.get_single() else {
+        return;
+    };
+    for event in events.read().filter(|event| event.window == entity) {
+        hidden.occluded = event.occluded;
+    }
+    let now_hidden =
+        hidden.occluded || window.physical_width() == 0 || window.physical_height() == 0;
+    if now_hidden == hidden.hidden {
+        return;
+    }
+    hidden.hidden = now_hidden;
+
+    if now_hidden {
+        info!("Window hidden, pausing rendering");
+        winit.unfocused_mode = UpdateMode::reactive_low_power(HIDDEN_TICK);
+        hidden.cameras = cameras
+            .iter_mut()
+            .filter(|(_, camera)| camera.is_active)
+            .map(|(entity, mut camera)| {
+                camera.is_active = false;
+                entity
+            })
+            .collect();
+    } else {
+        info!("Window shown, resuming rendering");
+        winit.unfocused_mode = UpdateMode::Continuous;
+        for entity in std::mem::take(&mut hidden.cameras) {
+            if let Ok((_, mut camera)) = cameras.get_mut(entity) {
+                camera.is_active = true;
+            }
+        }
+    }
+}
+
+/// System to hold frames to the frame limit by sleeping off what's left of
+/// each frame's time. Runs last, so the sleep covers the whole frame.
+pub fn limit_frame_rate(settings: Res<VideoSettings>, mut frame_start: Local<Option<Instant>>) {
+    if let Some(start) = frame_start.filter(|_| settings.frame_limit > 0) {
+        let budget = Duration::from_secs_f64(1.0 / settings.frame_limit as f64);
+        if let Some(left) = budget.checked_sub(start.elapsed()) {
+            std::thread::sleep(left);
+        }
+    }
+    *frame_start = Some(Instant::now());
+}
+
+/// System to keep the player camera's field of view at the setting, eased
+/// wider while sprinting. The setting is horizontal, so the view doesn't
+/// narrow on wider windows, and the vertical angle the projection takes
+/// follows the aspect ratio.
+pub fn apply_camera_fov(
+    time: Res<Time>,
+    settings: Res<VideoSettings>,
+    player_query: Query<Has<Sprinting>, With<Player>>,
+    mut sprint_kick: Local<f32>,
+    mut cameras: Query<&mut Projection, With<PlayerCamera>>,
+) {
+    let sprinting = player_query.get_single().unwrap_or(false);
+    let target = if sprinting { SPRINT_FOV_KICK } else { 0.0 };
+    *sprint_kick = sprint_kick.lerp(target, (SPRINT_FOV_SMOOTHING * time.delta_secs()).min(1.0));
+
+    let horizontal = (settings.fov.clamp(MIN_FOV, MAX_FOV) + *sprint_kick).to_radians();
+    for mut projection in cameras.iter_mut() {
+        let Projection::Perspective(perspective) = &*projection else {
+            continue;
+        };
+        let aspect_ratio = perspective.aspect_ratio.max(f32::EPSILON);
+        let fov = 2.0 * ((horizontal / 2.0).tan() / aspect_ratio).atan();
+        if (perspective.fov - fov).abs() > FOV_EPSILON {
+            if let Projection::Perspective(perspective) = projection.as_mut() {
+                perspective.fov = fov;
+            }
+        }
+    }
+}
+
+/// Console command setting the stream's quality by the upload limit that
+/// gives it, the same as picking the limit in the settings menu.
+pub fn quality_command(In(args): In<Vec<String>>, mut settings: ResMut<VideoSettings>) -> String {
+    let limit = match args.first().map(String::as_str) {
+        Some("native") => UploadLimit::Unlimited,
+        Some("1080p60") => UploadLimit::Mbps10,
+        Some("1080p30") => UploadLimit::Mbps6,
+        Some("720p") => UploadLimit::Mbps3,
+        _ => return "Usage: quality <native|1080p60|1080p30|720p>".to_string(),
+    };
+    settings.upload_limit = limit;
+    format!("Upload limit set to {}", limit.label())
+}
@@ -0,0 +1,37 @@
+pub mod synth;
+pub mod systems;
+
+use bevy::audio::AddAudioSource;
+use bevy::prelude::*;
+
+use crate::game_state::AppState;
+use synth::SynthSound;
+use systems::*;
+
+/// Handles to the generated sound effects.
+#[derive(Resource)]
+pub struct SoundEffects {
+    pub footsteps: [Handle<SynthSound>; 4],
+    pub jump: Handle<SynthSound>,
+    pub land: Handle<SynthSound>,
+    pub ui_hover: Handle<SynthSound>,
+    pub ui_click: Handle<SynthSound>,
+    pub join: Handle<SynthSound>,
+    pub leave: Handle<SynthSound>,
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<SynthSound>()
+            .init_resource::<ChimeSettle>()
+            .add_systems(Startup, setup_sound_effects)
+            .add_systems(OnEnter(AppState::InGame), start_chime_settle)
+            .add_systems(Update, play_ui_sounds)
+            .add_systems(
+                Update,
+                (play_movement_sounds, play_join_leave_chimes).run_if(in_state(AppState::InGame)),
+            );
+    }
+}
@@ -0,0 +1,143 @@
+use bevy::audio::{Decodable, Source};
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Sample rate sound effects are generated at.
+const SAMPLE_RATE: u32 = 44100;
+
+/// A short mono sound effect synthesized at startup, so the game ships without
+/// audio asset files.
+#[derive(Asset, TypePath, Clone)]
+pub struct SynthSound {
+    samples: Arc<[f32]>,
+}
+
+impl Decodable for SynthSound {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        SynthDecoder {
+            samples: self.samples.clone(),
+            position: 0,
+        }
+    }
+}
+
+/// Plays back the samples of a [`SynthSound`].
+pub struct SynthDecoder {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+/// Render `duration` seconds of audio from a function of time.
+fn render(duration: f32, mut f: impl FnMut(f32) -> f32) -> SynthSound {
+    let len = (duration * SAMPLE_RATE as f32) as usize;
+    let samples = (0..len)
+        .map(|i| f(i as f32 / SAMPLE_RATE as f32).clamp(-1.0, 1.0))
+        .collect();
+    SynthSound { samples }
+}
+
+/// Deterministic white noise source (xorshift).
+struct Noise(u32);
+
+impl Noise {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+/// Soft, low-passed noise burst. `seed` varies the texture between steps.
+pub fn footstep(seed: u32) -> SynthSound {
+    let mut noise = Noise(seed.max(1));
+    let mut low = 0.0;
+    render(0.09, |t| {
+        low += (noise.next() - low) * 0.15;
+        let thump = (TAU * 70.0 * t).sin() * 0.5;
+        (low * 2.0 + thump) * (-t * 45.0).exp() * 0.5
+    })
+}
+
+/// Quick upward sweep.
+pub fn jump() -> SynthSound {
+    let mut phase = 0.0;
+    render(0.14, |t| {
+        let freq = 250.0 + 1800.0 * t;
+        phase += TAU * freq / SAMPLE_RATE as f32;
+        phase.sin() * (-t * 20.0).exp() * 0.25
+    })
+}
+
+/// Low thud with a bit of grit.
+pub fn land() -> SynthSound {
+    let mut noise = Noise(0x5eed);
+    let mut low = 0.0;
+    let mut phase = 0.0;
+    render(0.16, |t| {
+        let freq = 90.0 - 150.0 * t;
+        phase += TAU * freq / SAMPLE_RATE as f32;
+        low += (noise.next() - low) * 0.08;
+        (phase.sin() * 0.8 + low) * (-t * 30.0).exp() * 0.6
+    })
+}
+
+/// Very short tick for hovering buttons.
+pub fn ui_hover() -> SynthSound {
+    render(0.025, |t| (TAU * 2400.0 * t).sin() * (-t * 250.0).exp() * 0.15)
+}
+
+/// Click for pressing buttons.
+pub fn ui_click() -> SynthSound {
+    render(0.06, |t| {
+        let tone = (TAU * 1200.0 * t).sin() + (TAU * 1800.0 * t).sin() * 0.5;
+        tone * (-t * 70.0).exp() * 0.2
+    })
+}
+
+/// Two-note chime; rising for joins, falling for leaves.
+pub fn chime(rising: bool) -> SynthSound {
+    let (first, second) = if rising { (660.0, 880.0) } else { (880.0, 660.0) };
+    const NOTE: f32 = 0.16;
+    render(NOTE * 2.0 + 0.3, |t| {
+        let (freq, local) = if t < NOTE { (first, t) } else { (second, t - NOTE) };
+        let tone = (TAU * freq * t).sin() + (TAU * freq * 2.0 * t).sin() * 0.25;
+        let attack = (local * 200.0).min(1.0);
+        tone * attack * (-local * 8.0).exp() * 0.2
+    })
+}
@@ -0,0 +1,134 @@
+use bevy::audio::Volume;
+use bevy::prelude::*;
+
+use super::synth::{self, SynthSound};
+use super::SoundEffects;
+use crate::network::protocol::RemotePlayer;
+use crate::player::{Grounded, Player, Velocity};
+
+/// Time between footsteps at walking speed.
+const STEP_INTERVAL: f32 = 0.42;
+
+/// Horizontal speed below which the player counts as standing still.
+const MIN_STEP_SPEED: f32 = 0.5;
+
+/// Seconds after entering a session before join and leave chimes play, so
+/// the players already there don't chime as they appear.
+const CHIME_SETTLE_SECS: f32 = 2.0;
+
+/// Resource counting down from entering a session until chimes play.
+#[derive(Resource, Default)]
+pub struct ChimeSettle(Timer);
+
+pub fn start_chime_settle(mut settle: ResMut<ChimeSettle>) {
+    settle.0 = Timer::from_seconds(CHIME_SETTLE_SECS, TimerMode::Once);
+}
+
+pub fn setup_sound_effects(mut commands: Commands, mut sounds: ResMut<Assets<SynthSound>>) {
+    commands.insert_resource(SoundEffects {
+        footsteps: [1, 2, 3, 4].map(|seed| sounds.add(synth::footstep(seed * 7919))),
+        jump: sounds.add(synth::jump()),
+        land: sounds.add(synth::land()),
+        ui_hover: sounds.add(synth::ui_hover()),
+        ui_click: sounds.add(synth::ui_click()),
+        join: sounds.add(synth::chime(true)),
+        leave: sounds.add(synth::chime(false)),
+    });
+}
+
+/// Spawn a one-shot sound that despawns when finished.
+fn play(commands: &mut Commands, sound: &Handle<SynthSound>, volume: f32) {
+    commands.spawn((
+        AudioPlayer(sound.clone()),
+        PlaybackSettings::DESPAWN.with_volume(Volume::new(volume)),
+    ));
+}
+
+/// Hover and click sounds for every UI button.
+pub fn play_ui_sounds(
+    mut commands: Commands,
+    effects: Option<Res<SoundEffects>>,
+    query: Query<&Interaction, (Changed<Interaction>, With<Button>)>,
+) {
+    let Some(effects) = effects else { return };
+
+    for interaction in query.iter() {
+        match interaction {
+            Interaction::Hovered => play(&mut commands, &effects.ui_hover, 0.6),
+            Interaction::Pressed => play(&mut commands, &effects.ui_click, 0.8),
+            Interaction::None => {}
+        }
+    }
+}
+
+/// Footstep timing and airborne tracking for the local player.
+#[derive(Default)]
+pub struct MovementSoundState {
+    step_timer: f32,
+    next_step: usize,
+    airborne: bool,
+}
+
+/// Footsteps while walking on the ground, plus jump and landing sounds.
+pub fn play_movement_sounds(
+    mut commands: Commands,
+    time: Res<Time>,
+    effects: Option<Res<SoundEffects>>,
+    query: Query<(&Velocity, &Grounded), With<Player>>,
+    mut state: Local<MovementSoundState>,
+) {
+    let Some(effects) = effects else { return };
+    let Ok((velocity, grounded)) = query.get_single() else {
+        return;
+    };
+
+    let grounded = grounded.0;
+    if state.airborne && grounded {
+        play(&mut commands, &effects.land, 0.7);
+        state.step_timer = STEP_INTERVAL;
+    } else if !state.airborne && !grounded && velocity.0.y > 0.0 {
+        play(&mut commands, &effects.jump, 0.5);
+    }
+    state.airborne = !grounded;
+
+    let speed = Vec2::new(velocity.0.x, velocity.0.z).length();
+    if !grounded || speed < MIN_STEP_SPEED {
+        // First step comes shortly after starting to walk
+        state.step_timer = STEP_INTERVAL * 0.3;
+        return;
+    }
+
+    state.step_timer -= time.delta_secs();
+    if state.step_timer <= 0.0 {
+        let step = state.next_step % effects.footsteps.len();
+        play(&mut commands, &effects.footsteps[step], 0.5);
+        state.next_step += 1;
+        state.step_timer += STEP_INTERVAL;
+    }
+}
+
+/// Chime when another player joins or leaves the session. Quiet while
+/// settling in, when the players already there appear, and the removals
+/// from leaving the last session are only drained.
+pub fn play_join_leave_chimes(
+    mut commands: Commands,
+    time: Res<Time>,
+    effects: Option<Res<SoundEffects>>,
+    mut settle: ResMut<ChimeSettle>,
+    joined: Query<(), Added<RemotePlayer>>,
+    mut left: RemovedComponents<RemotePlayer>,
+) {
+    let anyone_left = left.read().count() > 0;
+    settle.0.tick(time.delta());
+    if !settle.0.finished() {
+        return;
+    }
+    let Some(effects) = effects else { return };
+
+    if !joined.is_empty() {
+        play(&mut commands, &effects.join, 0.6);
+    }
+    if anyone_left {
+        play(&mut commands, &effects.leave, 0.6);
+    }
+}
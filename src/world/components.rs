@@ -1,16 +1,44 @@
 use bevy::prelude::*;
 
+use crate::controls::Action;
+use crate::network::protocol::ScreenId;
+
 /// Marker for all world entities (room, lights, player, etc.) for cleanup.
 #[derive(Component)]
 pub struct WorldEntity;
 
-/// Marker for the cinema screen.
+/// A cinema screen. Its frame and control button are siblings under the same parent.
+#[derive(Component)]
+pub struct Screen {
+    pub id: ScreenId,
+}
+
+/// The control button for the screen with the given id.
 #[derive(Component)]
-pub struct Screen;
+pub struct ScreenControlButton {
+    pub screen: ScreenId,
+}
+
+/// A room light, dimmed while a stream is playing.
+#[derive(Component)]
+pub struct RoomLight {
+    /// Intensity with the house lights up.
+    pub base_intensity: f32,
+}
+
+/// Light in front of a screen, tinted with the colors it shows.
+#[derive(Component)]
+pub struct ScreenGlow {
+    pub screen: ScreenId,
+}
 
-/// Marker for the screen control button.
+/// A seat the player can sit in by pressing E while looking at it.
 #[derive(Component)]
-pub struct ScreenControlButton;
+pub struct Seat;
+
+/// Where players appear in a room loaded from glTF.
+#[derive(Component)]
+pub struct SpawnPoint;
 
 /// Identifies which side of the screen frame this entity represents.
 #[derive(Component, Clone, Copy, PartialEq, Eq)]
@@ -26,4 +54,48 @@ pub enum ScreenFrame {
 pub struct Interactable {
     pub hover_color: Color,
     pub normal_color: Color,
+    /// What the crosshair says while looking at it.
+    pub prompt: InteractionPrompt,
+}
+
+/// Input that uses an interactable.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PromptInput {
+    RightClick,
+    /// The key bound to an action, so the prompt follows rebinding.
+    Key(Action),
+}
+
+/// Prompt shown next to the crosshair, e.g. "E: Sit".
+#[derive(Clone, Copy)]
+pub struct InteractionPrompt {
+    pub input: PromptInput,
+    pub label: &'static str,
+    /// Only the host can use it, so clients see no prompt.
+    pub host_only: bool,
+}
+
+impl InteractionPrompt {
+    pub const fn key(action: Action, label: &'static str) -> Self {
+        Self {
+            input: PromptInput::Key(action),
+            label,
+            host_only: false,
+        }
+    }
+
+    pub const fn right_click(label: &'static str) -> Self {
+        Self {
+            input: PromptInput::RightClick,
+            label,
+            host_only: false,
+        }
+    }
+
+    pub const fn host_only(self) -> Self {
+        Self {
+            host_only: true,
+            ..self
+        }
+    }
 }
@@ -1,9 +1,21 @@
 use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use super::components::{Interactable, PromptInput};
+use super::interaction::LookingAt;
+use crate::controls::bindings::key_name;
+use crate::controls::{Action, KeyBindings};
+use crate::network::server::GameServer;
+use crate::player::{Player, Seated};
 
 /// Marker for the crosshair UI element.
 #[derive(Component)]
 pub struct Crosshair;
 
+/// Text under the crosshair saying what the interactable in view does.
+#[derive(Component)]
+pub struct InteractionPromptText;
+
 pub fn setup_crosshair(mut commands: Commands) {
     // Crosshair container (centered on screen)
     commands
@@ -28,9 +40,74 @@ pub fn setup_crosshair(mut commands: Commands) {
                 },
                 BackgroundColor(Color::srgba(1.0, 1.0, 1.0, 0.7)),
             ));
+
+            // Interaction prompt, just below the dot without moving it
+            parent
+                .spawn(Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(50.0),
+                    width: Val::Percent(100.0),
+                    margin: UiRect::top(Val::Px(18.0)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                })
+                .with_child((
+                    InteractionPromptText,
+                    Text::new(""),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgba(1.0, 1.0, 1.0, 0.85)),
+                    Visibility::Hidden,
+                ));
         });
 }
 
+/// System to show the prompt of the interactable under the crosshair.
+pub fn update_interaction_prompt(
+    looking_at: Res<LookingAt>,
+    bindings: Res<KeyBindings>,
+    server: Option<Res<GameServer>>,
+    interactables: Query<&Interactable>,
+    seated_player: Query<(), (With<Player>, With<Seated>)>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut prompt_query: Query<(&mut Text, &mut Visibility), With<InteractionPromptText>>,
+) {
+    // Interactions only work while the cursor is grabbed
+    let grabbed = windows
+        .get_single()
+        .is_ok_and(|window| !window.cursor_options.visible);
+    let prompt = looking_at
+        .entity
+        .filter(|_| grabbed)
+        .and_then(|entity| interactables.get(entity).ok())
+        .map(|interactable| interactable.prompt)
+        .filter(|prompt| !prompt.host_only || server.is_some())
+        // Interact stands up while seated, so nothing else can be used with it
+        .filter(|prompt| {
+            prompt.input != PromptInput::Key(Action::Interact) || seated_player.is_empty()
+        });
+
+    let Ok((mut text, mut visibility)) = prompt_query.get_single_mut() else {
+        return;
+    };
+    let Some(prompt) = prompt else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+
+    let input = match prompt.input {
+        PromptInput::RightClick => "Right-click".to_string(),
+        PromptInput::Key(action) => key_name(bindings.key(action)),
+    };
+    let value = format!("{}: {}", input, prompt.label);
+    if text.0 != value {
+        text.0 = value;
+    }
+    visibility.set_if_neq(Visibility::Inherited);
+}
+
 pub fn cleanup_crosshair(mut commands: Commands, query: Query<Entity, With<Crosshair>>) {
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive();
@@ -1,19 +1,28 @@
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
+use bevy_rapier3d::prelude::*;
 
-use super::components::{Interactable, ScreenControlButton};
+use super::components::{Interactable, ScreenControlButton, Seat};
+use crate::controls::{Action, KeyBindings, PlayerInput};
+use crate::network::protocol::ScreenId;
 use crate::network::server::GameServer;
+use crate::player::{CameraController, Player, Seated, Velocity, SEATED_EYE_HEIGHT};
 
 /// Resource tracking what the player is currently looking at.
 #[derive(Resource, Default)]
 pub struct LookingAt {
+    /// First collider hit by the crosshair ray, if within reach.
     pub entity: Option<Entity>,
     pub distance: f32,
+    /// World-space point where the ray hit.
+    pub point: Vec3,
 }
 
-/// Event fired when the screen control button is activated.
+/// Event fired when a screen's control button is activated.
 #[derive(Event)]
-pub struct ScreenControlEvent;
+pub struct ScreenControlEvent {
+    pub screen: ScreenId,
+}
 
 /// Maximum interaction distance.
 const INTERACTION_DISTANCE: f32 = 4.0;
@@ -22,7 +31,9 @@ const INTERACTION_DISTANCE: f32 = 4.0;
 pub fn update_looking_at(
     mut looking_at: ResMut<LookingAt>,
     camera_query: Query<&GlobalTransform, With<Camera3d>>,
-    interactables: Query<(Entity, &GlobalTransform), With<Interactable>>,
+    rapier_context: ReadDefaultRapierContext,
+    parents: Query<&Parent>,
+    interactables: Query<(), With<Interactable>>,
 ) {
     let Ok(camera_transform) = camera_query.get_single() else {
         return;
@@ -31,33 +42,28 @@ pub fn update_looking_at(
     let ray_origin = camera_transform.translation();
     let ray_direction = camera_transform.forward().as_vec3();
 
-    // Simple distance-based check for now (proper raycasting would use bevy_rapier or similar)
-    // We'll check against interactable positions with a tolerance
-    let mut closest: Option<(Entity, f32)> = None;
-
-    for (entity, transform) in interactables.iter() {
-        let to_object = transform.translation() - ray_origin;
-        let distance_along_ray = to_object.dot(ray_direction);
-
-        if distance_along_ray < 0.0 || distance_along_ray > INTERACTION_DISTANCE {
-            continue;
-        }
-
-        // Point on ray closest to the object center
-        let closest_point = ray_origin + ray_direction * distance_along_ray;
-        let distance_to_center = (transform.translation() - closest_point).length();
-
-        // Use a simple sphere approximation for hit detection
-        let hit_radius = 0.4; // Generous hit box
-        if distance_to_center < hit_radius {
-            if closest.is_none() || distance_along_ray < closest.unwrap().1 {
-                closest = Some((entity, distance_along_ray));
-            }
-        }
-    }
-
-    looking_at.entity = closest.map(|(e, _)| e);
-    looking_at.distance = closest.map(|(_, d)| d).unwrap_or(0.0);
+    // The player body is a controller shape rather than a collider, so the ray
+    // can't hit ourselves. Walls and the screen block anything behind them.
+    let hit = rapier_context.single().cast_ray(
+        ray_origin,
+        ray_direction,
+        INTERACTION_DISTANCE,
+        true,
+        QueryFilter::default().exclude_sensors(),
+    );
+
+    // Colliders generated for glTF rooms sit on mesh entities below the tagged
+    // node, so report the interactable ancestor when there is one.
+    looking_at.entity = hit.map(|(e, _)| {
+        std::iter::once(e)
+            .chain(parents.iter_ancestors(e))
+            .find(|&ancestor| interactables.contains(ancestor))
+            .unwrap_or(e)
+    });
+    looking_at.distance = hit.map(|(_, d)| d).unwrap_or(0.0);
+    looking_at.point = hit
+        .map(|(_, d)| ray_origin + ray_direction * d)
+        .unwrap_or(Vec3::ZERO);
 }
 
 /// System to highlight interactables when looking at them.
@@ -77,11 +83,12 @@ pub fn highlight_interactables(
     }
 }
 
-/// System to handle right-click interaction on the screen control button (host only).
+/// System to handle right-click (or Select on a gamepad) interaction on the
+/// screen control button (host only).
 pub fn handle_screen_control_interaction(
-    mouse_input: Res<ButtonInput<MouseButton>>,
+    input: Res<PlayerInput>,
     looking_at: Res<LookingAt>,
-    button_query: Query<Entity, With<ScreenControlButton>>,
+    button_query: Query<&ScreenControlButton>,
     server: Option<Res<GameServer>>,
     mut event_writer: EventWriter<ScreenControlEvent>,
     windows: Query<&Window, With<PrimaryWindow>>,
@@ -99,16 +106,90 @@ pub fn handle_screen_control_interaction(
         return;
     }
 
-    if mouse_input.just_pressed(MouseButton::Right) {
+    if input.screen_control {
         if let Some(looking_entity) = looking_at.entity {
-            if button_query.get(looking_entity).is_ok() {
-                info!("Screen control button activated!");
-                event_writer.send(ScreenControlEvent);
+            if let Ok(button) = button_query.get(looking_entity) {
+                info!(
+                    "Screen control button activated for screen {}",
+                    button.screen
+                );
+                event_writer.send(ScreenControlEvent {
+                    screen: button.screen,
+                });
             }
         }
     }
 }
 
+/// System to sit down in the seat under the crosshair, or stand back up, with E.
+pub fn handle_seat_interaction(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    looking_at: Res<LookingAt>,
+    seat_query: Query<&GlobalTransform, With<Seat>>,
+    mut player_query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut CameraController,
+            &mut Velocity,
+            &mut KinematicCharacterController,
+            Option<&Seated>,
+        ),
+        With<Player>,
+    >,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    if !bindings.just_pressed(Action::Interact, &keyboard_input) {
+        return;
+    }
+
+    // Only when cursor is grabbed (in game)
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if window.cursor_options.visible {
+        return;
+    }
+
+    let Ok((entity, mut transform, mut camera, mut velocity, mut controller, seated)) =
+        player_query.get_single_mut()
+    else {
+        return;
+    };
+
+    if let Some(seated) = seated {
+        transform.translation = seated.stand_position;
+        commands.entity(entity).remove::<Seated>();
+        return;
+    }
+
+    let Some(seat) = looking_at.entity else {
+        return;
+    };
+    let Ok(seat_transform) = seat_query.get(seat) else {
+        return;
+    };
+
+    commands.entity(entity).insert(Seated {
+        seat,
+        stand_position: transform.translation,
+    });
+
+    // Drop to eye level above the seat, facing the way it faces (-Z)
+    let seat_position = seat_transform.translation();
+    transform.translation = seat_position + Vec3::Y * SEATED_EYE_HEIGHT;
+    let (seat_yaw, _, _) = seat_transform
+        .compute_transform()
+        .rotation
+        .to_euler(EulerRot::YXZ);
+    camera.yaw = seat_yaw;
+    transform.rotation = Quat::from_euler(EulerRot::YXZ, camera.yaw, camera.pitch, 0.0);
+    velocity.0 = Vec3::ZERO;
+    controller.translation = None;
+}
+
 /// Temporary system to respond to screen control events (placeholder for future config UI).
 pub fn on_screen_control_event(mut events: EventReader<ScreenControlEvent>) {
     for _ in events.read() {
@@ -1,47 +1,123 @@
 pub mod components;
 pub mod crosshair;
 pub mod interaction;
+pub mod posters;
+pub mod room_scene;
+pub mod rooms;
+pub mod screen_editor;
 pub mod setup;
+pub mod skybox;
+pub mod whiteboard;
+pub mod zones;
 
 use bevy::prelude::*;
 
-pub use components::{Interactable, Screen, ScreenControlButton, ScreenFrame, WorldEntity};
+pub use components::{
+    RoomLight, Screen, ScreenControlButton, ScreenFrame, ScreenGlow, Seat, SpawnPoint,
+};
 pub use interaction::ScreenControlEvent;
+pub use posters::{PosterAssignments, PosterCache};
+pub use rooms::{CurrentRoom, RoomId};
+pub use screen_editor::ScreenLayout;
+pub use whiteboard::Whiteboard;
+pub use zones::DoorStates;
 
-use crate::game_state::AppState;
-use crosshair::{cleanup_crosshair, setup_crosshair};
+use crate::game_state::{AppState, PauseState};
+use crosshair::{cleanup_crosshair, setup_crosshair, update_interaction_prompt};
 use interaction::{
-    handle_screen_control_interaction, highlight_interactables, on_screen_control_event,
-    update_looking_at, LookingAt,
+    handle_screen_control_interaction, handle_seat_interaction, highlight_interactables,
+    on_screen_control_event, update_looking_at, LookingAt,
+};
+use posters::{
+    decode_poster_images, handle_dropped_poster_images, request_poster_images, reset_posters,
+    show_poster_images,
+};
+use room_scene::move_player_to_spawn_point;
+use screen_editor::{
+    apply_screen_layout, handle_screen_grab, move_grabbed_screen, reset_screen_layout,
+    toggle_screen_edit_mode, ScreenEditMode,
 };
 use setup::{cleanup_world, setup_world};
+use skybox::{apply_room_skybox, cleanup_skybox};
+use whiteboard::{
+    attach_whiteboard_texture, cleanup_whiteboard, draw_on_whiteboard, handle_whiteboard_clear,
+    rasterize_whiteboard, setup_whiteboard_texture,
+};
+use zones::{animate_doors, handle_door_interaction, reset_zones, track_player_zone, PlayerZone};
 
-// Room dimensions
-pub const ROOM_WIDTH: f32 = 10.0;
-pub const ROOM_DEPTH: f32 = 10.0;
-pub const ROOM_HEIGHT: f32 = 4.0;
 pub const WALL_THICKNESS: f32 = 0.2;
 
-// Room bounds for collision (slightly less than actual size to account for walls)
-pub const ROOM_HALF_WIDTH: f32 = 4.8;
-pub const ROOM_HALF_DEPTH: f32 = 4.8;
-
 pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<LookingAt>()
+            .init_resource::<CurrentRoom>()
+            .init_resource::<ScreenEditMode>()
+            .init_resource::<ScreenLayout>()
+            .init_resource::<Whiteboard>()
+            .init_resource::<DoorStates>()
+            .init_resource::<PlayerZone>()
+            .init_resource::<PosterAssignments>()
+            .init_resource::<PosterCache>()
             .add_event::<ScreenControlEvent>()
-            .add_systems(OnEnter(AppState::InGame), (setup_world, setup_crosshair))
-            .add_systems(OnExit(AppState::InGame), (cleanup_world, cleanup_crosshair))
+            .add_systems(
+                OnEnter(AppState::InGame),
+                (setup_world, setup_crosshair, setup_whiteboard_texture),
+            )
+            .add_systems(
+                OnExit(AppState::InGame),
+                (
+                    cleanup_world,
+                    cleanup_crosshair,
+                    reset_screen_layout,
+                    cleanup_whiteboard,
+                    reset_zones,
+                    cleanup_skybox,
+                    reset_posters,
+                ),
+            )
             .add_systems(
                 Update,
                 (
+                    move_player_to_spawn_point,
                     update_looking_at,
                     highlight_interactables,
-                    handle_screen_control_interaction,
+                    update_interaction_prompt.after(update_looking_at),
                     on_screen_control_event,
+                    apply_screen_layout,
+                    attach_whiteboard_texture,
+                    rasterize_whiteboard.after(draw_on_whiteboard),
+                    animate_doors,
+                    track_player_zone,
+                    apply_room_skybox,
+                )
+                    .run_if(in_state(AppState::InGame)),
+            )
+            // What the local player does with the room waits while paused
+            .add_systems(
+                Update,
+                (
+                    handle_screen_control_interaction,
+                    handle_seat_interaction,
+                    toggle_screen_edit_mode,
+                    handle_screen_grab.after(update_looking_at),
+                    move_grabbed_screen.after(handle_screen_grab),
+                    draw_on_whiteboard.after(update_looking_at),
+                    handle_whiteboard_clear,
+                    handle_door_interaction,
+                )
+                    .run_if(in_state(PauseState::Running)),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_dropped_poster_images,
+                    request_poster_images,
+                    decode_poster_images,
+                    show_poster_images,
                 )
+                    .chain()
                     .run_if(in_state(AppState::InGame)),
             );
     }
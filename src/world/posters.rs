@@ -0,0 +1,396 @@
+//! Poster frames showing images the host hangs in them.
+//!
+//! The host drops a PNG or JPEG file onto the window to hang it in the poster
+//! frame under the crosshair, or in the first empty frame otherwise. The host
+//! tells clients which image hangs where by the hash of its file, and clients
+//! fetch files they don't have in chunks, like video frames. Files stay cached
+//! by hash while the game runs, so they're only fetched once.
+
+use bevy::image::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy_rapier3d::prelude::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::interaction::LookingAt;
+use crate::menu::NotificationEvent;
+use crate::network::protocol::{PosterAssignment, PosterId, PosterImageChunk, BASE64_CHUNK_SIZE};
+use crate::network::server::GameServer;
+
+pub const POSTER_WIDTH: f32 = 0.9;
+pub const POSTER_HEIGHT: f32 = 1.3;
+pub const POSTER_Y: f32 = 1.6;
+
+/// Size of poster file chunks on the network.
+const POSTER_CHUNK_SIZE: usize = BASE64_CHUNK_SIZE;
+
+/// Largest image file the host will hang, to keep transfers short.
+const MAX_POSTER_FILE_BYTES: usize = 2 * 1024 * 1024;
+
+/// Most chunks a client asks for at once, so the request fits in a packet.
+const MAX_REQUESTED_CHUNKS: usize = 64;
+
+/// How long a client waits on a file before asking for its missing chunks again.
+const REQUEST_RETRY: Duration = Duration::from_secs(1);
+
+/// The picture surface of a poster frame.
+#[derive(Component)]
+pub struct PosterFrame {
+    pub id: PosterId,
+    /// Hash of the image shown on it.
+    shown: Option<u64>,
+}
+
+/// Resource holding which image hangs in which poster frame. The host sets it
+/// and replicates it to clients.
+#[derive(Resource, Default)]
+pub struct PosterAssignments {
+    pub posters: Vec<PosterAssignment>,
+}
+
+impl PosterAssignments {
+    pub fn get(&self, poster: PosterId) -> Option<u64> {
+        self.posters
+            .iter()
+            .find(|p| p.poster == poster)
+            .map(|p| p.hash)
+    }
+
+    fn set(&mut self, poster: PosterId, hash: u64) {
+        match self.posters.iter_mut().find(|p| p.poster == poster) {
+            Some(existing) => existing.hash = hash,
+            None => self.posters.push(PosterAssignment { poster, hash }),
+        }
+    }
+}
+
+/// A poster image file, whole or still arriving.
+pub struct PosterFile {
+    chunks: Vec<Option<Vec<u8>>>,
+    /// The decoded image, once the file is whole.
+    pub image: Option<Handle<Image>>,
+    /// The file is whole but isn't an image we can show.
+    failed: bool,
+}
+
+impl PosterFile {
+    fn from_data(data: &[u8]) -> Self {
+        Self {
+            chunks: data
+                .chunks(POSTER_CHUNK_SIZE)
+                .map(|chunk| Some(chunk.to_vec()))
+                .collect(),
+            image: None,
+            failed: false,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.chunks.iter().all(Option::is_some)
+    }
+
+    fn missing_chunks(&self) -> Vec<u16> {
+        (0..self.chunks.len() as u16)
+            .filter(|&idx| self.chunks[idx as usize].is_none())
+            .collect()
+    }
+
+    /// Number of chunks the file is sent in.
+    pub fn total_chunks(&self) -> u16 {
+        self.chunks.len() as u16
+    }
+
+    /// A chunk of the file ready to send, if we have it.
+    pub fn chunk(&self, hash: u64, chunk_idx: u16) -> Option<PosterImageChunk> {
+        let data = self.chunks.get(chunk_idx as usize)?.as_ref()?;
+        Some(PosterImageChunk::new(
+            hash,
+            chunk_idx,
+            self.total_chunks(),
+            data,
+        ))
+    }
+}
+
+/// Resource caching poster image files by hash. It's kept across rooms.
+#[derive(Resource, Default)]
+pub struct PosterCache {
+    pub files: HashMap<u64, PosterFile>,
+    /// Chunks the local client wants from the host, waiting to be sent. An
+    /// empty list asks for the whole file.
+    pub requests: Vec<(u64, Vec<u16>)>,
+    /// When each file was last asked for or had a chunk arrive.
+    last_activity: HashMap<u64, Instant>,
+}
+
+impl PosterCache {
+    /// Store a chunk received from the host.
+    pub fn insert_chunk(&mut self, chunk: &PosterImageChunk) {
+        let Some(data) = chunk.decode_data() else {
+            return;
+        };
+        let file = self.files.entry(chunk.hash).or_insert_with(|| PosterFile {
+            chunks: vec![None; chunk.total_chunks as usize],
+            image: None,
+            failed: false,
+        });
+        if let Some(slot) = file.chunks.get_mut(chunk.chunk_idx as usize) {
+            *slot = Some(data);
+        }
+        self.last_activity.insert(chunk.hash, Instant::now());
+    }
+}
+
+/// Spawns an empty poster frame as children of `anchor`, centered on the
+/// anchor and facing its +Z axis.
+pub fn spawn_poster(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    anchor: Entity,
+    id: PosterId,
+) {
+    let frame_material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.25, 0.18, 0.1),
+        ..default()
+    });
+    let empty_material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.85, 0.83, 0.78),
+        ..default()
+    });
+
+    commands.entity(anchor).with_children(|parent| {
+        parent.spawn((
+            Mesh3d(meshes.add(Cuboid::new(POSTER_WIDTH + 0.1, POSTER_HEIGHT + 0.1, 0.04))),
+            MeshMaterial3d(frame_material),
+            Transform::from_xyz(0.0, 0.0, 0.02),
+        ));
+
+        // Picture, replaced by the hung image once it's available
+        parent.spawn((
+            PosterFrame { id, shown: None },
+            Mesh3d(meshes.add(Rectangle::new(POSTER_WIDTH, POSTER_HEIGHT))),
+            MeshMaterial3d(empty_material),
+            Transform::from_xyz(0.0, 0.0, 0.045),
+            RigidBody::Fixed,
+            Collider::cuboid(POSTER_WIDTH / 2.0, POSTER_HEIGHT / 2.0, 0.005),
+        ));
+    });
+}
+
+/// System to hang image files dropped onto the window in poster frames (host only).
+pub fn handle_dropped_poster_images(
+    mut events: EventReader<FileDragAndDrop>,
+    server: Option<Res<GameServer>>,
+    looking_at: Res<LookingAt>,
+    frames: Query<&PosterFrame>,
+    mut assignments: ResMut<PosterAssignments>,
+    mut cache: ResMut<PosterCache>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    for event in events.read() {
+        let FileDragAndDrop::DroppedFile { path_buf, .. } = event else {
+            continue;
+        };
+        if server.is_none() {
+            notifications.send(NotificationEvent(
+                "Only the host can hang posters".to_string(),
+            ));
+            continue;
+        }
+
+        // The frame under the crosshair, or else the first empty one
+        let mut empty_frames: Vec<PosterId> = frames
+            .iter()
+            .map(|frame| frame.id)
+            .filter(|&id| assignments.get(id).is_none())
+            .collect();
+        empty_frames.sort();
+        let target = looking_at
+            .entity
+            .and_then(|entity| frames.get(entity).ok())
+            .map(|frame| frame.id)
+            .or(empty_frames.first().copied());
+        let Some(poster) = target else {
+            notifications.send(NotificationEvent(
+                "Look at a poster frame to hang an image in it".to_string(),
+            ));
+            continue;
+        };
+
+        let data = match std::fs::read(path_buf) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Failed to read {}: {}", path_buf.display(), e);
+                notifications.send(NotificationEvent("Couldn't read that file".to_string()));
+                continue;
+            }
+        };
+        if image_extension(&data).is_none() {
+            notifications.send(NotificationEvent(
+                "Posters must be PNG or JPEG images".to_string(),
+            ));
+            continue;
+        }
+        if data.len() > MAX_POSTER_FILE_BYTES {
+            notifications.send(NotificationEvent(format!(
+                "Posters can be at most {} MB",
+                MAX_POSTER_FILE_BYTES / (1024 * 1024)
+            )));
+            continue;
+        }
+
+        let hash = file_hash(&data);
+        info!("Hanging {} on poster {}", path_buf.display(), poster);
+        cache
+            .files
+            .entry(hash)
+            .or_insert_with(|| PosterFile::from_data(&data));
+        assignments.set(poster, hash);
+    }
+}
+
+/// System to ask the host for hung images that aren't cached yet (clients only).
+pub fn request_poster_images(
+    server: Option<Res<GameServer>>,
+    assignments: Res<PosterAssignments>,
+    mut cache: ResMut<PosterCache>,
+) {
+    if server.is_some() {
+        return;
+    }
+
+    for assignment in &assignments.posters {
+        let hash = assignment.hash;
+        let file = cache.files.get(&hash);
+        if file.is_some_and(PosterFile::is_complete) {
+            continue;
+        }
+        let waiting = cache
+            .last_activity
+            .get(&hash)
+            .is_some_and(|at| at.elapsed() < REQUEST_RETRY);
+        if waiting {
+            continue;
+        }
+
+        // Ask for the whole file until we know how many chunks it has
+        let mut chunks = file.map(PosterFile::missing_chunks).unwrap_or_default();
+        chunks.truncate(MAX_REQUESTED_CHUNKS);
+        cache.requests.push((hash, chunks));
+        cache.last_activity.insert(hash, Instant::now());
+    }
+}
+
+/// Decodes poster files once all their chunks are in.
+pub fn decode_poster_images(mut cache: ResMut<PosterCache>, mut images: ResMut<Assets<Image>>) {
+    let ready: Vec<u64> = cache
+        .files
+        .iter()
+        .filter(|(_, file)| file.image.is_none() && !file.failed && file.is_complete())
+        .map(|(&hash, _)| hash)
+        .collect();
+
+    for hash in ready {
+        let Some(file) = cache.files.get_mut(&hash) else {
+            continue;
+        };
+        let data: Vec<u8> = file.chunks.iter().flatten().flatten().copied().collect();
+        match decode_image(&data) {
+            Some(image) => file.image = Some(images.add(image)),
+            None => {
+                warn!("Poster image {:016x} couldn't be decoded", hash);
+                file.failed = true;
+            }
+        }
+    }
+}
+
+/// Shows the image hung in each poster frame once it's decoded, fitted inside the frame.
+pub fn show_poster_images(
+    assignments: Res<PosterAssignments>,
+    cache: Res<PosterCache>,
+    images: Res<Assets<Image>>,
+    new_frames: Query<(), Added<PosterFrame>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut frames: Query<(
+        &mut PosterFrame,
+        &mut MeshMaterial3d<StandardMaterial>,
+        &mut Transform,
+    )>,
+) {
+    if !assignments.is_changed() && !cache.is_changed() && new_frames.is_empty() {
+        return;
+    }
+
+    for (mut frame, mut material, mut transform) in frames.iter_mut() {
+        let hash = assignments.get(frame.id);
+        if hash.is_none() || hash == frame.shown {
+            continue;
+        }
+        let Some(image) = hash
+            .and_then(|hash| cache.files.get(&hash))
+            .and_then(|file| file.image.as_ref())
+        else {
+            continue;
+        };
+        let Some(size) = images.get(image).map(Image::size_f32) else {
+            continue;
+        };
+
+        material.0 = materials.add(StandardMaterial {
+            base_color_texture: Some(image.clone()),
+            unlit: true,
+            ..default()
+        });
+
+        // Letterbox the image inside the frame, keeping its aspect ratio
+        let aspect = size.x / size.y;
+        let frame_aspect = POSTER_WIDTH / POSTER_HEIGHT;
+        transform.scale = if aspect > frame_aspect {
+            Vec3::new(1.0, frame_aspect / aspect, 1.0)
+        } else {
+            Vec3::new(aspect / frame_aspect, 1.0, 1.0)
+        };
+        frame.shown = hash;
+    }
+}
+
+/// Takes the posters down when leaving the room. Cached files are kept.
+pub fn reset_posters(mut commands: Commands, mut cache: ResMut<PosterCache>) {
+    commands.insert_resource(PosterAssignments::default());
+    cache.requests.clear();
+    cache.last_activity.clear();
+}
+
+/// FNV-1a hash of a file. It's stable across builds, so the host and clients agree.
+fn file_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Extension for the image format of a file, for PNG and JPEG only.
+fn image_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else {
+        None
+    }
+}
+
+fn decode_image(data: &[u8]) -> Option<Image> {
+    let extension = image_extension(data)?;
+    Image::from_buffer(
+        data,
+        ImageType::Extension(extension),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+    .ok()
+}
@@ -0,0 +1,183 @@
+//! Rooms loaded from glTF scenes in `assets/rooms`.
+//!
+//! Artists tag empties (or meshes) by name, Blender-style suffixes allowed
+//! (`Seat.003`, `Light_2`):
+//! - `Screen`: where the screen goes, centered on the node and facing its +Z axis
+//! - `SpawnPoint`: where players appear, on the floor and facing -Z. Joining
+//!   players are spread over them
+//! - `Seat`: a seat players can sit in, origin on the floor and facing -Z
+//! - `Light`: a point light
+//! - `Whiteboard`: a whiteboard, centered on the node and facing its +Z axis
+//! - `Poster`: a poster frame, centered on the node and facing its +Z axis
+//!
+//! All meshes in the scene get trimesh colliders.
+
+use bevy::prelude::*;
+use bevy::scene::SceneInstanceReady;
+use bevy_rapier3d::prelude::*;
+
+use super::components::{
+    Interactable, InteractionPrompt, RoomLight, Seat, SpawnPoint, WorldEntity,
+};
+use super::posters::spawn_poster;
+use super::setup::spawn_screen;
+use super::whiteboard::spawn_whiteboard;
+use crate::controls::Action;
+use crate::network::protocol::{AssignedSpawn, PosterId, ScreenId};
+use crate::player::{CameraController, Player, PLAYER_HEIGHT};
+
+/// Intensity of the point lights placed at `Light` nodes.
+const SCENE_LIGHT_INTENSITY: f32 = 1_000_000.0;
+
+/// Marker for the root of a room loaded from glTF.
+#[derive(Component)]
+pub struct RoomScene;
+
+/// Node name tags recognised in room scenes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoomNodeTag {
+    Screen,
+    SpawnPoint,
+    Seat,
+    Light,
+    Whiteboard,
+    Poster,
+}
+
+impl RoomNodeTag {
+    /// Parse a node name, ignoring any `.NNN` or `_NNN` suffix.
+    fn from_name(name: &str) -> Option<Self> {
+        let base = name.split(['.', '_']).next().unwrap_or(name);
+        match base {
+            "Screen" => Some(RoomNodeTag::Screen),
+            "SpawnPoint" => Some(RoomNodeTag::SpawnPoint),
+            "Seat" => Some(RoomNodeTag::Seat),
+            "Light" => Some(RoomNodeTag::Light),
+            "Whiteboard" => Some(RoomNodeTag::Whiteboard),
+            "Poster" => Some(RoomNodeTag::Poster),
+            _ => None,
+        }
+    }
+}
+
+/// Start loading a room scene; tagged nodes are set up once it has spawned.
+pub fn spawn_room_scene(commands: &mut Commands, asset_server: &AssetServer, path: String) {
+    let scene = asset_server.load(GltfAssetLabel::Scene(0).from_asset(path));
+
+    commands
+        .spawn((
+            WorldEntity,
+            RoomScene,
+            SceneRoot(scene),
+            AsyncSceneCollider {
+                shape: Some(ComputedColliderShape::TriMesh(TriMeshFlags::default())),
+                ..default()
+            },
+        ))
+        .observe(setup_room_scene_nodes);
+}
+
+/// Map tagged nodes in a freshly spawned room scene to their components.
+fn setup_room_scene_nodes(
+    trigger: Trigger<SceneInstanceReady>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    children: Query<&Children>,
+    names: Query<&Name>,
+) {
+    let seat_color = Color::srgb(0.45, 0.1, 0.12);
+    let mut counts = [0usize; 6];
+
+    for entity in children.iter_descendants(trigger.entity()) {
+        let Some(tag) = names
+            .get(entity)
+            .ok()
+            .and_then(|n| RoomNodeTag::from_name(n.as_str()))
+        else {
+            continue;
+        };
+
+        match tag {
+            RoomNodeTag::Screen => {
+                // Screens are numbered in scene order
+                let id = counts[RoomNodeTag::Screen as usize] as ScreenId;
+                spawn_screen(&mut commands, &mut meshes, &mut materials, entity, id);
+            }
+            RoomNodeTag::SpawnPoint => {
+                commands.entity(entity).insert(SpawnPoint);
+            }
+            RoomNodeTag::Seat => {
+                commands.entity(entity).insert((
+                    Seat,
+                    Interactable {
+                        normal_color: seat_color,
+                        hover_color: seat_color.lighter(0.1),
+                        prompt: InteractionPrompt::key(Action::Interact, "Sit"),
+                    },
+                ));
+            }
+            RoomNodeTag::Light => {
+                commands.entity(entity).insert((
+                    RoomLight {
+                        base_intensity: SCENE_LIGHT_INTENSITY,
+                    },
+                    PointLight {
+                        shadows_enabled: false,
+                        intensity: SCENE_LIGHT_INTENSITY,
+                        range: 20.0,
+                        ..default()
+                    },
+                ));
+            }
+            RoomNodeTag::Whiteboard => {
+                spawn_whiteboard(&mut commands, &mut meshes, &mut materials, entity);
+            }
+            RoomNodeTag::Poster => {
+                // Poster frames are numbered in scene order, like screens
+                let id = counts[RoomNodeTag::Poster as usize] as PosterId;
+                spawn_poster(&mut commands, &mut meshes, &mut materials, entity, id);
+            }
+        }
+        counts[tag as usize] += 1;
+    }
+
+    info!(
+        "Room scene ready: {} screens, {} spawn points, {} seats, {} lights, {} whiteboards, {} posters",
+        counts[RoomNodeTag::Screen as usize],
+        counts[RoomNodeTag::SpawnPoint as usize],
+        counts[RoomNodeTag::Seat as usize],
+        counts[RoomNodeTag::Light as usize],
+        counts[RoomNodeTag::Whiteboard as usize],
+        counts[RoomNodeTag::Poster as usize],
+    );
+    if counts[RoomNodeTag::Screen as usize] == 0 {
+        warn!("Room scene has no 'Screen' node, nothing to watch");
+    }
+}
+
+/// Move the player to the room's first spawn point once one appears, unless
+/// the host already placed them.
+pub fn move_player_to_spawn_point(
+    assigned_spawn: Option<Res<AssignedSpawn>>,
+    spawn_points: Query<&GlobalTransform, Added<SpawnPoint>>,
+    mut player_query: Query<(&mut Transform, &mut CameraController), With<Player>>,
+) {
+    if assigned_spawn.is_some() {
+        return;
+    }
+    let Some(spawn_point) = spawn_points.iter().next() else {
+        return;
+    };
+    let Ok((mut transform, mut camera)) = player_query.get_single_mut() else {
+        return;
+    };
+
+    let (yaw, _, _) = spawn_point
+        .compute_transform()
+        .rotation
+        .to_euler(EulerRot::YXZ);
+    camera.yaw = yaw;
+    transform.translation = spawn_point.translation() + Vec3::Y * PLAYER_HEIGHT;
+    transform.rotation = Quat::from_euler(EulerRot::YXZ, camera.yaw, camera.pitch, 0.0);
+}
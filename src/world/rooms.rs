@@ -0,0 +1,258 @@
+use bevy::asset::io::file::FileAssetReader;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::asset_packs::{pack_dir, pack_model_path, pack_models, PACK_ROOMS_DIR};
+
+/// Folder, relative to the asset root, that glTF rooms are loaded from.
+pub const ROOM_SCENE_DIR: &str = "rooms";
+
+/// Folder, relative to the asset root, that skyboxes are loaded from.
+pub const SKYBOX_DIR: &str = "skyboxes";
+
+/// Gap between the spawn points of built-in rooms.
+const SPAWN_SPACING: f32 = 1.0;
+
+/// Identifies a room: one of the built-in layouts, or a glTF scene from
+/// `assets/rooms` or a pack's `rooms` folder. Sent over the network so
+/// clients build the same room as the host.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub enum RoomId {
+    #[default]
+    SmallTheater,
+    Lounge,
+    Auditorium,
+    SportsBar,
+    /// A room loaded from `assets/rooms/<name>.glb`, or from a pack.
+    Scene(String),
+}
+
+impl RoomId {
+    /// Built-in rooms, in the order shown in the room picker.
+    pub const BUILT_IN: [RoomId; 4] = [
+        RoomId::SmallTheater,
+        RoomId::Lounge,
+        RoomId::Auditorium,
+        RoomId::SportsBar,
+    ];
+
+    /// Built-in rooms followed by every glTF room found on disk, those in
+    /// the assets first, then those in packs.
+    pub fn available() -> Vec<RoomId> {
+        let mut rooms = Self::BUILT_IN.to_vec();
+
+        let dir = Self::scene_dir();
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            let mut scenes: Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "glb"))
+                .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+                .collect();
+            scenes.sort();
+            rooms.extend(scenes.into_iter().map(RoomId::Scene));
+        }
+
+        for name in pack_models(PACK_ROOMS_DIR) {
+            let room = RoomId::Scene(name);
+            if !rooms.contains(&room) {
+                rooms.push(room);
+            }
+        }
+        rooms
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            RoomId::SmallTheater => "Small Theater",
+            RoomId::Lounge => "Lounge",
+            RoomId::Auditorium => "Auditorium",
+            RoomId::SportsBar => "Sports Bar",
+            RoomId::Scene(name) => name,
+        }
+    }
+
+    /// Asset path of the glTF file for scene rooms. The assets' copy wins
+    /// over a pack's.
+    pub fn scene_asset_path(&self) -> Option<String> {
+        match self {
+            RoomId::Scene(name) if Self::pack_scene_only(name) => {
+                Some(pack_model_path(PACK_ROOMS_DIR, name))
+            }
+            RoomId::Scene(name) => Some(format!("{}/{}.glb", ROOM_SCENE_DIR, name)),
+            _ => None,
+        }
+    }
+
+    /// Whether this room can be built here. Scene rooms need the glTF file
+    /// locally, which a joining client may not have.
+    pub fn is_available(&self) -> bool {
+        match self {
+            RoomId::Scene(name) => {
+                Self::scene_dir().join(format!("{}.glb", name)).is_file()
+                    || Self::pack_scene_only(name)
+            }
+            _ => true,
+        }
+    }
+
+    /// Eye-level spawn point. Scene rooms start at the origin until their
+    /// `SpawnPoint` node is found.
+    pub fn spawn_point(&self, eye_height: f32) -> Vec3 {
+        match self.layout() {
+            Some(layout) => layout.spawn_point(eye_height),
+            None => Vec3::new(0.0, eye_height, 0.0),
+        }
+    }
+
+    /// Asset path of the sky shown around the room, if it has one.
+    pub fn skybox_asset_path(&self) -> Option<String> {
+        let skybox = self.layout()?.skybox?;
+        Some(format!("{}/{}.hdr", SKYBOX_DIR, skybox))
+    }
+
+    fn scene_dir() -> PathBuf {
+        FileAssetReader::get_base_path()
+            .join("assets")
+            .join(ROOM_SCENE_DIR)
+    }
+
+    /// Whether the scene room is only in a pack, not in the assets.
+    fn pack_scene_only(name: &str) -> bool {
+        let file = format!("{}.glb", name);
+        !Self::scene_dir().join(&file).is_file()
+            && pack_dir().join(PACK_ROOMS_DIR).join(file).is_file()
+    }
+
+    /// Layout of a built-in room, `None` for scene rooms.
+    pub fn layout(&self) -> Option<RoomLayout> {
+        Some(match self {
+            RoomId::Scene(_) => return None,
+            RoomId::SmallTheater => RoomLayout {
+                width: 10.0,
+                depth: 10.0,
+                height: 4.0,
+                seat_rows: 3,
+                seats_per_row: 5,
+                seat_spacing: 1.0,
+                row_spacing: 1.2,
+                first_row_z: 0.5,
+                floor_color: Color::srgb(0.4, 0.35, 0.3),
+                wall_color: Color::srgb(0.8, 0.75, 0.7),
+                ceiling_color: Color::srgb(0.9, 0.9, 0.9),
+                seat_color: Color::srgb(0.45, 0.1, 0.12),
+                light_intensity: 2_000_000.0,
+                side_screens: false,
+                windows: true,
+                skybox: Some("day"),
+            },
+            RoomId::Lounge => RoomLayout {
+                width: 12.0,
+                depth: 8.0,
+                height: 4.0,
+                seat_rows: 2,
+                seats_per_row: 4,
+                seat_spacing: 1.5,
+                row_spacing: 1.6,
+                first_row_z: 0.0,
+                floor_color: Color::srgb(0.3, 0.22, 0.15),
+                wall_color: Color::srgb(0.55, 0.45, 0.35),
+                ceiling_color: Color::srgb(0.7, 0.65, 0.6),
+                seat_color: Color::srgb(0.2, 0.3, 0.4),
+                light_intensity: 1_200_000.0,
+                side_screens: false,
+                windows: true,
+                skybox: Some("sunset"),
+            },
+            RoomId::Auditorium => RoomLayout {
+                width: 16.0,
+                depth: 18.0,
+                height: 6.0,
+                seat_rows: 7,
+                seats_per_row: 9,
+                seat_spacing: 1.0,
+                row_spacing: 1.2,
+                first_row_z: -3.0,
+                floor_color: Color::srgb(0.2, 0.1, 0.1),
+                wall_color: Color::srgb(0.25, 0.22, 0.25),
+                ceiling_color: Color::srgb(0.15, 0.15, 0.18),
+                seat_color: Color::srgb(0.5, 0.08, 0.1),
+                light_intensity: 4_000_000.0,
+                side_screens: false,
+                windows: false,
+                skybox: None,
+            },
+            RoomId::SportsBar => RoomLayout {
+                width: 12.0,
+                depth: 12.0,
+                height: 4.0,
+                seat_rows: 3,
+                seats_per_row: 4,
+                seat_spacing: 1.2,
+                row_spacing: 1.5,
+                first_row_z: -1.5,
+                floor_color: Color::srgb(0.25, 0.2, 0.15),
+                wall_color: Color::srgb(0.3, 0.35, 0.3),
+                ceiling_color: Color::srgb(0.2, 0.2, 0.2),
+                seat_color: Color::srgb(0.15, 0.15, 0.15),
+                light_intensity: 2_500_000.0,
+                side_screens: true,
+                windows: false,
+                skybox: None,
+            },
+        })
+    }
+}
+
+/// Dimensions and look of a room. The main screen hangs on the back wall
+/// (negative Z) and seats face it.
+#[derive(Debug, Clone)]
+pub struct RoomLayout {
+    pub width: f32,
+    pub depth: f32,
+    pub height: f32,
+    pub seat_rows: usize,
+    pub seats_per_row: usize,
+    pub seat_spacing: f32,
+    pub row_spacing: f32,
+    /// Z position of the row closest to the screen.
+    pub first_row_z: f32,
+    pub floor_color: Color,
+    pub wall_color: Color,
+    pub ceiling_color: Color,
+    pub seat_color: Color,
+    pub light_intensity: f32,
+    /// Extra screens on the left and right walls, for showing several streams.
+    pub side_screens: bool,
+    /// Window openings in the left and right walls.
+    pub windows: bool,
+    /// Sky seen through the windows, from `assets/skyboxes/<name>.hdr`.
+    pub skybox: Option<&'static str>,
+}
+
+impl RoomLayout {
+    /// Eye-level spawn point near the front wall, behind the seats.
+    pub fn spawn_point(&self, eye_height: f32) -> Vec3 {
+        Vec3::new(0.0, eye_height, self.depth / 2.0 - 1.0)
+    }
+
+    /// Floor positions players spawn at, in a row near the front wall,
+    /// starting from the middle one at `spawn_point`.
+    pub fn spawn_points(&self) -> Vec<Vec3> {
+        let z = self.depth / 2.0 - 1.0;
+        let per_side = ((self.width / 2.0 - 1.0) / SPAWN_SPACING).floor().max(0.0) as i32;
+        let mut points = vec![Vec3::new(0.0, 0.0, z)];
+        for i in 1..=per_side {
+            let x = i as f32 * SPAWN_SPACING;
+            points.push(Vec3::new(-x, 0.0, z));
+            points.push(Vec3::new(x, 0.0, z));
+        }
+        points
+    }
+}
+
+/// The room the current session takes place in. Chosen by the host before
+/// hosting, and received in the welcome message when joining.
+#[derive(Resource, Default, Clone)]
+pub struct CurrentRoom(pub RoomId);
@@ -0,0 +1,262 @@
+//! Host edit mode for moving and resizing screens.
+//!
+//! F2 toggles edit mode. Left-click a screen to grab it: it then follows the
+//! crosshair along the walls and the mouse wheel resizes it. Left-click again
+//! to drop it. Placements reach clients through [`ScreenLayout`].
+
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_rapier3d::prelude::*;
+
+use super::components::{Screen, ScreenControlButton, ScreenFrame};
+use super::interaction::LookingAt;
+use crate::menu::NotificationEvent;
+use crate::network::protocol::{ScreenId, ScreenPlacement};
+use crate::network::server::GameServer;
+
+/// How far away a wall can be to move the screen onto it.
+const PLACEMENT_DISTANCE: f32 = 30.0;
+
+/// Screen size limits, relative to the default size.
+const MIN_SCREEN_SCALE: f32 = 0.5;
+const MAX_SCREEN_SCALE: f32 = 2.5;
+
+/// Scale change per mouse wheel notch.
+const SCALE_STEP: f32 = 0.1;
+
+/// Surfaces whose normal points less upward than this count as walls.
+const MAX_WALL_NORMAL_Y: f32 = 0.3;
+
+/// Resource tracking the host's screen edit mode.
+#[derive(Resource, Default)]
+pub struct ScreenEditMode {
+    pub active: bool,
+    pub grabbed: Option<GrabbedScreen>,
+}
+
+/// A screen being moved in edit mode.
+pub struct GrabbedScreen {
+    pub id: ScreenId,
+    /// Parent of the screen, its frame and its button.
+    pub anchor: Entity,
+    /// World-space placement of the anchor.
+    pub placement: Transform,
+}
+
+/// Resource holding the placement of every screen the host has moved.
+/// The host broadcasts it and clients apply it to their own screens.
+#[derive(Resource, Default)]
+pub struct ScreenLayout {
+    pub screens: Vec<ScreenPlacement>,
+}
+
+impl ScreenLayout {
+    pub fn get(&self, screen: ScreenId) -> Option<&ScreenPlacement> {
+        self.screens.iter().find(|p| p.screen == screen)
+    }
+
+    fn set(&mut self, placement: ScreenPlacement) {
+        match self
+            .screens
+            .iter_mut()
+            .find(|p| p.screen == placement.screen)
+        {
+            Some(existing) => *existing = placement,
+            None => self.screens.push(placement),
+        }
+    }
+}
+
+/// System to toggle screen edit mode with F2 (host only).
+pub fn toggle_screen_edit_mode(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    server: Option<Res<GameServer>>,
+    mut edit_mode: ResMut<ScreenEditMode>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    if server.is_none() || !keyboard_input.just_pressed(KeyCode::F2) {
+        return;
+    }
+
+    edit_mode.active = !edit_mode.active;
+    edit_mode.grabbed = None;
+
+    let message = if edit_mode.active {
+        "Screen edit mode: click a screen to move it, scroll to resize"
+    } else {
+        "Screen edit mode off"
+    };
+    notifications.send(NotificationEvent(message.to_string()));
+}
+
+/// System to grab the screen under the crosshair, or drop the grabbed one, on left-click.
+pub fn handle_screen_grab(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    looking_at: Res<LookingAt>,
+    mut edit_mode: ResMut<ScreenEditMode>,
+    screen_parts: Query<&Parent, Or<(With<Screen>, With<ScreenFrame>, With<ScreenControlButton>)>>,
+    children_query: Query<&Children>,
+    screens: Query<&Screen>,
+    global_transforms: Query<&GlobalTransform>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    if !edit_mode.active || !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    // Only when cursor is grabbed (in game)
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if window.cursor_options.visible {
+        return;
+    }
+
+    if let Some(grabbed) = edit_mode.grabbed.take() {
+        info!("Placed screen {}", grabbed.id);
+        return;
+    }
+
+    let Some(anchor) = looking_at
+        .entity
+        .and_then(|entity| screen_parts.get(entity).ok())
+        .map(|parent| parent.get())
+    else {
+        return;
+    };
+    let Some(screen) = children_query
+        .iter_descendants(anchor)
+        .find_map(|child| screens.get(child).ok())
+    else {
+        return;
+    };
+    let Ok(anchor_transform) = global_transforms.get(anchor) else {
+        return;
+    };
+
+    info!("Grabbed screen {}", screen.id);
+    edit_mode.grabbed = Some(GrabbedScreen {
+        id: screen.id,
+        anchor,
+        placement: anchor_transform.compute_transform(),
+    });
+}
+
+/// System to move the grabbed screen onto the wall under the crosshair and
+/// resize it with the mouse wheel.
+pub fn move_grabbed_screen(
+    mut edit_mode: ResMut<ScreenEditMode>,
+    mut layout: ResMut<ScreenLayout>,
+    mut wheel_events: EventReader<MouseWheel>,
+    camera_query: Query<&GlobalTransform, With<Camera3d>>,
+    rapier_context: ReadDefaultRapierContext,
+    children_query: Query<&Children>,
+    parents: Query<&Parent>,
+    global_transforms: Query<&GlobalTransform>,
+    mut transforms: Query<&mut Transform>,
+) {
+    let Some(grabbed) = edit_mode.grabbed.as_mut() else {
+        wheel_events.clear();
+        return;
+    };
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+
+    // Ignore the screen's own colliders so it doesn't stick to itself
+    let assembly: Vec<Entity> = std::iter::once(grabbed.anchor)
+        .chain(children_query.iter_descendants(grabbed.anchor))
+        .collect();
+    let filter = QueryFilter::default()
+        .exclude_sensors()
+        .predicate(&|entity| !assembly.contains(&entity));
+
+    let hit = rapier_context.single().cast_ray_and_get_normal(
+        camera_transform.translation(),
+        camera_transform.forward().as_vec3(),
+        PLACEMENT_DISTANCE,
+        true,
+        filter,
+    );
+    if let Some((_, intersection)) = hit {
+        if intersection.normal.y.abs() < MAX_WALL_NORMAL_Y {
+            // The screen faces out of the wall along its +Z axis
+            let normal = intersection.normal.with_y(0.0).normalize();
+            let facing = Transform::IDENTITY.looking_to(-normal, Vec3::Y);
+            grabbed.placement.translation = intersection.point;
+            grabbed.placement.rotation = facing.rotation;
+        }
+    }
+
+    for event in wheel_events.read() {
+        let scale = (grabbed.placement.scale.x + event.y.signum() * SCALE_STEP)
+            .clamp(MIN_SCREEN_SCALE, MAX_SCREEN_SCALE);
+        grabbed.placement.scale = Vec3::splat(scale);
+    }
+
+    if let Ok(mut transform) = transforms.get_mut(grabbed.anchor) {
+        *transform = to_local(
+            &grabbed.placement,
+            grabbed.anchor,
+            &parents,
+            &global_transforms,
+        );
+    }
+
+    let placement = ScreenPlacement::new(grabbed.id, &grabbed.placement);
+    if layout.get(grabbed.id) != Some(&placement) {
+        layout.set(placement);
+    }
+}
+
+/// System to move screens to the placements received from the host (clients only).
+pub fn apply_screen_layout(
+    server: Option<Res<GameServer>>,
+    layout: Res<ScreenLayout>,
+    new_screens: Query<(), Added<Screen>>,
+    screens: Query<(&Screen, &Parent)>,
+    parents: Query<&Parent>,
+    global_transforms: Query<&GlobalTransform>,
+    mut transforms: Query<&mut Transform>,
+) {
+    if server.is_some() || (!layout.is_changed() && new_screens.is_empty()) {
+        return;
+    }
+
+    for (screen, anchor) in screens.iter() {
+        let Some(placement) = layout.get(screen.id) else {
+            continue;
+        };
+        if let Ok(mut transform) = transforms.get_mut(anchor.get()) {
+            *transform = to_local(
+                &placement.transform(),
+                anchor.get(),
+                &parents,
+                &global_transforms,
+            );
+        }
+    }
+}
+
+/// Leave edit mode and forget moved screens when leaving the room.
+pub fn reset_screen_layout(mut commands: Commands) {
+    commands.insert_resource(ScreenEditMode::default());
+    commands.insert_resource(ScreenLayout::default());
+}
+
+/// Convert a world-space placement into the anchor's local transform.
+fn to_local(
+    placement: &Transform,
+    anchor: Entity,
+    parents: &Query<&Parent>,
+    global_transforms: &Query<&GlobalTransform>,
+) -> Transform {
+    let parent_transform = parents
+        .get(anchor)
+        .ok()
+        .and_then(|parent| global_transforms.get(parent.get()).ok())
+        .copied()
+        .unwrap_or_default();
+    GlobalTransform::from(*placement).reparented_to(&parent_transform)
+}
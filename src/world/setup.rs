@@ -1,9 +1,26 @@
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
 
-use crate::player::{CameraController, Player, Velocity, PLAYER_HEIGHT};
+use crate::character::{AvatarSelection, CharacterAnimationState, CharacterAvatar, LocalCharacter};
+use crate::controls::Action;
+use crate::network::protocol::{AssignedSpawn, PosterId, ScreenId};
+use crate::player::{
+    body_shape, CameraController, Grounded, Player, PlayerCamera, Stamina, Velocity, PLAYER_HEIGHT,
+};
+use crate::screen::capture::ScreenTexture;
+use crate::screen::ScreenDimensions;
 
-use super::components::{Interactable, Screen, ScreenControlButton, ScreenFrame, WorldEntity};
-use super::{ROOM_DEPTH, ROOM_HEIGHT, ROOM_WIDTH, WALL_THICKNESS};
+use super::components::{
+    Interactable, InteractionPrompt, RoomLight, Screen, ScreenControlButton, ScreenFrame,
+    ScreenGlow, Seat, SpawnPoint, WorldEntity,
+};
+use super::posters::{spawn_poster, POSTER_Y};
+use super::room_scene::spawn_room_scene;
+use super::rooms::{CurrentRoom, RoomId, RoomLayout};
+use super::skybox::load_room_skybox;
+use super::whiteboard::{spawn_whiteboard, WHITEBOARD_Y};
+use super::zones::{spawn_door, spawn_zone, DOOR_HEIGHT, DOOR_WIDTH};
+use super::WALL_THICKNESS;
 
 // Screen dimensions (base dimensions, can be scaled by aspect ratio)
 pub const SCREEN_WIDTH: f32 = 6.0;
@@ -16,194 +33,652 @@ pub const FRAME_THICKNESS: f32 = 0.08;
 pub const BUTTON_SIZE: f32 = 0.3;
 pub const BUTTON_OFFSET_X: f32 = 0.3; // Distance from screen edge
 
+// Seat dimensions
+pub const SEAT_WIDTH: f32 = 0.6;
+pub const SEAT_HEIGHT: f32 = 0.45;
+pub const SEAT_BACK_HEIGHT: f32 = 0.5;
+
+// Windows in the side walls of rooms that have them
+const WINDOWS_PER_WALL: usize = 2;
+const WINDOW_WIDTH: f32 = 2.0;
+const WINDOW_SILL: f32 = 1.0;
+const WINDOW_TOP: f32 = 2.6;
+
+// Lobby behind the front wall, in the room's +X corner
+const LOBBY_WIDTH: f32 = 4.0;
+const LOBBY_DEPTH: f32 = 4.0;
+
+/// Builds whichever room was chosen for this session.
 pub fn setup_world(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    room: Res<CurrentRoom>,
+    avatar: Res<AvatarSelection>,
+    assigned_spawn: Option<Res<AssignedSpawn>>,
 ) {
+    let mut room = room.0.clone();
+    if !room.is_available() {
+        warn!(
+            "Room '{}' is not installed locally, using the default room",
+            room.name()
+        );
+        room = RoomId::default();
+    }
+    info!("Building room: {}", room.name());
+
+    match room.layout() {
+        Some(layout) => spawn_room_layout(&mut commands, &mut meshes, &mut materials, &layout),
+        None => {
+            if let Some(path) = room.scene_asset_path() {
+                spawn_room_scene(&mut commands, &asset_server, path);
+            }
+        }
+    }
+
+    if let Some(path) = room.skybox_asset_path() {
+        load_room_skybox(&mut commands, &asset_server, path);
+    }
+
+    // Joining players start where the host put them, the host at the first spawn
+    let (spawn_point, spawn_yaw) = match assigned_spawn {
+        Some(spawn) => (Vec3::from(spawn.0.position), spawn.0.yaw),
+        None => (room.spawn_point(PLAYER_HEIGHT), 0.0),
+    };
+    spawn_player(&mut commands, spawn_point, spawn_yaw, &avatar);
+}
+
+/// Spawns a built-in room: a box with the screen on the back wall and rows of
+/// seats, and a lobby through a door in the front wall.
+fn spawn_room_layout(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    layout: &RoomLayout,
+) {
+    let room_width = layout.width;
+    let room_depth = layout.depth;
+    let room_height = layout.height;
+
     // Materials
     let floor_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.4, 0.35, 0.3),
+        base_color: layout.floor_color,
         ..default()
     });
     let wall_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.8, 0.75, 0.7),
+        base_color: layout.wall_color,
         ..default()
     });
     let ceiling_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.9, 0.9, 0.9),
+        base_color: layout.ceiling_color,
         ..default()
     });
 
     // Floor
     commands.spawn((
         WorldEntity,
-        Mesh3d(meshes.add(Plane3d::default().mesh().size(ROOM_WIDTH, ROOM_DEPTH))),
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(room_width, room_depth))),
         MeshMaterial3d(floor_material),
         Transform::from_xyz(0.0, 0.0, 0.0),
+        RigidBody::Fixed,
+        Collider::halfspace(Vec3::Y).unwrap(),
     ));
 
     // Ceiling
     commands.spawn((
         WorldEntity,
-        Mesh3d(meshes.add(Plane3d::default().mesh().size(ROOM_WIDTH, ROOM_DEPTH))),
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(room_width, room_depth))),
         MeshMaterial3d(ceiling_material),
-        Transform::from_xyz(0.0, ROOM_HEIGHT, 0.0)
+        Transform::from_xyz(0.0, room_height, 0.0)
             .with_rotation(Quat::from_rotation_x(std::f32::consts::PI)),
+        RigidBody::Fixed,
+        // Rotated with the ceiling, so this faces down into the room
+        Collider::halfspace(Vec3::Y).unwrap(),
     ));
 
     // Back wall (negative Z)
     commands.spawn((
         WorldEntity,
-        Mesh3d(meshes.add(Cuboid::new(ROOM_WIDTH, ROOM_HEIGHT, WALL_THICKNESS))),
+        Mesh3d(meshes.add(Cuboid::new(room_width, room_height, WALL_THICKNESS))),
         MeshMaterial3d(wall_material.clone()),
-        Transform::from_xyz(0.0, ROOM_HEIGHT / 2.0, -ROOM_DEPTH / 2.0),
+        Transform::from_xyz(0.0, room_height / 2.0, -room_depth / 2.0),
+        RigidBody::Fixed,
+        Collider::cuboid(room_width / 2.0, room_height / 2.0, WALL_THICKNESS / 2.0),
     ));
 
-    // Front wall (positive Z)
-    commands.spawn((
-        WorldEntity,
-        Mesh3d(meshes.add(Cuboid::new(ROOM_WIDTH, ROOM_HEIGHT, WALL_THICKNESS))),
-        MeshMaterial3d(wall_material.clone()),
-        Transform::from_xyz(0.0, ROOM_HEIGHT / 2.0, ROOM_DEPTH / 2.0),
-    ));
-
-    // Left wall (negative X)
-    commands.spawn((
-        WorldEntity,
-        Mesh3d(meshes.add(Cuboid::new(WALL_THICKNESS, ROOM_HEIGHT, ROOM_DEPTH))),
-        MeshMaterial3d(wall_material.clone()),
-        Transform::from_xyz(-ROOM_WIDTH / 2.0, ROOM_HEIGHT / 2.0, 0.0),
-    ));
+    // Front wall (positive Z), with a doorway into the lobby
+    let door_x = room_width / 2.0 - LOBBY_WIDTH / 2.0;
+    let door_left = door_x - DOOR_WIDTH / 2.0;
+    let door_right = door_x + DOOR_WIDTH / 2.0;
+    let front_z = room_depth / 2.0;
+    spawn_wall(
+        commands,
+        meshes,
+        &wall_material,
+        Vec3::new(
+            (-room_width / 2.0 + door_left) / 2.0,
+            room_height / 2.0,
+            front_z,
+        ),
+        Vec3::new(door_left + room_width / 2.0, room_height, WALL_THICKNESS),
+    );
+    spawn_wall(
+        commands,
+        meshes,
+        &wall_material,
+        Vec3::new(
+            (door_right + room_width / 2.0) / 2.0,
+            room_height / 2.0,
+            front_z,
+        ),
+        Vec3::new(room_width / 2.0 - door_right, room_height, WALL_THICKNESS),
+    );
+    spawn_wall(
+        commands,
+        meshes,
+        &wall_material,
+        Vec3::new(door_x, (DOOR_HEIGHT + room_height) / 2.0, front_z),
+        Vec3::new(DOOR_WIDTH, room_height - DOOR_HEIGHT, WALL_THICKNESS),
+    );
+    spawn_door(
+        commands,
+        meshes,
+        materials,
+        Transform::from_xyz(door_left, 0.0, front_z),
+        0,
+    );
+    spawn_lobby(
+        commands,
+        meshes,
+        materials,
+        layout,
+        &wall_material,
+        Vec3::new(door_x, 0.0, front_z + LOBBY_DEPTH / 2.0),
+    );
+    spawn_zone(
+        commands,
+        "Theater",
+        Vec3::new(0.0, room_height / 2.0, 0.0),
+        Vec3::new(room_width / 2.0, room_height / 2.0, room_depth / 2.0),
+    );
 
-    // Right wall (positive X)
-    commands.spawn((
-        WorldEntity,
-        Mesh3d(meshes.add(Cuboid::new(WALL_THICKNESS, ROOM_HEIGHT, ROOM_DEPTH))),
-        MeshMaterial3d(wall_material),
-        Transform::from_xyz(ROOM_WIDTH / 2.0, ROOM_HEIGHT / 2.0, 0.0),
-    ));
+    // Left and right walls, with windows looking out if the layout has them
+    let glass_material = layout.windows.then(|| {
+        materials.add(StandardMaterial {
+            base_color: Color::srgba(0.8, 0.9, 1.0, 0.15),
+            alpha_mode: AlphaMode::Blend,
+            perceptual_roughness: 0.1,
+            ..default()
+        })
+    });
+    for x in [-room_width / 2.0, room_width / 2.0] {
+        spawn_side_wall(
+            commands,
+            meshes,
+            &wall_material,
+            glass_material.as_ref(),
+            x,
+            layout,
+        );
+    }
 
     // Point light (ceiling light)
     commands.spawn((
         WorldEntity,
+        RoomLight {
+            base_intensity: layout.light_intensity,
+        },
         PointLight {
             shadows_enabled: false,
-            intensity: 2_000_000.0,
+            intensity: layout.light_intensity,
             range: 20.0,
             ..default()
         },
-        Transform::from_xyz(0.0, ROOM_HEIGHT - 0.5, 0.0),
+        Transform::from_xyz(0.0, room_height - 0.5, 0.0),
     ));
 
-    // Screen on back wall
-    let screen_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.05, 0.05, 0.08),
-        emissive: Color::linear_rgb(0.02, 0.02, 0.03).into(),
-        ..default()
-    });
+    // Screen on back wall, plus one on each side wall if the layout asks for it
+    let mut screen_anchors = vec![Transform::from_xyz(
+        0.0,
+        SCREEN_Y,
+        -room_depth / 2.0 + WALL_THICKNESS / 2.0,
+    )];
+    if layout.side_screens {
+        screen_anchors.push(
+            Transform::from_xyz(-room_width / 2.0 + WALL_THICKNESS / 2.0, SCREEN_Y, 0.0)
+                .with_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)),
+        );
+        screen_anchors.push(
+            Transform::from_xyz(room_width / 2.0 - WALL_THICKNESS / 2.0, SCREEN_Y, 0.0)
+                .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
+        );
+    }
+    for (id, anchor_transform) in screen_anchors.into_iter().enumerate() {
+        let anchor = commands
+            .spawn((WorldEntity, anchor_transform, Visibility::default()))
+            .id();
+        spawn_screen(commands, meshes, materials, anchor, id as ScreenId);
+    }
 
-    commands.spawn((
-        WorldEntity,
-        Screen,
-        Mesh3d(meshes.add(Cuboid::new(SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_DEPTH))),
-        MeshMaterial3d(screen_material),
-        Transform::from_xyz(0.0, SCREEN_Y, -ROOM_DEPTH / 2.0 + WALL_THICKNESS / 2.0 + 0.03),
-    ));
+    // Spawn points behind the seats, facing the screen
+    for point in layout.spawn_points() {
+        commands.spawn((WorldEntity, SpawnPoint, Transform::from_translation(point)));
+    }
 
-    // Screen frame/border
-    let frame_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.15, 0.15, 0.15),
-        ..default()
-    });
+    // Whiteboard on the front wall, facing the seats' backs
+    let whiteboard_anchor = commands
+        .spawn((
+            WorldEntity,
+            Transform::from_xyz(0.0, WHITEBOARD_Y, room_depth / 2.0 - WALL_THICKNESS / 2.0)
+                .with_rotation(Quat::from_rotation_y(std::f32::consts::PI)),
+            Visibility::default(),
+        ))
+        .id();
+    spawn_whiteboard(commands, meshes, materials, whiteboard_anchor);
+
+    // Seats, in rows facing the screen
+    let seat_normal_color = layout.seat_color;
+    let seat_hover_color = layout.seat_color.lighter(0.1);
+    let seat_mesh = meshes.add(
+        Mesh::from(Cuboid::new(SEAT_WIDTH, SEAT_HEIGHT, SEAT_WIDTH))
+            .translated_by(Vec3::Y * SEAT_HEIGHT / 2.0),
+    );
+    let back_mesh = meshes.add(Cuboid::new(SEAT_WIDTH, SEAT_BACK_HEIGHT, 0.1));
+    let cushion_offset = Vec3::Y * SEAT_HEIGHT / 2.0;
+    let back_offset = Vec3::new(
+        0.0,
+        SEAT_HEIGHT + SEAT_BACK_HEIGHT / 2.0,
+        SEAT_WIDTH / 2.0 - 0.05,
+    );
+
+    for row in 0..layout.seat_rows {
+        for col in 0..layout.seats_per_row {
+            let x = (col as f32 - (layout.seats_per_row - 1) as f32 / 2.0) * layout.seat_spacing;
+            let z = layout.first_row_z + row as f32 * layout.row_spacing;
+
+            // Each seat gets its own material so only the hovered one lights up
+            let seat_material = materials.add(StandardMaterial {
+                base_color: seat_normal_color,
+                ..default()
+            });
+
+            // Seats have their origin on the floor, like seats in glTF rooms
+            commands
+                .spawn((
+                    WorldEntity,
+                    Seat,
+                    Interactable {
+                        normal_color: seat_normal_color,
+                        hover_color: seat_hover_color,
+                        prompt: InteractionPrompt::key(Action::Interact, "Sit"),
+                    },
+                    Mesh3d(seat_mesh.clone()),
+                    MeshMaterial3d(seat_material.clone()),
+                    Transform::from_xyz(x, 0.0, z),
+                    RigidBody::Fixed,
+                    // One compound collider so raycasts on the backrest hit the seat entity
+                    Collider::compound(vec![
+                        (
+                            cushion_offset,
+                            Quat::IDENTITY,
+                            Collider::cuboid(SEAT_WIDTH / 2.0, SEAT_HEIGHT / 2.0, SEAT_WIDTH / 2.0),
+                        ),
+                        (
+                            back_offset,
+                            Quat::IDENTITY,
+                            Collider::cuboid(SEAT_WIDTH / 2.0, SEAT_BACK_HEIGHT / 2.0, 0.05),
+                        ),
+                    ]),
+                ))
+                .with_child((
+                    Mesh3d(back_mesh.clone()),
+                    MeshMaterial3d(seat_material),
+                    Transform::from_translation(back_offset),
+                ));
+        }
+    }
+}
 
-    let frame_z = -ROOM_DEPTH / 2.0 + WALL_THICKNESS / 2.0 + 0.02;
+/// Spawns a lobby of `LOBBY_WIDTH` by `LOBBY_DEPTH` around `center` on the floor.
+/// Its -Z side is the theater's front wall.
+fn spawn_lobby(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    layout: &RoomLayout,
+    wall_material: &Handle<StandardMaterial>,
+    center: Vec3,
+) {
+    let room_height = layout.height;
 
-    // Top frame
+    // Floor and ceiling meshes; the room's halfspace colliders already cover them
     commands.spawn((
         WorldEntity,
-        ScreenFrame::Top,
-        Mesh3d(meshes.add(Cuboid::new(
-            SCREEN_WIDTH + FRAME_THICKNESS * 2.0,
-            FRAME_THICKNESS,
-            0.06,
-        ))),
-        MeshMaterial3d(frame_material.clone()),
-        Transform::from_xyz(
-            0.0,
-            SCREEN_Y + SCREEN_HEIGHT / 2.0 + FRAME_THICKNESS / 2.0,
-            frame_z,
-        ),
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(LOBBY_WIDTH, LOBBY_DEPTH))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: layout.floor_color.darker(0.05),
+            ..default()
+        })),
+        Transform::from_translation(center),
     ));
-    // Bottom frame
     commands.spawn((
         WorldEntity,
-        ScreenFrame::Bottom,
-        Mesh3d(meshes.add(Cuboid::new(
-            SCREEN_WIDTH + FRAME_THICKNESS * 2.0,
-            FRAME_THICKNESS,
-            0.06,
-        ))),
-        MeshMaterial3d(frame_material.clone()),
-        Transform::from_xyz(
-            0.0,
-            SCREEN_Y - SCREEN_HEIGHT / 2.0 - FRAME_THICKNESS / 2.0,
-            frame_z,
-        ),
+        Mesh3d(meshes.add(Plane3d::default().mesh().size(LOBBY_WIDTH, LOBBY_DEPTH))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: layout.ceiling_color,
+            ..default()
+        })),
+        Transform::from_translation(center + Vec3::Y * room_height)
+            .with_rotation(Quat::from_rotation_x(std::f32::consts::PI)),
     ));
-    // Left frame
+
+    // Back and side walls
+    spawn_wall(
+        commands,
+        meshes,
+        wall_material,
+        center + Vec3::new(0.0, room_height / 2.0, LOBBY_DEPTH / 2.0),
+        Vec3::new(LOBBY_WIDTH + WALL_THICKNESS, room_height, WALL_THICKNESS),
+    );
+    for side in [-1.0, 1.0] {
+        spawn_wall(
+            commands,
+            meshes,
+            wall_material,
+            center + Vec3::new(side * LOBBY_WIDTH / 2.0, room_height / 2.0, 0.0),
+            Vec3::new(WALL_THICKNESS, room_height, LOBBY_DEPTH),
+        );
+    }
+
+    // Poster frames on the inside of the back and side walls
+    let inset = LOBBY_WIDTH / 2.0 - WALL_THICKNESS / 2.0;
+    let poster_anchors = [
+        Transform::from_translation(
+            center + Vec3::new(0.0, POSTER_Y, LOBBY_DEPTH / 2.0 - WALL_THICKNESS / 2.0),
+        )
+        .with_rotation(Quat::from_rotation_y(std::f32::consts::PI)),
+        Transform::from_translation(center + Vec3::new(-inset, POSTER_Y, 0.0))
+            .with_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)),
+        Transform::from_translation(center + Vec3::new(inset, POSTER_Y, 0.0))
+            .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
+    ];
+    for (id, anchor_transform) in poster_anchors.into_iter().enumerate() {
+        let anchor = commands
+            .spawn((WorldEntity, anchor_transform, Visibility::default()))
+            .id();
+        spawn_poster(commands, meshes, materials, anchor, id as PosterId);
+    }
+
+    // Lobby lights stay up while a stream plays
     commands.spawn((
         WorldEntity,
-        ScreenFrame::Left,
-        Mesh3d(meshes.add(Cuboid::new(FRAME_THICKNESS, SCREEN_HEIGHT, 0.06))),
-        MeshMaterial3d(frame_material.clone()),
-        Transform::from_xyz(
-            -SCREEN_WIDTH / 2.0 - FRAME_THICKNESS / 2.0,
-            SCREEN_Y,
-            frame_z,
-        ),
+        PointLight {
+            shadows_enabled: false,
+            intensity: 400_000.0,
+            range: 8.0,
+            ..default()
+        },
+        Transform::from_translation(center + Vec3::Y * (room_height - 0.5)),
     ));
-    // Right frame
+
+    spawn_zone(
+        commands,
+        "Lobby",
+        center + Vec3::Y * room_height / 2.0,
+        Vec3::new(LOBBY_WIDTH / 2.0, room_height / 2.0, LOBBY_DEPTH / 2.0),
+    );
+}
+
+/// Spawns a side wall along Z at `x`. With a glass material, the wall gets
+/// evenly spaced glazed window openings.
+fn spawn_side_wall(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    wall_material: &Handle<StandardMaterial>,
+    glass_material: Option<&Handle<StandardMaterial>>,
+    x: f32,
+    layout: &RoomLayout,
+) {
+    let (room_height, room_depth) = (layout.height, layout.depth);
+    let Some(glass_material) = glass_material else {
+        spawn_wall(
+            commands,
+            meshes,
+            wall_material,
+            Vec3::new(x, room_height / 2.0, 0.0),
+            Vec3::new(WALL_THICKNESS, room_height, room_depth),
+        );
+        return;
+    };
+
+    // Below and above the windows, the full length of the wall
+    spawn_wall(
+        commands,
+        meshes,
+        wall_material,
+        Vec3::new(x, WINDOW_SILL / 2.0, 0.0),
+        Vec3::new(WALL_THICKNESS, WINDOW_SILL, room_depth),
+    );
+    spawn_wall(
+        commands,
+        meshes,
+        wall_material,
+        Vec3::new(x, (WINDOW_TOP + room_height) / 2.0, 0.0),
+        Vec3::new(WALL_THICKNESS, room_height - WINDOW_TOP, room_depth),
+    );
+
+    // Pillars between the windows, and a pane of glass in each
+    let window_height = WINDOW_TOP - WINDOW_SILL;
+    let window_y = (WINDOW_SILL + WINDOW_TOP) / 2.0;
+    let mut pillar_start = -room_depth / 2.0;
+    for i in 0..WINDOWS_PER_WALL {
+        let window_z = -room_depth / 2.0 + room_depth * (i as f32 + 0.5) / WINDOWS_PER_WALL as f32;
+        let pillar_end = window_z - WINDOW_WIDTH / 2.0;
+        spawn_wall(
+            commands,
+            meshes,
+            wall_material,
+            Vec3::new(x, window_y, (pillar_start + pillar_end) / 2.0),
+            Vec3::new(WALL_THICKNESS, window_height, pillar_end - pillar_start),
+        );
+        pillar_start = window_z + WINDOW_WIDTH / 2.0;
+
+        commands.spawn((
+            WorldEntity,
+            Mesh3d(meshes.add(Cuboid::new(0.02, window_height, WINDOW_WIDTH))),
+            MeshMaterial3d(glass_material.clone()),
+            Transform::from_xyz(x, window_y, window_z),
+            RigidBody::Fixed,
+            Collider::cuboid(0.01, window_height / 2.0, WINDOW_WIDTH / 2.0),
+        ));
+    }
+    spawn_wall(
+        commands,
+        meshes,
+        wall_material,
+        Vec3::new(x, window_y, (pillar_start + room_depth / 2.0) / 2.0),
+        Vec3::new(
+            WALL_THICKNESS,
+            window_height,
+            room_depth / 2.0 - pillar_start,
+        ),
+    );
+}
+
+/// Spawns a solid box of wall centered on `center`.
+fn spawn_wall(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    material: &Handle<StandardMaterial>,
+    center: Vec3,
+    size: Vec3,
+) {
     commands.spawn((
         WorldEntity,
-        ScreenFrame::Right,
-        Mesh3d(meshes.add(Cuboid::new(FRAME_THICKNESS, SCREEN_HEIGHT, 0.06))),
-        MeshMaterial3d(frame_material),
-        Transform::from_xyz(SCREEN_WIDTH / 2.0 + FRAME_THICKNESS / 2.0, SCREEN_Y, frame_z),
+        Mesh3d(meshes.add(Cuboid::from_size(size))),
+        MeshMaterial3d(material.clone()),
+        Transform::from_translation(center),
+        RigidBody::Fixed,
+        Collider::cuboid(size.x / 2.0, size.y / 2.0, size.z / 2.0),
     ));
+}
 
-    // Screen control button (right side of screen)
+/// Spawns the screen, its frame and its control button as children of `anchor`,
+/// centered on the anchor and facing its +Z axis.
+pub fn spawn_screen(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    anchor: Entity,
+    id: ScreenId,
+) {
+    let screen_material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.05, 0.05, 0.08),
+        emissive: Color::linear_rgb(0.02, 0.02, 0.03).into(),
+        ..default()
+    });
+    let frame_material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.15, 0.15, 0.15),
+        ..default()
+    });
     let button_normal_color = Color::srgb(0.3, 0.5, 0.3);
     let button_material = materials.add(StandardMaterial {
         base_color: button_normal_color,
         ..default()
     });
 
-    commands.spawn((
-        WorldEntity,
-        ScreenControlButton,
-        Interactable {
-            normal_color: button_normal_color,
-            hover_color: Color::srgb(0.4, 0.7, 0.4),
-        },
-        Mesh3d(meshes.add(Cuboid::new(BUTTON_SIZE, BUTTON_SIZE, 0.05))),
-        MeshMaterial3d(button_material),
-        Transform::from_xyz(
-            SCREEN_WIDTH / 2.0 + FRAME_THICKNESS + BUTTON_OFFSET_X + BUTTON_SIZE / 2.0,
-            SCREEN_Y - SCREEN_HEIGHT / 2.0 + BUTTON_SIZE / 2.0,
-            -ROOM_DEPTH / 2.0 + WALL_THICKNESS / 2.0 + 0.03,
-        ),
+    let horizontal_frame = meshes.add(Cuboid::new(
+        SCREEN_WIDTH + FRAME_THICKNESS * 2.0,
+        FRAME_THICKNESS,
+        0.06,
     ));
+    let vertical_frame = meshes.add(Cuboid::new(FRAME_THICKNESS, SCREEN_HEIGHT, 0.06));
+    let horizontal_collider = Collider::cuboid(
+        SCREEN_WIDTH / 2.0 + FRAME_THICKNESS,
+        FRAME_THICKNESS / 2.0,
+        0.03,
+    );
+    let vertical_collider = Collider::cuboid(FRAME_THICKNESS / 2.0, SCREEN_HEIGHT / 2.0, 0.03);
+    let frame_z = 0.02;
+
+    commands.entity(anchor).with_children(|parent| {
+        // Screen
+        parent.spawn((
+            Screen { id },
+            ScreenDimensions::default(),
+            ScreenTexture::default(),
+            Mesh3d(meshes.add(Cuboid::new(SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_DEPTH))),
+            MeshMaterial3d(screen_material),
+            Transform::from_xyz(0.0, 0.0, 0.03),
+            RigidBody::Fixed,
+            Collider::cuboid(SCREEN_WIDTH / 2.0, SCREEN_HEIGHT / 2.0, SCREEN_DEPTH / 2.0),
+        ));
+
+        // Top frame
+        parent.spawn((
+            ScreenFrame::Top,
+            Mesh3d(horizontal_frame.clone()),
+            MeshMaterial3d(frame_material.clone()),
+            Transform::from_xyz(0.0, SCREEN_HEIGHT / 2.0 + FRAME_THICKNESS / 2.0, frame_z),
+            RigidBody::Fixed,
+            horizontal_collider.clone(),
+        ));
+        // Bottom frame
+        parent.spawn((
+            ScreenFrame::Bottom,
+            Mesh3d(horizontal_frame),
+            MeshMaterial3d(frame_material.clone()),
+            Transform::from_xyz(0.0, -SCREEN_HEIGHT / 2.0 - FRAME_THICKNESS / 2.0, frame_z),
+            RigidBody::Fixed,
+            horizontal_collider,
+        ));
+        // Left frame
+        parent.spawn((
+            ScreenFrame::Left,
+            Mesh3d(vertical_frame.clone()),
+            MeshMaterial3d(frame_material.clone()),
+            Transform::from_xyz(-SCREEN_WIDTH / 2.0 - FRAME_THICKNESS / 2.0, 0.0, frame_z),
+            RigidBody::Fixed,
+            vertical_collider.clone(),
+        ));
+        // Right frame
+        parent.spawn((
+            ScreenFrame::Right,
+            Mesh3d(vertical_frame),
+            MeshMaterial3d(frame_material),
+            Transform::from_xyz(SCREEN_WIDTH / 2.0 + FRAME_THICKNESS / 2.0, 0.0, frame_z),
+            RigidBody::Fixed,
+            vertical_collider,
+        ));
+
+        // Screen control button (right side of screen)
+        parent.spawn((
+            ScreenControlButton { screen: id },
+            Interactable {
+                normal_color: button_normal_color,
+                hover_color: Color::srgb(0.4, 0.7, 0.4),
+                prompt: InteractionPrompt::right_click("Share screen").host_only(),
+            },
+            Mesh3d(meshes.add(Cuboid::new(BUTTON_SIZE, BUTTON_SIZE, 0.05))),
+            MeshMaterial3d(button_material),
+            Transform::from_xyz(
+                SCREEN_WIDTH / 2.0 + FRAME_THICKNESS + BUTTON_OFFSET_X + BUTTON_SIZE / 2.0,
+                -SCREEN_HEIGHT / 2.0 + BUTTON_SIZE / 2.0,
+                0.03,
+            ),
+            RigidBody::Fixed,
+            Collider::cuboid(BUTTON_SIZE / 2.0, BUTTON_SIZE / 2.0, 0.025),
+        ));
+
+        // Glow cast by the picture, off until something plays
+        parent.spawn((
+            ScreenGlow { screen: id },
+            PointLight {
+                shadows_enabled: false,
+                intensity: 0.0,
+                range: 12.0,
+                ..default()
+            },
+            Transform::from_xyz(0.0, 0.0, 1.5),
+        ));
+    });
+}
+
+/// Spawns the local player's camera, character controller and character.
+fn spawn_player(commands: &mut Commands, spawn_point: Vec3, yaw: f32, avatar: &AvatarSelection) {
+    commands
+        .spawn((
+            WorldEntity,
+            Player,
+            CameraController { yaw, ..default() },
+            Velocity::default(),
+            Grounded(true),
+            Stamina::default(),
+            // The player entity sits at eye level, so the body capsule hangs below it
+            KinematicCharacterController {
+                custom_shape: Some(body_shape(PLAYER_HEIGHT)),
+                autostep: Some(CharacterAutostep {
+                    max_height: CharacterLength::Absolute(0.3),
+                    min_width: CharacterLength::Absolute(0.2),
+                    include_dynamic_bodies: false,
+                }),
+                ..default()
+            },
+            Transform::from_translation(spawn_point).with_rotation(Quat::from_rotation_y(yaw)),
+            Visibility::default(),
+        ))
+        // At the eyes in first person, pulled back in third person
+        .with_child((PlayerCamera, Camera3d::default()));
 
-    // Player (Camera)
+    // The model is attached once character assets are loaded
     commands.spawn((
         WorldEntity,
-        Player,
-        CameraController::default(),
-        Velocity::default(),
-        Camera3d::default(),
-        Transform::from_xyz(0.0, PLAYER_HEIGHT, 4.0)
-            .looking_at(Vec3::new(0.0, PLAYER_HEIGHT, 0.0), Vec3::Y),
+        LocalCharacter,
+        CharacterAvatar::new(avatar.0),
+        CharacterAnimationState::default(),
+        Transform::from_translation(spawn_point - Vec3::Y * PLAYER_HEIGHT),
+        Visibility::Hidden,
     ));
 }
 
@@ -0,0 +1,80 @@
+//! Sky seen through the windows of rooms that have one.
+//!
+//! Skyboxes are HDR images in `assets/skyboxes` with the six cube faces
+//! stacked vertically, in the order +X, -X, +Y, -Y, +Z, -Z.
+
+use bevy::core_pipeline::Skybox;
+use bevy::prelude::*;
+use bevy::render::render_resource::{TextureViewDescriptor, TextureViewDimension};
+
+use crate::player::PlayerCamera;
+
+/// Brightness of the sky, in cd/m².
+const SKYBOX_BRIGHTNESS: f32 = 1000.0;
+
+/// Resource holding the sky of the current room until it's on the camera.
+#[derive(Resource)]
+pub struct RoomSkybox {
+    pub image: Handle<Image>,
+    pub applied: bool,
+}
+
+/// Start loading the sky for the current room.
+pub fn load_room_skybox(commands: &mut Commands, asset_server: &AssetServer, path: String) {
+    info!("Loading skybox: {}", path);
+    commands.insert_resource(RoomSkybox {
+        image: asset_server.load(path),
+        applied: false,
+    });
+}
+
+/// Turn the loaded sky image into a cubemap and put it on the player camera.
+pub fn apply_room_skybox(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    skybox: Option<ResMut<RoomSkybox>>,
+    mut images: ResMut<Assets<Image>>,
+    camera_query: Query<Entity, With<PlayerCamera>>,
+) {
+    let Some(mut skybox) = skybox else {
+        return;
+    };
+    if skybox.applied {
+        return;
+    }
+
+    let load_state = asset_server.load_state(&skybox.image);
+    if load_state.is_failed() {
+        warn!("Skybox failed to load, rooms will show a plain background");
+        skybox.applied = true;
+        return;
+    }
+    if !load_state.is_loaded() {
+        return;
+    }
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+
+    if let Some(image) = images.get_mut(&skybox.image) {
+        if image.texture_descriptor.array_layer_count() == 1 {
+            image.reinterpret_stacked_2d_as_array(image.height() / image.width());
+            image.texture_view_descriptor = Some(TextureViewDescriptor {
+                dimension: Some(TextureViewDimension::Cube),
+                ..default()
+            });
+        }
+    }
+
+    commands.entity(camera).insert(Skybox {
+        image: skybox.image.clone(),
+        brightness: SKYBOX_BRIGHTNESS,
+        ..default()
+    });
+    skybox.applied = true;
+}
+
+/// Forget the room's sky when leaving it.
+pub fn cleanup_skybox(mut commands: Commands) {
+    commands.remove_resource::<RoomSkybox>();
+}
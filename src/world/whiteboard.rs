@@ -0,0 +1,343 @@
+//! Shared whiteboard players draw on with the mouse.
+//!
+//! Holding the left mouse button while looking at the board draws a stroke.
+//! Strokes are sent in short pieces through the host, which keeps them all so
+//! late joiners see the same board, and every player rasterizes them into a
+//! shared texture. The host wipes the board with the clear button.
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::window::PrimaryWindow;
+use bevy_rapier3d::prelude::*;
+
+use super::components::{Interactable, InteractionPrompt};
+use super::interaction::LookingAt;
+use super::screen_editor::ScreenEditMode;
+use crate::network::protocol::{LocalPlayerId, WhiteboardStroke};
+use crate::network::server::GameServer;
+use crate::player::identity::PlayerIdentity;
+
+pub const WHITEBOARD_WIDTH: f32 = 3.0;
+pub const WHITEBOARD_HEIGHT: f32 = 1.5;
+pub const WHITEBOARD_Y: f32 = 1.6;
+
+/// Texture resolution of the board.
+const TEXTURE_WIDTH: u32 = 1024;
+const TEXTURE_HEIGHT: u32 = 512;
+
+/// Pen radius in texture pixels.
+const PEN_RADIUS: i32 = 3;
+
+/// Stroke pieces are sent once they have this many points.
+const STROKE_FLUSH_POINTS: usize = 8;
+
+const BOARD_COLOR: [u8; 4] = [245, 245, 240, 255];
+
+/// Pen color of players the player list doesn't name yet.
+const DEFAULT_PEN_COLOR: [u8; 3] = [20, 20, 20];
+
+/// The drawable surface of a whiteboard.
+#[derive(Component)]
+pub struct WhiteboardSurface;
+
+/// Button that wipes the whiteboard (host only).
+#[derive(Component)]
+pub struct WhiteboardClearButton;
+
+/// Resource holding the board texture shared by every whiteboard in the room.
+#[derive(Resource)]
+pub struct WhiteboardTexture {
+    pub image: Handle<Image>,
+    pub material: Handle<StandardMaterial>,
+}
+
+/// Resource holding the strokes on the board and those waiting to be sent.
+#[derive(Resource, Default)]
+pub struct Whiteboard {
+    /// Strokes to draw onto the texture, from any player.
+    pub incoming: Vec<WhiteboardStroke>,
+    /// Points the local player drew, waiting to be sent.
+    pub outgoing: Vec<Vec<[u16; 2]>>,
+    /// Every stroke drawn since the last clear.
+    pub history: Vec<WhiteboardStroke>,
+    /// Wipe the texture before drawing anything else.
+    pub clear_pending: bool,
+    /// The host wiped the board and clients haven't been told yet.
+    pub clear_unsent: bool,
+}
+
+/// Spawns a whiteboard as children of `anchor`, centered on the anchor and
+/// facing its +Z axis.
+pub fn spawn_whiteboard(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    anchor: Entity,
+) {
+    let frame_material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.6, 0.6, 0.62),
+        ..default()
+    });
+    let button_normal_color = Color::srgb(0.6, 0.3, 0.3);
+    let button_material = materials.add(StandardMaterial {
+        base_color: button_normal_color,
+        ..default()
+    });
+    let button_size = 0.25;
+
+    commands.entity(anchor).with_children(|parent| {
+        // Backing board, slightly larger than the surface
+        parent.spawn((
+            Mesh3d(meshes.add(Cuboid::new(
+                WHITEBOARD_WIDTH + 0.1,
+                WHITEBOARD_HEIGHT + 0.1,
+                0.04,
+            ))),
+            MeshMaterial3d(frame_material),
+            Transform::from_xyz(0.0, 0.0, 0.02),
+        ));
+
+        // Drawing surface, textured once the board texture is attached
+        parent.spawn((
+            WhiteboardSurface,
+            Mesh3d(meshes.add(Rectangle::new(WHITEBOARD_WIDTH, WHITEBOARD_HEIGHT))),
+            MeshMaterial3d(materials.add(StandardMaterial::default())),
+            Transform::from_xyz(0.0, 0.0, 0.045),
+            RigidBody::Fixed,
+            Collider::cuboid(WHITEBOARD_WIDTH / 2.0, WHITEBOARD_HEIGHT / 2.0, 0.005),
+        ));
+
+        // Clear button (right side of the board)
+        parent.spawn((
+            WhiteboardClearButton,
+            Interactable {
+                normal_color: button_normal_color,
+                hover_color: Color::srgb(0.8, 0.4, 0.4),
+                prompt: InteractionPrompt::right_click("Clear whiteboard").host_only(),
+            },
+            Mesh3d(meshes.add(Cuboid::new(button_size, button_size, 0.05))),
+            MeshMaterial3d(button_material),
+            Transform::from_xyz(
+                WHITEBOARD_WIDTH / 2.0 + 0.3,
+                -WHITEBOARD_HEIGHT / 2.0 + button_size / 2.0,
+                0.03,
+            ),
+            RigidBody::Fixed,
+            Collider::cuboid(button_size / 2.0, button_size / 2.0, 0.025),
+        ));
+    });
+}
+
+/// Creates the blank board texture when entering the game.
+pub fn setup_whiteboard_texture(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let image = images.add(Image::new_fill(
+        Extent3d {
+            width: TEXTURE_WIDTH,
+            height: TEXTURE_HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &BOARD_COLOR,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    ));
+    let material = materials.add(StandardMaterial {
+        base_color_texture: Some(image.clone()),
+        unlit: true,
+        ..default()
+    });
+
+    commands.insert_resource(WhiteboardTexture { image, material });
+}
+
+/// Puts the board texture on newly spawned whiteboard surfaces.
+pub fn attach_whiteboard_texture(
+    texture: Option<Res<WhiteboardTexture>>,
+    mut surfaces: Query<&mut MeshMaterial3d<StandardMaterial>, Added<WhiteboardSurface>>,
+) {
+    let Some(texture) = texture else {
+        return;
+    };
+    for mut material in surfaces.iter_mut() {
+        material.0 = texture.material.clone();
+    }
+}
+
+/// System to draw on the whiteboard under the crosshair while the left mouse button is held.
+pub fn draw_on_whiteboard(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    looking_at: Res<LookingAt>,
+    edit_mode: Res<ScreenEditMode>,
+    local_id: Option<Res<LocalPlayerId>>,
+    surfaces: Query<&GlobalTransform, With<WhiteboardSurface>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut whiteboard: ResMut<Whiteboard>,
+    mut stroke: Local<Vec<[u16; 2]>>,
+) {
+    let Some(local_id) = local_id else {
+        return;
+    };
+    let cursor_grabbed = windows
+        .get_single()
+        .is_ok_and(|window| !window.cursor_options.visible);
+
+    let point = looking_at
+        .entity
+        .filter(|_| cursor_grabbed && !edit_mode.active)
+        .filter(|_| mouse_input.pressed(MouseButton::Left))
+        .and_then(|entity| surfaces.get(entity).ok())
+        .map(|surface| board_point(surface, looking_at.point));
+
+    if let Some(point) = point {
+        if stroke.last() != Some(&point) {
+            stroke.push(point);
+        }
+    }
+
+    let drawing = point.is_some();
+    if stroke.is_empty() || (drawing && stroke.len() < STROKE_FLUSH_POINTS) {
+        return;
+    }
+
+    let points = std::mem::take(&mut *stroke);
+    // Keep going from the last point so the pieces join up
+    if drawing {
+        stroke.extend(points.last().copied());
+    }
+    whiteboard.incoming.push(WhiteboardStroke {
+        player: local_id.0,
+        points: points.clone(),
+    });
+    whiteboard.outgoing.push(points);
+}
+
+/// System to wipe the whiteboard on right-click of its clear button (host only).
+pub fn handle_whiteboard_clear(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    looking_at: Res<LookingAt>,
+    button_query: Query<(), With<WhiteboardClearButton>>,
+    server: Option<Res<GameServer>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut whiteboard: ResMut<Whiteboard>,
+) {
+    if server.is_none() || !mouse_input.just_pressed(MouseButton::Right) {
+        return;
+    }
+
+    // Only when cursor is grabbed (in game)
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if window.cursor_options.visible {
+        return;
+    }
+
+    if looking_at.entity.is_some_and(|e| button_query.contains(e)) {
+        info!("Whiteboard cleared");
+        whiteboard.clear_pending = true;
+        whiteboard.clear_unsent = true;
+    }
+}
+
+/// Draws new strokes into the board texture.
+pub fn rasterize_whiteboard(
+    mut whiteboard: ResMut<Whiteboard>,
+    texture: Option<Res<WhiteboardTexture>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    identity: Res<PlayerIdentity>,
+) {
+    let Some(texture) = texture else {
+        return;
+    };
+    if !whiteboard.clear_pending && whiteboard.incoming.is_empty() {
+        return;
+    }
+    let Some(image) = images.get_mut(&texture.image) else {
+        return;
+    };
+
+    let whiteboard = &mut *whiteboard;
+    if whiteboard.clear_pending {
+        for pixel in image.data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&BOARD_COLOR);
+        }
+        whiteboard.history.clear();
+        whiteboard.clear_pending = false;
+    }
+
+    for stroke in whiteboard.incoming.drain(..) {
+        // Each player draws in their own color
+        let color = identity.rgb(stroke.player).unwrap_or(DEFAULT_PEN_COLOR);
+        draw_stroke(&mut image.data, &stroke, color);
+        whiteboard.history.push(stroke);
+    }
+
+    // Touch the material so it picks up the new texture data
+    materials.get_mut(&texture.material);
+}
+
+/// Forgets the board when leaving the room.
+pub fn cleanup_whiteboard(mut commands: Commands) {
+    commands.insert_resource(Whiteboard::default());
+    commands.remove_resource::<WhiteboardTexture>();
+}
+
+/// Convert a world-space hit on a board surface into board coordinates.
+fn board_point(surface: &GlobalTransform, world_point: Vec3) -> [u16; 2] {
+    let local = surface.affine().inverse().transform_point3(world_point);
+    let u = (local.x / WHITEBOARD_WIDTH + 0.5).clamp(0.0, 1.0);
+    let v = (0.5 - local.y / WHITEBOARD_HEIGHT).clamp(0.0, 1.0);
+    [(u * u16::MAX as f32) as u16, (v * u16::MAX as f32) as u16]
+}
+
+fn draw_stroke(data: &mut [u8], stroke: &WhiteboardStroke, color: [u8; 3]) {
+    let to_pixel = |[u, v]: [u16; 2]| {
+        Vec2::new(
+            u as f32 / u16::MAX as f32 * (TEXTURE_WIDTH - 1) as f32,
+            v as f32 / u16::MAX as f32 * (TEXTURE_HEIGHT - 1) as f32,
+        )
+    };
+
+    let mut points = stroke.points.iter().map(|&p| to_pixel(p));
+    let Some(mut previous) = points.next() else {
+        return;
+    };
+    stamp(data, previous, color);
+
+    for point in points {
+        // Stamp every pixel along the segment so fast strokes stay solid
+        let steps = previous.distance(point).ceil().max(1.0) as usize;
+        for step in 1..=steps {
+            stamp(
+                data,
+                previous.lerp(point, step as f32 / steps as f32),
+                color,
+            );
+        }
+        previous = point;
+    }
+}
+
+/// Paint a round pen dot centered on `center`.
+fn stamp(data: &mut [u8], center: Vec2, color: [u8; 3]) {
+    let (cx, cy) = (center.x.round() as i32, center.y.round() as i32);
+    for y in (cy - PEN_RADIUS)..=(cy + PEN_RADIUS) {
+        for x in (cx - PEN_RADIUS)..=(cx + PEN_RADIUS) {
+            let (dx, dy) = (x - cx, y - cy);
+            if dx * dx + dy * dy > PEN_RADIUS * PEN_RADIUS {
+                continue;
+            }
+            if x < 0 || y < 0 || x >= TEXTURE_WIDTH as i32 || y >= TEXTURE_HEIGHT as i32 {
+                continue;
+            }
+            let i = (y as usize * TEXTURE_WIDTH as usize + x as usize) * 4;
+            data[i..i + 3].copy_from_slice(&color);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Zones the room is split into, and the doors between them.
+//!
+//! A zone is a box players can be in, like the theater or its lobby. The local
+//! player is kept inside the zones: if they somehow end up outside all of
+//! them, they're put back where they last were inside one. Doors are portals
+//! between zones that anyone can open or close with E; the host owns their
+//! state and replicates it.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::collections::HashSet;
+
+use super::components::{Interactable, InteractionPrompt, WorldEntity};
+use super::interaction::LookingAt;
+use crate::controls::{Action, KeyBindings};
+use crate::menu::NotificationEvent;
+use crate::network::protocol::DoorId;
+use crate::network::server::GameServer;
+use crate::player::{Player, Seated};
+
+pub const DOOR_WIDTH: f32 = 1.2;
+pub const DOOR_HEIGHT: f32 = 2.2;
+const DOOR_THICKNESS: f32 = 0.08;
+
+/// How far doors swing open.
+const DOOR_OPEN_ANGLE: f32 = std::f32::consts::FRAC_PI_2;
+
+/// How quickly doors swing, per second.
+const DOOR_SWING_RATE: f32 = 6.0;
+
+/// A box-shaped area of the room, centered on the entity's transform.
+#[derive(Component)]
+pub struct Zone {
+    pub name: String,
+    pub half_extents: Vec3,
+}
+
+impl Zone {
+    pub fn contains(&self, transform: &GlobalTransform, point: Vec3) -> bool {
+        let local = transform.affine().inverse().transform_point3(point);
+        local.abs().cmple(self.half_extents).all()
+    }
+}
+
+/// A door panel. It hangs off a hinge entity that swings it open.
+#[derive(Component)]
+pub struct Door {
+    pub id: DoorId,
+}
+
+/// Resource holding which doors are open.
+#[derive(Resource, Default)]
+pub struct DoorStates {
+    pub open: HashSet<DoorId>,
+    /// Doors the local client wants toggled, waiting to be sent to the host.
+    pub toggle_requests: Vec<DoorId>,
+}
+
+impl DoorStates {
+    pub fn toggle(&mut self, door: DoorId) {
+        if !self.open.remove(&door) {
+            self.open.insert(door);
+        }
+    }
+
+    /// Open doors in a stable order, for sending.
+    pub fn open_list(&self) -> Vec<DoorId> {
+        let mut open: Vec<DoorId> = self.open.iter().copied().collect();
+        open.sort();
+        open
+    }
+}
+
+/// Resource tracking the zone the local player is in.
+#[derive(Resource, Default)]
+pub struct PlayerZone {
+    pub current: Option<Entity>,
+    /// Last position where the player was inside a zone.
+    pub last_inside: Option<Vec3>,
+}
+
+/// Spawns a zone covering the box `half_extents` around `center`.
+pub fn spawn_zone(commands: &mut Commands, name: &str, center: Vec3, half_extents: Vec3) {
+    let transform = Transform::from_translation(center);
+    commands.spawn((
+        WorldEntity,
+        Zone {
+            name: name.to_string(),
+            half_extents,
+        },
+        transform,
+        // Set up front so the zone is in place before transforms propagate
+        GlobalTransform::from(transform),
+    ));
+}
+
+/// Spawns a closed door in a doorway, with its hinge at `hinge`. The door
+/// spans the +X direction from the hinge and swings towards +Z.
+pub fn spawn_door(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    hinge: Transform,
+    id: DoorId,
+) {
+    let door_color = Color::srgb(0.35, 0.22, 0.12);
+    let door_material = materials.add(StandardMaterial {
+        base_color: door_color,
+        ..default()
+    });
+
+    commands
+        .spawn((WorldEntity, hinge, Visibility::default()))
+        .with_child((
+            Door { id },
+            Interactable {
+                normal_color: door_color,
+                hover_color: door_color.lighter(0.1),
+                prompt: InteractionPrompt::key(Action::Interact, "Open/close door"),
+            },
+            Mesh3d(meshes.add(Cuboid::new(DOOR_WIDTH, DOOR_HEIGHT, DOOR_THICKNESS))),
+            MeshMaterial3d(door_material),
+            Transform::from_xyz(DOOR_WIDTH / 2.0, DOOR_HEIGHT / 2.0, 0.0),
+            RigidBody::Fixed,
+            Collider::cuboid(DOOR_WIDTH / 2.0, DOOR_HEIGHT / 2.0, DOOR_THICKNESS / 2.0),
+        ));
+}
+
+/// System to open or close the door under the crosshair with E.
+pub fn handle_door_interaction(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    looking_at: Res<LookingAt>,
+    doors: Query<&Door>,
+    seated_player: Query<(), (With<Player>, With<Seated>)>,
+    server: Option<Res<GameServer>>,
+    mut door_states: ResMut<DoorStates>,
+) {
+    if !bindings.just_pressed(Action::Interact, &keyboard_input) || !seated_player.is_empty() {
+        return;
+    }
+    let Some(door) = looking_at.entity.and_then(|e| doors.get(e).ok()) else {
+        return;
+    };
+
+    // The host decides directly, clients ask the host
+    if server.is_some() {
+        door_states.toggle(door.id);
+    } else {
+        door_states.toggle_requests.push(door.id);
+    }
+}
+
+/// Swing doors towards their open or closed position.
+pub fn animate_doors(
+    time: Res<Time>,
+    door_states: Res<DoorStates>,
+    doors: Query<(&Door, &Parent)>,
+    mut hinges: Query<&mut Transform, Without<Door>>,
+) {
+    let t = (DOOR_SWING_RATE * time.delta_secs()).min(1.0);
+    for (door, hinge) in doors.iter() {
+        let Ok(mut transform) = hinges.get_mut(hinge.get()) else {
+            continue;
+        };
+        let angle = if door_states.open.contains(&door.id) {
+            -DOOR_OPEN_ANGLE
+        } else {
+            0.0
+        };
+        let target = Quat::from_rotation_y(angle);
+        transform.rotation = transform.rotation.slerp(target, t);
+    }
+}
+
+/// Track which zone the local player is in, and keep them inside the zones.
+pub fn track_player_zone(
+    zones: Query<(Entity, &Zone, &GlobalTransform)>,
+    mut player_query: Query<&mut Transform, With<Player>>,
+    mut player_zone: ResMut<PlayerZone>,
+    mut notifications: EventWriter<NotificationEvent>,
+) {
+    // Rooms without zones have no bounds to keep
+    if zones.is_empty() {
+        return;
+    }
+    let Ok(mut transform) = player_query.get_single_mut() else {
+        return;
+    };
+
+    let position = transform.translation;
+    let zone = zones
+        .iter()
+        .find(|(_, zone, zone_transform)| zone.contains(zone_transform, position));
+
+    match zone {
+        Some((entity, zone, _)) => {
+            if player_zone.current != Some(entity) {
+                if player_zone.current.is_some() {
+                    notifications.send(NotificationEvent(format!("Entered {}", zone.name)));
+                }
+                player_zone.current = Some(entity);
+            }
+            player_zone.last_inside = Some(position);
+        }
+        None => {
+            if let Some(last_inside) = player_zone.last_inside {
+                warn!("Player left the room bounds, moving them back");
+                transform.translation = last_inside;
+            }
+        }
+    }
+}
+
+/// Close every door and forget the player's zone when leaving the room.
+pub fn reset_zones(mut commands: Commands) {
+    commands.insert_resource(DoorStates::default());
+    commands.insert_resource(PlayerZone::default());
+}